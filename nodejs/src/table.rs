@@ -124,12 +124,16 @@ impl Table {
 
     #[napi(catch_unwind)]
     pub async fn delete(&self, predicate: String) -> napi::Result<()> {
-        self.inner_ref()?.delete(&predicate).await.map_err(|e| {
-            napi::Error::from_reason(format!(
-                "Failed to delete rows in table {}: predicate={}",
-                self.name, e
-            ))
-        })
+        self.inner_ref()?
+            .delete(&predicate)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                napi::Error::from_reason(format!(
+                    "Failed to delete rows in table {}: predicate={}",
+                    self.name, e
+                ))
+            })
     }
 
     #[napi(catch_unwind)]
@@ -164,7 +168,8 @@ impl Table {
         for (column_name, value) in columns {
             op = op.column(column_name, value);
         }
-        op.execute().await.default_error()
+        op.execute().await.default_error()?;
+        Ok(())
     }
 
     #[napi(catch_unwind)]
@@ -293,6 +298,7 @@ impl Table {
             .optimize(OptimizeAction::Prune {
                 older_than,
                 delete_unverified: None,
+                compaction: None,
             })
             .await
             .default_error()?
@@ -119,7 +119,8 @@ impl Table {
     pub fn delete(self_: PyRef<'_, Self>, condition: String) -> PyResult<&PyAny> {
         let inner = self_.inner_ref()?.clone();
         future_into_py(self_.py(), async move {
-            inner.delete(&condition).await.infer_error()
+            inner.delete(&condition).await.infer_error()?;
+            Ok(())
         })
     }
 
@@ -256,6 +257,7 @@ impl Table {
                 .optimize(OptimizeAction::Prune {
                     older_than,
                     delete_unverified: None,
+                    compaction: None,
                 })
                 .await
                 .infer_error()?
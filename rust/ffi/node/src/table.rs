@@ -326,6 +326,7 @@ impl JsTable {
                 .optimize(OptimizeAction::Prune {
                     older_than: Some(older_than),
                     delete_unverified,
+                    compaction: None,
                 })
                 .await;
 
@@ -0,0 +1,119 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This example compares running many vector searches one at a time with running
+//! them as a single batch using `nearest_to_many`
+//!
+//! Run it in release mode to get meaningful timings:
+//!
+//! ```sh
+//! cargo run --release --example batch_search
+//! ```
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use arrow_array::types::Float32Type;
+use arrow_array::{FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator};
+use futures::TryStreamExt;
+use rand::{Rng, SeedableRng};
+
+use lancedb::index::vector::IvfPqIndexBuilder;
+use lancedb::index::Index;
+use lancedb::query::{ExecutableQuery, QueryBase};
+use lancedb::schema::{Builder, Type};
+use lancedb::{connect, Result};
+
+const TOTAL: usize = 100_000;
+const DIM: usize = 128;
+const NUM_QUERIES: usize = 100;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    if std::path::Path::new("data").exists() {
+        std::fs::remove_dir_all("data").unwrap();
+    }
+    let db = connect("data/sample-lancedb").execute().await?;
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .vector("vector", DIM)
+        .build()?
+        .schema;
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter_values(0..TOTAL as i32)),
+            Arc::new(
+                FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                    (0..TOTAL).map(|_| Some((0..DIM).map(|_| Some(rng.gen())).collect::<Vec<_>>())),
+                    DIM as i32,
+                ),
+            ),
+        ],
+    )?;
+    let table = db
+        .create_table(
+            "my_table",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .execute()
+        .await?;
+    table
+        .create_index(&["vector"], Index::IvfPq(IvfPqIndexBuilder::default()))
+        .execute()
+        .await?;
+
+    let query_vectors = (0..NUM_QUERIES)
+        .map(|_| (0..DIM).map(|_| rng.gen()).collect::<Vec<f32>>())
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let mut sequential_rows = 0;
+    for query_vector in &query_vectors {
+        let batches = table
+            .query()
+            .nearest_to(query_vector.as_slice())?
+            .limit(10)
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        sequential_rows += batches.iter().map(|b| b.num_rows()).sum::<usize>();
+    }
+    let sequential = start.elapsed();
+
+    let start = Instant::now();
+    let batches = table
+        .query()
+        .nearest_to_many(query_vectors)?
+        .limit(10)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let batched_rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+    let batched = start.elapsed();
+
+    println!(
+        "{} sequential searches: {:?} ({} rows)",
+        NUM_QUERIES, sequential, sequential_rows
+    );
+    println!(
+        "{} batched searches:    {:?} ({} rows)",
+        NUM_QUERIES, batched, batched_rows
+    );
+    Ok(())
+}
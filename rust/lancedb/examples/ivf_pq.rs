@@ -22,13 +22,13 @@ use arrow_array::types::Float32Type;
 use arrow_array::{
     FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
 };
-use arrow_schema::{DataType, Field, Schema};
 
 use futures::TryStreamExt;
 use lancedb::connection::Connection;
 use lancedb::index::vector::IvfPqIndexBuilder;
 use lancedb::index::Index;
 use lancedb::query::{ExecutableQuery, QueryBase};
+use lancedb::schema::{Builder, Type};
 use lancedb::{connect, DistanceType, Result, Table};
 
 #[tokio::main]
@@ -49,17 +49,11 @@ fn create_some_records() -> Result<Box<dyn RecordBatchReader + Send>> {
     const TOTAL: usize = 1000;
     const DIM: usize = 128;
 
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("id", DataType::Int32, false),
-        Field::new(
-            "vector",
-            DataType::FixedSizeList(
-                Arc::new(Field::new("item", DataType::Float32, true)),
-                DIM as i32,
-            ),
-            true,
-        ),
-    ]));
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .vector("vector", DIM)
+        .build()?
+        .schema;
 
     // Create a RecordBatch stream.
     let batches = RecordBatchIterator::new(
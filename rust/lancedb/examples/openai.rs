@@ -1,13 +1,13 @@
 use std::{iter::once, sync::Arc};
 
 use arrow_array::{Float64Array, Int32Array, RecordBatch, RecordBatchIterator, StringArray};
-use arrow_schema::{DataType, Field, Schema};
 use futures::StreamExt;
 use lancedb::{
     arrow::IntoArrow,
     connect,
     embeddings::{openai::OpenAIEmbeddingFunction, EmbeddingDefinition, EmbeddingFunction},
     query::{ExecutableQuery, QueryBase},
+    schema::{Builder, Type},
     Result,
 };
 
@@ -37,7 +37,7 @@ async fn main() -> Result<()> {
 
     // there is no equivalent to '.search(<query>)' yet
     let query = Arc::new(StringArray::from_iter_values(once("something warm")));
-    let query_vector = embedding.compute_query_embeddings(query)?;
+    let query_vector = embedding.compute_query_embeddings_async(query).await?;
     let mut results = table
         .vector_search(query_vector)?
         .limit(1)
@@ -58,11 +58,13 @@ async fn main() -> Result<()> {
 }
 
 fn make_data() -> impl IntoArrow {
-    let schema = Schema::new(vec![
-        Field::new("id", DataType::Int32, true),
-        Field::new("text", DataType::Utf8, false),
-        Field::new("price", DataType::Float64, false),
-    ]);
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .text("text")
+        .field("price", Type::Float64)
+        .build()
+        .unwrap()
+        .schema;
 
     let id = Int32Array::from(vec![1, 2, 3, 4]);
     let text = StringArray::from_iter_values(vec![
@@ -72,7 +74,6 @@ fn make_data() -> impl IntoArrow {
         "Hooded Sweatshirt",
     ]);
     let price = Float64Array::from(vec![10.0, 50.0, 100.0, 30.0]);
-    let schema = Arc::new(schema);
     let rb = RecordBatch::try_new(
         schema.clone(),
         vec![Arc::new(id), Arc::new(text), Arc::new(price)],
@@ -20,13 +20,13 @@ use std::sync::Arc;
 
 use arrow_array::types::Float32Type;
 use arrow_array::{FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator};
-use arrow_schema::{DataType, Field, Schema};
 use futures::TryStreamExt;
 
 use lancedb::arrow::IntoArrow;
 use lancedb::connection::Connection;
 use lancedb::index::Index;
 use lancedb::query::{ExecutableQuery, QueryBase};
+use lancedb::schema::{Builder, Type};
 use lancedb::{connect, Result, Table as LanceDbTable};
 
 #[tokio::main]
@@ -50,7 +50,9 @@ async fn main() -> Result<()> {
     create_empty_table(&db).await.unwrap();
 
     // --8<-- [start:delete]
-    tbl.delete("id > 24").await.unwrap();
+    if let Some(num_deleted) = tbl.delete("id > 24").await.unwrap() {
+        println!("Deleted {} rows", num_deleted);
+    }
     // --8<-- [end:delete]
 
     // --8<-- [start:drop_table]
@@ -74,17 +76,11 @@ fn create_some_records() -> Result<impl IntoArrow> {
     const TOTAL: usize = 1000;
     const DIM: usize = 128;
 
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("id", DataType::Int32, false),
-        Field::new(
-            "vector",
-            DataType::FixedSizeList(
-                Arc::new(Field::new("item", DataType::Float32, true)),
-                DIM as i32,
-            ),
-            true,
-        ),
-    ]));
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .vector("vector", DIM)
+        .build()?
+        .schema;
 
     // Create a RecordBatch stream.
     let batches = RecordBatchIterator::new(
@@ -128,10 +124,11 @@ async fn create_table(db: &Connection) -> Result<LanceDbTable> {
 
 async fn create_empty_table(db: &Connection) -> Result<LanceDbTable> {
     // --8<-- [start:create_empty_table]
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("id", DataType::Int32, false),
-        Field::new("item", DataType::Utf8, true),
-    ]));
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .text("item")
+        .build()?
+        .schema;
     db.create_empty_table("empty_table", schema).execute().await
     // --8<-- [end:create_empty_table]
 }
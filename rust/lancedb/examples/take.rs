@@ -0,0 +1,153 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This example compares fetching the rows of search results with `take` and
+//! `take_by_key` with fetching them with an `IN (...)` filter
+//!
+//! Run it in release mode to get meaningful timings:
+//!
+//! ```sh
+//! cargo run --release --example take
+//! ```
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use arrow_array::types::{Float32Type, Int32Type, UInt64Type};
+use arrow_array::{
+    cast::AsArray, FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator,
+};
+use futures::TryStreamExt;
+use rand::{Rng, SeedableRng};
+
+use lancedb::index::scalar::BTreeIndexBuilder;
+use lancedb::index::Index;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::schema::{Builder, Type};
+use lancedb::table::MissingRows;
+use lancedb::{connect, Result};
+
+const TOTAL: usize = 1_000_000;
+const DIM: usize = 32;
+const NUM_ROWS: usize = 100;
+const NUM_RUNS: usize = 20;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    if std::path::Path::new("data").exists() {
+        std::fs::remove_dir_all("data").unwrap();
+    }
+    let db = connect("data/sample-lancedb").execute().await?;
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+
+    let schema = Builder::new()
+        .field("id", Type::Int32)
+        .vector("vector", DIM)
+        .build()?
+        .schema;
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter_values(0..TOTAL as i32)),
+            Arc::new(
+                FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                    (0..TOTAL).map(|_| Some((0..DIM).map(|_| Some(rng.gen())).collect::<Vec<_>>())),
+                    DIM as i32,
+                ),
+            ),
+        ],
+    )?;
+    let table = db
+        .create_table(
+            "my_table",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .execute()
+        .await?;
+    table
+        .create_index(&["id"], Index::BTree(BTreeIndexBuilder::default()))
+        .execute()
+        .await?;
+
+    // The row ids and keys of the results of a search
+    let query_vector = (0..DIM).map(|_| rng.gen()).collect::<Vec<f32>>();
+    let results = table
+        .query()
+        .nearest_to(query_vector)?
+        .limit(NUM_ROWS)
+        .select(Select::columns(&["id"]))
+        .with_row_id()
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let row_ids = results
+        .iter()
+        .flat_map(|batch| {
+            batch["_rowid"]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    let keys = results
+        .iter()
+        .flat_map(|batch| batch["id"].as_primitive::<Int32Type>().values().to_vec())
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for _ in 0..NUM_RUNS {
+        table.take(&row_ids, None, MissingRows::Error).await?;
+    }
+    println!(
+        "take:        {:?} per fetch",
+        start.elapsed() / NUM_RUNS as u32
+    );
+
+    let key_array = Int32Array::from(keys.clone());
+    let start = Instant::now();
+    for _ in 0..NUM_RUNS {
+        table
+            .take_by_key("id", &key_array, None, MissingRows::Error)
+            .await?;
+    }
+    println!(
+        "take_by_key: {:?} per fetch",
+        start.elapsed() / NUM_RUNS as u32
+    );
+
+    let filter = format!(
+        "id IN ({})",
+        keys.iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let start = Instant::now();
+    for _ in 0..NUM_RUNS {
+        table
+            .query()
+            .only_if(&filter)
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+    }
+    println!(
+        "IN filter:   {:?} per fetch",
+        start.elapsed() / NUM_RUNS as u32
+    );
+
+    Ok(())
+}
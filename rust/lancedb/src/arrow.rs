@@ -12,15 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{pin::Pin, sync::Arc};
+use std::{collections::VecDeque, future::Future, pin::Pin, sync::Arc, time::Duration};
 
 pub use arrow_schema;
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
 
 #[cfg(feature = "polars")]
 use {crate::polars_arrow_convertors, polars::frame::ArrowChunk, polars::prelude::DataFrame};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+
+mod de;
+mod ser;
+
+pub use de::from_record_batch;
+pub use ser::{from_iter, FromIter};
 
 /// An iterator of batches that also has a schema
 pub trait RecordBatchReader: Iterator<Item = Result<arrow_array::RecordBatch>> {
@@ -104,6 +111,177 @@ impl<S: Stream<Item = Result<arrow_array::RecordBatch>>> RecordBatchStream
     }
 }
 
+/// A stream that fails with [`Error::Timeout`] if it hasn't finished by a deadline
+///
+/// The inner stream is dropped when the deadline passes, which stops any work it
+/// was doing in the background.
+#[pin_project::pin_project]
+pub(crate) struct TimeoutStream {
+    schema: Arc<arrow_schema::Schema>,
+    stream: Option<SendableRecordBatchStream>,
+    #[pin]
+    deadline: tokio::time::Sleep,
+    timeout: Duration,
+}
+
+impl TimeoutStream {
+    pub(crate) fn new(
+        stream: SendableRecordBatchStream,
+        deadline: tokio::time::Instant,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            schema: stream.schema(),
+            stream: Some(stream),
+            deadline: tokio::time::sleep_until(deadline),
+            timeout,
+        }
+    }
+}
+
+impl Stream for TimeoutStream {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if this.stream.is_none() {
+            return std::task::Poll::Ready(None);
+        }
+        // The clock is checked before the inner stream is polled, because a stream
+        // that is always ready would never let the timer fire
+        let timed_out = tokio::time::Instant::now() >= this.deadline.deadline();
+        if !timed_out {
+            let stream = this.stream.as_mut().expect("checked above");
+            if let std::task::Poll::Ready(item) = stream.poll_next_unpin(cx) {
+                if item.is_none() {
+                    *this.stream = None;
+                }
+                return std::task::Poll::Ready(item);
+            }
+        }
+        if timed_out || this.deadline.as_mut().poll(cx).is_ready() {
+            *this.stream = None;
+            return std::task::Poll::Ready(Some(Err(Error::Timeout {
+                message: "the query did not finish in time".to_string(),
+                elapsed: *this.timeout,
+            })));
+        }
+        std::task::Poll::Pending
+    }
+}
+
+impl RecordBatchStream for TimeoutStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.schema.clone()
+    }
+}
+
+/// A stream that splits batches longer than `max_length` and merges batches
+/// shorter than `min_length` with the batches that follow them
+///
+/// The order of the rows is kept.  Only the last batch can be shorter than
+/// `min_length`.  Splitting is zero-copy, merging copies the merged batches.
+pub(crate) struct RebatchStream {
+    schema: Arc<arrow_schema::Schema>,
+    stream: SendableRecordBatchStream,
+    min_length: usize,
+    max_length: usize,
+    /// Batches that were read but not returned yet
+    pending: VecDeque<arrow_array::RecordBatch>,
+    /// The number of rows in `pending`
+    buffered: usize,
+    done: bool,
+}
+
+impl RebatchStream {
+    pub(crate) fn new(
+        stream: SendableRecordBatchStream,
+        min_length: usize,
+        max_length: usize,
+    ) -> Self {
+        debug_assert!(max_length > 0 && min_length <= max_length);
+        Self {
+            schema: stream.schema(),
+            stream,
+            min_length,
+            max_length,
+            pending: VecDeque::new(),
+            buffered: 0,
+            done: false,
+        }
+    }
+
+    /// Remove the first `num_rows` rows of `pending` as a single batch
+    fn take_rows(&mut self, num_rows: usize) -> Result<arrow_array::RecordBatch> {
+        let mut parts = Vec::new();
+        let mut remaining = num_rows;
+        while remaining > 0 {
+            let batch = self.pending.pop_front().expect("enough rows are buffered");
+            if batch.num_rows() > remaining {
+                parts.push(batch.slice(0, remaining));
+                self.pending
+                    .push_front(batch.slice(remaining, batch.num_rows() - remaining));
+                remaining = 0;
+            } else {
+                remaining -= batch.num_rows();
+                parts.push(batch);
+            }
+        }
+        self.buffered -= num_rows;
+        if parts.len() == 1 {
+            Ok(parts.pop().expect("there is one part"))
+        } else {
+            Ok(::arrow::compute::concat_batches(
+                &parts[0].schema(),
+                &parts,
+            )?)
+        }
+    }
+}
+
+impl Stream for RebatchStream {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.buffered > 0 && (this.buffered >= this.min_length || this.done) {
+                let num_rows = this.buffered.min(this.max_length);
+                return std::task::Poll::Ready(Some(this.take_rows(num_rows)));
+            }
+            if this.done {
+                return std::task::Poll::Ready(None);
+            }
+            match futures::ready!(this.stream.poll_next_unpin(cx)) {
+                Some(Ok(batch)) if batch.num_rows() == 0 => {
+                    // Empty batches are passed on unless batches are merged
+                    if this.min_length == 0 {
+                        return std::task::Poll::Ready(Some(Ok(batch)));
+                    }
+                }
+                Some(Ok(batch)) => {
+                    this.buffered += batch.num_rows();
+                    this.pending.push_back(batch);
+                }
+                Some(Err(err)) => return std::task::Poll::Ready(Some(Err(err))),
+                None => this.done = true,
+            }
+        }
+    }
+}
+
+impl RecordBatchStream for RebatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.schema.clone()
+    }
+}
+
 /// A trait for converting incoming data to Arrow
 ///
 /// Integrations should implement this trait to allow data to be
@@ -124,6 +302,44 @@ impl<T: arrow_array::RecordBatchReader + Send + 'static> IntoArrow for T {
     }
 }
 
+/// A trait for reading the results of a LanceDB query into Rust types
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Item {
+///     id: i64,
+///     name: Option<String>,
+///     vector: Vec<f32>,
+/// }
+///
+/// let items = table
+///     .query()
+///     .execute()
+///     .await?
+///     .into_typed::<Item>()
+///     .try_collect::<Vec<_>>()
+///     .await?;
+/// ```
+pub trait IntoTyped {
+    /// Deserialize each row of the results into a `T`
+    ///
+    /// See [`from_record_batch`] for how the columns are matched to the fields of
+    /// `T`.  Rows that can't be read as a `T` are returned as errors.
+    fn into_typed<T: DeserializeOwned + Send + 'static>(
+        self,
+    ) -> impl Stream<Item = Result<T>> + Send;
+}
+
+impl IntoTyped for SendableRecordBatchStream {
+    fn into_typed<T: DeserializeOwned + Send + 'static>(
+        self,
+    ) -> impl Stream<Item = Result<T>> + Send {
+        self.and_then(|batch| async move { from_record_batch::<T>(&batch) })
+            .map_ok(|rows| futures::stream::iter(rows.into_iter().map(Ok)))
+            .try_flatten()
+    }
+}
+
 impl<S: Stream<Item = Result<arrow_array::RecordBatch>>> SimpleRecordBatchStream<S> {
     pub fn new(stream: S, schema: Arc<arrow_schema::Schema>) -> Self {
         Self { schema, stream }
@@ -131,6 +347,12 @@ impl<S: Stream<Item = Result<arrow_array::RecordBatch>>> SimpleRecordBatchStream
 }
 #[cfg(feature = "polars")]
 /// An iterator of record batches formed from a Polars DataFrame.
+///
+/// This is how a DataFrame is passed anywhere [`IntoArrow`] is expected, e.g.
+/// `table.add(PolarsDataFrameRecordBatchReader::new(df)?)`.  The column buffers are
+/// shared with Polars, not copied.  Polars `Array` columns become vectors (fixed
+/// size lists) and `List` columns become lists.  Categorical columns are not
+/// supported, cast them to strings first.
 pub struct PolarsDataFrameRecordBatchReader {
     chunks: std::vec::IntoIter<ArrowChunk>,
     arrow_schema: Arc<arrow_schema::Schema>,
@@ -295,4 +517,67 @@ mod tests {
             ]
         );
     }
+
+    #[tokio::test]
+    async fn polars_round_trip() {
+        use polars::prelude::DataType;
+
+        use crate::connect;
+        use crate::query::ExecutableQuery;
+
+        let vectors = Series::new(
+            "vector",
+            &[
+                Series::new("", &[1.0f32, 2.0]),
+                Series::new("", &[3.0f32, 4.0]),
+                Series::new("", &[5.0f32, 6.0]),
+            ],
+        )
+        .cast(&DataType::Array(Box::new(DataType::Float32), 2))
+        .unwrap();
+        let tags = Series::new(
+            "tags",
+            &[
+                Series::new("", &[1i64, 2]),
+                Series::new("", &[3i64]),
+                Series::new("", &Vec::<i64>::new()),
+            ],
+        );
+        let df1 = DataFrame::new(vec![
+            Series::new("id", &[1i32, 2, 3]),
+            Series::new("name", &[Some("a"), None, Some("c")]),
+            Series::new("score", &[0.5f64, 1.5, 2.5]),
+            vectors,
+            tags,
+        ])
+        .unwrap();
+        // vstack leaves the columns with two chunks
+        let df = df1.vstack(&df1).unwrap();
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table(
+                "my_table",
+                PolarsDataFrameRecordBatchReader::new(df.clone()).unwrap(),
+            )
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 6);
+
+        let round_tripped = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .into_polars()
+            .await
+            .unwrap();
+        assert_eq!(round_tripped.dtypes(), df.dtypes());
+        assert!(round_tripped.equals_missing(&df), "{:?}", round_tripped);
+    }
 }
@@ -0,0 +1,511 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read the rows of record batches into Rust types with serde
+
+use std::fmt::Display;
+
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Fields, TimeUnit};
+use serde::de::{
+    self, value::SeqDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+    IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::error::{Error, Result};
+
+/// Deserialize each row of a batch into a `T`
+///
+/// Each row is read as a struct with a field for each column, so `T` is usually a
+/// struct whose fields are named after the columns.  Columns without a field are
+/// ignored.  Nullable columns must be read into an `Option`, lists (including
+/// vectors) into a `Vec` and struct columns into nested structs.
+///
+/// An [`Error::Schema`] naming the column is returned if a field has no column
+/// or a value can't be read as the type of its field.
+pub fn from_record_batch<T: DeserializeOwned>(batch: &RecordBatch) -> Result<Vec<T>> {
+    let schema = batch.schema();
+    (0..batch.num_rows())
+        .map(|row| {
+            T::deserialize(RowDeserializer {
+                fields: schema.fields(),
+                columns: batch.columns(),
+                row,
+            })
+            .map_err(|err| Error::Schema {
+                message: format!(
+                    "can't read the results as {}: {}",
+                    std::any::type_name::<T>(),
+                    err
+                ),
+            })
+        })
+        .collect()
+}
+
+type DeResult<T> = std::result::Result<T, DeError>;
+
+#[derive(Debug)]
+struct DeError {
+    message: String,
+    /// The names of the columns the error is in, innermost first
+    path: Vec<String>,
+}
+
+impl DeError {
+    fn in_column(mut self, name: &str) -> Self {
+        self.path.push(name.to_string());
+        self
+    }
+}
+
+impl Display for DeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            let path = self.path.iter().rev().cloned().collect::<Vec<_>>();
+            write!(f, "column '{}': {}", path.join("."), self.message)
+        }
+    }
+}
+
+impl std::error::Error for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self {
+            message: msg.to_string(),
+            path: Vec::new(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::custom(format!(
+            "missing field `{}`, there is no column with this name",
+            field
+        ))
+    }
+}
+
+/// A row of a batch or of a struct column
+struct RowDeserializer<'a> {
+    fields: &'a Fields,
+    columns: &'a [ArrayRef],
+    row: usize,
+}
+
+impl<'de, 'a> Deserializer<'de> for RowDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_map(RowAccess {
+            row: self,
+            index: 0,
+        })
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier
+    }
+}
+
+struct RowAccess<'a> {
+    row: RowDeserializer<'a>,
+    index: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for RowAccess<'a> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> DeResult<Option<K::Value>> {
+        match self.row.fields.get(self.index) {
+            Some(field) => seed
+                .deserialize(field.name().as_str().into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> DeResult<V::Value> {
+        let fields = self.row.fields;
+        let name = fields[self.index].name();
+        let array = self.row.columns[self.index].as_ref();
+        self.index += 1;
+        seed.deserialize(ValueDeserializer {
+            array,
+            row: self.row.row,
+        })
+        .map_err(|err| err.in_column(name))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.fields.len() - self.index)
+    }
+}
+
+/// The items of a list value
+struct ListAccess {
+    values: ArrayRef,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for ListAccess {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> DeResult<Option<T::Value>> {
+        if self.index >= self.values.len() {
+            return Ok(None);
+        }
+        let row = self.index;
+        self.index += 1;
+        seed.deserialize(ValueDeserializer {
+            array: self.values.as_ref(),
+            row,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len() - self.index)
+    }
+}
+
+/// A single value of a column
+struct ValueDeserializer<'a> {
+    array: &'a dyn Array,
+    row: usize,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn is_null(&self) -> bool {
+        self.array.data_type() == &DataType::Null || self.array.is_null(self.row)
+    }
+
+    fn binary(&self) -> Option<&'a [u8]> {
+        match self.array.data_type() {
+            DataType::Binary => Some(self.array.as_binary::<i32>().value(self.row)),
+            DataType::LargeBinary => Some(self.array.as_binary::<i64>().value(self.row)),
+            DataType::FixedSizeBinary(_) => Some(self.array.as_fixed_size_binary().value(self.row)),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<&'a str> {
+        match self.array.data_type() {
+            DataType::Utf8 => Some(self.array.as_string::<i32>().value(self.row)),
+            DataType::LargeUtf8 => Some(self.array.as_string::<i64>().value(self.row)),
+            _ => None,
+        }
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        if self.is_null() {
+            return Err(de::Error::custom(
+                "the value is null, use an Option for nullable columns",
+            ));
+        }
+        if let Some(value) = self.string() {
+            return visitor.visit_str(value);
+        }
+        if let Some(value) = self.binary() {
+            return visitor.visit_bytes(value);
+        }
+        let (array, row) = (self.array, self.row);
+        match array.data_type() {
+            DataType::Boolean => visitor.visit_bool(array.as_boolean().value(row)),
+            DataType::Int8 => visitor.visit_i8(array.as_primitive::<Int8Type>().value(row)),
+            DataType::Int16 => visitor.visit_i16(array.as_primitive::<Int16Type>().value(row)),
+            DataType::Int32 => visitor.visit_i32(array.as_primitive::<Int32Type>().value(row)),
+            DataType::Int64 => visitor.visit_i64(array.as_primitive::<Int64Type>().value(row)),
+            DataType::UInt8 => visitor.visit_u8(array.as_primitive::<UInt8Type>().value(row)),
+            DataType::UInt16 => visitor.visit_u16(array.as_primitive::<UInt16Type>().value(row)),
+            DataType::UInt32 => visitor.visit_u32(array.as_primitive::<UInt32Type>().value(row)),
+            DataType::UInt64 => visitor.visit_u64(array.as_primitive::<UInt64Type>().value(row)),
+            DataType::Float16 => {
+                visitor.visit_f32(array.as_primitive::<Float16Type>().value(row).to_f32())
+            }
+            DataType::Float32 => visitor.visit_f32(array.as_primitive::<Float32Type>().value(row)),
+            DataType::Float64 => visitor.visit_f64(array.as_primitive::<Float64Type>().value(row)),
+            // Dates and times are read as their number of days or units since the epoch
+            DataType::Date32 => visitor.visit_i32(array.as_primitive::<Date32Type>().value(row)),
+            DataType::Date64 => visitor.visit_i64(array.as_primitive::<Date64Type>().value(row)),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                visitor.visit_i64(array.as_primitive::<TimestampSecondType>().value(row))
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                visitor.visit_i64(array.as_primitive::<TimestampMillisecondType>().value(row))
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                visitor.visit_i64(array.as_primitive::<TimestampMicrosecondType>().value(row))
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                visitor.visit_i64(array.as_primitive::<TimestampNanosecondType>().value(row))
+            }
+            DataType::List(_) => visitor.visit_seq(ListAccess {
+                values: array.as_list::<i32>().value(row),
+                index: 0,
+            }),
+            DataType::LargeList(_) => visitor.visit_seq(ListAccess {
+                values: array.as_list::<i64>().value(row),
+                index: 0,
+            }),
+            DataType::FixedSizeList(_, _) => visitor.visit_seq(ListAccess {
+                values: array.as_fixed_size_list().value(row),
+                index: 0,
+            }),
+            DataType::Struct(_) => {
+                let array = array.as_struct();
+                RowDeserializer {
+                    fields: array.fields(),
+                    columns: array.columns(),
+                    row,
+                }
+                .deserialize_any(visitor)
+            }
+            data_type => Err(de::Error::custom(format!(
+                "columns of type {} can't be deserialized",
+                data_type
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        if self.is_null() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        if self.is_null() {
+            visitor.visit_unit()
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        // Allow binary values to be read into a Vec<u8>
+        match self.binary() {
+            Some(value) if !self.is_null() => {
+                let mut seq = SeqDeserializer::<_, DeError>::new(value.iter().copied());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        // Strings are read as the unit variant with the same name
+        match self.string() {
+            Some(value) if !self.is_null() => visitor.visit_enum(value.into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit_struct tuple tuple_struct map struct identifier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        builder::{ListBuilder, StringBuilder},
+        FixedSizeListArray, Float32Array, Float64Array, Int32Array, RecordBatch, StringArray,
+        StructArray,
+    };
+    use arrow_schema::{Field, Schema};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Row {
+        id: i64,
+        name: Option<String>,
+        vector: Vec<f32>,
+        tags: Vec<String>,
+        meta: Meta,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Meta {
+        score: f64,
+    }
+
+    fn make_batch() -> RecordBatch {
+        let meta = StructArray::from(vec![(
+            Arc::new(Field::new("score", DataType::Float64, false)),
+            Arc::new(Float64Array::from(vec![0.5, 1.5])) as ArrayRef,
+        )]);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "vector",
+                DataType::new_fixed_size_list(DataType::Float32, 2, true),
+                false,
+            ),
+            Field::new("tags", DataType::new_list(DataType::Utf8, true), false),
+            Field::new("meta", meta.data_type().clone(), false),
+            Field::new("_distance", DataType::Float32, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        vec![
+                            Some(vec![Some(1.0), Some(2.0)]),
+                            Some(vec![Some(3.0), Some(4.0)]),
+                        ],
+                        2,
+                    ),
+                ),
+                Arc::new({
+                    let mut builder = ListBuilder::new(StringBuilder::new());
+                    builder.values().append_value("x");
+                    builder.values().append_value("y");
+                    builder.append(true);
+                    builder.append(true);
+                    builder.finish()
+                }) as ArrayRef,
+                Arc::new(meta),
+                Arc::new(Float32Array::from(vec![0.1, 0.2])),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_record_batch() {
+        let rows = from_record_batch::<Row>(&make_batch()).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    id: 1,
+                    name: Some("a".to_string()),
+                    vector: vec![1.0, 2.0],
+                    tags: vec!["x".to_string(), "y".to_string()],
+                    meta: Meta { score: 0.5 },
+                },
+                Row {
+                    id: 2,
+                    name: None,
+                    vector: vec![3.0, 4.0],
+                    tags: vec![],
+                    meta: Meta { score: 1.5 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_record_batch_errors() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct NotNull {
+            name: String,
+        }
+        let err = from_record_batch::<NotNull>(&make_batch()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("column 'name': the value is null, use an Option"),
+            "{}",
+            err
+        );
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Missing {
+            id: i32,
+            category: String,
+        }
+        let err = from_record_batch::<Missing>(&make_batch()).unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        assert!(
+            err.to_string().contains("missing field `category`"),
+            "{}",
+            err
+        );
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct WrongType {
+            id: String,
+        }
+        let err = from_record_batch::<WrongType>(&make_batch()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("column 'id': invalid type: integer `1`, expected a string"),
+            "{}",
+            err
+        );
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Nested {
+            meta: NestedMeta,
+        }
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct NestedMeta {
+            score: bool,
+        }
+        let err = from_record_batch::<Nested>(&make_batch()).unwrap_err();
+        assert!(err.to_string().contains("column 'meta.score'"), "{}", err);
+    }
+}
@@ -0,0 +1,1044 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Write Rust values that implement serde's `Serialize` into record batches
+
+use std::{fmt::Display, sync::Arc};
+
+use arrow::buffer::{NullBuffer, OffsetBuffer, ScalarBuffer};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, FixedSizeListArray, Float64Array, GenericListArray,
+    Int64Array, OffsetSizeTrait, RecordBatch, RecordBatchReader, StringArray, StructArray,
+    UInt64Array,
+};
+use arrow_cast::{cast_with_options, CastOptions};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, SchemaRef};
+use serde::ser::{self, Impossible, Serialize, Serializer};
+
+use crate::arrow::IntoArrow;
+use crate::error::{Error, Result};
+
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// Convert an iterator of rows into Arrow, see [`from_iter`]
+pub struct FromIter<I> {
+    rows: I,
+    schema: Option<SchemaRef>,
+    batch_size: usize,
+}
+
+/// Convert rows that implement serde's `Serialize` into Arrow
+///
+/// Each row must serialize as a struct (or a map with string keys), its fields
+/// become the columns.  The result implements [`IntoArrow`], so it can be passed to
+/// [`crate::connection::Connection::create_table`] and [`crate::table::Table::add`].
+///
+/// The schema is inferred from the rows of the first batch unless one is given with
+/// [`FromIter::schema`]:
+///
+/// * Integers, floats, booleans and strings become the matching Arrow types
+/// * A `Vec<f32>` becomes a vector, a fixed size list whose dimension is the length
+///   of the first value.  Every row must have the same dimension.
+/// * Other sequences become lists and nested structs become struct columns
+/// * Unit enum variants become strings
+///
+/// Every inferred column is nullable.  A column whose values in the first batch are
+/// all `None` has no type to infer, pass a schema for these.
+///
+/// The errors for rows that don't match the schema name the offending field.
+///
+/// ```ignore
+/// #[derive(Serialize)]
+/// struct Item {
+///     id: i64,
+///     name: Option<String>,
+///     vector: Vec<f32>,
+/// }
+///
+/// table.add(lancedb::arrow::from_iter(items)).execute().await?;
+/// ```
+pub fn from_iter<I>(rows: I) -> FromIter<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    FromIter {
+        rows: rows.into_iter(),
+        schema: None,
+        batch_size: DEFAULT_BATCH_SIZE,
+    }
+}
+
+impl<I> FromIter<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    /// Use this schema instead of inferring one from the rows
+    ///
+    /// Values are cast to the types of the schema, and the fields of the schema that
+    /// are missing from a row are null.  A row with a field that isn't in the schema
+    /// is an error.
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The number of rows in each batch, 1024 by default
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn next_rows(&mut self) -> std::result::Result<Vec<Value>, String> {
+        self.rows
+            .by_ref()
+            .take(self.batch_size)
+            .map(|row| {
+                row.serialize(ValueSerializer)
+                    .map_err(|err| err.to_string())
+            })
+            .collect()
+    }
+}
+
+impl<I> IntoArrow for FromIter<I>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Serialize,
+{
+    fn into_arrow(mut self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        let rows = self
+            .next_rows()
+            .map_err(|message| Error::Schema { message })?;
+        let schema = match self.schema.take() {
+            Some(schema) => schema,
+            None if rows.is_empty() => {
+                return Err(Error::Schema {
+                    message: "can't infer a schema without any rows, pass a schema".to_string(),
+                })
+            }
+            None => {
+                if let Some(row) = rows.iter().find(|row| !matches!(row, Value::Struct(_))) {
+                    return Err(Error::Schema {
+                        message: format!("the rows must be structs, found {}", row.describe()),
+                    });
+                }
+                match infer_type("", &rows.iter().collect::<Vec<_>>()) {
+                    Ok(DataType::Struct(fields)) => Arc::new(Schema::new(fields)),
+                    Ok(_) => unreachable!("rows that are structs have a struct type"),
+                    Err(message) => return Err(Error::Schema { message }),
+                }
+            }
+        };
+        let first = if rows.is_empty() {
+            None
+        } else {
+            Some(build_batch(&schema, &rows).map_err(|message| Error::Schema { message })?)
+        };
+        Ok(Box::new(SerdeRecordBatchReader {
+            rows: self,
+            schema,
+            first,
+        }))
+    }
+}
+
+struct SerdeRecordBatchReader<I> {
+    rows: FromIter<I>,
+    schema: SchemaRef,
+    /// The batch the schema was inferred from
+    first: Option<RecordBatch>,
+}
+
+impl<I> Iterator for SerdeRecordBatchReader<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(first) = self.first.take() {
+            return Some(Ok(first));
+        }
+        let batch = self.rows.next_rows().and_then(|rows| {
+            if rows.is_empty() {
+                Ok(None)
+            } else {
+                build_batch(&self.schema, &rows).map(Some)
+            }
+        });
+        batch.map_err(ArrowError::InvalidArgumentError).transpose()
+    }
+}
+
+impl<I> RecordBatchReader for SerdeRecordBatchReader<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// A serialized value, before it is converted to Arrow
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64, DataType),
+    UInt(u64, DataType),
+    Float32(f32),
+    Float64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "a boolean",
+            Self::Int(..) | Self::UInt(..) => "an integer",
+            Self::Float32(_) | Self::Float64(_) => "a float",
+            Self::String(_) => "a string",
+            Self::Bytes(_) => "bytes",
+            Self::List(_) => "a list",
+            Self::Struct(_) => "a struct",
+        }
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn mismatch(path: &str, data_type: &DataType, value: &Value) -> String {
+    format!(
+        "field '{}': expected a value of type {} but found {}",
+        path,
+        data_type,
+        value.describe()
+    )
+}
+
+/// Infer the type of a field from its values in the first batch
+fn infer_type(path: &str, values: &[&Value]) -> std::result::Result<DataType, String> {
+    let Some(first) = values.iter().find(|v| !matches!(v, Value::Null)) else {
+        return Err(format!(
+            "field '{}': can't infer the type, every value in the first batch is empty, pass a schema",
+            path
+        ));
+    };
+    Ok(match first {
+        Value::Null => unreachable!(),
+        Value::Bool(_) => DataType::Boolean,
+        Value::Int(_, data_type) | Value::UInt(_, data_type) => data_type.clone(),
+        Value::Float32(_) => DataType::Float32,
+        Value::Float64(_) => DataType::Float64,
+        Value::String(_) => DataType::Utf8,
+        Value::Bytes(_) => DataType::Binary,
+        Value::List(items)
+            if !items.is_empty() && items.iter().all(|item| matches!(item, Value::Float32(_))) =>
+        {
+            DataType::new_fixed_size_list(DataType::Float32, items.len() as i32, true)
+        }
+        Value::List(_) => {
+            let items = values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::List(items) => Some(items.iter()),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<_>>();
+            DataType::new_list(infer_type(path, &items)?, true)
+        }
+        Value::Struct(entries) => {
+            let fields = entries
+                .iter()
+                .map(|(name, _)| {
+                    let children = values
+                        .iter()
+                        .map(|v| field_value(v, name))
+                        .collect::<Vec<_>>();
+                    Ok(Field::new(
+                        name,
+                        infer_type(&join(path, name), &children)?,
+                        true,
+                    ))
+                })
+                .collect::<std::result::Result<Vec<_>, String>>()?;
+            DataType::Struct(Fields::from(fields))
+        }
+    })
+}
+
+fn field_value<'a>(value: &'a Value, name: &str) -> &'a Value {
+    match value {
+        Value::Struct(entries) => entries
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .unwrap_or(&NULL),
+        _ => &NULL,
+    }
+}
+
+fn build_batch(schema: &SchemaRef, rows: &[Value]) -> std::result::Result<RecordBatch, String> {
+    let rows = rows.iter().collect::<Vec<_>>();
+    let data_type = DataType::Struct(schema.fields().clone());
+    if let Some(row) = rows.iter().find(|row| !matches!(row, Value::Struct(_))) {
+        return Err(format!(
+            "the rows must be structs, found {}",
+            row.describe()
+        ));
+    }
+    let array = build_array("", &data_type, &rows)?;
+    let array = array
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .expect("a struct array is built for a struct type");
+    RecordBatch::try_new(schema.clone(), array.columns().to_vec()).map_err(|err| err.to_string())
+}
+
+fn validity(values: &[&Value]) -> Option<NullBuffer> {
+    let validity = NullBuffer::from(
+        values
+            .iter()
+            .map(|v| !matches!(v, Value::Null))
+            .collect::<Vec<_>>(),
+    );
+    (validity.null_count() > 0).then_some(validity)
+}
+
+/// Cast an array to the type of the field, failing if a value doesn't fit
+fn cast_to(
+    path: &str,
+    array: ArrayRef,
+    data_type: &DataType,
+) -> std::result::Result<ArrayRef, String> {
+    if array.data_type() == data_type {
+        return Ok(array);
+    }
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    cast_with_options(&array, data_type, &options)
+        .map_err(|err| format!("field '{}': can't convert to {}: {}", path, data_type, err))
+}
+
+fn build_array(
+    path: &str,
+    data_type: &DataType,
+    values: &[&Value],
+) -> std::result::Result<ArrayRef, String> {
+    match data_type {
+        DataType::Boolean => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Bool(b) => Ok(Some(*b)),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(Arc::new(BooleanArray::from(values)))
+        }
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::String(s) => Ok(Some(s.as_str())),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            cast_to(path, Arc::new(StringArray::from(values)), data_type)
+        }
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Bytes(b) => Ok(Some(b.as_slice())),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            cast_to(path, Arc::new(BinaryArray::from(values)), data_type)
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Float32(f) => Ok(Some(*f as f64)),
+                    Value::Float64(f) => Ok(Some(*f)),
+                    Value::Int(i, _) => Ok(Some(*i as f64)),
+                    Value::UInt(u, _) => Ok(Some(*u as f64)),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            cast_to(path, Arc::new(Float64Array::from(values)), data_type)
+        }
+        DataType::UInt64 => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::UInt(u, _) => Ok(Some(*u)),
+                    Value::Int(i, _) => u64::try_from(*i).map(Some).map_err(|_| {
+                        format!("field '{}': {} doesn't fit in {}", path, i, data_type)
+                    }),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(Arc::new(UInt64Array::from(values)))
+        }
+        // Dates and times are written from their number of days or units since the epoch
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::Date32
+        | DataType::Date64
+        | DataType::Timestamp(_, _) => {
+            let values = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Int(i, _) => Ok(Some(*i)),
+                    Value::UInt(u, _) => i64::try_from(*u).map(Some).map_err(|_| {
+                        format!("field '{}': {} doesn't fit in {}", path, u, data_type)
+                    }),
+                    v => Err(mismatch(path, data_type, v)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let array: ArrayRef = Arc::new(Int64Array::from(values));
+            if data_type == &DataType::Date32 {
+                // There is no cast from 64 bit integers to Date32
+                let array = cast_to(path, array, &DataType::Int32)?;
+                cast_to(path, array, data_type)
+            } else {
+                cast_to(path, array, data_type)
+            }
+        }
+        DataType::FixedSizeList(item, dimension) => {
+            let mut items = Vec::with_capacity(values.len() * *dimension as usize);
+            for value in values {
+                match value {
+                    Value::Null => {
+                        items.extend(std::iter::repeat(&NULL).take(*dimension as usize))
+                    }
+                    Value::List(list) if list.len() == *dimension as usize => {
+                        items.extend(list.iter())
+                    }
+                    Value::List(list) => {
+                        return Err(format!(
+                            "field '{}': expected {} values but found {}, every vector must have the same dimension",
+                            path,
+                            dimension,
+                            list.len()
+                        ))
+                    }
+                    v => return Err(mismatch(path, data_type, v)),
+                }
+            }
+            let items = build_array(path, item.data_type(), &items)?;
+            FixedSizeListArray::try_new(item.clone(), *dimension, items, validity(values))
+                .map(|array| Arc::new(array) as ArrayRef)
+                .map_err(|err| format!("field '{}': {}", path, err))
+        }
+        DataType::List(item) => build_list::<i32>(path, data_type, item, values),
+        DataType::LargeList(item) => build_list::<i64>(path, data_type, item, values),
+        DataType::Struct(fields) => {
+            for value in values {
+                match value {
+                    Value::Null => {}
+                    Value::Struct(entries) => {
+                        if let Some((name, _)) =
+                            entries.iter().find(|(name, _)| fields.find(name).is_none())
+                        {
+                            return Err(format!(
+                                "field '{}' is not in the schema",
+                                join(path, name)
+                            ));
+                        }
+                    }
+                    v => return Err(mismatch(path, data_type, v)),
+                }
+            }
+            let columns = fields
+                .iter()
+                .map(|field| {
+                    let children = values
+                        .iter()
+                        .map(|v| field_value(v, field.name()))
+                        .collect::<Vec<_>>();
+                    build_array(&join(path, field.name()), field.data_type(), &children)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            StructArray::try_new(fields.clone(), columns, validity(values))
+                .map(|array| Arc::new(array) as ArrayRef)
+                .map_err(|err| match path {
+                    "" => err.to_string(),
+                    path => format!("field '{}': {}", path, err),
+                })
+        }
+        data_type => Err(format!(
+            "field '{}': columns of type {} are not supported",
+            path, data_type
+        )),
+    }
+}
+
+fn build_list<O: OffsetSizeTrait>(
+    path: &str,
+    data_type: &DataType,
+    item: &Arc<Field>,
+    values: &[&Value],
+) -> std::result::Result<ArrayRef, String> {
+    let mut offsets = vec![O::usize_as(0)];
+    let mut items = Vec::new();
+    for value in values {
+        match value {
+            Value::Null => {}
+            Value::List(list) => items.extend(list.iter()),
+            v => return Err(mismatch(path, data_type, v)),
+        }
+        offsets.push(O::usize_as(items.len()));
+    }
+    let items = build_array(path, item.data_type(), &items)?;
+    GenericListArray::<O>::try_new(
+        item.clone(),
+        OffsetBuffer::new(ScalarBuffer::from(offsets)),
+        items,
+        validity(values),
+    )
+    .map(|array| Arc::new(array) as ArrayRef)
+    .map_err(|err| format!("field '{}': {}", path, err))
+}
+
+#[derive(Debug)]
+struct SerError {
+    message: String,
+    /// The names of the fields the error is in, innermost first
+    path: Vec<String>,
+}
+
+impl SerError {
+    fn in_field(mut self, name: &str) -> Self {
+        self.path.push(name.to_string());
+        self
+    }
+}
+
+impl Display for SerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            let path = self.path.iter().rev().cloned().collect::<Vec<_>>();
+            write!(f, "field '{}': {}", path.join("."), self.message)
+        }
+    }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self {
+            message: msg.to_string(),
+            path: Vec::new(),
+        }
+    }
+}
+
+type SerResult<T> = std::result::Result<T, SerError>;
+
+fn unsupported<T>(what: &str) -> SerResult<T> {
+    Err(ser::Error::custom(format!(
+        "{} can't be converted to Arrow",
+        what
+    )))
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = Impossible<Value, SerError>;
+    type SerializeMap = StructSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = Impossible<Value, SerError>;
+
+    fn serialize_bool(self, v: bool) -> SerResult<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerResult<Value> {
+        Ok(Value::Int(v as i64, DataType::Int8))
+    }
+
+    fn serialize_i16(self, v: i16) -> SerResult<Value> {
+        Ok(Value::Int(v as i64, DataType::Int16))
+    }
+
+    fn serialize_i32(self, v: i32) -> SerResult<Value> {
+        Ok(Value::Int(v as i64, DataType::Int32))
+    }
+
+    fn serialize_i64(self, v: i64) -> SerResult<Value> {
+        Ok(Value::Int(v, DataType::Int64))
+    }
+
+    fn serialize_u8(self, v: u8) -> SerResult<Value> {
+        Ok(Value::UInt(v as u64, DataType::UInt8))
+    }
+
+    fn serialize_u16(self, v: u16) -> SerResult<Value> {
+        Ok(Value::UInt(v as u64, DataType::UInt16))
+    }
+
+    fn serialize_u32(self, v: u32) -> SerResult<Value> {
+        Ok(Value::UInt(v as u64, DataType::UInt32))
+    }
+
+    fn serialize_u64(self, v: u64) -> SerResult<Value> {
+        Ok(Value::UInt(v, DataType::UInt64))
+    }
+
+    fn serialize_f32(self, v: f32) -> SerResult<Value> {
+        Ok(Value::Float32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> SerResult<Value> {
+        Ok(Value::Float64(v))
+    }
+
+    fn serialize_char(self, v: char) -> SerResult<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SerResult<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerResult<Value> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> SerResult<Value> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> SerResult<Value> {
+        unsupported(&format!("the enum variant {} with data", variant))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> SerResult<ListSerializer> {
+        Ok(ListSerializer {
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> SerResult<ListSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> SerResult<ListSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeTupleVariant> {
+        unsupported(&format!("the enum variant {} with data", variant))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> SerResult<StructSerializer> {
+        Ok(StructSerializer {
+            entries: Vec::with_capacity(len.unwrap_or_default()),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> SerResult<StructSerializer> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> SerResult<Self::SerializeStructVariant> {
+        unsupported(&format!("the enum variant {} with data", variant))
+    }
+}
+
+struct ListSerializer {
+    items: Vec<Value>,
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> SerResult<Value> {
+        Ok(Value::List(self.items))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerResult<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> SerResult<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Serializes structs, and maps with string keys, into [`Value::Struct`]
+struct StructSerializer {
+    entries: Vec<(String, Value)>,
+    /// The key of the map entry whose value is serialized next
+    key: Option<String>,
+}
+
+impl StructSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, name: String, value: &T) -> SerResult<()> {
+        let value = value
+            .serialize(ValueSerializer)
+            .map_err(|err| err.in_field(&name))?;
+        self.entries.push((name, value));
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for StructSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> SerResult<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            key => unsupported(&format!("a map with keys that are {}", key.describe())),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_key is called before serialize_value");
+        self.push(key, value)
+    }
+
+    fn end(self) -> SerResult<Value> {
+        Ok(Value::Struct(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> SerResult<()> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> SerResult<Value> {
+        Ok(Value::Struct(self.entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Float32Type};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::arrow::from_record_batch;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Row {
+        id: i32,
+        name: Option<String>,
+        vector: Vec<f32>,
+        tags: Vec<String>,
+        meta: Meta,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Meta {
+        score: f64,
+    }
+
+    fn rows(n: usize) -> Vec<Row> {
+        (0..n)
+            .map(|i| Row {
+                id: i as i32,
+                name: (i % 2 == 0).then(|| format!("row {}", i)),
+                vector: vec![i as f32, 1.0, 2.0],
+                tags: vec!["x".to_string(); i % 3],
+                meta: Meta { score: i as f64 },
+            })
+            .collect()
+    }
+
+    fn collect(reader: Box<dyn RecordBatchReader + Send>) -> Vec<RecordBatch> {
+        reader.collect::<std::result::Result<Vec<_>, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let reader = from_iter(rows(5)).batch_size(2).into_arrow().unwrap();
+        let schema = reader.schema();
+        assert_eq!(
+            schema
+                .fields()
+                .iter()
+                .map(|f| (f.name().as_str(), f.data_type().clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("id", DataType::Int32),
+                ("name", DataType::Utf8),
+                (
+                    "vector",
+                    DataType::new_fixed_size_list(DataType::Float32, 3, true)
+                ),
+                ("tags", DataType::new_list(DataType::Utf8, true)),
+                (
+                    "meta",
+                    DataType::Struct(Fields::from(vec![Field::new(
+                        "score",
+                        DataType::Float64,
+                        true
+                    )]))
+                ),
+            ]
+        );
+        let batches = collect(reader);
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        let vectors = batches[1]["vector"].as_fixed_size_list();
+        assert_eq!(
+            vectors.value(0).as_primitive::<Float32Type>().values(),
+            &[2.0, 1.0, 2.0]
+        );
+        assert!(batches[1]["name"].is_null(1));
+
+        let read = batches
+            .iter()
+            .flat_map(|batch| from_record_batch::<Row>(batch).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(read, rows(5));
+    }
+
+    #[test]
+    fn test_from_iter_schema() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::LargeUtf8, true),
+            Field::new(
+                "vector",
+                DataType::new_fixed_size_list(DataType::Float32, 3, true),
+                true,
+            ),
+            Field::new("tags", DataType::new_list(DataType::Utf8, true), true),
+            Field::new(
+                "meta",
+                DataType::Struct(Fields::from(vec![Field::new(
+                    "score",
+                    DataType::Float32,
+                    true,
+                )])),
+                true,
+            ),
+            Field::new("extra", DataType::Boolean, true),
+        ]));
+        let reader = from_iter(rows(3))
+            .schema(schema.clone())
+            .into_arrow()
+            .unwrap();
+        assert_eq!(reader.schema(), schema);
+        let batches = collect(reader);
+        assert_eq!(batches[0].num_rows(), 3);
+        assert_eq!(batches[0]["extra"].null_count(), 3);
+
+        // Without rows there is nothing to infer the schema from
+        let err = from_iter(Vec::<Row>::new()).into_arrow().err().unwrap();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        let reader = from_iter(Vec::<Row>::new())
+            .schema(schema.clone())
+            .into_arrow()
+            .unwrap();
+        assert!(collect(reader).is_empty());
+    }
+
+    #[test]
+    fn test_from_iter_errors() {
+        let message = |err: Error| err.to_string();
+
+        let mut bad = rows(3);
+        bad[2].vector.push(3.0);
+        let err = message(from_iter(bad.clone()).into_arrow().err().unwrap());
+        assert!(
+            err.contains("field 'vector': expected 3 values but found 4"),
+            "{}",
+            err
+        );
+        // Rows after the first batch fail when the batch is read
+        let reader = from_iter(bad).batch_size(2).into_arrow().unwrap();
+        let err = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(err.to_string().contains("field 'vector'"), "{}", err);
+
+        let rows = vec![
+            serde_json::json!({"id": 1, "meta": {"score": 1.0}}),
+            serde_json::json!({"id": 2, "meta": {"score": "high"}}),
+        ];
+        let err = message(from_iter(rows).into_arrow().err().unwrap());
+        assert!(
+            err.contains("field 'meta.score': expected a value of type Float64 but found a string"),
+            "{}",
+            err
+        );
+
+        let rows = vec![
+            serde_json::json!({"id": 1}),
+            serde_json::json!({"id": 2, "name": "two"}),
+        ];
+        let err = message(from_iter(rows).into_arrow().err().unwrap());
+        assert!(err.contains("field 'name' is not in the schema"), "{}", err);
+
+        let rows = vec![serde_json::json!({"id": 1, "name": null})];
+        let err = message(from_iter(rows).into_arrow().err().unwrap());
+        assert!(
+            err.contains("field 'name': can't infer the type"),
+            "{}",
+            err
+        );
+
+        #[derive(Serialize)]
+        enum Shape {
+            Circle { radius: f32 },
+        }
+        #[derive(Serialize)]
+        struct WithShape {
+            shape: Shape,
+        }
+        let rows = vec![WithShape {
+            shape: Shape::Circle { radius: 1.0 },
+        }];
+        let err = message(from_iter(rows).into_arrow().err().unwrap());
+        assert!(
+            err.contains("field 'shape': the enum variant Circle with data can't be converted"),
+            "{}",
+            err
+        );
+    }
+}
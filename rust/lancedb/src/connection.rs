@@ -20,25 +20,37 @@ use std::path::Path;
 use std::sync::Arc;
 
 use arrow_array::{RecordBatchIterator, RecordBatchReader};
-use arrow_schema::SchemaRef;
+use arrow_schema::{Schema, SchemaRef};
+use futures::TryStreamExt;
 use lance::dataset::{ReadParams, WriteMode};
 use lance::io::{ObjectStore, ObjectStoreParams, WrappingObjectStore};
-use object_store::{aws::AwsCredential, local::LocalFileSystem};
+use object_store::{aws::AwsCredential, local::LocalFileSystem, ObjectStore as _};
+use rand::{distributions::Alphanumeric, Rng};
 use snafu::prelude::*;
 
-use crate::arrow::IntoArrow;
+use crate::arrow::{IntoArrow, SendableRecordBatchStream};
+use crate::data::import::{self, ParquetImportOptions};
 use crate::embeddings::{
-    EmbeddingDefinition, EmbeddingFunction, EmbeddingRegistry, MemoryRegistry, WithEmbeddings,
+    check_registered_functions, default_registry, EmbeddingDefinition, EmbeddingFunction,
+    EmbeddingRegistry, WithEmbeddings,
 };
 use crate::error::{CreateDirSnafu, Error, InvalidTableNameSnafu, Result};
 use crate::io::object_store::MirroringObjectStoreWrapper;
-use crate::table::{NativeTable, TableDefinition, WriteOptions};
+use crate::table::{
+    CoercingReader, Coercion, CommitRetryConfig, NativeTable, TableDefinition, TableInternal,
+    ValidationIssue, ValidationReport, Validator, WriteOptions,
+};
 use crate::utils::validate_table_name;
 use crate::Table;
 
 #[cfg(feature = "remote")]
 use log::warn;
 
+mod clone;
+mod sql;
+
+pub use self::clone::{CloneOptions, CloneProgress, CloneProgressCallback};
+
 pub const LANCE_FILE_EXTENSION: &str = "lance";
 
 pub type TableBuilderCallback = Box<dyn FnOnce(OpenTableBuilder) -> OpenTableBuilder + Send>;
@@ -51,8 +63,16 @@ pub enum CreateTableMode {
     /// If the table already exists, it is opened.  Any provided data is
     /// ignored.  The function will be passed an OpenTableBuilder to customize
     /// how the table is opened
+    ///
+    /// The schema of the existing table must match the schema of the data (or the
+    /// schema given to [`Connection::create_empty_table`]), otherwise an
+    /// [`Error::Schema`] listing the differing fields is returned.  Fields are
+    /// compared by name and type.
     ExistOk(TableBuilderCallback),
-    /// If the table already exists, it is overwritten
+    /// If the table already exists, its data is replaced
+    ///
+    /// The data is replaced in a single commit, readers see either the old or
+    /// the new table.
     Overwrite,
 }
 
@@ -64,6 +84,48 @@ impl CreateTableMode {
     }
 }
 
+/// Check that a table that already exists has the schema it was created with
+///
+/// See [`CreateTableMode::ExistOk`]
+pub(crate) fn check_existing_schema(
+    name: &str,
+    requested: &Schema,
+    existing: &Schema,
+) -> Result<()> {
+    let mut differences = Vec::new();
+    for field in requested.fields() {
+        match existing.field_with_name(field.name()) {
+            Ok(existing_field) if existing_field.data_type() == field.data_type() => {}
+            Ok(existing_field) => differences.push(format!(
+                "'{}' is {} in the table but {} was given",
+                field.name(),
+                existing_field.data_type(),
+                field.data_type()
+            )),
+            Err(_) => differences.push(format!("'{}' is not in the table", field.name())),
+        }
+    }
+    for field in existing.fields() {
+        if requested.field_with_name(field.name()).is_err() {
+            differences.push(format!(
+                "'{}' is in the table but was not given",
+                field.name()
+            ));
+        }
+    }
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Schema {
+            message: format!(
+                "table '{}' already exists with a different schema: {}",
+                name,
+                differences.join(", ")
+            ),
+        })
+    }
+}
+
 impl Default for CreateTableMode {
     fn default() -> Self {
         Self::Create
@@ -90,14 +152,16 @@ pub struct TableNamesBuilder {
     parent: Arc<dyn ConnectionInternal>,
     pub(crate) start_after: Option<String>,
     pub(crate) limit: Option<u32>,
+    pub(crate) prefix: Option<String>,
 }
 
 impl TableNamesBuilder {
-    fn new(parent: Arc<dyn ConnectionInternal>) -> Self {
+    pub(crate) fn new(parent: Arc<dyn ConnectionInternal>) -> Self {
         Self {
             parent,
             start_after: None,
             limit: None,
+            prefix: None,
         }
     }
 
@@ -117,6 +181,12 @@ impl TableNamesBuilder {
         self
     }
 
+    /// If present, only return names that start with the supplied value
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
     /// Execute the table names operation
     pub async fn execute(self) -> Result<Vec<String>> {
         self.parent.clone().table_names(self).await
@@ -164,6 +234,20 @@ impl<T: IntoArrow> CreateTableBuilder<true, T> {
         self
     }
 
+    /// The schema of the new table
+    ///
+    /// The data must match the schema, except that a list column of the data is
+    /// converted to the vector column of the same name if the items have the same
+    /// type.  Every list must then have the dimension of the vectors, or the create
+    /// fails with an error that names the first row that doesn't.  Embedding columns
+    /// are computed and don't have to be in the data.
+    ///
+    /// By default, the schema of the table is the schema of the data.
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.table_definition = Some(TableDefinition::new_from_schema(schema));
+        self
+    }
+
     /// Execute the create table operation
     pub async fn execute(self) -> Result<Table> {
         let parent = self.parent.clone();
@@ -177,7 +261,43 @@ impl<T: IntoArrow> CreateTableBuilder<true, T> {
         Box<dyn RecordBatchReader + Send>,
         CreateTableBuilder<false, NoData>,
     )> {
-        let data = self.data.take().unwrap().into_arrow()?;
+        let mut data = self.data.take().unwrap().into_arrow()?;
+        if let Some(definition) = &self.table_definition {
+            data = Box::new(CoercingReader::new(
+                data,
+                &definition.schema,
+                Coercion::Vectors,
+            ));
+            let data_schema = data.schema();
+            let embedding_columns = self
+                .embeddings
+                .iter()
+                .map(|(embedding, _)| embedding.dest_column_name())
+                .collect::<Vec<_>>();
+            let is_missing = |name: &String| {
+                !embedding_columns.contains(name) && data_schema.field_with_name(name).is_err()
+            };
+            // Columns that aren't nullable are already reported when missing
+            let mut issues = Validator::new(definition)
+                .check_schema(&data_schema)
+                .into_iter()
+                .filter(|issue| !embedding_columns.contains(&issue.column))
+                .collect::<Vec<_>>();
+            for field in definition.schema.fields() {
+                if field.is_nullable() && is_missing(field.name()) {
+                    issues.push(ValidationIssue {
+                        batch: None,
+                        column: field.name().clone(),
+                        message: "is missing from the data".to_string(),
+                    });
+                }
+            }
+            ValidationReport {
+                issues,
+                ..Default::default()
+            }
+            .into_result()?;
+        }
         let builder = CreateTableBuilder::<false, NoData> {
             parent: self.parent,
             name: self.name,
@@ -283,6 +403,27 @@ impl<const HAS_DATA: bool, T: IntoArrow> CreateTableBuilder<HAS_DATA, T> {
         self
     }
 
+    /// Set the AWS region of the S3 bucket for this table
+    ///
+    /// See [`ConnectBuilder::aws_region`]
+    pub fn aws_region(self, region: impl Into<String>) -> Self {
+        self.storage_option("aws_region", region)
+    }
+
+    /// Set the endpoint of an S3 compatible store for this table
+    ///
+    /// See [`ConnectBuilder::aws_endpoint`]
+    pub fn aws_endpoint(self, endpoint: impl Into<String>) -> Self {
+        self.storage_option("aws_endpoint", endpoint)
+    }
+
+    /// Allow connecting to the store over plain HTTP for this table
+    ///
+    /// See [`ConnectBuilder::allow_http`]
+    pub fn allow_http(self, allow_http: bool) -> Self {
+        self.storage_option("allow_http", allow_http.to_string())
+    }
+
     /// Set to true to use the v1 format for data files
     ///
     /// This is currently defaulted to true and can be set to false to opt-in
@@ -304,6 +445,7 @@ pub struct OpenTableBuilder {
     name: String,
     index_cache_size: u32,
     lance_read_params: Option<ReadParams>,
+    read_consistency_interval: Option<Option<std::time::Duration>>,
 }
 
 impl OpenTableBuilder {
@@ -313,9 +455,27 @@ impl OpenTableBuilder {
             name,
             index_cache_size: 256,
             lance_read_params: None,
+            read_consistency_interval: None,
         }
     }
 
+    /// The interval at which the table checks for updates from other processes
+    ///
+    /// This overrides [`ConnectBuilder::read_consistency_interval`] for this table.
+    /// Zero checks on every read, `None` never checks (call [`Table::checkout_latest`]
+    /// to see new data).  A check only looks up whether the manifest of the next
+    /// version exists, the new version is loaded if it does.  Queries that are
+    /// already running keep reading the version they started with.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn read_consistency_interval(
+        mut self,
+        read_consistency_interval: impl Into<Option<std::time::Duration>>,
+    ) -> Self {
+        self.read_consistency_interval = Some(read_consistency_interval.into());
+        self
+    }
+
     /// Set the size of the index cache, specified as a number of entries
     ///
     /// The default value is 256
@@ -382,6 +542,27 @@ impl OpenTableBuilder {
         self
     }
 
+    /// Set the AWS region of the S3 bucket for this table
+    ///
+    /// See [`ConnectBuilder::aws_region`]
+    pub fn aws_region(self, region: impl Into<String>) -> Self {
+        self.storage_option("aws_region", region)
+    }
+
+    /// Set the endpoint of an S3 compatible store for this table
+    ///
+    /// See [`ConnectBuilder::aws_endpoint`]
+    pub fn aws_endpoint(self, endpoint: impl Into<String>) -> Self {
+        self.storage_option("aws_endpoint", endpoint)
+    }
+
+    /// Allow connecting to the store over plain HTTP for this table
+    ///
+    /// See [`ConnectBuilder::allow_http`]
+    pub fn allow_http(self, allow_http: bool) -> Self {
+        self.storage_option("allow_http", allow_http.to_string())
+    }
+
     /// Open the table
     pub async fn execute(self) -> Result<Table> {
         self.parent.clone().do_open_table(self).await
@@ -393,6 +574,10 @@ pub(crate) trait ConnectionInternal:
     Send + Sync + std::fmt::Debug + std::fmt::Display + 'static
 {
     fn embedding_registry(&self) -> &dyn EmbeddingRegistry;
+    /// The storage options that are used to access files, e.g. when importing data
+    fn storage_options(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
     async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>>;
     async fn do_create_table(
         &self,
@@ -401,6 +586,7 @@ pub(crate) trait ConnectionInternal:
     ) -> Result<Table>;
     async fn do_open_table(&self, options: OpenTableBuilder) -> Result<Table>;
     async fn drop_table(&self, name: &str) -> Result<()>;
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<()>;
     async fn drop_db(&self) -> Result<()>;
 
     async fn do_create_empty_table(
@@ -429,6 +615,14 @@ impl std::fmt::Display for Connection {
 }
 
 impl Connection {
+    #[cfg(all(test, feature = "remote"))]
+    pub(crate) fn new(uri: &str, internal: Arc<dyn ConnectionInternal>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            internal,
+        }
+    }
+
     /// Get the URI of the connection
     pub fn uri(&self) -> &str {
         self.uri.as_str()
@@ -438,7 +632,9 @@ impl Connection {
     ///
     /// The names will be returned in lexicographical order (ascending)
     ///
-    /// The parameters `page_token` and `limit` can be used to paginate the results
+    /// The parameters `start_after` and `limit` can be used to paginate the results,
+    /// and `prefix` to only list some of the tables.  LanceDB Cloud returns the names
+    /// in pages, all of which are fetched unless a limit is set.
     pub fn table_names(&self) -> TableNamesBuilder {
         TableNamesBuilder::new(self.internal.clone())
     }
@@ -484,12 +680,73 @@ impl Connection {
 
     /// Drop a table in the database.
     ///
+    /// Returns [`Error::TableNotFound`] if the table does not exist, see
+    /// [`Self::drop_table_if_exists`].
+    ///
+    /// Tables that are open can be dropped.  Writes to them fail with
+    /// [`Error::TableNotFound`] afterwards, as do reads once they check for a newer
+    /// version of the table (see [`ConnectBuilder::read_consistency_interval`] and
+    /// [`Table::checkout_latest`]).  Until then reads may still be served from the
+    /// version of the table that was loaded.
+    ///
     /// # Arguments
     /// * `name` - The name of the table to drop
     pub async fn drop_table(&self, name: impl AsRef<str>) -> Result<()> {
         self.internal.drop_table(name.as_ref()).await
     }
 
+    /// Drop a table in the database if it exists
+    ///
+    /// Like [`Self::drop_table`], but a missing table is not an error.  Returns
+    /// whether the table was dropped.
+    pub async fn drop_table_if_exists(&self, name: impl AsRef<str>) -> Result<bool> {
+        match self.internal.drop_table(name.as_ref()).await {
+            Ok(()) => Ok(true),
+            Err(Error::TableNotFound { .. }) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Drop all the tables in the database
+    ///
+    /// Unlike [`Self::drop_db`] this only removes tables, the database itself is
+    /// kept.  Tables that are dropped by someone else at the same time are skipped.
+    pub async fn drop_all_tables(&self) -> Result<()> {
+        // List all the names first, so that dropping tables doesn't affect the pages
+        for name in self.table_names().execute().await? {
+            self.drop_table_if_exists(&name).await?;
+        }
+        Ok(())
+    }
+
+    /// Rename a table in the database
+    ///
+    /// The history of the table, including its versions and tags, is kept.  The
+    /// rename fails with [`Error::TableAlreadyExists`] if a table named `new_name`
+    /// exists.  Names that only differ in case are supported, also on case
+    /// insensitive file systems.
+    ///
+    /// Tables opened before the rename still refer to the old name.  They return
+    /// [`Error::TableNotFound`] the next time they check for a newer version of the
+    /// table, see [`ConnectBuilder::read_consistency_interval`] and
+    /// [`Table::checkout_latest`].
+    ///
+    /// For local and object store databases the files of the table are moved one by
+    /// one, the table should not be used by anyone else while it is renamed.
+    ///
+    /// # Arguments
+    /// * `old_name` - The current name of the table
+    /// * `new_name` - The new name of the table
+    pub async fn rename_table(
+        &self,
+        old_name: impl AsRef<str>,
+        new_name: impl AsRef<str>,
+    ) -> Result<()> {
+        self.internal
+            .rename_table(old_name.as_ref(), new_name.as_ref())
+            .await
+    }
+
     /// Drop the database
     ///
     /// This is the same as dropping all of the tables
@@ -497,9 +754,88 @@ impl Connection {
         self.internal.drop_db().await
     }
 
-    /// Get the in-memory embedding registry.
-    /// It's important to note that the embedding registry is not persisted across connections.
-    /// So if a table contains embeddings, you will need to make sure that you are using a connection that has the same embedding functions registered
+    /// Create a table from Parquet files
+    ///
+    /// The files are read batch by batch as the table is written.  Paths can be local
+    /// paths or object store URLs, which are accessed with the storage options of the
+    /// connection, and may contain wildcards (e.g. `s3://bucket/data/*.parquet`).
+    ///
+    /// # Arguments
+    /// * `name` - The name of the table
+    /// * `paths` - The files to read, in order
+    /// * `options` - The schema of the table (inferred from the first file if not
+    ///   given) and a callback for the progress of the import
+    pub async fn create_table_from_parquet(
+        &self,
+        name: impl Into<String>,
+        paths: &[&str],
+        options: ParquetImportOptions,
+    ) -> Result<Table> {
+        let connection = self.clone();
+        let name = name.into();
+        let paths = paths
+            .iter()
+            .map(|path| path.to_string())
+            .collect::<Vec<_>>();
+        import::run_import(async move {
+            let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+            let storage_options = connection.internal.storage_options();
+            let data = import::read_parquet(&paths, storage_options, options).await?;
+            connection.create_table(name, data).execute().await
+        })
+        .await
+    }
+
+    /// Copy a table into a new table
+    ///
+    /// The rows of the source table, at the latest version or at
+    /// [`CloneOptions::version`], are streamed into the new table.  The clone
+    /// starts a new history, it does not have the versions of the source table.
+    ///
+    /// If [`CloneOptions::rebuild_indices`] is set the indices of the source table
+    /// are built on the clone once the rows are copied.  If building an index
+    /// fails the clone is kept, without the indices that were not built.
+    ///
+    /// # Arguments
+    /// * `source` - The name of the table to copy
+    /// * `target` - The name of the new table
+    pub async fn clone_table(
+        &self,
+        source: &str,
+        target: &str,
+        options: CloneOptions,
+    ) -> Result<Table> {
+        let connection = self.clone();
+        let (source, target) = (source.to_string(), target.to_string());
+        import::run_import(async move {
+            clone::clone_table(&connection, &source, &target, options).await
+        })
+        .await
+    }
+
+    /// Run a SQL query over the tables of the database
+    ///
+    /// Tables are referred to by name and are opened as the query refers to them.
+    /// Table and column names are case sensitive.  Only queries are allowed, a
+    /// statement that would change the database (e.g. `DROP TABLE`) is rejected, and
+    /// the SQL must contain a single statement.  Vector search is not available
+    /// through SQL, use [`Table::query`] for that.
+    ///
+    /// # Returns
+    /// The results of the query, [`Error::TableNotFound`] if the query refers to a
+    /// table that does not exist, or [`Error::InvalidInput`] if the SQL is not valid.
+    pub async fn sql(&self, sql: &str) -> Result<SendableRecordBatchStream> {
+        sql::sql(self, sql).await
+    }
+
+    /// Get the embedding registry, to register the embedding functions of the tables
+    /// that this connection opens or creates.
+    ///
+    /// This is the registry given to [`ConnectBuilder::embedding_registry`], or the
+    /// process-wide [`crate::embeddings::default_registry`] if none was given.
+    /// It's important to note that the embedding registry is not persisted across processes.
+    /// So if a table contains embeddings, you will need to make sure that you are using a connection that has the same embedding functions registered,
+    /// or a factory that can re-create them from the configuration stored with the table (see [`crate::embeddings::EmbeddingFunctionFactory`])
     pub fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
         self.internal.embedding_registry()
     }
@@ -536,7 +872,12 @@ pub struct ConnectBuilder {
     /// consistency only applies to read operations. Write operations are
     /// always consistent.
     read_consistency_interval: Option<std::time::Duration>,
+    commit_retry_config: CommitRetryConfig,
     embedding_registry: Option<Arc<dyn EmbeddingRegistry>>,
+
+    /// Configuration for the LanceDB Cloud HTTP client
+    #[cfg(feature = "remote")]
+    client_config: crate::remote::ClientConfig,
 }
 
 impl ConnectBuilder {
@@ -548,8 +889,11 @@ impl ConnectBuilder {
             region: None,
             host_override: None,
             read_consistency_interval: None,
+            commit_retry_config: CommitRetryConfig::default(),
             storage_options: HashMap::new(),
             embedding_registry: None,
+            #[cfg(feature = "remote")]
+            client_config: Default::default(),
         }
     }
 
@@ -569,6 +913,9 @@ impl ConnectBuilder {
     }
 
     /// Provide a custom [`EmbeddingRegistry`] to use for this connection.
+    ///
+    /// The tables that the connection opens or creates use it to compute their
+    /// embeddings.  By default connections share [`crate::embeddings::default_registry`].
     pub fn embedding_registry(mut self, registry: Arc<dyn EmbeddingRegistry>) -> Self {
         self.embedding_registry = Some(registry);
         self
@@ -609,6 +956,29 @@ impl ConnectBuilder {
         self
     }
 
+    /// Set the AWS region of the S3 bucket, the `aws_region` storage option
+    ///
+    /// Tables inherit this, but it can be overridden when creating or opening them.
+    pub fn aws_region(self, region: impl Into<String>) -> Self {
+        self.storage_option("aws_region", region)
+    }
+
+    /// Set the endpoint of an S3 compatible store, e.g. MinIO, the `aws_endpoint`
+    /// storage option
+    ///
+    /// Tables inherit this, but it can be overridden when creating or opening them.
+    pub fn aws_endpoint(self, endpoint: impl Into<String>) -> Self {
+        self.storage_option("aws_endpoint", endpoint)
+    }
+
+    /// Allow connecting to the store over plain HTTP, the `allow_http` storage option
+    ///
+    /// This is needed for endpoints such as a local MinIO server that don't use TLS.
+    /// Tables inherit this, but it can be overridden when creating or opening them.
+    pub fn allow_http(self, allow_http: bool) -> Self {
+        self.storage_option("allow_http", allow_http.to_string())
+    }
+
     /// The interval at which to check for updates from other processes. This
     /// only affects LanceDB OSS.
     ///
@@ -632,6 +1002,74 @@ impl ConnectBuilder {
         self
     }
 
+    /// Set how writes that conflict with concurrent writes to the same table are
+    /// retried, see [`CommitRetryConfig`]. This only affects LanceDB OSS.
+    pub fn commit_retry_config(mut self, commit_retry_config: CommitRetryConfig) -> Self {
+        self.commit_retry_config = commit_retry_config;
+        self
+    }
+
+    /// Set how requests to LanceDB Cloud are retried when they fail with a transient error
+    ///
+    /// This option only applies to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn retry_config(mut self, retry_config: crate::remote::RetryConfig) -> Self {
+        self.client_config.retry_config = retry_config;
+        self
+    }
+
+    /// Set the maximum time a request to LanceDB Cloud may take
+    ///
+    /// The default is 30 seconds.  Individual queries can override this with
+    /// [`crate::query::QueryExecutionOptions::timeout`].  This option only applies
+    /// to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.client_config.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum time to wait for a connection to LanceDB Cloud
+    ///
+    /// This option only applies to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn connect_timeout(mut self, connect_timeout: std::time::Duration) -> Self {
+        self.client_config.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Set the `User-Agent` sent with requests to LanceDB Cloud
+    ///
+    /// This option only applies to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.client_config.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Add headers that are sent with every request to LanceDB Cloud
+    ///
+    /// This is useful when connecting through a proxy that expects extra headers.
+    /// Invalid headers, or headers that would replace the ones used for
+    /// authentication, cause [`Self::execute`] to fail.
+    ///
+    /// This option only applies to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn extra_headers(mut self, extra_headers: HashMap<String, String>) -> Self {
+        self.client_config.extra_headers.extend(extra_headers);
+        self
+    }
+
+    /// Compress data uploaded to LanceDB Cloud, e.g. when adding data to a table
+    ///
+    /// This reduces the bandwidth needed for uploads at the cost of some CPU time.
+    /// This option only applies to LanceDB Cloud connections.
+    #[cfg(feature = "remote")]
+    pub fn upload_compression(mut self, compression: crate::remote::Compression) -> Self {
+        self.client_config.upload_compression = Some(compression);
+        self
+    }
+
     #[cfg(feature = "remote")]
     fn execute_remote(self) -> Result<Connection> {
         let region = self.region.ok_or_else(|| Error::InvalidInput {
@@ -646,6 +1084,8 @@ impl ConnectBuilder {
             &api_key,
             &region,
             self.host_override,
+            self.client_config,
+            self.embedding_registry.unwrap_or_else(default_registry),
         )?);
         Ok(Connection {
             internal,
@@ -697,6 +1137,7 @@ struct Database {
     pub(crate) store_wrapper: Option<Arc<dyn WrappingObjectStore>>,
 
     read_consistency_interval: Option<std::time::Duration>,
+    commit_retry_config: CommitRetryConfig,
 
     // Storage options to be inherited by tables created from this connection
     storage_options: HashMap<String, String>,
@@ -734,12 +1175,7 @@ impl Database {
         // TODO: pass params regardless of OS
         match parse_res {
             Ok(url) if url.scheme().len() == 1 && cfg!(windows) => {
-                Self::open_path(
-                    uri,
-                    options.read_consistency_interval,
-                    options.embedding_registry.clone(),
-                )
-                .await
+                Self::open_path(uri, options).await
             }
             Ok(mut url) => {
                 // iter thru the query params and extract the commit store param
@@ -812,7 +1248,7 @@ impl Database {
                 let embedding_registry = options
                     .embedding_registry
                     .clone()
-                    .unwrap_or_else(|| Arc::new(MemoryRegistry::new()));
+                    .unwrap_or_else(default_registry);
                 Ok(Self {
                     uri: table_base_uri,
                     query_string,
@@ -820,33 +1256,25 @@ impl Database {
                     object_store,
                     store_wrapper: write_store_wrapper,
                     read_consistency_interval: options.read_consistency_interval,
+                    commit_retry_config: options.commit_retry_config.clone(),
                     storage_options,
                     embedding_registry,
                 })
             }
-            Err(_) => {
-                Self::open_path(
-                    uri,
-                    options.read_consistency_interval,
-                    options.embedding_registry.clone(),
-                )
-                .await
-            }
+            Err(_) => Self::open_path(uri, options).await,
         }
     }
 
-    async fn open_path(
-        path: &str,
-        read_consistency_interval: Option<std::time::Duration>,
-        embedding_registry: Option<Arc<dyn EmbeddingRegistry>>,
-    ) -> Result<Self> {
+    async fn open_path(path: &str, options: &ConnectBuilder) -> Result<Self> {
         let (object_store, base_path) = ObjectStore::from_uri(path).await?;
         if object_store.is_local() {
             Self::try_create_dir(path).context(CreateDirSnafu { path })?;
         }
 
-        let embedding_registry =
-            embedding_registry.unwrap_or_else(|| Arc::new(MemoryRegistry::new()));
+        let embedding_registry = options
+            .embedding_registry
+            .clone()
+            .unwrap_or_else(default_registry);
 
         Ok(Self {
             uri: path.to_string(),
@@ -854,8 +1282,10 @@ impl Database {
             base_path,
             object_store,
             store_wrapper: None,
-            read_consistency_interval,
-            storage_options: HashMap::new(),
+            read_consistency_interval: options.read_consistency_interval,
+            commit_retry_config: options.commit_retry_config.clone(),
+            // Not needed to open the directory, but inherited by the tables
+            storage_options: options.storage_options.clone(),
             embedding_registry,
         })
     }
@@ -869,6 +1299,51 @@ impl Database {
         Ok(())
     }
 
+    /// Move all the files under `from` to `to`
+    ///
+    /// Object stores have no directories, so the files are renamed one by one.  The
+    /// manifests are moved last so the table only shows up at `to` once all of its
+    /// data is there.  If a file can't be moved, the files that were already moved
+    /// are moved back.
+    async fn move_dir(
+        &self,
+        from: &object_store::path::Path,
+        to: &object_store::path::Path,
+    ) -> Result<()> {
+        let store = self.object_store.inner.clone();
+        let mut moves = store
+            .list(Some(from))
+            .map_ok(|meta| {
+                let target = meta
+                    .location
+                    .prefix_match(from)
+                    .into_iter()
+                    .flatten()
+                    .fold(to.clone(), |target, part| target.child(part));
+                (meta.location, target)
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+        moves.sort_by_key(|(source, _)| source.parts().any(|part| part.as_ref() == "_versions"));
+
+        for (i, (source, target)) in moves.iter().enumerate() {
+            if let Err(err) = store.rename(source, target).await {
+                for (source, target) in moves[..i].iter().rev() {
+                    if let Err(err) = store.rename(target, source).await {
+                        log::warn!("failed to move '{}' back to '{}': {}", target, source, err);
+                    }
+                }
+                return Err(err.into());
+            }
+        }
+
+        // Local file systems keep the (now empty) directories around
+        match self.object_store.remove_dir_all(from.clone()).await {
+            Ok(()) | Err(lance::Error::NotFound { .. }) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// Get the URI of a table in the database.
     fn table_uri(&self, name: &str) -> Result<String> {
         validate_table_name(name)?;
@@ -900,6 +1375,9 @@ impl ConnectionInternal for Database {
     fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
         self.embedding_registry.as_ref()
     }
+    fn storage_options(&self) -> HashMap<String, String> {
+        self.storage_options.clone()
+    }
     async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>> {
         let mut f = self
             .object_store
@@ -924,6 +1402,9 @@ impl ConnectionInternal for Database {
                 .unwrap_or(f.len());
             f.drain(0..index);
         }
+        if let Some(prefix) = options.prefix {
+            f.retain(|name| name.starts_with(&prefix));
+        }
         if let Some(limit) = options.limit {
             f.truncate(limit as usize);
         }
@@ -954,8 +1435,15 @@ impl ConnectionInternal for Database {
         let data = if options.embeddings.is_empty() {
             data
         } else {
-            Box::new(WithEmbeddings::new(data, options.embeddings))
+            // Embed the start of the data up front, so that the embeddings are awaited
+            // rather than computed while lance reads the data
+            Box::new(
+                WithEmbeddings::try_new(data, options.embeddings)?
+                    .embed_ahead(self.commit_retry_config.max_replayable_size)
+                    .await?,
+            )
         };
+        let schema = data.schema();
 
         let mut write_params = options.write_options.lance_write_params.unwrap_or_default();
         if matches!(&options.mode, CreateTableMode::Overwrite) {
@@ -974,15 +1462,17 @@ impl ConnectionInternal for Database {
         .await
         {
             Ok(table) => Ok(Table::new_with_embedding_registry(
-                Arc::new(table),
+                Arc::new(table.with_commit_retry_config(self.commit_retry_config.clone())),
                 embedding_registry,
             )),
             Err(Error::TableAlreadyExists { name }) => match options.mode {
                 CreateTableMode::Create => Err(Error::TableAlreadyExists { name }),
                 CreateTableMode::ExistOk(callback) => {
-                    let builder = OpenTableBuilder::new(options.parent, options.name);
+                    let builder = OpenTableBuilder::new(options.parent, options.name.clone());
                     let builder = (callback)(builder);
-                    builder.execute().await
+                    let table = builder.execute().await?;
+                    check_existing_schema(&options.name, &schema, &table.schema().await?)?;
+                    Ok(table)
                 }
                 CreateTableMode::Overwrite => unreachable!(),
             },
@@ -1024,11 +1514,22 @@ impl ConnectionInternal for Database {
                 &options.name,
                 self.store_wrapper.clone(),
                 Some(read_params),
-                self.read_consistency_interval,
+                options
+                    .read_consistency_interval
+                    .unwrap_or(self.read_consistency_interval),
             )
-            .await?,
+            .await?
+            .with_commit_retry_config(self.commit_retry_config.clone()),
         );
-        Ok(Table::new(native_table))
+        // Fail early if a registered embedding function no longer matches the table
+        check_registered_functions(
+            self.embedding_registry.as_ref(),
+            &native_table.table_definition().await?,
+        )?;
+        Ok(Table::new_with_embedding_registry(
+            native_table,
+            self.embedding_registry.clone(),
+        ))
     }
 
     async fn drop_table(&self, name: &str) -> Result<()> {
@@ -1048,6 +1549,61 @@ impl ConnectionInternal for Database {
         Ok(())
     }
 
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<()> {
+        validate_table_name(new_name)?;
+        let old_dir = format!("{}.{}", old_name, LANCE_EXTENSION);
+        let new_dir = format!("{}.{}", new_name, LANCE_EXTENSION);
+        // The listing has the names as they are stored, even on case insensitive
+        // file systems
+        let dirs = self.object_store.read_dir(self.base_path.clone()).await?;
+        if !dirs.contains(&old_dir) {
+            return Err(Error::TableNotFound {
+                name: old_name.to_owned(),
+            });
+        }
+        if old_name == new_name {
+            return Ok(());
+        }
+
+        let old_path = self.base_path.child(old_dir);
+        let new_path = self.base_path.child(new_dir.clone());
+        if old_name.to_lowercase() == new_name.to_lowercase() {
+            if dirs.contains(&new_dir) {
+                return Err(Error::TableAlreadyExists {
+                    name: new_name.to_owned(),
+                });
+            }
+            // On a case insensitive file system both names refer to the same
+            // directory, so move the files out of the way first
+            let token: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(8)
+                .map(char::from)
+                .collect();
+            let tmp_path = self.base_path.child(format!(".{}.{}.tmp", new_dir, token));
+            self.move_dir(&old_path, &tmp_path).await?;
+            if let Err(err) = self.move_dir(&tmp_path, &new_path).await {
+                self.move_dir(&tmp_path, &old_path).await?;
+                return Err(err);
+            }
+            return Ok(());
+        }
+
+        // Also catches tables whose name only differs in case on case insensitive
+        // file systems
+        let existing = self
+            .object_store
+            .inner
+            .list_with_delimiter(Some(&new_path))
+            .await?;
+        if !existing.objects.is_empty() || !existing.common_prefixes.is_empty() {
+            return Err(Error::TableAlreadyExists {
+                name: new_name.to_owned(),
+            });
+        }
+        self.move_dir(&old_path, &new_path).await
+    }
+
     async fn drop_db(&self) -> Result<()> {
         self.object_store
             .remove_dir_all(self.base_path.clone())
@@ -1058,6 +1614,7 @@ impl ConnectionInternal for Database {
 
 #[cfg(test)]
 mod tests {
+    use arrow_array::{types::Float32Type, Int32Array, ListArray, RecordBatch};
     use arrow_schema::{DataType, Field, Schema};
     use futures::TryStreamExt;
     use lance_testing::datagen::{BatchGenerator, IncrementingInt32};
@@ -1076,6 +1633,35 @@ mod tests {
         assert_eq!(db.uri, uri);
     }
 
+    #[tokio::test]
+    async fn test_storage_options() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri)
+            .aws_region("us-east-1")
+            .allow_http(true)
+            .execute()
+            .await
+            .unwrap();
+        // Plain paths keep the options as well, so that tables inherit them
+        let options = db.internal.storage_options();
+        assert_eq!(options["aws_region"], "us-east-1");
+        assert_eq!(options["allow_http"], "true");
+
+        let builder = db
+            .open_table("test")
+            .aws_endpoint("http://127.0.0.1:9000")
+            .aws_region("eu-west-1");
+        let options = builder
+            .lance_read_params
+            .as_ref()
+            .and_then(|params| params.store_options.as_ref())
+            .and_then(|params| params.storage_options.as_ref())
+            .unwrap();
+        assert_eq!(options["aws_endpoint"], "http://127.0.0.1:9000");
+        assert_eq!(options["aws_region"], "eu-west-1");
+    }
+
     #[cfg(not(windows))]
     #[tokio::test]
     async fn test_connect_relative() {
@@ -1137,6 +1723,16 @@ mod tests {
         let tables = db.table_names().limit(7).execute().await.unwrap();
 
         assert_eq!(tables, names[..7]);
+
+        let prefix = &names[50][..1];
+        let tables = db.table_names().prefix(prefix).execute().await.unwrap();
+        let expected = names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(!tables.is_empty());
+        assert_eq!(tables, expected);
     }
 
     #[tokio::test]
@@ -1164,9 +1760,18 @@ mod tests {
             .execute()
             .await
             .unwrap();
-        db.open_table("table1").execute().await.unwrap();
+        let table = db.open_table("table1").execute().await.unwrap();
         let tables = db.table_names().execute().await.unwrap();
         assert_eq!(tables, vec!["table1".to_owned()]);
+
+        // The location of the table is in the database
+        assert_eq!(db.uri(), uri);
+        assert!(!table.is_remote());
+        let table_uri = table.uri().unwrap();
+        assert_eq!(
+            std::path::Path::new(&table_uri),
+            tmp_dir.path().join("table1.lance")
+        );
     }
 
     fn make_data() -> impl RecordBatchReader + Send + 'static {
@@ -1191,6 +1796,7 @@ mod tests {
             .query()
             .execute_with_options(QueryExecutionOptions {
                 max_batch_length: 50000,
+                ..Default::default()
             })
             .await
             .unwrap()
@@ -1211,6 +1817,7 @@ mod tests {
             .query()
             .execute_with_options(QueryExecutionOptions {
                 max_batch_length: 50000,
+                ..Default::default()
             })
             .await
             .unwrap()
@@ -1221,6 +1828,235 @@ mod tests {
         assert_eq!(batches.len(), 1);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_clone_table() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+
+        let source = db
+            .create_table("source", make_data())
+            .execute()
+            .await
+            .unwrap();
+        source
+            .create_index(&["id"], crate::index::Index::BTree(Default::default()))
+            .execute()
+            .await
+            .unwrap();
+        source.add(make_data()).execute().await.unwrap();
+
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = CloneOptions {
+            version: Some(2),
+            rebuild_indices: true,
+            progress: Some(Arc::new({
+                let reported = reported.clone();
+                move |progress: &CloneProgress| reported.lock().unwrap().push(progress.clone())
+            })),
+            ..Default::default()
+        };
+        let clone = db
+            .clone_table("source", "clone", options.clone())
+            .await
+            .unwrap();
+        assert_eq!(clone.count_rows(None).await.unwrap(), 20_000);
+        assert_eq!(clone.version().await.unwrap(), 2);
+        let indices = clone.list_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].columns, vec!["id"]);
+
+        let reported = reported.lock().unwrap().clone();
+        assert!(matches!(
+            reported
+                .iter()
+                .rev()
+                .find(|p| matches!(p, CloneProgress::CopyingRows { .. })),
+            Some(CloneProgress::CopyingRows {
+                rows: 20_000,
+                total_rows: 20_000
+            })
+        ));
+        assert!(matches!(
+            reported.last(),
+            Some(CloneProgress::BuildingIndex {
+                index: 0,
+                num_indices: 1,
+                ..
+            })
+        ));
+
+        // The destination is only replaced when asked to
+        assert!(matches!(
+            db.clone_table("source", "clone", CloneOptions::default()).await,
+            Err(Error::TableAlreadyExists { name }) if name == "clone"
+        ));
+        let clone = db
+            .clone_table(
+                "source",
+                "clone",
+                CloneOptions {
+                    overwrite: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(clone.count_rows(None).await.unwrap(), 40_000);
+
+        assert!(matches!(
+            db.clone_table(
+                "source",
+                "shallow",
+                CloneOptions {
+                    shallow: true,
+                    ..Default::default()
+                },
+            )
+            .await,
+            Err(Error::NotSupported { .. })
+        ));
+        assert!(matches!(
+            db.clone_table("missing", "other", CloneOptions::default())
+                .await,
+            Err(Error::TableNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_drop_table_open_handles() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let data = || {
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1]))])
+                    .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        db.create_table("test", data()).execute().await.unwrap();
+        let table = db.open_table("test").execute().await.unwrap();
+        let consistent = db
+            .open_table("test")
+            .read_consistency_interval(std::time::Duration::from_secs(0))
+            .execute()
+            .await
+            .unwrap();
+
+        db.drop_table("test").await.unwrap();
+
+        // Writes don't create the table again
+        assert!(matches!(
+            table.add(data()).execute().await,
+            Err(Error::TableNotFound { name }) if name == "test"
+        ));
+        assert!(matches!(
+            table.delete("i = 1").await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(matches!(
+            table.update().column("i", "2").execute().await,
+            Err(Error::TableNotFound { .. })
+        ));
+        let mut merge_insert = table.merge_insert(&["i"]);
+        merge_insert.when_not_matched_insert_all();
+        assert!(matches!(
+            merge_insert.execute(Box::new(data())).await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(db.table_names().execute().await.unwrap().is_empty());
+        // Reads fail once they check for a newer version
+        assert!(matches!(
+            consistent.count_rows(None).await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(matches!(
+            table.checkout_latest().await,
+            Err(Error::TableNotFound { .. })
+        ));
+
+        assert!(matches!(
+            db.drop_table("test").await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(!db.drop_table_if_exists("test").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_drop_all_tables() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        for name in ["a", "b", "c"] {
+            db.create_empty_table(name, schema.clone())
+                .execute()
+                .await
+                .unwrap();
+        }
+        assert!(db.drop_table_if_exists("b").await.unwrap());
+
+        db.drop_all_tables().await.unwrap();
+        assert!(db.table_names().execute().await.unwrap().is_empty());
+        // The database is still usable
+        db.create_empty_table("d", schema).execute().await.unwrap();
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["d"]);
+    }
+
+    #[tokio::test]
+    async fn test_rename_table() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri)
+            .read_consistency_interval(std::time::Duration::from_secs(0))
+            .execute()
+            .await
+            .unwrap();
+
+        let old = db.create_table("old", make_data()).execute().await.unwrap();
+        old.add(make_data()).execute().await.unwrap();
+        db.rename_table("old", "new").await.unwrap();
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["new"]);
+
+        // The history is kept
+        let new = db.open_table("new").execute().await.unwrap();
+        assert_eq!(new.count_rows(None).await.unwrap(), 40_000);
+        assert_eq!(new.list_versions().await.unwrap().len(), 2);
+
+        // Handles to the old name no longer find the table
+        assert!(matches!(
+            old.count_rows(None).await,
+            Err(Error::TableNotFound { name }) if name == "old"
+        ));
+        assert!(matches!(
+            db.rename_table("old", "other").await,
+            Err(Error::TableNotFound { name }) if name == "old"
+        ));
+
+        db.create_table("other", make_data())
+            .execute()
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.rename_table("new", "other").await,
+            Err(Error::TableAlreadyExists { name }) if name == "other"
+        ));
+        assert!(matches!(
+            db.rename_table("new", "not/valid").await,
+            Err(Error::InvalidTableName { .. })
+        ));
+
+        // Case only renames work on case insensitive file systems too
+        db.rename_table("new", "New").await.unwrap();
+        assert_eq!(
+            db.table_names().execute().await.unwrap(),
+            vec!["New", "other"]
+        );
+        let new = db.open_table("New").execute().await.unwrap();
+        assert_eq!(new.count_rows(None).await.unwrap(), 40_000);
+    }
+
     #[tokio::test]
     async fn drop_table() {
         let tmp_dir = tempdir().unwrap();
@@ -1274,4 +2110,248 @@ mod tests {
             .unwrap();
         assert_eq!(other_schema, overwritten.schema().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_create_table_modes() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = |column: &str| {
+            Arc::new(Schema::new(vec![Field::new(
+                column,
+                DataType::Int32,
+                false,
+            )]))
+        };
+        let data = |column: &str, rows: i32| {
+            let batch = RecordBatch::try_new(
+                schema(column),
+                vec![Arc::new(Int32Array::from_iter_values(0..rows))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema(column))
+        };
+
+        // Create
+        db.create_table("create", data("x", 2))
+            .execute()
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.create_table("create", data("x", 3)).execute().await,
+            Err(Error::TableAlreadyExists { .. })
+        ));
+        let table = db.open_table("create").execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+
+        // ExistOk keeps the existing data, as long as the schema matches
+        for _ in 0..2 {
+            let table = db
+                .create_table("exist_ok", data("x", 2))
+                .mode(CreateTableMode::exist_ok(|builder| builder))
+                .execute()
+                .await
+                .unwrap();
+            assert_eq!(table.count_rows(None).await.unwrap(), 2);
+            assert_eq!(table.schema().await.unwrap(), schema("x"));
+        }
+        let err = db
+            .create_table("exist_ok", data("y", 3))
+            .mode(CreateTableMode::exist_ok(|builder| builder))
+            .execute()
+            .await
+            .unwrap_err();
+        let Error::Schema { message } = err else {
+            panic!("Expected a schema error, got {:?}", err);
+        };
+        assert!(
+            message.contains("'x'") && message.contains("'y'"),
+            "{}",
+            message
+        );
+
+        // Overwrite replaces the data and the schema
+        for (column, rows) in [("x", 2), ("y", 3)] {
+            let table = db
+                .create_table("overwrite", data(column, rows))
+                .mode(CreateTableMode::Overwrite)
+                .execute()
+                .await
+                .unwrap();
+            assert_eq!(table.count_rows(None).await.unwrap(), rows as usize);
+            assert_eq!(table.schema().await.unwrap(), schema(column));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_table_with_schema() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("vector", DataType::FixedSizeList(item.clone(), 2), true),
+        ]));
+        let data = |lengths: &[usize]| {
+            let data_schema = Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, true),
+                Field::new("vector", DataType::List(item.clone()), true),
+            ]));
+            let vectors = ListArray::from_iter_primitive::<Float32Type, _, _>(
+                lengths.iter().map(|&length| Some(vec![Some(1.0); length])),
+            );
+            let batch = RecordBatch::try_new(
+                data_schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(0..lengths.len() as i32)),
+                    Arc::new(vectors),
+                ],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], data_schema)
+        };
+
+        let table = db
+            .create_table("test", data(&[2, 2, 2]))
+            .schema(schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.schema().await.unwrap(), schema);
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+
+        let err = db
+            .create_table("wrong_length", data(&[2, 2, 1]))
+            .schema(schema.clone())
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the vector of row 2 has dimension 1 but the table expects 2"),
+            "{}",
+            err
+        );
+
+        // The data must otherwise match the schema
+        let other_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("vector", DataType::FixedSizeList(item.clone(), 2), true),
+            Field::new("text", DataType::Utf8, true),
+        ]));
+        let err = db
+            .create_table("mismatch", data(&[2]))
+            .schema(other_schema)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("column 'id' has type Int32")
+                && err.to_string().contains("column 'text' is missing"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_import_files() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().join("db").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+
+        let batch = BatchGenerator::new()
+            .col(Box::new(IncrementingInt32::new().named("id")))
+            .batch(20)
+            .next()
+            .unwrap()
+            .unwrap();
+        let parquet = tmp_dir.path().join("data.parquet");
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&parquet).unwrap(),
+            batch.schema(),
+            None,
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let table = db
+            .create_table_from_parquet(
+                "imported",
+                &[parquet.to_str().unwrap()],
+                ParquetImportOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(table.schema().await.unwrap(), batch.schema());
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        let csv = tmp_dir.path().join("data.csv");
+        std::fs::write(&csv, "id\n100\n101\n").unwrap();
+        table
+            .add_from_csv(csv.to_str().unwrap(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 22);
+        assert_eq!(
+            table
+                .count_rows(Some("id >= 100".to_string()))
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    // The reader of an import waits for the files with `block_in_place`, which is
+    // only possible on multi-threaded runtimes
+    #[tokio::test]
+    async fn test_import_current_thread() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().join("db").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let batch = BatchGenerator::new()
+            .col(Box::new(IncrementingInt32::new().named("id")))
+            .batch(20)
+            .next()
+            .unwrap()
+            .unwrap();
+        let parquet = tmp_dir.path().join("data.parquet");
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&parquet).unwrap(),
+            batch.schema(),
+            None,
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let table = db
+            .create_table_from_parquet(
+                "imported",
+                &[parquet.to_str().unwrap()],
+                ParquetImportOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        let csv = tmp_dir.path().join("data.csv");
+        std::fs::write(&csv, "id\n100\n101\n").unwrap();
+        table
+            .add_from_csv(csv.to_str().unwrap(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 22);
+
+        let clone = db
+            .clone_table("imported", "clone", CloneOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(clone.count_rows(None).await.unwrap(), 22);
+    }
 }
@@ -0,0 +1,156 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copying a table into a new table, see [`super::Connection::clone_table`]
+
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use futures::StreamExt;
+
+use crate::data::import::{ImportProgress, ImportProgressCallback, ImportReader};
+use crate::error::{Error, Result};
+use crate::index::Index;
+use crate::query::ExecutableQuery;
+use crate::Table;
+
+use super::{Connection, CreateTableMode};
+
+/// The progress of a clone, see [`CloneOptions::progress`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloneProgress {
+    /// Reported after each batch of rows that is copied
+    CopyingRows {
+        /// The number of rows copied so far
+        rows: usize,
+        /// The number of rows in the source table
+        total_rows: usize,
+    },
+    /// Reported before each index is built on the clone
+    BuildingIndex {
+        /// The name of the index on the source table
+        name: String,
+        /// The position of the index in the indices that are built
+        index: usize,
+        /// The number of indices that are built
+        num_indices: usize,
+    },
+}
+
+pub type CloneProgressCallback = Arc<dyn Fn(&CloneProgress) + Send + Sync>;
+
+/// Options for [`super::Connection::clone_table`]
+#[derive(Default, Clone)]
+pub struct CloneOptions {
+    /// Share the data files of the source table instead of copying them
+    ///
+    /// Shallow clones are not supported yet, the version of lance in use cannot
+    /// refer to the data files of another table.
+    pub shallow: bool,
+    /// The version of the source table to clone, the latest version if not set
+    pub version: Option<u64>,
+    /// Build the indices of the source table on the clone
+    ///
+    /// The indices are built with the parameters the source table reports, see
+    /// [`crate::index::IndexConfig`], other parameters have their default values.
+    pub rebuild_indices: bool,
+    /// Replace the destination table if it exists
+    ///
+    /// If this is false and the destination table exists, the clone fails with
+    /// [`Error::TableAlreadyExists`].
+    pub overwrite: bool,
+    /// Called as rows are copied and before each index is built
+    pub progress: Option<CloneProgressCallback>,
+}
+
+pub(crate) async fn clone_table(
+    connection: &Connection,
+    source: &str,
+    target: &str,
+    options: CloneOptions,
+) -> Result<Table> {
+    if options.shallow {
+        return Err(Error::NotSupported {
+            message: "shallow clones are not supported yet, use a deep clone".to_string(),
+        });
+    }
+    if source == target {
+        return Err(Error::InvalidInput {
+            message: format!("cannot clone table '{}' onto itself", source),
+        });
+    }
+
+    let source_table = connection.open_table(source).execute().await?;
+    if let Some(version) = options.version {
+        source_table.checkout(version).await?;
+    }
+    // The schema of the table keeps the embedding definitions, query results don't
+    let schema = source_table.schema().await?;
+    let total_rows = source_table.count_rows(None).await?;
+    let indices = if options.rebuild_indices {
+        source_table.list_indices().await?
+    } else {
+        Vec::new()
+    };
+
+    let batches = source_table
+        .query()
+        .execute()
+        .await?
+        .map({
+            let schema = schema.clone();
+            move |batch: Result<RecordBatch>| -> Result<(usize, RecordBatch)> {
+                Ok((
+                    0,
+                    RecordBatch::try_new(schema.clone(), batch?.columns().to_vec())?,
+                ))
+            }
+        })
+        .boxed();
+    let progress = options.progress.clone().map(|progress| {
+        Arc::new(move |import: &ImportProgress| {
+            progress(&CloneProgress::CopyingRows {
+                rows: import.rows,
+                total_rows,
+            })
+        }) as ImportProgressCallback
+    });
+    let data = ImportReader::new(schema, vec![source.to_string()], batches, progress);
+
+    let mode = if options.overwrite {
+        CreateTableMode::Overwrite
+    } else {
+        CreateTableMode::Create
+    };
+    let table = connection
+        .create_table(target, data)
+        .mode(mode)
+        .execute()
+        .await?;
+
+    for (i, index) in indices.iter().enumerate() {
+        if let Some(progress) = &options.progress {
+            progress(&CloneProgress::BuildingIndex {
+                name: index.name.clone(),
+                index: i,
+                num_indices: indices.len(),
+            });
+        }
+        table
+            .create_index(&index.columns, Index::from_config(index)?)
+            .execute()
+            .await?;
+    }
+    Ok(table)
+}
@@ -0,0 +1,261 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run SQL queries over the tables of a connection with DataFusion
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use datafusion::{
+    catalog::schema::SchemaProvider,
+    datasource::TableProvider,
+    execution::context::{SQLOptions, SessionConfig, SessionContext},
+};
+use datafusion_common::DataFusionError;
+use futures::TryStreamExt;
+
+use super::Connection;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+
+/// The tables of a connection, opened when a query refers to them
+struct ConnectionSchemaProvider {
+    connection: Connection,
+    tables: Mutex<HashMap<String, Arc<dyn TableProvider>>>,
+    /// The tables that were referred to but don't exist
+    missing: Mutex<Vec<String>>,
+}
+
+impl ConnectionSchemaProvider {
+    fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            tables: Mutex::new(HashMap::new()),
+            missing: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl SchemaProvider for ConnectionSchemaProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Only the tables that have been opened, the tables of the connection are not
+    /// listed up front
+    fn table_names(&self) -> Vec<String> {
+        self.tables.lock().unwrap().keys().cloned().collect()
+    }
+
+    async fn table(&self, name: &str) -> datafusion_common::Result<Option<Arc<dyn TableProvider>>> {
+        if let Some(table) = self.tables.lock().unwrap().get(name) {
+            return Ok(Some(table.clone()));
+        }
+        let table = match self.connection.open_table(name).execute().await {
+            Ok(table) => table,
+            Err(Error::TableNotFound { .. }) => {
+                self.missing.lock().unwrap().push(name.to_string());
+                return Ok(None);
+            }
+            Err(err) => return Err(DataFusionError::External(Box::new(err))),
+        };
+        let provider = table
+            .as_table_provider()
+            .await
+            .map_err(|err| DataFusionError::External(Box::new(err)))?;
+        self.tables
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), provider.clone());
+        Ok(Some(provider))
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        self.tables.lock().unwrap().contains_key(name)
+    }
+}
+
+pub(crate) async fn sql(connection: &Connection, sql: &str) -> Result<SendableRecordBatchStream> {
+    let mut config = SessionConfig::new();
+    // Table and column names are case sensitive, like everywhere else in LanceDB
+    config.options_mut().sql_parser.enable_ident_normalization = false;
+    let ctx = SessionContext::new_with_config(config);
+    let tables = Arc::new(ConnectionSchemaProvider::new(connection.clone()));
+    let default_catalog = ctx
+        .state()
+        .config()
+        .options()
+        .catalog
+        .default_catalog
+        .clone();
+    let default_schema = ctx
+        .state()
+        .config()
+        .options()
+        .catalog
+        .default_schema
+        .clone();
+    ctx.catalog(&default_catalog)
+        .expect("the default catalog exists")
+        .register_schema(&default_schema, tables.clone())?;
+
+    let options = SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false)
+        .with_allow_statements(false);
+    let df = match ctx.sql_with_options(sql, options).await {
+        Ok(df) => df,
+        Err(err) => {
+            if let Some(name) = tables.missing.lock().unwrap().first() {
+                return Err(Error::TableNotFound { name: name.clone() });
+            }
+            return Err(match err {
+                DataFusionError::External(_) => err.into(),
+                err => Error::InvalidInput {
+                    message: format!("can't run the SQL query: {}", err),
+                },
+            });
+        }
+    };
+    let stream = df.execute_stream().await?;
+    let schema = stream.schema();
+    Ok(Box::pin(SimpleRecordBatchStream::new(
+        stream.map_err(Error::from),
+        schema,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::compute::concat_batches;
+    use arrow_array::{
+        Array, Int32Array, Int64Array, RecordBatch, RecordBatchIterator, StringArray,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+
+    use crate::connect;
+
+    use super::*;
+
+    async fn collect(db: &Connection, sql: &str) -> Result<RecordBatch> {
+        let batches = db.sql(sql).await?.try_collect::<Vec<_>>().await?;
+        Ok(concat_batches(&batches[0].schema(), &batches)?)
+    }
+
+    #[tokio::test]
+    async fn test_sql() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("category", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..10).map(|i| ["a", "b"][i % 2]),
+                )),
+            ],
+        )
+        .unwrap();
+        db.create_table(
+            "Items",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .execute()
+        .await
+        .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Utf8, false),
+            Field::new("label", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b"])),
+                Arc::new(StringArray::from(vec!["apples", "bananas"])),
+            ],
+        )
+        .unwrap();
+        db.create_table(
+            "labels",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .execute()
+        .await
+        .unwrap();
+
+        let results = collect(&db, "SELECT id FROM Items WHERE category = 'a' ORDER BY id")
+            .await
+            .unwrap();
+        assert_eq!(
+            results["id"].as_ref(),
+            &Int32Array::from(vec![0, 2, 4, 6, 8]) as &dyn Array
+        );
+
+        let results = collect(
+            &db,
+            "SELECT label, COUNT(*) AS n FROM Items JOIN labels \
+             ON Items.category = labels.category \
+             WHERE id > 2 GROUP BY label ORDER BY label",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            results["label"].as_ref(),
+            &StringArray::from(vec!["apples", "bananas"]) as &dyn Array
+        );
+        assert_eq!(
+            results["n"].as_ref(),
+            &Int64Array::from(vec![3, 4]) as &dyn Array
+        );
+
+        // Table names are case sensitive
+        let err = collect(&db, "SELECT * FROM items").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::TableNotFound { name } if name == "items"),
+            "{:?}",
+            err
+        );
+
+        for sql in [
+            "SELECT missing FROM Items",
+            "SELECT 1; SELECT 2",
+            "DROP TABLE Items",
+            "SELEC id FROM Items",
+        ] {
+            let err = collect(&db, sql).await.unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidInput { .. }),
+                "{}: {:?}",
+                sql,
+                err
+            );
+        }
+        assert_eq!(
+            db.table_names().execute().await.unwrap(),
+            vec!["Items", "labels"]
+        );
+    }
+}
@@ -14,5 +14,6 @@
 
 //! Data types, schema coercion, and data cleaning and etc.
 
+pub mod import;
 pub mod inspect;
 pub mod sanitize;
@@ -0,0 +1,741 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import data into tables from Parquet and CSV files
+//!
+//! Files are read batch by batch as the table is written, they are never loaded
+//! into memory in full.  Paths can be local paths or object store URLs (e.g.
+//! `s3://bucket/data/*.parquet`) and may contain `*`, `**` and `?` wildcards in
+//! the file name part.  Object store URLs use the same storage options as the
+//! connection, or the table, the data is imported into.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::Cursor,
+    sync::{Arc, OnceLock},
+};
+
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_csv::reader::{Decoder, Format, ReaderBuilder};
+use arrow_schema::{ArrowError, Schema, SchemaRef};
+use bytes::{Buf, Bytes};
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    Stream, StreamExt, TryStreamExt,
+};
+use lance::io::{ObjectStore, ObjectStoreParams};
+use object_store::{ObjectMeta, ObjectStore as _};
+use parquet::arrow::{
+    arrow_reader::{ArrowReaderMetadata, ArrowReaderOptions},
+    async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder},
+};
+use regex::Regex;
+
+use crate::embeddings::can_block_in_place;
+use crate::error::{Error, Result};
+
+const DEFAULT_BATCH_SIZE: usize = 8192;
+/// How much of a CSV file is read to infer its schema
+const INFER_SCHEMA_BYTES: usize = 1024 * 1024;
+const INFER_SCHEMA_RECORDS: usize = 1000;
+
+/// The progress of an import, reported after each batch that is read
+#[derive(Debug, Clone)]
+pub struct ImportProgress {
+    /// The file that is being read
+    pub path: String,
+    /// The index of the file in the files being imported
+    pub file_index: usize,
+    /// The number of files being imported
+    pub num_files: usize,
+    /// The number of rows read from the file so far
+    pub rows: usize,
+    /// True when the file has been read completely
+    pub done: bool,
+}
+
+pub type ImportProgressCallback = Arc<dyn Fn(&ImportProgress) + Send + Sync>;
+
+/// Options for [`crate::Connection::create_table_from_parquet`]
+#[derive(Default, Clone)]
+pub struct ParquetImportOptions {
+    /// The schema of the table
+    ///
+    /// If not set, the schema of the first file is used.  Columns are matched
+    /// by name and cast to the types of the schema.
+    pub schema: Option<SchemaRef>,
+    /// The number of rows in each batch that is read, 8192 by default
+    pub batch_size: Option<usize>,
+    /// Called after each batch that is read
+    pub progress: Option<ImportProgressCallback>,
+}
+
+/// Options for [`crate::Table::add_from_csv`]
+#[derive(Clone)]
+pub struct CsvOptions {
+    /// The character that separates fields, `,` by default
+    pub delimiter: u8,
+    /// Whether the first line of each file holds the column names, true by default
+    pub has_header: bool,
+    /// The schema of the files, in the order of the columns in the files
+    ///
+    /// If not set, the schema is inferred from the start of the first file.
+    pub schema_override: Option<SchemaRef>,
+    /// The number of rows in each batch that is read, 8192 by default
+    pub batch_size: Option<usize>,
+    /// Called after each batch that is read
+    pub progress: Option<ImportProgressCallback>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_header: true,
+            schema_override: None,
+            batch_size: None,
+            progress: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SourceFile {
+    store: Arc<dyn object_store::ObjectStore>,
+    meta: ObjectMeta,
+    uri: String,
+}
+
+fn import_error(uri: &str, row_group: Option<usize>, err: impl std::fmt::Display) -> Error {
+    let message = match row_group {
+        Some(row_group) => format!(
+            "failed to read row group {} of '{}': {}",
+            row_group, uri, err
+        ),
+        None => format!("failed to read '{}': {}", uri, err),
+    };
+    Error::Runtime { message }
+}
+
+/// Convert a glob pattern for the file name part of a path to a regex
+fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Find the files a path (which may contain wildcards) refers to
+async fn list_files(path: &str, params: &ObjectStoreParams) -> Result<Vec<SourceFile>> {
+    let glob_start = path.find(['*', '?']);
+    let (dir, pattern) = match path[..glob_start.unwrap_or(path.len())].rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => (".", path),
+    };
+    let (store, base) = ObjectStore::from_uri_and_params(dir, params).await?;
+    let store = store.inner.clone();
+
+    if glob_start.is_none() {
+        let meta = store
+            .head(&base.child(pattern))
+            .await
+            .map_err(|err| match err {
+                object_store::Error::NotFound { .. } => Error::InvalidInput {
+                    message: format!("the file '{}' does not exist", path),
+                },
+                err => err.into(),
+            })?;
+        return Ok(vec![SourceFile {
+            store,
+            meta,
+            uri: path.to_string(),
+        }]);
+    }
+
+    let regex = Regex::new(&glob_to_regex(pattern)).map_err(|err| Error::InvalidInput {
+        message: format!("invalid path '{}': {}", path, err),
+    })?;
+    let mut files = store
+        .list(Some(&base))
+        .try_filter_map(|meta| {
+            let relative = meta
+                .location
+                .as_ref()
+                .strip_prefix(base.as_ref())
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string();
+            let file = regex.is_match(&relative).then(|| SourceFile {
+                store: store.clone(),
+                uri: format!("{}/{}", dir.trim_end_matches('/'), relative),
+                meta,
+            });
+            future::ready(Ok(file))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    if files.is_empty() {
+        return Err(Error::InvalidInput {
+            message: format!("no files match '{}'", path),
+        });
+    }
+    files.sort_by(|a, b| a.uri.cmp(&b.uri));
+    Ok(files)
+}
+
+async fn list_all_files(
+    paths: &[&str],
+    storage_options: HashMap<String, String>,
+) -> Result<Vec<SourceFile>> {
+    if paths.is_empty() {
+        return Err(Error::InvalidInput {
+            message: "at least one file must be given".to_string(),
+        });
+    }
+    let params = ObjectStoreParams {
+        storage_options: Some(storage_options),
+        ..Default::default()
+    };
+    let mut files = Vec::new();
+    for path in paths {
+        files.extend(list_files(path, &params).await?);
+    }
+    Ok(files)
+}
+
+/// Match the columns of a batch to a schema by name, casting them if needed
+fn conform(batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    if batch.schema() == *schema {
+        return Ok(batch);
+    }
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let column = batch
+                .column_by_name(field.name())
+                .ok_or_else(|| Error::Schema {
+                    message: format!("the column '{}' is missing", field.name()),
+                })?;
+            Ok(cast_with_options(column, field.data_type(), &options)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+async fn open_parquet(file: &SourceFile) -> Result<ArrowReaderMetadata> {
+    let mut reader = ParquetObjectReader::new(file.store.clone(), file.meta.clone());
+    ArrowReaderMetadata::load_async(&mut reader, ArrowReaderOptions::new())
+        .await
+        .map_err(|err| import_error(&file.uri, None, err))
+}
+
+/// Read the row groups of a Parquet file one after the other
+fn parquet_batches(
+    file: SourceFile,
+    metadata: ArrowReaderMetadata,
+    schema: SchemaRef,
+    batch_size: usize,
+) -> impl Stream<Item = Result<RecordBatch>> + Send + 'static {
+    let num_row_groups = metadata.metadata().num_row_groups();
+    stream::iter(0..num_row_groups).flat_map(move |row_group| {
+        let reader = ParquetObjectReader::new(file.store.clone(), file.meta.clone());
+        let batches = ParquetRecordBatchStreamBuilder::new_with_metadata(reader, metadata.clone())
+            .with_row_groups(vec![row_group])
+            .with_batch_size(batch_size)
+            .build();
+        let uri = file.uri.clone();
+        let schema = schema.clone();
+        stream::once(future::ready(batches))
+            .try_flatten()
+            .map(move |batch| {
+                let batch = batch.map_err(|err| import_error(&uri, Some(row_group), err))?;
+                conform(batch, &schema).map_err(|err| import_error(&uri, Some(row_group), err))
+            })
+    })
+}
+
+/// Read Parquet files, the schema is taken from the options or the first file
+pub(crate) async fn read_parquet(
+    paths: &[&str],
+    storage_options: HashMap<String, String>,
+    options: ParquetImportOptions,
+) -> Result<ImportReader> {
+    let files = list_all_files(paths, storage_options).await?;
+    let mut first = Some(open_parquet(&files[0]).await?);
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => first.as_ref().unwrap().schema().clone(),
+    };
+    let batch_size = options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let uris = files.iter().map(|file| file.uri.clone()).collect();
+
+    let target = schema.clone();
+    let batches = stream::iter(files.into_iter().enumerate())
+        .flat_map(move |(index, file)| {
+            let metadata = first.take();
+            let schema = target.clone();
+            stream::once(async move {
+                let metadata = match metadata {
+                    Some(metadata) => metadata,
+                    None => open_parquet(&file).await?,
+                };
+                Ok::<_, Error>(
+                    parquet_batches(file, metadata, schema, batch_size)
+                        .map_ok(move |batch| (index, batch)),
+                )
+            })
+            .try_flatten()
+        })
+        .boxed();
+    Ok(ImportReader::new(schema, uris, batches, options.progress))
+}
+
+/// Decode the bytes of a CSV file into batches as they arrive
+fn csv_batches(
+    decoder: Decoder,
+    input: BoxStream<'static, object_store::Result<Bytes>>,
+) -> impl Stream<Item = Result<RecordBatch>> + Send + 'static {
+    stream::try_unfold(
+        (decoder, input, Bytes::new()),
+        |(mut decoder, mut input, mut buffered)| async move {
+            loop {
+                if buffered.is_empty() {
+                    buffered = input.try_next().await?.unwrap_or_default();
+                }
+                let decoded = decoder.decode(&buffered)?;
+                buffered.advance(decoded);
+                if decoded == 0 || decoder.capacity() == 0 {
+                    break;
+                }
+            }
+            Ok::<_, Error>(
+                decoder
+                    .flush()?
+                    .map(|batch| (batch, (decoder, input, buffered))),
+            )
+        },
+    )
+}
+
+/// Read CSV files
+///
+/// If `table_schema` is given, the columns of the files are matched by name to
+/// the columns of the table and cast to their types.
+pub(crate) async fn read_csv(
+    path: &str,
+    storage_options: HashMap<String, String>,
+    options: CsvOptions,
+    table_schema: Option<SchemaRef>,
+) -> Result<ImportReader> {
+    let files = list_all_files(&[path], storage_options).await?;
+    let first_file = &files[0];
+    let mut first_input = first_file
+        .store
+        .get(&first_file.meta.location)
+        .await?
+        .into_stream();
+
+    // The start of the first file is read to infer the schema and then decoded
+    // along with the rest of the file
+    let mut head = Vec::new();
+    let csv_schema = match options.schema_override {
+        Some(schema) => schema,
+        None => {
+            let mut complete = false;
+            while head.len() < INFER_SCHEMA_BYTES {
+                match first_input.try_next().await? {
+                    Some(chunk) => head.extend_from_slice(&chunk),
+                    None => {
+                        complete = true;
+                        break;
+                    }
+                }
+            }
+            // Only complete lines are used, a partial line could be mistyped
+            let end = if complete {
+                head.len()
+            } else {
+                head.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1)
+            };
+            let (schema, _) = Format::default()
+                .with_header(options.has_header)
+                .with_delimiter(options.delimiter)
+                .infer_schema(Cursor::new(&head[..end]), Some(INFER_SCHEMA_RECORDS))
+                .map_err(|err| import_error(&first_file.uri, None, err))?;
+            Arc::new(schema)
+        }
+    };
+    let schema = match table_schema {
+        Some(table_schema) => Arc::new(Schema::new(
+            table_schema
+                .fields()
+                .iter()
+                .filter(|field| csv_schema.field_with_name(field.name()).is_ok())
+                .cloned()
+                .collect::<Vec<_>>(),
+        )),
+        None => csv_schema.clone(),
+    };
+    let batch_size = options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let uris = files.iter().map(|file| file.uri.clone()).collect();
+
+    let mut first_input = Some(
+        stream::once(future::ready(Ok(Bytes::from(head))))
+            .chain(first_input)
+            .boxed(),
+    );
+    let target = schema.clone();
+    let batches = stream::iter(files.into_iter().enumerate())
+        .flat_map(move |(index, file)| {
+            let input = first_input.take();
+            let decoder = ReaderBuilder::new(csv_schema.clone())
+                .with_header(options.has_header)
+                .with_delimiter(options.delimiter)
+                .with_batch_size(batch_size)
+                .build_decoder();
+            let schema = target.clone();
+            let uri = file.uri.clone();
+            stream::once(async move {
+                let input = match input {
+                    Some(input) => input,
+                    None => file.store.get(&file.meta.location).await?.into_stream(),
+                };
+                Ok::<_, Error>(csv_batches(decoder, input))
+            })
+            .try_flatten()
+            .map(move |batch| {
+                batch
+                    .and_then(|batch| conform(batch, &schema))
+                    .map(|batch| (index, batch))
+                    .map_err(|err| import_error(&uri, None, err))
+            })
+        })
+        .boxed();
+    Ok(ImportReader::new(schema, uris, batches, options.progress))
+}
+
+/// Run an import, which reads the files and writes the table
+///
+/// Lance reads the data through the synchronous [`RecordBatchReader`] interface,
+/// whose [`ImportReader`] waits for the batches of the files with
+/// [`tokio::task::block_in_place`].  That is only possible on a multi-threaded
+/// runtime, so on a current thread runtime, or outside of one, the import runs on
+/// a multi-threaded runtime of its own while the caller waits for it.
+pub(crate) async fn run_import<F, T>(import: F) -> Result<T>
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    static RUNTIME: OnceLock<std::io::Result<tokio::runtime::Runtime>> = OnceLock::new();
+
+    if can_block_in_place().is_some() {
+        return import.await;
+    }
+    let runtime = RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .thread_name("lancedb-import")
+                .enable_all()
+                .build()
+        })
+        .as_ref()
+        .map_err(|e| Error::Runtime {
+            message: format!("failed to start a runtime to import data: {}", e),
+        })?;
+    runtime.spawn(import).await.map_err(|e| Error::Runtime {
+        message: format!("the import was interrupted: {}", e),
+    })?
+}
+
+/// Wait for the next batch of an import from synchronous code, see [`run_import`]
+fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let handle = can_block_in_place().ok_or_else(|| Error::Runtime {
+        message: "data must be imported from within a multi-threaded tokio runtime".to_string(),
+    })?;
+    Ok(tokio::task::block_in_place(|| handle.block_on(future)))
+}
+
+/// The batches read from the files of an import, as a [`RecordBatchReader`]
+pub(crate) struct ImportReader {
+    schema: SchemaRef,
+    uris: Vec<String>,
+    batches: BoxStream<'static, Result<(usize, RecordBatch)>>,
+    progress: Option<ImportProgressCallback>,
+    /// The index of the file being read and the number of rows read from it
+    current: Option<(usize, usize)>,
+}
+
+impl ImportReader {
+    pub(crate) fn new(
+        schema: SchemaRef,
+        uris: Vec<String>,
+        batches: BoxStream<'static, Result<(usize, RecordBatch)>>,
+        progress: Option<ImportProgressCallback>,
+    ) -> Self {
+        Self {
+            schema,
+            uris,
+            batches,
+            progress,
+            current: None,
+        }
+    }
+
+    fn report(&self, done: bool) {
+        if let (Some(progress), Some((file_index, rows))) = (&self.progress, self.current) {
+            progress(&ImportProgress {
+                path: self.uris[file_index].clone(),
+                file_index,
+                num_files: self.uris.len(),
+                rows,
+                done,
+            });
+        }
+    }
+
+    fn finish_file(&mut self) {
+        self.report(true);
+        self.current = None;
+    }
+}
+
+impl Iterator for ImportReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = block_on(self.batches.next()).and_then(|next| next.transpose());
+        match next {
+            Ok(Some((file_index, batch))) => {
+                if matches!(self.current, Some((current, _)) if current != file_index) {
+                    self.finish_file();
+                }
+                self.current.get_or_insert((file_index, 0)).1 += batch.num_rows();
+                self.report(false);
+                Some(Ok(batch))
+            }
+            Ok(None) => {
+                self.finish_file();
+                None
+            }
+            Err(Error::Arrow { source }) => Some(Err(source)),
+            Err(err) => Some(Err(ArrowError::ExternalError(Box::new(err)))),
+        }
+    }
+}
+
+impl RecordBatchReader for ImportReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use arrow_array::{Array, Float64Array, Int32Array, Int64Array, StringArray};
+    use arrow_schema::{DataType, Field};
+    use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+    use super::*;
+
+    fn write_parquet(path: &std::path::Path, ids: std::ops::Range<i32>) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids.clone())),
+                Arc::new(StringArray::from_iter_values(
+                    ids.map(|i| format!("name {}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(10)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(path).unwrap(), schema, Some(props))
+                .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        let regex = Regex::new(&glob_to_regex("part-*.parquet")).unwrap();
+        assert!(regex.is_match("part-0.parquet"));
+        assert!(!regex.is_match("nested/part-0.parquet"));
+        assert!(!regex.is_match("part-0.parquet.bak"));
+        let regex = Regex::new(&glob_to_regex("**/*.csv")).unwrap();
+        assert!(regex.is_match("a.csv"));
+        assert!(regex.is_match("x/y/a.csv"));
+        let regex = Regex::new(&glob_to_regex("data?.csv")).unwrap();
+        assert!(regex.is_match("data1.csv"));
+        assert!(!regex.is_match("data10.csv"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_parquet() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        write_parquet(&tmp_dir.path().join("part-1.parquet"), 0..25);
+        write_parquet(&tmp_dir.path().join("part-2.parquet"), 25..30);
+        std::fs::write(tmp_dir.path().join("other.txt"), "not parquet").unwrap();
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let recorded = progress.clone();
+        let glob = format!("{}/part-*.parquet", tmp_dir.path().to_str().unwrap());
+        let options = ParquetImportOptions {
+            schema: Some(Arc::new(Schema::new(vec![Field::new(
+                "id",
+                DataType::Int64,
+                true,
+            )]))),
+            progress: Some(Arc::new(move |p: &ImportProgress| {
+                recorded.lock().unwrap().push(p.clone());
+            })),
+            ..Default::default()
+        };
+        let reader = read_parquet(&[&glob], HashMap::new(), options)
+            .await
+            .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        // One batch per row group
+        assert_eq!(batches.len(), 4);
+        let ids = batches
+            .iter()
+            .flat_map(|batch| {
+                batch["id"]
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, (0..30).collect::<Vec<_>>());
+
+        let progress = progress.lock().unwrap();
+        let done = progress
+            .iter()
+            .filter(|p| p.done)
+            .map(|p| (p.file_index, p.num_files, p.rows))
+            .collect::<Vec<_>>();
+        assert_eq!(done, vec![(0, 2, 25), (1, 2, 5)]);
+        assert!(progress[0].path.ends_with("part-1.parquet"));
+
+        let missing = format!("{}/missing.parquet", tmp_dir.path().to_str().unwrap());
+        let err = read_parquet(&[&missing], HashMap::new(), Default::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        let bad = format!("{}/other.txt", tmp_dir.path().to_str().unwrap());
+        let err = read_parquet(&[&bad], HashMap::new(), Default::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("other.txt"), "{}", err);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_csv() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("data.csv");
+        let mut csv = String::from("name;score;id\n");
+        for i in 0..100 {
+            csv.push_str(&format!("n{};{}.5;{}\n", i, i, i));
+        }
+        std::fs::write(&path, csv).unwrap();
+
+        let options = CsvOptions {
+            delimiter: b';',
+            batch_size: Some(30),
+            ..Default::default()
+        };
+        let table_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("score", DataType::Float64, true),
+            Field::new("vector", DataType::Float32, true),
+        ]));
+        let reader = read_csv(
+            path.to_str().unwrap(),
+            HashMap::new(),
+            options,
+            Some(table_schema),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            reader.schema().fields().len(),
+            2,
+            "only the columns in the file are read"
+        );
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 4);
+        assert_eq!(
+            batches[0]["id"].as_ref(),
+            &Int32Array::from_iter_values(0..30) as &dyn Array
+        );
+        assert_eq!(
+            batches[3]["score"].as_ref(),
+            &Float64Array::from_iter_values((90..100).map(|i| i as f64 + 0.5)) as &dyn Array
+        );
+
+        std::fs::write(&path, "id\n1\n2\nthree\n").unwrap();
+        let options = CsvOptions {
+            schema_override: Some(Arc::new(Schema::new(vec![Field::new(
+                "id",
+                DataType::Int32,
+                true,
+            )]))),
+            ..Default::default()
+        };
+        let reader = read_csv(path.to_str().unwrap(), HashMap::new(), options, None)
+            .await
+            .unwrap();
+        let err = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(err.to_string().contains("data.csv"), "{}", err);
+    }
+}
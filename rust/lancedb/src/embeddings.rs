@@ -11,27 +11,56 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+#[cfg(feature = "cohere")]
+pub mod cohere;
+#[cfg(feature = "ollama")]
+pub mod ollama;
 #[cfg(feature = "openai")]
 pub mod openai;
+#[cfg(feature = "sentence-transformers")]
+pub mod sentence_transformers;
+#[cfg(all(test, any(feature = "cohere", feature = "ollama", feature = "openai")))]
+mod test_utils;
+pub mod throttled;
 
 use lance::arrow::RecordBatchExt;
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
-    sync::{Arc, RwLock},
+    collections::{
+        hash_map::{DefaultHasher, Entry},
+        HashMap, HashSet, VecDeque,
+    },
+    future::Future,
+    hash::{Hash, Hasher},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
 };
 
-use arrow_array::{Array, RecordBatch, RecordBatchReader};
-use arrow_schema::{DataType, Field, SchemaBuilder};
-// use async_trait::async_trait;
+use arrow::array::AsArray;
+use arrow_array::{
+    new_empty_array, new_null_array, Array, BooleanArray, LargeStringArray, RecordBatch,
+    RecordBatchReader, StringArray, UInt32Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use async_trait::async_trait;
+use lru::LruCache;
 use serde::{Deserialize, Serialize};
+use tokio::runtime::RuntimeFlavor;
 
 use crate::{
     error::Result,
+    query::ROW_ADDR,
+    rerankers::{DISTANCE, RELEVANCE_SCORE, ROW_ID, SCORE},
     table::{ColumnDefinition, ColumnKind, TableDefinition},
     Error,
 };
 
+/// Columns that searches add to their results, embeddings can't be written to them
+const RESERVED_COLUMN_NAMES: &[&str] = &[DISTANCE, ROW_ID, ROW_ADDR, SCORE, RELEVANCE_SCORE];
+
 /// Trait for embedding functions
 ///
 /// An embedding function is a function that is applied to a column of input data
@@ -46,6 +75,11 @@ use crate::{
 /// To use an embedding function you must first register it with the `EmbeddingsRegistry`.
 /// Then you can define it on a column in the table schema. That embedding will then be used
 /// to embed the data in that column.
+///
+/// Embeddings are computed with the async methods, which by default call the synchronous
+/// methods.  Embedding functions that call a remote service should override the async
+/// methods so that requests don't block a thread and can run concurrently.
+#[async_trait]
 pub trait EmbeddingFunction: std::fmt::Debug + Send + Sync {
     fn name(&self) -> &str;
     /// The type of the input data
@@ -56,7 +90,432 @@ pub trait EmbeddingFunction: std::fmt::Debug + Send + Sync {
     /// Compute the embeddings for the source column in the database
     fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>>;
     /// Compute the embeddings for a given user query
-    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>>;
+    ///
+    /// Asymmetric models, which embed queries differently from the documents they
+    /// search, override this.  By default this calls [`Self::compute_source_embeddings`].
+    /// Models that only need a prefix on their input can instead be given one with
+    /// [`EmbeddingDefinition::query_prefix`] and [`EmbeddingDefinition::document_prefix`].
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        self.compute_source_embeddings(input)
+    }
+    /// Compute the embeddings for the source column in the database without blocking
+    ///
+    /// By default this calls [`Self::compute_source_embeddings`]
+    async fn compute_source_embeddings_async(
+        &self,
+        source: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        self.compute_source_embeddings(source)
+    }
+    /// Compute the embeddings for a given user query without blocking
+    ///
+    /// By default this calls [`Self::compute_query_embeddings`]
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        self.compute_query_embeddings(input)
+    }
+    /// The maximum number of rows to pass to a single call of
+    /// [`Self::compute_source_embeddings_async`]
+    ///
+    /// Larger inputs are split into chunks of at most this many rows.  By default
+    /// there is no limit and the entire source column of a batch is embedded at once.
+    fn max_batch_size(&self) -> Option<usize> {
+        None
+    }
+    /// The configuration needed to re-create this function in another session
+    ///
+    /// When this returns a value it is stored in the table metadata and, if the
+    /// registry has an [`EmbeddingFunctionFactory`] with the same name as this
+    /// function, the function is re-created from it when the table is used with
+    /// a registry that does not contain it.  Secrets, such as API keys, must not
+    /// be part of the configuration.
+    fn to_config(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// Creates embedding functions from the configuration stored in table metadata
+///
+/// See [`EmbeddingFunction::to_config`]
+pub trait EmbeddingFunctionFactory: std::fmt::Debug + Send + Sync {
+    /// The name of the functions this factory creates, this matches [`EmbeddingFunction::name`]
+    fn name(&self) -> &str;
+    /// Create an embedding function from its configuration
+    #[allow(clippy::wrong_self_convention)]
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>>;
+}
+
+/// The stored configuration of an embedding function
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EmbeddingFunctionConfig {
+    /// The name of the [`EmbeddingFunctionFactory`] that can re-create the function
+    pub factory: String,
+    /// The configuration returned by [`EmbeddingFunction::to_config`]
+    pub config: serde_json::Value,
+}
+
+impl EmbeddingFunctionConfig {
+    pub(crate) fn from_function(func: &dyn EmbeddingFunction) -> Option<Self> {
+        func.to_config().map(|config| Self {
+            factory: func.name().to_string(),
+            config,
+        })
+    }
+}
+
+/// Check that a source column has the type its embedding function takes
+///
+/// Embedding functions downcast their input, so a mismatch must be caught before
+/// the function is called.  Strings and binaries are accepted with either offset
+/// size, they are cast to the type the function takes (see [`cast_source`]).
+pub(crate) fn check_source_type(
+    definition: &EmbeddingDefinition,
+    func: &dyn EmbeddingFunction,
+    data_type: &DataType,
+) -> Result<()> {
+    let source_type = func.source_type()?;
+    let compatible = source_type.as_ref() == data_type
+        || matches!(
+            (source_type.as_ref(), data_type),
+            (
+                DataType::Utf8 | DataType::LargeUtf8,
+                DataType::Utf8 | DataType::LargeUtf8
+            ) | (
+                DataType::Binary | DataType::LargeBinary,
+                DataType::Binary | DataType::LargeBinary
+            )
+        );
+    if !compatible {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "source column '{}' has type {} but embedding function '{}' takes {}",
+                definition.source_column, data_type, definition.embedding_name, source_type
+            ),
+        });
+    }
+    let has_prefix = definition.document_prefix.is_some() || definition.query_prefix.is_some();
+    if has_prefix && !matches!(data_type, DataType::Utf8 | DataType::LargeUtf8) {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the embedding of source column '{}' has a prefix, but prefixes only apply to string columns and the column has type {}",
+                definition.source_column, data_type
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Cast a source column that [`check_source_type`] accepts to the type `func` takes
+fn cast_source(func: &dyn EmbeddingFunction, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+    let source_type = func.source_type()?;
+    if source.data_type() == source_type.as_ref() {
+        return Ok(source);
+    }
+    Ok(arrow_cast::cast(&source, &source_type)?)
+}
+
+/// Prepend `prefix` to the strings of `input`, see [`EmbeddingDefinition::document_prefix`]
+///
+/// Null values stay null.
+pub(crate) fn with_prefix(input: Arc<dyn Array>, prefix: Option<&str>) -> Result<Arc<dyn Array>> {
+    let Some(prefix) = prefix else {
+        return Ok(input);
+    };
+    let prefixed = |value: Option<&str>| value.map(|value| format!("{}{}", prefix, value));
+    match input.data_type() {
+        DataType::Utf8 => Ok(Arc::new(
+            input
+                .as_string::<i32>()
+                .iter()
+                .map(prefixed)
+                .collect::<StringArray>(),
+        )),
+        DataType::LargeUtf8 => Ok(Arc::new(
+            input
+                .as_string::<i64>()
+                .iter()
+                .map(prefixed)
+                .collect::<LargeStringArray>(),
+        )),
+        data_type => Err(Error::InvalidInput {
+            message: format!(
+                "prefixes only apply to string input, the input has type {}",
+                data_type
+            ),
+        }),
+    }
+}
+
+/// Compare the shape of two embedding types
+///
+/// Nested field names and nullability are not compared, functions often build
+/// the items of their lists with a different field than their [`EmbeddingFunction::dest_type`].
+fn same_embedding_shape(a: &DataType, b: &DataType) -> bool {
+    match (a, b) {
+        (DataType::FixedSizeList(a, a_size), DataType::FixedSizeList(b, b_size)) => {
+            a_size == b_size && same_embedding_shape(a.data_type(), b.data_type())
+        }
+        (DataType::List(a), DataType::List(b))
+        | (DataType::LargeList(a), DataType::LargeList(b)) => {
+            same_embedding_shape(a.data_type(), b.data_type())
+        }
+        _ => a == b,
+    }
+}
+
+/// Whether embeddings of type `produced` can be stored in a column of `stored`
+///
+/// Vectors of floats are cast to the floats of the column, so that a function
+/// that produces f32 vectors can fill an f16 column and the other way round.
+fn compatible_embedding_type(produced: &DataType, stored: &DataType) -> bool {
+    match (produced, stored) {
+        (DataType::FixedSizeList(a, a_size), DataType::FixedSizeList(b, b_size))
+            if a.data_type().is_floating() && b.data_type().is_floating() =>
+        {
+            a_size == b_size
+        }
+        _ => same_embedding_shape(produced, stored),
+    }
+}
+
+/// Cast embeddings that [`compatible_embedding_type`] allows to `dest_type`
+fn cast_embeddings(embeddings: Arc<dyn Array>, dest_type: &DataType) -> Result<Arc<dyn Array>> {
+    if same_embedding_shape(embeddings.data_type(), dest_type) {
+        return Ok(embeddings);
+    }
+    Ok(arrow_cast::cast(&embeddings, dest_type)?)
+}
+
+fn describe_embedding_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::FixedSizeList(field, size) => {
+            format!("{}-dimensional {} vectors", size, field.data_type())
+        }
+        DataType::List(item) | DataType::LargeList(item)
+            if matches!(item.data_type(), DataType::FixedSizeList(_, _)) =>
+        {
+            format!(
+                "multivectors of {}",
+                describe_embedding_type(item.data_type())
+            )
+        }
+        data_type => format!("embeddings of type {}", data_type),
+    }
+}
+
+/// Check that an embedding function returned one embedding of the expected type
+/// for each of `num_rows` input values
+fn check_embeddings(
+    definition: &EmbeddingDefinition,
+    dest_type: &DataType,
+    num_rows: usize,
+    embeddings: &dyn Array,
+) -> Result<()> {
+    let mismatch = |expected: String, actual: String| Error::EmbeddingOutputMismatch {
+        name: definition.embedding_name.clone(),
+        expected,
+        actual,
+    };
+    if embeddings.len() != num_rows {
+        return Err(mismatch(
+            format!("{} embeddings", num_rows),
+            format!("{} embeddings", embeddings.len()),
+        ));
+    }
+    if !compatible_embedding_type(embeddings.data_type(), dest_type) {
+        return Err(mismatch(
+            describe_embedding_type(dest_type),
+            describe_embedding_type(embeddings.data_type()),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the registered embedding functions of a table produce the type of
+/// their destination columns
+///
+/// This catches functions that were reconfigured, e.g. with a different model or
+/// dimension, since the table was created.  Functions that are not registered are
+/// not checked, they are re-created from the table metadata when needed.
+pub(crate) fn check_registered_functions(
+    registry: &dyn EmbeddingRegistry,
+    table_definition: &TableDefinition,
+) -> Result<()> {
+    for column in &table_definition.column_definitions {
+        let ColumnKind::Embedding(definition) = &column.kind else {
+            continue;
+        };
+        let Some(func) = registry.get(&definition.embedding_name) else {
+            continue;
+        };
+        let dest_column = definition.dest_column_name();
+        let Ok(field) = table_definition.schema.field_with_name(&dest_column) else {
+            continue;
+        };
+        let dest_type = func.dest_type()?;
+        if !compatible_embedding_type(&dest_type, field.data_type()) {
+            return Err(Error::Schema {
+                message: format!(
+                    "column '{}' stores {} but embedding function '{}' produces {}",
+                    dest_column,
+                    describe_embedding_type(field.data_type()),
+                    definition.embedding_name,
+                    describe_embedding_type(&dest_type),
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Compute the source embeddings for `source`, applying the null policy of `definition`
+///
+/// Only the valid values are embedded.  Null values get a null embedding, or an
+/// error, depending on the policy.  Rows to skip must have been removed already.
+/// If `blocking` is set the synchronous interface of `func` is used.
+async fn compute_source_embeddings_with_nulls(
+    definition: &EmbeddingDefinition,
+    func: &dyn EmbeddingFunction,
+    source: Arc<dyn Array>,
+    dest_type: &DataType,
+    blocking: bool,
+) -> Result<Arc<dyn Array>> {
+    if source.is_empty() {
+        return Ok(new_empty_array(dest_type));
+    }
+    let source = with_prefix(source, definition.document_prefix.as_deref())?;
+    let source = cast_source(func, source)?;
+    if source.null_count() == 0 {
+        return compute_source_embeddings_chunked(definition, func, source, dest_type, blocking)
+            .await;
+    }
+    if definition.on_null == NullPolicy::Error {
+        let row = (0..source.len()).find(|i| source.is_null(*i)).unwrap();
+        return Err(Error::InvalidInput {
+            message: format!(
+                "source column '{}' for embedding function '{}' has a null value at row {}",
+                definition.source_column, definition.embedding_name, row
+            ),
+        });
+    }
+
+    let valid = arrow::compute::is_not_null(&source)?;
+    let values = arrow::compute::filter(&source, &valid)?;
+    if values.is_empty() {
+        return Ok(new_null_array(dest_type, source.len()));
+    }
+    let embeddings =
+        compute_source_embeddings_chunked(definition, func, values, dest_type, blocking).await?;
+
+    // Scatter the embeddings back to the rows they came from
+    let mut next = 0;
+    let indices = valid
+        .iter()
+        .map(|is_valid| {
+            is_valid.unwrap_or(false).then(|| {
+                next += 1;
+                next - 1
+            })
+        })
+        .collect::<UInt32Array>();
+    Ok(arrow::compute::take(&embeddings, &indices, None)?)
+}
+
+/// Compute the source embeddings for `source`, at most `func.max_batch_size()` rows at a time
+///
+/// The output of every call of `func` is checked with [`check_embeddings`] and cast
+/// to `dest_type`.
+async fn compute_source_embeddings_chunked(
+    definition: &EmbeddingDefinition,
+    func: &dyn EmbeddingFunction,
+    source: Arc<dyn Array>,
+    dest_type: &DataType,
+    blocking: bool,
+) -> Result<Arc<dyn Array>> {
+    let chunk_size = match func.max_batch_size() {
+        Some(chunk_size) if chunk_size < source.len() => chunk_size.max(1),
+        _ => {
+            let num_rows = source.len();
+            let embeddings = call_embedding_function(func, source, blocking)
+                .await
+                .map_err(|e| Error::Runtime {
+                    message: format!("Error computing embedding: {}", e),
+                })?;
+            check_embeddings(definition, dest_type, num_rows, embeddings.as_ref())?;
+            return cast_embeddings(embeddings, dest_type);
+        }
+    };
+
+    let num_chunks = source.len().div_ceil(chunk_size);
+    let mut chunks = Vec::with_capacity(num_chunks);
+    for offset in (0..source.len()).step_by(chunk_size) {
+        let len = chunk_size.min(source.len() - offset);
+        let chunk = call_embedding_function(func, source.slice(offset, len), blocking)
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!(
+                    "Error computing embedding for rows {}..{} (chunk {} of {}): {}",
+                    offset,
+                    offset + len,
+                    chunks.len() + 1,
+                    num_chunks,
+                    e
+                ),
+            })?;
+        check_embeddings(definition, dest_type, len, chunk.as_ref())?;
+        chunks.push(cast_embeddings(chunk, dest_type)?);
+    }
+    let chunks = chunks.iter().map(|c| c.as_ref()).collect::<Vec<_>>();
+    Ok(arrow::compute::concat(&chunks)?)
+}
+
+/// Compute the source embeddings of `source` with the async interface of `func`, or
+/// its synchronous one if `blocking` is set
+async fn call_embedding_function(
+    func: &dyn EmbeddingFunction,
+    source: Arc<dyn Array>,
+    blocking: bool,
+) -> Result<Arc<dyn Array>> {
+    if blocking {
+        func.compute_source_embeddings(source)
+    } else {
+        func.compute_source_embeddings_async(source).await
+    }
+}
+
+/// The runtime of the current thread if it is a multi-threaded one, whose threads
+/// can wait with [`tokio::task::block_in_place`]
+pub(crate) fn can_block_in_place() -> Option<tokio::runtime::Handle> {
+    tokio::runtime::Handle::try_current()
+        .ok()
+        .filter(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread)
+}
+
+/// Wait for an embedding computation from synchronous code
+///
+/// This is used by the synchronous interface of embedding functions that call
+/// remote services.  On a multi-threaded runtime the worker hands its other tasks
+/// off while it waits.  Outside of a runtime a temporary one is started.  A
+/// current thread runtime can't be blocked without stalling the requests, so this
+/// returns [`Error::Runtime`] there, use the async interface instead.
+pub(crate) fn block_on_embeddings<F: Future<Output = Result<T>>, T>(future: F) -> Result<T> {
+    if let Some(handle) = can_block_in_place() {
+        return tokio::task::block_in_place(|| handle.block_on(future));
+    }
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err(Error::Runtime {
+            message: "embeddings can't be computed synchronously on a current thread runtime, use the async interface or a multi-threaded runtime".to_string(),
+        });
+    }
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::Runtime {
+            message: format!("failed to start a runtime to compute embeddings: {}", e),
+        })?
+        .block_on(future)
 }
 
 /// Defines an embedding from input data into a lower-dimensional space
@@ -69,6 +528,48 @@ pub struct EmbeddingDefinition {
     pub dest_column: Option<String>,
     /// The name of the embedding function to apply
     pub embedding_name: String,
+    /// What to do with rows where the source column is null
+    #[serde(default)]
+    pub on_null: NullPolicy,
+    /// Prepended to the source values before they are embedded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_prefix: Option<String>,
+    /// Prepended to the text of queries before it is embedded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_prefix: Option<String>,
+    /// What to do when the data already has the destination column
+    #[serde(default)]
+    pub if_exists: ExistingColumnPolicy,
+}
+
+/// How an embedding handles null values in its source column
+///
+/// Null values are never passed to the embedding function.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum NullPolicy {
+    /// Drop rows where the source column is null before writing them
+    SkipRow,
+    /// Store a null embedding for rows where the source column is null
+    #[default]
+    NullVector,
+    /// Fail the write if the source column contains a null
+    Error,
+}
+
+/// What an embedding does when the data already has its destination column, for
+/// example because the embeddings were computed beforehand
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum ExistingColumnPolicy {
+    /// Fail the write
+    #[default]
+    Error,
+    /// Keep the column of the data and don't compute the embeddings
+    ///
+    /// The column must have the type of the embeddings of the function.  The
+    /// source column is not needed.
+    Skip,
+    /// Compute the embeddings and replace the column of the data with them
+    Overwrite,
 }
 
 impl EmbeddingDefinition {
@@ -77,25 +578,228 @@ impl EmbeddingDefinition {
             source_column: source_column.into(),
             dest_column: dest.map(|d| d.into()),
             embedding_name: embedding_name.into(),
+            on_null: NullPolicy::default(),
+            document_prefix: None,
+            query_prefix: None,
+            if_exists: ExistingColumnPolicy::default(),
         }
     }
+
+    /// Set what to do with rows where the source column is null
+    ///
+    /// Defaults to [`NullPolicy::NullVector`]
+    pub fn on_null(mut self, on_null: NullPolicy) -> Self {
+        self.on_null = on_null;
+        self
+    }
+
+    /// Prepend `prefix` to the source values before they are embedded
+    ///
+    /// Some models expect their input to be marked as a document or a query, for
+    /// example the e5 models take `"passage: "` and `"query: "` prefixes.  The
+    /// prefixes are stored with the table and only apply to string columns.
+    pub fn document_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.document_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set what to do when the data already has the destination column
+    ///
+    /// Defaults to [`ExistingColumnPolicy::Error`]
+    pub fn if_exists(mut self, if_exists: ExistingColumnPolicy) -> Self {
+        self.if_exists = if_exists;
+        self
+    }
+
+    /// Prepend `prefix` to the text of queries before it is embedded, see
+    /// [`Self::document_prefix`]
+    pub fn query_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.query_prefix = Some(prefix.into());
+        self
+    }
+
+    /// The name of the column the embeddings are written to
+    pub fn dest_column_name(&self) -> String {
+        self.dest_column
+            .clone()
+            .unwrap_or_else(|| format!("{}_embedding", &self.source_column))
+    }
 }
 
 /// A registry of embedding
+///
+/// Tables look up their embedding functions on every write (and text query).
+/// Unregistering or replacing a function affects the operations that start
+/// afterwards.  A write that is already in progress holds on to the function it
+/// looked up and finishes with it.
 pub trait EmbeddingRegistry: Send + Sync + std::fmt::Debug {
     /// Return the names of all registered embedding functions
     fn functions(&self) -> HashSet<String>;
-    /// Register a new [`EmbeddingFunction
+    /// Register a new [`EmbeddingFunction`]
+    ///
+    /// Returns [`Error::EmbeddingFunctionAlreadyExists`] if a different function is
+    /// already registered with this name, use [`Self::register_or_replace`] to
+    /// replace it.  Registering the same function again does nothing, functions
+    /// are the same if they are the same instance, or if they have the same name,
+    /// types and [`EmbeddingFunction::to_config`].
     /// Returns an error if the function can not be registered
     fn register(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()>;
+    /// Register an [`EmbeddingFunction`], replacing any function with the same name
+    fn register_or_replace(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()> {
+        self.unregister(name)?;
+        self.register(name, function)
+    }
+    /// Remove an embedding function
+    ///
+    /// Returns true if a function was registered with this name
+    fn unregister(&self, name: &str) -> Result<bool> {
+        Err(Error::NotSupported {
+            message: format!(
+                "this registry does not support unregistering embedding functions (unregistering '{}')",
+                name
+            ),
+        })
+    }
     /// Get an embedding function by name
     fn get(&self, name: &str) -> Option<Arc<dyn EmbeddingFunction>>;
+    /// The number of registered embedding functions
+    fn len(&self) -> usize {
+        self.functions().len()
+    }
+    /// Returns true if no embedding functions are registered
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Register a factory that re-creates embedding functions from stored configuration
+    fn register_factory(&self, factory: Arc<dyn EmbeddingFunctionFactory>) -> Result<()> {
+        Err(Error::NotSupported {
+            message: format!(
+                "this registry does not support embedding function factories (registering '{}')",
+                factory.name()
+            ),
+        })
+    }
+    /// Get an embedding function factory by name
+    fn get_factory(&self, _name: &str) -> Option<Arc<dyn EmbeddingFunctionFactory>> {
+        None
+    }
+}
+
+/// Find the embedding function for `definition`
+///
+/// If the function isn't registered but the table stored its configuration, the
+/// function is re-created with a factory from the registry and registered.
+pub(crate) fn resolve_embedding_function(
+    registry: &dyn EmbeddingRegistry,
+    definition: &EmbeddingDefinition,
+    table_definition: &TableDefinition,
+) -> Result<Arc<dyn EmbeddingFunction>> {
+    let name = &definition.embedding_name;
+    if let Some(func) = registry.get(name) {
+        return Ok(func);
+    }
+    let not_found = |reason: String| Error::EmbeddingFunctionNotFound {
+        name: name.clone(),
+        reason,
+    };
+    let Some(stored) = table_definition.embedding_functions.get(name) else {
+        return Err(not_found(format!(
+            "Table was defined with an embedding column `{}` but no embedding function was found with that name within the registry.",
+            name
+        )));
+    };
+    let factory = registry.get_factory(&stored.factory).ok_or_else(|| {
+        not_found(format!(
+            "The embedding function is not registered and there is no factory named `{}` to re-create it from the table metadata.",
+            stored.factory
+        ))
+    })?;
+    let func = factory.from_config(&stored.config).map_err(|e| {
+        not_found(format!(
+            "Failed to re-create the embedding function from the table metadata: {}",
+            e
+        ))
+    })?;
+    match registry.register(name, func.clone()) {
+        Ok(()) => Ok(func),
+        // Someone else registered the function in the meantime
+        Err(Error::EmbeddingFunctionAlreadyExists { .. }) => Ok(registry.get(name).unwrap_or(func)),
+        Err(e) => Err(e),
+    }
 }
 
 /// A [`EmbeddingRegistry`] that uses in-memory [`HashMap`]s
-#[derive(Debug, Default, Clone)]
+///
+/// Factories for the built-in embedding functions are registered by default.
+#[derive(Debug, Clone)]
 pub struct MemoryRegistry {
     functions: Arc<RwLock<HashMap<String, Arc<dyn EmbeddingFunction>>>>,
+    factories: Arc<RwLock<HashMap<String, Arc<dyn EmbeddingFunctionFactory>>>>,
+}
+
+/// The registry of the connections that don't set one with
+/// [`crate::connection::ConnectBuilder::embedding_registry`]
+///
+/// It is shared by all of those connections in the process, so a function that is
+/// registered once can be used by the tables of each of them.
+///
+/// Names are shared too: registering a different function under a name that is
+/// already taken fails with [`Error::EmbeddingFunctionAlreadyExists`], while
+/// registering the same function again does nothing, see
+/// [`EmbeddingRegistry::register`].
+pub fn default_registry() -> Arc<dyn EmbeddingRegistry> {
+    static DEFAULT_REGISTRY: OnceLock<Arc<MemoryRegistry>> = OnceLock::new();
+    DEFAULT_REGISTRY
+        .get_or_init(|| Arc::new(MemoryRegistry::new()))
+        .clone()
+}
+
+/// Whether registering `b` where `a` is registered is a no-op, see
+/// [`EmbeddingRegistry::register`]
+fn same_function(a: &dyn EmbeddingFunction, b: &dyn EmbeddingFunction) -> bool {
+    if std::ptr::eq(
+        a as *const dyn EmbeddingFunction as *const (),
+        b as *const dyn EmbeddingFunction as *const (),
+    ) {
+        return true;
+    }
+    let config = a.to_config();
+    config.is_some()
+        && config == b.to_config()
+        && a.name() == b.name()
+        && matches!((a.source_type(), b.source_type()), (Ok(x), Ok(y)) if x == y)
+        && matches!((a.dest_type(), b.dest_type()), (Ok(x), Ok(y)) if x == y)
+}
+
+impl Default for MemoryRegistry {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut factories: HashMap<String, Arc<dyn EmbeddingFunctionFactory>> = HashMap::new();
+        #[cfg(feature = "cohere")]
+        factories.insert(
+            "cohere".to_string(),
+            Arc::new(cohere::CohereEmbeddingFunctionFactory::default()),
+        );
+        #[cfg(feature = "ollama")]
+        factories.insert(
+            "ollama".to_string(),
+            Arc::new(ollama::OllamaEmbeddingFunctionFactory::default()),
+        );
+        #[cfg(feature = "openai")]
+        factories.insert(
+            "openai".to_string(),
+            Arc::new(openai::OpenAIEmbeddingFunctionFactory::default()),
+        );
+        #[cfg(feature = "sentence-transformers")]
+        factories.insert(
+            "sentence-transformers".to_string(),
+            Arc::new(sentence_transformers::SentenceTransformersEmbeddingsFactory::default()),
+        );
+        Self {
+            functions: Default::default(),
+            factories: Arc::new(RwLock::new(factories)),
+        }
+    }
 }
 
 impl EmbeddingRegistry for MemoryRegistry {
@@ -103,6 +807,21 @@ impl EmbeddingRegistry for MemoryRegistry {
         self.functions.read().unwrap().keys().cloned().collect()
     }
     fn register(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()> {
+        match self.functions.write().unwrap().entry(name.to_string()) {
+            Entry::Occupied(entry) if same_function(entry.get().as_ref(), function.as_ref()) => {
+                Ok(())
+            }
+            Entry::Occupied(_) => Err(Error::EmbeddingFunctionAlreadyExists {
+                name: name.to_string(),
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(function);
+                Ok(())
+            }
+        }
+    }
+
+    fn register_or_replace(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()> {
         self.functions
             .write()
             .unwrap()
@@ -111,9 +830,29 @@ impl EmbeddingRegistry for MemoryRegistry {
         Ok(())
     }
 
+    fn unregister(&self, name: &str) -> Result<bool> {
+        Ok(self.functions.write().unwrap().remove(name).is_some())
+    }
+
     fn get(&self, name: &str) -> Option<Arc<dyn EmbeddingFunction>> {
         self.functions.read().unwrap().get(name).cloned()
     }
+
+    fn len(&self) -> usize {
+        self.functions.read().unwrap().len()
+    }
+
+    fn register_factory(&self, factory: Arc<dyn EmbeddingFunctionFactory>) -> Result<()> {
+        self.factories
+            .write()
+            .unwrap()
+            .insert(factory.name().to_string(), factory);
+        Ok(())
+    }
+
+    fn get_factory(&self, name: &str) -> Option<Arc<dyn EmbeddingFunctionFactory>> {
+        self.factories.read().unwrap().get(name).cloned()
+    }
 }
 
 impl MemoryRegistry {
@@ -123,12 +862,302 @@ impl MemoryRegistry {
     }
 }
 
+/// Counters of a [`CachedEmbeddingFunction`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingCacheStats {
+    /// The number of input values whose embedding was found in the cache
+    pub hits: u64,
+    /// The number of distinct input values that were passed to the wrapped function
+    pub misses: u64,
+    /// The number of embeddings currently in the cache
+    pub entries: usize,
+}
+
+/// An [`EmbeddingFunction`] that caches the source embeddings of another one
+///
+/// The cache is an LRU cache keyed by a hash of each input value.  Identical values
+/// within a call are embedded once, and only values that are not cached are passed
+/// to the wrapped function.  The input must be a string or binary array without nulls
+/// (the embedding pipeline never passes null values to embedding functions).
+///
+/// Query embeddings are not cached.  The wrapper has the same name and configuration
+/// as the wrapped function, so a table that re-creates the function from its metadata
+/// gets the function without the cache.
+#[derive(Debug)]
+pub struct CachedEmbeddingFunction {
+    inner: Arc<dyn EmbeddingFunction>,
+    cache: Mutex<LruCache<u64, Arc<dyn Array>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// The result of looking up the values of one call in the cache
+struct CacheLookup {
+    /// The cache key of every row
+    keys: Vec<u64>,
+    /// The embedding of every distinct value, `None` if it must be computed
+    embeddings: HashMap<u64, Option<Arc<dyn Array>>>,
+    /// The first row of every value that must be computed
+    missing: Vec<u32>,
+}
+
+impl CachedEmbeddingFunction {
+    /// Wrap `inner` with a cache of at most `capacity` embeddings
+    ///
+    /// Returns an error if the capacity is zero or `inner` doesn't take string or
+    /// binary input.
+    pub fn try_new(inner: Arc<dyn EmbeddingFunction>, capacity: usize) -> Result<Self> {
+        let capacity = NonZeroUsize::new(capacity).ok_or_else(|| Error::InvalidInput {
+            message: "the capacity of an embedding cache must be at least 1".to_string(),
+        })?;
+        let source_type = inner.source_type()?;
+        if !Self::is_supported(&source_type) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "embedding function '{}' takes {} input, only string and binary input can be cached",
+                    inner.name(),
+                    source_type
+                ),
+            });
+        }
+        Ok(Self {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// The hit and miss counters and the size of the cache
+    pub fn stats(&self) -> EmbeddingCacheStats {
+        EmbeddingCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.cache.lock().unwrap().len(),
+        }
+    }
+
+    fn is_supported(data_type: &DataType) -> bool {
+        matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary
+        )
+    }
+
+    fn cache_keys(source: &dyn Array) -> Result<Vec<u64>> {
+        if source.null_count() > 0 {
+            return Err(Error::InvalidInput {
+                message: "cached embedding functions can't embed null values".to_string(),
+            });
+        }
+        fn hash(value: &[u8]) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+        Ok(match source.data_type() {
+            DataType::Utf8 => source
+                .as_string::<i32>()
+                .iter()
+                .flatten()
+                .map(|v| hash(v.as_bytes()))
+                .collect(),
+            DataType::LargeUtf8 => source
+                .as_string::<i64>()
+                .iter()
+                .flatten()
+                .map(|v| hash(v.as_bytes()))
+                .collect(),
+            DataType::Binary => source
+                .as_binary::<i32>()
+                .iter()
+                .flatten()
+                .map(hash)
+                .collect(),
+            DataType::LargeBinary => source
+                .as_binary::<i64>()
+                .iter()
+                .flatten()
+                .map(hash)
+                .collect(),
+            other => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "cached embedding functions only support string and binary input, not {}",
+                        other
+                    ),
+                })
+            }
+        })
+    }
+
+    fn lookup(&self, source: &dyn Array) -> Result<CacheLookup> {
+        let keys = Self::cache_keys(source)?;
+        let mut embeddings: HashMap<u64, Option<Arc<dyn Array>>> =
+            HashMap::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        let mut hits = 0;
+        let mut cache = self.cache.lock().unwrap();
+        for (row, key) in keys.iter().enumerate() {
+            match embeddings.entry(*key) {
+                Entry::Occupied(entry) => {
+                    if entry.get().is_some() {
+                        hits += 1;
+                    }
+                }
+                Entry::Vacant(entry) => match cache.get(key) {
+                    Some(embedding) => {
+                        hits += 1;
+                        entry.insert(Some(embedding.clone()));
+                    }
+                    None => {
+                        missing.push(row as u32);
+                        entry.insert(None);
+                    }
+                },
+            }
+        }
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+        Ok(CacheLookup {
+            keys,
+            embeddings,
+            missing,
+        })
+    }
+
+    /// Cache the embeddings computed for the missing values and assemble the output
+    fn finish(
+        &self,
+        mut lookup: CacheLookup,
+        computed: Option<Arc<dyn Array>>,
+    ) -> Result<Arc<dyn Array>> {
+        if let Some(computed) = computed {
+            if computed.len() != lookup.missing.len() {
+                return Err(Error::Runtime {
+                    message: format!(
+                        "embedding function '{}' returned {} embeddings for {} values",
+                        self.inner.name(),
+                        computed.len(),
+                        lookup.missing.len()
+                    ),
+                });
+            }
+            let mut cache = self.cache.lock().unwrap();
+            for (i, row) in lookup.missing.iter().enumerate() {
+                // Copy the row so that the cache doesn't keep the whole result alive
+                let embedding =
+                    arrow::compute::take(&computed, &UInt32Array::from(vec![i as u32]), None)?;
+                let key = lookup.keys[*row as usize];
+                cache.put(key, embedding.clone());
+                lookup.embeddings.insert(key, Some(embedding));
+            }
+            self.misses
+                .fetch_add(lookup.missing.len() as u64, Ordering::Relaxed);
+        }
+        let rows = lookup
+            .keys
+            .iter()
+            .map(|key| lookup.embeddings[key].as_deref().unwrap())
+            .collect::<Vec<_>>();
+        Ok(arrow::compute::concat(&rows)?)
+    }
+
+    /// The distinct values that are not cached, if any
+    fn missing_values(lookup: &CacheLookup, source: &dyn Array) -> Result<Option<Arc<dyn Array>>> {
+        if lookup.missing.is_empty() {
+            return Ok(None);
+        }
+        let indices = UInt32Array::from(lookup.missing.clone());
+        Ok(Some(arrow::compute::take(source, &indices, None)?))
+    }
+}
+
+#[async_trait]
+impl EmbeddingFunction for CachedEmbeddingFunction {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn source_type(&self) -> Result<Cow<DataType>> {
+        self.inner.source_type()
+    }
+
+    fn dest_type(&self) -> Result<Cow<DataType>> {
+        self.inner.dest_type()
+    }
+
+    fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        if source.is_empty() {
+            return Ok(new_empty_array(self.dest_type()?.as_ref()));
+        }
+        let lookup = self.lookup(source.as_ref())?;
+        let computed = match Self::missing_values(&lookup, source.as_ref())? {
+            Some(values) => Some(self.inner.compute_source_embeddings(values)?),
+            None => None,
+        };
+        self.finish(lookup, computed)
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        self.inner.compute_query_embeddings(input)
+    }
+
+    async fn compute_source_embeddings_async(
+        &self,
+        source: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        if source.is_empty() {
+            return Ok(new_empty_array(self.dest_type()?.as_ref()));
+        }
+        let lookup = self.lookup(source.as_ref())?;
+        let computed = match Self::missing_values(&lookup, source.as_ref())? {
+            Some(values) => Some(self.inner.compute_source_embeddings_async(values).await?),
+            None => None,
+        };
+        self.finish(lookup, computed)
+    }
+
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        self.inner.compute_query_embeddings_async(input).await
+    }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        self.inner.max_batch_size()
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        self.inner.to_config()
+    }
+}
+
 /// A record batch reader that has embeddings applied to it
 /// This is a wrapper around another record batch reader that applies an embedding function
 /// when reading from the record batch
+///
+/// Batches that weren't embedded ahead with [`Self::embed_ahead`] are embedded as
+/// they are read.  On a multi-threaded runtime the reader waits for the async
+/// interface of the functions, otherwise it calls their synchronous interface.
 pub struct WithEmbeddings<R: RecordBatchReader> {
     inner: R,
+    embedder: Embedder,
+    /// The number of batches that are embedded at the same time
+    concurrency: usize,
+    /// Embedded batches that were read ahead, in the order of the input
+    embedded: VecDeque<RecordBatch>,
+}
+
+/// The embeddings applied by [`WithEmbeddings`]
+///
+/// This is kept apart from the input so that embedding a batch doesn't borrow the
+/// reader, which is only [`Send`].
+struct Embedder {
     embeddings: Vec<(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)>,
+    /// The definition of the output, computed up front so that invalid embeddings
+    /// are reported before any data is read
+    table_definition: TableDefinition,
 }
 
 /// A record batch that might have embeddings applied to it.
@@ -142,103 +1171,386 @@ pub enum MaybeEmbedded<R: RecordBatchReader> {
 
 impl<R: RecordBatchReader> MaybeEmbedded<R> {
     /// Create a new RecordBatchReader with embeddings applied to it if the table definition
-    /// specifies an embedding column and the registry contains an embedding function with that name
-    /// Otherwise, this is a no-op and the inner RecordBatchReader is returned.
+    /// specifies an embedding column.  Otherwise, this is a no-op and the inner
+    /// RecordBatchReader is returned.
+    ///
+    /// Returns [`Error::EmbeddingFunctionNotFound`] if the table has an embedding column
+    /// but there is no registry, or the registry can't provide its function, rather
+    /// than writing the data without the embeddings.
     pub fn try_new(
         inner: R,
         table_definition: TableDefinition,
         registry: Option<Arc<dyn EmbeddingRegistry>>,
     ) -> Result<Self> {
-        if let Some(registry) = registry {
-            let mut embeddings = Vec::with_capacity(table_definition.column_definitions.len());
-            for cd in table_definition.column_definitions.iter() {
-                if let ColumnKind::Embedding(embedding_def) = &cd.kind {
-                    match registry.get(&embedding_def.embedding_name) {
-                        Some(func) => {
-                            embeddings.push((embedding_def.clone(), func));
-                        }
-                        None => {
-                            return Err(Error::EmbeddingFunctionNotFound {
-                                name: embedding_def.embedding_name.clone(),
-                                reason: format!(
-                                    "Table was defined with an embedding column `{}` but no embedding function was found with that name within the registry.",
-                                    embedding_def.embedding_name
-                                ),
-                            });
-                        }
-                    }
-                }
+        let mut embeddings = Vec::with_capacity(table_definition.column_definitions.len());
+        for cd in table_definition.column_definitions.iter() {
+            if let ColumnKind::Embedding(embedding_def) = &cd.kind {
+                let Some(registry) = &registry else {
+                    return Err(Error::EmbeddingFunctionNotFound {
+                        name: embedding_def.embedding_name.clone(),
+                        reason: format!(
+                            "The table has an embedding column `{}` but no embedding registry was given to compute it.",
+                            embedding_def.dest_column_name()
+                        ),
+                    });
+                };
+                let func = resolve_embedding_function(
+                    registry.as_ref(),
+                    embedding_def,
+                    &table_definition,
+                )?;
+                embeddings.push((embedding_def.clone(), func));
             }
+        }
 
-            if !embeddings.is_empty() {
-                return Ok(Self::Yes(WithEmbeddings { inner, embeddings }));
-            }
-        };
+        if !embeddings.is_empty() {
+            return Ok(Self::Yes(WithEmbeddings::try_new(inner, embeddings)?));
+        }
 
         // No embeddings to apply
         Ok(Self::No(inner))
     }
+
+    /// Embed up to `concurrency` batches at the same time, see
+    /// [`WithEmbeddings::with_concurrency`]
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        match self {
+            Self::Yes(inner) => Self::Yes(inner.with_concurrency(concurrency)),
+            Self::No(inner) => Self::No(inner),
+        }
+    }
+
+    /// Embed batches before they are read, see [`WithEmbeddings::embed_ahead`]
+    pub async fn embed_ahead(self, max_size: usize) -> Result<Self> {
+        match self {
+            Self::Yes(inner) => Ok(Self::Yes(inner.embed_ahead(max_size).await?)),
+            Self::No(inner) => Ok(Self::No(inner)),
+        }
+    }
 }
 
 impl<R: RecordBatchReader> WithEmbeddings<R> {
-    pub fn new(
+    /// Create a reader that applies `embeddings` to the batches read from `inner`
+    ///
+    /// Returns an error if a source column is missing from the input or the output
+    /// type of an embedding function can't be determined.
+    pub fn try_new(
         inner: R,
         embeddings: Vec<(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)>,
-    ) -> Self {
-        Self { inner, embeddings }
+    ) -> Result<Self> {
+        let table_definition = Self::build_table_definition(&inner.schema(), &embeddings)?;
+        Ok(Self {
+            inner,
+            embedder: Embedder {
+                embeddings,
+                table_definition,
+            },
+            concurrency: 1,
+            embedded: VecDeque::new(),
+        })
+    }
+
+    /// Embed up to `concurrency` batches at the same time
+    ///
+    /// The reader reads that many batches ahead of the consumer, embeds them
+    /// concurrently and returns them in their original order.  If any of them
+    /// fails, the others are cancelled and the error is returned.  By default one
+    /// batch is embedded at a time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Embed the batches of the input until `max_size` bytes of them are embedded or
+    /// the input ends
+    ///
+    /// The embeddings are awaited here, so async embedding functions don't block
+    /// the runtime.  The reader returns these batches first and embeds the rest of a
+    /// larger input as it is read.  Lance reads its input synchronously, so the
+    /// async write paths call this before handing the reader to it.
+    pub async fn embed_ahead(mut self, max_size: usize) -> Result<Self> {
+        let mut size = self
+            .embedded
+            .iter()
+            .map(RecordBatch::get_array_memory_size)
+            .sum::<usize>();
+        while size < max_size {
+            let batches = self.read_batches()?;
+            if batches.is_empty() {
+                break;
+            }
+            let embedder = &self.embedder;
+            let embedded = futures::future::try_join_all(
+                batches
+                    .into_iter()
+                    .map(|batch| embedder.embed_batch(batch, false)),
+            )
+            .await?;
+            size += embedded
+                .iter()
+                .map(RecordBatch::get_array_memory_size)
+                .sum::<usize>();
+            self.embedded.extend(embedded);
+        }
+        Ok(self)
+    }
+
+    /// Read up to `concurrency` batches from the input
+    fn read_batches(&mut self) -> std::result::Result<Vec<RecordBatch>, ArrowError> {
+        let mut batches = Vec::with_capacity(self.concurrency);
+        while batches.len() < self.concurrency {
+            match self.inner.next() {
+                Some(batch) => batches.push(batch?),
+                None => break,
+            }
+        }
+        Ok(batches)
     }
 }
 
 impl<R: RecordBatchReader> WithEmbeddings<R> {
-    fn dest_fields(&self) -> Result<Vec<Field>> {
-        let schema = self.inner.schema();
-        self.embeddings
-            .iter()
-            .map(|(ed, func)| {
-                let src_field = schema.field_with_name(&ed.source_column).unwrap();
-
-                let field_name = ed
-                    .dest_column
-                    .clone()
-                    .unwrap_or_else(|| format!("{}_embedding", &ed.source_column));
-                Ok(Field::new(
-                    field_name,
-                    func.dest_type()?.into_owned(),
-                    src_field.is_nullable(),
-                ))
-            })
-            .collect()
+    /// Apply every embedding to a batch, appending the embedding columns
+    ///
+    /// The embedding functions are run concurrently.
+    pub async fn embed_batch(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        self.embedder.embed_batch(batch, false).await
     }
+}
 
-    fn column_defs(&self) -> Vec<ColumnDefinition> {
-        let base_schema = self.inner.schema();
-        base_schema
-            .fields()
+impl Embedder {
+    /// Apply every embedding to a batch, with the synchronous interface of the
+    /// functions if `blocking` is set
+    async fn embed_batch(&self, batch: RecordBatch, blocking: bool) -> Result<RecordBatch> {
+        let batch = self.skip_null_rows(batch)?;
+        // Pre-computed embeddings are kept as they are
+        let embeddings = self
+            .embeddings
             .iter()
-            .map(|_| ColumnDefinition {
-                kind: ColumnKind::Physical,
+            .filter(|(fld, _)| {
+                fld.if_exists != ExistingColumnPolicy::Skip
+                    || batch.column_by_name(&fld.dest_column_name()).is_none()
             })
-            .chain(self.embeddings.iter().map(|(ed, _)| ColumnDefinition {
-                kind: ColumnKind::Embedding(ed.clone()),
-            }))
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+        let computed = futures::future::try_join_all(embeddings.iter().map(|(fld, func)| {
+            let src_column = batch.column_by_name(&fld.source_column).cloned();
+            async move {
+                let src_column = src_column.ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                        "source column '{}' for embedding was not found in the data",
+                        fld.source_column
+                    ),
+                })?;
+                check_source_type(fld, func.as_ref(), src_column.data_type())?;
+                let dest_type = match self
+                    .table_definition
+                    .schema
+                    .field_with_name(&fld.dest_column_name())
+                {
+                    Ok(field) => field.data_type().clone(),
+                    Err(_) => func.dest_type()?.into_owned(),
+                };
+                compute_source_embeddings_with_nulls(
+                    fld,
+                    func.as_ref(),
+                    src_column,
+                    &dest_type,
+                    blocking,
+                )
+                .await
+            }
+        }))
+        .await?;
+
+        let mut batch = batch;
+        for ((fld, _), embedding) in embeddings.into_iter().zip(computed) {
+            let dst_field_name = fld.dest_column_name();
+            // Use the type and nullability from the table definition rather than the
+            // array since concatenating chunks may drop an all-valid null buffer and
+            // functions may name their list items differently.  Writers like merge
+            // insert require the batches to match the schema exactly.
+            let (embedding, nullable) = match self
+                .table_definition
+                .schema
+                .field_with_name(&dst_field_name)
+            {
+                Ok(field) if field.data_type() != embedding.data_type() => (
+                    arrow::compute::cast(&embedding, field.data_type())?,
+                    field.is_nullable(),
+                ),
+                Ok(field) => (embedding, field.is_nullable()),
+                Err(_) => {
+                    let nullable = embedding.nulls().is_some();
+                    (embedding, nullable)
+                }
+            };
+
+            let dst_field = Field::new(dst_field_name, embedding.data_type().clone(), nullable);
+
+            batch = match batch.schema().index_of(dst_field.name()) {
+                // The data has the column and the embedding overwrites it
+                Ok(index) => {
+                    let schema = batch.schema();
+                    let mut fields = schema.fields().to_vec();
+                    let mut columns = batch.columns().to_vec();
+                    fields[index] = Arc::new(dst_field);
+                    columns[index] = embedding;
+                    RecordBatch::try_new(
+                        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+                        columns,
+                    )?
+                }
+                Err(_) => batch.try_with_column(dst_field, embedding)?,
+            };
+        }
+        Ok(batch)
     }
 
-    pub fn table_definition(&self) -> Result<TableDefinition> {
-        let base_schema = self.inner.schema();
+    /// Remove the rows whose source column is null for embeddings with [`NullPolicy::SkipRow`]
+    fn skip_null_rows(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let mut keep: Option<BooleanArray> = None;
+        for (fld, _) in self.embeddings.iter() {
+            if fld.on_null != NullPolicy::SkipRow {
+                continue;
+            }
+            let Some(src_column) = batch.column_by_name(&fld.source_column) else {
+                continue;
+            };
+            if src_column.null_count() == 0 {
+                continue;
+            }
+            let valid = arrow::compute::is_not_null(src_column)?;
+            keep = Some(match keep {
+                Some(keep) => arrow::compute::and(&keep, &valid)?,
+                None => valid,
+            });
+        }
+        match keep {
+            Some(keep) => Ok(arrow::compute::filter_record_batch(&batch, &keep)?),
+            None => Ok(batch),
+        }
+    }
+}
+
+impl<R: RecordBatchReader> WithEmbeddings<R> {
+    /// The field of the embeddings of `ed`, checking the source column of the data
+    fn dest_field(
+        schema: &Schema,
+        ed: &EmbeddingDefinition,
+        func: &dyn EmbeddingFunction,
+    ) -> Result<Field> {
+        let src_field =
+            schema
+                .field_with_name(&ed.source_column)
+                .map_err(|_| Error::InvalidInput {
+                    message: format!(
+                        "source column '{}' for embedding function '{}' was not found in the data",
+                        ed.source_column, ed.embedding_name
+                    ),
+                })?;
+        check_source_type(ed, func, src_field.data_type())?;
 
-        let output_fields = self.dest_fields()?;
-        let column_definitions = self.column_defs();
+        Ok(Field::new(
+            ed.dest_column_name(),
+            func.dest_type()?.into_owned(),
+            src_field.is_nullable(),
+        ))
+    }
+
+    /// The definition of the output, with a column for each embedding
+    ///
+    /// The destination columns are appended to the columns of the data, unless the
+    /// data already has them and [`EmbeddingDefinition::if_exists`] allows it.
+    fn build_table_definition(
+        base_schema: &Schema,
+        embeddings: &[(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)],
+    ) -> Result<TableDefinition> {
+        let mut fields = base_schema.fields().to_vec();
+        let mut column_definitions = vec![
+            ColumnDefinition {
+                kind: ColumnKind::Physical,
+            };
+            fields.len()
+        ];
+        let mut dest_columns = HashSet::with_capacity(embeddings.len());
+        for (ed, func) in embeddings {
+            let dest_column = ed.dest_column_name();
+            if RESERVED_COLUMN_NAMES.contains(&dest_column.as_str()) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the destination column '{}' of embedding function '{}' is reserved for search results, choose another name",
+                        dest_column, ed.embedding_name
+                    ),
+                });
+            }
+            if !dest_columns.insert(dest_column.clone()) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "more than one embedding writes to the column '{}', each embedding needs its own destination column",
+                        dest_column
+                    ),
+                });
+            }
 
-        let mut sb: SchemaBuilder = base_schema.as_ref().into();
-        sb.extend(output_fields);
+            let definition = ColumnDefinition {
+                kind: ColumnKind::Embedding(ed.clone()),
+            };
+            let Ok(index) = base_schema.index_of(&dest_column) else {
+                fields.push(Arc::new(Self::dest_field(base_schema, ed, func.as_ref())?));
+                column_definitions.push(definition);
+                continue;
+            };
+            match ed.if_exists {
+                ExistingColumnPolicy::Error => {
+                    return Err(Error::InvalidInput {
+                        message: format!(
+                            "the destination column '{}' of embedding function '{}' already exists in the data, set if_exists on the embedding definition to skip or overwrite it",
+                            dest_column, ed.embedding_name
+                        ),
+                    });
+                }
+                ExistingColumnPolicy::Skip => {
+                    let dest_type = func.dest_type()?;
+                    let data_type = fields[index].data_type();
+                    if !compatible_embedding_type(data_type, &dest_type) {
+                        return Err(Error::InvalidInput {
+                            message: format!(
+                                "the column '{}' of the data has {} but embedding function '{}' produces {}",
+                                dest_column,
+                                describe_embedding_type(data_type),
+                                ed.embedding_name,
+                                describe_embedding_type(&dest_type)
+                            ),
+                        });
+                    }
+                }
+                ExistingColumnPolicy::Overwrite => {
+                    fields[index] = Arc::new(Self::dest_field(base_schema, ed, func.as_ref())?);
+                }
+            }
+            column_definitions[index] = definition;
+        }
 
-        let schema = Arc::new(sb.finish());
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            base_schema.metadata().clone(),
+        ));
+        let embedding_functions = embeddings
+            .iter()
+            .filter_map(|(ed, func)| {
+                EmbeddingFunctionConfig::from_function(func.as_ref())
+                    .map(|config| (ed.embedding_name.clone(), config))
+            })
+            .collect();
         Ok(TableDefinition {
             schema,
             column_definitions,
+            embedding_functions,
         })
     }
+
+    pub fn table_definition(&self) -> Result<TableDefinition> {
+        Ok(self.embedder.table_definition.clone())
+    }
 }
 
 impl<R: RecordBatchReader> Iterator for MaybeEmbedded<R> {
@@ -264,48 +1576,41 @@ impl<R: RecordBatchReader> Iterator for WithEmbeddings<R> {
     type Item = std::result::Result<RecordBatch, arrow_schema::ArrowError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let batch = self.inner.next()?;
-        match batch {
-            Ok(mut batch) => {
-                // todo: parallelize this
-                for (fld, func) in self.embeddings.iter() {
-                    let src_column = batch.column_by_name(&fld.source_column).unwrap();
-                    let embedding = match func.compute_source_embeddings(src_column.clone()) {
-                        Ok(embedding) => embedding,
-                        Err(e) => {
-                            return Some(Err(arrow_schema::ArrowError::ComputeError(format!(
-                                "Error computing embedding: {}",
-                                e
-                            ))))
-                        }
-                    };
-                    let dst_field_name = fld
-                        .dest_column
-                        .clone()
-                        .unwrap_or_else(|| format!("{}_embedding", &fld.source_column));
-
-                    let dst_field = Field::new(
-                        dst_field_name,
-                        embedding.data_type().clone(),
-                        embedding.nulls().is_some(),
-                    );
-
-                    match batch.try_with_column(dst_field.clone(), embedding) {
-                        Ok(b) => batch = b,
-                        Err(e) => return Some(Err(e)),
-                    };
-                }
-                Some(Ok(batch))
+        if let Some(batch) = self.embedded.pop_front() {
+            return Some(Ok(batch));
+        }
+        let batches = match self.read_batches() {
+            Ok(batches) if batches.is_empty() => return None,
+            Ok(batches) => batches,
+            Err(err) => return Some(Err(err)),
+        };
+        let embedder = &self.embedder;
+        let embed = |blocking| {
+            futures::future::try_join_all(
+                batches
+                    .into_iter()
+                    .map(|batch| embedder.embed_batch(batch, blocking)),
+            )
+        };
+        let embedded = match can_block_in_place() {
+            Some(handle) => tokio::task::block_in_place(|| handle.block_on(embed(false))),
+            // There is no runtime that can be blocked to wait for async functions,
+            // so the synchronous interface of the functions is used
+            None => futures::executor::block_on(embed(true)),
+        };
+        match embedded {
+            Ok(embedded) => {
+                self.embedded.extend(embedded);
+                self.embedded.pop_front().map(Ok)
             }
-            Err(e) => Some(Err(e)),
+            Err(Error::Arrow { source }) => Some(Err(source)),
+            Err(e) => Some(Err(ArrowError::ExternalError(Box::new(e)))),
         }
     }
 }
 
 impl<R: RecordBatchReader> RecordBatchReader for WithEmbeddings<R> {
     fn schema(&self) -> Arc<arrow_schema::Schema> {
-        self.table_definition()
-            .expect("table definition should be infallible at this point")
-            .into_rich_schema()
+        self.embedder.table_definition.clone().into_rich_schema()
     }
 }
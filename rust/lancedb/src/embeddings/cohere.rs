@@ -0,0 +1,601 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeddings computed with the [Cohere embed API](https://docs.cohere.com/reference/embed)
+
+use std::{borrow::Cow, fmt::Formatter, str::FromStr, sync::Arc, time::Duration};
+
+use arrow::array::AsArray;
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, Float32Array};
+use arrow_data::ArrayData;
+use arrow_schema::DataType;
+use async_trait::async_trait;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+use super::{block_on_embeddings, EmbeddingFunction, EmbeddingFunctionFactory};
+
+const DEFAULT_API_BASE: &str = "https://api.cohere.com/v1";
+/// The embed endpoint accepts at most this many texts per request
+const MAX_TEXTS_PER_REQUEST: usize = 96;
+const API_KEY_ENV: &str = "COHERE_API_KEY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CohereEmbeddingModel {
+    EmbedEnglishV3,
+    EmbedMultilingualV3,
+    EmbedEnglishLightV3,
+    EmbedMultilingualLightV3,
+}
+
+impl CohereEmbeddingModel {
+    fn ndims(&self) -> usize {
+        match self {
+            Self::EmbedEnglishV3 | Self::EmbedMultilingualV3 => 1024,
+            Self::EmbedEnglishLightV3 | Self::EmbedMultilingualLightV3 => 384,
+        }
+    }
+}
+
+impl FromStr for CohereEmbeddingModel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "embed-english-v3.0" => Ok(Self::EmbedEnglishV3),
+            "embed-multilingual-v3.0" => Ok(Self::EmbedMultilingualV3),
+            "embed-english-light-v3.0" => Ok(Self::EmbedEnglishLightV3),
+            "embed-multilingual-light-v3.0" => Ok(Self::EmbedMultilingualLightV3),
+            _ => Err(Error::InvalidInput {
+                message: "Invalid input. Available models are: 'embed-english-v3.0', 'embed-multilingual-v3.0', 'embed-english-light-v3.0', 'embed-multilingual-light-v3.0'".to_string()
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for CohereEmbeddingModel {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::EmbedEnglishV3 => write!(f, "embed-english-v3.0"),
+            Self::EmbedMultilingualV3 => write!(f, "embed-multilingual-v3.0"),
+            Self::EmbedEnglishLightV3 => write!(f, "embed-english-light-v3.0"),
+            Self::EmbedMultilingualLightV3 => write!(f, "embed-multilingual-light-v3.0"),
+        }
+    }
+}
+
+impl TryFrom<&str> for CohereEmbeddingModel {
+    type Error = Error;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// What the embeddings will be used for
+///
+/// The v3 models produce different embeddings for documents and for the
+/// queries used to search them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CohereInputType {
+    /// Documents stored in the table (the default for source embeddings)
+    SearchDocument,
+    /// Search queries (always used for query embeddings)
+    SearchQuery,
+    Classification,
+    Clustering,
+}
+
+/// How inputs longer than the model's maximum length are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CohereTruncate {
+    /// Return an error for inputs that are too long
+    None,
+    /// Discard the start of the input
+    Start,
+    /// Discard the end of the input (the default)
+    End,
+}
+
+pub struct CohereEmbeddingFunction {
+    model: CohereEmbeddingModel,
+    api_key: String,
+    api_base: Option<String>,
+    input_type: CohereInputType,
+    truncate: Option<CohereTruncate>,
+    max_retries: usize,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for CohereEmbeddingFunction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        // let's be safe and not print the API key
+        f.debug_struct("Cohere")
+            .field("model", &self.model)
+            .field("api_base", &self.api_base)
+            .field("input_type", &self.input_type)
+            .field("truncate", &self.truncate)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl CohereEmbeddingFunction {
+    /// Create a new CohereEmbeddingFunction using `embed-english-v3.0`
+    pub fn new<A: Into<String>>(api_key: A) -> Self {
+        Self::new_impl(api_key.into(), CohereEmbeddingModel::EmbedEnglishV3)
+    }
+
+    /// Create a new CohereEmbeddingFunction with the API key from the
+    /// `COHERE_API_KEY` environment variable
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(api_key_from_env()?))
+    }
+
+    pub fn new_with_model<A: Into<String>, M: TryInto<CohereEmbeddingModel>>(
+        api_key: A,
+        model: M,
+    ) -> crate::Result<Self>
+    where
+        M::Error: Into<crate::Error>,
+    {
+        Ok(Self::new_impl(
+            api_key.into(),
+            model.try_into().map_err(|e| e.into())?,
+        ))
+    }
+
+    /// concrete implementation to reduce monomorphization
+    fn new_impl(api_key: String, model: CohereEmbeddingModel) -> Self {
+        Self {
+            model,
+            api_key,
+            api_base: None,
+            input_type: CohereInputType::SearchDocument,
+            truncate: None,
+            max_retries: 3,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// To use a API base url different from default "https://api.cohere.com/v1"
+    pub fn api_base<S: Into<String>>(mut self, api_base: S) -> Self {
+        self.api_base = Some(api_base.into());
+        self
+    }
+
+    /// The input type used when embedding the source column
+    ///
+    /// Defaults to [`CohereInputType::SearchDocument`].  Query embeddings always
+    /// use [`CohereInputType::SearchQuery`].
+    pub fn input_type(mut self, input_type: CohereInputType) -> Self {
+        self.input_type = input_type;
+        self
+    }
+
+    /// How to handle inputs that are longer than the model supports
+    ///
+    /// If not set, the API default (truncating the end) is used.
+    pub fn truncate(mut self, truncate: CohereTruncate) -> Self {
+        self.truncate = Some(truncate);
+        self
+    }
+
+    /// How many times a request that was rate limited, or failed with a server
+    /// error, is retried.  Defaults to 3.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    fn fixed_size_list(&self, values: Float32Array, len: usize) -> Result<ArrayRef> {
+        let fsl =
+            DataType::new_fixed_size_list(DataType::Float32, self.model.ndims() as i32, false);
+        // We can't use the FixedSizeListBuilder here because it always adds a null bitmap
+        // and we want to explicitly work with non-nullable arrays.
+        let array_data = ArrayData::builder(fsl)
+            .len(len)
+            .add_child_data(values.into_data())
+            .build()?;
+        Ok(Arc::new(FixedSizeListArray::from(array_data)))
+    }
+}
+
+#[async_trait]
+impl EmbeddingFunction for CohereEmbeddingFunction {
+    fn name(&self) -> &str {
+        "cohere"
+    }
+
+    fn source_type(&self) -> Result<Cow<DataType>> {
+        Ok(Cow::Owned(DataType::Utf8))
+    }
+
+    fn dest_type(&self) -> Result<Cow<DataType>> {
+        Ok(Cow::Owned(DataType::new_fixed_size_list(
+            DataType::Float32,
+            self.model.ndims() as i32,
+            false,
+        )))
+    }
+
+    fn compute_source_embeddings(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
+        block_on_embeddings(self.compute_source_embeddings_async(source))
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        block_on_embeddings(self.compute_query_embeddings_async(input))
+    }
+
+    async fn compute_source_embeddings_async(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
+        let len = source.len();
+        let values = self.compute_inner(source, self.input_type).await?;
+        self.fixed_size_list(values, len)
+    }
+
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        let arr = self
+            .compute_inner(input, CohereInputType::SearchQuery)
+            .await?;
+        Ok(Arc::new(arr))
+    }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        Some(MAX_TEXTS_PER_REQUEST)
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        // The API key is a secret and is never stored
+        let config = StoredConfig {
+            model: self.model.to_string(),
+            api_base: self.api_base.clone(),
+            input_type: self.input_type,
+            truncate: self.truncate,
+        };
+        Some(serde_json::to_value(config).unwrap())
+    }
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    texts: Vec<&'a str>,
+    model: String,
+    input_type: CohereInputType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncate: Option<CohereTruncate>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl CohereEmbeddingFunction {
+    async fn compute_inner(
+        &self,
+        source: Arc<dyn Array>,
+        input_type: CohereInputType,
+    ) -> Result<Float32Array> {
+        // Cohere only supports non-nullable string arrays
+        if source.is_nullable() {
+            return Err(crate::Error::InvalidInput {
+                message: "Expected non-nullable data type".to_string(),
+            });
+        }
+        let texts: Vec<&str> = match source.data_type() {
+            DataType::Utf8 => source.as_string::<i32>().iter().flatten().collect(),
+            DataType::LargeUtf8 => source.as_string::<i64>().iter().flatten().collect(),
+            _ => {
+                return Err(crate::Error::InvalidInput {
+                    message: "Expected Utf8 data type".to_string(),
+                })
+            }
+        };
+
+        let mut values = Vec::with_capacity(texts.len() * self.model.ndims());
+        for chunk in texts.chunks(MAX_TEXTS_PER_REQUEST) {
+            let request = EmbedRequest {
+                texts: chunk.to_vec(),
+                model: self.model.to_string(),
+                input_type,
+                truncate: self.truncate,
+            };
+            let response = self.embed(&request).await?;
+            if response.embeddings.len() != chunk.len() {
+                return Err(Error::Runtime {
+                    message: format!(
+                        "Cohere returned {} embeddings for {} texts",
+                        response.embeddings.len(),
+                        chunk.len()
+                    ),
+                });
+            }
+            for embedding in response.embeddings {
+                if embedding.len() != self.model.ndims() {
+                    return Err(Error::Runtime {
+                        message: format!(
+                            "Cohere returned an embedding with {} dimensions, expected {}",
+                            embedding.len(),
+                            self.model.ndims()
+                        ),
+                    });
+                }
+                values.extend(embedding);
+            }
+        }
+        Ok(Float32Array::from(values))
+    }
+
+    /// Send an embed request, retrying when rate limited or on server errors
+    async fn embed(&self, request: &EmbedRequest<'_>) -> Result<EmbedResponse> {
+        let url = format!(
+            "{}/embed",
+            self.api_base
+                .as_deref()
+                .unwrap_or(DEFAULT_API_BASE)
+                .trim_end_matches('/')
+        );
+        let mut backoff = Duration::from_secs(1);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let response = self
+                .client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(request)
+                .send()
+                .await
+                .map_err(|e| Error::Runtime {
+                    message: format!("Cohere request failed: {}", e),
+                })?;
+            let status = response.status();
+            if status.is_success() {
+                return response.json().await.map_err(|e| Error::Runtime {
+                    message: format!("Failed to parse the Cohere response: {}", e),
+                });
+            }
+
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            let body = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<ErrorResponse>(&body)
+                .map(|e| e.message)
+                .unwrap_or(body);
+
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if retryable && attempts <= self.max_retries {
+                let delay = retry_after.unwrap_or(backoff);
+                log::debug!(
+                    "Cohere request failed with {} on attempt {}, retrying in {:?}",
+                    status,
+                    attempts,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                backoff *= 2;
+                continue;
+            }
+
+            return Err(if status == StatusCode::TOO_MANY_REQUESTS {
+                Error::RateLimited {
+                    message: format!("Cohere rate limit exceeded: {}", message),
+                    retry_after,
+                }
+            } else {
+                Error::Runtime {
+                    message: format!("Cohere request failed ({}): {}", status, message),
+                }
+            });
+        }
+    }
+}
+
+fn api_key_from_env() -> Result<String> {
+    std::env::var(API_KEY_ENV).map_err(|_| Error::InvalidInput {
+        message: format!(
+            "a Cohere API key is required, set the {} environment variable",
+            API_KEY_ENV
+        ),
+    })
+}
+
+/// The configuration of a [`CohereEmbeddingFunction`] stored in table metadata
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_base: Option<String>,
+    input_type: CohereInputType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    truncate: Option<CohereTruncate>,
+}
+
+/// Re-creates [`CohereEmbeddingFunction`]s from table metadata
+///
+/// The API key is not stored with the table.  It is taken from the factory or,
+/// if the factory has none, from the `COHERE_API_KEY` environment variable.
+#[derive(Default)]
+pub struct CohereEmbeddingFunctionFactory {
+    api_key: Option<String>,
+}
+
+impl std::fmt::Debug for CohereEmbeddingFunctionFactory {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("CohereEmbeddingFunctionFactory")
+            .field("api_key", &self.api_key.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl CohereEmbeddingFunctionFactory {
+    /// Create a factory that uses the given API key
+    pub fn new<A: Into<String>>(api_key: A) -> Self {
+        Self {
+            api_key: Some(api_key.into()),
+        }
+    }
+}
+
+impl EmbeddingFunctionFactory for CohereEmbeddingFunctionFactory {
+    fn name(&self) -> &str {
+        "cohere"
+    }
+
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>> {
+        let config: StoredConfig =
+            serde_json::from_value(config.clone()).map_err(|e| Error::InvalidInput {
+                message: format!("invalid Cohere embedding function config: {}", e),
+            })?;
+        let api_key = match &self.api_key {
+            Some(api_key) => api_key.clone(),
+            None => api_key_from_env()?,
+        };
+        let mut func = CohereEmbeddingFunction::new_with_model(api_key, config.model.as_str())?
+            .input_type(config.input_type);
+        func.api_base = config.api_base;
+        func.truncate = config.truncate;
+        Ok(Arc::new(func))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::embeddings::test_utils::mock_server;
+
+    fn embeddings_response(count: usize, ndims: usize) -> String {
+        let embeddings = (0..count)
+            .map(|i| vec![i as f32; ndims])
+            .collect::<Vec<_>>();
+        serde_json::json!({ "id": "abc", "embeddings": embeddings }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_batches_requests() {
+        let ndims = 384;
+        let (api_base, requests) = mock_server(vec![
+            (200, vec![], embeddings_response(96, ndims)),
+            (200, vec![], embeddings_response(4, ndims)),
+        ])
+        .await;
+        let func = CohereEmbeddingFunction::new_with_model("key", "embed-english-light-v3.0")
+            .unwrap()
+            .api_base(api_base)
+            .truncate(CohereTruncate::Start);
+
+        let source = Arc::new(StringArray::from_iter_values(
+            (0..100).map(|i| format!("text {}", i)),
+        ));
+        let embeddings = func.compute_source_embeddings_async(source).await.unwrap();
+        assert_eq!(embeddings.len(), 100);
+        assert_eq!(embeddings.data_type(), func.dest_type().unwrap().as_ref());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].0, "/embed");
+        assert_eq!(requests[0].1["texts"].as_array().unwrap().len(), 96);
+        assert_eq!(requests[1].1["texts"][0], "text 96");
+        assert_eq!(requests[0].1["model"], "embed-english-light-v3.0");
+        assert_eq!(requests[0].1["input_type"], "search_document");
+        assert_eq!(requests[0].1["truncate"], "START");
+    }
+
+    #[tokio::test]
+    async fn test_query_input_type() {
+        let (api_base, requests) =
+            mock_server(vec![(200, vec![], embeddings_response(1, 1024))]).await;
+        let func = CohereEmbeddingFunction::new("key").api_base(api_base);
+        let query = Arc::new(StringArray::from(vec!["red shoes"]));
+        let embedding = func.compute_query_embeddings_async(query).await.unwrap();
+        assert_eq!(embedding.len(), 1024);
+        assert_eq!(requests.lock().unwrap()[0].1["input_type"], "search_query");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let rate_limited = || {
+            (
+                429,
+                vec![("retry-after", "0".to_string())],
+                r#"{"message": "too many requests"}"#.to_string(),
+            )
+        };
+        // Retried until it succeeds
+        let (api_base, requests) = mock_server(vec![
+            rate_limited(),
+            (200, vec![], embeddings_response(1, 1024)),
+        ])
+        .await;
+        let func = CohereEmbeddingFunction::new("key").api_base(api_base);
+        let source = Arc::new(StringArray::from(vec!["a"]));
+        func.compute_source_embeddings_async(source.clone())
+            .await
+            .unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // The retry-after hint is returned once the retries are used up
+        let (api_base, _) = mock_server(vec![rate_limited(), rate_limited()]).await;
+        let func = CohereEmbeddingFunction::new("key")
+            .api_base(api_base)
+            .max_retries(1);
+        let err = func
+            .compute_source_embeddings_async(source)
+            .await
+            .unwrap_err();
+        match err {
+            Error::RateLimited {
+                message,
+                retry_after,
+            } => {
+                assert!(message.contains("too many requests"), "{}", message);
+                assert_eq!(retry_after, Some(Duration::from_secs(0)));
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let func = CohereEmbeddingFunction::new_with_model("secret-key", "embed-multilingual-v3.0")
+            .unwrap()
+            .input_type(CohereInputType::Clustering)
+            .truncate(CohereTruncate::None);
+        let config = func.to_config().unwrap();
+        assert!(!config.to_string().contains("secret-key"));
+
+        let func = CohereEmbeddingFunctionFactory::new("other-key")
+            .from_config(&config)
+            .unwrap();
+        assert_eq!(func.to_config().unwrap(), config);
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeddings computed by a local [Ollama](https://ollama.com) server
+
+use std::{
+    borrow::Cow,
+    sync::{Arc, OnceLock},
+};
+
+use arrow::array::AsArray;
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, Float32Array};
+use arrow_data::ArrayData;
+use arrow_schema::DataType;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+use super::{block_on_embeddings, EmbeddingFunction, EmbeddingFunctionFactory};
+
+const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// An embedding function that calls the embed API of an Ollama server
+///
+/// No data leaves the machine running the server and no API key is needed.
+///
+/// The dimension of the embeddings can be given with [`Self::ndims`].  If it
+/// isn't, it is discovered from the first embeddings computed, or by calling
+/// [`Self::initialize`], and is fixed from then on.  [`EmbeddingFunction::dest_type`]
+/// returns an error until the dimension is known.
+#[derive(Debug)]
+pub struct OllamaEmbeddingFunction {
+    host: String,
+    model: String,
+    ndims: OnceLock<usize>,
+    client: reqwest::Client,
+}
+
+impl OllamaEmbeddingFunction {
+    /// Create a new OllamaEmbeddingFunction for a model on the default host
+    /// (`http://localhost:11434`)
+    pub fn new<M: Into<String>>(model: M) -> Self {
+        Self {
+            host: DEFAULT_HOST.to_string(),
+            model: model.into(),
+            ndims: OnceLock::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// The url of the Ollama server
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// The dimension of the embeddings the model produces
+    ///
+    /// Embeddings with a different dimension are rejected.
+    pub fn ndims(self, ndims: usize) -> Self {
+        let ndims_lock = OnceLock::new();
+        ndims_lock.set(ndims).unwrap();
+        Self {
+            ndims: ndims_lock,
+            ..self
+        }
+    }
+
+    /// Discover the dimension of the embeddings, if it isn't known yet, by embedding
+    /// a short text
+    pub async fn initialize(&self) -> Result<usize> {
+        if let Some(ndims) = self.ndims.get() {
+            return Ok(*ndims);
+        }
+        self.embed(vec!["dimension probe"]).await?;
+        self.embedded_ndims()
+    }
+
+    /// The dimension recorded by [`Self::embed`]
+    fn embedded_ndims(&self) -> Result<usize> {
+        self.ndims.get().copied().ok_or_else(|| Error::Runtime {
+            message: format!(
+                "Ollama returned no embeddings, the dimension of the embeddings of the model '{}' is unknown",
+                self.model
+            ),
+        })
+    }
+
+    fn fixed_size_list(&self, values: Float32Array, len: usize, ndims: usize) -> Result<ArrayRef> {
+        let fsl = DataType::new_fixed_size_list(DataType::Float32, ndims as i32, false);
+        // We can't use the FixedSizeListBuilder here because it always adds a null bitmap
+        // and we want to explicitly work with non-nullable arrays.
+        let array_data = ArrayData::builder(fsl)
+            .len(len)
+            .add_child_data(values.into_data())
+            .build()?;
+        Ok(Arc::new(FixedSizeListArray::from(array_data)))
+    }
+}
+
+#[async_trait]
+impl EmbeddingFunction for OllamaEmbeddingFunction {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn source_type(&self) -> Result<Cow<DataType>> {
+        Ok(Cow::Owned(DataType::Utf8))
+    }
+
+    fn dest_type(&self) -> Result<Cow<DataType>> {
+        let ndims = self.ndims.get().ok_or_else(|| Error::InvalidInput {
+            message: format!(
+                "the dimension of the embeddings of the Ollama model '{}' is not known yet, set it with ndims() or call initialize() first",
+                self.model
+            ),
+        })?;
+        Ok(Cow::Owned(DataType::new_fixed_size_list(
+            DataType::Float32,
+            *ndims as i32,
+            false,
+        )))
+    }
+
+    fn compute_source_embeddings(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
+        block_on_embeddings(self.compute_source_embeddings_async(source))
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        block_on_embeddings(self.compute_query_embeddings_async(input))
+    }
+
+    async fn compute_source_embeddings_async(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
+        let len = source.len();
+        let (values, ndims) = self.compute_inner(source).await?;
+        self.fixed_size_list(values, len, ndims)
+    }
+
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        let (values, _) = self.compute_inner(input).await?;
+        Ok(Arc::new(values))
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        let config = StoredConfig {
+            host: self.host.clone(),
+            model: self.model.clone(),
+            ndims: self.ndims.get().copied(),
+        };
+        Some(serde_json::to_value(config).unwrap())
+    }
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl OllamaEmbeddingFunction {
+    async fn compute_inner(&self, source: Arc<dyn Array>) -> Result<(Float32Array, usize)> {
+        // Ollama only supports non-nullable string arrays
+        if source.is_nullable() {
+            return Err(crate::Error::InvalidInput {
+                message: "Expected non-nullable data type".to_string(),
+            });
+        }
+        let input: Vec<&str> = match source.data_type() {
+            DataType::Utf8 => source.as_string::<i32>().iter().flatten().collect(),
+            DataType::LargeUtf8 => source.as_string::<i64>().iter().flatten().collect(),
+            _ => {
+                return Err(crate::Error::InvalidInput {
+                    message: "Expected Utf8 data type".to_string(),
+                })
+            }
+        };
+        if input.is_empty() {
+            let ndims = self.initialize().await?;
+            return Ok((Float32Array::from(Vec::<f32>::new()), ndims));
+        }
+        let len = input.len();
+        let embeddings = self.embed(input).await?;
+        if embeddings.len() != len {
+            return Err(Error::Runtime {
+                message: format!(
+                    "Ollama returned {} embeddings for {} inputs",
+                    embeddings.len(),
+                    len
+                ),
+            });
+        }
+        let ndims = self.embedded_ndims()?;
+        Ok((
+            Float32Array::from(embeddings.into_iter().flatten().collect::<Vec<_>>()),
+            ndims,
+        ))
+    }
+
+    /// Embed the input, checking (or, the first time, recording) the dimension
+    async fn embed(&self, input: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embed", self.host.trim_end_matches('/'));
+        let request = EmbedRequest {
+            model: &self.model,
+            input,
+        };
+        let response = self
+            .client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!("Ollama request to {} failed: {}", url, e),
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<ErrorResponse>(&body)
+                .map(|e| e.error)
+                .unwrap_or(body);
+            return Err(Error::Runtime {
+                message: format!("Ollama request failed ({}): {}", status, message),
+            });
+        }
+        let response: EmbedResponse = response.json().await.map_err(|e| Error::Runtime {
+            message: format!("Failed to parse the Ollama response: {}", e),
+        })?;
+
+        for embedding in &response.embeddings {
+            let ndims = *self.ndims.get_or_init(|| embedding.len());
+            if embedding.len() != ndims {
+                return Err(Error::Runtime {
+                    message: format!(
+                        "Ollama returned an embedding with {} dimensions, expected {}",
+                        embedding.len(),
+                        ndims
+                    ),
+                });
+            }
+        }
+        Ok(response.embeddings)
+    }
+}
+
+/// The configuration of an [`OllamaEmbeddingFunction`] stored in table metadata
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    host: String,
+    model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ndims: Option<usize>,
+}
+
+/// Re-creates [`OllamaEmbeddingFunction`]s from table metadata
+#[derive(Debug, Default)]
+pub struct OllamaEmbeddingFunctionFactory {}
+
+impl EmbeddingFunctionFactory for OllamaEmbeddingFunctionFactory {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>> {
+        let config: StoredConfig =
+            serde_json::from_value(config.clone()).map_err(|e| Error::InvalidInput {
+                message: format!("invalid Ollama embedding function config: {}", e),
+            })?;
+        let mut func = OllamaEmbeddingFunction::new(config.model).host(config.host);
+        if let Some(ndims) = config.ndims {
+            func = func.ndims(ndims);
+        }
+        Ok(Arc::new(func))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::embeddings::test_utils::{mock_server, MockResponse};
+
+    fn embed_response(embeddings: Vec<Vec<f32>>) -> MockResponse {
+        let body = serde_json::json!({ "model": "nomic-embed-text", "embeddings": embeddings });
+        (200, vec![], body.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_http_contract() {
+        let (host, requests) = mock_server(vec![embed_response(vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+        ])])
+        .await;
+        let func = OllamaEmbeddingFunction::new("nomic-embed-text").host(host);
+        // The dimension is discovered by the first call
+        assert!(func.dest_type().is_err());
+
+        let source = Arc::new(StringArray::from(vec!["hello", "world"]));
+        let embeddings = func.compute_source_embeddings_async(source).await.unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings.data_type(), func.dest_type().unwrap().as_ref());
+        let values = embeddings.as_fixed_size_list().value(1);
+        assert_eq!(
+            values
+                .as_primitive::<arrow_array::types::Float32Type>()
+                .values(),
+            &[4.0, 5.0, 6.0]
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].0, "/api/embed");
+        assert_eq!(
+            requests[0].1,
+            serde_json::json!({ "model": "nomic-embed-text", "input": ["hello", "world"] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_dimension_check() {
+        let (host, _) = mock_server(vec![
+            embed_response(vec![vec![1.0, 2.0]]),
+            embed_response(vec![vec![1.0, 2.0, 3.0]]),
+        ])
+        .await;
+        let func = OllamaEmbeddingFunction::new("nomic-embed-text").host(host);
+        assert_eq!(func.initialize().await.unwrap(), 2);
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 2, false)
+        );
+
+        // The dimension is fixed once it is known
+        let query = Arc::new(StringArray::from(vec!["hello"]));
+        let err = func
+            .compute_query_embeddings_async(query)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("expected 2"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_no_embeddings() {
+        let (host, _) = mock_server(vec![embed_response(vec![])]).await;
+        let func = OllamaEmbeddingFunction::new("nomic-embed-text").host(host);
+        let err = func.initialize().await.unwrap_err();
+        assert!(matches!(err, Error::Runtime { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_server_error() {
+        let (host, _) = mock_server(vec![(
+            404,
+            vec![],
+            r#"{"error": "model \"missing\" not found"}"#.to_string(),
+        )])
+        .await;
+        let func = OllamaEmbeddingFunction::new("missing").host(host).ndims(3);
+        let source = Arc::new(StringArray::from(vec!["hello"]));
+        let err = func
+            .compute_source_embeddings_async(source)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let func = OllamaEmbeddingFunction::new("nomic-embed-text")
+            .host("http://gpu-box:11434")
+            .ndims(768);
+        let config = func.to_config().unwrap();
+        let func = OllamaEmbeddingFunctionFactory::default()
+            .from_config(&config)
+            .unwrap();
+        assert_eq!(func.to_config().unwrap(), config);
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 768, false)
+        );
+    }
+}
@@ -1,19 +1,33 @@
-use std::{borrow::Cow, fmt::Formatter, str::FromStr, sync::Arc};
+use std::{
+    borrow::Cow,
+    fmt::Formatter,
+    ops::Range,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 use arrow::array::{AsArray, Float32Builder};
 use arrow_array::{Array, ArrayRef, FixedSizeListArray, Float32Array};
 use arrow_data::ArrayData;
 use arrow_schema::DataType;
 use async_openai::{
-    config::OpenAIConfig,
-    types::{CreateEmbeddingRequest, Embedding, EmbeddingInput, EncodingFormat},
-    Client,
+    config::{AzureConfig, Config, OpenAIConfig},
+    error::ApiError,
+    types::{
+        CreateEmbeddingRequest, CreateEmbeddingResponse, Embedding, EmbeddingInput, EncodingFormat,
+    },
 };
-use tokio::{runtime::Handle, task};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::StatusCode;
+use tiktoken_rs::CoreBPE;
 
 use crate::{Error, Result};
 
-use super::EmbeddingFunction;
+use serde::{Deserialize, Serialize};
+
+use super::{block_on_embeddings, EmbeddingFunction, EmbeddingFunctionFactory};
 
 #[derive(Debug)]
 pub enum EmbeddingModel {
@@ -30,6 +44,62 @@ impl EmbeddingModel {
             Self::TextEmbedding3Large => 3072,
         }
     }
+
+    /// Whether the model can shorten its embeddings to a requested dimension
+    fn supports_dimensions(&self) -> bool {
+        !matches!(self, Self::TextEmbeddingAda002)
+    }
+
+    /// The number of tokens the model accepts for one input
+    fn max_tokens(&self) -> usize {
+        8191
+    }
+}
+
+/// What an [`OpenAIEmbeddingFunction`] does with an input that has more tokens
+/// than the model accepts, see [`OpenAIEmbeddingFunction::on_overflow`]
+///
+/// The tokens are counted with the tokenizer of the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Fail with an error that names the row, without sending a request
+    #[default]
+    Error,
+    /// Embed the first tokens of the input, as many as the model accepts
+    Truncate,
+    /// Embed windows of the input and average their embeddings
+    ///
+    /// Each window has `window_tokens` tokens, and starts `overlap_tokens` tokens
+    /// before the end of the window before it.  The average is weighted by the
+    /// number of tokens of each window and normalized to unit length, like the
+    /// embeddings of the models.
+    Split {
+        window_tokens: usize,
+        overlap_tokens: usize,
+    },
+}
+
+/// The tokenizer of the embedding models, which is loaded once
+fn tokenizer() -> Result<&'static CoreBPE> {
+    static TOKENIZER: OnceLock<CoreBPE> = OnceLock::new();
+    if let Some(tokenizer) = TOKENIZER.get() {
+        return Ok(tokenizer);
+    }
+    let tokenizer = tiktoken_rs::cl100k_base().map_err(|e| Error::Runtime {
+        message: format!("could not load the OpenAI tokenizer: {}", e),
+    })?;
+    Ok(TOKENIZER.get_or_init(|| tokenizer))
+}
+
+/// The requests for a batch of inputs, see [`OpenAIEmbeddingFunction::plan_requests`]
+struct PlannedRequests {
+    /// The input of each request and its number of embeddings
+    requests: Vec<(EmbeddingInput, usize)>,
+    /// For each row, the embeddings that make up its embedding
+    rows: Vec<Range<usize>>,
+    /// The number of tokens of each embedding, to weigh the average of a row
+    weights: Vec<usize>,
 }
 
 impl FromStr for EmbeddingModel {
@@ -65,24 +135,66 @@ impl TryFrom<&str> for EmbeddingModel {
     }
 }
 
+/// The API version used for Azure OpenAI deployments unless another one is set
+/// with [`OpenAIEmbeddingFunction::api_version`]
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-02-01";
+
+/// The header of the API key of Azure OpenAI requests
+const AZURE_API_KEY_HEADER: &str = "api-key";
+
+/// A callback that returns a short-lived API key or access token, see
+/// [`OpenAIEmbeddingFunction::token_provider`]
+pub type TokenProvider = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// Where the API key of an [`OpenAIEmbeddingFunction`] comes from
+#[derive(Clone)]
+enum Credentials {
+    /// A key given to the builder
+    Key(String),
+    /// A key read from `OPENAI_API_KEY`, or `AZURE_OPENAI_API_KEY` for Azure, before
+    /// each request
+    Env,
+    /// A key or token returned by a callback before each request
+    Provider(TokenProvider),
+}
+
+/// An embedding function that uses the OpenAI embeddings API
+///
+/// By default requests are sent to `https://api.openai.com/v1`.  Set
+/// [`Self::api_base`] to use a proxy or another compatible service, or
+/// [`Self::azure_deployment`] to use a deployment of Azure OpenAI.
+///
+/// The models are symmetric, queries are embedded the same way as documents.
+///
+/// Rate limited requests and server errors are retried, after the delay the service
+/// asks for or with an exponential backoff, see [`Self::max_retries`].  Once the
+/// retries are used up a rate limited request fails with [`Error::RateLimited`].
 pub struct OpenAIEmbeddingFunction {
     model: EmbeddingModel,
-    api_key: String,
+    credentials: Credentials,
     api_base: Option<String>,
     org_id: Option<String>,
+    azure_deployment: Option<String>,
+    api_version: Option<String>,
+    headers: Vec<(String, String)>,
+    dimensions: Option<usize>,
+    on_overflow: OverflowPolicy,
+    max_retries: usize,
+    client: reqwest::Client,
+    /// Reads the environment variables of [`Credentials::Env`]
+    env_var: fn(&str) -> Option<String>,
 }
 
 impl std::fmt::Debug for OpenAIEmbeddingFunction {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         // let's be safe and not print the full API key
-        let creds_display = if self.api_key.len() > 6 {
-            format!(
-                "{}***{}",
-                &self.api_key[0..2],
-                &self.api_key[self.api_key.len() - 4..]
-            )
-        } else {
-            "[INVALID]".to_string()
+        let creds_display = match &self.credentials {
+            Credentials::Key(api_key) if api_key.len() > 6 => {
+                format!("{}***{}", &api_key[0..2], &api_key[api_key.len() - 4..])
+            }
+            Credentials::Key(_) => "[INVALID]".to_string(),
+            Credentials::Env => "[FROM ENV]".to_string(),
+            Credentials::Provider(_) => "[FROM TOKEN PROVIDER]".to_string(),
         };
 
         f.debug_struct("OpenAI")
@@ -90,6 +202,20 @@ impl std::fmt::Debug for OpenAIEmbeddingFunction {
             .field("api_key", &creds_display)
             .field("api_base", &self.api_base)
             .field("org_id", &self.org_id)
+            .field("azure_deployment", &self.azure_deployment)
+            .field("api_version", &self.api_version)
+            // Headers may carry credentials too
+            .field(
+                "headers",
+                &self
+                    .headers
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .field("dimensions", &self.dimensions)
+            .field("on_overflow", &self.on_overflow)
+            .field("max_retries", &self.max_retries)
             .finish()
     }
 }
@@ -108,34 +234,168 @@ impl OpenAIEmbeddingFunction {
         M::Error: Into<crate::Error>,
     {
         Ok(Self::new_impl(
-            api_key.into(),
+            Credentials::Key(api_key.into()),
+            model.try_into().map_err(|e| e.into())?,
+        ))
+    }
+
+    /// Create a new OpenAIEmbeddingFunction that reads its API key from the environment
+    ///
+    /// The key is read from `OPENAI_API_KEY`, or from `AZURE_OPENAI_API_KEY` for
+    /// Azure deployments, before each request.
+    pub fn from_env() -> Self {
+        Self::new_impl(Credentials::Env, EmbeddingModel::TextEmbeddingAda002)
+    }
+
+    /// Like [`Self::from_env`], with the given model
+    pub fn from_env_with_model<M: TryInto<EmbeddingModel>>(model: M) -> crate::Result<Self>
+    where
+        M::Error: Into<crate::Error>,
+    {
+        Ok(Self::new_impl(
+            Credentials::Env,
             model.try_into().map_err(|e| e.into())?,
         ))
     }
 
     /// concrete implementation to reduce monomorphization
-    fn new_impl(api_key: String, model: EmbeddingModel) -> Self {
+    fn new_impl(credentials: Credentials, model: EmbeddingModel) -> Self {
         Self {
             model,
-            api_key,
+            credentials,
             api_base: None,
             org_id: None,
+            azure_deployment: None,
+            api_version: None,
+            headers: Vec::new(),
+            dimensions: None,
+            on_overflow: OverflowPolicy::default(),
+            max_retries: 3,
+            client: reqwest::Client::new(),
+            env_var: |name| std::env::var(name).ok(),
         }
     }
 
     /// To use a API base url different from default "https://api.openai.com/v1"
+    ///
+    /// For Azure OpenAI this is the endpoint of the resource, such as
+    /// `https://my-resource.openai.azure.com`.
     pub fn api_base<S: Into<String>>(mut self, api_base: S) -> Self {
         self.api_base = Some(api_base.into());
         self
     }
 
     /// To use a different OpenAI organization id other than default
-    pub fn org_id<S: Into<String>>(mut self, org_id: S) -> Self {
-        self.org_id = Some(org_id.into());
+    #[deprecated(since = "0.6.0", note = "Please use `organization` instead")]
+    pub fn org_id<S: Into<String>>(self, org_id: S) -> Self {
+        self.organization(org_id)
+    }
+
+    /// Send requests on behalf of the OpenAI organization with this id, instead of
+    /// the default organization of the API key
+    ///
+    /// Azure OpenAI has no organizations, this is ignored for Azure deployments.
+    pub fn organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.org_id = Some(organization.into());
+        self
+    }
+
+    /// Use the Azure OpenAI deployment with this name
+    ///
+    /// Requests are sent to `{api_base}/openai/deployments/{deployment}/embeddings`
+    /// with the API key in the `api-key` header, so [`Self::api_base`] must be set
+    /// to the endpoint of the resource.  The deployment must serve the model of
+    /// this function.
+    pub fn azure_deployment<S: Into<String>>(mut self, deployment: S) -> Self {
+        self.azure_deployment = Some(deployment.into());
         self
     }
+
+    /// The Azure OpenAI API version to request, [`DEFAULT_AZURE_API_VERSION`] by
+    /// default
+    ///
+    /// Only Azure deployments have API versions, setting it without
+    /// [`Self::azure_deployment`] fails the requests.
+    pub fn api_version<S: Into<String>>(mut self, api_version: S) -> Self {
+        self.api_version = Some(api_version.into());
+        self
+    }
+
+    /// Send an extra header with every request, for example for a proxy in front
+    /// of the service
+    ///
+    /// The headers are not stored with the table.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Call `provider` for the API key before each request, for credentials that
+    /// expire
+    ///
+    /// This replaces the API key of the function.  The provider should cache the
+    /// key or token and only refresh it when it is about to expire.  For Azure
+    /// deployments the token is sent as a bearer token, as for Microsoft Entra ID
+    /// tokens, instead of in the `api-key` header.
+    pub fn token_provider(mut self, provider: TokenProvider) -> Self {
+        self.credentials = Credentials::Provider(provider);
+        self
+    }
+
+    /// Request embeddings with `dimensions` values instead of the model's default
+    ///
+    /// Only the text-embedding-3 models support this, and the dimension must not be
+    /// larger than the model's default.  The dimension is stored with the table, the
+    /// destination column is a vector of exactly this size.
+    pub fn dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// What to do with inputs that have more tokens than the model accepts
+    ///
+    /// By default such an input fails the whole batch with an error that names
+    /// the row.  The policy is stored with the table.
+    pub fn on_overflow(mut self, on_overflow: OverflowPolicy) -> Self {
+        self.on_overflow = on_overflow;
+        self
+    }
+
+    /// How many times a rate limited request, or one that failed with a server
+    /// error, is retried (3 by default)
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The dimension of the embeddings, checking that the model supports it
+    fn ndims(&self) -> Result<usize> {
+        let Some(dimensions) = self.dimensions else {
+            return Ok(self.model.ndims());
+        };
+        if !self.model.supports_dimensions() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the OpenAI model '{}' does not support dimensions",
+                    self.model
+                ),
+            });
+        }
+        if dimensions == 0 || dimensions > self.model.ndims() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the OpenAI model '{}' supports between 1 and {} dimensions, {} were requested",
+                    self.model,
+                    self.model.ndims(),
+                    dimensions
+                ),
+            });
+        }
+        Ok(dimensions)
+    }
 }
 
+#[async_trait]
 impl EmbeddingFunction for OpenAIEmbeddingFunction {
     fn name(&self) -> &str {
         "openai"
@@ -146,7 +406,7 @@ impl EmbeddingFunction for OpenAIEmbeddingFunction {
     }
 
     fn dest_type(&self) -> Result<Cow<DataType>> {
-        let n_dims = self.model.ndims();
+        let n_dims = self.ndims()?;
         Ok(Cow::Owned(DataType::new_fixed_size_list(
             DataType::Float32,
             n_dims as i32,
@@ -155,9 +415,17 @@ impl EmbeddingFunction for OpenAIEmbeddingFunction {
     }
 
     fn compute_source_embeddings(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
+        block_on_embeddings(self.compute_source_embeddings_async(source))
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        block_on_embeddings(self.compute_query_embeddings_async(input))
+    }
+
+    async fn compute_source_embeddings_async(&self, source: ArrayRef) -> crate::Result<ArrayRef> {
         let len = source.len();
-        let n_dims = self.model.ndims();
-        let inner = self.compute_inner(source)?;
+        let n_dims = self.ndims()?;
+        let inner = self.compute_inner(source).await?;
 
         let fsl = DataType::new_fixed_size_list(DataType::Float32, n_dims as i32, false);
 
@@ -171,13 +439,377 @@ impl EmbeddingFunction for OpenAIEmbeddingFunction {
         Ok(Arc::new(FixedSizeListArray::from(array_data)))
     }
 
-    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
-        let arr = self.compute_inner(input)?;
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        let arr = self.compute_inner(input).await?;
         Ok(Arc::new(arr))
     }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        // The embeddings endpoint accepts at most 2048 inputs per request
+        Some(2048)
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        // The API key and the headers may be secrets and are never stored
+        let config = StoredConfig {
+            model: self.model.to_string(),
+            api_base: self.api_base.clone(),
+            org_id: self.org_id.clone(),
+            azure_deployment: self.azure_deployment.clone(),
+            api_version: self.api_version.clone(),
+            dimensions: self.dimensions,
+            on_overflow: (self.on_overflow != OverflowPolicy::default())
+                .then_some(self.on_overflow),
+        };
+        Some(serde_json::to_value(config).unwrap())
+    }
+}
+
+/// The configuration of an [`OpenAIEmbeddingFunction`] stored in table metadata
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_base: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    org_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    azure_deployment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_overflow: Option<OverflowPolicy>,
+}
+
+/// Re-creates [`OpenAIEmbeddingFunction`]s from table metadata
+///
+/// The API key is not stored with the table.  It is taken from the factory or,
+/// if the factory has none, from the `OPENAI_API_KEY` environment variable
+/// (`AZURE_OPENAI_API_KEY` for Azure deployments).
+#[derive(Default)]
+pub struct OpenAIEmbeddingFunctionFactory {
+    api_key: Option<String>,
 }
+
+impl std::fmt::Debug for OpenAIEmbeddingFunctionFactory {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("OpenAIEmbeddingFunctionFactory")
+            .field("api_key", &self.api_key.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl OpenAIEmbeddingFunctionFactory {
+    /// Create a factory that uses the given API key
+    pub fn new<A: Into<String>>(api_key: A) -> Self {
+        Self {
+            api_key: Some(api_key.into()),
+        }
+    }
+}
+
+impl EmbeddingFunctionFactory for OpenAIEmbeddingFunctionFactory {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>> {
+        let config: StoredConfig =
+            serde_json::from_value(config.clone()).map_err(|e| Error::InvalidInput {
+                message: format!("invalid OpenAI embedding function config: {}", e),
+            })?;
+        let mut func = match &self.api_key {
+            Some(api_key) => {
+                OpenAIEmbeddingFunction::new_with_model(api_key.clone(), config.model.as_str())?
+            }
+            None => OpenAIEmbeddingFunction::from_env_with_model(config.model.as_str())?,
+        };
+        func.api_base = config.api_base;
+        func.org_id = config.org_id;
+        func.azure_deployment = config.azure_deployment;
+        func.api_version = config.api_version;
+        func.dimensions = config.dimensions;
+        func.on_overflow = config.on_overflow.unwrap_or_default();
+        // Report a missing key now rather than on the first request
+        func.api_key()?;
+        Ok(Arc::new(func))
+    }
+}
+/// The body of an error response of the API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
 impl OpenAIEmbeddingFunction {
-    fn compute_inner(&self, source: Arc<dyn Array>) -> Result<Float32Array> {
+    /// The API key or token for the next request
+    fn api_key(&self) -> Result<String> {
+        match &self.credentials {
+            Credentials::Key(api_key) => Ok(api_key.clone()),
+            Credentials::Env => {
+                let var = if self.azure_deployment.is_some() {
+                    "AZURE_OPENAI_API_KEY"
+                } else {
+                    "OPENAI_API_KEY"
+                };
+                (self.env_var)(var).ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                        "an OpenAI API key is required, set the {} environment variable",
+                        var
+                    ),
+                })
+            }
+            Credentials::Provider(provider) => provider(),
+        }
+    }
+
+    /// The extra headers of every request
+    fn header_map(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let invalid = || Error::InvalidInput {
+                message: format!("invalid header for OpenAI requests: '{}'", name),
+            };
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(value).map_err(|_| invalid())?,
+            );
+        }
+        Ok(headers)
+    }
+
+    /// Send the request to OpenAI or to the Azure deployment
+    async fn create_embeddings(
+        &self,
+        req: CreateEmbeddingRequest,
+    ) -> Result<CreateEmbeddingResponse> {
+        let api_key = self.api_key()?;
+        let mut headers = self.header_map()?;
+        match &self.azure_deployment {
+            Some(deployment) => {
+                let api_base = self.api_base.clone().ok_or_else(|| Error::InvalidInput {
+                    message: "the endpoint of an Azure OpenAI deployment must be set with api_base"
+                        .to_string(),
+                })?;
+                let mut config = AzureConfig::new()
+                    .with_api_base(api_base)
+                    .with_deployment_id(deployment.clone())
+                    .with_api_version(
+                        self.api_version
+                            .as_deref()
+                            .unwrap_or(DEFAULT_AZURE_API_VERSION),
+                    );
+                let mut request_headers = match &self.credentials {
+                    Credentials::Provider(_) => {
+                        let bearer = HeaderValue::from_str(&format!("Bearer {}", api_key))
+                            .map_err(|_| Error::InvalidInput {
+                                message: "the token provider returned an invalid token".to_string(),
+                            })?;
+                        // The config always has an api-key header, the token replaces it
+                        let mut request_headers = config.headers();
+                        request_headers.remove(AZURE_API_KEY_HEADER);
+                        request_headers.insert(AUTHORIZATION, bearer);
+                        request_headers
+                    }
+                    _ => {
+                        config = config.with_api_key(api_key);
+                        config.headers()
+                    }
+                };
+                request_headers.extend(headers);
+                self.send(&config, request_headers, &req).await
+            }
+            None => {
+                if self.api_version.is_some() {
+                    return Err(Error::InvalidInput {
+                        message: "api_version is only supported for Azure OpenAI deployments, set azure_deployment too".to_string(),
+                    });
+                }
+                let mut config = OpenAIConfig::new().with_api_key(api_key);
+                if let Some(api_base) = &self.api_base {
+                    config = config.with_api_base(api_base.clone());
+                }
+                if let Some(org_id) = &self.org_id {
+                    config = config.with_org_id(org_id.clone());
+                }
+                let mut request_headers = config.headers();
+                request_headers.extend(headers);
+                self.send(&config, request_headers, &req).await
+            }
+        }
+    }
+
+    /// Send an embeddings request with `headers`, retrying when rate limited or on
+    /// server errors
+    async fn send<C: Config>(
+        &self,
+        config: &C,
+        headers: HeaderMap,
+        req: &CreateEmbeddingRequest,
+    ) -> Result<CreateEmbeddingResponse> {
+        let mut backoff = Duration::from_secs(1);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let response = self
+                .client
+                .post(config.url("/embeddings"))
+                .query(&config.query())
+                .headers(headers.clone())
+                .json(req)
+                .send()
+                .await
+                .map_err(|e| Error::Runtime {
+                    message: format!("OpenAI embed request failed: {}", e),
+                })?;
+            let status = response.status();
+            if status.is_success() {
+                return response.json().await.map_err(|e| Error::Runtime {
+                    message: format!("Failed to parse the OpenAI response: {}", e),
+                });
+            }
+
+            // OpenAI sends whole seconds, Azure may send fractions
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64);
+            let body = response.text().await.unwrap_or_default();
+            let error = serde_json::from_str::<ErrorResponse>(&body).ok();
+            // A used up quota is also a 429, but waiting doesn't help
+            let out_of_quota = error
+                .as_ref()
+                .and_then(|e| e.error.code.as_ref())
+                .and_then(|code| code.as_str())
+                == Some("insufficient_quota");
+            let message = error.map(|e| e.error.message).unwrap_or(body);
+
+            let rate_limited = status == StatusCode::TOO_MANY_REQUESTS && !out_of_quota;
+            if (rate_limited || status.is_server_error()) && attempts <= self.max_retries {
+                let delay = retry_after.unwrap_or(backoff);
+                log::debug!(
+                    "OpenAI embed request failed with {} on attempt {}, retrying in {:?}",
+                    status,
+                    attempts,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                backoff *= 2;
+                continue;
+            }
+
+            return Err(if rate_limited {
+                Error::RateLimited {
+                    message: format!("OpenAI embed request was rate limited: {}", message),
+                    retry_after,
+                }
+            } else {
+                Error::Runtime {
+                    message: format!("OpenAI embed request failed ({}): {}", status, message),
+                }
+            });
+        }
+    }
+
+    /// Tokenize `texts` and plan the requests that embed them, following the
+    /// overflow policy
+    ///
+    /// If every text fits in the context window of the model, the texts are sent
+    /// as they are in a single request.  Otherwise the tokens are sent, in requests
+    /// of at most 2048 inputs.
+    fn plan_requests(&self, texts: Vec<String>) -> Result<PlannedRequests> {
+        let max_tokens = self.model.max_tokens();
+        if let OverflowPolicy::Split {
+            window_tokens,
+            overlap_tokens,
+        } = self.on_overflow
+        {
+            if window_tokens == 0 || window_tokens > max_tokens || overlap_tokens >= window_tokens {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the windows of the OpenAI model '{}' must have between 1 and {} tokens and overlap by fewer tokens than that, got {} tokens overlapping by {}",
+                        self.model, max_tokens, window_tokens, overlap_tokens
+                    ),
+                });
+            }
+        }
+
+        let tokenizer = tokenizer()?;
+        let tokens = texts
+            .iter()
+            .map(|text| tokenizer.encode_ordinary(text))
+            .collect::<Vec<_>>();
+        let Some((row, num_tokens)) = tokens
+            .iter()
+            .map(Vec::len)
+            .enumerate()
+            .find(|(_, num_tokens)| *num_tokens > max_tokens)
+        else {
+            return Ok(PlannedRequests {
+                requests: vec![(EmbeddingInput::StringArray(texts), tokens.len())],
+                rows: (0..tokens.len()).map(|i| i..i + 1).collect(),
+                weights: tokens.iter().map(Vec::len).collect(),
+            });
+        };
+        if self.on_overflow == OverflowPolicy::Error {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "row {} has {} tokens but the OpenAI model '{}' accepts at most {}, set on_overflow to truncate or split long inputs",
+                    row, num_tokens, self.model, max_tokens
+                ),
+            });
+        }
+
+        let mut windows = Vec::with_capacity(tokens.len());
+        let mut rows = Vec::with_capacity(tokens.len());
+        for tokens in tokens {
+            let start = windows.len();
+            match self.on_overflow {
+                OverflowPolicy::Split {
+                    window_tokens,
+                    overlap_tokens,
+                } if tokens.len() > max_tokens => {
+                    let mut offset = 0;
+                    loop {
+                        let end = (offset + window_tokens).min(tokens.len());
+                        windows.push(tokens[offset..end].to_vec());
+                        if end == tokens.len() {
+                            break;
+                        }
+                        offset += window_tokens - overlap_tokens;
+                    }
+                }
+                _ => windows.push(tokens.into_iter().take(max_tokens).collect()),
+            }
+            rows.push(start..windows.len());
+        }
+        let weights = windows.iter().map(Vec::len).collect();
+        let requests = windows
+            .chunks(2048)
+            .map(|chunk| {
+                let input = chunk
+                    .iter()
+                    .map(|window| window.iter().map(|token| *token as u32).collect())
+                    .collect::<Vec<_>>();
+                (EmbeddingInput::ArrayOfIntegerArray(input), chunk.len())
+            })
+            .collect();
+        Ok(PlannedRequests {
+            requests,
+            rows,
+            weights,
+        })
+    }
+
+    async fn compute_inner(&self, source: Arc<dyn Array>) -> Result<Float32Array> {
         // OpenAI only supports non-nullable string arrays
         if source.is_nullable() {
             return Err(crate::Error::InvalidInput {
@@ -191,17 +823,9 @@ impl OpenAIEmbeddingFunction {
                 message: "Expected Utf8 data type".to_string(),
             });
         };
+        let n_dims = self.ndims()?;
 
-        let mut creds = OpenAIConfig::new().with_api_key(self.api_key.clone());
-
-        if let Some(api_base) = &self.api_base {
-            creds = creds.with_api_base(api_base.clone());
-        }
-        if let Some(org_id) = &self.org_id {
-            creds = creds.with_org_id(org_id.clone());
-        }
-
-        let input = match source.data_type() {
+        let texts = match source.data_type() {
             DataType::Utf8 => {
                 let array = source
                     .as_string::<i32>()
@@ -211,7 +835,7 @@ impl OpenAIEmbeddingFunction {
                             .to_string()
                     })
                     .collect::<Vec<String>>();
-                EmbeddingInput::StringArray(array)
+                array
             }
             DataType::LargeUtf8 => {
                 let array = source
@@ -222,36 +846,535 @@ impl OpenAIEmbeddingFunction {
                             .to_string()
                     })
                     .collect::<Vec<String>>();
-                EmbeddingInput::StringArray(array)
+                array
             }
             _ => unreachable!("This should not happen. We already checked the data type."),
         };
 
-        let client = Client::with_config(creds);
-        let embed = client.embeddings();
-        let req = CreateEmbeddingRequest {
-            model: self.model.to_string(),
-            input,
-            encoding_format: Some(EncodingFormat::Float),
-            user: None,
-            dimensions: None,
-        };
+        let planned = self.plan_requests(texts)?;
 
-        // TODO: request batching and retry logic
-        task::block_in_place(move || {
-            Handle::current().block_on(async {
-                let mut builder = Float32Builder::new();
+        // TODO: retry logic
+        let mut embeddings = Vec::with_capacity(planned.weights.len());
+        for (input, num_inputs) in planned.requests {
+            let req = CreateEmbeddingRequest {
+                model: self.model.to_string(),
+                input,
+                encoding_format: Some(EncodingFormat::Float),
+                user: None,
+                dimensions: self.dimensions.map(|d| d as u32),
+            };
+            let res = self.create_embeddings(req).await?;
 
-                let res = embed.create(req).await.map_err(|e| crate::Error::Runtime {
-                    message: format!("OpenAI embed request failed: {e}"),
-                })?;
+            if res.data.len() != num_inputs {
+                return Err(crate::Error::Runtime {
+                    message: format!(
+                        "OpenAI returned {} embeddings for {} inputs",
+                        res.data.len(),
+                        num_inputs
+                    ),
+                });
+            }
+            for Embedding { embedding, .. } in res.data {
+                if embedding.len() != n_dims {
+                    return Err(crate::Error::Runtime {
+                        message: format!(
+                            "OpenAI returned a {}-dimensional embedding but {} dimensions were expected",
+                            embedding.len(),
+                            n_dims
+                        ),
+                    });
+                }
+                embeddings.push(embedding);
+            }
+        }
 
-                for Embedding { embedding, .. } in res.data.iter() {
-                    builder.append_slice(embedding);
+        let mut builder = Float32Builder::new();
+        for row in planned.rows {
+            if row.len() == 1 {
+                builder.append_slice(&embeddings[row.start]);
+                continue;
+            }
+            // The windows of a split row, weighted by their number of tokens
+            let mut average = vec![0.0; n_dims];
+            for i in row {
+                let weight = planned.weights[i] as f32;
+                for (sum, value) in average.iter_mut().zip(&embeddings[i]) {
+                    *sum += value * weight;
                 }
+            }
+            let norm = average.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                average.iter_mut().for_each(|v| *v /= norm);
+            }
+            builder.append_slice(&average);
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::StringArray;
+
+    use super::*;
+    use crate::embeddings::test_utils::mock_server;
+
+    fn error_response(code: &str) -> String {
+        serde_json::json!({
+            "error": {
+                "message": "Rate limit reached for requests",
+                "type": "requests",
+                "param": null,
+                "code": code,
+            }
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let rate_limited = || {
+            (
+                429,
+                vec![("retry-after", "0".to_string())],
+                error_response("rate_limit_exceeded"),
+            )
+        };
+        // Retried after the delay the service asks for, until it succeeds
+        let (base, requests) = mock_server(vec![
+            rate_limited(),
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+        ])
+        .await;
+        let func = small_func("sk-test-key").api_base(base);
+        let start = std::time::Instant::now();
+        embed_one(&func).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(requests.lock().unwrap()[1].0, "/embeddings");
+
+        // The retry-after hint is returned once the retries are used up
+        let (base, requests) = mock_server(vec![rate_limited(), rate_limited()]).await;
+        let func = small_func("sk-test-key").api_base(base).max_retries(1);
+        let err = embed_one(&func).await.unwrap_err();
+        match err {
+            Error::RateLimited {
+                message,
+                retry_after,
+            } => {
+                assert!(message.contains("Rate limit reached"), "{}", message);
+                assert_eq!(retry_after, Some(Duration::ZERO));
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
 
-                Ok(builder.finish())
+        // A used up quota is not retried
+        let (base, requests) =
+            mock_server(vec![(429, vec![], error_response("insufficient_quota"))]).await;
+        let func = small_func("sk-test-key").api_base(base);
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(matches!(err, Error::Runtime { .. }), "{:?}", err);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    fn embeddings_response(embeddings: Vec<Vec<f32>>) -> String {
+        let data = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| {
+                serde_json::json!({"object": "embedding", "index": index, "embedding": embedding})
             })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "object": "list",
+            "model": "text-embedding-3-small",
+            "data": data,
+            "usage": {"prompt_tokens": 2, "total_tokens": 2},
         })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_dimensions() {
+        let (base, requests) = mock_server(vec![
+            (
+                200,
+                vec![],
+                embeddings_response(vec![vec![1.0; 4], vec![2.0; 4]]),
+            ),
+            (
+                200,
+                vec![],
+                embeddings_response(vec![vec![1.0; 3], vec![2.0; 3]]),
+            ),
+        ])
+        .await;
+        let func = OpenAIEmbeddingFunction::new_with_model("sk-test-key", "text-embedding-3-small")
+            .unwrap()
+            .api_base(base)
+            .dimensions(4);
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 4, false)
+        );
+
+        let source: ArrayRef = Arc::new(StringArray::from(vec!["hello", "world"]));
+        let embeddings = func
+            .compute_source_embeddings_async(source.clone())
+            .await
+            .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings.data_type(), func.dest_type().unwrap().as_ref());
+        assert_eq!(requests.lock().unwrap()[0].1["dimensions"], 4);
+
+        // The service ignoring the dimension is an error
+        let err = func
+            .compute_source_embeddings_async(source)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("3-dimensional"), "{}", err);
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn small_func(api_key: &str) -> OpenAIEmbeddingFunction {
+        OpenAIEmbeddingFunction::new_with_model(api_key, "text-embedding-3-small")
+            .unwrap()
+            .dimensions(2)
+    }
+
+    async fn embed_one(func: &OpenAIEmbeddingFunction) -> Result<ArrayRef> {
+        func.compute_source_embeddings_async(Arc::new(StringArray::from(vec!["hello"])))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_openai_request() {
+        let (base, requests) =
+            mock_server(vec![(200, vec![], embeddings_response(vec![vec![1.0; 2]]))]).await;
+        let func = small_func("sk-test-key")
+            .api_base(format!("{}/v1", base))
+            .organization("org-123")
+            .header("X-Proxy-Team", "search");
+        embed_one(&func).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let (path, body, headers) = &requests[0];
+        assert_eq!(path, "/v1/embeddings");
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(header(headers, "authorization"), Some("Bearer sk-test-key"));
+        assert_eq!(header(headers, "openai-organization"), Some("org-123"));
+        assert_eq!(header(headers, "x-proxy-team"), Some("search"));
+        assert_eq!(header(headers, "api-key"), None);
+    }
+
+    #[tokio::test]
+    async fn test_azure_request() {
+        let (base, requests) = mock_server(vec![
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+        ])
+        .await;
+        let func = small_func("azure-key")
+            .api_base(base.clone())
+            .azure_deployment("my-embeddings")
+            .organization("org-123")
+            .header("X-Proxy-Team", "search");
+        embed_one(&func).await.unwrap();
+        let func = func.api_version("2024-06-01");
+        embed_one(&func).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let (path, _, headers) = &requests[0];
+        assert_eq!(
+            path,
+            &format!(
+                "/openai/deployments/my-embeddings/embeddings?api-version={}",
+                DEFAULT_AZURE_API_VERSION
+            )
+        );
+        assert_eq!(header(headers, "api-key"), Some("azure-key"));
+        assert_eq!(header(headers, "authorization"), None);
+        assert_eq!(header(headers, "openai-organization"), None);
+        assert_eq!(header(headers, "x-proxy-team"), Some("search"));
+        assert_eq!(
+            requests[1].0,
+            "/openai/deployments/my-embeddings/embeddings?api-version=2024-06-01"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_credentials() {
+        let (base, requests) = mock_server(vec![
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+            (200, vec![], embeddings_response(vec![vec![1.0; 2]])),
+        ])
+        .await;
+
+        // The provider is called for every request
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let provider: TokenProvider = Arc::new(move || {
+            let call = calls_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(format!("token-{}", call))
+        });
+        let func = small_func("sk-unused")
+            .api_base(base.clone())
+            .token_provider(provider.clone());
+        embed_one(&func).await.unwrap();
+        embed_one(&func).await.unwrap();
+
+        // Azure gets the token as a bearer token
+        let func = small_func("sk-unused")
+            .api_base(base.clone())
+            .azure_deployment("my-embeddings")
+            .token_provider(provider);
+        embed_one(&func).await.unwrap();
+
+        let mut func = OpenAIEmbeddingFunction::from_env_with_model("text-embedding-3-small")
+            .unwrap()
+            .dimensions(2)
+            .api_base(base)
+            .azure_deployment("my-embeddings");
+        func.env_var = |name| (name == "AZURE_OPENAI_API_KEY").then(|| "azure-env-key".to_string());
+        embed_one(&func).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            header(&requests[0].2, "authorization"),
+            Some("Bearer token-0")
+        );
+        assert_eq!(
+            header(&requests[1].2, "authorization"),
+            Some("Bearer token-1")
+        );
+        assert_eq!(
+            header(&requests[2].2, "authorization"),
+            Some("Bearer token-2")
+        );
+        assert_eq!(header(&requests[2].2, "api-key"), None);
+        assert_eq!(header(&requests[3].2, "api-key"), Some("azure-env-key"));
+        assert!(!format!("{:?}", func).contains("azure-env-key"));
+
+        // A failing provider fails the request
+        let func = small_func("sk-unused").token_provider(Arc::new(|| {
+            Err(Error::Runtime {
+                message: "token expired".to_string(),
+            })
+        }));
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(err.to_string().contains("token expired"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_azure_config() {
+        let func = small_func("sk-test-key").api_version("2024-02-01");
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        let func = small_func("azure-key").azure_deployment("my-embeddings");
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(err.to_string().contains("api_base"), "{}", err);
+
+        let func = small_func("sk-test-key").header("bad header", "value");
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    /// A text of about `words` tokens
+    fn long_text(words: usize) -> String {
+        "hello ".repeat(words)
+    }
+
+    #[tokio::test]
+    async fn test_overflow_error() {
+        let (base, requests) = mock_server(vec![]).await;
+        let func = small_func("sk-test-key").api_base(base);
+        let source = Arc::new(StringArray::from(vec![
+            "short".to_string(),
+            long_text(10_000),
+        ]));
+        let err = func
+            .compute_source_embeddings_async(source)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("row 1")),
+            "{:?}",
+            err
+        );
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overflow_truncate() {
+        let (base, requests) = mock_server(vec![(
+            200,
+            vec![],
+            embeddings_response(vec![vec![1.0, 0.0], vec![0.0, 1.0]]),
+        )])
+        .await;
+        let func = small_func("sk-test-key")
+            .api_base(base)
+            .on_overflow(OverflowPolicy::Truncate);
+        let long = long_text(10_000);
+        let source = Arc::new(StringArray::from(vec!["short".to_string(), long.clone()]));
+        let embeddings = func.compute_source_embeddings_async(source).await.unwrap();
+        assert_eq!(embeddings.len(), 2);
+
+        let requests = requests.lock().unwrap();
+        let input = requests[0].1["input"].as_array().unwrap();
+        let tokenizer = tokenizer().unwrap();
+        let expected = |tokens: Vec<usize>| {
+            tokens
+                .into_iter()
+                .map(serde_json::Value::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            input[0].as_array().unwrap(),
+            &expected(tokenizer.encode_ordinary("short"))
+        );
+        let mut long_tokens = tokenizer.encode_ordinary(&long);
+        long_tokens.truncate(8191);
+        assert_eq!(input[1].as_array().unwrap(), &expected(long_tokens));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_split() {
+        let long = long_text(10_000);
+        let num_tokens = tokenizer().unwrap().encode_ordinary(&long).len();
+        let (window_tokens, overlap_tokens) = (4000, 100);
+        let step = window_tokens - overlap_tokens;
+        let num_windows = (num_tokens - overlap_tokens).div_ceil(step);
+        assert_eq!(num_windows, 3);
+
+        // The first window points one way, the others another way
+        let (base, requests) = mock_server(vec![(
+            200,
+            vec![],
+            embeddings_response(vec![
+                vec![1.0, 0.0],
+                vec![1.0, 0.0],
+                vec![0.0, 1.0],
+                vec![0.0, 1.0],
+            ]),
+        )])
+        .await;
+        let func = small_func("sk-test-key")
+            .api_base(base)
+            .on_overflow(OverflowPolicy::Split {
+                window_tokens,
+                overlap_tokens,
+            });
+        let source = Arc::new(StringArray::from(vec!["short".to_string(), long]));
+        let embeddings = func.compute_source_embeddings_async(source).await.unwrap();
+        let values = embeddings
+            .as_fixed_size_list()
+            .values()
+            .as_primitive::<arrow_array::types::Float32Type>()
+            .values()
+            .to_vec();
+        let last_window = num_tokens - 2 * step;
+        let (x, y) = (window_tokens as f32, (window_tokens + last_window) as f32);
+        let norm = (x * x + y * y).sqrt();
+        assert_eq!(values[..2], [1.0, 0.0]);
+        assert!((values[2] - x / norm).abs() < 1e-6, "{:?}", values);
+        assert!((values[3] - y / norm).abs() < 1e-6, "{:?}", values);
+
+        let requests = requests.lock().unwrap();
+        let input = requests[0].1["input"].as_array().unwrap();
+        let windows = input[1..]
+            .iter()
+            .map(|window| window.as_array().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(windows.len(), num_windows);
+        assert_eq!(windows[0].len(), window_tokens);
+        assert_eq!(windows[2].len(), last_window);
+        // Each window starts with the end of the window before it
+        assert_eq!(windows[1][..overlap_tokens], windows[0][step..]);
+
+        let func = small_func("sk-test-key").on_overflow(OverflowPolicy::Split {
+            window_tokens: 10_000,
+            overlap_tokens: 0,
+        });
+        let err = embed_one(&func).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_invalid_dimensions() {
+        let func = OpenAIEmbeddingFunction::new("sk-test-key").dimensions(256);
+        assert!(matches!(
+            func.dest_type().unwrap_err(),
+            Error::InvalidInput { .. }
+        ));
+        for dimensions in [0, 1537] {
+            let func =
+                OpenAIEmbeddingFunction::new_with_model("sk-test-key", "text-embedding-3-small")
+                    .unwrap()
+                    .dimensions(dimensions);
+            assert!(matches!(
+                func.dest_type().unwrap_err(),
+                Error::InvalidInput { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let func =
+            OpenAIEmbeddingFunction::new_with_model("sk-secret-key", "text-embedding-3-large")
+                .unwrap()
+                .api_base("http://localhost:8080/v1")
+                .azure_deployment("my-embeddings")
+                .api_version("2024-06-01")
+                .header("x-proxy-token", "proxy-secret");
+        let config = func.to_config().unwrap();
+        assert!(!config.to_string().contains("sk-secret-key"));
+        assert!(!config.to_string().contains("proxy-secret"));
+        assert_eq!(config["azure_deployment"], "my-embeddings");
+
+        let func = OpenAIEmbeddingFunctionFactory::new("sk-other-key")
+            .from_config(&config)
+            .unwrap();
+        assert_eq!(func.to_config().unwrap(), config);
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 3072, false)
+        );
+
+        // The dimension and the overflow policy are stored too
+        let on_overflow = OverflowPolicy::Split {
+            window_tokens: 1000,
+            overlap_tokens: 50,
+        };
+        let func =
+            OpenAIEmbeddingFunction::new_with_model("sk-secret-key", "text-embedding-3-large")
+                .unwrap()
+                .dimensions(256)
+                .on_overflow(on_overflow);
+        let config = func.to_config().unwrap();
+        assert_eq!(
+            config["on_overflow"],
+            serde_json::json!({"policy": "split", "window_tokens": 1000, "overlap_tokens": 50})
+        );
+        let func = OpenAIEmbeddingFunctionFactory::new("sk-other-key")
+            .from_config(&config)
+            .unwrap();
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 256, false)
+        );
+        assert_eq!(func.to_config().unwrap(), config);
     }
 }
@@ -0,0 +1,513 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeddings computed in-process by a sentence-transformers (BERT) model
+//!
+//! The model runs on the CPU with [candle](https://github.com/huggingface/candle), so
+//! nothing is sent over the network.  Models are never downloaded, they are loaded from a
+//! local directory or from the Hugging Face cache.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::array::AsArray;
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, Float32Array};
+use arrow_data::ArrayData;
+use arrow_schema::DataType;
+use candle_core::{Device, IndexOp, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokenizers::{Tokenizer, TruncationParams};
+
+use crate::{Error, Result};
+
+use super::{EmbeddingFunction, EmbeddingFunctionFactory};
+
+/// The model used by default, it produces embeddings with 384 dimensions
+pub const DEFAULT_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+
+const CONFIG_FILE: &str = "config.json";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+const WEIGHTS_FILE: &str = "model.safetensors";
+
+/// How the embeddings of the tokens are combined into the embedding of the text
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// The mean of the embeddings of all tokens
+    #[default]
+    Mean,
+    /// The embedding of the first (`[CLS]`) token
+    Cls,
+}
+
+/// Where the model files were loaded from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ModelSource {
+    Path(PathBuf),
+    HfCache(String),
+}
+
+/// An embedding function that runs a sentence-transformers model in-process
+///
+/// The model directory must contain `config.json`, `tokenizer.json` and
+/// `model.safetensors`, as published on the Hugging Face hub for BERT based models
+/// such as `sentence-transformers/all-MiniLM-L6-v2`.
+///
+/// Rows are embedded in parallel on the rayon thread pool.
+pub struct SentenceTransformersEmbeddings {
+    source: ModelSource,
+    model: BertModel,
+    tokenizer: Tokenizer,
+    ndims: usize,
+    pooling: Pooling,
+    normalize: bool,
+}
+
+impl std::fmt::Debug for SentenceTransformersEmbeddings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SentenceTransformersEmbeddings")
+            .field("source", &self.source)
+            .field("ndims", &self.ndims)
+            .field("pooling", &self.pooling)
+            .field("normalize", &self.normalize)
+            .finish()
+    }
+}
+
+impl SentenceTransformersEmbeddings {
+    /// Load a model from a local directory
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::load(
+            ModelSource::Path(path.to_path_buf()),
+            path.join(CONFIG_FILE),
+            path.join(TOKENIZER_FILE),
+            path.join(WEIGHTS_FILE),
+        )
+    }
+
+    /// Load a model, e.g. [`DEFAULT_MODEL`], from the Hugging Face cache
+    ///
+    /// The cache is found the same way as the Hugging Face libraries do (`HF_HOME`,
+    /// defaulting to `~/.cache/huggingface`).  The model must already have been
+    /// downloaded, e.g. with `huggingface-cli download <model_id>`.
+    pub fn from_hf_cache(model_id: &str) -> Result<Self> {
+        Self::from_cache(&hf_hub::Cache::default(), model_id)
+    }
+
+    fn from_cache(cache: &hf_hub::Cache, model_id: &str) -> Result<Self> {
+        let repo = cache.model(model_id.to_string());
+        let get = |file: &str| {
+            repo.get(file).ok_or_else(|| Error::InvalidInput {
+                message: format!(
+                    "the model '{}' is not in the Hugging Face cache at {} (missing {}), download it first",
+                    model_id,
+                    cache.path().display(),
+                    file
+                ),
+            })
+        };
+        Self::load(
+            ModelSource::HfCache(model_id.to_string()),
+            get(CONFIG_FILE)?,
+            get(TOKENIZER_FILE)?,
+            get(WEIGHTS_FILE)?,
+        )
+    }
+
+    fn load(
+        source: ModelSource,
+        config_path: PathBuf,
+        tokenizer_path: PathBuf,
+        weights_path: PathBuf,
+    ) -> Result<Self> {
+        let config = std::fs::read_to_string(&config_path).map_err(|e| Error::InvalidInput {
+            message: format!("failed to read {}: {}", config_path.display(), e),
+        })?;
+        let invalid_config = |e: serde_json::Error| Error::InvalidInput {
+            message: format!("invalid model config {}: {}", config_path.display(), e),
+        };
+        // The sizes aren't exposed by the candle config
+        let sizes: ModelSizes = serde_json::from_str(&config).map_err(invalid_config)?;
+        let config: Config = serde_json::from_str(&config).map_err(invalid_config)?;
+
+        let mut tokenizer =
+            Tokenizer::from_file(&tokenizer_path).map_err(|e| Error::InvalidInput {
+                message: format!(
+                    "failed to load the tokenizer {}: {}",
+                    tokenizer_path.display(),
+                    e
+                ),
+            })?;
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: sizes.max_position_embeddings,
+                ..Default::default()
+            }))
+            .map_err(|e| Error::InvalidInput {
+                message: format!("failed to configure the tokenizer: {}", e),
+            })?;
+
+        let device = Device::Cpu;
+        let weights = candle_core::safetensors::load(weights_path, &device)?;
+        let model = BertModel::load(VarBuilder::from_tensors(weights, DTYPE, &device), &config)?;
+
+        Ok(Self {
+            source,
+            model,
+            tokenizer,
+            ndims: sizes.hidden_size,
+            pooling: Pooling::default(),
+            normalize: true,
+        })
+    }
+
+    /// How the token embeddings are pooled, defaults to [`Pooling::Mean`]
+    pub fn pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    /// Whether the embeddings are scaled to unit length, defaults to true
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| Error::Runtime {
+                message: format!("failed to tokenize the input: {}", e),
+            })?;
+        if encoding.get_ids().is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!("the text '{}' produced no tokens", text),
+            });
+        }
+        // The candle BERT model has no attention mask so each text is run on its own
+        // rather than padded into a batch, which would change the embeddings.
+        let device = &self.model.device;
+        let token_ids = Tensor::new(encoding.get_ids(), device)?.unsqueeze(0)?;
+        let type_ids = Tensor::new(encoding.get_type_ids(), device)?.unsqueeze(0)?;
+        let hidden = self.model.forward(&token_ids, &type_ids)?;
+        let pooled = match self.pooling {
+            Pooling::Mean => hidden.mean(1)?,
+            Pooling::Cls => hidden.i((.., 0))?,
+        };
+        let pooled = if self.normalize {
+            pooled.broadcast_div(&pooled.sqr()?.sum_keepdim(1)?.sqrt()?)?
+        } else {
+            pooled
+        };
+        Ok(pooled.squeeze(0)?.to_vec1::<f32>()?)
+    }
+
+    fn compute_inner(&self, source: &dyn Array) -> Result<Float32Array> {
+        if source.null_count() > 0 {
+            return Err(Error::InvalidInput {
+                message: "Expected non-nullable data".to_string(),
+            });
+        }
+        let input: Vec<&str> = match source.data_type() {
+            DataType::Utf8 => source.as_string::<i32>().iter().flatten().collect(),
+            DataType::LargeUtf8 => source.as_string::<i64>().iter().flatten().collect(),
+            _ => {
+                return Err(Error::InvalidInput {
+                    message: "Expected Utf8 data type".to_string(),
+                })
+            }
+        };
+        let embeddings = input
+            .par_iter()
+            .map(|text| self.embed_one(text))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Float32Array::from(
+            embeddings.into_iter().flatten().collect::<Vec<_>>(),
+        ))
+    }
+}
+
+impl EmbeddingFunction for SentenceTransformersEmbeddings {
+    fn name(&self) -> &str {
+        "sentence-transformers"
+    }
+
+    fn source_type(&self) -> Result<Cow<DataType>> {
+        Ok(Cow::Owned(DataType::Utf8))
+    }
+
+    fn dest_type(&self) -> Result<Cow<DataType>> {
+        Ok(Cow::Owned(DataType::new_fixed_size_list(
+            DataType::Float32,
+            self.ndims as i32,
+            false,
+        )))
+    }
+
+    fn compute_source_embeddings(&self, source: ArrayRef) -> Result<ArrayRef> {
+        let len = source.len();
+        let values = self.compute_inner(&source)?;
+        // We can't use the FixedSizeListBuilder here because it always adds a null bitmap
+        // and we want to explicitly work with non-nullable arrays.
+        let array_data = ArrayData::builder(self.dest_type()?.into_owned())
+            .len(len)
+            .add_child_data(values.into_data())
+            .build()?;
+        Ok(Arc::new(FixedSizeListArray::from(array_data)))
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        Ok(Arc::new(self.compute_inner(&input)?))
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        let config = StoredConfig {
+            source: self.source.clone(),
+            pooling: self.pooling,
+            normalize: self.normalize,
+        };
+        Some(serde_json::to_value(config).unwrap())
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelSizes {
+    hidden_size: usize,
+    max_position_embeddings: usize,
+}
+
+/// The configuration of a [`SentenceTransformersEmbeddings`] stored in table metadata
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    source: ModelSource,
+    pooling: Pooling,
+    normalize: bool,
+}
+
+/// Re-creates [`SentenceTransformersEmbeddings`] from table metadata
+///
+/// The model is loaded again from the same path or from the Hugging Face cache.
+#[derive(Debug, Default)]
+pub struct SentenceTransformersEmbeddingsFactory {}
+
+impl EmbeddingFunctionFactory for SentenceTransformersEmbeddingsFactory {
+    fn name(&self) -> &str {
+        "sentence-transformers"
+    }
+
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>> {
+        let config: StoredConfig =
+            serde_json::from_value(config.clone()).map_err(|e| Error::InvalidInput {
+                message: format!("invalid sentence-transformers embedding config: {}", e),
+            })?;
+        let func = match config.source {
+            ModelSource::Path(path) => SentenceTransformersEmbeddings::from_path(path)?,
+            ModelSource::HfCache(model_id) => {
+                SentenceTransformersEmbeddings::from_hf_cache(&model_id)?
+            }
+        };
+        Ok(Arc::new(
+            func.pooling(config.pooling).normalize(config.normalize),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arrow_array::{types::Float32Type, StringArray};
+    use candle_core::DType;
+    use candle_nn::VarMap;
+    use tokenizers::{
+        models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace,
+        processors::template::TemplateProcessing,
+    };
+
+    use super::*;
+
+    const WORDS: [&str; 6] = ["hello", "world", "red", "shoe", "blue", "bike"];
+
+    /// Write a tiny randomly initialized BERT model to `dir`
+    fn write_model(dir: &Path) {
+        let config = serde_json::json!({
+            "vocab_size": WORDS.len() + 2,
+            "hidden_size": 8,
+            "num_hidden_layers": 1,
+            "num_attention_heads": 2,
+            "intermediate_size": 16,
+            "hidden_act": "gelu",
+            "hidden_dropout_prob": 0.0,
+            "max_position_embeddings": 16,
+            "type_vocab_size": 2,
+            "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12,
+            "pad_token_id": 0,
+            "classifier_dropout": null,
+            "model_type": "bert",
+        });
+        std::fs::write(dir.join(CONFIG_FILE), config.to_string()).unwrap();
+
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        BertModel::load(vb, &serde_json::from_value(config).unwrap()).unwrap();
+        varmap.save(dir.join(WEIGHTS_FILE)).unwrap();
+
+        let mut vocab: HashMap<String, u32> = HashMap::new();
+        vocab.insert("[UNK]".to_string(), 0);
+        vocab.insert("[CLS]".to_string(), 1);
+        for (i, word) in WORDS.iter().enumerate() {
+            vocab.insert(word.to_string(), i as u32 + 2);
+        }
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Whitespace {});
+        tokenizer.with_post_processor(
+            TemplateProcessing::builder()
+                .try_single("[CLS] $A")
+                .unwrap()
+                .special_tokens(vec![("[CLS]", 1)])
+                .build()
+                .unwrap(),
+        );
+        tokenizer.save(dir.join(TOKENIZER_FILE), false).unwrap();
+    }
+
+    fn embeddings(func: &dyn EmbeddingFunction, input: Vec<&str>) -> Vec<Vec<f32>> {
+        let embeddings = func
+            .compute_source_embeddings(Arc::new(StringArray::from(input)))
+            .unwrap();
+        assert_eq!(embeddings.data_type(), func.dest_type().unwrap().as_ref());
+        embeddings
+            .as_fixed_size_list()
+            .iter()
+            .map(|v| v.unwrap().as_primitive::<Float32Type>().values().to_vec())
+            .collect()
+    }
+
+    fn norm(v: &[f32]) -> f32 {
+        v.iter().map(|x| x * x).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let func = SentenceTransformersEmbeddings::from_path(dir.path()).unwrap();
+        assert_eq!(
+            func.dest_type().unwrap().as_ref(),
+            &DataType::new_fixed_size_list(DataType::Float32, 8, false)
+        );
+
+        let result = embeddings(&func, vec!["hello world", "red shoe", "hello world"]);
+        assert_eq!(result.len(), 3);
+        for embedding in &result {
+            assert!((norm(embedding) - 1.0).abs() < 1e-5);
+        }
+        assert_eq!(result[0], result[2]);
+        assert_ne!(result[0], result[1]);
+
+        let query = func
+            .compute_query_embeddings(Arc::new(StringArray::from(vec!["red shoe"])))
+            .unwrap();
+        assert_eq!(query.as_primitive::<Float32Type>().values(), &result[1][..]);
+    }
+
+    #[test]
+    fn test_pooling_and_normalize() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let mean = SentenceTransformersEmbeddings::from_path(dir.path())
+            .unwrap()
+            .normalize(false);
+        let cls = SentenceTransformersEmbeddings::from_path(dir.path())
+            .unwrap()
+            .pooling(Pooling::Cls)
+            .normalize(false);
+
+        let mean = embeddings(&mean, vec!["blue bike"]);
+        let cls = embeddings(&cls, vec!["blue bike"]);
+        assert_ne!(mean, cls);
+        assert!((norm(&mean[0]) - 1.0).abs() > 1e-3);
+    }
+
+    #[test]
+    fn test_large_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let func = SentenceTransformersEmbeddings::from_path(dir.path()).unwrap();
+
+        let texts = ["hello world", "red shoe", "blue bike", "hello red bike"];
+        let expected = embeddings(&func, texts.to_vec());
+        let input = (0..10_000).map(|i| texts[i % texts.len()]).collect();
+        let result = embeddings(&func, input);
+        assert_eq!(result.len(), 10_000);
+        for (i, embedding) in result.iter().enumerate() {
+            assert_eq!(embedding, &expected[i % texts.len()]);
+        }
+    }
+
+    #[test]
+    fn test_hf_cache() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = hf_hub::Cache::new(cache_dir.path().to_path_buf());
+        let err = SentenceTransformersEmbeddings::from_cache(&cache, DEFAULT_MODEL).unwrap_err();
+        assert!(err.to_string().contains("download it first"), "{}", err);
+
+        // The layout written by the Hugging Face libraries
+        let repo = cache_dir
+            .path()
+            .join("models--sentence-transformers--all-MiniLM-L6-v2");
+        std::fs::create_dir_all(repo.join("refs")).unwrap();
+        std::fs::write(repo.join("refs/main"), "abc123").unwrap();
+        let snapshot = repo.join("snapshots/abc123");
+        std::fs::create_dir_all(&snapshot).unwrap();
+        write_model(&snapshot);
+
+        let func = SentenceTransformersEmbeddings::from_cache(&cache, DEFAULT_MODEL).unwrap();
+        assert_eq!(embeddings(&func, vec!["hello"]).len(), 1);
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path());
+        let func = SentenceTransformersEmbeddings::from_path(dir.path())
+            .unwrap()
+            .pooling(Pooling::Cls);
+        let config = func.to_config().unwrap();
+        let restored = SentenceTransformersEmbeddingsFactory::default()
+            .from_config(&config)
+            .unwrap();
+        assert_eq!(restored.to_config().unwrap(), config);
+        assert_eq!(
+            embeddings(restored.as_ref(), vec!["red shoe"]),
+            embeddings(&func, vec!["red shoe"])
+        );
+    }
+}
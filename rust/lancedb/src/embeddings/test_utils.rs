@@ -0,0 +1,88 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for testing embedding functions that call an HTTP service
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A response status, extra headers and body
+pub type MockResponse = (u16, Vec<(&'static str, String)>, String);
+
+/// The path (with the query string), JSON body and headers of a request
+///
+/// The names of the headers are lowercase.
+pub type MockRequest = (String, serde_json::Value, Vec<(String, String)>);
+
+/// Serve the given responses, in order, and record the requests
+///
+/// Returns the base url of the server
+pub async fn mock_server(responses: Vec<MockResponse>) -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    tokio::spawn(async move {
+        for (status, headers, body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = vec![0; 64 * 1024];
+            // Read the head, then the body as given by the content-length
+            let body_start = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&buf[..body_start]).to_string();
+            let headers = head
+                .lines()
+                .skip(1)
+                .filter_map(|l| l.split_once(':'))
+                .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+                .collect::<Vec<_>>();
+            let content_length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .map(|(_, value)| value.parse::<usize>().unwrap())
+                .unwrap_or(0);
+            while buf.len() < body_start + content_length {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            let path = head
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            let request = serde_json::from_slice(&buf[body_start..]).unwrap();
+            recorded.lock().unwrap().push((path, request, headers));
+
+            let mut response = format!(
+                "HTTP/1.1 {} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n",
+                status,
+                body.len()
+            );
+            for (name, value) in headers {
+                response.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            response.push_str("\r\n");
+            response.push_str(&body);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    (format!("http://{}", addr), requests)
+}
@@ -0,0 +1,461 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rate limiting and concurrency control for embedding functions that call a
+//! remote service
+
+use std::{
+    borrow::Cow,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use arrow::array::AsArray;
+use arrow_array::Array;
+use arrow_schema::DataType;
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+
+use crate::{Error, Result};
+
+use super::{block_on_embeddings, EmbeddingFunction};
+
+/// Counters of a [`ThrottledEmbeddingFunction`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleStats {
+    /// The number of requests sent to the wrapped function, including retries
+    pub requests: u64,
+    /// The number of requests that were retried after being rate limited
+    pub retries: u64,
+    /// The total time requests waited for a concurrency slot, a rate budget or a
+    /// rate limit backoff
+    pub throttle_wait: Duration,
+}
+
+/// A budget that refills continuously at a fixed rate per minute
+///
+/// Callers reserve from the budget up front and wait until the reservation is
+/// covered, so waiting callers are served in the order they arrived.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        let capacity = per_minute.max(1) as f64;
+        Self {
+            capacity,
+            available: capacity,
+            per_second: capacity / 60.0,
+            updated: now,
+        }
+    }
+
+    /// Reserve `amount` from the budget, returning how long to wait before using it
+    ///
+    /// Amounts larger than the capacity are capped so that they can still go through
+    /// once the budget is full.
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+        self.available -= amount.min(self.capacity);
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.per_second)
+        }
+    }
+}
+
+/// Wraps an [`EmbeddingFunction`] that calls a remote service with limits on the
+/// requests it sends
+///
+/// Every call of the wrapped function is one request.  Requests wait for a free
+/// concurrency slot and for the requests-per-minute and tokens-per-minute budgets.
+/// Tokens are estimated as one token per four bytes of text (or one per value for
+/// non-string input), which is close enough for the OpenAI and Cohere tokenizers
+/// to stay under their limits.
+///
+/// A request that fails with [`Error::RateLimited`] is retried after the delay the
+/// service asked for, or an exponential backoff if it didn't, up to
+/// [`Self::max_retries`] times.
+///
+/// The wrapper has the same name and configuration as the wrapped function.
+#[derive(Debug)]
+pub struct ThrottledEmbeddingFunction {
+    inner: Arc<dyn EmbeddingFunction>,
+    concurrency: Option<Semaphore>,
+    requests_per_minute: Option<Mutex<TokenBucket>>,
+    tokens_per_minute: Option<Mutex<TokenBucket>>,
+    max_retries: usize,
+    initial_backoff: Duration,
+    requests: AtomicU64,
+    retries: AtomicU64,
+    throttle_wait_nanos: AtomicU64,
+}
+
+impl ThrottledEmbeddingFunction {
+    /// Wrap `inner`, by default without limits and with 3 retries of rate limited requests
+    pub fn new(inner: Arc<dyn EmbeddingFunction>) -> Self {
+        Self {
+            inner,
+            concurrency: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            requests: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            throttle_wait_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// The maximum number of requests in flight at once
+    pub fn max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.concurrency = Some(Semaphore::new(max_concurrent_requests.max(1)));
+        self
+    }
+
+    /// The maximum number of requests sent per minute
+    pub fn requests_per_minute(mut self, requests_per_minute: u32) -> Self {
+        self.requests_per_minute = Some(Mutex::new(TokenBucket::new(
+            requests_per_minute,
+            Instant::now(),
+        )));
+        self
+    }
+
+    /// The maximum number of (estimated) input tokens sent per minute
+    pub fn tokens_per_minute(mut self, tokens_per_minute: u32) -> Self {
+        self.tokens_per_minute = Some(Mutex::new(TokenBucket::new(
+            tokens_per_minute,
+            Instant::now(),
+        )));
+        self
+    }
+
+    /// The number of times a rate limited request is retried before the error is
+    /// returned, defaults to 3
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The delay before the first retry when the service doesn't say how long to
+    /// wait, it doubles with every retry.  Defaults to 1 second.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// The number of requests sent, the number of retries and the time spent waiting
+    pub fn stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            throttle_wait: Duration::from_nanos(self.throttle_wait_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn estimate_tokens(input: &dyn Array) -> f64 {
+        let bytes: usize = match input.data_type() {
+            DataType::Utf8 => input
+                .as_string::<i32>()
+                .iter()
+                .flatten()
+                .map(str::len)
+                .sum(),
+            DataType::LargeUtf8 => input
+                .as_string::<i64>()
+                .iter()
+                .flatten()
+                .map(str::len)
+                .sum(),
+            _ => return input.len() as f64,
+        };
+        (bytes as f64 / 4.0).ceil().max(1.0)
+    }
+
+    async fn wait(&self, delay: Duration) {
+        if delay > Duration::ZERO {
+            tokio::time::sleep(delay).await;
+            self.record_wait(delay);
+        }
+    }
+
+    fn record_wait(&self, delay: Duration) {
+        self.throttle_wait_nanos
+            .fetch_add(delay.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Reserve the request and token budgets for one request
+    fn reserve(&self, tokens: f64) -> Duration {
+        let now = Instant::now();
+        let requests = self
+            .requests_per_minute
+            .as_ref()
+            .map(|bucket| bucket.lock().unwrap().reserve(1.0, now))
+            .unwrap_or_default();
+        let tokens = self
+            .tokens_per_minute
+            .as_ref()
+            .map(|bucket| bucket.lock().unwrap().reserve(tokens, now))
+            .unwrap_or_default();
+        requests.max(tokens)
+    }
+
+    async fn throttled<F, Fut>(&self, input: &dyn Array, request: F) -> Result<Arc<dyn Array>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Arc<dyn Array>>>,
+    {
+        let tokens = Self::estimate_tokens(input);
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            let permit = match &self.concurrency {
+                Some(semaphore) => {
+                    let start = Instant::now();
+                    let permit = semaphore.acquire().await.map_err(|e| Error::Runtime {
+                        message: format!("embedding request throttle was closed: {}", e),
+                    })?;
+                    self.record_wait(start.elapsed());
+                    Some(permit)
+                }
+                None => None,
+            };
+            self.wait(self.reserve(tokens)).await;
+
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            match request().await {
+                Err(Error::RateLimited { retry_after, .. }) if attempt < self.max_retries => {
+                    attempt += 1;
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                    let delay = retry_after.unwrap_or(backoff);
+                    backoff *= 2;
+                    log::debug!(
+                        "embedding function '{}' was rate limited, retrying in {:?} (retry {} of {})",
+                        self.inner.name(),
+                        delay,
+                        attempt,
+                        self.max_retries
+                    );
+                    // Let other requests use the slot while we back off
+                    drop(permit);
+                    self.wait(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingFunction for ThrottledEmbeddingFunction {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn source_type(&self) -> Result<Cow<DataType>> {
+        self.inner.source_type()
+    }
+
+    fn dest_type(&self) -> Result<Cow<DataType>> {
+        self.inner.dest_type()
+    }
+
+    fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        block_on_embeddings(self.compute_source_embeddings_async(source))
+    }
+
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        block_on_embeddings(self.compute_query_embeddings_async(input))
+    }
+
+    async fn compute_source_embeddings_async(
+        &self,
+        source: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        self.throttled(source.as_ref(), || {
+            self.inner.compute_source_embeddings_async(source.clone())
+        })
+        .await
+    }
+
+    async fn compute_query_embeddings_async(
+        &self,
+        input: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        self.throttled(input.as_ref(), || {
+            self.inner.compute_query_embeddings_async(input.clone())
+        })
+        .await
+    }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        self.inner.max_batch_size()
+    }
+
+    fn to_config(&self) -> Option<serde_json::Value> {
+        self.inner.to_config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use arrow_array::{Float32Array, StringArray};
+
+    use super::*;
+
+    /// Fails with a rate limit error `rate_limited` times, then echoes the input length
+    #[derive(Debug, Default)]
+    struct FlakyEmbed {
+        rate_limited: AtomicUsize,
+        retry_after: Option<Duration>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmbeddingFunction for FlakyEmbed {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+        fn source_type(&self) -> Result<Cow<DataType>> {
+            Ok(Cow::Owned(DataType::Utf8))
+        }
+        fn dest_type(&self) -> Result<Cow<DataType>> {
+            Ok(Cow::Owned(DataType::Float32))
+        }
+        fn compute_source_embeddings(&self, _source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+            unimplemented!()
+        }
+        fn compute_query_embeddings(&self, _input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+            unimplemented!()
+        }
+        async fn compute_source_embeddings_async(
+            &self,
+            source: Arc<dyn Array>,
+        ) -> Result<Arc<dyn Array>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let rate_limited = self.rate_limited.load(Ordering::SeqCst);
+            if rate_limited > 0 {
+                self.rate_limited.store(rate_limited - 1, Ordering::SeqCst);
+                return Err(Error::RateLimited {
+                    message: "slow down".to_string(),
+                    retry_after: self.retry_after,
+                });
+            }
+            Ok(Arc::new(Float32Array::from(vec![1.0; source.len()])))
+        }
+    }
+
+    fn input(values: &[&str]) -> Arc<dyn Array> {
+        Arc::new(StringArray::from(values.to_vec()))
+    }
+
+    #[tokio::test]
+    async fn test_retry_rate_limited() {
+        let inner = Arc::new(FlakyEmbed {
+            rate_limited: AtomicUsize::new(2),
+            retry_after: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let func = ThrottledEmbeddingFunction::new(inner.clone());
+        let embeddings = func
+            .compute_source_embeddings_async(input(&["a", "b"]))
+            .await
+            .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        let stats = func.stats();
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.retries, 2);
+        assert!(stats.throttle_wait >= Duration::from_millis(100));
+
+        // The error is returned once the retries are used up
+        inner.rate_limited.store(5, Ordering::SeqCst);
+        let func = ThrottledEmbeddingFunction::new(inner)
+            .max_retries(1)
+            .initial_backoff(Duration::from_millis(1));
+        let err = func
+            .compute_source_embeddings_async(input(&["a"]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RateLimited { .. }), "{:?}", err);
+        assert_eq!(func.stats().requests, 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_requests() {
+        let inner = Arc::new(FlakyEmbed::default());
+        let func = ThrottledEmbeddingFunction::new(inner.clone()).max_concurrent_requests(2);
+        futures::future::try_join_all(
+            (0..8).map(|_| func.compute_source_embeddings_async(input(&["a"]))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(inner.max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(func.stats().requests, 8);
+        assert!(func.stats().throttle_wait > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_tokens_per_minute() {
+        let func = ThrottledEmbeddingFunction::new(Arc::new(FlakyEmbed::default()))
+            .tokens_per_minute(6000);
+        // 6000 tokens use up the budget, which refills at 100 tokens per second
+        let large = "x".repeat(24_000);
+        func.compute_source_embeddings_async(input(&[large.as_str()]))
+            .await
+            .unwrap();
+        assert_eq!(func.stats().throttle_wait, Duration::ZERO);
+        func.compute_source_embeddings_async(input(&["xxxx".repeat(20).as_str()]))
+            .await
+            .unwrap();
+        assert!(func.stats().throttle_wait >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, start);
+        // The full budget is available up front
+        for _ in 0..60 {
+            assert_eq!(bucket.reserve(1.0, start), Duration::ZERO);
+        }
+        // Then it refills at one per second and reservations queue up
+        assert_eq!(bucket.reserve(1.0, start), Duration::from_secs(1));
+        assert_eq!(bucket.reserve(1.0, start), Duration::from_secs(2));
+        let later = start + Duration::from_secs(2);
+        assert_eq!(bucket.reserve(1.0, later), Duration::from_secs(1));
+        // Requests larger than the budget are capped
+        let much_later = later + Duration::from_secs(600);
+        assert_eq!(bucket.reserve(1000.0, much_later), Duration::ZERO);
+    }
+}
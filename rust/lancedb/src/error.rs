@@ -15,6 +15,7 @@
 use std::sync::PoisonError;
 
 use arrow_schema::ArrowError;
+use datafusion_common::DataFusionError;
 use snafu::Snafu;
 
 #[derive(Debug, Snafu)]
@@ -28,6 +29,16 @@ pub enum Error {
     TableNotFound { name: String },
     #[snafu(display("Embedding function '{name}' was not found. : {reason}"))]
     EmbeddingFunctionNotFound { name: String, reason: String },
+    #[snafu(display("Embedding function '{name}' is already registered"))]
+    EmbeddingFunctionAlreadyExists { name: String },
+    #[snafu(display(
+        "Embedding function '{name}' returned {actual} but {expected} were expected"
+    ))]
+    EmbeddingOutputMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
 
     #[snafu(display("Table '{name}' already exists"))]
     TableAlreadyExists { name: String },
@@ -40,14 +51,44 @@ pub enum Error {
     Schema { message: String },
     #[snafu(display("Runtime error: {message}"))]
     Runtime { message: String },
+    /// A write conflicted with concurrent writes to the table and was not retried
+    /// (any further), see [`crate::table::CommitRetryConfig`]
+    #[snafu(display("Commit conflict: {message}"))]
+    CommitConflict { message: String },
+    #[snafu(display("Rate limited{}: {message}", fmt_retry_after(retry_after)))]
+    RateLimited {
+        message: String,
+        /// How long the service asked us to wait before trying again
+        retry_after: Option<std::time::Duration>,
+    },
+    #[snafu(display("Timeout error after {elapsed:?}: {message}"))]
+    Timeout {
+        message: String,
+        elapsed: std::time::Duration,
+    },
 
     // 3rd party / external errors
     #[snafu(display("object_store error: {source}"))]
     ObjectStore { source: object_store::Error },
     #[snafu(display("lance error: {source}"))]
     Lance { source: lance::Error },
-    #[snafu(display("Http error: {message}"))]
-    Http { message: String },
+    /// A request to a remote LanceDB server failed
+    ///
+    /// If the server responded then `status` is set and `code`, `message` and
+    /// `request_id` are taken from the server's error response.
+    #[snafu(display(
+        "Http error{}: {message}{}",
+        fmt_status(status),
+        fmt_request_id(request_id)
+    ))]
+    Http {
+        status: Option<u16>,
+        code: Option<String>,
+        message: String,
+        request_id: Option<String>,
+    },
+    #[snafu(display("Request failed after {attempts} attempts: {source}"))]
+    Retry { attempts: usize, source: Box<Error> },
     #[snafu(display("Arrow error: {source}"))]
     Arrow { source: ArrowError },
     #[snafu(display("LanceDBError: not supported: {message}"))]
@@ -62,12 +103,53 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+fn fmt_status(status: &Option<u16>) -> String {
+    status
+        .map(|s| format!(" (status {})", s))
+        .unwrap_or_default()
+}
+
+fn fmt_retry_after(retry_after: &Option<std::time::Duration>) -> String {
+    retry_after
+        .map(|d| format!(" (retry after {:?})", d))
+        .unwrap_or_default()
+}
+
+fn fmt_request_id(request_id: &Option<String>) -> String {
+    request_id
+        .as_ref()
+        .map(|id| format!(" (request id: {})", id))
+        .unwrap_or_default()
+}
+
+impl Error {
+    /// An [`Error::Http`] for a request that never received a response
+    #[cfg(feature = "remote")]
+    pub(crate) fn http(message: impl Into<String>) -> Self {
+        Self::Http {
+            status: None,
+            code: None,
+            message: message.into(),
+            request_id: None,
+        }
+    }
+}
+
 impl From<ArrowError> for Error {
     fn from(source: ArrowError) -> Self {
         Self::Arrow { source }
     }
 }
 
+impl From<DataFusionError> for Error {
+    fn from(source: DataFusionError) -> Self {
+        Self::Other {
+            message: source.to_string(),
+            source: Some(Box::new(source)),
+        }
+    }
+}
+
 impl From<lance::Error> for Error {
     fn from(source: lance::Error) -> Self {
         // TODO: Once Lance is changed to preserve ObjectStore, DataFusion, and Arrow errors, we can
@@ -101,17 +183,23 @@ impl<T> From<PoisonError<T>> for Error {
 #[cfg(feature = "remote")]
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
-        Self::Http {
-            message: e.to_string(),
-        }
+        Self::http(e.to_string())
     }
 }
 
 #[cfg(feature = "remote")]
 impl From<url::ParseError> for Error {
     fn from(e: url::ParseError) -> Self {
-        Self::Http {
-            message: e.to_string(),
+        Self::http(e.to_string())
+    }
+}
+
+#[cfg(feature = "sentence-transformers")]
+impl From<candle_core::Error> for Error {
+    fn from(source: candle_core::Error) -> Self {
+        Self::Other {
+            message: "Error running the embedding model.".to_string(),
+            source: Some(Box::new(source)),
         }
     }
 }
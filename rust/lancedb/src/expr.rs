@@ -0,0 +1,453 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Building filters without writing SQL by hand
+//!
+//! Formatting values into a filter string breaks as soon as a value contains a
+//! quote.  The expressions of this module quote column names and escape literals,
+//! so any value can be used safely:
+//!
+//! ```
+//! use lancedb::expr::{col, lit};
+//!
+//! let title = "Ender's Game";
+//! let filter = col("title").eq(lit(title)).and(col("price").gt(lit(10)));
+//! assert_eq!(
+//!     filter.to_string(),
+//!     "((`title` = 'Ender''s Game') AND (`price` > 10))"
+//! );
+//! ```
+//!
+//! An [`Expr`] can be passed anywhere a filter string is accepted, such as
+//! [`crate::query::QueryBase::only_if`].
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use datafusion_common::ScalarValue;
+
+/// A filter expression, rendered as the SQL of a Lance filter
+///
+/// Create expressions with [`col`] and [`lit`] and combine them with the methods
+/// of this type.  Every operation is parenthesized, so the precedence of SQL
+/// operators never changes the meaning of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    sql: String,
+}
+
+/// A reference to the column `name`
+///
+/// The name is quoted, so it can contain spaces, quotes or SQL keywords.  It is
+/// the name of a single top-level column, a `.` in it is not a field access.
+pub fn col(name: &str) -> Expr {
+    Expr {
+        sql: format!("`{}`", name.replace('`', "``")),
+    }
+}
+
+/// A literal value, see [`Literal`] for the types that can be used
+pub fn lit(value: impl Literal) -> Expr {
+    let value = value.into_scalar();
+    Expr {
+        sql: literal_to_sql(&value).expect("literals of every Literal type can be written"),
+    }
+}
+
+/// A value that can be used in a filter, see [`lit`]
+pub trait Literal {
+    fn into_scalar(self) -> ScalarValue;
+}
+
+macro_rules! impl_literal {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl Literal for $ty {
+                fn into_scalar(self) -> ScalarValue {
+                    ScalarValue::$variant(Some(self.into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_literal!(
+    bool => Boolean,
+    i8 => Int8,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+    u8 => UInt8,
+    u16 => UInt16,
+    u32 => UInt32,
+    u64 => UInt64,
+    f32 => Float32,
+    f64 => Float64,
+    &str => Utf8,
+    String => Utf8,
+);
+
+impl Literal for &String {
+    fn into_scalar(self) -> ScalarValue {
+        ScalarValue::Utf8(Some(self.clone()))
+    }
+}
+
+impl Literal for NaiveDate {
+    fn into_scalar(self) -> ScalarValue {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("the epoch is a valid date");
+        ScalarValue::Date32(Some((self - epoch).num_days() as i32))
+    }
+}
+
+/// Timestamps are compared with microsecond precision
+impl Literal for NaiveDateTime {
+    fn into_scalar(self) -> ScalarValue {
+        ScalarValue::TimestampMicrosecond(Some(self.and_utc().timestamp_micros()), None)
+    }
+}
+
+impl Literal for DateTime<Utc> {
+    fn into_scalar(self) -> ScalarValue {
+        self.naive_utc().into_scalar()
+    }
+}
+
+impl Expr {
+    fn binary(self, op: &str, other: Self) -> Self {
+        Self {
+            sql: format!("({} {} {})", self.sql, op, other.sql),
+        }
+    }
+
+    /// `self = other`
+    pub fn eq(self, other: Self) -> Self {
+        self.binary("=", other)
+    }
+
+    /// `self != other`
+    pub fn not_eq(self, other: Self) -> Self {
+        self.binary("!=", other)
+    }
+
+    /// `self < other`
+    pub fn lt(self, other: Self) -> Self {
+        self.binary("<", other)
+    }
+
+    /// `self <= other`
+    pub fn lt_eq(self, other: Self) -> Self {
+        self.binary("<=", other)
+    }
+
+    /// `self > other`
+    pub fn gt(self, other: Self) -> Self {
+        self.binary(">", other)
+    }
+
+    /// `self >= other`
+    pub fn gt_eq(self, other: Self) -> Self {
+        self.binary(">=", other)
+    }
+
+    /// `self AND other`
+    pub fn and(self, other: Self) -> Self {
+        self.binary("AND", other)
+    }
+
+    /// `self OR other`
+    pub fn or(self, other: Self) -> Self {
+        self.binary("OR", other)
+    }
+
+    /// `self IS NULL`
+    pub fn is_null(self) -> Self {
+        Self {
+            sql: format!("({} IS NULL)", self.sql),
+        }
+    }
+
+    /// `self IS NOT NULL`
+    pub fn is_not_null(self) -> Self {
+        Self {
+            sql: format!("({} IS NOT NULL)", self.sql),
+        }
+    }
+
+    /// `self IN (list...)`, or `self NOT IN (list...)` if `negated`
+    ///
+    /// An empty list matches no rows, or every row if `negated`.
+    pub fn in_list(self, list: impl IntoIterator<Item = Self>, negated: bool) -> Self {
+        let list = list.into_iter().map(|expr| expr.sql).collect::<Vec<_>>();
+        if list.is_empty() {
+            // `IN ()` is not valid SQL
+            return Self {
+                sql: (if negated { "true" } else { "false" }).to_string(),
+            };
+        }
+        Self {
+            sql: format!(
+                "({} {}IN ({}))",
+                self.sql,
+                if negated { "NOT " } else { "" },
+                list.join(", ")
+            ),
+        }
+    }
+
+    /// `self LIKE pattern`, `%` matches any characters and `_` a single one
+    pub fn like(self, pattern: Self) -> Self {
+        self.binary("LIKE", pattern)
+    }
+
+    /// `self NOT LIKE pattern`
+    pub fn not_like(self, pattern: Self) -> Self {
+        self.binary("NOT LIKE", pattern)
+    }
+
+    /// `self BETWEEN low AND high`, both bounds are inclusive
+    pub fn between(self, low: Self, high: Self) -> Self {
+        Self {
+            sql: format!("({} BETWEEN {} AND {})", self.sql, low.sql, high.sql),
+        }
+    }
+}
+
+impl std::ops::Not for Expr {
+    type Output = Self;
+
+    /// `NOT self`
+    fn not(self) -> Self {
+        Self {
+            sql: format!("(NOT {})", self.sql),
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.sql)
+    }
+}
+
+impl AsRef<str> for Expr {
+    fn as_ref(&self) -> &str {
+        &self.sql
+    }
+}
+
+impl From<Expr> for String {
+    fn from(expr: Expr) -> Self {
+        expr.sql
+    }
+}
+
+/// Write a literal as the SQL of a Lance filter
+///
+/// Returns None for the types that can't be written.
+pub(crate) fn literal_to_sql(value: &ScalarValue) -> Option<String> {
+    if value.is_null() {
+        return Some("NULL".to_string());
+    }
+    match value {
+        ScalarValue::Boolean(Some(v)) => Some(v.to_string()),
+        ScalarValue::Int8(Some(v)) => Some(v.to_string()),
+        ScalarValue::Int16(Some(v)) => Some(v.to_string()),
+        ScalarValue::Int32(Some(v)) => Some(v.to_string()),
+        ScalarValue::Int64(Some(v)) => Some(v.to_string()),
+        ScalarValue::UInt8(Some(v)) => Some(v.to_string()),
+        ScalarValue::UInt16(Some(v)) => Some(v.to_string()),
+        ScalarValue::UInt32(Some(v)) => Some(v.to_string()),
+        ScalarValue::UInt64(Some(v)) => Some(v.to_string()),
+        // Debug formatting always includes a decimal point or an exponent, so the
+        // literal is parsed as a float
+        ScalarValue::Float32(Some(v)) if v.is_finite() => Some(format!("{:?}", v)),
+        ScalarValue::Float64(Some(v)) if v.is_finite() => Some(format!("{:?}", v)),
+        ScalarValue::Float32(Some(v)) => Some(format!("CAST('{}' AS FLOAT)", v)),
+        ScalarValue::Float64(Some(v)) => Some(format!("CAST('{}' AS DOUBLE)", v)),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => Some(string_to_sql(v)),
+        ScalarValue::Date32(Some(days)) => {
+            let date = NaiveDate::from_ymd_opt(1970, 1, 1)?
+                .checked_add_signed(chrono::Duration::try_days(*days as i64)?)?;
+            Some(format!("date '{}'", date.format("%Y-%m-%d")))
+        }
+        ScalarValue::TimestampSecond(Some(v), _) => timestamp_to_sql(*v, 1_000_000_000),
+        ScalarValue::TimestampMillisecond(Some(v), _) => timestamp_to_sql(*v, 1_000_000),
+        ScalarValue::TimestampMicrosecond(Some(v), _) => timestamp_to_sql(*v, 1_000),
+        ScalarValue::TimestampNanosecond(Some(v), _) => timestamp_to_sql(*v, 1),
+        _ => None,
+    }
+}
+
+/// Quote a string, Lance parses filters with the MySQL dialect, in which a
+/// backslash escapes the next character, so both quotes and backslashes are escaped
+fn string_to_sql(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// Write a timestamp of `value` units of `nanos_per_unit` nanoseconds since the epoch,
+/// in UTC
+fn timestamp_to_sql(value: i64, nanos_per_unit: i64) -> Option<String> {
+    let nanos_per_second = 1_000_000_000 / nanos_per_unit;
+    let seconds = value.div_euclid(nanos_per_second);
+    let nanos = value.rem_euclid(nanos_per_second) * nanos_per_unit;
+    let timestamp = DateTime::from_timestamp(seconds, nanos as u32)?;
+    Some(format!(
+        "timestamp '{}'",
+        timestamp.naive_utc().format("%Y-%m-%d %H:%M:%S%.f")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{RecordBatch, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+    use rand::Rng;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    #[test]
+    fn test_render() {
+        let filter = col("category")
+            .eq(lit("a"))
+            .and(col("price").gt(lit(10)))
+            .or(!col("sold out").is_null());
+        assert_eq!(
+            filter.to_string(),
+            "(((`category` = 'a') AND (`price` > 10)) OR (NOT (`sold out` IS NULL)))"
+        );
+        assert_eq!(
+            col("weird`name").like(lit("it's 100% \\ off")).to_string(),
+            "(`weird``name` LIKE 'it''s 100% \\\\ off')"
+        );
+        assert_eq!(
+            col("id").in_list([lit(1u8), lit(2i64)], true).to_string(),
+            "(`id` NOT IN (1, 2))"
+        );
+        assert_eq!(col("id").in_list([], false).to_string(), "false");
+        assert_eq!(
+            col("x").between(lit(0.5f32), lit(1.0)).to_string(),
+            "(`x` BETWEEN 0.5 AND 1.0)"
+        );
+        assert_eq!(lit(f64::NAN).to_string(), "CAST('NaN' AS DOUBLE)");
+
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(lit(date).to_string(), "date '2024-02-29'");
+        let timestamp = date.and_hms_micro_opt(12, 30, 5, 250).unwrap();
+        assert_eq!(
+            lit(timestamp).to_string(),
+            "timestamp '2024-02-29 12:30:05.000250'"
+        );
+        assert_eq!(
+            literal_to_sql(&ScalarValue::TimestampSecond(Some(-1), None)).unwrap(),
+            "timestamp '1969-12-31 23:59:59'"
+        );
+    }
+
+    /// Strings made of the characters that are most likely to break quoting
+    fn adversarial_string(rng: &mut impl Rng) -> String {
+        const CHARS: &[char] = &[
+            '\'', '"', '`', '\\', '%', '_', ';', '-', '(', ')', ' ', '\n', '\t', 'a', 'Z', '0',
+            'é', '字', '🦀',
+        ];
+        let len = rng.gen_range(0..16);
+        (0..len)
+            .map(|_| CHARS[rng.gen_range(0..CHARS.len())])
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_strings() {
+        let mut rng = rand::thread_rng();
+        let mut values = (0..200)
+            .map(|_| adversarial_string(&mut rng))
+            .collect::<Vec<_>>();
+        values.extend(
+            [
+                "",
+                "'",
+                "''",
+                "\\",
+                "\\'",
+                "' OR '1'='1",
+                "a'); DROP TABLE t; --",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+        values.sort();
+        values.dedup();
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "it's a column",
+            DataType::Utf8,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(values.clone()))],
+        )
+        .unwrap();
+        let table = db
+            .create_table("strings", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        // Each value matches exactly its own row
+        for value in &values {
+            let batches = table
+                .query()
+                .only_if(col("it's a column").eq(lit(value)))
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let found = batches
+                .iter()
+                .flat_map(|batch| {
+                    let column = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    column
+                        .iter()
+                        .map(|v| v.unwrap().to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(found, vec![value.clone()], "{:?}", value);
+        }
+
+        let count = table
+            .count_rows(Some(
+                col("it's a column")
+                    .in_list(values[..10].iter().map(lit), false)
+                    .into(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(count, 10);
+    }
+}
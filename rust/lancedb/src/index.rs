@@ -12,15 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use serde::Deserialize;
 use serde_with::skip_serializing_none;
 
-use crate::{table::TableInternal, Result};
+use crate::{
+    table::{IndexProgressCallback, TableInternal},
+    DistanceType, Error, Result,
+};
 
 use self::{
-    scalar::BTreeIndexBuilder,
+    scalar::{BTreeIndexBuilder, FtsIndexBuilder},
     vector::{IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder, IvfPqIndexBuilder},
 };
 
@@ -33,6 +39,57 @@ pub enum Index {
     IvfPq(IvfPqIndexBuilder),
     IvfHnswPq(IvfHnswPqIndexBuilder),
     IvfHnswSq(IvfHnswSqIndexBuilder),
+    /// A full text search index on a string column
+    FTS(FtsIndexBuilder),
+}
+
+impl Index {
+    /// An index like an existing index, e.g. to build it on another table
+    ///
+    /// Build parameters that tables don't report, such as the sample rate of a
+    /// vector index or the tokenizer of a full text index, have their default values.
+    /// Bitmap indices, which this crate cannot build, are replaced by btree indices
+    /// that serve the same filters.  Label list indices have no replacement.
+    pub(crate) fn from_config(config: &IndexConfig) -> Result<Self> {
+        let params = config.vector_params.clone().unwrap_or_default();
+        let index = match config.index_type {
+            IndexType::IvfPq => {
+                let mut builder = IvfPqIndexBuilder::default();
+                builder.distance_type = params.distance_type.unwrap_or(builder.distance_type);
+                builder.num_partitions = params.num_partitions;
+                builder.num_sub_vectors = params.num_sub_vectors;
+                Self::IvfPq(builder)
+            }
+            IndexType::IvfHnswPq => {
+                let mut builder = IvfHnswPqIndexBuilder::default();
+                builder.distance_type = params.distance_type.unwrap_or(builder.distance_type);
+                builder.num_partitions = params.num_partitions;
+                builder.num_sub_vectors = params.num_sub_vectors;
+                builder.m = params.m.unwrap_or(builder.m);
+                builder.ef_construction = params.ef_construction.unwrap_or(builder.ef_construction);
+                Self::IvfHnswPq(builder)
+            }
+            IndexType::IvfHnswSq => {
+                let mut builder = IvfHnswSqIndexBuilder::default();
+                builder.distance_type = params.distance_type.unwrap_or(builder.distance_type);
+                builder.num_partitions = params.num_partitions;
+                builder.m = params.m.unwrap_or(builder.m);
+                builder.ef_construction = params.ef_construction.unwrap_or(builder.ef_construction);
+                Self::IvfHnswSq(builder)
+            }
+            IndexType::BTree | IndexType::Bitmap => Self::BTree(BTreeIndexBuilder::default()),
+            IndexType::FTS => Self::FTS(FtsIndexBuilder::default()),
+            IndexType::LabelList => {
+                return Err(Error::NotSupported {
+                    message: format!(
+                        "the label list index '{}' cannot be built, label list indices can only be created by the server",
+                        config.name
+                    ),
+                })
+            }
+        };
+        Ok(index)
+    }
 }
 
 /// Builder for the create_index operation
@@ -43,6 +100,8 @@ pub struct IndexBuilder {
     pub(crate) index: Index,
     pub(crate) columns: Vec<String>,
     pub(crate) replace: bool,
+    pub(crate) wait_timeout: Option<Duration>,
+    pub(crate) progress: Option<IndexProgressCallback>,
 }
 
 impl IndexBuilder {
@@ -52,6 +111,8 @@ impl IndexBuilder {
             index,
             columns,
             replace: true,
+            wait_timeout: None,
+            progress: None,
         }
     }
 
@@ -65,6 +126,27 @@ impl IndexBuilder {
         self
     }
 
+    /// Wait up to `timeout` for the index to finish building before returning
+    ///
+    /// Remote tables build indices asynchronously and, by default, `execute` returns
+    /// as soon as the build has been requested.  Setting a wait timeout makes `execute`
+    /// poll until the index covers every row, returning an error if the timeout elapses.
+    ///
+    /// Native tables build indices before `execute` returns and ignore this setting.
+    pub fn wait_timeout(mut self, timeout: Duration) -> Self {
+        self.wait_timeout = Some(timeout);
+        self
+    }
+
+    /// Call `progress` as the index is built
+    ///
+    /// Native tables report progress when the build starts and when it completes.
+    /// Remote tables build indices on the server and don't report progress.
+    pub fn on_progress(mut self, progress: IndexProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
     pub async fn execute(self) -> Result<()> {
         self.parent.clone().create_index(self).await
     }
@@ -76,6 +158,129 @@ pub enum IndexType {
     IvfHnswPq,
     IvfHnswSq,
     BTree,
+    /// A bitmap index, these are created by LanceDB Cloud and can't be built by this crate
+    Bitmap,
+    /// A bitmap index on the items of a list column, these are created by LanceDB Cloud
+    /// and can't be built by this crate
+    LabelList,
+    FTS,
+}
+
+impl IndexType {
+    /// Whether this is a vector (nearest neighbor) index
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Self::IvfPq | Self::IvfHnswPq | Self::IvfHnswSq)
+    }
+}
+
+impl std::fmt::Display for IndexType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            Self::IvfPq => "IVF_PQ",
+            Self::IvfHnswPq => "IVF_HNSW_PQ",
+            Self::IvfHnswSq => "IVF_HNSW_SQ",
+            Self::BTree => "BTREE",
+            Self::Bitmap => "BITMAP",
+            Self::LabelList => "LABEL_LIST",
+            Self::FTS => "FTS",
+        };
+        f.write_str(value)
+    }
+}
+
+impl TryFrom<&str> for IndexType {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "IVF_PQ" => Ok(Self::IvfPq),
+            "IVF_HNSW_PQ" => Ok(Self::IvfHnswPq),
+            "IVF_HNSW_SQ" => Ok(Self::IvfHnswSq),
+            "BTREE" => Ok(Self::BTree),
+            "BITMAP" => Ok(Self::Bitmap),
+            "LABEL_LIST" => Ok(Self::LabelList),
+            "FTS" => Ok(Self::FTS),
+            _ => Err(Error::InvalidInput {
+                message: format!("unknown index type: {}", value),
+            }),
+        }
+    }
+}
+
+/// Waits between the polls of an operation that runs in the background
+///
+/// The poll interval starts at 100ms and doubles up to a maximum of 5s.
+pub(crate) struct Poller {
+    start: Instant,
+    deadline: Instant,
+    interval: Duration,
+}
+
+impl Poller {
+    const MAX_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub(crate) fn new(timeout: Duration) -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            deadline: start + timeout,
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Wait for the next poll, or return [`Error::Timeout`] with `status`, what the
+    /// last poll found, if the timeout has elapsed
+    pub(crate) async fn wait(&mut self, status: String) -> Result<()> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(Error::Timeout {
+                message: status,
+                elapsed: now - self.start,
+            });
+        }
+        tokio::time::sleep(self.interval.min(self.deadline - now)).await;
+        self.interval = (self.interval * 2).min(Self::MAX_INTERVAL);
+        Ok(())
+    }
+}
+
+/// Poll until every named index covers every row in the table
+pub(crate) async fn wait_for_index(
+    table: &dyn TableInternal,
+    index_names: &[&str],
+    timeout: Duration,
+) -> Result<()> {
+    let mut poller = Poller::new(timeout);
+    let mut pending = index_names.to_vec();
+    loop {
+        let mut status = None;
+        let mut still_pending = Vec::with_capacity(pending.len());
+        for index_name in pending {
+            match table.index_stats(index_name).await? {
+                Some(stats) if stats.num_unindexed_rows == 0 => {}
+                stats => {
+                    if status.is_none() {
+                        let description = match stats {
+                            Some(stats) => {
+                                format!("{} rows not yet indexed", stats.num_unindexed_rows)
+                            }
+                            None => "index not yet created".to_string(),
+                        };
+                        status = Some(format!(
+                            "index '{}' was not ready: {}",
+                            index_name, description
+                        ));
+                    }
+                    still_pending.push(index_name);
+                }
+            }
+        }
+        pending = still_pending;
+        let Some(status) = status else {
+            return Ok(());
+        };
+        poller.wait(status).await?;
+    }
 }
 
 /// A description of an index currently configured on a column
@@ -89,6 +294,28 @@ pub struct IndexConfig {
     /// Currently this is always a Vec of size 1.  In the future there may
     /// be more columns to represent composite indices.
     pub columns: Vec<String>,
+    /// The parameters a vector index was built with
+    ///
+    /// This is `None` for scalar and full text indices.
+    pub vector_params: Option<VectorIndexParams>,
+}
+
+/// The parameters used to build a vector index
+///
+/// Any parameter that does not apply to the index type, or that the table cannot
+/// report, is `None`.  Native tables recover them from the statistics of the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorIndexParams {
+    /// The distance type the index was trained with
+    pub distance_type: Option<DistanceType>,
+    /// The number of IVF partitions
+    pub num_partitions: Option<u32>,
+    /// The number of PQ sub vectors
+    pub num_sub_vectors: Option<u32>,
+    /// The number of edges per node in the HNSW graph
+    pub m: Option<u32>,
+    /// The candidate list size used when building the HNSW graph
+    pub ef_construction: Option<u32>,
 }
 
 #[skip_serializing_none]
@@ -104,5 +331,28 @@ pub struct IndexStatistics {
     pub num_indexed_rows: usize,
     pub num_unindexed_rows: usize,
     pub index_type: Option<String>,
+    #[serde(default)]
+    pub distance_type: Option<String>,
+    #[serde(default)]
+    pub num_partitions: Option<u32>,
+    #[serde(default)]
     pub indices: Vec<IndexMetadata>,
 }
+
+/// Statistics about an index, see [`crate::Table::index_stats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStats {
+    /// The number of rows covered by the index
+    pub num_indexed_rows: usize,
+    /// The number of rows added since the index was last built or optimized
+    ///
+    /// Queries still return these rows but have to search them without the
+    /// index.  Use [`crate::table::OptimizeAction::Index`] to add them to the index.
+    pub num_unindexed_rows: usize,
+    /// The type of the index
+    pub index_type: IndexType,
+    /// The distance type of a vector index, `None` for other indices
+    pub distance_type: Option<DistanceType>,
+    /// The number of IVF partitions of a vector index, `None` for other indices
+    pub num_partitions: Option<u32>,
+}
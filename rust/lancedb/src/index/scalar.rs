@@ -28,3 +28,93 @@
 pub struct BTreeIndexBuilder {}
 
 impl BTreeIndexBuilder {}
+
+/// How text is split into words by a full text index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BaseTokenizer {
+    /// Split on whitespace and punctuation
+    #[default]
+    Simple,
+    /// Split on whitespace only
+    Whitespace,
+}
+
+impl std::fmt::Display for BaseTokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Simple => f.write_str("simple"),
+            Self::Whitespace => f.write_str("whitespace"),
+        }
+    }
+}
+
+/// Builder for a full text search (FTS) index
+///
+/// A full text index is an inverted index, it maps each word to the rows that
+/// contain it.  It is used by [`crate::query::Query::full_text_search`] and hybrid
+/// queries.  The text is split into words with the base tokenizer, then the words
+/// are optionally lower cased, stemmed and filtered for stop words.  The same steps
+/// are applied to the query.
+///
+/// The index can only be created on string columns.  The index of a local table
+/// covers the rows the table had when it was built, newer rows are searched
+/// without it until [`crate::table::OptimizeAction::Index`] rebuilds it.  Local
+/// tables can't stem words and only know the stop words of English.
+#[derive(Debug, Clone)]
+pub struct FtsIndexBuilder {
+    pub(crate) base_tokenizer: BaseTokenizer,
+    pub(crate) language: String,
+    pub(crate) stem: bool,
+    pub(crate) lower_case: bool,
+    pub(crate) remove_stop_words: bool,
+}
+
+impl Default for FtsIndexBuilder {
+    fn default() -> Self {
+        Self {
+            base_tokenizer: BaseTokenizer::default(),
+            language: "English".to_string(),
+            stem: false,
+            lower_case: true,
+            remove_stop_words: false,
+        }
+    }
+}
+
+impl FtsIndexBuilder {
+    /// How the text is split into words, the default is [`BaseTokenizer::Simple`]
+    pub fn base_tokenizer(mut self, base_tokenizer: BaseTokenizer) -> Self {
+        self.base_tokenizer = base_tokenizer;
+        self
+    }
+
+    /// The language of the text, used for stemming and stop words
+    ///
+    /// The default is "English".
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Whether to reduce words to their stem, e.g. "running" to "run"
+    ///
+    /// The default is false.
+    pub fn stem(mut self, stem: bool) -> Self {
+        self.stem = stem;
+        self
+    }
+
+    /// Whether to lower case the words, the default is true
+    pub fn lower_case(mut self, lower_case: bool) -> Self {
+        self.lower_case = lower_case;
+        self
+    }
+
+    /// Whether to remove common words of the language, like "the" or "and"
+    ///
+    /// The default is false.
+    pub fn remove_stop_words(mut self, remove_stop_words: bool) -> Self {
+        self.remove_stop_words = remove_stop_words;
+        self
+    }
+}
@@ -196,6 +196,7 @@ pub mod connection;
 pub mod data;
 pub mod embeddings;
 pub mod error;
+pub mod expr;
 pub mod index;
 pub mod io;
 pub mod ipc;
@@ -203,7 +204,9 @@ pub mod ipc;
 mod polars_arrow_convertors;
 pub mod query;
 #[cfg(feature = "remote")]
-pub(crate) mod remote;
+pub mod remote;
+pub mod rerankers;
+pub mod schema;
 pub mod table;
 pub mod utils;
 
@@ -216,12 +219,17 @@ pub use error::{Error, Result};
 use lance_linalg::distance::DistanceType as LanceDistanceType;
 pub use table::Table;
 
+/// The distance metric used to compare vectors
+///
+/// Every distance type is reported so that smaller values are nearer, this is the
+/// order of the `_distance` column returned by vector searches.
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum DistanceType {
     /// Euclidean distance. This is a very common distance metric that
     /// accounts for both magnitude and direction when determining the distance
-    /// between vectors. L2 distance has a range of [0, ∞).
+    /// between vectors. The squared euclidean distance is reported, since it has
+    /// the same order and is cheaper to compute. L2 distance has a range of [0, ∞).
     L2,
     /// Cosine distance.  Cosine distance is a distance metric
     /// calculated from the cosine similarity between two vectors. Cosine
@@ -234,9 +242,10 @@ pub enum DistanceType {
     /// are all zeros (there is no direction).  These vectors are invalid and may
     /// never be returned from a vector search.
     Cosine,
-    /// Dot product. Dot distance is the dot product of two vectors. Dot
-    /// distance has a range of (-∞, ∞). If the vectors are normalized (i.e. their
-    /// L2 norm is 1), then dot distance is equivalent to the cosine distance.
+    /// Dot product. Dot distance is one minus the dot product of two vectors, so
+    /// that larger dot products are nearer. Dot distance has a range of (-∞, ∞).
+    /// If the vectors are normalized (i.e. their L2 norm is 1), then dot distance
+    /// is equivalent to the cosine distance.
     Dot,
     /// Hamming distance. Hamming distance is a distance metric that measures
     /// the number of positions at which the corresponding elements are different.
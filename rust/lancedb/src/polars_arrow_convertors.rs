@@ -9,7 +9,7 @@
 /// requires a feature flagged dependency on arrow-rs. The version of arrow-rs
 /// depended on by polars-arrow and LanceDB may not be compatible,
 /// which necessitates using the C FFI.
-use crate::error::Result;
+use crate::error::{Error, Result};
 use polars::prelude::{DataFrame, Series};
 use std::{mem, sync::Arc};
 
@@ -33,7 +33,9 @@ pub fn convert_polars_df_schema_to_arrow_rb_schema(
             let polars_arrow_dtype = df_dtype.to_arrow(POLARS_ARROW_FLAVOR);
             let polars_field =
                 polars_arrow::datatypes::Field::new(name, polars_arrow_dtype, IS_ARRAY_NULLABLE);
-            convert_polars_arrow_field_to_arrow_rs_field(polars_field)
+            let arrow_rs_field = convert_polars_arrow_field_to_arrow_rs_field(polars_field)?;
+            check_supported_dtype(arrow_rs_field.name(), arrow_rs_field.data_type())?;
+            Ok(arrow_rs_field)
         })
         .collect();
     Ok(Arc::new(arrow_schema::Schema::new(arrow_fields?)))
@@ -47,6 +49,7 @@ pub fn convert_arrow_rb_schema_to_polars_df_schema(
         .fields()
         .iter()
         .map(|arrow_rs_field| {
+            check_supported_dtype(arrow_rs_field.name(), arrow_rs_field.data_type())?;
             let polars_arrow_field = convert_arrow_rs_field_to_polars_arrow_field(arrow_rs_field)?;
             Ok(polars::prelude::Field::new(
                 arrow_rs_field.name(),
@@ -57,6 +60,36 @@ pub fn convert_arrow_rb_schema_to_polars_df_schema(
     Ok(polars::prelude::Schema::from_iter(polars_df_fields?))
 }
 
+/// Checks that a column can be converted between Polars and arrow-rs.
+///
+/// Polars panics on some Arrow types it doesn't support, and categorical columns
+/// become dictionaries, which LanceDB can't store yet.  These are reported as
+/// [`Error::NotSupported`] before any conversion happens.
+fn check_supported_dtype(column: &str, arrow_dtype: &arrow_schema::DataType) -> Result<()> {
+    use arrow_schema::DataType;
+    match arrow_dtype {
+        DataType::Dictionary(_, _) => Err(Error::NotSupported {
+            message: format!(
+                "the column '{}' is categorical, cast it to a string before converting it",
+                column
+            ),
+        }),
+        DataType::Float16 => Err(Error::NotSupported {
+            message: format!(
+                "the column '{}' has type {}, which Polars doesn't support",
+                column, arrow_dtype
+            ),
+        }),
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            check_supported_dtype(column, field.data_type())
+        }
+        DataType::Struct(fields) => fields
+            .iter()
+            .try_for_each(|field| check_supported_dtype(column, field.data_type())),
+        _ => Ok(()),
+    }
+}
+
 /// Converts an Arrow RecordBatch to a Polars DataFrame, using a provided Polars DataFrame schema.
 pub fn convert_arrow_rb_to_polars_df(
     arrow_rb: &arrow::record_batch::RecordBatch,
@@ -15,20 +15,33 @@
 use std::future::Future;
 use std::sync::Arc;
 
-use arrow_array::{make_array, Array, Float16Array, Float32Array, Float64Array};
+use arrow_array::{
+    cast::AsArray, make_array, Array, FixedSizeListArray, Float16Array, Float32Array, Float64Array,
+    ListArray, RecordBatch, StringArray,
+};
 use arrow_schema::DataType;
-use datafusion_physical_plan::ExecutionPlan;
+use datafusion_physical_plan::{
+    display::DisplayableExecutionPlan, displayable, stream::RecordBatchStreamAdapter, ExecutionPlan,
+};
+use futures::TryStreamExt;
 use half::f16;
 use lance::dataset::scanner::DatasetRecordBatchStream;
-use lance_datafusion::exec::execute_plan;
+use lance_datafusion::exec::{execute_plan, OneShotExec};
 
-use crate::arrow::SendableRecordBatchStream;
+use crate::arrow::{RebatchStream, SendableRecordBatchStream, TimeoutStream};
+use crate::embeddings::{resolve_embedding_function, with_prefix, EmbeddingRegistry};
 use crate::error::{Error, Result};
-use crate::table::TableInternal;
+use crate::rerankers::{RRFReranker, Reranker, ROW_ID};
+use crate::table::{ColumnKind, TableInternal};
 use crate::DistanceType;
 
 pub(crate) const DEFAULT_TOP_K: usize = 10;
 
+/// The row address column, see [`QueryBase::with_row_address`]
+pub const ROW_ADDR: &str = "_rowaddr";
+/// The column with the position of the query vector, see [`Query::nearest_to_many`]
+pub const QUERY_INDEX: &str = "query_index";
+
 /// Which columns should be retrieved from the database
 #[derive(Debug, Clone)]
 pub enum Select {
@@ -69,6 +82,72 @@ impl Select {
     }
 }
 
+/// A full text search for the rows that contain some words
+///
+/// Rows that contain any of the words match, unless the words are joined with
+/// `AND`, which binds tighter than `OR`.  Quoted words must appear as a phrase.
+/// For example `pump AND failure OR "stuck valve"`.  The query is split into
+/// words like the column by its full text index, columns without an index are
+/// split on punctuation and compared without case.
+///
+/// Rows are scored with BM25 over the given text columns.  See
+/// [`Query::full_text_search`] and [`VectorQuery::hybrid`].
+#[derive(Debug, Clone)]
+pub struct FullTextSearchQuery {
+    pub(crate) query: String,
+    pub(crate) columns: Vec<String>,
+    pub(crate) use_index: bool,
+}
+
+impl FullTextSearchQuery {
+    /// Search for the words of `query`
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            columns: Vec::new(),
+            use_index: true,
+        }
+    }
+
+    /// If this is called then any full text index is skipped
+    ///
+    /// An exhaustive (flat) search will be performed, every row of the searched
+    /// columns is read and split into words.  Without this, searching a column
+    /// that has no full text index is an error, see
+    /// [`crate::index::Index::FTS`].
+    pub fn bypass_index(mut self) -> Self {
+        self.use_index = false;
+        self
+    }
+
+    /// The columns to search
+    ///
+    /// By default, all string columns are searched.
+    pub fn columns(mut self, columns: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.columns = columns
+            .into_iter()
+            .map(|c| c.as_ref().to_string())
+            .collect();
+        self
+    }
+}
+
+/// A random sample of the rows of a query, see [`Query::sample`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sample {
+    pub(crate) size: SampleSize,
+    /// The seed of the random choice, a random seed is used if this is None
+    pub(crate) seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SampleSize {
+    /// Exactly this many rows, or every row if fewer match
+    Rows(usize),
+    /// Each row is kept with this probability
+    Fraction(f64),
+}
+
 /// A trait for converting a type to a query vector
 ///
 /// This is primarily intended to allow rust users that are unfamiliar with Arrow
@@ -154,19 +233,10 @@ impl IntoQueryVector for &dyn Array {
     fn to_query_vector(
         self,
         data_type: &DataType,
-        _embedding_model_label: &str,
+        embedding_model_label: &str,
     ) -> Result<Arc<dyn Array>> {
-        if data_type != self.data_type() {
-            Err(Error::InvalidInput {
-                message: format!(
-                "failed to create query vector, the input data type was {:?} but the expected data type was {:?}",
-                self.data_type(),
-                data_type
-            )})
-        } else {
-            let data = self.to_data();
-            Ok(make_array(data))
-        }
+        // Casts like the owned array, so f16 vectors can be used as f32 query vectors
+        make_array(self.to_data()).to_query_vector(data_type, embedding_model_label)
     }
 }
 
@@ -337,6 +407,17 @@ pub trait QueryBase {
     /// it will default to 10.
     fn limit(self, limit: usize) -> Self;
 
+    /// Skip the first `offset` rows of the results
+    ///
+    /// This can be combined with [`Self::limit`] to page through the results.  For
+    /// vector searches the rows are skipped after sorting by distance, so the
+    /// nearest `offset` rows are skipped.  An offset beyond the end of the results
+    /// returns no rows.
+    ///
+    /// Without an explicit order, plain queries only page consistently while the
+    /// table is unchanged.
+    fn offset(self, offset: usize) -> Self;
+
     /// Only return rows which match the filter.
     ///
     /// The filter should be supplied as an SQL query string.  For example:
@@ -347,6 +428,10 @@ pub trait QueryBase {
     /// x > 5 OR y = 'test'
     /// ```
     ///
+    /// To filter on values that come from elsewhere, build the filter with
+    /// [`crate::expr`] instead of formatting the values into the string, which
+    /// quotes and escapes them.
+    ///
     /// Filtering performance can often be improved by creating a scalar index
     /// on the filter column(s).
     fn only_if(self, filter: impl AsRef<str>) -> Self;
@@ -371,9 +456,43 @@ pub trait QueryBase {
     /// For example, an SQL query might state `SELECT a + b AS combined, c`.  The equivalent
     /// input to [`Select::dynamic`] would be `&[("combined", "a + b"), ("c", "c")]`.
     ///
+    /// The expressions can use SQL functions (e.g. `upper(title)`) and, in a vector search,
+    /// the `_distance` column.  An invalid expression makes the query fail with an
+    /// [`Error::InvalidInput`] naming the expression when it is executed.  Remote tables
+    /// do not support dynamic columns yet.
+    ///
     /// Columns will always be returned in the order given, even if that order is different than
     /// the order used when adding the data.
     fn select(self, selection: Select) -> Self;
+
+    /// Return the internal row id of each row in a `_rowid` column
+    ///
+    /// Row ids can change when the table is compacted, they are meant to relate
+    /// the results of queries on the same version of the table.
+    ///
+    /// The row id isn't a stored column, selecting a column named `_rowid` with
+    /// [`Self::select`] is the same as calling this method, unless the table has
+    /// a column with that name.
+    fn with_row_id(self) -> Self;
+
+    /// Return the address of each row in a `_rowaddr` column
+    ///
+    /// The address locates the row in the files of the table (the fragment and
+    /// the offset in the fragment).  Like row ids, addresses change when the table
+    /// is compacted.  Selecting a column named `_rowaddr` is the same as calling
+    /// this method.
+    fn with_row_address(self) -> Self;
+
+    /// Only scan the fragments with these ids
+    ///
+    /// This splits the work of scanning a table, e.g. between workers that each
+    /// scan some of the fragments listed by [`crate::table::NativeTable::fragments`].
+    /// The fragments must exist in the version of the table that is queried.
+    ///
+    /// A vector search only supports this if it doesn't use the vector index, see
+    /// [`VectorQuery::bypass_vector_index`], full text searches and samples don't
+    /// support it.  Remote tables do not support it.
+    fn with_fragments(self, fragment_ids: &[u64]) -> Self;
 }
 
 pub trait HasQuery {
@@ -386,6 +505,11 @@ impl<T: HasQuery> QueryBase for T {
         self
     }
 
+    fn offset(mut self, offset: usize) -> Self {
+        self.mut_query().offset = Some(offset);
+        self
+    }
+
     fn only_if(mut self, filter: impl AsRef<str>) -> Self {
         self.mut_query().filter = Some(filter.as_ref().to_string());
         self
@@ -395,9 +519,25 @@ impl<T: HasQuery> QueryBase for T {
         self.mut_query().select = select;
         self
     }
+
+    fn with_row_id(mut self) -> Self {
+        self.mut_query().with_row_id = true;
+        self
+    }
+
+    fn with_row_address(mut self) -> Self {
+        self.mut_query().with_row_address = true;
+        self
+    }
+
+    fn with_fragments(mut self, fragment_ids: &[u64]) -> Self {
+        self.mut_query().fragments = Some(fragment_ids.to_vec());
+        self
+    }
 }
 
 /// Options for controlling the execution of a query
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct QueryExecutionOptions {
     /// The maximum number of rows that will be contained in a single
@@ -405,20 +545,47 @@ pub struct QueryExecutionOptions {
     ///
     /// Note: This is a maximum only.  The query may return smaller
     /// batches, even in the middle of a query, to avoid forcing
-    /// memory copies due to concatenation.
+    /// memory copies due to concatenation.  Use [`Self::min_batch_length`]
+    /// to merge small batches.
     ///
     /// Note: Slicing an Arrow RecordBatch is a zero-copy operation
     /// and so the performance penalty of reading smaller batches
     /// is typically very small.
     ///
+    /// This applies to every kind of query, larger batches are split before they
+    /// are returned.  It must be greater than zero.
+    ///
     /// By default, this is 1024
     pub max_batch_length: u32,
+    /// The minimum number of rows in a single `RecordBatch` delivered by the query
+    ///
+    /// Batches with fewer rows are merged with the batches that follow them, which
+    /// copies their data.  Only the last batch of the results can be smaller.  This
+    /// must not be greater than [`Self::max_batch_length`].
+    ///
+    /// By default, this is 0 and batches are not merged
+    pub min_batch_length: u32,
+    /// The maximum time to wait for the query to complete
+    ///
+    /// The time starts when the query is executed and includes reading all of the
+    /// results.  If the query isn't finished by then, the stream of results returns
+    /// an [`Error::Timeout`] and stops the query.
+    ///
+    /// For remote tables this also overrides the request timeout configured on the
+    /// connection, which is useful for expensive queries that are expected to take
+    /// longer than other requests.
+    ///
+    /// By default, there is no timeout (besides the connection's timeout for remote
+    /// tables)
+    pub timeout: Option<std::time::Duration>,
 }
 
 impl Default for QueryExecutionOptions {
     fn default() -> Self {
         Self {
             max_batch_length: 1024,
+            min_batch_length: 0,
+            timeout: None,
         }
     }
 }
@@ -437,6 +604,36 @@ pub trait ExecutableQuery {
         options: QueryExecutionOptions,
     ) -> impl Future<Output = Result<Arc<dyn ExecutionPlan>>> + Send;
 
+    /// Describe the [ExecutionPlan] of the query, one operator per line
+    ///
+    /// The plan shows how the query will run, for example whether a vector index
+    /// is used and which filters are applied while scanning.  If `verbose` is true
+    /// then more details about each operator are included.
+    ///
+    /// Remote tables ask the server for the plan, if the server can't explain
+    /// queries then [`Error::NotSupported`] is returned.
+    fn explain_plan(&self, verbose: bool) -> impl Future<Output = Result<String>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let plan = self.create_plan(QueryExecutionOptions::default()).await?;
+            Ok(displayable(plan.as_ref()).indent(verbose).to_string())
+        }
+    }
+
+    /// Run the query and describe its [ExecutionPlan] with the metrics of the run
+    ///
+    /// This is [`Self::explain_plan`] with the metrics each operator recorded while
+    /// the query ran, such as the number of rows it output and the time it took.
+    /// The results of the query are discarded.
+    fn explain_analyze(&self) -> impl Future<Output = Result<String>> + Send
+    where
+        Self: Sync,
+    {
+        async move { analyze_plan(self.create_plan(QueryExecutionOptions::default()).await?).await }
+    }
+
     /// Execute the query with default options and return results
     ///
     /// See [`ExecutableQuery::execute_with_options`] for more details.
@@ -459,6 +656,9 @@ pub trait ExecutableQuery {
     /// stream is consumed slowly (this constrains the maximum memory used by a
     /// single query.
     ///
+    /// Dropping the stream stops the query, any work still running in the background
+    /// is cancelled.
+    ///
     /// For simpler access or row-based access we recommend creating extension traits
     /// to convert Arrow data into your internal data model.
     fn execute_with_options(
@@ -467,6 +667,60 @@ pub trait ExecutableQuery {
     ) -> impl Future<Output = Result<SendableRecordBatchStream>> + Send;
 }
 
+/// Start a query and apply the timeout and the batch lengths of `options` to its
+/// results
+async fn execute_with_limits(
+    options: QueryExecutionOptions,
+    execute: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<SendableRecordBatchStream> {
+    if options.max_batch_length == 0 {
+        return Err(Error::InvalidInput {
+            message: "max_batch_length must be greater than zero".to_string(),
+        });
+    }
+    if options.min_batch_length > options.max_batch_length {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "min_batch_length ({}) must not be greater than max_batch_length ({})",
+                options.min_batch_length, options.max_batch_length
+            ),
+        });
+    }
+    let stream = execute_with_timeout(options.timeout, execute).await?;
+    Ok(Box::pin(RebatchStream::new(
+        stream,
+        options.min_batch_length as usize,
+        options.max_batch_length as usize,
+    )))
+}
+
+/// Start a query, its results must be read before `timeout` elapses
+async fn execute_with_timeout(
+    timeout: Option<std::time::Duration>,
+    execute: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<SendableRecordBatchStream> {
+    let Some(timeout) = timeout else {
+        return execute.await;
+    };
+    let deadline = tokio::time::Instant::now() + timeout;
+    let stream = tokio::time::timeout_at(deadline, execute)
+        .await
+        .map_err(|_| Error::Timeout {
+            message: "the query did not finish in time".to_string(),
+            elapsed: timeout,
+        })??;
+    Ok(Box::pin(TimeoutStream::new(stream, deadline, timeout)))
+}
+
+/// Run a plan to completion and describe it with the metrics of the run
+pub(crate) async fn analyze_plan(plan: Arc<dyn ExecutionPlan>) -> Result<String> {
+    let mut stream = execute_plan(plan.clone(), Default::default())?;
+    while stream.try_next().await?.is_some() {}
+    Ok(DisplayableExecutionPlan::with_metrics(plan.as_ref())
+        .indent(true)
+        .to_string())
+}
+
 /// A builder for LanceDB queries.
 ///
 /// See [`crate::Table::query`] for more details on queries
@@ -482,27 +736,92 @@ pub trait ExecutableQuery {
 #[derive(Debug, Clone)]
 pub struct Query {
     parent: Arc<dyn TableInternal>,
+    /// Used to embed text queries, see [`Query::nearest_to_text`]
+    embedding_registry: Arc<dyn EmbeddingRegistry>,
 
     /// limit the number of rows to return.
     pub(crate) limit: Option<usize>,
+    /// skip this many rows before returning any.
+    pub(crate) offset: Option<usize>,
     /// Apply filter to the returned rows.
     pub(crate) filter: Option<String>,
     /// Select column projection.
     pub(crate) select: Select,
+    /// Search for text instead of returning every row
+    pub(crate) full_text_search: Option<FullTextSearchQuery>,
+    /// Return the row ids
+    pub(crate) with_row_id: bool,
+    /// Return the row addresses
+    pub(crate) with_row_address: bool,
+    /// The columns to group by, see [`Query::aggregate`]
+    pub(crate) group_by: Vec<String>,
+    /// Return a random sample of the rows, see [`Query::sample`]
+    pub(crate) sample: Option<Sample>,
+    /// Only scan these fragments, see [`QueryBase::with_fragments`]
+    pub(crate) fragments: Option<Vec<u64>>,
 }
 
 impl Query {
-    pub(crate) fn new(parent: Arc<dyn TableInternal>) -> Self {
+    pub(crate) fn new(
+        parent: Arc<dyn TableInternal>,
+        embedding_registry: Arc<dyn EmbeddingRegistry>,
+    ) -> Self {
         Self {
             parent,
+            embedding_registry,
             limit: None,
+            offset: None,
             filter: None,
             select: Select::All,
+            full_text_search: None,
+            with_row_id: false,
+            with_row_address: false,
+            group_by: Vec::new(),
+            sample: None,
+            fragments: None,
+        }
+    }
+
+    /// Remove the row id and address from the selected columns
+    ///
+    /// They are not stored columns, selecting them by name asks for them the same
+    /// way as [`QueryBase::with_row_id`] and [`QueryBase::with_row_address`] do.
+    /// A table may have a column with one of these names, then that column is
+    /// selected instead.
+    pub(crate) async fn resolve_row_id_selection(mut self) -> Result<Self> {
+        let Select::Columns(columns) = &self.select else {
+            return Ok(self);
+        };
+        if !columns
+            .iter()
+            .any(|column| column == ROW_ID || column == ROW_ADDR)
+        {
+            return Ok(self);
+        }
+        let schema = self.parent.schema().await?;
+        if let Select::Columns(columns) = &mut self.select {
+            columns.retain(|column| {
+                if schema.field_with_name(column).is_ok() {
+                    return true;
+                }
+                match column.as_str() {
+                    ROW_ID => {
+                        self.with_row_id = true;
+                        false
+                    }
+                    ROW_ADDR => {
+                        self.with_row_address = true;
+                        false
+                    }
+                    _ => true,
+                }
+            });
         }
+        Ok(self)
     }
 
-    /// Helper method to convert the query to a VectorQuery with a `query_vector`
-    /// of None.  This retrofits to some existing inner paths that work with a
+    /// Helper method to convert the query to a VectorQuery without any query
+    /// vectors.  This retrofits to some existing inner paths that work with a
     /// single query object for both vector and plain queries.
     pub(crate) fn into_vector(self) -> VectorQuery {
         VectorQuery::new(self)
@@ -521,8 +840,9 @@ impl Query {
     ///
     /// If there is only one vector column (a column whose data type is a
     /// fixed size list of floats) then the column does not need to be specified.
-    /// If there is more than one vector column you must use [`Query::column`]
-    /// to specify which column you would like to compare with.
+    /// Multivector columns (lists of vectors) are only picked when the table has
+    /// no such column.  If there is more than one candidate column you must use
+    /// [`Query::column`] to specify which column you would like to compare with.
     ///
     /// If no index has been created on the vector column then a vector query
     /// will perform a distance comparison between the query vector and every
@@ -545,9 +865,307 @@ impl Query {
     pub fn nearest_to(self, vector: impl IntoQueryVector) -> Result<VectorQuery> {
         let mut vector_query = self.into_vector();
         let query_vector = vector.to_query_vector(&DataType::Float32, "default")?;
-        vector_query.query_vector = Some(query_vector);
+        vector_query.query_vector = vec![query_vector];
+        Ok(vector_query)
+    }
+
+    /// Find the nearest vectors to each of the given query vectors
+    ///
+    /// This is a batch of vector searches which run in parallel, see
+    /// [`Query::nearest_to`] for the details of a single search.  The searches
+    /// share the settings of the returned builder: the limit applies to each query
+    /// vector and the filter to all of them.
+    ///
+    /// The results have a `query_index` column with the position of the query
+    /// vector that each row was found for.  The rows of different query vectors
+    /// may be interleaved, and a row appears once for each query vector it is
+    /// near to.
+    ///
+    /// An error is returned if there are no vectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `vectors` - The vectors that will be used for search.
+    pub fn nearest_to_many(
+        self,
+        vectors: impl IntoIterator<Item = impl IntoQueryVector>,
+    ) -> Result<VectorQuery> {
+        let mut vector_query = self.into_vector();
+        vector_query.query_vector = Self::query_vectors(vectors, "nearest_to_many")?;
+        Ok(vector_query)
+    }
+
+    /// Find the rows of a multivector column that best match all of the given
+    /// query vectors
+    ///
+    /// A multivector column stores several vectors per row, as a list of
+    /// fixed-size-lists of floats, e.g. the token embeddings of late interaction
+    /// models like ColBERT.  Each query vector is matched with its nearest vector of
+    /// the row and the row's `_distance` is the sum of those distances.  With the
+    /// default cosine distance this ranks rows by their MaxSim score, which is the
+    /// number of query vectors minus the distance.
+    ///
+    /// Multivector columns can't be indexed yet, so every row that passes the
+    /// filter is scored.  A search of a multivector column with
+    /// [`Self::nearest_to`] is the same as a multivector search with one query
+    /// vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `vectors` - The query vectors, which must all have the dimension of the
+    ///   vectors of the column.
+    pub fn nearest_to_multivector(
+        self,
+        vectors: impl IntoIterator<Item = impl IntoQueryVector>,
+    ) -> Result<VectorQuery> {
+        let mut vector_query = self.into_vector();
+        vector_query.query_vector = Self::query_vectors(vectors, "nearest_to_multivector")?;
+        vector_query.multivector = true;
         Ok(vector_query)
     }
+
+    /// Convert query vectors, which must all have the same dimension
+    fn query_vectors(
+        vectors: impl IntoIterator<Item = impl IntoQueryVector>,
+        method: &str,
+    ) -> Result<Vec<Arc<dyn Array>>> {
+        let query_vectors = vectors
+            .into_iter()
+            .map(|vector| vector.to_query_vector(&DataType::Float32, "default"))
+            .collect::<Result<Vec<_>>>()?;
+        let Some(first) = query_vectors.first() else {
+            return Err(Error::InvalidInput {
+                message: format!("{} needs at least one query vector", method),
+            });
+        };
+        if let Some((index, other)) = query_vectors
+            .iter()
+            .enumerate()
+            .find(|(_, v)| v.len() != first.len())
+        {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the query vectors must have the same dimension, the vector at index {} has dimension {} but the first has {}",
+                    index,
+                    other.len(),
+                    first.len()
+                ),
+            });
+        }
+        Ok(query_vectors)
+    }
+
+    /// Find the nearest vectors to the embedding of the given text
+    ///
+    /// The text is embedded with the embedding function of the vector column
+    /// when the query is executed.  If the table has a single embedding column
+    /// then it is used, otherwise use [`VectorQuery::column`] to choose one.
+    ///
+    /// An error is returned when the query runs if the column has no embedding
+    /// function or the function isn't available in the connection's registry.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text that will be embedded and used for search.
+    pub fn nearest_to_text(self, text: impl Into<String>) -> VectorQuery {
+        let mut vector_query = self.into_vector();
+        vector_query.query_text = Some(text.into());
+        vector_query
+    }
+
+    /// Only return the rows that match a full text search, best matches first
+    ///
+    /// The score of each row is returned in a `_score` column.  Without a limit,
+    /// every matching row is returned.
+    ///
+    /// The searched columns need a full text index, see [`crate::index::Index::FTS`],
+    /// unless [`FullTextSearchQuery::bypass_index`] is called.  Dynamic projections
+    /// are not supported.
+    pub fn full_text_search(mut self, query: FullTextSearchQuery) -> Self {
+        self.full_text_search = Some(query);
+        self
+    }
+
+    /// Return a uniform random sample of `n` rows
+    ///
+    /// The rows are sampled after the filter is applied.  If fewer than `n` rows
+    /// match then all of them are returned.  Only the row ids are read to make the
+    /// choice, the columns are read for the sampled rows alone.  The sample is
+    /// returned in table order and the limit and offset apply to it.
+    ///
+    /// The same seed returns the same sample as long as the table doesn't change.
+    /// Without a seed a different sample is returned each time.
+    ///
+    /// Sampling is not supported for vector and full text searches, or for remote
+    /// tables.
+    pub fn sample(mut self, n: usize, seed: Option<u64>) -> Self {
+        self.sample = Some(Sample {
+            size: SampleSize::Rows(n),
+            seed,
+        });
+        self
+    }
+
+    /// Return a uniform random sample of about `fraction` of the rows
+    ///
+    /// Each row that matches the filter is kept with probability `fraction`, so the
+    /// size of the sample varies around `fraction` times the number of matches.
+    /// The fraction must be between 0 and 1.  See [`Query::sample`] for the rest.
+    pub fn sample_fraction(mut self, fraction: f64, seed: Option<u64>) -> Self {
+        self.sample = Some(Sample {
+            size: SampleSize::Fraction(fraction),
+            seed,
+        });
+        self
+    }
+
+    /// Group the rows by the values of the given columns before aggregating them
+    ///
+    /// This only applies to [`Query::aggregate`], which returns one row per group.
+    /// Executing the query without aggregates, or as a vector search, fails.
+    pub fn group_by(mut self, columns: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.group_by = columns
+            .into_iter()
+            .map(|column| column.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Compute aggregates over the rows that match the query
+    ///
+    /// The results have a column for each [`Query::group_by`] column followed by a
+    /// column for each aggregate.  Without groups there is a single row.  Only the
+    /// results are returned, the rows are aggregated where the table is stored.
+    ///
+    /// The filter of the query applies to the rows that are aggregated and the
+    /// limit and offset to the groups that are returned.  Remote tables do not
+    /// support aggregates yet.
+    ///
+    /// ```ignore
+    /// let query = table
+    ///     .query()
+    ///     .only_if("price > 10")
+    ///     .group_by(["category"])
+    ///     .aggregate([Agg::count(), Agg::max("created_at")]);
+    /// ```
+    pub fn aggregate(self, aggregates: impl IntoIterator<Item = Agg>) -> AggregateQuery {
+        AggregateQuery {
+            base: self,
+            aggregates: aggregates.into_iter().collect(),
+        }
+    }
+}
+
+/// An aggregate function, see [`Query::aggregate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agg {
+    pub(crate) function: AggFunction,
+    /// The column to aggregate, all rows are counted if this is None
+    pub(crate) column: Option<String>,
+    pub(crate) alias: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AggFunction {
+    Count,
+    Min,
+    Max,
+    Sum,
+}
+
+impl Agg {
+    fn new(function: AggFunction, column: Option<String>) -> Self {
+        Self {
+            function,
+            column,
+            alias: None,
+        }
+    }
+
+    /// The number of rows, in a `count` column
+    pub fn count() -> Self {
+        Self::new(AggFunction::Count, None)
+    }
+
+    /// The number of non-null values of a column, in a `count(column)` column
+    pub fn count_column(column: impl Into<String>) -> Self {
+        Self::new(AggFunction::Count, Some(column.into()))
+    }
+
+    /// The smallest value of a column, in a `min(column)` column
+    pub fn min(column: impl Into<String>) -> Self {
+        Self::new(AggFunction::Min, Some(column.into()))
+    }
+
+    /// The largest value of a column, in a `max(column)` column
+    pub fn max(column: impl Into<String>) -> Self {
+        Self::new(AggFunction::Max, Some(column.into()))
+    }
+
+    /// The sum of a numeric column, in a `sum(column)` column
+    ///
+    /// Integers are summed as 64 bit integers and floats as 64 bit floats.
+    pub fn sum(column: impl Into<String>) -> Self {
+        Self::new(AggFunction::Sum, Some(column.into()))
+    }
+
+    /// Use a different name for the result column
+    pub fn alias(mut self, name: impl Into<String>) -> Self {
+        self.alias = Some(name.into());
+        self
+    }
+
+    /// The name of the result column
+    pub fn name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        let function = match self.function {
+            AggFunction::Count => "count",
+            AggFunction::Min => "min",
+            AggFunction::Max => "max",
+            AggFunction::Sum => "sum",
+        };
+        match &self.column {
+            Some(column) => format!("{}({})", function, column),
+            None => function.to_string(),
+        }
+    }
+}
+
+/// A builder for aggregate queries, see [`Query::aggregate`]
+///
+/// See [`ExecutableQuery`] for methods that can be used to execute
+/// the query and retrieve results.
+#[derive(Debug, Clone)]
+pub struct AggregateQuery {
+    pub(crate) base: Query,
+    pub(crate) aggregates: Vec<Agg>,
+}
+
+impl ExecutableQuery for AggregateQuery {
+    async fn create_plan(&self, options: QueryExecutionOptions) -> Result<Arc<dyn ExecutionPlan>> {
+        self.base
+            .parent
+            .clone()
+            .create_aggregate_plan(self, options)
+            .await
+    }
+
+    async fn execute_with_options(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        execute_with_limits(options.clone(), async {
+            Ok(SendableRecordBatchStream::from(
+                DatasetRecordBatchStream::new(execute_plan(
+                    self.create_plan(options).await?,
+                    Default::default(),
+                )?),
+            ))
+        })
+        .await
+    }
 }
 
 impl HasQuery for Query {
@@ -564,13 +1182,30 @@ impl ExecutableQuery for Query {
             .await
     }
 
+    async fn explain_plan(&self, verbose: bool) -> Result<String> {
+        self.parent
+            .clone()
+            .explain_plan(&self.clone().into_vector(), verbose)
+            .await
+    }
+
+    async fn explain_analyze(&self) -> Result<String> {
+        self.parent
+            .clone()
+            .analyze_plan(&self.clone().into_vector())
+            .await
+    }
+
     async fn execute_with_options(
         &self,
         options: QueryExecutionOptions,
     ) -> Result<SendableRecordBatchStream> {
-        Ok(SendableRecordBatchStream::from(
-            self.parent.clone().plain_query(self, options).await?,
-        ))
+        execute_with_limits(options.clone(), async {
+            Ok(SendableRecordBatchStream::from(
+                self.parent.clone().plain_query(self, options).await?,
+            ))
+        })
+        .await
     }
 }
 
@@ -589,15 +1224,23 @@ pub struct VectorQuery {
     // The column to run the query on. If not specified, we will attempt to guess
     // the column based on the dataset's schema.
     pub(crate) column: Option<String>,
-    // IVF PQ - ANN search.
-    pub(crate) query_vector: Option<Arc<dyn Array>>,
+    // IVF PQ - ANN search, one search for each vector.
+    pub(crate) query_vector: Vec<Arc<dyn Array>>,
+    // Text to embed into the query vector when the query is executed
+    pub(crate) query_text: Option<String>,
     pub(crate) nprobes: usize,
     pub(crate) refine_factor: Option<u32>,
+    pub(crate) ef: Option<usize>,
     pub(crate) distance_type: Option<DistanceType>,
     /// Default is true. Set to false to enforce a brute force search.
     pub(crate) use_index: bool,
     /// Apply filter before ANN search/
     pub(crate) prefilter: bool,
+    /// Only search the rows covered by the vector index
+    pub(crate) fast_search: bool,
+    /// The query vectors are the vectors of one multivector search, rather than
+    /// separate searches
+    pub(crate) multivector: bool,
 }
 
 impl VectorQuery {
@@ -605,12 +1248,16 @@ impl VectorQuery {
         Self {
             base,
             column: None,
-            query_vector: None,
+            query_vector: Vec::new(),
+            query_text: None,
             nprobes: 20,
             refine_factor: None,
+            ef: None,
             distance_type: None,
             use_index: true,
             prefilter: true,
+            fast_search: false,
+            multivector: false,
         }
     }
 
@@ -683,6 +1330,20 @@ impl VectorQuery {
         self
     }
 
+    /// The size of the candidate list to explore in each HNSW graph
+    ///
+    /// This argument is only used when the vector column has an IVF HNSW index
+    /// (IVF_HNSW_PQ or IVF_HNSW_SQ).  For other indices, or if there is no index,
+    /// this value is ignored.
+    ///
+    /// Increasing this value will increase the recall of your query but will also
+    /// increase the latency of your query.  The value should be at least as large as
+    /// `limit`.  If this method is not called then a default based on `limit` is used.
+    pub fn ef(mut self, ef: usize) -> Self {
+        self.ef = Some(ef);
+        self
+    }
+
     /// Set the distance metric to use
     ///
     /// When performing a vector search we try and find the "nearest" vectors according
@@ -690,11 +1351,17 @@ impl VectorQuery {
     /// use.  See [`DistanceType`] for more details on the different distance metrics
     /// available.
     ///
-    /// Note: if there is a vector index then the distance type used MUST match the distance
-    /// type used to train the vector index.  If this is not done then the results will be
-    /// invalid.
+    /// The `_distance` column of the results holds the distance between each result
+    /// and the query vector.  Smaller values are always nearer, whichever distance type
+    /// is used, and results are sorted by ascending `_distance`.
+    ///
+    /// If there is a vector index then the distance type MUST match the distance type
+    /// used to train the vector index, otherwise the query fails.  Call
+    /// [`Self::bypass_vector_index`] to run an exact search with a different distance
+    /// type.
     ///
-    /// By default [`DistanceType::L2`] is used.
+    /// By default the distance type of the vector index is used, or
+    /// [`DistanceType::L2`] if there is no index.
     pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
         self.distance_type = Some(distance_type);
         self
@@ -723,6 +1390,16 @@ impl VectorQuery {
         self
     }
 
+    /// Choose whether the filter is applied before (`true`) or after (`false`) the
+    /// vector search
+    ///
+    /// This is the default (prefiltering) when `true` and the same as
+    /// [`Self::postfilter`] when `false`.
+    pub fn prefilter(mut self, prefilter: bool) -> Self {
+        self.prefilter = prefilter;
+        self
+    }
+
     /// If this is called then any vector index is skipped
     ///
     /// An exhaustive (flat) search will be performed.  The query vector will
@@ -734,23 +1411,164 @@ impl VectorQuery {
         self.use_index = false;
         self
     }
+
+    /// If this is called then only the rows covered by the vector index are searched
+    ///
+    /// Rows added since the index was created or last optimized are searched with a
+    /// flat search, which can become slow when there are many of them.  A fast search
+    /// skips them: only the fragments that the index covers are searched, at the
+    /// current version, so deleted rows are never returned.  Updated rows are written
+    /// to new fragments, so they are skipped until the index is optimized.
+    /// [`crate::Table::index_stats`] reports how many rows are not indexed, which
+    /// can help decide when this is acceptable.
+    ///
+    /// The filter of the query is always applied before the search, as if
+    /// [`Self::prefilter`] was set, since the skipped rows are left out by a filter.
+    ///
+    /// The query fails if the vector column has no index.  This has no effect if
+    /// the index is bypassed with [`Self::bypass_vector_index`].
+    pub fn fast_search(mut self) -> Self {
+        self.fast_search = true;
+        self
+    }
+
+    /// Combine this vector search with a full text search
+    ///
+    /// Both searches are run with the limit, filter and projection of the query and
+    /// their results are merged by a [`Reranker`], [`RRFReranker`] by default.  The
+    /// results have a `_relevance_score` column, see [`crate::rerankers::merge_results`]
+    /// for the other columns.
+    pub fn hybrid(self, query: FullTextSearchQuery) -> HybridQuery {
+        HybridQuery {
+            vector_query: self,
+            full_text_query: query,
+            reranker: Arc::new(RRFReranker::default()),
+        }
+    }
+
+    /// Embed the query text, if there is any, into the query vector
+    async fn embed_query_text(&self) -> Result<Option<Self>> {
+        let Some(text) = &self.query_text else {
+            return Ok(None);
+        };
+        let table_definition = self.base.parent.table_definition().await?;
+        let embeddings = table_definition
+            .column_definitions
+            .iter()
+            .filter_map(|cd| match &cd.kind {
+                ColumnKind::Embedding(definition) => Some(definition),
+                ColumnKind::Physical => None,
+            })
+            .collect::<Vec<_>>();
+        let definition = match (&self.column, embeddings.as_slice()) {
+            (Some(column), _) => embeddings
+                .iter()
+                .find(|ed| ed.dest_column_name() == *column)
+                .ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                        "cannot search for text, the column '{}' has no embedding function",
+                        column
+                    ),
+                })?,
+            (None, []) => {
+                return Err(Error::InvalidInput {
+                    message: "cannot search for text, the table has no embedding columns"
+                        .to_string(),
+                })
+            }
+            (None, [definition]) => definition,
+            (None, _) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "cannot search for text, the table has more than one embedding column ({}), use column() to choose one",
+                        embeddings
+                            .iter()
+                            .map(|ed| ed.dest_column_name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                })
+            }
+        };
+
+        let func = resolve_embedding_function(
+            self.base.embedding_registry.as_ref(),
+            definition,
+            &table_definition,
+        )?;
+        let input = with_prefix(
+            Arc::new(StringArray::from(vec![text.as_str()])),
+            definition.query_prefix.as_deref(),
+        )?;
+        let embedding = func.compute_query_embeddings_async(input).await?;
+
+        let mut query = self.clone();
+        query.column = Some(definition.dest_column_name());
+        query.query_text = None;
+        // Multivector functions embed the text as a list of several vectors
+        if let Some(list) = embedding.as_any().downcast_ref::<ListArray>() {
+            let vectors = list.value(0);
+            let vectors = vectors
+                .as_fixed_size_list_opt()
+                .ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                    "embedding function '{}' returned a list of {} rather than a list of vectors",
+                    func.name(),
+                    vectors.data_type()
+                ),
+                })?;
+            query.query_vector = (0..vectors.len())
+                .map(|i| {
+                    vectors
+                        .value(i)
+                        .to_query_vector(&DataType::Float32, func.name())
+                })
+                .collect::<Result<Vec<_>>>()?;
+            query.multivector = true;
+        } else {
+            // Functions may return the embedding as a list with one item
+            let embedding = match embedding.as_any().downcast_ref::<FixedSizeListArray>() {
+                Some(list) => list.value(0),
+                None => embedding,
+            };
+            query.query_vector = vec![embedding.to_query_vector(&DataType::Float32, func.name())?];
+        }
+        Ok(Some(query))
+    }
 }
 
 impl ExecutableQuery for VectorQuery {
     async fn create_plan(&self, options: QueryExecutionOptions) -> Result<Arc<dyn ExecutionPlan>> {
-        self.base.parent.clone().create_plan(self, options).await
+        let embedded = self.embed_query_text().await?;
+        let query = embedded.as_ref().unwrap_or(self);
+        self.base.parent.clone().create_plan(query, options).await
+    }
+
+    async fn explain_plan(&self, verbose: bool) -> Result<String> {
+        let embedded = self.embed_query_text().await?;
+        let query = embedded.as_ref().unwrap_or(self);
+        self.base.parent.clone().explain_plan(query, verbose).await
+    }
+
+    async fn explain_analyze(&self) -> Result<String> {
+        let embedded = self.embed_query_text().await?;
+        let query = embedded.as_ref().unwrap_or(self);
+        self.base.parent.clone().analyze_plan(query).await
     }
 
     async fn execute_with_options(
         &self,
         options: QueryExecutionOptions,
     ) -> Result<SendableRecordBatchStream> {
-        Ok(SendableRecordBatchStream::from(
-            DatasetRecordBatchStream::new(execute_plan(
-                self.create_plan(options).await?,
-                Default::default(),
-            )?),
-        ))
+        execute_with_limits(options.clone(), async {
+            Ok(SendableRecordBatchStream::from(
+                DatasetRecordBatchStream::new(execute_plan(
+                    self.create_plan(options).await?,
+                    Default::default(),
+                )?),
+            ))
+        })
+        .await
     }
 }
 
@@ -760,20 +1578,123 @@ impl HasQuery for VectorQuery {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+/// A builder for hybrid searches, see [`VectorQuery::hybrid`]
+///
+/// See [`QueryBase`] for additional methods that can be used to parameterize
+/// both searches.
+#[derive(Debug, Clone)]
+pub struct HybridQuery {
+    pub(crate) vector_query: VectorQuery,
+    pub(crate) full_text_query: FullTextSearchQuery,
+    pub(crate) reranker: Arc<dyn Reranker>,
+}
+
+impl HybridQuery {
+    /// Merge the results of the two searches with the given reranker
+    pub fn rerank(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = reranker;
+        self
+    }
+
+    /// Run both searches and rerank their results
+    async fn execute_to_batch(&self) -> Result<RecordBatch> {
+        if self.vector_query.query_vector.len() > 1 {
+            return Err(Error::InvalidInput {
+                message: "a hybrid search can only have one query vector".to_string(),
+            });
+        }
+        let limit = self.vector_query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let offset = self.vector_query.base.offset.unwrap_or(0);
+        // The offset is applied to the merged results, not to each search
+        let mut vector_query = self.vector_query.clone().with_row_id();
+        vector_query.base.limit = Some(limit + offset);
+        vector_query.base.offset = None;
+        let full_text_query = vector_query
+            .base
+            .clone()
+            .full_text_search(self.full_text_query.clone());
+
+        let (vector_hits, fts_hits) = futures::try_join!(
+            collect_batch(vector_query.execute()),
+            collect_batch(full_text_query.execute())
+        )?;
+        let merged = self.reranker.rerank(vector_hits, fts_hits)?;
+        let offset = offset.min(merged.num_rows());
+        let mut merged = merged.slice(offset, limit.min(merged.num_rows() - offset));
+        if !self
+            .vector_query
+            .base
+            .clone()
+            .resolve_row_id_selection()
+            .await?
+            .with_row_id
+        {
+            if let Ok(index) = merged.schema().index_of(ROW_ID) {
+                merged.remove_column(index);
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// Collect the results of a query into a single batch
+async fn collect_batch(
+    stream: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<RecordBatch> {
+    let stream = stream.await?;
+    let schema = stream.schema();
+    let batches = stream.try_collect::<Vec<_>>().await?;
+    Ok(arrow::compute::concat_batches(&schema, &batches)?)
+}
+
+impl HasQuery for HybridQuery {
+    fn mut_query(&mut self) -> &mut Query {
+        &mut self.vector_query.base
+    }
+}
+
+impl ExecutableQuery for HybridQuery {
+    async fn create_plan(&self, _options: QueryExecutionOptions) -> Result<Arc<dyn ExecutionPlan>> {
+        // The reranker needs all the results, so the searches run while planning
+        let batch = self.execute_to_batch().await?;
+        let stream =
+            RecordBatchStreamAdapter::new(batch.schema(), futures::stream::iter([Ok(batch)]));
+        Ok(Arc::new(OneShotExec::new(Box::pin(stream))))
+    }
+
+    async fn execute_with_options(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        execute_with_limits(options.clone(), async {
+            Ok(SendableRecordBatchStream::from(
+                DatasetRecordBatchStream::new(execute_plan(
+                    self.create_plan(options).await?,
+                    Default::default(),
+                )?),
+            ))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
     use super::*;
     use arrow_array::{
-        cast::AsArray, Float32Array, Int32Array, RecordBatch, RecordBatchIterator,
-        RecordBatchReader,
+        cast::AsArray,
+        types::{Float32Type, Int32Type, Int64Type, UInt32Type, UInt64Type},
+        Float32Array, Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
     };
     use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
     use futures::{StreamExt, TryStreamExt};
     use lance_testing::datagen::{BatchGenerator, IncrementingInt32, RandomVector};
     use tempfile::tempdir;
 
+    use crate::arrow::IntoTyped;
+    use crate::rerankers::{DISTANCE, RELEVANCE_SCORE, SCORE};
     use crate::{connect, Table};
 
     #[tokio::test]
@@ -794,7 +1715,7 @@ mod tests {
 
         let vector = Float32Array::from_iter_values([0.1, 0.2]);
         let query = table.query().nearest_to(&[0.1, 0.2]).unwrap();
-        assert_eq!(*query.query_vector.unwrap().as_ref().as_primitive(), vector);
+        assert_eq!(*query.query_vector[0].as_ref().as_primitive(), vector);
 
         let new_vector = Float32Array::from_iter_values([9.8, 8.7]);
 
@@ -806,17 +1727,16 @@ mod tests {
             .nprobes(1000)
             .postfilter()
             .distance_type(DistanceType::Cosine)
-            .refine_factor(999);
+            .refine_factor(999)
+            .ef(64);
 
-        assert_eq!(
-            *query.query_vector.unwrap().as_ref().as_primitive(),
-            new_vector
-        );
+        assert_eq!(*query.query_vector[0].as_ref().as_primitive(), new_vector);
         assert_eq!(query.base.limit.unwrap(), 100);
         assert_eq!(query.nprobes, 1000);
         assert!(query.use_index);
         assert_eq!(query.distance_type, Some(DistanceType::Cosine));
         assert_eq!(query.refine_factor, Some(999));
+        assert_eq!(query.ef, Some(64));
     }
 
     #[tokio::test]
@@ -907,6 +1827,217 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_select_dynamic() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_text_table(&tmp_dir).await;
+
+        let results = table
+            .query()
+            .only_if("id < 2")
+            .select(Select::dynamic(&[
+                ("id", "id"),
+                ("next", "id + 1"),
+                ("shout", "upper(text)"),
+            ]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&results[0].schema(), &results).unwrap();
+        let schema = batch.schema();
+        let columns = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(columns, vec!["id", "next", "shout"]);
+        let next = arrow::compute::cast(&batch["next"], &DataType::Int64).unwrap();
+        let next = next.as_primitive::<arrow_array::types::Int64Type>();
+        let shout = batch["shout"].as_string::<i32>();
+        for (i, id) in batch["id"]
+            .as_primitive::<Int32Type>()
+            .values()
+            .iter()
+            .enumerate()
+        {
+            assert_eq!(next.value(i), *id as i64 + 1);
+        }
+        let mut shouted = shout.iter().map(|s| s.unwrap()).collect::<Vec<_>>();
+        shouted.sort();
+        assert_eq!(shouted, vec!["A LAZY DOG", "THE QUICK BROWN FOX"]);
+
+        // Vector searches can compute columns from the distance
+        let results = table
+            .query()
+            .nearest_to(&[4.0, 0.0])
+            .unwrap()
+            .limit(2)
+            .select(Select::dynamic(&[
+                ("id", "id"),
+                ("similarity", "-_distance"),
+            ]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let schema = results[0].schema();
+        let columns = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(columns, vec!["id", "similarity"]);
+        assert_eq!(ids(&results), vec![4, 3]);
+        let similarity = results
+            .iter()
+            .flat_map(|b| {
+                b["similarity"]
+                    .as_primitive::<Float32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(similarity, vec![0.0, -1.0]);
+
+        // Bad expressions are reported when the query runs
+        let query = table.query().select(Select::dynamic(&[("x", "id +* 2")]));
+        let err = query.execute().await.err().unwrap();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("id +* 2")),
+            "{:?}",
+            err
+        );
+        let err = table
+            .query()
+            .select(Select::dynamic(&[("x", "no_such_column + 1")]))
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no_such_column"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate() {
+        let tmp_dir = tempdir().unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("category", DataType::Utf8, false),
+            ArrowField::new("price", DataType::Int32, false),
+            ArrowField::new(
+                "vector",
+                DataType::new_fixed_size_list(DataType::Float32, 2, true),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "a", "c", "b", "a"])),
+                Arc::new(Int32Array::from_iter_values(1..=6)),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        (0..6).map(|i| Some(vec![Some(i as f32), Some(0.0)])),
+                        2,
+                    ),
+                ),
+            ],
+        )
+        .unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = conn
+            .create_table(
+                "my_table",
+                Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let collect = |batches: Vec<RecordBatch>| {
+            arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap()
+        };
+
+        let results = table
+            .query()
+            .group_by(["category"])
+            .aggregate([
+                Agg::count(),
+                Agg::max("price"),
+                Agg::sum("price").alias("total"),
+            ])
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let results = collect(results);
+        let schema = results.schema();
+        let columns = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(columns, vec!["category", "count", "max(price)", "total"]);
+        let mut groups = (0..results.num_rows())
+            .map(|i| {
+                (
+                    results["category"].as_string::<i32>().value(i).to_string(),
+                    results["count"].as_primitive::<Int64Type>().value(i),
+                    results["max(price)"].as_primitive::<Int32Type>().value(i),
+                    results["total"].as_primitive::<Int64Type>().value(i),
+                )
+            })
+            .collect::<Vec<_>>();
+        groups.sort();
+        assert_eq!(
+            groups,
+            vec![
+                ("a".to_string(), 3, 6, 10),
+                ("b".to_string(), 2, 5, 7),
+                ("c".to_string(), 1, 4, 4),
+            ]
+        );
+
+        // The filter applies before aggregating, without groups there is one row
+        let results = table
+            .query()
+            .only_if("price > 2")
+            .aggregate([Agg::count(), Agg::min("price")])
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let results = collect(results);
+        assert_eq!(results.num_rows(), 1);
+        assert_eq!(results["count"].as_primitive::<Int64Type>().value(0), 4);
+        assert_eq!(
+            results["min(price)"].as_primitive::<Int32Type>().value(0),
+            3
+        );
+
+        let err = table
+            .query()
+            .aggregate([Agg::sum("category")])
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        let err = table
+            .query()
+            .group_by(["category"])
+            .nearest_to(&[0.0, 0.0])
+            .unwrap()
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("vector search")),
+            "{:?}",
+            err
+        );
+    }
+
     #[tokio::test]
     async fn test_execute_no_vector() {
         // TODO: Switch back to memory://foo after https://github.com/lancedb/lancedb/issues/1051
@@ -981,76 +2112,1506 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_execute_with_options() {
+    async fn test_execute_timeout() {
         let tmp_dir = tempdir().unwrap();
-        let table = make_test_table(&tmp_dir).await;
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let conn = connect(dataset_path.to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let batches = BatchGenerator::new()
+            .col(Box::new(RandomVector::new().named("vector".to_string())))
+            .col(Box::new(IncrementingInt32::new().named("id".to_string())))
+            .batch(200_000);
+        let table = conn
+            .create_table("my_table", Box::new(batches))
+            .execute()
+            .await
+            .unwrap();
 
-        let mut results = table
+        // Reading 200k rows one at a time takes much longer than the timeout
+        let slow = || QueryExecutionOptions {
+            max_batch_length: 1,
+            timeout: Some(std::time::Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let plain = table.query().execute_with_options(slow());
+        let vector = table
+            .query()
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .limit(200_000)
+            .execute_with_options(slow());
+        for results in [plain.await, vector.await] {
+            let err = match results {
+                Ok(stream) => stream.try_collect::<Vec<_>>().await.unwrap_err(),
+                Err(err) => err,
+            };
+            assert!(matches!(err, Error::Timeout { .. }), "{:?}", err);
+        }
+
+        let results = table
             .query()
+            .limit(10)
             .execute_with_options(QueryExecutionOptions {
-                max_batch_length: 10,
+                timeout: Some(std::time::Duration::from_secs(60)),
+                ..Default::default()
             })
             .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
             .unwrap();
+        assert_eq!(ids(&results).len(), 10);
+    }
 
-        while let Some(batch) = results.next().await {
-            assert!(batch.unwrap().num_rows() <= 10);
-        }
+    #[tokio::test]
+    async fn test_timeout_ready_stream() {
+        // A stream that never returns Pending still times out
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "id",
+            DataType::Int32,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+        )
+        .unwrap();
+        let endless: SendableRecordBatchStream = Box::pin(crate::arrow::SimpleRecordBatchStream {
+            schema,
+            stream: futures::stream::repeat_with(move || Ok(batch.clone())),
+        });
+        let timeout = std::time::Duration::from_millis(10);
+        let stream = TimeoutStream::new(endless, tokio::time::Instant::now() + timeout, timeout);
+        let err = stream.try_for_each(|_| async { Ok(()) }).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{:?}", err);
     }
 
-    fn assert_plan_exists(plan: &Arc<dyn ExecutionPlan>, name: &str) -> bool {
-        if plan.name() == name {
-            return true;
+    #[tokio::test]
+    async fn test_prefilter_postfilter() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        // Only 5 of the 512 rows match
+        let query = table
+            .query()
+            .limit(10)
+            .only_if("id % 100 == 0")
+            .nearest_to(&[0.1; 4])
+            .unwrap();
+
+        // Prefiltering searches the matching rows, all of them are found
+        let results = query
+            .clone()
+            .prefilter(true)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut found = ids(&results);
+        found.sort();
+        assert_eq!(found, vec![0, 100, 200, 300, 400]);
+
+        // Postfiltering filters the 10 nearest rows, which may match fewer rows
+        for postfiltered in [query.clone().postfilter(), query.clone().prefilter(false)] {
+            let results = postfiltered
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let found = ids(&results);
+            assert!(found.len() < 5, "{:?}", found);
+            assert!(found.iter().all(|id| id % 100 == 0));
         }
-        plan.children()
-            .iter()
-            .any(|child| assert_plan_exists(child, name))
+
+        // The filter runs below the vector search when prefiltering, above it otherwise
+        let plan = query.clone().explain_plan(false).await.unwrap();
+        let knn = plan.find("KNN").unwrap();
+        assert!(plan.find("FilterExec").unwrap() > knn, "{}", plan);
+        let plan = query
+            .clone()
+            .postfilter()
+            .explain_plan(false)
+            .await
+            .unwrap();
+        let knn = plan.find("KNN").unwrap();
+        assert!(plan.find("FilterExec").unwrap() < knn, "{}", plan);
     }
 
     #[tokio::test]
-    async fn test_create_execute_plan() {
+    async fn test_offset() {
         let tmp_dir = tempdir().unwrap();
-        let table = make_test_table(&tmp_dir).await;
-        let plan = table
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let conn = connect(dataset_path.to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let batches = BatchGenerator::new()
+            .col(Box::new(RandomVector::new().named("vector".to_string())))
+            .col(Box::new(IncrementingInt32::new().named("id".to_string())))
+            .batch(1000);
+        let table = conn
+            .create_table("my_table", Box::new(batches))
+            .execute()
+            .await
+            .unwrap();
+
+        let all_nearest = table
             .query()
-            .nearest_to(vec![0.1, 0.2, 0.3, 0.4])
+            .nearest_to(&[0.1; 4])
             .unwrap()
-            .create_plan(QueryExecutionOptions::default())
+            .limit(1000)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert_plan_exists(&plan, "KNNFlatSearch");
-        assert_plan_exists(&plan, "ProjectionExec");
+        let all_nearest = ids(&all_nearest);
+        assert_eq!(all_nearest.len(), 1000);
+
+        let mut plain_pages = Vec::new();
+        let mut nearest_pages = Vec::new();
+        for page in 0..10 {
+            let page_rows = table
+                .query()
+                .limit(100)
+                .offset(page * 100)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(page_rows.iter().map(|b| b.num_rows()).sum::<usize>(), 100);
+            plain_pages.extend(ids(&page_rows));
+
+            let page_rows = table
+                .query()
+                .nearest_to(&[0.1; 4])
+                .unwrap()
+                .limit(100)
+                .offset(page * 100)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            nearest_pages.extend(ids(&page_rows));
+        }
+        // Every row is returned once, nearest rows in order of distance
+        plain_pages.sort();
+        assert_eq!(plain_pages, (0..1000).collect::<Vec<_>>());
+        assert_eq!(nearest_pages, all_nearest);
+
+        // Paging past the end returns no rows
+        for query in [
+            table.query().offset(1000),
+            table.query().limit(100).offset(2000),
+        ] {
+            let results = query
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+        }
+        let results = table
+            .query()
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .limit(100)
+            .offset(950)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(ids(&results), all_nearest[950..]);
     }
 
     #[tokio::test]
-    async fn query_base_methods_on_vector_query() {
-        // Make sure VectorQuery can be used as a QueryBase
+    async fn test_with_row_id() {
         let tmp_dir = tempdir().unwrap();
         let table = make_test_table(&tmp_dir).await;
+        let column_names = |batches: &[RecordBatch]| {
+            batches[0]
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .collect::<Vec<_>>()
+        };
 
-        let mut results = table
-            .vector_search(&[1.0, 2.0, 3.0, 4.0])
+        let results = table
+            .query()
+            .select(Select::columns(&["id"]))
+            .with_row_id()
+            .with_row_address()
+            .execute()
+            .await
             .unwrap()
-            .limit(1)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(column_names(&results), vec!["id", ROW_ID, ROW_ADDR]);
+
+        // Selecting the row id by name doesn't add a second row id column
+        let results = table
+            .query()
+            .select(Select::columns(&["id", ROW_ID]))
+            .with_row_id()
             .execute()
             .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
             .unwrap();
+        assert_eq!(column_names(&results), vec!["id", ROW_ID]);
 
-        let first_batch = results.next().await.unwrap().unwrap();
-        assert_eq!(first_batch.num_rows(), 1);
-        assert!(results.next().await.is_none());
+        // A stored column with the name of the row id is selected as it is
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new(ROW_ID, DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(Int32Array::from(vec![7])),
+            ],
+        )
+        .unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let stored = conn
+            .create_table(
+                "stored_row_id",
+                Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let query = stored
+            .query()
+            .select(Select::columns(&["id", ROW_ID, ROW_ADDR]))
+            .resolve_row_id_selection()
+            .await
+            .unwrap();
+        assert!(!query.with_row_id);
+        assert!(query.with_row_address);
+        assert!(matches!(&query.select, Select::Columns(columns) if columns == &["id", ROW_ID]));
 
-        // query with wrong vector dimension
-        let error_result = table
-            .vector_search(&[1.0, 2.0, 3.0])
+        let results = table
+            .query()
+            .nearest_to(&[0.1; 4])
             .unwrap()
-            .limit(1)
+            .select(Select::columns(&["id"]))
+            .with_row_id()
             .execute()
-            .await;
-        assert!(error_result
-            .err()
+            .await
             .unwrap()
-            .to_string()
-            .contains("No vector column found to match with the query vector dimension: 3"));
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut columns = column_names(&results);
+        columns.sort();
+        assert_eq!(columns, vec![DISTANCE, ROW_ID, "id"]);
+        let found = ids(&results);
+        let row_ids = results
+            .iter()
+            .flat_map(|b| b[ROW_ID].as_primitive::<UInt64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+
+        // The row ids still find the same rows after other rows are deleted
+        let deleted = found[0];
+        table.delete(&format!("id = {}", deleted)).await.unwrap();
+        let dataset = table.as_native().unwrap().dataset.get().await.unwrap();
+        let projection = dataset.schema().project(&["id"]).unwrap();
+        let taken = dataset.take_rows(&row_ids[1..], &projection).await.unwrap();
+        assert_eq!(ids(&[taken]), found[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_nearest_to_many() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let query_vectors = [[0.1_f32; 4], [0.9; 4], [0.5; 4]];
+
+        let results = table
+            .query()
+            .limit(5)
+            .only_if("id % 2 == 0")
+            .nearest_to_many(query_vectors.iter().map(|v| v.as_slice()))
+            .unwrap()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // Each query vector finds the same rows as a search of its own
+        let mut found = vec![Vec::new(); query_vectors.len()];
+        for batch in &results {
+            let query_index = batch[QUERY_INDEX].as_primitive::<UInt32Type>();
+            let ids = batch["id"].as_primitive::<Int32Type>();
+            for (query_index, id) in query_index.values().iter().zip(ids.values()) {
+                found[*query_index as usize].push(*id);
+            }
+        }
+        for (query_vector, mut found) in query_vectors.iter().zip(found) {
+            let expected = table
+                .query()
+                .limit(5)
+                .only_if("id % 2 == 0")
+                .nearest_to(query_vector.as_slice())
+                .unwrap()
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let mut expected = ids(&expected);
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+            assert!(found.iter().all(|id| id % 2 == 0));
+        }
+
+        let no_vectors: [&[f32]; 0] = [];
+        assert!(matches!(
+            table.query().nearest_to_many(no_vectors),
+            Err(Error::InvalidInput { .. })
+        ));
+        // Vectors built as nested vecs work too, and the one that is wrong is named
+        let nested = vec![vec![0.1_f32; 4], vec![0.9; 4], vec![0.5; 3]];
+        let err = table.query().nearest_to_many(nested).unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }));
+        assert!(
+            err.to_string()
+                .contains("the vector at index 2 has dimension 3 but the first has 4"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let plan = table
+            .query()
+            .only_if("id > 10")
+            .explain_plan(false)
+            .await
+            .unwrap();
+        assert!(plan.contains("LanceScan"), "{}", plan);
+
+        let query = table.query().nearest_to(&[0.1; 4]).unwrap().limit(5);
+        let plan = query.explain_plan(true).await.unwrap();
+        assert!(plan.contains("KNN"), "{}", plan);
+
+        // Analyzing runs the query and reports how many rows each step produced
+        let analyzed = query.explain_analyze().await.unwrap();
+        assert!(analyzed.contains("KNN"), "{}", analyzed);
+        assert!(analyzed.contains("output_rows=5"), "{}", analyzed);
+    }
+
+    #[tokio::test]
+    async fn test_fast_search() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let query = table.query().nearest_to(&[0.5; 4]).unwrap().limit(10);
+
+        let err = query.clone().fast_search().execute().await.err().unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        table
+            .create_index(
+                &["vector"],
+                crate::index::Index::IvfPq(
+                    crate::index::vector::IvfPqIndexBuilder::default().num_partitions(2),
+                ),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        // Append rows that are exact matches of the query
+        let schema = table.schema().await.unwrap();
+        let DataType::FixedSizeList(item, dim) =
+            schema.field_with_name("vector").unwrap().data_type()
+        else {
+            panic!("vector is not a vector column");
+        };
+        let vectors = FixedSizeListArray::try_new(
+            item.clone(),
+            *dim,
+            Arc::new(Float32Array::from(vec![0.5; 10 * *dim as usize])),
+            None,
+        )
+        .unwrap();
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| match field.name().as_str() {
+                "vector" => Arc::new(vectors.clone()) as Arc<dyn Array>,
+                _ => Arc::new(Int32Array::from_iter_values(1000..1010)) as Arc<dyn Array>,
+            })
+            .collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        table
+            .add(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)))
+            .execute()
+            .await
+            .unwrap();
+        let stats = table.index_stats("vector_idx").await.unwrap().unwrap();
+        assert_eq!(stats.num_unindexed_rows, 10);
+
+        let results = query
+            .clone()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut found = ids(&results);
+        found.sort();
+        assert_eq!(found, (1000..1010).collect::<Vec<_>>());
+
+        let query = query.fast_search();
+        let results = query
+            .clone()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let found = ids(&results);
+        assert_eq!(found.len(), 10);
+        assert!(found.iter().all(|id| *id < 1000), "{:?}", found);
+
+        // The current version is searched, so deleted rows aren't returned and the
+        // version the index was built on can be removed
+        table.delete(&format!("id = {}", found[0])).await.unwrap();
+        table
+            .cleanup_old_versions(crate::table::Duration::zero(), true)
+            .await
+            .unwrap();
+        let results = query
+            .only_if("id != -1")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let after_delete = ids(&results);
+        assert_eq!(after_delete.len(), 10);
+        assert!(!after_delete.contains(&found[0]), "{:?}", after_delete);
+        assert!(
+            after_delete.iter().all(|id| *id < 1000),
+            "{:?}",
+            after_delete
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_options() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let mut results = table
+            .query()
+            .execute_with_options(QueryExecutionOptions {
+                max_batch_length: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        while let Some(batch) = results.next().await {
+            assert!(batch.unwrap().num_rows() <= 10);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_length_bounds() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        for num_rows in [1, 100, 1000, 2500] {
+            let batches = BatchGenerator::new()
+                .col(Box::new(RandomVector::new().named("vector".to_string())))
+                .col(Box::new(IncrementingInt32::new().named("id".to_string())))
+                .batch(num_rows);
+            let table = conn
+                .create_table(format!("table_{}", num_rows), Box::new(batches))
+                .execute()
+                .await
+                .unwrap();
+            // Small fragments give small batches to merge
+            for _ in 0..3 {
+                let batches = BatchGenerator::new()
+                    .col(Box::new(RandomVector::new().named("vector".to_string())))
+                    .col(Box::new(IncrementingInt32::new().named("id".to_string())))
+                    .batch(3);
+                table.add(Box::new(batches)).execute().await.unwrap();
+            }
+            let total = num_rows as usize + 9;
+
+            let plain = table.query();
+            let vector = table.query().nearest_to(&[0.1; 4]).unwrap().limit(total);
+            let plain_ids = ids(&plain
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap());
+            let vector_ids = ids(&vector
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap());
+
+            for (min, max) in [(0, 10), (50, 100), (64, 64)] {
+                let options = QueryExecutionOptions {
+                    max_batch_length: max,
+                    min_batch_length: min,
+                    ..Default::default()
+                };
+                let results = [
+                    (
+                        plain.execute_with_options(options.clone()).await,
+                        &plain_ids,
+                    ),
+                    (vector.execute_with_options(options).await, &vector_ids),
+                ];
+                for (results, expected) in results {
+                    let batches = results.unwrap().try_collect::<Vec<_>>().await.unwrap();
+                    let (last, rest) = batches.split_last().unwrap();
+                    assert!(last.num_rows() <= max as usize);
+                    for batch in rest {
+                        let length = batch.num_rows();
+                        assert!(length >= min as usize && length <= max as usize);
+                    }
+                    // The rows are in the same order as without the bounds
+                    assert_eq!(&ids(&batches), expected);
+                }
+            }
+        }
+
+        let table = make_test_table(&tmp_dir).await;
+        let err = table
+            .query()
+            .execute_with_options(QueryExecutionOptions {
+                max_batch_length: 10,
+                min_batch_length: 20,
+                ..Default::default()
+            })
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    fn assert_plan_exists(plan: &Arc<dyn ExecutionPlan>, name: &str) -> bool {
+        if plan.name() == name {
+            return true;
+        }
+        plan.children()
+            .iter()
+            .any(|child| assert_plan_exists(child, name))
+    }
+
+    #[tokio::test]
+    async fn test_create_execute_plan() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let plan = table
+            .query()
+            .nearest_to(vec![0.1, 0.2, 0.3, 0.4])
+            .unwrap()
+            .create_plan(QueryExecutionOptions::default())
+            .await
+            .unwrap();
+        assert_plan_exists(&plan, "KNNFlatSearch");
+        assert_plan_exists(&plan, "ProjectionExec");
+    }
+
+    #[tokio::test]
+    async fn query_base_methods_on_vector_query() {
+        // Make sure VectorQuery can be used as a QueryBase
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let mut results = table
+            .vector_search(&[1.0, 2.0, 3.0, 4.0])
+            .unwrap()
+            .limit(1)
+            .execute()
+            .await
+            .unwrap();
+
+        let first_batch = results.next().await.unwrap().unwrap();
+        assert_eq!(first_batch.num_rows(), 1);
+        assert!(results.next().await.is_none());
+
+        // query with wrong vector dimension
+        let error_result = table
+            .vector_search(&[1.0, 2.0, 3.0])
+            .unwrap()
+            .limit(1)
+            .execute()
+            .await;
+        assert!(error_result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("No vector column found to match with the query vector dimension: 3"));
+    }
+
+    async fn make_text_table(tmp_dir: &tempfile::TempDir) -> Table {
+        let table = make_unindexed_text_table(tmp_dir).await;
+        table
+            .create_index(
+                &["text"],
+                crate::index::Index::FTS(crate::index::scalar::FtsIndexBuilder::default()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        table
+    }
+
+    async fn make_unindexed_text_table(tmp_dir: &tempfile::TempDir) -> Table {
+        let text = [
+            "the quick brown fox",
+            "a lazy dog",
+            "the quick dog",
+            "cats and dogs",
+            "an empty document",
+        ];
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("text", DataType::Utf8, false),
+            ArrowField::new(
+                "vector",
+                DataType::new_fixed_size_list(DataType::Float32, 2, true),
+                false,
+            ),
+        ]));
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            (0..text.len()).map(|i| Some(vec![Some(i as f32), Some(0.0)])),
+            2,
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..text.len() as i32)),
+                Arc::new(StringArray::from_iter_values(text)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        conn.create_table(
+            "my_table",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .execute()
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_distance_types() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let rows = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let rows = arrow::compute::concat_batches(&rows[0].schema(), &rows).unwrap();
+        let vectors = rows["vector"].as_fixed_size_list();
+        let dim = vectors.value_length() as usize;
+        let query = vectors
+            .value(7)
+            .as_primitive::<Float32Type>()
+            .values()
+            .to_vec();
+
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let reference = |distance_type: DistanceType, v: &[f32]| match distance_type {
+            DistanceType::L2 => v.iter().zip(&query).map(|(x, y)| (x - y).powi(2)).sum(),
+            DistanceType::Cosine => {
+                1.0 - dot(v, &query) / (dot(v, v).sqrt() * dot(&query, &query).sqrt())
+            }
+            DistanceType::Dot => 1.0 - dot(v, &query),
+            _ => unreachable!(),
+        };
+
+        for distance_type in [DistanceType::L2, DistanceType::Cosine, DistanceType::Dot] {
+            let mut expected = (0..rows.num_rows())
+                .map(|i| {
+                    let v = vectors.value(i);
+                    let v = v.as_primitive::<Float32Type>().values();
+                    assert_eq!(v.len(), dim);
+                    (
+                        rows["id"].as_primitive::<Int32Type>().value(i),
+                        reference(distance_type, v),
+                    )
+                })
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+            expected.truncate(20);
+
+            let results = table
+                .query()
+                .nearest_to(query.as_slice())
+                .unwrap()
+                .distance_type(distance_type)
+                .limit(20)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let distances = results
+                .iter()
+                .flat_map(|b| b[DISTANCE].as_primitive::<Float32Type>().values().to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(distances.len(), 20);
+            assert!(
+                distances.windows(2).all(|w| w[0] <= w[1]),
+                "{:?}",
+                distances
+            );
+            for ((id, expected), actual) in expected.iter().zip(&distances) {
+                assert!(
+                    (expected - actual).abs() < 1e-4,
+                    "{:?} distance of row {}: expected {} got {}",
+                    distance_type,
+                    id,
+                    expected,
+                    actual
+                );
+            }
+            // Ties aside, the same rows are returned in the same order
+            assert_eq!(ids(&results)[0], expected[0].0);
+        }
+
+        let err = table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .distance_type(DistanceType::Hamming)
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_distance_type_with_index() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        table
+            .create_index(
+                &["vector"],
+                crate::index::Index::IvfPq(
+                    crate::index::vector::IvfPqIndexBuilder::default()
+                        .distance_type(DistanceType::Cosine)
+                        .num_partitions(2),
+                ),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let query = [0.5_f32; 4];
+
+        // The index's distance type is used by default
+        let results = table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .flat_map(|b| b[DISTANCE].as_primitive::<Float32Type>().values().to_vec())
+            .all(|d| (-1e-4..=2.0).contains(&d)));
+
+        table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .distance_type(DistanceType::Cosine)
+            .execute()
+            .await
+            .unwrap();
+
+        let err = table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .distance_type(DistanceType::Dot)
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("cosine")),
+            "{:?}",
+            err
+        );
+
+        // A batch of searches uses it for each of them
+        let other = [0.1_f32; 4];
+        let results = table
+            .query()
+            .nearest_to_many([query.as_slice(), other.as_slice()])
+            .unwrap()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .flat_map(|b| b[DISTANCE].as_primitive::<Float32Type>().values().to_vec())
+            .all(|d| (-1e-4..=2.0).contains(&d)));
+        let err = table
+            .query()
+            .nearest_to_many([query.as_slice(), other.as_slice()])
+            .unwrap()
+            .distance_type(DistanceType::Dot)
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        // An exact search can use any distance type
+        table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .distance_type(DistanceType::Dot)
+            .bypass_vector_index()
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_float16_vectors() {
+        use rand::Rng;
+
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let (num_rows, dim) = (512, 16);
+        let mut rng = rand::thread_rng();
+        let values = (0..num_rows * dim)
+            .map(|_| rng.gen::<f32>())
+            .collect::<Float32Array>();
+        let item = |data_type| Arc::new(ArrowField::new("item", data_type, true));
+        let v32 =
+            FixedSizeListArray::new(item(DataType::Float32), dim as i32, Arc::new(values), None);
+        // The same vectors, stored at half precision
+        let v16 = arrow_cast::cast(
+            &v32,
+            &DataType::FixedSizeList(item(DataType::Float16), dim as i32),
+        )
+        .unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("v32", v32.data_type().clone(), true),
+            ArrowField::new("v16", v16.data_type().clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..num_rows as i32)),
+                Arc::new(v32),
+                v16,
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let queries = (0..10)
+            .map(|_| (0..dim).map(|_| rng.gen::<f32>()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let search = |column: &'static str, query: Vec<f32>, refine: bool| {
+            let table = table.clone();
+            async move {
+                let mut search = table
+                    .query()
+                    .limit(10)
+                    .nearest_to(query)
+                    .unwrap()
+                    .column(column)
+                    .nprobes(2);
+                if refine {
+                    search = search.refine_factor(5);
+                }
+                search
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            }
+        };
+        // The fraction of the nearest f32 vectors that a search of f16 vectors finds
+        let recall = |expected: &[i32], found: &[i32]| {
+            found.iter().filter(|id| expected.contains(id)).count() as f32 / expected.len() as f32
+        };
+
+        let mut exact = Vec::new();
+        let mut flat_recall = 0.0;
+        for query in &queries {
+            let expected = ids(&search("v32", query.clone(), false).await);
+            let results = search("v16", query.clone(), false).await;
+            // f32 query vectors are accepted and the distances are f32
+            for batch in &results {
+                assert_eq!(batch[DISTANCE].data_type(), &DataType::Float32);
+            }
+            flat_recall += recall(&expected, &ids(&results)) / queries.len() as f32;
+            exact.push(expected);
+        }
+        assert!(flat_recall >= 0.9, "recall of f16 vectors: {}", flat_recall);
+
+        table
+            .create_index(
+                &["v16"],
+                crate::index::Index::IvfPq(
+                    crate::index::vector::IvfPqIndexBuilder::default()
+                        .num_partitions(2)
+                        .num_sub_vectors(4),
+                ),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let mut indexed_recall = 0.0;
+        for (query, expected) in queries.iter().zip(&exact) {
+            let results = search("v16", query.clone(), true).await;
+            for batch in &results {
+                assert_eq!(batch[DISTANCE].data_type(), &DataType::Float32);
+            }
+            indexed_recall += recall(expected, &ids(&results)) / queries.len() as f32;
+        }
+        assert!(
+            indexed_recall >= 0.8,
+            "recall of indexed f16 vectors: {}",
+            indexed_recall
+        );
+
+        // f16 query vectors work too, even as arrays
+        let query = queries[0]
+            .iter()
+            .map(|v| f16::from_f32(*v))
+            .collect::<Float16Array>();
+        table
+            .query()
+            .nearest_to(&query as &dyn Array)
+            .unwrap()
+            .column("v32")
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multivector_search() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let rows: Vec<Vec<[f32; 2]>> = vec![
+            vec![[1.0, 0.0], [0.0, 1.0]],
+            vec![[1.0, 0.0]],
+            vec![[0.0, 1.0]],
+            vec![[-1.0, 0.0], [0.0, -1.0]],
+            vec![],
+        ];
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            rows.iter()
+                .flatten()
+                .map(|v| Some(v.iter().map(|x| Some(*x)).collect::<Vec<_>>())),
+            2,
+        );
+        let vector_field = Arc::new(ArrowField::new("item", vectors.data_type().clone(), true));
+        let tokens = ListArray::new(
+            vector_field.clone(),
+            arrow::buffer::OffsetBuffer::from_lengths(rows.iter().map(|r| r.len())),
+            Arc::new(vectors),
+            None,
+        );
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("tokens", DataType::List(vector_field), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..rows.len() as i32)),
+                Arc::new(tokens),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let query = [[1.0_f32, 0.0], [0.0, 1.0]];
+        let search = |vector_query: VectorQuery| async move {
+            vector_query
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+        };
+        // Each query vector is matched with the nearest vector of the row, rows
+        // without vectors are never found
+        let results = search(
+            table
+                .query()
+                .nearest_to_multivector(query.iter().map(|v| v.as_slice()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(ids(&results), vec![0, 1, 2, 3]);
+        let distances = results
+            .iter()
+            .flat_map(|b| b[DISTANCE].as_primitive::<Float32Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(distances, vec![0.0, 1.0, 1.0, 2.0]);
+
+        let results = search(
+            table
+                .query()
+                .only_if("id > 0")
+                .limit(2)
+                .nearest_to_multivector(query.iter().map(|v| v.as_slice()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(ids(&results), vec![1, 2]);
+
+        // A single query vector searches the multivector column too
+        let results = search(table.query().nearest_to(&[0.0_f32, 1.0]).unwrap()).await;
+        assert_eq!(ids(&results), vec![0, 2, 1, 3]);
+
+        let err = table
+            .create_index(&["tokens"], crate::index::Index::Auto)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+
+        // Other vector columns can't be searched with several vectors at once
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let err = table
+            .query()
+            .nearest_to_multivector([[0.1_f32; 4], [0.2; 4]].iter().map(|v| v.as_slice()))
+            .unwrap()
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("is not a multivector column"),
+            "{}",
+            err
+        );
+    }
+
+    fn ids(batches: &[RecordBatch]) -> Vec<i32> {
+        batches
+            .iter()
+            .flat_map(|b| b["id"].as_primitive::<Int32Type>().values().to_vec())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_full_text_search() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_text_table(&tmp_dir).await;
+
+        let results = table
+            .query()
+            .full_text_search(FullTextSearchQuery::new("Quick dog"))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        // Only exact words match, "dogs" is not "dog"
+        let found = ids(&results);
+        assert_eq!(found[0], 2);
+        assert_eq!(found.len(), 3);
+        let scores = results[0][SCORE].as_primitive::<Float32Type>();
+        assert!(scores.values().windows(2).all(|w| w[0] >= w[1]));
+
+        let results = table
+            .query()
+            .only_if("id > 0")
+            .limit(1)
+            .select(Select::columns(&["id"]))
+            .with_row_id()
+            .full_text_search(FullTextSearchQuery::new("quick dog").columns(["text"]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(ids(&results), vec![2]);
+        let schema = results[0].schema();
+        let columns = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(columns, vec!["id", ROW_ID, SCORE]);
+
+        // The statistics cover the whole table, so a filter doesn't change the scores
+        let score = |filter: Option<&'static str>| {
+            let mut query = table
+                .query()
+                .full_text_search(FullTextSearchQuery::new("dog").columns(["text"]));
+            if let Some(filter) = filter {
+                query = query.only_if(filter);
+            }
+            async move {
+                let results = query
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let position = ids(&results).iter().position(|id| *id == 1).unwrap();
+                results[0][SCORE]
+                    .as_primitive::<Float32Type>()
+                    .value(position)
+            }
+        };
+        assert_eq!(score(None).await, score(Some("id = 1")).await);
+
+        // Phrases and operators narrow the matches
+        for (query, expected) in [
+            ("\"quick dog\"", vec![2]),
+            ("quick AND dog", vec![2]),
+            ("lazy AND dog OR fox", vec![1, 0]),
+        ] {
+            let results = table
+                .query()
+                .full_text_search(FullTextSearchQuery::new(query).columns(["text"]))
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let mut found = ids(&results);
+            found.sort_by_key(|id| std::cmp::Reverse(*id));
+            assert_eq!(found, expected, "{}", query);
+        }
+
+        let err = table
+            .query()
+            .full_text_search(FullTextSearchQuery::new("dog").columns(["missing"]))
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("'missing'"), "{}", err);
+
+        let err = table
+            .query()
+            .full_text_search(FullTextSearchQuery::new("dog").columns(["id"]))
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+
+        // Without an index, columns are only searched if the index is bypassed
+        let tmp_dir = tempdir().unwrap();
+        let table = make_unindexed_text_table(&tmp_dir).await;
+        let err = table
+            .query()
+            .full_text_search(FullTextSearchQuery::new("quick dog"))
+            .execute()
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no FTS index"), "{}", err);
+        let results = table
+            .query()
+            .full_text_search(FullTextSearchQuery::new("quick dog").bypass_index())
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(ids(&results)[0], 2);
+        assert_eq!(ids(&results).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_text_table(&tmp_dir).await;
+
+        // With a limit of 3, the vector search finds 4, 3, 2 and the text search 2, 0, 1
+        let query = table
+            .query()
+            .limit(3)
+            .nearest_to(&[4.0, 0.0])
+            .unwrap()
+            .hybrid(FullTextSearchQuery::new("quick dog"));
+        let results = query
+            .clone()
+            .limit(10)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        // The limit is larger than either search, every row is returned once
+        let found = ids(&results);
+        assert_eq!(found.len(), 5);
+        assert_eq!(found[0], 2);
+        let relevance = results[0][RELEVANCE_SCORE].as_primitive::<Float32Type>();
+        assert!(relevance.values().windows(2).all(|w| w[0] >= w[1]));
+        assert!(results[0].schema().field_with_name(ROW_ID).is_err());
+
+        // Rows that only one search found lack the other's score
+        let row = found.iter().position(|id| *id == 4).unwrap();
+        assert!(results[0][SCORE].is_null(row));
+        assert!(results[0][DISTANCE].is_valid(row));
+
+        let results = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(ids(&results).len(), 3);
+        assert_eq!(ids(&results)[0], 2);
+    }
+
+    #[tokio::test]
+    async fn test_sample() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let conn = connect(dataset_path.to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let batches = BatchGenerator::new()
+            .col(Box::new(RandomVector::new().named("vector".to_string())))
+            .col(Box::new(IncrementingInt32::new().named("id".to_string())))
+            .batch(1000);
+        let table = conn
+            .create_table("my_table", Box::new(batches))
+            .execute()
+            .await
+            .unwrap();
+        let sample = |query: Query| async move {
+            let batches = query
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            ids(&batches)
+        };
+
+        let first = sample(table.query().sample(100, Some(42))).await;
+        assert_eq!(first.len(), 100);
+        assert!(first.windows(2).all(|w| w[0] < w[1]));
+        let mean = first.iter().map(|id| *id as f64).sum::<f64>() / first.len() as f64;
+        assert!((mean - 499.5).abs() < 100.0, "mean id {}", mean);
+        assert_eq!(sample(table.query().sample(100, Some(42))).await, first);
+        assert_ne!(sample(table.query().sample(100, Some(43))).await, first);
+        assert_ne!(sample(table.query().sample(100, None)).await, first);
+
+        // The rows are sampled after the filter
+        let filtered = sample(table.query().only_if("id < 500").sample(100, Some(42))).await;
+        assert_eq!(filtered.len(), 100);
+        assert!(filtered.iter().all(|id| *id < 500));
+        let all = sample(table.query().only_if("id < 10").sample(100, Some(42))).await;
+        assert_eq!(all, (0..10).collect::<Vec<_>>());
+
+        let fraction = sample(table.query().sample_fraction(0.1, Some(7))).await;
+        assert!((50..150).contains(&fraction.len()), "{}", fraction.len());
+        assert_eq!(
+            sample(table.query().sample_fraction(0.1, Some(7))).await,
+            fraction
+        );
+        let fraction = sample(
+            table
+                .query()
+                .only_if("id >= 500")
+                .sample_fraction(0.2, Some(7)),
+        )
+        .await;
+        assert!((50..150).contains(&fraction.len()), "{}", fraction.len());
+        assert!(fraction.iter().all(|id| *id >= 500));
+
+        // The limit and offset page through the sample
+        let page = sample(table.query().sample(100, Some(42)).offset(10).limit(20)).await;
+        assert_eq!(page, first[10..30]);
+
+        let batches = table
+            .query()
+            .select(Select::columns(&["id"]))
+            .with_row_id()
+            .sample(5, Some(1))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let schema = batches[0].schema();
+        let columns = schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(columns, vec!["id", "_rowid"]);
+
+        let err = table
+            .query()
+            .sample_fraction(1.5, None)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        let err = table
+            .query()
+            .sample(10, None)
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_into_typed() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Row {
+            id: i32,
+            text: String,
+            vector: Vec<f32>,
+            _distance: Option<f32>,
+        }
+
+        let tmp_dir = tempdir().unwrap();
+        let table = make_text_table(&tmp_dir).await;
+        let rows = table
+            .query()
+            .nearest_to(&[0.75, 0.0])
+            .unwrap()
+            .limit(2)
+            .execute()
+            .await
+            .unwrap()
+            .into_typed::<Row>()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    id: 1,
+                    text: "a lazy dog".to_string(),
+                    vector: vec![1.0, 0.0],
+                    _distance: Some(0.0625),
+                },
+                Row {
+                    id: 0,
+                    text: "the quick brown fox".to_string(),
+                    vector: vec![0.0, 0.0],
+                    _distance: Some(0.5625),
+                },
+            ]
+        );
+
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Missing {
+            id: i32,
+            title: String,
+        }
+        let err = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .into_typed::<Missing>()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
     }
 }
@@ -17,7 +17,10 @@
 //! building client/server applications with LanceDB or as a client for some
 //! other custom LanceDB service.
 
-pub mod client;
-pub mod db;
-pub mod table;
-pub mod util;
+pub(crate) mod client;
+pub(crate) mod db;
+pub(crate) mod table;
+pub(crate) mod util;
+
+pub use client::{ClientConfig, Compression, RetryConfig};
+pub use table::RemoteTable;
@@ -12,26 +12,206 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant},
+};
 
+use arrow_array::RecordBatchReader;
+use rand::Rng;
 use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    RequestBuilder, Response,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER, USER_AGENT,
+    },
+    RequestBuilder, Response, StatusCode,
 };
+use serde::Deserialize;
 
+use super::db::ARROW_STREAM_CONTENT_TYPE;
+use super::util::{batches_to_ipc_body, MAX_BUFFERED_UPLOAD_SIZE};
 use crate::error::{Error, Result};
 
+/// Configuration for the LanceDB Cloud HTTP client
 #[derive(Clone, Debug)]
-pub struct RestfulLanceDbClient {
-    client: reqwest::Client,
-    host: String,
+pub struct ClientConfig {
+    /// How requests that fail with a transient error are retried
+    pub retry_config: RetryConfig,
+    /// The maximum time a request may take, including reading the response
+    ///
+    /// If None then requests never time out.  The default is 30 seconds.  This
+    /// can be overridden for individual queries with
+    /// [`crate::query::QueryExecutionOptions::timeout`].
+    pub timeout: Option<Duration>,
+    /// The maximum time to wait for a connection to be established
+    ///
+    /// If None then only [`Self::timeout`] applies.  This is the default.
+    pub connect_timeout: Option<Duration>,
+    /// The `User-Agent` sent with every request
+    ///
+    /// The default is `LanceDB-Rust-Client/{version}`
+    pub user_agent: String,
+    /// Additional headers sent with every request
+    ///
+    /// These take precedence over any header of the same name set on an individual
+    /// request, but may not replace the headers used for authentication.
+    pub extra_headers: HashMap<String, String>,
+    /// How data uploaded to the server (e.g. when adding data) is compressed
+    ///
+    /// Compression reduces the bandwidth needed for uploads at the cost of some CPU
+    /// time.  The server must support the chosen compression.  The default is no
+    /// compression.
+    pub upload_compression: Option<Compression>,
 }
 
-impl RestfulLanceDbClient {
-    pub fn host(&self) -> &str {
-        &self.host
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            retry_config: RetryConfig::default(),
+            timeout: Some(Duration::from_secs(30)),
+            connect_timeout: None,
+            user_agent: concat!("LanceDB-Rust-Client/", env!("CARGO_PKG_VERSION")).to_string(),
+            extra_headers: HashMap::new(),
+            upload_compression: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Headers the client sets itself, which can't be replaced by [`Self::extra_headers`]
+    const RESERVED_HEADERS: [&'static str; 3] = ["x-api-key", "x-lancedb-database", "host"];
+
+    /// Build the headers attached to every request, validating them
+    fn request_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&self.user_agent).map_err(|_| Error::InvalidInput {
+                message: format!("invalid user agent '{}'", self.user_agent),
+            })?,
+        );
+        for (name, value) in &self.extra_headers {
+            let header_name =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| Error::InvalidInput {
+                    message: format!("invalid header name '{}'", name),
+                })?;
+            if Self::RESERVED_HEADERS.contains(&header_name.as_str()) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the header '{}' is set by the client and cannot be overridden",
+                        name
+                    ),
+                });
+            }
+            let header_value = HeaderValue::from_str(value).map_err(|_| Error::InvalidInput {
+                message: format!("invalid value for header '{}'", name),
+            })?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
+    }
+}
+
+/// A compression algorithm used for uploads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Gzip with the given level, from 0 (none) to 9 (best)
+    Gzip(u32),
+    /// Zstandard with the given level, from 1 to 22.  Zero selects the default level.
+    Zstd(i32),
+}
+
+impl Compression {
+    /// The value of the `Content-Encoding` header for this compression
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Self::Gzip(_) => "gzip",
+            Self::Zstd(_) => "zstd",
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let valid = match self {
+            Self::Gzip(level) => *level <= 9,
+            Self::Zstd(level) => *level == 0 || zstd::compression_level_range().contains(level),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput {
+                message: format!("invalid upload compression level: {:?}", self),
+            })
+        }
+    }
+}
+
+/// How the client retries requests that fail with a transient error
+///
+/// Idempotent requests (describing a table, queries, listing tables, ...) are retried
+/// when the server responds with one of the [`Self::retry_on`] status codes or the
+/// connection fails.  Other requests (inserts, merge inserts, ...) are only retried
+/// when the server cannot have processed them: the connection could not be
+/// established or the server responded with `429 Too Many Requests`.  Requests with
+/// a streaming body are never retried because the body cannot be replayed.
+///
+/// The delay between attempts starts at [`Self::initial_backoff`] and doubles after
+/// every attempt, up to [`Self::max_backoff`].  Delays are jittered to avoid many
+/// clients retrying at the same moment.  If the server sends a `Retry-After` header
+/// then that delay is used instead.
+#[derive(Clone, Debug)]
+pub struct RetryConfig {
+    /// The maximum number of times a request is retried, the default is 3
+    pub max_retries: usize,
+    /// The delay before the first retry, the default is 250ms
+    pub initial_backoff: Duration,
+    /// The maximum delay between retries, the default is 10s
+    pub max_backoff: Duration,
+    /// The status codes that cause an idempotent request to be retried
+    ///
+    /// The default is 429, 500, 502, 503, and 504
+    pub retry_on: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            retry_on: vec![429, 500, 502, 503, 504],
+        }
+    }
+}
+
+/// Sends requests built by a [`RestfulLanceDbClient`]
+///
+/// This exists so that tests can intercept requests without needing a real server.
+pub trait HttpSend: Clone + Send + Sync + std::fmt::Debug + 'static {
+    fn send(&self, req: RequestBuilder) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+/// The default [`HttpSend`] implementation, which sends requests over the network
+#[derive(Clone, Debug)]
+pub struct Sender;
+
+impl HttpSend for Sender {
+    async fn send(&self, req: RequestBuilder) -> reqwest::Result<Response> {
+        req.send().await
     }
+}
+
+#[derive(Clone, Debug)]
+pub struct RestfulLanceDbClient<S: HttpSend = Sender> {
+    client: reqwest::Client,
+    host: String,
+    retry_config: RetryConfig,
+    /// Headers from the [`ClientConfig`] that are applied to every request
+    request_headers: HeaderMap,
+    upload_compression: Option<Compression>,
+    sender: S,
+}
 
+impl RestfulLanceDbClient<Sender> {
     fn default_headers(
         api_key: &str,
         region: &str,
@@ -41,24 +221,23 @@ impl RestfulLanceDbClient {
         let mut headers = HeaderMap::new();
         headers.insert(
             "x-api-key",
-            HeaderValue::from_str(api_key).map_err(|_| Error::Http {
-                message: "non-ascii api key provided".to_string(),
-            })?,
+            HeaderValue::from_str(api_key)
+                .map_err(|_| Error::http("non-ascii api key provided"))?,
         );
         if region == "local" {
             let host = format!("{}.local.api.lancedb.com", db_name);
             headers.insert(
                 "Host",
-                HeaderValue::from_str(&host).map_err(|_| Error::Http {
-                    message: format!("non-ascii database name '{}' provided", db_name),
+                HeaderValue::from_str(&host).map_err(|_| {
+                    Error::http(format!("non-ascii database name '{}' provided", db_name))
                 })?,
             );
         }
         if has_host_override {
             headers.insert(
                 "x-lancedb-database",
-                HeaderValue::from_str(db_name).map_err(|_| Error::Http {
-                    message: format!("non-ascii database name '{}' provided", db_name),
+                HeaderValue::from_str(db_name).map_err(|_| {
+                    Error::http(format!("non-ascii database name '{}' provided", db_name))
                 })?,
             );
         }
@@ -71,29 +250,51 @@ impl RestfulLanceDbClient {
         api_key: &str,
         region: &str,
         host_override: Option<String>,
+        config: ClientConfig,
     ) -> Result<Self> {
+        if let Some(compression) = &config.upload_compression {
+            compression.validate()?;
+        }
         let parsed_url = url::Url::parse(db_url)?;
         debug_assert_eq!(parsed_url.scheme(), "db");
         if !parsed_url.has_host() {
-            return Err(Error::Http {
-                message: format!("Invalid database URL (missing host) '{}'", db_url),
-            });
+            return Err(Error::http(format!(
+                "Invalid database URL (missing host) '{}'",
+                db_url
+            )));
         }
         let db_name = parsed_url.host_str().unwrap();
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .default_headers(Self::default_headers(
-                api_key,
-                region,
-                db_name,
-                host_override.is_some(),
-            )?)
-            .build()?;
+        let mut client = reqwest::Client::builder().default_headers(Self::default_headers(
+            api_key,
+            region,
+            db_name,
+            host_override.is_some(),
+        )?);
+        if let Some(timeout) = config.timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(connect_timeout) = config.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
+        let client = client.build()?;
         let host = match host_override {
             Some(host_override) => host_override,
             None => format!("https://{}.{}.api.lancedb.com", db_name, region),
         };
-        Ok(Self { client, host })
+        Ok(Self {
+            client,
+            host,
+            request_headers: config.request_headers()?,
+            upload_compression: config.upload_compression,
+            retry_config: config.retry_config,
+            sender: Sender,
+        })
+    }
+}
+
+impl<S: HttpSend> RestfulLanceDbClient<S> {
+    pub fn host(&self) -> &str {
+        &self.host
     }
 
     pub fn get(&self, uri: &str) -> RequestBuilder {
@@ -106,23 +307,552 @@ impl RestfulLanceDbClient {
         self.client.post(full_uri)
     }
 
+    /// Attach `data` to a request as an Arrow IPC stream, compressing it if configured
+    pub async fn with_ipc_body(
+        &self,
+        request: RequestBuilder,
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<RequestBuilder> {
+        let body =
+            batches_to_ipc_body(data, MAX_BUFFERED_UPLOAD_SIZE, self.upload_compression).await?;
+        let mut request = request
+            .header(CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)
+            .body(body);
+        if let Some(compression) = &self.upload_compression {
+            request = request.header(CONTENT_ENCODING, compression.content_encoding());
+        }
+        Ok(request)
+    }
+
+    /// Send a request that is not safe to repeat if the server may have processed it
+    pub async fn send(&self, req: RequestBuilder) -> Result<Response> {
+        self.send_with_retry(req, false).await
+    }
+
+    /// Send a request that can safely be repeated
+    pub async fn send_idempotent(&self, req: RequestBuilder) -> Result<Response> {
+        self.send_with_retry(req, true).await
+    }
+
+    fn should_retry(&self, result: &reqwest::Result<Response>, idempotent: bool) -> bool {
+        match result {
+            // Retrying would most likely time out again
+            Err(err) if err.is_timeout() => false,
+            Ok(response) if idempotent => self
+                .retry_config
+                .retry_on
+                .contains(&response.status().as_u16()),
+            Ok(response) => response.status() == StatusCode::TOO_MANY_REQUESTS,
+            Err(err) if idempotent => err.is_connect() || err.is_request() || err.is_body(),
+            Err(err) => err.is_connect(),
+        }
+    }
+
+    fn retry_after(response: &Response) -> Option<Duration> {
+        let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+        seconds.trim().parse::<u64>().ok().map(Duration::from_secs)
+    }
+
+    async fn send_with_retry(&self, req: RequestBuilder, idempotent: bool) -> Result<Response> {
+        // Applied last so that they replace any header of the same name on the request
+        let req = req.headers(self.request_headers.clone());
+        let mut backoff = self.retry_config.initial_backoff;
+        let mut next_req = Some(req);
+        let mut attempts = 0;
+        loop {
+            let req = next_req
+                .take()
+                .expect("a request is prepared for every attempt");
+            attempts += 1;
+            if attempts <= self.retry_config.max_retries {
+                // Requests with a streaming body can't be cloned and so are never retried
+                next_req = req.try_clone();
+            }
+
+            let attempt_start = Instant::now();
+            let result = self.sender.send(req).await;
+            if let Err(err) = &result {
+                if err.is_timeout() {
+                    return Err(Error::Timeout {
+                        message: format!("request timed out: {}", err),
+                        elapsed: attempt_start.elapsed(),
+                    });
+                }
+            }
+            if !self.should_retry(&result, idempotent) {
+                return Ok(result?);
+            }
+            if next_req.is_none() {
+                if attempts == 1 {
+                    return Ok(result?);
+                }
+                let source = match result {
+                    Ok(response) => self
+                        .check_response(response)
+                        .await
+                        .expect_err("retried statuses are errors"),
+                    Err(err) => err.into(),
+                };
+                return Err(Error::Retry {
+                    attempts,
+                    source: Box::new(source),
+                });
+            }
+
+            let retry_after = result.as_ref().ok().and_then(Self::retry_after);
+            let delay = retry_after.unwrap_or_else(|| {
+                // Jitter between half and all of the backoff
+                backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+            });
+            log::debug!(
+                "Request failed on attempt {}, retrying in {:?}",
+                attempts,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            backoff = (backoff * 2).min(self.retry_config.max_backoff);
+        }
+    }
+
     async fn rsp_to_str(response: Response) -> String {
         let status = response.status();
         response.text().await.unwrap_or_else(|_| status.to_string())
     }
 
+    /// Convert an unsuccessful response into an error
+    ///
+    /// Requests the server rejected as invalid become [`Error::InvalidInput`], all
+    /// other failures become [`Error::Http`].  Callers that know more about the
+    /// request (e.g. which table it targets) can map specific statuses further.
     pub async fn check_response(&self, response: Response) -> Result<Response> {
-        let status_int: u16 = u16::from(response.status());
-        if (400..500).contains(&status_int) {
-            Err(Error::InvalidInput {
-                message: Self::rsp_to_str(response).await,
-            })
-        } else if status_int != 200 {
-            Err(Error::Runtime {
-                message: Self::rsp_to_str(response).await,
-            })
-        } else {
-            Ok(response)
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let request_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .map(str::to_string);
+        let body = Self::rsp_to_str(response).await;
+        let (code, message) = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(error) => (error.code, error.message),
+            Err(_) => (None, body),
+        };
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                Err(Error::InvalidInput { message })
+            }
+            StatusCode::UNSUPPORTED_MEDIA_TYPE if self.upload_compression.is_some() => {
+                Err(Error::NotSupported {
+                    message: format!(
+                        "the server rejected the compressed upload, try disabling upload compression: {}",
+                        message
+                    ),
+                })
+            }
+            _ => Err(Error::Http {
+                status: Some(status.as_u16()),
+                code,
+                message,
+                request_id,
+            }),
+        }
+    }
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The JSON body the server sends with an error status
+#[derive(Deserialize)]
+struct ErrorResponse {
+    #[serde(alias = "error", alias = "detail")]
+    message: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[derive(Clone)]
+    pub struct MockSender {
+        f: Arc<dyn Fn(reqwest::Request) -> reqwest::Response + Send + Sync + 'static>,
+    }
+
+    impl std::fmt::Debug for MockSender {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "MockSender")
+        }
+    }
+
+    impl HttpSend for MockSender {
+        async fn send(&self, req: RequestBuilder) -> reqwest::Result<Response> {
+            let request = req.build()?;
+            Ok((self.f)(request))
+        }
+    }
+
+    /// Create a client whose requests are answered by `handler` instead of a server
+    pub fn client_with_handler<T>(
+        handler: impl Fn(reqwest::Request) -> http::response::Response<T> + Send + Sync + 'static,
+    ) -> RestfulLanceDbClient<MockSender>
+    where
+        T: Into<reqwest::Body>,
+    {
+        client_with_handler_and_config(ClientConfig::default(), handler)
+    }
+
+    /// Like [`client_with_handler`] but with a custom configuration
+    pub fn client_with_handler_and_config<T>(
+        config: ClientConfig,
+        handler: impl Fn(reqwest::Request) -> http::response::Response<T> + Send + Sync + 'static,
+    ) -> RestfulLanceDbClient<MockSender>
+    where
+        T: Into<reqwest::Body>,
+    {
+        let wrapper = move |req: reqwest::Request| {
+            let response = handler(req);
+            response.into()
+        };
+
+        RestfulLanceDbClient {
+            client: reqwest::Client::new(),
+            host: "http://localhost".to_string(),
+            request_headers: config.request_headers().unwrap(),
+            upload_compression: config.upload_compression,
+            retry_config: config.retry_config,
+            sender: MockSender {
+                f: Arc::new(wrapper),
+            },
+        }
+    }
+
+    /// Start a local HTTP server that waits for `delay` before responding to each
+    /// request with `body`, and return a real client connected to it
+    pub async fn client_with_slow_server(
+        config: ClientConfig,
+        delay: Duration,
+        body: Vec<u8>,
+    ) -> RestfulLanceDbClient {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let body = body.clone();
+                tokio::spawn(async move {
+                    // The request is small enough to arrive in a single read
+                    let mut buf = vec![0; 64 * 1024];
+                    let _ = socket.read(&mut buf).await;
+                    tokio::time::sleep(delay).await;
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&body).await;
+                });
+            }
+        });
+        RestfulLanceDbClient::try_new(
+            "db://my_db",
+            "api_key",
+            "us-east-1",
+            Some(format!("http://{}", addr)),
+            config,
+        )
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::test_utils::client_with_handler_and_config;
+    use super::*;
+
+    fn fast_retries() -> ClientConfig {
+        ClientConfig {
+            retry_config: RetryConfig {
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// A client that responds with each of `statuses` in turn and counts the attempts
+    fn client_with_statuses(
+        statuses: Vec<u16>,
+    ) -> (
+        RestfulLanceDbClient<test_utils::MockSender>,
+        Arc<Mutex<usize>>,
+    ) {
+        let attempts = Arc::new(Mutex::new(0));
+        let attempts_clone = attempts.clone();
+        let client = client_with_handler_and_config(fast_retries(), move |_| {
+            let mut attempts = attempts_clone.lock().unwrap();
+            let status = statuses[(*attempts).min(statuses.len() - 1)];
+            *attempts += 1;
+            http::Response::builder()
+                .status(status)
+                .body(format!("status {}", status))
+                .unwrap()
+        });
+        (client, attempts)
+    }
+
+    #[tokio::test]
+    async fn test_retry_idempotent() {
+        let (client, attempts) = client_with_statuses(vec![503, 502, 200]);
+        let response = client.send_idempotent(client.get("/")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(*attempts.lock().unwrap(), 3);
+
+        // Non-retryable statuses are returned immediately
+        let (client, attempts) = client_with_statuses(vec![400]);
+        let response = client.send_idempotent(client.get("/")).await.unwrap();
+        assert_eq!(response.status(), 400);
+        assert_eq!(*attempts.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_non_idempotent() {
+        // The server may have processed the request so it is not retried
+        let (client, attempts) = client_with_statuses(vec![503, 200]);
+        let response = client.send(client.post("/").body("data")).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(*attempts.lock().unwrap(), 1);
+
+        // A rate limited request was not processed and can be replayed
+        let (client, attempts) = client_with_statuses(vec![429, 200]);
+        let response = client.send(client.post("/").body("data")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(*attempts.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted() {
+        let (client, attempts) = client_with_statuses(vec![503]);
+        let err = client.send_idempotent(client.get("/")).await.unwrap_err();
+        assert_eq!(*attempts.lock().unwrap(), 4);
+        match err {
+            Error::Retry { attempts, source } => {
+                assert_eq!(attempts, 4);
+                assert!(matches!(
+                    source.as_ref(),
+                    Error::Http { status: Some(503), message, .. } if message == "status 503"
+                ));
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let attempts_clone = attempts.clone();
+        let client = client_with_handler_and_config(fast_retries(), move |_| {
+            let mut attempts = attempts_clone.lock().unwrap();
+            attempts.push(std::time::Instant::now());
+            if attempts.len() == 1 {
+                http::Response::builder()
+                    .status(429)
+                    .header(RETRY_AFTER, "1")
+                    .body(String::new())
+                    .unwrap()
+            } else {
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+        });
+        client.send_idempotent(client.get("/")).await.unwrap();
+        let attempts = attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts[1] - attempts[0] >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_retry_connection_error() {
+        // Nothing listens on port 1, so the connection is refused
+        let client = RestfulLanceDbClient::try_new(
+            "db://my_db",
+            "api_key",
+            "us-east-1",
+            Some("http://127.0.0.1:1".to_string()),
+            fast_retries(),
+        )
+        .unwrap();
+        let err = client.send(client.post("/")).await.unwrap_err();
+        match err {
+            Error::Retry { attempts, source } => {
+                assert_eq!(attempts, 4);
+                assert!(matches!(source.as_ref(), Error::Http { status: None, .. }));
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_response() {
+        let client =
+            client_with_handler_and_config(fast_retries(), |request| match request.url().path() {
+                "/invalid" => http::Response::builder()
+                    .status(400)
+                    .body(r#"{"message": "bad filter"}"#.to_string())
+                    .unwrap(),
+                "/unauthorized" => http::Response::builder()
+                    .status(401)
+                    .header(REQUEST_ID_HEADER, "abc123")
+                    .body(r#"{"error": "invalid api key", "code": "unauthenticated"}"#.to_string())
+                    .unwrap(),
+                _ => http::Response::builder()
+                    .status(500)
+                    .body("not json".to_string())
+                    .unwrap(),
+            });
+        let check = |path: &'static str| {
+            let client = client.clone();
+            async move {
+                let response = client.send(client.get(path)).await.unwrap();
+                client.check_response(response).await.unwrap_err()
+            }
+        };
+
+        let err = check("/invalid").await;
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message == "bad filter"),
+            "{:?}",
+            err
+        );
+
+        let err = check("/unauthorized").await;
+        match &err {
+            Error::Http {
+                status,
+                code,
+                message,
+                request_id,
+            } => {
+                assert_eq!(*status, Some(401));
+                assert_eq!(code.as_deref(), Some("unauthenticated"));
+                assert_eq!(message, "invalid api key");
+                assert_eq!(request_id.as_deref(), Some("abc123"));
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(
+            err.to_string(),
+            "Http error (status 401): invalid api key (request id: abc123)"
+        );
+
+        let err = check("/internal").await;
+        assert!(
+            matches!(&err, Error::Http { status: Some(500), code: None, message, .. } if message == "not json"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let config = ClientConfig {
+            timeout: Some(Duration::from_millis(200)),
+            ..fast_retries()
+        };
+        let client =
+            test_utils::client_with_slow_server(config, Duration::from_secs(2), vec![]).await;
+        let start = Instant::now();
+        let err = client
+            .send_idempotent(client.get("/v1/table/"))
+            .await
+            .unwrap_err();
+        // Timeouts are not retried
+        assert!(start.elapsed() < Duration::from_secs(2));
+        match err {
+            Error::Timeout { elapsed, .. } => assert!(elapsed >= Duration::from_millis(200)),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        // A per-request timeout overrides the client's
+        let response = client
+            .send_idempotent(client.get("/v1/table/").timeout(Duration::from_secs(10)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_request_headers() {
+        let config = ClientConfig {
+            user_agent: "my-service/1.0".to_string(),
+            extra_headers: HashMap::from([("x-tenant".to_string(), "tenant-a".to_string())]),
+            ..Default::default()
+        };
+        let client = client_with_handler_and_config(config, |request| {
+            let headers = request.headers();
+            assert_eq!(headers[USER_AGENT], "my-service/1.0");
+            assert_eq!(headers["x-tenant"], "tenant-a");
+            assert_eq!(headers.get_all("x-tenant").iter().count(), 1);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        client.send_idempotent(client.get("/")).await.unwrap();
+        // Headers from the config can't be replaced by a request
+        let request = client.post("/").header("x-tenant", "tenant-b");
+        client.send(request).await.unwrap();
+    }
+
+    #[test]
+    fn test_invalid_request_headers() {
+        let try_headers = |name: &str, value: &str| {
+            let config = ClientConfig {
+                extra_headers: HashMap::from([(name.to_string(), value.to_string())]),
+                ..Default::default()
+            };
+            RestfulLanceDbClient::try_new("db://my_db", "api_key", "us-east-1", None, config)
+        };
+        assert!(try_headers("x-tenant", "tenant-a").is_ok());
+        for (name, value) in [
+            ("bad header", "a"),
+            ("x-tenant", "bad\nvalue"),
+            ("X-Api-Key", "a"),
+        ] {
+            let err = try_headers(name, value).unwrap_err();
+            assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        }
+
+        let config = ClientConfig {
+            user_agent: "bad\nagent".to_string(),
+            ..Default::default()
+        };
+        let err = RestfulLanceDbClient::try_new("db://my_db", "api_key", "us-east-1", None, config)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_invalid_upload_compression() {
+        for compression in [Compression::Gzip(10), Compression::Zstd(100)] {
+            let config = ClientConfig {
+                upload_compression: Some(compression),
+                ..Default::default()
+            };
+            let err =
+                RestfulLanceDbClient::try_new("db://my_db", "api_key", "us-east-1", None, config)
+                    .unwrap_err();
+            assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
         }
     }
 }
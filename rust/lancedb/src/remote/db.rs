@@ -16,31 +16,34 @@ use std::sync::Arc;
 
 use arrow_array::RecordBatchReader;
 use async_trait::async_trait;
-use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
 use serde::Deserialize;
-use tokio::task::spawn_blocking;
 
 use crate::connection::{
-    ConnectionInternal, CreateTableBuilder, NoData, OpenTableBuilder, TableNamesBuilder,
+    check_existing_schema, ConnectionInternal, CreateTableBuilder, CreateTableMode, NoData,
+    OpenTableBuilder, TableNamesBuilder,
 };
 use crate::embeddings::EmbeddingRegistry;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::Table;
 
-use super::client::RestfulLanceDbClient;
+use super::client::{ClientConfig, HttpSend, RestfulLanceDbClient, Sender};
 use super::table::RemoteTable;
-use super::util::batches_to_ipc_bytes;
 
-const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+pub(super) const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
 
 #[derive(Deserialize)]
 struct ListTablesResponse {
     tables: Vec<String>,
+    /// Set if there are more tables, to be passed to the request for the next page
+    #[serde(default)]
+    page_token: Option<String>,
 }
 
 #[derive(Debug)]
-pub struct RemoteDatabase {
-    client: RestfulLanceDbClient,
+pub struct RemoteDatabase<S: HttpSend = Sender> {
+    client: RestfulLanceDbClient<S>,
+    embedding_registry: Arc<dyn EmbeddingRegistry>,
 }
 
 impl RemoteDatabase {
@@ -49,31 +52,89 @@ impl RemoteDatabase {
         api_key: &str,
         region: &str,
         host_override: Option<String>,
+        client_config: ClientConfig,
+        embedding_registry: Arc<dyn EmbeddingRegistry>,
     ) -> Result<Self> {
-        let client = RestfulLanceDbClient::try_new(uri, api_key, region, host_override)?;
-        Ok(Self { client })
+        let client =
+            RestfulLanceDbClient::try_new(uri, api_key, region, host_override, client_config)?;
+        Ok(Self {
+            client,
+            embedding_registry,
+        })
     }
 }
 
-impl std::fmt::Display for RemoteDatabase {
+impl<S: HttpSend> RemoteDatabase<S> {
+    #[cfg(test)]
+    fn new_mock(client: RestfulLanceDbClient<S>) -> Self {
+        Self {
+            client,
+            embedding_registry: crate::embeddings::default_registry(),
+        }
+    }
+}
+
+impl<S: HttpSend> std::fmt::Display for RemoteDatabase<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "RemoteDatabase(host={})", self.client.host())
     }
 }
 
 #[async_trait]
-impl ConnectionInternal for RemoteDatabase {
+impl<S: HttpSend> ConnectionInternal for RemoteDatabase<S> {
     async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>> {
-        let mut req = self.client.get("/v1/table/");
-        if let Some(limit) = options.limit {
-            req = req.query(&[("limit", limit)]);
-        }
-        if let Some(start_after) = options.start_after {
-            req = req.query(&[("page_token", start_after)]);
+        let limit = options.limit.map(|limit| limit as usize);
+        let mut names = Vec::new();
+        let mut page_token = options.start_after.clone();
+        // The last name that was listed, pages that overlap are skipped over
+        let mut last = options.start_after;
+        loop {
+            let mut req = self.client.get("/v1/table/");
+            // Names are filtered by prefix here, so the limit only applies to the
+            // pages the server returns if there is no prefix
+            if let (Some(limit), None) = (limit, &options.prefix) {
+                req = req.query(&[("limit", limit - names.len())]);
+            }
+            if let Some(page_token) = &page_token {
+                req = req.query(&[("page_token", page_token)]);
+            }
+            let rsp = self.client.send_idempotent(req).await?;
+            let rsp = self.client.check_response(rsp).await?;
+            let page = rsp.json::<ListTablesResponse>().await?;
+
+            let past_prefix = match (&options.prefix, page.tables.last()) {
+                (Some(prefix), Some(name)) => {
+                    name.as_str() > prefix.as_str() && !name.starts_with(prefix)
+                }
+                _ => false,
+            };
+            let page_is_empty = page.tables.is_empty();
+            for name in page.tables {
+                if last.as_ref().is_some_and(|last| &name <= last) {
+                    continue;
+                }
+                last = Some(name.clone());
+                if options
+                    .prefix
+                    .as_ref()
+                    .map_or(true, |prefix| name.starts_with(prefix))
+                {
+                    names.push(name);
+                }
+            }
+
+            if let Some(limit) = limit {
+                if names.len() >= limit {
+                    names.truncate(limit);
+                    break;
+                }
+            }
+            match page.page_token {
+                Some(token) if !page_is_empty && !past_prefix => page_token = Some(token),
+                _ => break,
+            }
         }
-        let rsp = req.send().await?;
-        let rsp = self.client.check_response(rsp).await?;
-        Ok(rsp.json::<ListTablesResponse>().await?.tables)
+        Ok(names)
     }
 
     async fn do_create_table(
@@ -81,36 +142,76 @@ impl ConnectionInternal for RemoteDatabase {
         options: CreateTableBuilder<false, NoData>,
         data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<Table> {
-        // TODO: https://github.com/lancedb/lancedb/issues/1026
-        // We should accept data from an async source.  In the meantime, spawn this as blocking
-        // to make sure we don't block the tokio runtime if the source is slow.
-        let data_buffer = spawn_blocking(move || batches_to_ipc_bytes(data))
-            .await
-            .unwrap()?;
-
-        let rsp = self
+        let mode = match &options.mode {
+            CreateTableMode::Create => "create",
+            CreateTableMode::ExistOk(_) => "exist_ok",
+            CreateTableMode::Overwrite => "overwrite",
+        };
+        let schema = data.schema();
+        let req = self
             .client
             .post(&format!("/v1/table/{}/create/", options.name))
-            .body(data_buffer)
-            .header(CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)
+            .query(&[("mode", mode)])
             // This is currently expected by LanceDb cloud but will be removed soon.
-            .header("x-request-id", "na")
-            .send()
-            .await?;
-        self.client.check_response(rsp).await?;
+            .header("x-request-id", "na");
+        let req = self.client.with_ipc_body(req, data).await?;
+        let rsp = self.client.send(req).await?;
+        let exist_ok = matches!(options.mode, CreateTableMode::ExistOk(_));
+        if rsp.status() == StatusCode::CONFLICT {
+            // Servers that don't know the mode report the existing table as a conflict
+            if !exist_ok {
+                return Err(Error::TableAlreadyExists { name: options.name });
+            }
+        } else {
+            self.client.check_response(rsp).await?;
+        }
 
-        Ok(Table::new(Arc::new(RemoteTable::new(
-            self.client.clone(),
-            options.name,
-        ))))
+        let table = Table::new_with_embedding_registry(
+            Arc::new(RemoteTable::new(self.client.clone(), options.name.clone())),
+            self.embedding_registry.clone(),
+        );
+        if exist_ok {
+            // The response doesn't say whether the table existed, so the schema is
+            // always checked
+            check_existing_schema(&options.name, &schema, &table.schema().await?)?;
+        }
+        Ok(table)
     }
 
     async fn do_open_table(&self, _options: OpenTableBuilder) -> Result<Table> {
         todo!()
     }
 
-    async fn drop_table(&self, _name: &str) -> Result<()> {
-        todo!()
+    async fn drop_table(&self, name: &str) -> Result<()> {
+        let req = self.client.post(&format!("/v1/table/{}/drop/", name));
+        let rsp = self.client.send(req).await?;
+        if rsp.status() == StatusCode::NOT_FOUND {
+            return Err(Error::TableNotFound {
+                name: name.to_owned(),
+            });
+        }
+        self.client.check_response(rsp).await?;
+        Ok(())
+    }
+
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let req = self
+            .client
+            .post(&format!("/v1/table/{}/rename/", old_name))
+            .json(&serde_json::json!({ "new_table_name": new_name }));
+        let rsp = self.client.send(req).await?;
+        match rsp.status() {
+            StatusCode::NOT_FOUND => Err(Error::TableNotFound {
+                name: old_name.to_owned(),
+            }),
+            StatusCode::CONFLICT => Err(Error::TableAlreadyExists {
+                name: new_name.to_owned(),
+            }),
+            _ => {
+                self.client.check_response(rsp).await?;
+                Ok(())
+            }
+        }
     }
 
     async fn drop_db(&self) -> Result<()> {
@@ -118,6 +219,188 @@ impl ConnectionInternal for RemoteDatabase {
     }
 
     fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
-        todo!()
+        self.embedding_registry.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::{DataType, Field, Schema};
+    use lance::arrow::json::JsonSchema;
+
+    use crate::remote::client::test_utils::client_with_handler;
+    use crate::Connection;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_table_modes() {
+        let existing = Schema::new(vec![Field::new("a", DataType::Int32, false)]);
+        let json_schema = JsonSchema::try_from(&existing).unwrap();
+        let client = client_with_handler(move |request| {
+            let mode = request
+                .url()
+                .query_pairs()
+                .find(|(key, _)| key == "mode")
+                .map(|(_, value)| value.to_string());
+            match (request.url().path(), mode.as_deref()) {
+                ("/v1/table/existing/create/", Some("create")) => {
+                    http::Response::builder().status(409).body(String::new())
+                }
+                ("/v1/table/existing/create/", Some("exist_ok" | "overwrite")) => {
+                    http::Response::builder().status(200).body(String::new())
+                }
+                ("/v1/table/existing/describe/", None) => {
+                    let body = serde_json::json!({ "version": 1, "schema": json_schema });
+                    http::Response::builder().status(200).body(body.to_string())
+                }
+                (path, mode) => panic!("Unexpected request: {} mode={:?}", path, mode),
+            }
+            .unwrap()
+        });
+        let conn = Connection::new("db://test", Arc::new(RemoteDatabase::new_mock(client)));
+
+        let schema = Arc::new(existing.clone());
+        assert!(matches!(
+            conn.create_empty_table("existing", schema.clone()).execute().await,
+            Err(Error::TableAlreadyExists { name }) if name == "existing"
+        ));
+        conn.create_empty_table("existing", schema.clone())
+            .mode(CreateTableMode::exist_ok(|builder| builder))
+            .execute()
+            .await
+            .unwrap();
+        conn.create_empty_table("existing", schema)
+            .mode(CreateTableMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+
+        let other = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let err = conn
+            .create_empty_table("existing", other)
+            .mode(CreateTableMode::exist_ok(|builder| builder))
+            .execute()
+            .await
+            .unwrap_err();
+        let Error::Schema { message } = err else {
+            panic!("Expected a schema error, got {:?}", err);
+        };
+        assert!(message.contains("'a'"), "{}", message);
+        assert!(message.contains("'b'"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_table_names_pages() {
+        let client = client_with_handler(|request| {
+            assert_eq!(request.method(), "GET");
+            assert_eq!(request.url().path(), "/v1/table/");
+            let page_token = request
+                .url()
+                .query_pairs()
+                .find(|(key, _)| key == "page_token")
+                .map(|(_, value)| value.to_string());
+            // The second page repeats the last name of the first page
+            let body = match page_token.as_deref() {
+                None => r#"{"tables": ["a", "b"], "page_token": "b"}"#,
+                Some("b") => r#"{"tables": ["b", "c", "d"], "page_token": "d"}"#,
+                Some("d") => r#"{"tables": ["da", "e"]}"#,
+                Some(token) => panic!("Unexpected page token: {}", token),
+            };
+            http::Response::builder().status(200).body(body).unwrap()
+        });
+        let conn = Arc::new(RemoteDatabase::new_mock(client));
+        let names = || TableNamesBuilder::new(conn.clone());
+
+        assert_eq!(
+            conn.table_names(names()).await.unwrap(),
+            vec!["a", "b", "c", "d", "da", "e"]
+        );
+        assert_eq!(
+            conn.table_names(names().limit(3)).await.unwrap(),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(
+            conn.table_names(names().start_after("b")).await.unwrap(),
+            vec!["c", "d", "da", "e"]
+        );
+        assert_eq!(
+            conn.table_names(names().prefix("d")).await.unwrap(),
+            vec!["d", "da"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drop_tables() {
+        let dropped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let dropped_clone = dropped.clone();
+        let client = client_with_handler(move |request| {
+            let path = request.url().path();
+            if path == "/v1/table/" {
+                let page_token = request
+                    .url()
+                    .query_pairs()
+                    .find(|(key, _)| key == "page_token")
+                    .map(|(_, value)| value.to_string());
+                let body = match page_token.as_deref() {
+                    None => r#"{"tables": ["a", "b"], "page_token": "b"}"#,
+                    Some("b") => r#"{"tables": ["c"]}"#,
+                    Some(token) => panic!("Unexpected page token: {}", token),
+                };
+                return http::Response::builder().status(200).body(body).unwrap();
+            }
+            let name = path
+                .strip_prefix("/v1/table/")
+                .and_then(|path| path.strip_suffix("/drop/"))
+                .unwrap_or_else(|| panic!("Unexpected path: {}", path));
+            assert_eq!(request.method(), "POST");
+            // "b" is dropped by someone else in the meantime
+            let status = if name == "b" || name == "missing" {
+                404
+            } else {
+                dropped_clone.lock().unwrap().push(name.to_string());
+                200
+            };
+            http::Response::builder().status(status).body("").unwrap()
+        });
+        let conn = Connection::new("db://test", Arc::new(RemoteDatabase::new_mock(client)));
+
+        assert!(matches!(
+            conn.drop_table("missing").await,
+            Err(Error::TableNotFound { name }) if name == "missing"
+        ));
+        assert!(!conn.drop_table_if_exists("missing").await.unwrap());
+        conn.drop_all_tables().await.unwrap();
+        assert_eq!(*dropped.lock().unwrap(), vec!["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_rename_table() {
+        let client = client_with_handler(|request| {
+            assert_eq!(request.method(), "POST");
+            let body = request.body().unwrap().as_bytes().unwrap();
+            let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+            let status = match (request.url().path(), body["new_table_name"].as_str()) {
+                ("/v1/table/old/rename/", Some("new")) => 200,
+                ("/v1/table/old/rename/", Some("taken")) => 409,
+                ("/v1/table/missing/rename/", _) => 404,
+                (path, _) => panic!("Unexpected request: {} {}", path, body),
+            };
+            http::Response::builder().status(status).body("").unwrap()
+        });
+        let conn = RemoteDatabase::new_mock(client);
+
+        conn.rename_table("old", "new").await.unwrap();
+        assert!(matches!(
+            conn.rename_table("old", "taken").await,
+            Err(Error::TableAlreadyExists { name }) if name == "taken"
+        ));
+        assert!(matches!(
+            conn.rename_table("missing", "new").await,
+            Err(Error::TableNotFound { name }) if name == "missing"
+        ));
     }
 }
@@ -1,129 +1,3282 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-use arrow_array::RecordBatchReader;
-use arrow_schema::SchemaRef;
+use arrow_array::{cast::AsArray, types::Float32Type, RecordBatch, RecordBatchReader};
+use arrow_schema::{DataType, Schema, SchemaRef};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use datafusion_physical_plan::ExecutionPlan;
-use lance::dataset::{scanner::DatasetRecordBatchStream, ColumnAlteration, NewColumnTransform};
+use datafusion_physical_plan::SendableRecordBatchStream;
+use lance::arrow::json::JsonSchema;
+use lance::dataset::{
+    cleanup::RemovalStats,
+    optimize::{CompactionMetrics, CompactionOptions},
+    scanner::DatasetRecordBatchStream,
+    ColumnAlteration, NewColumnTransform,
+};
+use lance_datafusion::exec::OneShotExec;
+use lance_index::optimize::OptimizeOptions;
+use reqwest::Response;
+use serde::{de::DeserializeOwned, de::IgnoredAny, Deserialize};
 
 use crate::{
     connection::NoData,
-    error::Result,
-    index::{IndexBuilder, IndexConfig},
-    query::{Query, QueryExecutionOptions, VectorQuery},
+    error::{Error, Result},
+    index::{
+        wait_for_index, Index, IndexBuilder, IndexConfig, IndexStatistics, IndexStats, IndexType,
+        Poller, VectorIndexParams,
+    },
+    query::{AggregateQuery, Query, QueryExecutionOptions, Select, VectorQuery, DEFAULT_TOP_K},
     table::{
-        merge::MergeInsertBuilder, AddDataBuilder, NativeTable, OptimizeAction, OptimizeStats,
-        TableDefinition, TableInternal, UpdateBuilder,
+        merge::{MergeInsertBuilder, MergeResult},
+        take, AddDataBuilder, AddDataMode, ApproxCount, ChangeOperation, CompactedFragments,
+        CompactionProgressCallback, DeleteRowsStats, FragmentStatistics, MissingRows, NativeTable,
+        OptimizeAction, OptimizeStats, TableDefinition, TableInternal, TableStatistics,
+        UpdateBuilder, UpdateResult, ValidationReport, Validator, VersionInfo,
     },
+    utils::resolve_vector_column,
+    DistanceType,
 };
 
-use super::client::RestfulLanceDbClient;
+use super::client::{HttpSend, RestfulLanceDbClient, Sender};
+use super::util::{data_type_to_json, ipc_response_to_stream};
+
+/// The number of row ids sent in each request of [`Table::delete_rows`], so that the
+/// predicates stay well below the request size limits of the server
+///
+/// [`Table::delete_rows`]: crate::Table::delete_rows
+const DELETE_ROWS_BATCH_SIZE: usize = 1000;
+
+#[derive(Deserialize)]
+struct TableDescription {
+    version: u64,
+    schema: JsonSchema,
+    /// Only returned by servers that report table statistics
+    #[serde(default)]
+    stats: Option<TableStatsDescription>,
+}
+
+#[derive(Deserialize)]
+struct TableStatsDescription {
+    total_bytes: u64,
+    num_rows: usize,
+    num_deleted_rows: usize,
+    fragment_stats: FragmentStatistics,
+}
+
+#[derive(Deserialize)]
+struct ListVersionsResponse {
+    versions: Vec<VersionDescription>,
+}
+
+#[derive(Deserialize)]
+struct VersionDescription {
+    version: u64,
+    timestamp: String,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ListTagsResponse {
+    tags: HashMap<String, TagDescription>,
+}
+
+#[derive(Deserialize)]
+struct TagDescription {
+    version: u64,
+}
+
+#[derive(Deserialize)]
+struct ListIndicesResponse {
+    indexes: Vec<IndexDescription>,
+}
+
+#[derive(Deserialize)]
+struct IndexDescription {
+    index_name: String,
+    columns: Vec<String>,
+    index_type: String,
+    #[serde(default)]
+    metric_type: Option<String>,
+    #[serde(default)]
+    num_partitions: Option<u32>,
+    #[serde(default)]
+    num_sub_vectors: Option<u32>,
+    #[serde(default)]
+    m: Option<u32>,
+    #[serde(default)]
+    ef_construction: Option<u32>,
+}
+
+/// Servers that can't estimate counts ignore the `approx` flag and count exactly
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApproxCountResponse {
+    Exact(usize),
+    Approx {
+        estimate: usize,
+        lower: usize,
+        upper: usize,
+    },
+}
+
+/// The rows affected by a write, older servers don't report them
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RowCountsResponse {
+    num_inserted_rows: Option<u64>,
+    num_updated_rows: Option<u64>,
+    num_deleted_rows: Option<u64>,
+    /// The version that was written, only reported for merge inserts
+    version: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CompactionResponse {
+    fragments_removed: usize,
+    fragments_added: usize,
+    files_removed: usize,
+    files_added: usize,
+    #[serde(default)]
+    removed_fragment_ids: Option<Vec<u64>>,
+    #[serde(default)]
+    added_fragment_ids: Option<Vec<u64>>,
+}
 
+#[derive(Deserialize)]
+struct CleanupResponse {
+    bytes_removed: u64,
+    old_versions: u64,
+}
+
+/// The reply of a server that runs an optimization in the background
+#[derive(Deserialize)]
+struct OptimizeJob {
+    job_id: String,
+}
+
+/// The state of an optimization that runs in the background, whose result is what
+/// the server replies when it runs the operation right away
+#[derive(Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum OptimizeJobStatus<T> {
+    Running,
+    Succeeded { result: T },
+    Failed { error: String },
+}
+
+/// A table stored by a LanceDB Cloud server, see [`crate::Table::as_remote`]
+///
+/// Every operation is a request to the server, through the methods of
+/// [`crate::Table`].
 #[derive(Debug)]
-pub struct RemoteTable {
-    #[allow(dead_code)]
-    client: RestfulLanceDbClient,
+pub struct RemoteTable<S: HttpSend = Sender> {
+    client: RestfulLanceDbClient<S>,
     name: String,
+    /// The version pinned by [`TableInternal::checkout`], if any.  Reads are sent
+    /// with this version and writes are rejected while it is set.
+    version: RwLock<Option<u64>>,
+    /// The schema of the latest version, cached after the first describe or
+    /// schema change.  Cleared whenever the schema may have changed.
+    schema: RwLock<Option<SchemaRef>>,
 }
 
-impl RemoteTable {
-    pub fn new(client: RestfulLanceDbClient, name: String) -> Self {
-        Self { client, name }
+impl<S: HttpSend> RemoteTable<S> {
+    pub(crate) fn new(client: RestfulLanceDbClient<S>, name: String) -> Self {
+        Self {
+            client,
+            name,
+            version: RwLock::new(None),
+            schema: RwLock::new(None),
+        }
+    }
+
+    /// Send one of the optimize operations and parse the reply of the server
+    ///
+    /// Servers that do not expose an operation respond with 405 or 501, which is
+    /// reported as [`Error::NotSupported`].  Servers may also accept the operation
+    /// with `202 Accepted` and the id of a job that runs it in the background.  Then
+    /// this returns None, unless `wait_timeout` is set, in which case the job is
+    /// polled until it finishes or the timeout passes.
+    async fn send_optimize<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        body: serde_json::Value,
+        wait_timeout: Option<Duration>,
+    ) -> Result<Option<T>> {
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/{}/", self.name, operation))
+            .json(&body);
+        let response = self.client.send(request).await?;
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::METHOD_NOT_ALLOWED | reqwest::StatusCode::NOT_IMPLEMENTED
+        ) {
+            return Err(Error::NotSupported {
+                message: format!("{} is not supported by this LanceDB server", operation),
+            });
+        }
+        let response = self.check_table_response(response).await?;
+        if response.status() != reqwest::StatusCode::ACCEPTED {
+            let body = response.text().await?;
+            // Operations without statistics reply with an empty body
+            let body = if body.is_empty() { "null" } else { &body };
+            return serde_json::from_str(body)
+                .map(Some)
+                .map_err(|e| Error::http(format!("invalid reply to {}: {}", operation, e)));
+        }
+
+        let job = response.json::<OptimizeJob>().await?;
+        let Some(timeout) = wait_timeout else {
+            return Ok(None);
+        };
+        let mut poller = Poller::new(timeout);
+        loop {
+            let request = self
+                .client
+                .post(&format!("/v1/table/{}/{}/status/", self.name, operation))
+                .json(&serde_json::json!({ "job_id": job.job_id }));
+            let response = self.client.send_idempotent(request).await?;
+            let response = self.check_table_response(response).await?;
+            match response.json::<OptimizeJobStatus<T>>().await? {
+                OptimizeJobStatus::Running => {
+                    poller
+                        .wait(format!(
+                            "the {} job '{}' was still running",
+                            operation, job.job_id
+                        ))
+                        .await?
+                }
+                OptimizeJobStatus::Succeeded { result } => return Ok(Some(result)),
+                OptimizeJobStatus::Failed { error } => {
+                    return Err(Error::Runtime {
+                        message: format!(
+                            "the {} job '{}' failed: {}",
+                            operation, job.job_id, error
+                        ),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Fetch the schema of the latest version and cache it
+    async fn refresh_schema(&self) -> Result<SchemaRef> {
+        let description = self.describe_version(None).await?;
+        let schema = Arc::new(Schema::try_from(&description.schema)?);
+        *self.schema.write()? = Some(schema.clone());
+        Ok(schema)
+    }
+
+    /// Send a schema evolution request and refresh the cached schema afterwards
+    async fn evolve_schema(&self, operation: &str, body: serde_json::Value) -> Result<()> {
+        self.ensure_mutable()?;
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/{}/", self.name, operation))
+            .json(&body);
+        let response = self.client.send(request).await?;
+        self.check_table_response(response)
+            .await
+            .map_err(|err| match err {
+                Error::InvalidInput { message } => Error::InvalidInput {
+                    message: format!(
+                        "{} rejected for table '{}': {}",
+                        operation, self.name, message
+                    ),
+                },
+                Error::Http {
+                    status,
+                    code,
+                    message,
+                    request_id,
+                } => Error::Http {
+                    status,
+                    code,
+                    message: format!(
+                        "{} failed for table '{}': {}",
+                        operation, self.name, message
+                    ),
+                    request_id,
+                },
+                err => err,
+            })?;
+        self.refresh_schema().await?;
+        Ok(())
+    }
+
+    fn checked_out_version(&self) -> Result<Option<u64>> {
+        Ok(*self.version.read()?)
+    }
+
+    /// Parse the rows affected by a write, which are zero if they aren't reported
+    async fn parse_counts(response: Response) -> Result<RowCountsResponse> {
+        let body = response.text().await?;
+        Ok(serde_json::from_str(&body).unwrap_or_default())
+    }
+
+    fn ensure_mutable(&self) -> Result<()> {
+        if self.checked_out_version()?.is_some() {
+            return Err(Error::InvalidInput {
+                message: "table cannot be modified when a specific version is checked out"
+                    .to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Describe the checked out version of the table, or the latest version if no
+    /// version is checked out
+    async fn describe(&self) -> Result<TableDescription> {
+        self.describe_version(self.checked_out_version()?).await
+    }
+
+    async fn describe_version(&self, version: Option<u64>) -> Result<TableDescription> {
+        let mut request = self
+            .client
+            .post(&format!("/v1/table/{}/describe/", self.name));
+        if let Some(version) = version {
+            request = request.json(&serde_json::json!({ "version": version }));
+        }
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        Ok(response.json::<TableDescription>().await?)
+    }
+
+    async fn send_tag_request(&self, operation: &str, body: serde_json::Value) -> Result<()> {
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/tags/{}/", self.name, operation))
+            .json(&body);
+        let response = self.client.send(request).await?;
+        self.check_table_response(response).await?;
+        Ok(())
+    }
+
+    /// Like [`RestfulLanceDbClient::check_response`] but maps a 404 to
+    /// [`Error::TableNotFound`]
+    async fn check_table_response(&self, response: Response) -> Result<Response> {
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::TableNotFound {
+                name: self.name.clone(),
+            });
+        }
+        self.client.check_response(response).await
+    }
+
+    async fn fetch_index_stats(&self, index_name: &str) -> Result<Option<IndexStatistics>> {
+        let request = self.client.post(&format!(
+            "/v1/table/{}/index/{}/stats/",
+            self.name, index_name
+        ));
+        let response = self.client.send_idempotent(request).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = self.client.check_response(response).await?;
+        Ok(Some(response.json::<IndexStatistics>().await?))
+    }
+
+    /// Ask the server to describe the plan of a query
+    ///
+    /// Older servers don't have the endpoint, the plan isn't available from them.
+    async fn fetch_plan(&self, endpoint: &str, body: serde_json::Value) -> Result<String> {
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/{}/", self.name, endpoint))
+            .json(&body);
+        let response = self.client.send_idempotent(request).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::NotSupported {
+                message: format!("the server doesn't support {} for remote tables", endpoint),
+            });
+        }
+        let response = self.check_table_response(response).await?;
+        Ok(response.json::<String>().await?)
+    }
+
+    /// Serialize the parts of a query shared by plain and vector queries
+    async fn query_body(query: &Query) -> Result<serde_json::Value> {
+        let query = query.clone().resolve_row_id_selection().await?;
+        if !query.group_by.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "group_by() only applies to aggregate()".to_string(),
+            });
+        }
+        if query.sample.is_some() {
+            return Err(Error::NotSupported {
+                message: "sampling is not yet supported for remote tables".to_string(),
+            });
+        }
+        if query.fragments.is_some() {
+            return Err(Error::NotSupported {
+                message: "with_fragments() is not supported for remote tables".to_string(),
+            });
+        }
+        let mut body = serde_json::json!({});
+        if let Some(limit) = query.limit {
+            body["k"] = limit.into();
+        }
+        if let Some(offset) = query.offset {
+            body["offset"] = offset.into();
+        }
+        if let Some(filter) = &query.filter {
+            body["filter"] = filter.as_str().into();
+        }
+        match &query.select {
+            Select::All => {}
+            Select::Columns(columns) => {
+                body["columns"] = columns.clone().into();
+            }
+            Select::Dynamic(_) => {
+                return Err(Error::NotSupported {
+                    message: "dynamic projections are not yet supported for remote tables"
+                        .to_string(),
+                })
+            }
+        }
+        if let Some(full_text_search) = &query.full_text_search {
+            body["full_text_query"] = serde_json::json!({
+                "columns": full_text_search.columns,
+                "query": full_text_search.query,
+            });
+        }
+        if query.with_row_id {
+            body["with_row_id"] = true.into();
+        }
+        if query.with_row_address {
+            body["with_row_address"] = true.into();
+        }
+        Ok(body)
+    }
+
+    /// Serialize a vector search, validating the query vector against the table schema
+    async fn vector_query_body(&self, query: &VectorQuery) -> Result<serde_json::Value> {
+        if query.multivector {
+            return Err(Error::NotSupported {
+                message: "multivector searches are not yet supported for remote tables".to_string(),
+            });
+        }
+        let mut body = Self::query_body(&query.base).await?;
+        let Some(first_vector) = query.query_vector.first() else {
+            return Ok(body);
+        };
+
+        let description = self.describe().await?;
+        let schema = Schema::try_from(&description.schema)?;
+        let column = resolve_vector_column(&schema, query.column.as_deref(), first_vector.len())?;
+        let mut query_vectors = Vec::with_capacity(query.query_vector.len());
+        for query_vector in &query.query_vector {
+            let query_vector = arrow_cast::cast(query_vector, &DataType::Float32)?;
+            query_vectors.push(query_vector.as_primitive::<Float32Type>().values().to_vec());
+        }
+
+        // A batch of searches is sent as a list of vectors in a single request
+        body["vector"] = if query_vectors.len() == 1 {
+            query_vectors.remove(0).into()
+        } else {
+            query_vectors.into()
+        };
+        body["vector_column"] = column.into();
+        body["k"] = query.base.limit.unwrap_or(DEFAULT_TOP_K).into();
+        body["nprobes"] = query.nprobes.into();
+        body["prefilter"] = query.prefilter.into();
+        body["bypass_vector_index"] = (!query.use_index).into();
+        if query.fast_search {
+            body["fast_search"] = true.into();
+        }
+        if let Some(refine_factor) = query.refine_factor {
+            body["refine_factor"] = refine_factor.into();
+        }
+        if let Some(ef) = query.ef {
+            body["ef"] = ef.into();
+        }
+        if let Some(distance_type) = query.distance_type {
+            body["metric"] = distance_type.to_string().into();
+        }
+        Ok(body)
+    }
+
+    async fn execute_query(
+        &self,
+        mut body: serde_json::Value,
+        options: &QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if let Some(version) = self.checked_out_version()? {
+            body["version"] = version.into();
+        }
+        let mut request = self
+            .client
+            .post(&format!("/v1/table/{}/query/", self.name))
+            .json(&body);
+        if let Some(timeout) = options.timeout {
+            request = request.timeout(timeout);
+        }
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        ipc_response_to_stream(response).await
     }
 }
 
-impl std::fmt::Display for RemoteTable {
+impl<S: HttpSend> std::fmt::Display for RemoteTable<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "RemoteTable({})", self.name)
     }
 }
 
 #[async_trait]
-impl TableInternal for RemoteTable {
+impl<S: HttpSend> TableInternal for RemoteTable<S> {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
     fn as_native(&self) -> Option<&NativeTable> {
         None
     }
+    fn is_remote(&self) -> bool {
+        true
+    }
     fn name(&self) -> &str {
         &self.name
     }
+    fn uri(&self) -> Option<String> {
+        Some(format!("{}/v1/table/{}/", self.client.host(), self.name))
+    }
     async fn version(&self) -> Result<u64> {
-        todo!()
+        match self.checked_out_version()? {
+            Some(version) => Ok(version),
+            None => Ok(self.describe_version(None).await?.version),
+        }
+    }
+    async fn latest_version(&self) -> Result<u64> {
+        Ok(self.describe_version(None).await?.version)
+    }
+    async fn version_operations(&self, _from: u64, _to: u64) -> Result<Vec<ChangeOperation>> {
+        // The server doesn't describe what a version changed
+        Ok(Vec::new())
+    }
+    async fn list_versions(&self) -> Result<Vec<VersionInfo>> {
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/version/list/", self.name));
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        let mut versions = response
+            .json::<ListVersionsResponse>()
+            .await?
+            .versions
+            .into_iter()
+            .map(|version| {
+                let timestamp =
+                    DateTime::parse_from_rfc3339(&version.timestamp).map_err(|err| {
+                        Error::Runtime {
+                            message: format!(
+                                "the server returned an invalid timestamp '{}' for version {}: {}",
+                                version.timestamp, version.version, err
+                            ),
+                        }
+                    })?;
+                Ok(VersionInfo {
+                    version: version.version,
+                    timestamp: timestamp.with_timezone(&Utc),
+                    metadata: version.metadata,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        versions.sort_by_key(|version| version.version);
+        Ok(versions)
+    }
+    async fn list_tags(&self) -> Result<HashMap<String, u64>> {
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/tags/list/", self.name));
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        Ok(response
+            .json::<ListTagsResponse>()
+            .await?
+            .tags
+            .into_iter()
+            .map(|(name, tag)| (name, tag.version))
+            .collect())
     }
-    async fn checkout(&self, _version: u64) -> Result<()> {
-        todo!()
+    async fn create_tag(&self, name: &str, version: u64) -> Result<()> {
+        self.send_tag_request(
+            "create",
+            serde_json::json!({ "tag": name, "version": version }),
+        )
+        .await
+    }
+    async fn update_tag(&self, name: &str, version: u64) -> Result<()> {
+        self.send_tag_request(
+            "update",
+            serde_json::json!({ "tag": name, "version": version }),
+        )
+        .await
+    }
+    async fn delete_tag(&self, name: &str) -> Result<()> {
+        self.send_tag_request("delete", serde_json::json!({ "tag": name }))
+            .await
+    }
+    async fn checkout(&self, version: u64) -> Result<()> {
+        let latest = self.describe_version(None).await?.version;
+        if version > latest {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "cannot checkout version {} of table '{}', the latest version is {}",
+                    version, self.name, latest
+                ),
+            });
+        }
+        // Older versions may have been cleaned up so make sure this one still exists
+        self.describe_version(Some(version))
+            .await
+            .map_err(|err| match err {
+                Error::TableNotFound { .. } => Error::InvalidInput {
+                    message: format!(
+                        "version {} of table '{}' does not exist",
+                        version, self.name
+                    ),
+                },
+                err => err,
+            })?;
+        *self.version.write()? = Some(version);
+        Ok(())
     }
     async fn checkout_latest(&self) -> Result<()> {
-        todo!()
+        *self.version.write()? = None;
+        Ok(())
     }
     async fn restore(&self) -> Result<()> {
-        todo!()
+        let version = self
+            .checked_out_version()?
+            .ok_or_else(|| Error::InvalidInput {
+                message: "you must run checkout before running restore".to_string(),
+            })?;
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/restore/", self.name))
+            .json(&serde_json::json!({ "version": version }));
+        let response = self.client.send(request).await?;
+        self.check_table_response(response).await?;
+        *self.version.write()? = None;
+        *self.schema.write()? = None;
+        Ok(())
     }
     async fn schema(&self) -> Result<SchemaRef> {
-        todo!()
+        if self.checked_out_version()?.is_some() {
+            let description = self.describe().await?;
+            return Ok(Arc::new(Schema::try_from(&description.schema)?));
+        }
+        if let Some(schema) = self.schema.read()?.clone() {
+            return Ok(schema);
+        }
+        self.refresh_schema().await
+    }
+    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        let mut body = serde_json::json!({ "predicate": filter });
+        if let Some(version) = self.checked_out_version()? {
+            body["version"] = version.into();
+        }
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/count_rows/", self.name))
+            .json(&body);
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        Ok(response.json::<usize>().await?)
     }
-    async fn count_rows(&self, _filter: Option<String>) -> Result<usize> {
-        todo!()
+    async fn count_rows_approx(&self, filter: Option<String>) -> Result<ApproxCount> {
+        let mut body = serde_json::json!({ "predicate": filter, "approx": true });
+        if let Some(version) = self.checked_out_version()? {
+            body["version"] = version.into();
+        }
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/count_rows/", self.name))
+            .json(&body);
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        Ok(match response.json::<ApproxCountResponse>().await? {
+            ApproxCountResponse::Exact(count) => ApproxCount::exact(count),
+            ApproxCountResponse::Approx {
+                estimate,
+                lower,
+                upper,
+            } => ApproxCount {
+                estimate,
+                lower,
+                upper,
+                exact: false,
+            },
+        })
+    }
+    async fn take(
+        &self,
+        _row_ids: &[u64],
+        _columns: Option<Vec<String>>,
+        _missing: MissingRows,
+    ) -> Result<RecordBatch> {
+        Err(Error::NotSupported {
+            message: "taking rows by row id is not supported on LanceDB cloud, use take_by_key"
+                .to_string(),
+        })
     }
     async fn add(
         &self,
-        _add: AddDataBuilder<NoData>,
-        _data: Box<dyn RecordBatchReader + Send>,
+        add: AddDataBuilder<NoData>,
+        data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<()> {
-        todo!()
+        self.ensure_mutable()?;
+        // An overwrite may replace the schema, so only appends need to match the
+        // existing table.  Checking up front avoids uploading data that would be rejected.
+        if matches!(add.mode, AddDataMode::Append) {
+            let description = self.describe().await?;
+            let table_schema = Schema::try_from(&description.schema)?;
+            let definition = TableDefinition::try_from_rich_schema(Arc::new(table_schema))?;
+            ValidationReport {
+                issues: Validator::new(&definition).check_schema(&data.schema()),
+                ..Default::default()
+            }
+            .into_result()?;
+        }
+
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/insert/", self.name));
+        let mut request = self.client.with_ipc_body(request, data).await?;
+        if matches!(add.mode, AddDataMode::Overwrite) {
+            request = request.query(&[("mode", "overwrite")]);
+        }
+
+        let response = self.client.send(request).await?;
+        self.check_table_response(response).await?;
+        if matches!(add.mode, AddDataMode::Overwrite) {
+            *self.schema.write()? = None;
+        }
+        Ok(())
     }
     async fn create_plan(
         &self,
-        _query: &VectorQuery,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // The server runs the query so the plan is just a wrapper around the response
+        let body = self.vector_query_body(query).await?;
+        let stream = self.execute_query(body, &options).await?;
+        Ok(Arc::new(OneShotExec::new(stream)))
+    }
+    async fn create_aggregate_plan(
+        &self,
+        _query: &AggregateQuery,
         _options: QueryExecutionOptions,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        unimplemented!()
+        Err(Error::NotSupported {
+            message: "aggregate queries are not yet supported for remote tables".to_string(),
+        })
+    }
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String> {
+        let body = serde_json::json!({
+            "query": self.vector_query_body(query).await?,
+            "verbose": verbose,
+        });
+        self.fetch_plan("explain_plan", body).await
+    }
+    async fn analyze_plan(&self, query: &VectorQuery) -> Result<String> {
+        let body = serde_json::json!({
+            "query": self.vector_query_body(query).await?,
+        });
+        self.fetch_plan("analyze_plan", body).await
     }
     async fn plain_query(
         &self,
-        _query: &Query,
-        _options: QueryExecutionOptions,
+        query: &Query,
+        options: QueryExecutionOptions,
     ) -> Result<DatasetRecordBatchStream> {
-        todo!()
+        let body = Self::query_body(query).await?;
+        let stream = self.execute_query(body, &options).await?;
+        Ok(DatasetRecordBatchStream::new(stream))
     }
-    async fn update(&self, _update: UpdateBuilder) -> Result<()> {
-        todo!()
+    async fn update(&self, update: UpdateBuilder) -> Result<UpdateResult> {
+        self.ensure_mutable()?;
+        if update.returning.is_some() {
+            return Err(Error::NotSupported {
+                message: "returning values from an update is not supported by LanceDB Cloud"
+                    .to_string(),
+            });
+        }
+        let body = serde_json::json!({
+            "updates": update.columns,
+            "only_if": update.filter,
+        });
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/update/", self.name))
+            .json(&body);
+        let response = self.client.send(request).await?;
+        let response = self.check_table_response(response).await?;
+        let counts = Self::parse_counts(response).await?;
+        Ok(UpdateResult {
+            num_updated: counts.num_updated_rows,
+            rows: RecordBatch::new_empty(Arc::new(Schema::empty())),
+        })
     }
-    async fn delete(&self, _predicate: &str) -> Result<()> {
-        todo!()
+    async fn delete(&self, predicate: &str) -> Result<Option<u64>> {
+        self.ensure_mutable()?;
+        // Some backends treat an empty predicate as "delete everything", which is
+        // never what the caller meant.
+        if predicate.trim().is_empty() {
+            return Err(Error::InvalidInput {
+                message: "delete predicate cannot be empty".to_string(),
+            });
+        }
+        let body = serde_json::json!({ "predicate": predicate });
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/delete/", self.name))
+            .json(&body);
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        let counts = Self::parse_counts(response).await?;
+        Ok(counts.num_deleted_rows)
     }
-    async fn create_index(&self, _index: IndexBuilder) -> Result<()> {
-        todo!()
+    async fn delete_rows(&self, row_ids: &[u64]) -> Result<DeleteRowsStats> {
+        self.ensure_mutable()?;
+        let mut row_ids = row_ids.to_vec();
+        row_ids.sort_unstable();
+        row_ids.dedup();
+        let mut stats = DeleteRowsStats::default();
+        // Each batch is counted and deleted on its own, see Table::delete_rows
+        for batch in row_ids.chunks(DELETE_ROWS_BATCH_SIZE) {
+            let predicate = take::row_ids_filter(batch);
+            let num_existing = self.count_rows(Some(predicate.clone())).await? as u64;
+            if num_existing > 0 {
+                self.delete(&predicate).await?;
+            }
+            stats.num_deleted += num_existing;
+            stats.num_missing += batch.len() as u64 - num_existing;
+        }
+        Ok(stats)
+    }
+    async fn create_index(&self, index: IndexBuilder) -> Result<()> {
+        self.ensure_mutable()?;
+        if index.columns.len() != 1 {
+            return Err(Error::Schema {
+                message: "Multi-column (composite) indices are not yet supported".to_string(),
+            });
+        }
+        let column = index.columns[0].clone();
+
+        let mut body = serde_json::json!({
+            "column": column,
+            "replace": index.replace,
+        });
+        let index_type = match &index.index {
+            Index::Auto => {
+                let description = self.describe().await?;
+                let schema = Schema::try_from(&description.schema)?;
+                let field = schema.field_with_name(&column)?;
+                if matches!(field.data_type(), DataType::FixedSizeList(_, _)) {
+                    IndexType::IvfPq
+                } else {
+                    IndexType::BTree
+                }
+            }
+            Index::BTree(_) => IndexType::BTree,
+            Index::IvfPq(ivf_pq) => {
+                body["metric_type"] = ivf_pq.distance_type.to_string().into();
+                body["num_partitions"] = ivf_pq.num_partitions.into();
+                body["num_sub_vectors"] = ivf_pq.num_sub_vectors.into();
+                body["sample_rate"] = ivf_pq.sample_rate.into();
+                body["max_iterations"] = ivf_pq.max_iterations.into();
+                IndexType::IvfPq
+            }
+            Index::IvfHnswPq(ivf_hnsw_pq) => {
+                body["metric_type"] = ivf_hnsw_pq.distance_type.to_string().into();
+                body["num_partitions"] = ivf_hnsw_pq.num_partitions.into();
+                body["num_sub_vectors"] = ivf_hnsw_pq.num_sub_vectors.into();
+                body["sample_rate"] = ivf_hnsw_pq.sample_rate.into();
+                body["max_iterations"] = ivf_hnsw_pq.max_iterations.into();
+                body["m"] = ivf_hnsw_pq.m.into();
+                body["ef_construction"] = ivf_hnsw_pq.ef_construction.into();
+                IndexType::IvfHnswPq
+            }
+            Index::IvfHnswSq(ivf_hnsw_sq) => {
+                body["metric_type"] = ivf_hnsw_sq.distance_type.to_string().into();
+                body["num_partitions"] = ivf_hnsw_sq.num_partitions.into();
+                body["sample_rate"] = ivf_hnsw_sq.sample_rate.into();
+                body["max_iterations"] = ivf_hnsw_sq.max_iterations.into();
+                body["m"] = ivf_hnsw_sq.m.into();
+                body["ef_construction"] = ivf_hnsw_sq.ef_construction.into();
+                IndexType::IvfHnswSq
+            }
+            Index::FTS(fts) => {
+                body["base_tokenizer"] = fts.base_tokenizer.to_string().into();
+                body["language"] = fts.language.clone().into();
+                body["stem"] = fts.stem.into();
+                body["lower_case"] = fts.lower_case.into();
+                body["remove_stop_words"] = fts.remove_stop_words.into();
+                IndexType::FTS
+            }
+        };
+        body["index_type"] = index_type.to_string().into();
+
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/create_index/", self.name))
+            .json(&body);
+        let response = self.client.send(request).await?;
+        self.check_table_response(response).await?;
+
+        if let Some(timeout) = index.wait_timeout {
+            // Indices are given the default name used by lance
+            wait_for_index(self, &[&format!("{}_idx", column)], timeout).await?;
+        }
+        Ok(())
     }
     async fn merge_insert(
         &self,
-        _params: MergeInsertBuilder,
-        _new_data: Box<dyn RecordBatchReader + Send>,
-    ) -> Result<()> {
-        todo!()
+        params: MergeInsertBuilder,
+        new_data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<MergeResult> {
+        self.ensure_mutable()?;
+        let mut query: Vec<(&str, String)> = params
+            .on
+            .iter()
+            .map(|column| ("on", column.clone()))
+            .collect();
+        query.push((
+            "when_matched_update_all",
+            params.when_matched_update_all.to_string(),
+        ));
+        if let Some(filter) = params.when_matched_update_all_filt {
+            query.push(("when_matched_update_all_filt", filter));
+        }
+        if params.when_matched_delete {
+            query.push(("when_matched_delete", "true".to_string()));
+            if let Some(filter) = params.when_matched_delete_filt {
+                query.push(("when_matched_delete_filt", filter));
+            }
+        }
+        query.push((
+            "when_not_matched_insert_all",
+            params.when_not_matched_insert_all.to_string(),
+        ));
+        query.push((
+            "when_not_matched_by_source_delete",
+            params.when_not_matched_by_source_delete.to_string(),
+        ));
+        if let Some(filter) = params.when_not_matched_by_source_delete_filt {
+            query.push(("when_not_matched_by_source_delete_filt", filter));
+        }
+
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/merge_insert/", self.name))
+            .query(&query);
+        let request = self.client.with_ipc_body(request, new_data).await?;
+        let response = self.client.send(request).await?;
+        let response = self.check_table_response(response).await?;
+        let counts = Self::parse_counts(response).await?;
+        Ok(MergeResult {
+            num_inserted: counts.num_inserted_rows,
+            num_updated: counts.num_updated_rows,
+            num_deleted: counts.num_deleted_rows,
+            version: counts.version,
+        })
     }
-    async fn optimize(&self, _action: OptimizeAction) -> Result<OptimizeStats> {
-        todo!()
+    async fn optimize(
+        &self,
+        action: OptimizeAction,
+        _progress: Option<CompactionProgressCallback>,
+        wait_timeout: Option<Duration>,
+    ) -> Result<OptimizeStats> {
+        self.ensure_mutable()?;
+        let mut stats = OptimizeStats {
+            compaction: None,
+            compacted_fragments: None,
+            prune: None,
+        };
+        // The timeout covers all of the operations of the action
+        let deadline = wait_timeout.map(|timeout| Instant::now() + timeout);
+        let remaining =
+            || deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match action {
+            OptimizeAction::All => {
+                let compacted = self
+                    .optimize(
+                        OptimizeAction::Compact {
+                            options: CompactionOptions::default(),
+                            remap_options: None,
+                        },
+                        None,
+                        remaining(),
+                    )
+                    .await?;
+                stats.compaction = compacted.compaction;
+                stats.compacted_fragments = compacted.compacted_fragments;
+                stats.prune = self
+                    .optimize(
+                        OptimizeAction::Prune {
+                            older_than: None,
+                            delete_unverified: None,
+                            compaction: None,
+                        },
+                        None,
+                        remaining(),
+                    )
+                    .await?
+                    .prune;
+                self.optimize(
+                    OptimizeAction::Index(OptimizeOptions::default()),
+                    None,
+                    remaining(),
+                )
+                .await?;
+            }
+            OptimizeAction::Compact {
+                options,
+                remap_options,
+            } => {
+                if remap_options.is_some() {
+                    return Err(Error::NotSupported {
+                        message: "custom index remapping is not supported for remote tables"
+                            .to_string(),
+                    });
+                }
+                let body = serde_json::json!({
+                    "target_rows_per_fragment": options.target_rows_per_fragment,
+                    "max_rows_per_group": options.max_rows_per_group,
+                    "materialize_deletions": options.materialize_deletions,
+                    "materialize_deletions_threshold": options.materialize_deletions_threshold,
+                    "num_threads": options.num_threads,
+                });
+                let Some(metrics) = self
+                    .send_optimize::<CompactionResponse>("compact", body, remaining())
+                    .await?
+                else {
+                    return Ok(stats);
+                };
+                stats.compaction = Some(CompactionMetrics {
+                    fragments_removed: metrics.fragments_removed,
+                    fragments_added: metrics.fragments_added,
+                    files_removed: metrics.files_removed,
+                    files_added: metrics.files_added,
+                });
+                if let (Some(removed), Some(added)) =
+                    (metrics.removed_fragment_ids, metrics.added_fragment_ids)
+                {
+                    stats.compacted_fragments = Some(CompactedFragments { removed, added });
+                }
+            }
+            OptimizeAction::Prune {
+                older_than,
+                delete_unverified,
+                compaction,
+            } => {
+                if let Some(options) = compaction {
+                    let compacted = self
+                        .optimize(
+                            OptimizeAction::Compact {
+                                options,
+                                remap_options: None,
+                            },
+                            None,
+                            remaining(),
+                        )
+                        .await?;
+                    stats.compaction = compacted.compaction;
+                    stats.compacted_fragments = compacted.compacted_fragments;
+                }
+                let older_than =
+                    older_than.unwrap_or(chrono::Duration::try_days(7).expect("valid delta"));
+                let body = serde_json::json!({
+                    "older_than_seconds": older_than.num_seconds(),
+                    "delete_unverified": delete_unverified.unwrap_or(false),
+                });
+                stats.prune = self
+                    .send_optimize::<CleanupResponse>("cleanup", body, remaining())
+                    .await?
+                    .map(|removed| RemovalStats {
+                        bytes_removed: removed.bytes_removed,
+                        old_versions: removed.old_versions,
+                    });
+            }
+            OptimizeAction::Index(options) => {
+                let body = serde_json::json!({
+                    "num_indices_to_merge": options.num_indices_to_merge,
+                });
+                self.send_optimize::<IgnoredAny>("optimize_indices", body, remaining())
+                    .await?;
+            }
+        }
+        Ok(stats)
     }
     async fn add_columns(
         &self,
-        _transforms: NewColumnTransform,
+        transforms: NewColumnTransform,
         _read_columns: Option<Vec<String>>,
     ) -> Result<()> {
-        todo!()
+        let new_columns = match transforms {
+            NewColumnTransform::SqlExpressions(expressions) => expressions
+                .into_iter()
+                .map(|(name, expression)| {
+                    serde_json::json!({ "name": name, "expression": expression })
+                })
+                .collect::<Vec<_>>(),
+            NewColumnTransform::BatchUDF(_) => {
+                return Err(Error::NotSupported {
+                    message: "adding columns with a UDF is not supported for remote tables"
+                        .to_string(),
+                })
+            }
+        };
+        self.evolve_schema(
+            "add_columns",
+            serde_json::json!({ "new_columns": new_columns }),
+        )
+        .await
     }
-    async fn alter_columns(&self, _alterations: &[ColumnAlteration]) -> Result<()> {
-        todo!()
+    async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()> {
+        let alterations = alterations
+            .iter()
+            .map(|alteration| {
+                let mut value = serde_json::json!({ "path": alteration.path });
+                if let Some(rename) = &alteration.rename {
+                    value["rename"] = rename.as_str().into();
+                }
+                if let Some(nullable) = alteration.nullable {
+                    value["nullable"] = nullable.into();
+                }
+                if let Some(data_type) = &alteration.data_type {
+                    value["data_type"] = data_type_to_json(data_type)?;
+                }
+                Ok(value)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.evolve_schema(
+            "alter_columns",
+            serde_json::json!({ "alterations": alterations }),
+        )
+        .await
     }
-    async fn drop_columns(&self, _columns: &[&str]) -> Result<()> {
-        todo!()
+    async fn drop_columns(&self, columns: &[&str]) -> Result<()> {
+        self.evolve_schema("drop_columns", serde_json::json!({ "columns": columns }))
+            .await
     }
     async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
-        todo!()
+        let request = self
+            .client
+            .post(&format!("/v1/table/{}/index/list/", self.name));
+        let response = self.client.send_idempotent(request).await?;
+        let response = self.check_table_response(response).await?;
+        response
+            .json::<ListIndicesResponse>()
+            .await?
+            .indexes
+            .into_iter()
+            .map(|index| {
+                let index_type = IndexType::try_from(index.index_type.as_str())?;
+                let vector_params = if index_type.is_vector() {
+                    Some(VectorIndexParams {
+                        distance_type: index
+                            .metric_type
+                            .as_deref()
+                            .map(DistanceType::try_from)
+                            .transpose()?,
+                        num_partitions: index.num_partitions,
+                        num_sub_vectors: index.num_sub_vectors,
+                        m: index.m,
+                        ef_construction: index.ef_construction,
+                    })
+                } else {
+                    None
+                };
+                Ok(IndexConfig {
+                    name: index.index_name,
+                    index_type,
+                    columns: index.columns,
+                    vector_params,
+                })
+            })
+            .collect()
+    }
+    async fn index_stats(&self, index_name: &str) -> Result<Option<IndexStats>> {
+        let Some(stats) = self.fetch_index_stats(index_name).await? else {
+            return Ok(None);
+        };
+        let index_type = stats.index_type.as_deref().ok_or_else(|| Error::Runtime {
+            message: format!(
+                "the statistics of index '{}' have no index type",
+                index_name
+            ),
+        })?;
+        let distance_type = stats
+            .distance_type
+            .or_else(|| stats.indices.into_iter().find_map(|idx| idx.metric_type))
+            .as_deref()
+            .map(DistanceType::try_from)
+            .transpose()?;
+        Ok(Some(IndexStats {
+            num_indexed_rows: stats.num_indexed_rows,
+            num_unindexed_rows: stats.num_unindexed_rows,
+            index_type: IndexType::try_from(index_type)?,
+            distance_type,
+            num_partitions: stats.num_partitions,
+        }))
+    }
+    async fn stats(&self) -> Result<TableStatistics> {
+        let description = self.describe_version(self.checked_out_version()?).await?;
+        let stats = description.stats.ok_or_else(|| Error::NotSupported {
+            message: "the server does not report table statistics".to_string(),
+        })?;
+        let last_write = self
+            .list_versions()
+            .await?
+            .into_iter()
+            .find(|version| version.version == description.version)
+            .ok_or_else(|| Error::Runtime {
+                message: format!(
+                    "the server did not list version {} of table '{}'",
+                    description.version, self.name
+                ),
+            })?
+            .timestamp;
+        Ok(TableStatistics {
+            version: description.version,
+            last_write,
+            total_bytes: stats.total_bytes,
+            num_rows: stats.num_rows,
+            num_deleted_rows: stats.num_deleted_rows,
+            fragment_stats: stats.fragment_stats,
+            index_coverage: crate::table::stats::index_coverage(self).await?,
+        })
     }
     async fn table_definition(&self) -> Result<TableDefinition> {
-        todo!()
+        // The column definitions are stored in the schema metadata
+        TableDefinition::try_from_rich_schema(self.schema().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use arrow_array::{
+        Array, FixedSizeListArray, Float32Array, Int32Array, RecordBatch, RecordBatchIterator,
+    };
+    use arrow_schema::Field;
+    use futures::TryStreamExt;
+    use lance::arrow::json::JsonSchema;
+
+    use super::*;
+    use crate::embeddings::{
+        EmbeddingDefinition, EmbeddingFunction, EmbeddingRegistry, MemoryRegistry,
+    };
+    use crate::index::scalar::{BaseTokenizer, FtsIndexBuilder};
+    use crate::index::vector::IvfPqIndexBuilder;
+    use crate::query::{ExecutableQuery, FullTextSearchQuery, QueryBase};
+    use crate::remote::client::test_utils::client_with_handler_and_config;
+    use crate::remote::client::test_utils::{
+        client_with_handler, client_with_slow_server, MockSender,
+    };
+    use crate::remote::db::ARROW_STREAM_CONTENT_TYPE;
+    use crate::remote::util::batches_to_ipc_bytes;
+    use crate::remote::Compression;
+    use crate::remote::{ClientConfig, RetryConfig};
+    use crate::table::ColumnKind;
+    use crate::Table;
+    use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+
+    fn test_table<T: Into<reqwest::Body>>(
+        handler: impl Fn(reqwest::Request) -> http::Response<T> + Send + Sync + 'static,
+    ) -> Table {
+        let client = client_with_handler(handler);
+        Table::new(Arc::new(RemoteTable::<MockSender>::new(
+            client,
+            "my_table".to_string(),
+        )))
+    }
+
+    fn describe_response(schema: &Schema) -> http::Response<String> {
+        let schema = JsonSchema::try_from(schema).unwrap();
+        let body = serde_json::json!({ "version": 1, "schema": schema }).to_string();
+        http::Response::builder().status(200).body(body).unwrap()
+    }
+
+    fn some_batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1, 2, 3]))]).unwrap()
+    }
+
+    fn decode_ipc(body: &[u8]) -> Vec<RecordBatch> {
+        arrow_ipc::reader::StreamReader::try_new(body, None)
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_append() {
+        let batch = some_batch();
+        let schema = batch.schema();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&schema),
+            "/v1/table/my_table/insert/" => {
+                assert_eq!(request.method(), "POST");
+                assert_eq!(
+                    request.headers().get(CONTENT_TYPE).unwrap(),
+                    ARROW_STREAM_CONTENT_TYPE
+                );
+                assert_eq!(request.url().query(), None);
+                let body = request.body().unwrap().as_bytes().unwrap();
+                received_clone.lock().unwrap().extend(decode_ipc(body));
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        table.add(reader).execute().await.unwrap();
+
+        assert_eq!(received.lock().unwrap().as_slice(), &[batch]);
+    }
+
+    #[tokio::test]
+    async fn test_add_compressed() {
+        let batch = some_batch();
+        let schema = batch.schema();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let config = ClientConfig {
+            upload_compression: Some(Compression::Zstd(3)),
+            ..Default::default()
+        };
+        let client =
+            client_with_handler_and_config(config, move |request| match request.url().path() {
+                "/v1/table/my_table/describe/" => describe_response(&schema),
+                "/v1/table/my_table/insert/" => {
+                    assert_eq!(request.headers().get(CONTENT_ENCODING).unwrap(), "zstd");
+                    let body = request.body().unwrap().as_bytes().unwrap();
+                    let body = zstd::decode_all(body).unwrap();
+                    received_clone.lock().unwrap().extend(decode_ipc(&body));
+                    http::Response::builder()
+                        .status(200)
+                        .body(String::new())
+                        .unwrap()
+                }
+                path => panic!("Unexpected path: {}", path),
+            });
+        let table = Table::new(Arc::new(RemoteTable::new(client, "my_table".to_string())));
+
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        table.add(reader).execute().await.unwrap();
+        assert_eq!(received.lock().unwrap().as_slice(), &[batch.clone()]);
+
+        // A server that doesn't support the compression gives a clear error
+        let config = ClientConfig {
+            upload_compression: Some(Compression::Gzip(6)),
+            ..Default::default()
+        };
+        let schema = batch.schema();
+        let client =
+            client_with_handler_and_config(config, move |request| match request.url().path() {
+                "/v1/table/my_table/describe/" => describe_response(&schema),
+                _ => http::Response::builder()
+                    .status(415)
+                    .body("unsupported content encoding".to_string())
+                    .unwrap(),
+            });
+        let table = Table::new(Arc::new(RemoteTable::new(client, "my_table".to_string())));
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let err = table.add(reader).execute().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::NotSupported { message } if message.contains("disabling upload compression")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_overwrite() {
+        let batch = some_batch();
+        let table = test_table(move |request| {
+            // Overwrite does not need to describe the table first
+            assert_eq!(request.url().path(), "/v1/table/my_table/insert/");
+            assert_eq!(request.url().query(), Some("mode=overwrite"));
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        table
+            .add(reader)
+            .mode(AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_add_schema_mismatch() {
+        let table_schema = Schema::new(vec![Field::new("a", DataType::Utf8, false)]);
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&table_schema),
+            path => panic!("Data should not be uploaded, got request to {}", path),
+        });
+
+        let batch = some_batch();
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let err = table.add(reader).execute().await.unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_add_errors() {
+        let batch = some_batch();
+        let schema = batch.schema();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&schema),
+            _ => http::Response::builder()
+                .status(500)
+                .body("internal failure".to_string())
+                .unwrap(),
+        });
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let err = table.add(reader).execute().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Http { status: Some(500), message, .. } if message == "internal failure"),
+            "{:?}",
+            err
+        );
+
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(404)
+                .body(String::new())
+                .unwrap()
+        });
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let err = table.add(reader).execute().await.unwrap_err();
+        assert!(matches!(err, Error::TableNotFound { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_describe_not_found() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/describe/");
+            http::Response::builder()
+                .status(404)
+                .body(r#"{"message": "table not found"}"#.to_string())
+                .unwrap()
+        });
+        let err = table.schema().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::TableNotFound { name } if name == "my_table"),
+            "{:?}",
+            err
+        );
+        let err = table.version().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::TableNotFound { name } if name == "my_table"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_rows() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/count_rows/");
+            let body = request_json(&request);
+            let count = if body["predicate"].is_null() { 100 } else { 42 };
+            http::Response::builder()
+                .status(200)
+                .body(count.to_string())
+                .unwrap()
+        });
+        assert_eq!(table.count_rows(None).await.unwrap(), 100);
+        assert_eq!(
+            table.count_rows(Some("id > 5".to_string())).await.unwrap(),
+            42
+        );
+
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(403)
+                .body(r#"{"message": "forbidden", "code": "permission_denied"}"#.to_string())
+                .unwrap()
+        });
+        let err = table.count_rows(None).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Http { status: Some(403), code: Some(code), .. } if code == "permission_denied"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_uri() {
+        let table = test_table::<String>(|_| panic!("No request should be sent"));
+        assert!(table.is_remote());
+        assert!(table.as_native().is_none());
+        assert_eq!(table.uri().unwrap(), "http://localhost/v1/table/my_table/");
+    }
+
+    #[test]
+    fn test_as_remote() {
+        let client = RestfulLanceDbClient::try_new(
+            "db://my_db",
+            "sk-test",
+            "us-east-1",
+            None,
+            ClientConfig::default(),
+        )
+        .unwrap();
+        let table = Table::new(Arc::new(RemoteTable::new(client, "my_table".to_string())));
+        let remote = table.as_remote().unwrap();
+        assert_eq!(remote.to_string(), table.to_string());
+        assert!(table.as_native().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_count_rows_approx() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/count_rows/");
+            let body = request_json(&request);
+            assert_eq!(body["approx"], true);
+            let count = if body["predicate"].is_null() {
+                "100".to_string()
+            } else {
+                r#"{"estimate": 42, "lower": 40, "upper": 45}"#.to_string()
+            };
+            http::Response::builder().status(200).body(count).unwrap()
+        });
+        assert_eq!(
+            table.count_rows_approx(None).await.unwrap(),
+            ApproxCount::exact(100)
+        );
+        assert_eq!(
+            table
+                .count_rows_approx(Some("id > 5".to_string()))
+                .await
+                .unwrap(),
+            ApproxCount {
+                estimate: 42,
+                lower: 40,
+                upper: 45,
+                exact: false,
+            }
+        );
+    }
+
+    fn request_json(request: &reqwest::Request) -> serde_json::Value {
+        let body = request.body().unwrap().as_bytes().unwrap();
+        serde_json::from_slice(body).unwrap()
+    }
+
+    fn ipc_response(batches: Vec<RecordBatch>) -> http::Response<Vec<u8>> {
+        let schema = batches[0].schema();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+        let body = batches_to_ipc_bytes(reader).unwrap();
+        http::Response::builder().status(200).body(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_timeout() {
+        let batch = some_batch();
+        let body = ipc_response(vec![batch.clone()]).into_body();
+        let config = ClientConfig {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let client = client_with_slow_server(config, Duration::from_millis(500), body).await;
+        let table = Table::new(Arc::new(RemoteTable::new(client, "my_table".to_string())));
+
+        let err = table.query().execute().await.map(|_| ()).unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{:?}", err);
+
+        let options = QueryExecutionOptions {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let results = table
+            .query()
+            .execute_with_options(options)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results, vec![batch]);
+    }
+
+    #[tokio::test]
+    async fn test_plain_query() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batches = (0..10)
+            .map(|i| {
+                let values = Int32Array::from_iter_values(i * 100..(i + 1) * 100);
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let response_batches = batches.clone();
+        let table = test_table(move |request| {
+            assert_eq!(request.method(), "POST");
+            assert_eq!(request.url().path(), "/v1/table/my_table/query/");
+            let expected = serde_json::json!({
+                "k": 1000,
+                "filter": "a >= 0",
+                "columns": ["a"],
+            });
+            assert_eq!(request_json(&request), expected);
+            ipc_response(response_batches.clone())
+        });
+
+        let query = table
+            .query()
+            .limit(1000)
+            .only_if("a >= 0")
+            .select(Select::columns(&["a"]));
+        let results = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results, batches);
+
+        // The decoded batches are split and merged to the requested lengths
+        let results = query
+            .execute_with_options(QueryExecutionOptions {
+                max_batch_length: 300,
+                min_batch_length: 250,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            results.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![300, 300, 300, 100]
+        );
+        assert_eq!(
+            arrow::compute::concat_batches(&schema, &results).unwrap(),
+            arrow::compute::concat_batches(&schema, &batches).unwrap()
+        );
+
+        // The plan wraps the same request
+        let plan = query.create_plan(Default::default()).await.unwrap();
+        assert_eq!(plan.schema(), schema);
+    }
+
+    #[tokio::test]
+    async fn test_full_text_query() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1]))]).unwrap();
+        let response_batch = batch.clone();
+        let table = test_table(move |request| {
+            let expected = serde_json::json!({
+                "k": 5,
+                "full_text_query": {"columns": ["text"], "query": "red shoes"},
+                "with_row_id": true,
+                "with_row_address": true,
+            });
+            assert_eq!(request_json(&request), expected);
+            ipc_response(vec![response_batch.clone()])
+        });
+
+        let results = table
+            .query()
+            .limit(5)
+            .with_row_id()
+            .with_row_address()
+            .full_text_search(FullTextSearchQuery::new("red shoes").columns(["text"]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results, vec![batch]);
+    }
+
+    #[tokio::test]
+    async fn test_plain_query_errors() {
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(400)
+                .body("Invalid filter".to_string())
+                .unwrap()
+        });
+        let err = table
+            .query()
+            .only_if("a >")
+            .execute()
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(200)
+                .body("not arrow data".to_string())
+                .unwrap()
+        });
+        let err = table.query().execute().await.map(|_| ()).unwrap_err();
+        assert!(matches!(err, Error::Arrow { .. }), "{:?}", err);
+    }
+
+    fn vector_schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 3),
+                true,
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_vector_query() {
+        let result_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("_distance", DataType::Float32, true),
+        ]));
+        let result = RecordBatch::try_new(
+            result_schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![7, 2])),
+                Arc::new(Float32Array::from(vec![0.5, 1.5])),
+            ],
+        )
+        .unwrap();
+        let response_result = result.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                describe_response(&vector_schema()).map(String::into_bytes)
+            }
+            "/v1/table/my_table/query/" => {
+                let expected = serde_json::json!({
+                    "vector": [1.0, 2.0, 3.0],
+                    "vector_column": "vector",
+                    "k": 2,
+                    "offset": 10,
+                    "filter": "id > 0",
+                    "columns": ["id"],
+                    "nprobes": 12,
+                    "prefilter": false,
+                    "bypass_vector_index": false,
+                    "fast_search": true,
+                    "refine_factor": 2,
+                    "ef": 64,
+                    "metric": "cosine",
+                });
+                assert_eq!(request_json(&request), expected);
+                ipc_response(vec![response_result.clone()])
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let results = table
+            .query()
+            .nearest_to(&[1.0, 2.0, 3.0])
+            .unwrap()
+            .limit(2)
+            .offset(10)
+            .only_if("id > 0")
+            .select(Select::columns(&["id"]))
+            .nprobes(12)
+            .postfilter()
+            .fast_search()
+            .refine_factor(2)
+            .ef(64)
+            .distance_type(crate::DistanceType::Cosine)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results, vec![result]);
+    }
+
+    /// Apply the same vector search to a native and a remote table
+    fn parity_query(table: &Table, distance_type: crate::DistanceType) -> VectorQuery {
+        table
+            .query()
+            .nearest_to(&[1.0, 2.0, 3.0])
+            .unwrap()
+            .limit(3)
+            .only_if("id > 1")
+            .select(Select::columns(&["id"]))
+            .distance_type(distance_type)
+    }
+
+    #[tokio::test]
+    async fn test_vector_query_parity() {
+        let schema = Arc::new(vector_schema());
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            (0..10).map(|i| Some(vec![Some(i as f32), Some(2.0), Some(10.0 - i as f32)])),
+            3,
+        );
+        let data = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = crate::connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let native = db
+            .create_table(
+                "my_table",
+                RecordBatchIterator::new(vec![Ok(data)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        for distance_type in [crate::DistanceType::L2, crate::DistanceType::Cosine] {
+            let expected = parity_query(&native, distance_type)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let num_rows: usize = expected.iter().map(|batch| batch.num_rows()).sum();
+            assert_eq!(num_rows, 3);
+
+            // The mocked server replies with what the native table returned
+            let response = expected.clone();
+            let schema = schema.clone();
+            let remote = test_table(move |request| match request.url().path() {
+                "/v1/table/my_table/describe/" => {
+                    describe_response(&schema).map(String::into_bytes)
+                }
+                "/v1/table/my_table/query/" => {
+                    let body = request_json(&request);
+                    assert_eq!(body["vector"], serde_json::json!([1.0, 2.0, 3.0]));
+                    assert_eq!(body["k"], 3);
+                    assert_eq!(body["filter"], "id > 1");
+                    assert_eq!(body["columns"], serde_json::json!(["id"]));
+                    assert_eq!(body["metric"], distance_type.to_string());
+                    ipc_response(response.clone())
+                }
+                path => panic!("Unexpected path: {}", path),
+            });
+            let results = parity_query(&remote, distance_type)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results, expected);
+            let distances = |batches: &[RecordBatch]| {
+                batches
+                    .iter()
+                    .flat_map(|batch| {
+                        batch
+                            .column_by_name("_distance")
+                            .unwrap()
+                            .as_primitive::<Float32Type>()
+                            .values()
+                            .to_vec()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(distances(&results), distances(&expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vector_query_text() {
+        #[derive(Debug)]
+        struct FixedEmbed;
+        impl EmbeddingFunction for FixedEmbed {
+            fn name(&self) -> &str {
+                "fixed"
+            }
+            fn source_type(&self) -> Result<Cow<DataType>> {
+                Ok(Cow::Owned(DataType::Utf8))
+            }
+            fn dest_type(&self) -> Result<Cow<DataType>> {
+                Ok(Cow::Owned(DataType::new_fixed_size_list(
+                    DataType::Float32,
+                    3,
+                    true,
+                )))
+            }
+            fn compute_source_embeddings(&self, _: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+                unimplemented!()
+            }
+            fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+                assert_eq!(input.as_string::<i32>().value(0), "red shoes");
+                Ok(Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0])))
+            }
+        }
+
+        let mut column_definitions =
+            TableDefinition::new_from_schema(Arc::new(vector_schema())).column_definitions;
+        column_definitions[1].kind =
+            ColumnKind::Embedding(EmbeddingDefinition::new("text", "fixed", Some("vector")));
+        let schema =
+            TableDefinition::new(Arc::new(vector_schema()), column_definitions).into_rich_schema();
+
+        let client = client_with_handler(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&schema).map(String::into_bytes),
+            "/v1/table/my_table/query/" => {
+                let body = request_json(&request);
+                assert_eq!(body["vector"], serde_json::json!([1.0, 2.0, 3.0]));
+                assert_eq!(body["vector_column"], "vector");
+                ipc_response(vec![some_batch()])
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        let registry = Arc::new(MemoryRegistry::new());
+        registry.register("fixed", Arc::new(FixedEmbed)).unwrap();
+        let table = Table::new_with_embedding_registry(
+            Arc::new(RemoteTable::new(client, "my_table".to_string())),
+            registry,
+        );
+
+        table
+            .query()
+            .nearest_to_text("red shoes")
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_vector_query_empty_result() {
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                describe_response(&vector_schema()).map(String::into_bytes)
+            }
+            "/v1/table/my_table/query/" => {
+                let schema = Schema::new(vec![Field::new("_distance", DataType::Float32, true)]);
+                let mut body = Vec::new();
+                let mut writer =
+                    arrow_ipc::writer::StreamWriter::try_new(&mut body, &schema).unwrap();
+                writer.finish().unwrap();
+                drop(writer);
+                http::Response::builder().status(200).body(body).unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        let stream = table
+            .query()
+            .nearest_to(&[1.0, 2.0, 3.0])
+            .unwrap()
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(stream.schema().field(0).name(), "_distance");
+        let results = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_vector_query_many() {
+        let result_schema = Arc::new(Schema::new(vec![
+            Field::new("query_index", DataType::UInt32, false),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let result = RecordBatch::try_new(
+            result_schema,
+            vec![
+                Arc::new(arrow_array::UInt32Array::from(vec![0, 1])),
+                Arc::new(Int32Array::from(vec![7, 2])),
+            ],
+        )
+        .unwrap();
+        let response_result = result.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                describe_response(&vector_schema()).map(String::into_bytes)
+            }
+            "/v1/table/my_table/query/" => {
+                let body = request_json(&request);
+                assert_eq!(
+                    body["vector"],
+                    serde_json::json!([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]])
+                );
+                assert_eq!(body["k"], 1);
+                ipc_response(vec![response_result.clone()])
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let results = table
+            .query()
+            .limit(1)
+            .nearest_to_many(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]])
+            .unwrap()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results, vec![result]);
+    }
+
+    #[tokio::test]
+    async fn test_explain_plan() {
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/explain_plan/" => {
+                let expected = serde_json::json!({
+                    "query": {"k": 5, "filter": "a > 0"},
+                    "verbose": true,
+                });
+                assert_eq!(request_json(&request), expected);
+                http::Response::builder()
+                    .status(200)
+                    .body(r#""ProjectionExec\n  LanceScan""#)
+                    .unwrap()
+            }
+            "/v1/table/my_table/analyze_plan/" => {
+                http::Response::builder().status(404).body("").unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let query = table.query().limit(5).only_if("a > 0");
+        let plan = query.explain_plan(true).await.unwrap();
+        assert_eq!(plan, "ProjectionExec\n  LanceScan");
+
+        let err = query.explain_analyze().await.unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_vector_query_wrong_dimension() {
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&vector_schema()),
+            path => panic!("Query should not be sent, got request to {}", path),
+        });
+        let err = table
+            .query()
+            .nearest_to(&[1.0, 2.0])
+            .unwrap()
+            .column("vector")
+            .execute()
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("query dim=2")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_index() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/create_index/");
+            let expected = serde_json::json!({
+                "column": "vector",
+                "replace": true,
+                "index_type": "IVF_PQ",
+                "metric_type": "cosine",
+                "num_partitions": 8,
+                "num_sub_vectors": null,
+                "sample_rate": 256,
+                "max_iterations": 50,
+            });
+            assert_eq!(request_json(&request), expected);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        let index = IvfPqIndexBuilder::default()
+            .distance_type(crate::DistanceType::Cosine)
+            .num_partitions(8);
+        table
+            .create_index(&["vector"], Index::IvfPq(index))
+            .execute()
+            .await
+            .unwrap();
+
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/describe/" => describe_response(&vector_schema()),
+            "/v1/table/my_table/create_index/" => {
+                let expected = serde_json::json!({
+                    "column": "id",
+                    "replace": false,
+                    "index_type": "BTREE",
+                });
+                assert_eq!(request_json(&request), expected);
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        table
+            .create_index(&["id"], Index::Auto)
+            .replace(false)
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_btree_index() {
+        let table = test_table(|request| {
+            let expected = serde_json::json!({
+                "column": "tags",
+                "replace": true,
+                "index_type": "BTREE",
+            });
+            assert_eq!(request_json(&request), expected);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        table
+            .create_index(&["tags"], Index::BTree(Default::default()))
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_fts_index() {
+        let table = test_table(|request| {
+            let expected = serde_json::json!({
+                "column": "text",
+                "replace": true,
+                "index_type": "FTS",
+                "base_tokenizer": "whitespace",
+                "language": "French",
+                "stem": true,
+                "lower_case": true,
+                "remove_stop_words": false,
+            });
+            assert_eq!(request_json(&request), expected);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        let index = FtsIndexBuilder::default()
+            .base_tokenizer(BaseTokenizer::Whitespace)
+            .language("French")
+            .stem(true);
+        table
+            .create_index(&["text"], Index::FTS(index))
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_index_wait() {
+        let polls = Arc::new(Mutex::new(0));
+        let polls_clone = polls.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/create_index/" => http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap(),
+            "/v1/table/my_table/index/vector_idx/stats/" => {
+                let mut polls = polls_clone.lock().unwrap();
+                *polls += 1;
+                if *polls == 1 {
+                    // The index does not exist until the build starts
+                    return http::Response::builder()
+                        .status(404)
+                        .body(String::new())
+                        .unwrap();
+                }
+                let stats = serde_json::json!({
+                    "num_indexed_rows": 100 * (*polls - 1),
+                    "num_unindexed_rows": 300 - 100 * (*polls - 1),
+                    "index_type": "IVF_PQ",
+                    "indices": [],
+                });
+                http::Response::builder()
+                    .status(200)
+                    .body(stats.to_string())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        table
+            .create_index(&["vector"], Index::IvfPq(Default::default()))
+            .wait_timeout(Duration::from_secs(10))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(*polls.lock().unwrap(), 4);
+
+        *polls.lock().unwrap() = 0;
+        let err = table
+            .create_index(&["vector"], Index::IvfPq(Default::default()))
+            .wait_timeout(Duration::from_millis(150))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_index_stats() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/index/vector_idx/stats/" => {
+                let stats = serde_json::json!({
+                    "num_indexed_rows": 1000,
+                    "num_unindexed_rows": 20,
+                    "index_type": "IVF_PQ",
+                    "distance_type": "cosine",
+                    "num_partitions": 8,
+                });
+                http::Response::builder()
+                    .status(200)
+                    .body(stats.to_string())
+                    .unwrap()
+            }
+            "/v1/table/my_table/index/id_idx/stats/" => {
+                let stats = serde_json::json!({
+                    "num_indexed_rows": 1020,
+                    "num_unindexed_rows": 0,
+                    "index_type": "BTREE",
+                });
+                http::Response::builder()
+                    .status(200)
+                    .body(stats.to_string())
+                    .unwrap()
+            }
+            _ => http::Response::builder()
+                .status(404)
+                .body(String::new())
+                .unwrap(),
+        });
+
+        let stats = table.index_stats("vector_idx").await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                num_indexed_rows: 1000,
+                num_unindexed_rows: 20,
+                index_type: IndexType::IvfPq,
+                distance_type: Some(crate::DistanceType::Cosine),
+                num_partitions: Some(8),
+            }
+        );
+        let stats = table.index_stats("id_idx").await.unwrap().unwrap();
+        assert_eq!(stats.index_type, IndexType::BTree);
+        assert_eq!(stats.distance_type, None);
+        assert_eq!(table.index_stats("missing_idx").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_index() {
+        let polls = Arc::new(Mutex::new(0));
+        let polls_clone = polls.clone();
+        let table = test_table(move |request| {
+            let stats = match request.url().path() {
+                "/v1/table/my_table/index/vector_idx/stats/" => {
+                    let mut polls = polls_clone.lock().unwrap();
+                    *polls += 1;
+                    // Ready on the third poll
+                    serde_json::json!({
+                        "num_indexed_rows": 100 * (*polls).min(3),
+                        "num_unindexed_rows": 300 - 100 * (*polls).min(3),
+                        "index_type": "IVF_PQ",
+                    })
+                }
+                "/v1/table/my_table/index/id_idx/stats/" => serde_json::json!({
+                    "num_indexed_rows": 300,
+                    "num_unindexed_rows": 0,
+                    "index_type": "BTREE",
+                }),
+                _ => {
+                    return http::Response::builder()
+                        .status(404)
+                        .body(String::new())
+                        .unwrap()
+                }
+            };
+            http::Response::builder()
+                .status(200)
+                .body(stats.to_string())
+                .unwrap()
+        });
+
+        table
+            .wait_for_index(&["id_idx", "vector_idx"], Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(*polls.lock().unwrap(), 3);
+
+        let err = table
+            .wait_for_index(&["id_idx", "text_idx"], Duration::from_millis(150))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Timeout { message, .. } if message.contains("'text_idx'")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_indices() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/index/list/");
+            let body = serde_json::json!({
+                "indexes": [
+                    {
+                        "index_name": "vector_idx",
+                        "columns": ["vector"],
+                        "index_type": "IVF_HNSW_SQ",
+                        "metric_type": "cosine",
+                        "num_partitions": 4,
+                        "m": 16,
+                        "ef_construction": 150,
+                    },
+                    { "index_name": "id_idx", "columns": ["id"], "index_type": "BTREE" },
+                    { "index_name": "text_idx", "columns": ["text"], "index_type": "FTS" },
+                    { "index_name": "tags_idx", "columns": ["tags"], "index_type": "LABEL_LIST" },
+                ]
+            });
+            http::Response::builder()
+                .status(200)
+                .body(body.to_string())
+                .unwrap()
+        });
+        let indices = table.list_indices().await.unwrap();
+        assert_eq!(indices.len(), 4);
+        assert_eq!(indices[0].name, "vector_idx");
+        assert_eq!(indices[0].columns, vec!["vector".to_string()]);
+        assert_eq!(indices[0].index_type, IndexType::IvfHnswSq);
+        assert_eq!(
+            indices[0].vector_params,
+            Some(VectorIndexParams {
+                distance_type: Some(crate::DistanceType::Cosine),
+                num_partitions: Some(4),
+                num_sub_vectors: None,
+                m: Some(16),
+                ef_construction: Some(150),
+            })
+        );
+        assert_eq!(indices[1].name, "id_idx");
+        assert_eq!(indices[1].index_type, IndexType::BTree);
+        assert_eq!(indices[1].vector_params, None);
+        assert_eq!(indices[2].index_type, IndexType::FTS);
+        assert_eq!(indices[3].index_type, IndexType::LabelList);
+        assert_eq!(indices[3].columns, vec!["tags".to_string()]);
+    }
+
+    fn request_query(request: &reqwest::Request) -> Vec<(String, String)> {
+        request
+            .url()
+            .query_pairs()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert() {
+        let batch = some_batch();
+        let expected_batch = batch.clone();
+        let table = test_table(move |request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/merge_insert/");
+            assert_eq!(
+                request.headers().get(CONTENT_TYPE).unwrap(),
+                ARROW_STREAM_CONTENT_TYPE
+            );
+            let expected_query = [
+                ("on", "a"),
+                ("when_matched_update_all", "true"),
+                ("when_matched_update_all_filt", "target.a < source.a"),
+                ("when_not_matched_insert_all", "true"),
+                ("when_not_matched_by_source_delete", "false"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+            assert_eq!(request_query(&request), expected_query);
+            let body = request.body().unwrap().as_bytes().unwrap();
+            assert_eq!(decode_ipc(body), vec![expected_batch.clone()]);
+            http::Response::builder()
+                .status(200)
+                .body(
+                    r#"{"num_inserted_rows": 2, "num_updated_rows": 1, "version": 4}"#.to_string(),
+                )
+                .unwrap()
+        });
+
+        let mut builder = table.merge_insert(&["a"]);
+        builder
+            .when_matched_update_all(Some("target.a < source.a".to_string()))
+            .when_not_matched_insert_all();
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let stats = builder.execute(Box::new(reader)).await.unwrap();
+        assert_eq!(
+            stats,
+            MergeResult {
+                num_inserted: Some(2),
+                num_updated: Some(1),
+                // The server didn't report it
+                num_deleted: None,
+                version: Some(4),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert_delete_not_matched_by_source() {
+        let batch = some_batch();
+        let table = test_table(|request| {
+            let expected_query = [
+                ("on", "a"),
+                ("when_matched_update_all", "false"),
+                ("when_not_matched_insert_all", "false"),
+                ("when_not_matched_by_source_delete", "true"),
+                ("when_not_matched_by_source_delete_filt", "a > 100"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+            assert_eq!(request_query(&request), expected_query);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        let mut builder = table.merge_insert(&["a"]);
+        builder.when_not_matched_by_source_delete(Some("a > 100".to_string()));
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        builder.execute(Box::new(reader)).await.unwrap();
+
+        // Without a filter every row missing from the source is deleted
+        let table = test_table(|request| {
+            let query = request_query(&request);
+            assert!(query.contains(&(
+                "when_not_matched_by_source_delete".to_string(),
+                "true".to_string()
+            )));
+            assert!(!query
+                .iter()
+                .any(|(key, _)| key == "when_not_matched_by_source_delete_filt"));
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        let mut builder = table.merge_insert(&["a"]);
+        builder.when_not_matched_by_source_delete(None);
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        builder.execute(Box::new(reader)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert_delete_matched() {
+        let batch = some_batch();
+        let table = test_table(|request| {
+            let expected_query = [
+                ("on", "a"),
+                ("when_matched_update_all", "false"),
+                ("when_matched_delete", "true"),
+                ("when_matched_delete_filt", "source.a > 100"),
+                ("when_not_matched_insert_all", "false"),
+                ("when_not_matched_by_source_delete", "false"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+            assert_eq!(request_query(&request), expected_query);
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        let mut builder = table.merge_insert(&["a"]);
+        builder.when_matched_delete(Some("source.a > 100".to_string()));
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        builder.execute(Box::new(reader)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let schema = Schema::new(vec![Field::new("a", DataType::Int32, false)]);
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        let table = test_table(move |request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/describe/");
+            // The first describe is the starting version, the second sees no change
+            let version = match polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 | 1 => 1,
+                _ => 4,
+            };
+            let schema = JsonSchema::try_from(&schema).unwrap();
+            let body = serde_json::json!({ "version": version, "schema": schema }).to_string();
+            http::Response::builder().status(200).body(body).unwrap()
+        });
+        let mut changes = Box::pin(table.watch(Duration::from_millis(1)));
+        let event = futures::StreamExt::next(&mut changes)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            crate::table::ChangeEvent {
+                old_version: 1,
+                new_version: 4,
+                operations: vec![],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkout() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                let version = request
+                    .body()
+                    .map(|_| request_json(&request)["version"].as_u64().unwrap());
+                // Version 1 has been cleaned up and version 3 is the latest
+                if version == Some(1) {
+                    return http::Response::builder().status(404).body(vec![]).unwrap();
+                }
+                let schema = JsonSchema::try_from(&vector_schema()).unwrap();
+                let body = serde_json::json!({ "version": version.unwrap_or(3), "schema": schema });
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            "/v1/table/my_table/query/" => {
+                assert_eq!(request_json(&request)["version"], 2);
+                ipc_response(vec![some_batch()])
+            }
+            "/v1/table/my_table/restore/" => {
+                assert_eq!(request_json(&request), serde_json::json!({ "version": 2 }));
+                http::Response::builder().status(200).body(vec![]).unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        assert_eq!(table.version().await.unwrap(), 3);
+
+        let err = table.checkout(4).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        let err = table.checkout(1).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("does not exist")),
+            "{:?}",
+            err
+        );
+        assert_eq!(table.version().await.unwrap(), 3);
+
+        table.checkout(2).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+        table.query().execute().await.unwrap();
+        let err = table.delete("id = 1").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("checked out")),
+            "{:?}",
+            err
+        );
+
+        table.checkout_latest().await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 3);
+
+        let err = table.restore().await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        table.checkout(2).await.unwrap();
+        table.restore().await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                let schema = JsonSchema::try_from(&vector_schema()).unwrap();
+                let body = serde_json::json!({
+                    "version": 3,
+                    "schema": schema,
+                    "stats": {
+                        "total_bytes": 4096,
+                        "num_rows": 120,
+                        "num_deleted_rows": 5,
+                        "fragment_stats": {
+                            "num_fragments": 2,
+                            "num_small_fragments": 2,
+                            "lengths": {
+                                "min": 20, "max": 100, "mean": 60,
+                                "p25": 20, "p50": 20, "p75": 100, "p99": 100,
+                            },
+                        },
+                    },
+                });
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            "/v1/table/my_table/version/list/" => {
+                let body = serde_json::json!({ "versions": [
+                    { "version": 3, "timestamp": "2024-06-03T00:00:00Z" },
+                ]});
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            "/v1/table/my_table/index/list/" => http::Response::builder()
+                .status(200)
+                .body(
+                    serde_json::json!({ "indexes": [] })
+                        .to_string()
+                        .into_bytes(),
+                )
+                .unwrap(),
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let stats = table.stats().await.unwrap();
+        assert_eq!(stats.version, 3);
+        assert_eq!(
+            stats.last_write,
+            DateTime::parse_from_rfc3339("2024-06-03T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc)
+        );
+        assert_eq!(stats.total_bytes, 4096);
+        assert_eq!(stats.num_rows, 120);
+        assert_eq!(stats.num_deleted_rows, 5);
+        assert_eq!(stats.fragment_stats.num_fragments, 2);
+        assert_eq!(stats.fragment_stats.lengths.max, 100);
+        assert!(stats.index_coverage.is_empty());
+
+        // Older servers don't report statistics
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                let schema = JsonSchema::try_from(&vector_schema()).unwrap();
+                let body = serde_json::json!({ "version": 3, "schema": schema });
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        let err = table.stats().await.unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_tags() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/tags/list/" => {
+                let body = serde_json::json!({ "tags": {
+                    "prod": { "version": 2, "manifest_size": 100 },
+                }});
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            "/v1/table/my_table/tags/create/" => {
+                assert_eq!(
+                    request_json(&request),
+                    serde_json::json!({ "tag": "dev", "version": 3 })
+                );
+                http::Response::builder().status(200).body(vec![]).unwrap()
+            }
+            "/v1/table/my_table/tags/update/" => {
+                assert_eq!(
+                    request_json(&request),
+                    serde_json::json!({ "tag": "prod", "version": 3 })
+                );
+                http::Response::builder().status(200).body(vec![]).unwrap()
+            }
+            "/v1/table/my_table/tags/delete/" => {
+                assert_eq!(request_json(&request), serde_json::json!({ "tag": "prod" }));
+                http::Response::builder().status(200).body(vec![]).unwrap()
+            }
+            "/v1/table/my_table/describe/" => {
+                let schema = JsonSchema::try_from(&vector_schema()).unwrap();
+                let body = serde_json::json!({ "version": 3, "schema": schema });
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let tags = table.tags();
+        assert_eq!(
+            tags.list().await.unwrap(),
+            HashMap::from([("prod".to_string(), 2)])
+        );
+        tags.create("dev", 3).await.unwrap();
+        tags.update("prod", 3).await.unwrap();
+        tags.delete("prod").await.unwrap();
+
+        table.checkout_tag("prod").await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_at() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/version/list/" => {
+                let body = serde_json::json!({ "versions": [
+                    { "version": 3, "timestamp": "2024-06-03T00:00:00Z", "metadata": {} },
+                    { "version": 2, "timestamp": "2024-06-02T00:00:00Z", "metadata": { "note": "two" } },
+                ]});
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            "/v1/table/my_table/describe/" => {
+                let schema = JsonSchema::try_from(&vector_schema()).unwrap();
+                let body = serde_json::json!({ "version": 3, "schema": schema });
+                http::Response::builder()
+                    .status(200)
+                    .body(body.to_string().into_bytes())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let versions = table.list_versions().await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(versions[0].metadata["note"], "two");
+
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        table.checkout_at(at("2024-06-02T12:00:00Z")).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+        table.checkout_at(at("2024-06-03T00:00:00Z")).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 3);
+        let err = table
+            .checkout_at(at("2024-06-01T23:59:59Z"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_schema_evolution() {
+        let describes = Arc::new(Mutex::new(0));
+        let describes_clone = describes.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/describe/" => {
+                *describes_clone.lock().unwrap() += 1;
+                let schema = Schema::new(vec![
+                    Field::new("id", DataType::Int64, true),
+                    Field::new("double_id", DataType::Int64, true),
+                ]);
+                describe_response(&schema)
+            }
+            "/v1/table/my_table/add_columns/" => {
+                let expected = serde_json::json!({
+                    "new_columns": [{ "name": "double_id", "expression": "id * 2" }]
+                });
+                assert_eq!(request_json(&request), expected);
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            "/v1/table/my_table/alter_columns/" => {
+                let body = request_json(&request);
+                if body["alterations"][0]["data_type"] == serde_json::json!({ "type": "string" }) {
+                    return http::Response::builder()
+                        .status(400)
+                        .body("cannot cast int64 to string".to_string())
+                        .unwrap();
+                }
+                let expected = serde_json::json!({
+                    "alterations": [{
+                        "path": "id",
+                        "rename": "key",
+                        "nullable": true,
+                        "data_type": { "type": "int64" },
+                    }]
+                });
+                assert_eq!(body, expected);
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            "/v1/table/my_table/drop_columns/" => {
+                let expected = serde_json::json!({ "columns": ["double_id"] });
+                assert_eq!(request_json(&request), expected);
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        table
+            .add_columns(
+                NewColumnTransform::SqlExpressions(vec![(
+                    "double_id".to_string(),
+                    "id * 2".to_string(),
+                )]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(*describes.lock().unwrap(), 1);
+        // The schema was refreshed by add_columns so no further describe is needed
+        let schema = table.schema().await.unwrap();
+        assert_eq!(schema.field(1).name(), "double_id");
+        assert_eq!(*describes.lock().unwrap(), 1);
+
+        let mut alteration = ColumnAlteration::new("id".to_string());
+        alteration.rename = Some("key".to_string());
+        alteration.nullable = Some(true);
+        alteration.data_type = Some(DataType::Int64);
+        table.alter_columns(&[alteration]).await.unwrap();
+
+        let mut alteration = ColumnAlteration::new("id".to_string());
+        alteration.data_type = Some(DataType::Utf8);
+        let err = table.alter_columns(&[alteration]).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("cannot cast int64 to string")),
+            "{:?}",
+            err
+        );
+
+        table.drop_columns(&["double_id"]).await.unwrap();
+        assert_eq!(*describes.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_optimize() {
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let targets_clone = targets.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/compact/" => {
+                let body = request_json(&request);
+                let target = body["target_rows_per_fragment"].as_u64().unwrap() as usize;
+                targets_clone.lock().unwrap().push(target);
+                // Older servers don't report the fragments
+                let metrics = if target == 1024 {
+                    serde_json::json!({
+                        "fragments_removed": 4,
+                        "fragments_added": 1,
+                        "files_removed": 5,
+                        "files_added": 1,
+                        "removed_fragment_ids": [0, 1, 2, 3],
+                        "added_fragment_ids": [4],
+                    })
+                } else {
+                    serde_json::json!({
+                        "fragments_removed": 0,
+                        "fragments_added": 0,
+                        "files_removed": 0,
+                        "files_added": 0,
+                    })
+                };
+                http::Response::builder()
+                    .status(200)
+                    .body(metrics.to_string())
+                    .unwrap()
+            }
+            "/v1/table/my_table/cleanup/" => {
+                let expected = serde_json::json!({
+                    "older_than_seconds": 7 * 24 * 60 * 60,
+                    "delete_unverified": false,
+                });
+                assert_eq!(request_json(&request), expected);
+                let removed = serde_json::json!({ "bytes_removed": 2048, "old_versions": 3 });
+                http::Response::builder()
+                    .status(200)
+                    .body(removed.to_string())
+                    .unwrap()
+            }
+            "/v1/table/my_table/optimize_indices/" => {
+                assert_eq!(
+                    request_json(&request),
+                    serde_json::json!({ "num_indices_to_merge": 1 })
+                );
+                http::Response::builder()
+                    .status(200)
+                    .body(String::new())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+
+        let stats = table
+            .optimize(OptimizeAction::Compact {
+                options: CompactionOptions {
+                    target_rows_per_fragment: 1024,
+                    ..Default::default()
+                },
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        let compaction = stats.compaction.unwrap();
+        assert_eq!(compaction.fragments_removed, 4);
+        assert_eq!(compaction.fragments_added, 1);
+        assert_eq!(
+            stats.compacted_fragments,
+            Some(CompactedFragments {
+                removed: vec![0, 1, 2, 3],
+                added: vec![4],
+            })
+        );
+        assert!(stats.prune.is_none());
+
+        let stats = table.optimize(OptimizeAction::All).await.unwrap();
+        assert!(stats.compaction.is_some());
+        assert!(stats.compacted_fragments.is_none());
+        let prune = stats.prune.unwrap();
+        assert_eq!(prune.bytes_removed, 2048);
+        assert_eq!(prune.old_versions, 3);
+
+        let default_target = CompactionOptions::default().target_rows_per_fragment;
+        assert_eq!(*targets.lock().unwrap(), vec![1024, default_target]);
+    }
+
+    #[tokio::test]
+    async fn test_optimize_wait() {
+        let polls = Arc::new(Mutex::new(0));
+        let polls_clone = polls.clone();
+        let table = test_table(move |request| match request.url().path() {
+            "/v1/table/my_table/compact/" => http::Response::builder()
+                .status(202)
+                .body(r#"{"job_id": "job1"}"#.to_string())
+                .unwrap(),
+            "/v1/table/my_table/compact/status/" => {
+                assert_eq!(
+                    request_json(&request),
+                    serde_json::json!({ "job_id": "job1" })
+                );
+                let mut polls = polls_clone.lock().unwrap();
+                *polls += 1;
+                let status = if *polls == 3 {
+                    serde_json::json!({
+                        "status": "succeeded",
+                        "result": {
+                            "fragments_removed": 4,
+                            "fragments_added": 1,
+                            "files_removed": 5,
+                            "files_added": 1,
+                        },
+                    })
+                } else {
+                    serde_json::json!({ "status": "running" })
+                };
+                http::Response::builder()
+                    .status(200)
+                    .body(status.to_string())
+                    .unwrap()
+            }
+            path => panic!("Unexpected path: {}", path),
+        });
+        let compact = || OptimizeAction::Compact {
+            options: CompactionOptions::default(),
+            remap_options: None,
+        };
+
+        // Without waiting the job is left running
+        let stats = table.optimize(compact()).await.unwrap();
+        assert!(stats.compaction.is_none());
+        assert_eq!(*polls.lock().unwrap(), 0);
+
+        let stats = table
+            .optimize_and_wait(compact(), Duration::from_secs(10))
+            .await
+            .unwrap();
+        let compaction = stats.compaction.unwrap();
+        assert_eq!(compaction.fragments_removed, 4);
+        assert_eq!(compaction.files_added, 1);
+        assert_eq!(*polls.lock().unwrap(), 3);
+
+        // Jobs that never finish time out
+        let table = test_table(|request| {
+            let status = match request.url().path() {
+                "/v1/table/my_table/compact/" => 202,
+                "/v1/table/my_table/compact/status/" => 200,
+                path => panic!("Unexpected path: {}", path),
+            };
+            let body = if status == 202 {
+                r#"{"job_id": "job1"}"#
+            } else {
+                r#"{"status": "running"}"#
+            };
+            http::Response::builder()
+                .status(status)
+                .body(body.to_string())
+                .unwrap()
+        });
+        let err = table
+            .optimize_and_wait(compact(), Duration::from_millis(150))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Timeout { message, .. } if message.contains("job1")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_optimize_failed() {
+        let table = test_table(|request| match request.url().path() {
+            "/v1/table/my_table/optimize_indices/" => http::Response::builder()
+                .status(202)
+                .body(r#"{"job_id": "job1"}"#.to_string())
+                .unwrap(),
+            "/v1/table/my_table/optimize_indices/status/" => http::Response::builder()
+                .status(200)
+                .body(r#"{"status": "failed", "error": "out of memory"}"#.to_string())
+                .unwrap(),
+            path => panic!("Unexpected path: {}", path),
+        });
+        let err = table
+            .optimize_and_wait(
+                OptimizeAction::Index(OptimizeOptions::default()),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Runtime { message } if message.contains("out of memory")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_in_background() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/cleanup/");
+            http::Response::builder()
+                .status(202)
+                .body(r#"{"job_id": "job1"}"#.to_string())
+                .unwrap()
+        });
+        let stats = table
+            .cleanup_old_versions(chrono::Duration::zero(), false)
+            .await
+            .unwrap();
+        assert!(stats.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_optimize_not_supported() {
+        let table = test_table(|request| {
+            assert_eq!(request.url().path(), "/v1/table/my_table/cleanup/");
+            http::Response::builder()
+                .status(501)
+                .body(String::new())
+                .unwrap()
+        });
+        let err = table
+            .optimize(OptimizeAction::Prune {
+                older_than: None,
+                delete_unverified: None,
+                compaction: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let table = test_table(|request| {
+            assert_eq!(request.method(), "POST");
+            assert_eq!(request.url().path(), "/v1/table/my_table/update/");
+            let body = request.body().unwrap().as_bytes().unwrap();
+            let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+            let expected = serde_json::json!({
+                "updates": [["a", "a + 1"], ["b", "'x'"]],
+                "only_if": "a > 10",
+            });
+            assert_eq!(body, expected);
+            http::Response::builder()
+                .status(200)
+                .body(r#"{"num_updated_rows": 7}"#.to_string())
+                .unwrap()
+        });
+        let num_updated = table
+            .update()
+            .only_if("a > 10")
+            .column("a", "a + 1")
+            .column("b", "'x'")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(num_updated, Some(7));
+
+        let table = test_table::<String>(|_| panic!("Updates returning values should not be sent"));
+        let err = table
+            .update()
+            .column("a", "a + 1")
+            .returning(&["a"])
+            .execute_returning()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+
+        let table = test_table::<String>(|_| panic!("Updates without columns should not be sent"));
+        let err = table
+            .update()
+            .only_if("a > 10")
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let table = test_table(|request| {
+            assert_eq!(request.method(), "POST");
+            assert_eq!(request.url().path(), "/v1/table/my_table/delete/");
+            assert_eq!(
+                request.headers().get(CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            let body = request.body().unwrap().as_bytes().unwrap();
+            let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(body, serde_json::json!({ "predicate": "id in (1, 2, 3)" }));
+            http::Response::builder()
+                .status(200)
+                .body(r#"{"num_deleted_rows": 3}"#.to_string())
+                .unwrap()
+        });
+        assert_eq!(table.delete("id in (1, 2, 3)").await.unwrap(), Some(3));
+
+        // Servers that don't count the rows return an empty response
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(200)
+                .body(String::new())
+                .unwrap()
+        });
+        assert_eq!(table.delete("id in (1, 2, 3)").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delete_rows() {
+        let deletes = Arc::new(Mutex::new(Vec::new()));
+        let deletes_clone = deletes.clone();
+        let table = test_table(move |request| {
+            let body = request_json(&request);
+            let predicate = body["predicate"].as_str().unwrap().to_string();
+            let num_ids = predicate.matches(',').count() + 1;
+            match request.url().path() {
+                // The rows of the last ids have already been deleted
+                "/v1/table/my_table/count_rows/" => {
+                    let count = if predicate.contains("2499") {
+                        num_ids - 100
+                    } else {
+                        num_ids
+                    };
+                    http::Response::builder()
+                        .status(200)
+                        .body(count.to_string())
+                        .unwrap()
+                }
+                "/v1/table/my_table/delete/" => {
+                    deletes_clone.lock().unwrap().push(num_ids);
+                    http::Response::builder()
+                        .status(200)
+                        .body(String::new())
+                        .unwrap()
+                }
+                path => panic!("Unexpected request to {}", path),
+            }
+        });
+
+        let row_ids = (0..2500).chain(0..10).collect::<Vec<u64>>();
+        let stats = table.delete_rows(&row_ids).await.unwrap();
+        assert_eq!(
+            stats,
+            DeleteRowsStats {
+                num_deleted: 2400,
+                num_missing: 100,
+            }
+        );
+        assert_eq!(*deletes.lock().unwrap(), vec![1000, 1000, 500]);
+
+        let table = test_table::<String>(|_| panic!("Nothing should be sent"));
+        let stats = table.delete_rows(&[]).await.unwrap();
+        assert_eq!(stats, DeleteRowsStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_delete_errors() {
+        let table = test_table::<String>(|_| panic!("Empty predicates should not be sent"));
+        let err = table.delete("  ").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        let table = test_table(|_| {
+            http::Response::builder()
+                .status(400)
+                .body("Invalid filter: id ==".to_string())
+                .unwrap()
+        });
+        let err = table.delete("id ==").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message == "Invalid filter: id =="),
+            "{:?}",
+            err
+        );
+
+        // Nothing listens on port 1, so the connection is refused
+        let no_retries = ClientConfig {
+            retry_config: RetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let client = RestfulLanceDbClient::try_new(
+            "db://my_db",
+            "api_key",
+            "us-east-1",
+            Some("http://127.0.0.1:1".to_string()),
+            no_retries,
+        )
+        .unwrap();
+        let table = Table::new(Arc::new(RemoteTable::new(client, "my_table".to_string())));
+        let err = table.delete("id = 1").await.unwrap_err();
+        assert!(matches!(err, Error::Http { .. }), "{:?}", err);
     }
 }
@@ -1,15 +1,24 @@
-use std::io::Cursor;
+use std::io::{Cursor, Read, Write};
 
-use arrow_array::RecordBatchReader;
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use bytes::Bytes;
+use datafusion_common::DataFusionError;
+use datafusion_physical_plan::{stream::RecordBatchStreamAdapter, SendableRecordBatchStream};
+use futures::{Stream, StreamExt};
+use lance::arrow::json::JsonSchema;
+use reqwest::{Body, Response};
+use tokio::sync::{mpsc, oneshot};
 
-use crate::Result;
+use super::client::Compression;
+use crate::{Error, Result};
 
 pub fn batches_to_ipc_bytes(batches: impl RecordBatchReader) -> Result<Vec<u8>> {
     const WRITE_BUF_SIZE: usize = 4096;
     let buf = Vec::with_capacity(WRITE_BUF_SIZE);
     let mut buf = Cursor::new(buf);
     {
-        let mut writer = arrow_ipc::writer::FileWriter::try_new(&mut buf, &batches.schema())?;
+        let mut writer = arrow_ipc::writer::StreamWriter::try_new(&mut buf, &batches.schema())?;
 
         for batch in batches {
             let batch = batch?;
@@ -19,3 +28,498 @@ pub fn batches_to_ipc_bytes(batches: impl RecordBatchReader) -> Result<Vec<u8>>
     }
     Ok(buf.into_inner())
 }
+
+/// A [`Write`] that sends what is written to a channel in chunks
+struct ChannelWriter {
+    chunks: mpsc::Sender<Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    /// The size of the chunks sent to the channel
+    const CHUNK_SIZE: usize = 1024 * 1024;
+
+    fn send_buf(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(Self::CHUNK_SIZE));
+        self.chunks
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "upload cancelled"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= Self::CHUNK_SIZE {
+            self.send_buf()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buf()
+    }
+}
+
+/// A [`Write`] that optionally compresses what is written
+enum CompressWriter<W: Write> {
+    Plain(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressWriter<W> {
+    fn new(writer: W, compression: Option<Compression>) -> std::io::Result<Self> {
+        Ok(match compression {
+            None => Self::Plain(writer),
+            Some(Compression::Gzip(level)) => Self::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::new(level),
+            )),
+            Some(Compression::Zstd(level)) => Self::Zstd(zstd::Encoder::new(writer, level)?),
+        })
+    }
+
+    /// Write any remaining compressed data and flush the inner writer
+    fn finish(self) -> std::io::Result<W> {
+        let mut writer = match self {
+            Self::Plain(writer) => writer,
+            Self::Gzip(encoder) => encoder.finish()?,
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Compress a buffer that is already fully in memory
+fn compress_bytes(bytes: Vec<u8>, compression: Option<Compression>) -> Result<Vec<u8>> {
+    if compression.is_none() {
+        return Ok(bytes);
+    }
+    let compress = || {
+        let mut writer = CompressWriter::new(Vec::with_capacity(bytes.len() / 2), compression)?;
+        writer.write_all(&bytes)?;
+        writer.finish()
+    };
+    Ok(compress().map_err(ArrowError::from)?)
+}
+
+/// Encode batches as an Arrow IPC stream without buffering the whole stream
+///
+/// This is the streaming counterpart of [`batches_to_ipc_bytes`].  Batches are
+/// read, encoded, and compressed (if `compression` is set) on a blocking thread
+/// as the returned stream is polled, so at most a few chunks are held in memory
+/// at once.
+pub fn batches_to_ipc_stream(
+    batches: impl RecordBatchReader + Send + 'static,
+    compression: Option<Compression>,
+) -> impl Stream<Item = Result<Bytes>> + Send + Sync + 'static {
+    // Lets the next chunk be encoded while the previous one is being sent
+    const CHUNK_BUFFER: usize = 2;
+
+    let (chunk_tx, mut chunk_rx) = mpsc::channel(CHUNK_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter {
+            chunks: chunk_tx.clone(),
+            buf: Vec::with_capacity(ChannelWriter::CHUNK_SIZE),
+        };
+        let result = (|| {
+            let writer = CompressWriter::new(writer, compression).map_err(ArrowError::from)?;
+            let mut writer = arrow_ipc::writer::StreamWriter::try_new(writer, &batches.schema())?;
+            for batch in batches {
+                writer.write(&batch?)?;
+            }
+            writer.into_inner()?.finish().map_err(ArrowError::from)?;
+            Result::Ok(())
+        })();
+        if let Err(err) = result {
+            // If the receiver is gone then the upload was cancelled and there is no one to tell
+            let _ = chunk_tx.blocking_send(Err(err));
+        }
+    });
+    futures::stream::poll_fn(move |cx| chunk_rx.poll_recv(cx))
+}
+
+/// Uploads up to this size are buffered rather than streamed
+///
+/// Buffered uploads are sent with a content length and can be retried, which
+/// isn't worth giving up unless the data is large.
+pub const MAX_BUFFERED_UPLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+/// Encode batches as an Arrow IPC request body
+///
+/// The size of the data isn't known up front so batches are read until they
+/// exceed `max_buffered_size` bytes.  If the data ends before that then the body
+/// is buffered, otherwise it is streamed with [`batches_to_ipc_stream`].  Either
+/// way the body is compressed with `compression`, if set.
+pub async fn batches_to_ipc_body(
+    batches: Box<dyn RecordBatchReader + Send>,
+    max_buffered_size: usize,
+    compression: Option<Compression>,
+) -> Result<Body> {
+    // TODO: https://github.com/lancedb/lancedb/issues/1026
+    // We should accept data from an async source.  In the meantime, spawn this as
+    // blocking so a slow source doesn't block the runtime.
+    tokio::task::spawn_blocking(move || {
+        let schema = batches.schema();
+        let mut batches = batches;
+        let mut peeked = Vec::new();
+        let mut size = 0;
+        while size <= max_buffered_size {
+            match batches.next() {
+                Some(batch) => {
+                    let batch = batch?;
+                    size += batch.get_array_memory_size();
+                    peeked.push(batch);
+                }
+                None => {
+                    let reader = RecordBatchIterator::new(peeked.into_iter().map(Ok), schema);
+                    let bytes = compress_bytes(batches_to_ipc_bytes(reader)?, compression)?;
+                    return Ok(Body::from(bytes));
+                }
+            }
+        }
+        let reader = RecordBatchIterator::new(peeked.into_iter().map(Ok).chain(batches), schema);
+        Ok(Body::wrap_stream(batches_to_ipc_stream(
+            reader,
+            compression,
+        )))
+    })
+    .await
+    .unwrap()
+}
+
+/// Serialize a data type in the same JSON format used for schemas
+pub fn data_type_to_json(data_type: &DataType) -> Result<serde_json::Value> {
+    // lance only exposes the JSON representation of whole schemas
+    let schema = Schema::new(vec![Field::new("", data_type.clone(), true)]);
+    let schema =
+        serde_json::to_value(JsonSchema::try_from(&schema)?).map_err(|e| Error::Runtime {
+            message: format!("failed to serialize data type {}: {}", data_type, e),
+        })?;
+    Ok(schema["fields"][0]["type"].clone())
+}
+
+/// A blocking [`Read`] over chunks of a response body that arrive on a channel
+struct ChunkReader {
+    chunks: mpsc::Receiver<std::result::Result<Bytes, reqwest::Error>>,
+    current: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(Ok(chunk)) => self.current = chunk,
+                Some(Err(err)) => return Err(std::io::Error::new(std::io::ErrorKind::Other, err)),
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Decode a response body containing an Arrow IPC stream into a stream of batches
+///
+/// The body is decoded as it arrives so large results are never fully buffered
+/// in memory.  This resolves once the schema has been read.  Dropping the returned
+/// stream aborts the request.
+pub async fn ipc_response_to_stream(mut response: Response) -> Result<SendableRecordBatchStream> {
+    // Enough to keep the network busy while the previous chunks are decoded
+    const CHUNK_BUFFER: usize = 16;
+
+    // The sender is owned by the returned stream so this resolves when it is dropped
+    let (cancel_tx, mut cancel_rx) = oneshot::channel::<()>();
+    let (chunk_tx, chunk_rx) = mpsc::channel(CHUNK_BUFFER);
+    tokio::spawn(async move {
+        loop {
+            let chunk = tokio::select! {
+                // Dropping the response closes the connection
+                _ = &mut cancel_rx => break,
+                chunk = response.chunk() => chunk,
+            };
+            match chunk {
+                Ok(Some(chunk)) => {
+                    if chunk_tx.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    let _ = chunk_tx.send(Err(err)).await;
+                    break;
+                }
+            }
+        }
+    });
+
+    let (schema_tx, schema_rx) = oneshot::channel();
+    let (batch_tx, batch_rx) = mpsc::channel::<std::result::Result<RecordBatch, ArrowError>>(1);
+    tokio::task::spawn_blocking(move || {
+        let reader = ChunkReader {
+            chunks: chunk_rx,
+            current: Bytes::new(),
+        };
+        match arrow_ipc::reader::StreamReader::try_new(reader, None) {
+            Ok(reader) => {
+                if schema_tx.send(Ok(reader.schema())).is_err() {
+                    return;
+                }
+                for batch in reader {
+                    // The receiver is gone if the caller dropped the stream
+                    if batch_tx.blocking_send(batch).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = schema_tx.send(Err(err));
+            }
+        }
+    });
+
+    let schema = schema_rx.await.map_err(|_| Error::Runtime {
+        message: "IPC decoder stopped before reading the schema".to_string(),
+    })??;
+    let batches = futures::stream::unfold((batch_rx, cancel_tx), |(mut rx, cancel)| async move {
+        rx.recv().await.map(|batch| (batch, (rx, cancel)))
+    })
+    .map(|batch| batch.map_err(DataFusionError::from));
+    Ok(Box::pin(RecordBatchStreamAdapter::new(schema, batches)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use arrow_array::Int32Array;
+    use futures::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn batch(start: i32) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let values = Int32Array::from_iter_values(start..start + 10);
+        RecordBatch::try_new(schema, vec![Arc::new(values)]).unwrap()
+    }
+
+    /// Encode the batches as an IPC stream, split into the schema and first batch,
+    /// and everything after that
+    fn ipc_parts(batches: &[RecordBatch]) -> (Vec<u8>, Vec<u8>) {
+        let encode = |batches: &[RecordBatch]| {
+            let reader = arrow_array::RecordBatchIterator::new(
+                batches.iter().cloned().map(Ok),
+                batches[0].schema(),
+            );
+            batches_to_ipc_bytes(reader).unwrap()
+        };
+        // Every stream ends with an 8 byte end of stream marker
+        let split = encode(&batches[..1]).len() - 8;
+        let mut first = encode(batches);
+        let rest = first.split_off(split);
+        (first, rest)
+    }
+
+    /// Serve a single response that sends `first`, waits for `delay`, then sends
+    /// `rest`.  The returned receiver resolves once the client disconnects.
+    async fn serve_in_parts(
+        first: Vec<u8>,
+        delay: Duration,
+        rest: Vec<u8>,
+    ) -> (String, oneshot::Receiver<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (closed_tx, closed_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 64 * 1024];
+            let _ = socket.read(&mut buf).await;
+            // Without a content length the body ends when the connection is closed
+            let head = "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&first).await.unwrap();
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {
+                    let _ = socket.write_all(&rest).await;
+                }
+                // A read finishes when the client closes the connection
+                _ = socket.read(&mut buf) => {
+                    let _ = closed_tx.send(());
+                }
+            }
+        });
+        (url, closed_rx)
+    }
+
+    fn decode(body: &[u8]) -> Vec<RecordBatch> {
+        arrow_ipc::reader::StreamReader::try_new(body, None)
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap()
+    }
+
+    fn reader(batches: Vec<RecordBatch>) -> Box<dyn RecordBatchReader + Send> {
+        let schema = batches[0].schema();
+        Box::new(RecordBatchIterator::new(
+            batches.into_iter().map(Ok),
+            schema,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_batches_to_ipc_stream() {
+        // Large enough to be split into several chunks
+        let schema = batch(0).schema();
+        let batches = (0..5)
+            .map(|i| {
+                let values = Int32Array::from_iter_values(i * 100_000..(i + 1) * 100_000);
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let chunks = batches_to_ipc_stream(reader(batches.clone()), None)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(decode(&chunks.concat()), batches);
+
+        // Errors from the source are passed on
+        let schema = batch(0).schema();
+        let failing = RecordBatchIterator::new(
+            vec![
+                Ok(batch(0)),
+                Err(ArrowError::ComputeError("source failed".to_string())),
+            ],
+            schema,
+        );
+        let err = batches_to_ipc_stream(failing, None)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("source failed"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_batches_to_ipc_body() {
+        let batches = vec![batch(0), batch(10)];
+        let body = batches_to_ipc_body(reader(batches.clone()), MAX_BUFFERED_UPLOAD_SIZE, None)
+            .await
+            .unwrap();
+        assert_eq!(decode(body.as_bytes().unwrap()), batches);
+
+        // Data larger than the limit is streamed
+        let body = batches_to_ipc_body(reader(batches.clone()), 1, None)
+            .await
+            .unwrap();
+        assert!(body.as_bytes().is_none());
+    }
+
+    fn decompress(body: &[u8], compression: Compression) -> Vec<u8> {
+        let mut decompressed = Vec::new();
+        match compression {
+            Compression::Gzip(_) => {
+                flate2::read::GzDecoder::new(body)
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+            }
+            Compression::Zstd(_) => {
+                zstd::Decoder::new(body)
+                    .unwrap()
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+            }
+        }
+        decompressed
+    }
+
+    #[tokio::test]
+    async fn test_compressed_upload() {
+        // Repetitive data that compresses well
+        let batches = (0..20).map(|_| batch(0)).collect::<Vec<_>>();
+        let uncompressed = batches_to_ipc_bytes(reader(batches.clone())).unwrap();
+
+        for compression in [Compression::Gzip(6), Compression::Zstd(3)] {
+            let body = batches_to_ipc_body(
+                reader(batches.clone()),
+                MAX_BUFFERED_UPLOAD_SIZE,
+                Some(compression),
+            )
+            .await
+            .unwrap();
+            let body = body.as_bytes().unwrap();
+            assert!(body.len() < uncompressed.len() / 4, "{:?}", compression);
+            assert_eq!(decompress(body, compression), uncompressed);
+
+            let chunks = batches_to_ipc_stream(reader(batches.clone()), Some(compression))
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(decode(&decompress(&chunks.concat(), compression)), batches);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ipc_response_incremental() {
+        let batches = vec![batch(0), batch(10), batch(20)];
+        let (first, rest) = ipc_parts(&batches);
+        let delay = Duration::from_millis(500);
+        let (url, _) = serve_in_parts(first, delay, rest).await;
+
+        let start = Instant::now();
+        let response = reqwest::get(url).await.unwrap();
+        let mut stream = ipc_response_to_stream(response).await.unwrap();
+        let first_batch = stream.try_next().await.unwrap().unwrap();
+        // The first batch is available before the rest of the body is sent
+        assert!(start.elapsed() < delay);
+        assert_eq!(first_batch, batches[0]);
+
+        let remaining = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert!(start.elapsed() >= delay);
+        assert_eq!(remaining, batches[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_ipc_response_cancel() {
+        let batches = vec![batch(0), batch(10)];
+        let (first, rest) = ipc_parts(&batches);
+        let (url, closed) = serve_in_parts(first, Duration::from_secs(30), rest).await;
+
+        let response = reqwest::get(url).await.unwrap();
+        let mut stream = ipc_response_to_stream(response).await.unwrap();
+        stream.try_next().await.unwrap().unwrap();
+        drop(stream);
+
+        // Dropping the stream closes the connection long before the server finishes
+        tokio::time::timeout(Duration::from_secs(5), closed)
+            .await
+            .expect("the connection was not closed")
+            .unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rerankers combine the results of the two searches of a hybrid query
+//!
+//! See [`crate::query::VectorQuery::hybrid`]
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use arrow::{array::AsArray, compute::interleave};
+use arrow_array::{types::UInt64Type, Array, ArrayRef, Float32Array, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::{Error, Result};
+
+pub mod rrf;
+
+pub use rrf::RRFReranker;
+
+/// The row id column, rerankers use it to find the rows found by both searches
+pub const ROW_ID: &str = "_rowid";
+/// The distance column of the vector search results
+pub const DISTANCE: &str = "_distance";
+/// The score column of the full text search results
+pub const SCORE: &str = "_score";
+/// The column with the combined relevance of a row, computed by the reranker
+pub const RELEVANCE_SCORE: &str = "_relevance_score";
+
+/// Combines the results of a vector search and a full text search
+pub trait Reranker: Send + Sync + std::fmt::Debug {
+    /// Merge the hits of the two searches into a single ranking
+    ///
+    /// Both inputs have a [`ROW_ID`] column and are ordered from the best match to
+    /// the worst.  The vector hits also have a [`DISTANCE`] column and the full text
+    /// hits a [`SCORE`] column.  Either may be empty.
+    ///
+    /// The output should contain each row once, with a [`RELEVANCE_SCORE`] column,
+    /// ordered from the most relevant row to the least.  [`merge_results`] does this
+    /// for a given relevance of each row.
+    fn rerank(&self, vector_hits: RecordBatch, fts_hits: RecordBatch) -> Result<RecordBatch>;
+}
+
+fn row_ids<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a UInt64Array> {
+    batch
+        .column_by_name(ROW_ID)
+        .and_then(|c| c.as_primitive_opt::<UInt64Type>())
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the {} hits to rerank have no {} column", name, ROW_ID),
+        })
+}
+
+/// The row ids of the vector hits and full text hits, in order
+pub fn hit_row_ids(
+    vector_hits: &RecordBatch,
+    fts_hits: &RecordBatch,
+) -> Result<(Vec<u64>, Vec<u64>)> {
+    Ok((
+        row_ids(vector_hits, "vector")?.values().to_vec(),
+        row_ids(fts_hits, "full text")?.values().to_vec(),
+    ))
+}
+
+/// Merge the hits of the two searches, ordered by the given relevance of each row id
+///
+/// Rows found by both searches are returned once.  The output has the columns of
+/// the vector hits, the [`DISTANCE`] of the vector search and [`SCORE`] of the full
+/// text search, which are null for rows that only one search found, and the
+/// [`RELEVANCE_SCORE`].  Rows with the same relevance keep the order of the vector
+/// hits followed by the full text hits.
+pub fn merge_results(
+    vector_hits: &RecordBatch,
+    fts_hits: &RecordBatch,
+    relevance: &HashMap<u64, f32>,
+) -> Result<RecordBatch> {
+    let (vector_ids, fts_ids) = hit_row_ids(vector_hits, fts_hits)?;
+    let mut vector_rows = HashMap::with_capacity(vector_ids.len());
+    for (row, id) in vector_ids.iter().enumerate() {
+        vector_rows.entry(*id).or_insert(row);
+    }
+    let mut fts_rows = HashMap::with_capacity(fts_ids.len());
+    for (row, id) in fts_ids.iter().enumerate() {
+        fts_rows.entry(*id).or_insert(row);
+    }
+
+    // Each row once, taken from the vector hits if possible
+    let mut seen = HashSet::with_capacity(vector_ids.len() + fts_ids.len());
+    let mut order = Vec::with_capacity(vector_ids.len() + fts_ids.len());
+    for (row, id) in vector_ids.iter().enumerate() {
+        if seen.insert(*id) {
+            order.push((*id, (0, row)));
+        }
+    }
+    for (row, id) in fts_ids.iter().enumerate() {
+        if seen.insert(*id) {
+            order.push((*id, (1, row)));
+        }
+    }
+    let relevance_of = |id: &u64| relevance.get(id).copied().unwrap_or(0.0);
+    order.sort_by(|(a, _), (b, _)| relevance_of(b).total_cmp(&relevance_of(a)));
+    let indices = order.iter().map(|(_, index)| *index).collect::<Vec<_>>();
+
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for (field, column) in vector_hits
+        .schema()
+        .fields()
+        .iter()
+        .zip(vector_hits.columns())
+    {
+        if matches!(field.name().as_str(), DISTANCE | SCORE | RELEVANCE_SCORE) {
+            continue;
+        }
+        let fts_column =
+            fts_hits
+                .column_by_name(field.name())
+                .ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                        "the full text hits to rerank have no column '{}'",
+                        field.name()
+                    ),
+                })?;
+        let merged = interleave(&[column.as_ref(), fts_column.as_ref()], &indices)?;
+        fields.push(Field::new(
+            field.name(),
+            field.data_type().clone(),
+            field.is_nullable() || fts_column.null_count() > 0,
+        ));
+        columns.push(merged);
+    }
+
+    let lookup =
+        |batch: &RecordBatch, rows: &HashMap<u64, usize>, name: &str| -> Result<ArrayRef> {
+            let values = match batch.column_by_name(name) {
+                Some(values) => arrow::compute::cast(values, &DataType::Float32)?,
+                None => return Ok(arrow_array::new_null_array(&DataType::Float32, order.len())),
+            };
+            let values = values.as_primitive::<arrow_array::types::Float32Type>();
+            Ok(Arc::new(
+                order
+                    .iter()
+                    .map(|(id, _)| {
+                        rows.get(id)
+                            .and_then(|row| values.is_valid(*row).then(|| values.value(*row)))
+                    })
+                    .collect::<Float32Array>(),
+            ))
+        };
+    fields.push(Field::new(DISTANCE, DataType::Float32, true));
+    columns.push(lookup(vector_hits, &vector_rows, DISTANCE)?);
+    fields.push(Field::new(SCORE, DataType::Float32, true));
+    columns.push(lookup(fts_hits, &fts_rows, SCORE)?);
+    fields.push(Field::new(RELEVANCE_SCORE, DataType::Float32, false));
+    columns.push(Arc::new(Float32Array::from_iter_values(
+        order.iter().map(|(id, _)| relevance_of(id)),
+    )));
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
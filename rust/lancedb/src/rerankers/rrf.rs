@@ -0,0 +1,148 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use arrow_array::RecordBatch;
+
+use super::{hit_row_ids, merge_results, Reranker};
+use crate::Result;
+
+/// Reciprocal rank fusion
+///
+/// The relevance of a row is the sum of `1 / (rank + k)` over the searches that
+/// found it, where `rank` is the zero-based position of the row in their results.
+/// Only the ranks matter, so the distances and scores of the two searches don't
+/// need to be comparable.
+#[derive(Debug, Clone)]
+pub struct RRFReranker {
+    k: f32,
+}
+
+impl RRFReranker {
+    /// Create a reranker with the given `k`
+    ///
+    /// A larger `k` reduces the advantage of the top ranked rows.
+    pub fn new(k: f32) -> Self {
+        Self { k }
+    }
+}
+
+impl Default for RRFReranker {
+    /// A reranker with `k = 60`, the value used in the paper that introduced RRF
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+
+impl Reranker for RRFReranker {
+    fn rerank(&self, vector_hits: RecordBatch, fts_hits: RecordBatch) -> Result<RecordBatch> {
+        let (vector_ids, fts_ids) = hit_row_ids(&vector_hits, &fts_hits)?;
+        let mut relevance = HashMap::with_capacity(vector_ids.len() + fts_ids.len());
+        for ids in [vector_ids, fts_ids] {
+            for (rank, id) in ids.into_iter().enumerate() {
+                *relevance.entry(id).or_insert(0.0) += 1.0 / (rank as f32 + self.k);
+            }
+        }
+        merge_results(&vector_hits, &fts_hits, &relevance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::AsArray;
+    use arrow_array::{types::Float32Type, Float32Array, StringArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::*;
+    use crate::rerankers::{DISTANCE, RELEVANCE_SCORE, ROW_ID, SCORE};
+
+    fn hits(ids: &[u64], score_column: &str) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("text", DataType::Utf8, false),
+            Field::new(ROW_ID, DataType::UInt64, false),
+            Field::new(score_column, DataType::Float32, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    ids.iter().map(|id| format!("row {}", id)),
+                )),
+                Arc::new(UInt64Array::from(ids.to_vec())),
+                Arc::new(Float32Array::from_iter_values(
+                    (0..ids.len()).map(|i| i as f32),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rrf() {
+        let reranker = RRFReranker::new(1.0);
+        let merged = reranker
+            .rerank(hits(&[1, 2, 3], DISTANCE), hits(&[3, 4], SCORE))
+            .unwrap();
+
+        // Row 3 is found by both searches, 1/3 + 1/1, rows 2 and 4 tie at 1/2
+        let ids = merged[ROW_ID].as_primitive::<arrow_array::types::UInt64Type>();
+        assert_eq!(ids.values(), &[3, 1, 2, 4]);
+        let relevance = merged[RELEVANCE_SCORE].as_primitive::<Float32Type>();
+        assert_eq!(relevance.values(), &[4.0 / 3.0, 1.0, 0.5, 0.5]);
+        let text = merged["text"].as_string::<i32>();
+        assert_eq!(text.value(3), "row 4");
+
+        // Each score is kept for the rows its search found
+        let distance = merged[DISTANCE].as_primitive::<Float32Type>();
+        assert_eq!(
+            distance.iter().collect::<Vec<_>>(),
+            vec![Some(2.0), Some(0.0), Some(1.0), None]
+        );
+        let score = merged[SCORE].as_primitive::<Float32Type>();
+        assert_eq!(
+            score.iter().collect::<Vec<_>>(),
+            vec![Some(0.0), None, None, Some(1.0)]
+        );
+    }
+
+    #[test]
+    fn test_rrf_one_sided() {
+        let reranker = RRFReranker::default();
+        let merged = reranker
+            .rerank(hits(&[], DISTANCE), hits(&[7, 8], SCORE))
+            .unwrap();
+        let ids = merged[ROW_ID].as_primitive::<arrow_array::types::UInt64Type>();
+        assert_eq!(ids.values(), &[7, 8]);
+        assert_eq!(merged[DISTANCE].null_count(), 2);
+
+        let merged = reranker
+            .rerank(hits(&[], DISTANCE), hits(&[], SCORE))
+            .unwrap();
+        assert_eq!(merged.num_rows(), 0);
+        assert!(merged.schema().field_with_name(RELEVANCE_SCORE).is_ok());
+    }
+
+    #[test]
+    fn test_missing_row_ids() {
+        let mut vector_hits = hits(&[1], DISTANCE);
+        vector_hits.remove_column(1);
+        let err = RRFReranker::default()
+            .rerank(vector_hits, hits(&[1], SCORE))
+            .unwrap_err();
+        assert!(err.to_string().contains(ROW_ID), "{}", err);
+    }
+}
@@ -0,0 +1,365 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder for the schemas of tables
+//!
+//! ```
+//! use lancedb::schema::{Builder, Type};
+//!
+//! let definition = Builder::new()
+//!     .field("id", Type::Int64)
+//!     .text("body")
+//!     .vector("embedding", 768)
+//!     .embedded("body", "openai", Some("embedding"))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(definition.schema.fields().len(), 3);
+//! ```
+//!
+//! The schema of the [`TableDefinition`] can be passed to
+//! [`crate::Connection::create_empty_table`] with [`TableDefinition::into_rich_schema`],
+//! which keeps the embedding definitions.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow_schema::{Field, Schema};
+
+use crate::embeddings::{
+    check_registered_functions, check_source_type, EmbeddingDefinition, EmbeddingFunctionConfig,
+    EmbeddingRegistry,
+};
+use crate::error::{Error, Result};
+use crate::table::{ColumnDefinition, ColumnKind, TableDefinition};
+
+/// The type of a column
+pub use arrow_schema::DataType as Type;
+
+/// A builder for the schema of a table
+///
+/// Columns are nullable and are added in the order the methods are called.
+/// Mistakes, such as duplicate column names or vectors without dimensions, are
+/// reported by [`Self::build`].
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    fields: Vec<Field>,
+    embeddings: Vec<EmbeddingDefinition>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a column of any type
+    pub fn field(mut self, name: impl Into<String>, data_type: Type) -> Self {
+        self.fields.push(Field::new(name, data_type, true));
+        self
+    }
+
+    /// Add a column described by an Arrow field
+    ///
+    /// Use this for columns that are not nullable or that have metadata.
+    pub fn arrow_field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// Add a vector column of 32-bit floats
+    pub fn vector(self, name: impl Into<String>, dimension: usize) -> Self {
+        self.vector_of(name, dimension, Type::Float32)
+    }
+
+    /// Add a vector column with the given item type, e.g. [`Type::Float16`]
+    pub fn vector_of(self, name: impl Into<String>, dimension: usize, item_type: Type) -> Self {
+        // Out of range dimensions are reported by `build`
+        let dimension = i32::try_from(dimension).unwrap_or(-1);
+        self.field(
+            name,
+            Type::FixedSizeList(Arc::new(Field::new("item", item_type, true)), dimension),
+        )
+    }
+
+    /// Add a string column
+    pub fn text(self, name: impl Into<String>) -> Self {
+        self.field(name, Type::Utf8)
+    }
+
+    /// Fill a column with the embeddings of another column
+    ///
+    /// # Arguments
+    /// * `source` - The column that is embedded
+    /// * `function` - The name the embedding function is registered with
+    /// * `dest` - The column the embeddings are stored in, `<source>_embedding` if
+    ///   not set.  With [`Self::build`] this column must be added with
+    ///   [`Self::vector`], [`Self::build_with_registry`] adds it if needed.
+    pub fn embedded(mut self, source: &str, function: &str, dest: Option<&str>) -> Self {
+        self.embeddings
+            .push(EmbeddingDefinition::new(source, function, dest));
+        self
+    }
+
+    /// Check the columns and create the table definition
+    pub fn build(self) -> Result<TableDefinition> {
+        self.validate()?;
+        for embedding in &self.embeddings {
+            let dest_column = embedding.dest_column_name();
+            if !self.fields.iter().any(|field| field.name() == &dest_column) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the column '{}' for the embeddings of '{}' is not in the schema, add it with vector() or use build_with_registry()",
+                        dest_column, embedding.source_column
+                    ),
+                });
+            }
+        }
+        Ok(self.definition(HashMap::new()))
+    }
+
+    /// Like [`Self::build`], but check the embeddings against their functions
+    ///
+    /// The source columns must have the type the functions take, and the destination
+    /// columns are added with the type the functions produce if they are missing.
+    /// The configuration of the functions is stored with the definition, so the
+    /// table can re-create them (see [`crate::embeddings::EmbeddingFunctionFactory`]).
+    pub fn build_with_registry(
+        mut self,
+        registry: &dyn EmbeddingRegistry,
+    ) -> Result<TableDefinition> {
+        self.validate()?;
+        let mut embedding_functions = HashMap::new();
+        for embedding in &self.embeddings {
+            let func = registry.get(&embedding.embedding_name).ok_or_else(|| {
+                Error::EmbeddingFunctionNotFound {
+                    name: embedding.embedding_name.clone(),
+                    reason: "No embedding function found in the embedding registry".to_string(),
+                }
+            })?;
+            let source = self
+                .fields
+                .iter()
+                .find(|field| field.name() == &embedding.source_column)
+                .expect("validated");
+            check_source_type(embedding, func.as_ref(), source.data_type())?;
+            let dest_column = embedding.dest_column_name();
+            if !self.fields.iter().any(|field| field.name() == &dest_column) {
+                self.fields.push(Field::new(
+                    dest_column,
+                    func.dest_type()?.into_owned(),
+                    true,
+                ));
+            }
+            if let Some(config) = EmbeddingFunctionConfig::from_function(func.as_ref()) {
+                embedding_functions.insert(embedding.embedding_name.clone(), config);
+            }
+        }
+        let definition = self.definition(embedding_functions);
+        check_registered_functions(registry, &definition)?;
+        Ok(definition)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(Error::InvalidInput { message });
+        let mut names = HashSet::with_capacity(self.fields.len());
+        for field in &self.fields {
+            if field.name().is_empty() {
+                return invalid("column names cannot be empty".to_string());
+            }
+            if !names.insert(field.name().as_str()) {
+                return invalid(format!("the column '{}' is added twice", field.name()));
+            }
+            if let Type::FixedSizeList(_, dimension) = field.data_type() {
+                if *dimension <= 0 {
+                    return invalid(format!(
+                        "the vector column '{}' needs a dimension between 1 and {}",
+                        field.name(),
+                        i32::MAX
+                    ));
+                }
+            }
+        }
+
+        let mut dest_columns = HashSet::with_capacity(self.embeddings.len());
+        for embedding in &self.embeddings {
+            if !names.contains(embedding.source_column.as_str()) {
+                return invalid(format!(
+                    "the column '{}' that embedding function '{}' embeds is not in the schema",
+                    embedding.source_column, embedding.embedding_name
+                ));
+            }
+            let dest_column = embedding.dest_column_name();
+            if dest_column == embedding.source_column || !dest_columns.insert(dest_column.clone()) {
+                return invalid(format!(
+                    "more than one column is written to the column '{}', each embedding needs its own destination column",
+                    dest_column
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn definition(
+        self,
+        embedding_functions: HashMap<String, EmbeddingFunctionConfig>,
+    ) -> TableDefinition {
+        let column_definitions = self
+            .fields
+            .iter()
+            .map(|field| {
+                let embedding = self
+                    .embeddings
+                    .iter()
+                    .find(|embedding| &embedding.dest_column_name() == field.name());
+                ColumnDefinition {
+                    kind: match embedding {
+                        Some(embedding) => ColumnKind::Embedding(embedding.clone()),
+                        None => ColumnKind::Physical,
+                    },
+                }
+            })
+            .collect();
+        let mut definition =
+            TableDefinition::new(Arc::new(Schema::new(self.fields)), column_definitions);
+        definition.embedding_functions = embedding_functions;
+        definition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use arrow_array::Array;
+
+    use crate::embeddings::{EmbeddingFunction, MemoryRegistry};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct MockEmbed;
+
+    impl EmbeddingFunction for MockEmbed {
+        fn name(&self) -> &str {
+            "mock"
+        }
+        fn source_type(&self) -> Result<Cow<Type>> {
+            Ok(Cow::Owned(Type::Utf8))
+        }
+        fn dest_type(&self) -> Result<Cow<Type>> {
+            Ok(Cow::Owned(Type::new_fixed_size_list(
+                Type::Float32,
+                4,
+                true,
+            )))
+        }
+        fn compute_source_embeddings(&self, _: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+            unimplemented!()
+        }
+        fn compute_query_embeddings(&self, _: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let definition = Builder::new()
+            .field("id", Type::Int64)
+            .text("body")
+            .vector("embedding", 4)
+            .embedded("body", "mock", Some("embedding"))
+            .build()
+            .unwrap();
+        let schema = &definition.schema;
+        assert_eq!(schema.field(0).data_type(), &Type::Int64);
+        assert_eq!(schema.field(1).data_type(), &Type::Utf8);
+        assert_eq!(
+            schema.field(2).data_type(),
+            &Type::FixedSizeList(Arc::new(Field::new("item", Type::Float32, true)), 4)
+        );
+        assert!(matches!(
+            definition.column_definitions[..],
+            [
+                ColumnDefinition {
+                    kind: ColumnKind::Physical
+                },
+                ColumnDefinition {
+                    kind: ColumnKind::Physical
+                },
+                ColumnDefinition {
+                    kind: ColumnKind::Embedding(_)
+                }
+            ]
+        ));
+        // The definitions survive the round trip through the schema metadata
+        let rich_schema = definition.into_rich_schema();
+        let definition = TableDefinition::try_from_rich_schema(rich_schema).unwrap();
+        assert!(matches!(
+            &definition.column_definitions[2].kind,
+            ColumnKind::Embedding(embedding) if embedding.source_column == "body"
+        ));
+
+        let invalid = |builder: Builder| match builder.build() {
+            Err(Error::InvalidInput { message }) => message,
+            other => panic!("expected an invalid input error, got {:?}", other),
+        };
+        assert!(invalid(Builder::new().field("id", Type::Int64).text("id")).contains("twice"));
+        assert!(invalid(Builder::new().vector("vector", 0)).contains("dimension"));
+        assert!(invalid(Builder::new().text("")).contains("empty"));
+        assert!(invalid(Builder::new().embedded("body", "mock", None)).contains("'body'"));
+        assert!(
+            invalid(Builder::new().text("body").embedded("body", "mock", None))
+                .contains("'body_embedding'")
+        );
+    }
+
+    #[test]
+    fn test_build_with_registry() {
+        let registry = MemoryRegistry::new();
+        registry.register("mock", Arc::new(MockEmbed)).unwrap();
+
+        let definition = Builder::new()
+            .text("body")
+            .embedded("body", "mock", None)
+            .build_with_registry(&registry)
+            .unwrap();
+        let dest = definition.schema.field_with_name("body_embedding").unwrap();
+        assert_eq!(dest.data_type(), MockEmbed.dest_type().unwrap().as_ref());
+
+        // The source column must have the type the function takes
+        let err = Builder::new()
+            .field("body", Type::Int32)
+            .embedded("body", "mock", None)
+            .build_with_registry(&registry)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        // A declared destination column must match the function
+        let err = Builder::new()
+            .text("body")
+            .vector("body_embedding", 8)
+            .embedded("body", "mock", None)
+            .build_with_registry(&registry)
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        let err = Builder::new()
+            .text("body")
+            .embedded("body", "missing", None)
+            .build_with_registry(&registry)
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::EmbeddingFunctionNotFound { .. }),
+            "{:?}",
+            err
+        );
+    }
+}
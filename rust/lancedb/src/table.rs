@@ -14,29 +14,45 @@
 
 //! LanceDB Table APIs
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
 use arrow::array::AsArray;
 use arrow::datatypes::Float32Type;
-use arrow_array::{RecordBatchIterator, RecordBatchReader};
+use arrow_array::{Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
 use arrow_schema::{DataType, Field, Schema, SchemaRef};
 use async_trait::async_trait;
-use datafusion_physical_plan::ExecutionPlan;
+use datafusion::datasource::TableProvider;
+use datafusion_common::ScalarValue;
+use datafusion_physical_plan::aggregates::{AggregateExec, AggregateMode, PhysicalGroupBy};
+use datafusion_physical_plan::coalesce_partitions::CoalescePartitionsExec;
+use datafusion_physical_plan::expressions::{cast, col, Column, Count, Literal, Max, Min, Sum};
+use datafusion_physical_plan::limit::GlobalLimitExec;
+use datafusion_physical_plan::projection::ProjectionExec;
+use datafusion_physical_plan::union::UnionExec;
+use datafusion_physical_plan::{
+    displayable, stream::RecordBatchStreamAdapter, AggregateExpr, ExecutionPlan, PhysicalExpr,
+};
+use futures::{StreamExt, TryStreamExt};
 use lance::dataset::builder::DatasetBuilder;
 use lance::dataset::cleanup::RemovalStats;
-use lance::dataset::optimize::{compact_files, CompactionMetrics, IndexRemapperOptions};
+use lance::dataset::optimize::{
+    commit_compaction, compact_files, plan_compaction, CompactionMetrics, IndexRemapperOptions,
+};
 use lance::dataset::scanner::{DatasetRecordBatchStream, Scanner};
+use lance::dataset::transaction::Operation;
 pub use lance::dataset::ColumnAlteration;
 pub use lance::dataset::NewColumnTransform;
 pub use lance::dataset::ReadParams;
 use lance::dataset::{
-    Dataset, UpdateBuilder as LanceUpdateBuilder, WhenMatched, WriteMode, WriteParams,
+    write_fragments, Dataset, UpdateBuilder as LanceUpdateBuilder, WhenMatched, WriteMode,
+    WriteParams,
 };
 use lance::dataset::{MergeInsertBuilder as LanceMergeInsertBuilder, WhenNotMatchedBySource};
-use lance::io::WrappingObjectStore;
-use lance_datafusion::exec::execute_plan;
+use lance::io::{ObjectStore, ObjectStoreParams, WrappingObjectStore};
+use lance_datafusion::exec::{execute_plan, OneShotExec};
+use lance_datafusion::planner::Planner;
 use lance_index::vector::hnsw::builder::HnswBuildParams;
 use lance_index::vector::ivf::IvfBuildParams;
 use lance_index::vector::pq::PQBuildParams;
@@ -44,34 +60,85 @@ use lance_index::vector::sq::builder::SQBuildParams;
 use lance_index::DatasetIndexExt;
 use lance_index::IndexType;
 use log::info;
+use object_store::ObjectStore as _;
 use serde::{Deserialize, Serialize};
 use snafu::whatever;
 
 use crate::arrow::IntoArrow;
 use crate::connection::NoData;
-use crate::embeddings::{EmbeddingDefinition, EmbeddingRegistry, MaybeEmbedded, MemoryRegistry};
+use crate::data::import::{self, CsvOptions};
+use crate::embeddings::{
+    default_registry, EmbeddingDefinition, EmbeddingFunctionConfig, EmbeddingRegistry,
+    MaybeEmbedded,
+};
 use crate::error::{Error, Result};
 use crate::index::vector::{
     IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder, IvfPqIndexBuilder, VectorIndex,
 };
 use crate::index::IndexConfig;
 use crate::index::IndexStatistics;
+use crate::index::IndexStats;
+use crate::index::VectorIndexParams;
 use crate::index::{
+    scalar::FtsIndexBuilder,
     vector::{suggested_num_partitions, suggested_num_sub_vectors},
     Index, IndexBuilder,
 };
 use crate::query::{
-    IntoQueryVector, Query, QueryExecutionOptions, Select, VectorQuery, DEFAULT_TOP_K,
+    analyze_plan, AggFunction, AggregateQuery, IntoQueryVector, Query, QueryExecutionOptions,
+    Select, VectorQuery, DEFAULT_TOP_K, QUERY_INDEX, ROW_ADDR,
 };
-use crate::utils::{default_vector_column, PatchReadParam, PatchWriteParam};
-
+use crate::rerankers::{DISTANCE, ROW_ID};
+use crate::utils::{multivector_dimension, resolve_vector_column, PatchReadParam, PatchWriteParam};
+use crate::DistanceType;
+
+pub use self::background::{BackgroundOptimize, OptimizeCallback, OptimizeSchedule};
+pub(crate) use self::coerce::{CoercingReader, Coercion};
+pub use self::commit::CommitRetryConfig;
+use self::commit::{CommitRetry, ReplayableData};
+pub use self::count::ApproxCount;
 use self::dataset::DatasetConsistencyWrapper;
-use self::merge::MergeInsertBuilder;
-
+use self::export::{ExportOptions, ExportSummary};
+pub use self::fragments::FragmentMetadata;
+use self::fts::FtsIndex;
+use self::merge::{MergeInsertBuilder, MergeResult};
+use self::progress::ProgressReader;
+pub use self::progress::{
+    CompactionProgress, CompactionProgressCallback, IndexProgress, IndexProgressCallback,
+    WriteProgress, WriteProgressCallback,
+};
+pub use self::stats::{
+    FragmentStatistics, FragmentSummaryStats, IndexCoverage, TableStatistics, SMALL_FRAGMENT_ROWS,
+};
+pub use self::tags::Tags;
+pub use self::take::MissingRows;
+pub use self::transaction::Transaction;
+pub(crate) use self::validate::Validator;
+pub use self::validate::{ValidationIssue, ValidationReport};
+pub use self::watch::{ChangeEvent, ChangeOperation};
+
+mod background;
+mod coerce;
+mod commit;
+mod count;
 pub(crate) mod dataset;
+pub mod export;
+mod fragments;
+mod fts;
 pub mod merge;
+mod multivector;
+mod progress;
+mod provider;
+mod sample;
+pub(crate) mod stats;
+mod tags;
+pub(crate) mod take;
+mod transaction;
+mod validate;
+mod watch;
 
 pub use chrono::Duration;
+use chrono::{DateTime, Utc};
 pub use lance::dataset::optimize::CompactionOptions;
 pub use lance_index::optimize::OptimizeOptions;
 
@@ -95,6 +162,9 @@ pub struct ColumnDefinition {
 pub struct TableDefinition {
     pub column_definitions: Vec<ColumnDefinition>,
     pub schema: SchemaRef,
+    /// The stored configuration of the embedding functions, keyed by the name
+    /// used in the embedding definitions
+    pub embedding_functions: HashMap<String, EmbeddingFunctionConfig>,
 }
 
 impl TableDefinition {
@@ -102,6 +172,7 @@ impl TableDefinition {
         Self {
             column_definitions,
             schema,
+            embedding_functions: HashMap::new(),
         }
     }
 
@@ -123,7 +194,14 @@ impl TableDefinition {
                 serde_json::from_str(column_definitions).map_err(|e| Error::Runtime {
                     message: format!("Failed to deserialize column definitions: {}", e),
                 })?;
-            Ok(Self::new(schema, column_definitions))
+            let mut definition = Self::new(schema.clone(), column_definitions);
+            if let Some(embedding_functions) = schema.metadata.get("lancedb::embedding_functions") {
+                definition.embedding_functions = serde_json::from_str(embedding_functions)
+                    .map_err(|e| Error::Runtime {
+                        message: format!("Failed to deserialize embedding functions: {}", e),
+                    })?;
+            }
+            Ok(definition)
         } else {
             let column_definitions = schema
                 .fields()
@@ -144,6 +222,13 @@ impl TableDefinition {
         schema_with_metadata
             .metadata
             .insert("lancedb::column_definitions".to_string(), lancedb_metadata);
+        if !self.embedding_functions.is_empty() {
+            let embedding_functions = serde_json::to_string(&self.embedding_functions).unwrap();
+            schema_with_metadata.metadata.insert(
+                "lancedb::embedding_functions".to_string(),
+                embedding_functions,
+            );
+        }
         Arc::new(schema_with_metadata)
     }
 }
@@ -154,6 +239,7 @@ impl TableDefinition {
 /// optimize different parts of the table on disk.
 ///
 /// By default, it optimizes everything, as [`OptimizeAction::All`].
+#[derive(Clone)]
 pub enum OptimizeAction {
     /// Run all optimizations with default values
     All,
@@ -169,6 +255,11 @@ pub enum OptimizeAction {
     ///
     /// If these operations are never run (search only) then compaction is not necessary.
     Compact {
+        /// How to compact the files, e.g. the number of rows each fragment should
+        /// have and the number of threads to rewrite them with
+        ///
+        /// Fragments that already have `target_rows_per_fragment` rows and fewer
+        /// deleted rows than `materialize_deletions_threshold` are left as they are.
         options: CompactionOptions,
         remap_options: Option<Arc<dyn IndexRemapperOptions>>,
     },
@@ -191,6 +282,11 @@ pub enum OptimizeAction {
         /// Because they may be part of an in-progress transaction, files newer than 7 days old are not deleted by default.
         /// If you are sure that there are no in-progress transactions, then you can set this to True to delete all files older than `older_than`.
         delete_unverified: Option<bool>,
+        /// If set, the files are compacted with these options before the old versions are pruned
+        ///
+        /// This compacts and prunes in a single call.  The versions replaced by the compaction are
+        /// only pruned if they are older than `older_than`.
+        compaction: Option<CompactionOptions>,
     },
     /// Optimize the indices
     ///
@@ -216,15 +312,65 @@ impl Default for OptimizeAction {
     }
 }
 
+/// What was removed by [`Table::cleanup_old_versions`]
+pub type CleanupStats = RemovalStats;
+
 /// Statistics about the optimization.
+#[derive(Debug)]
 pub struct OptimizeStats {
     /// Stats of the file compaction.
     pub compaction: Option<CompactionMetrics>,
 
+    /// The fragments that were rewritten by the file compaction
+    ///
+    /// This is `None` if the files were not compacted, or if LanceDB Cloud
+    /// did not report the fragments.
+    pub compacted_fragments: Option<CompactedFragments>,
+
     /// Stats of the version pruning
     pub prune: Option<RemovalStats>,
 }
 
+/// The fragments rewritten by a compaction, see [`OptimizeStats::compacted_fragments`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactedFragments {
+    /// The ids of the fragments that were rewritten, they are not part of the
+    /// new version
+    pub removed: Vec<u64>,
+    /// The ids of the fragments that replaced them, as listed by
+    /// [`NativeTable::fragments`]
+    pub added: Vec<u64>,
+}
+
+impl CompactedFragments {
+    /// Whether the table was already compacted, in which case no version was
+    /// committed
+    pub fn is_noop(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// The outcome of [`Table::delete_rows`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeleteRowsStats {
+    /// The number of rows that were deleted
+    pub num_deleted: u64,
+    /// The number of row ids that didn't belong to a row, e.g. because the row had
+    /// already been deleted
+    pub num_missing: u64,
+}
+
+/// A version of a table, see [`Table::list_versions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// The version number
+    pub version: u64,
+    /// When the version was created
+    pub timestamp: DateTime<Utc>,
+    /// Metadata stored with the version
+    pub metadata: HashMap<String, String>,
+}
+
 /// Options to use when writing data
 #[derive(Clone, Debug, Default)]
 pub struct WriteOptions {
@@ -255,6 +401,10 @@ pub struct AddDataBuilder<T: IntoArrow> {
     pub(crate) mode: AddDataMode,
     pub(crate) write_options: WriteOptions,
     embedding_registry: Option<Arc<dyn EmbeddingRegistry>>,
+    progress: Option<WriteProgressCallback>,
+    coerce: bool,
+    allow_lossy: bool,
+    pub(crate) embedding_concurrency: usize,
 }
 
 impl<T: IntoArrow> std::fmt::Debug for AddDataBuilder<T> {
@@ -278,15 +428,92 @@ impl<T: IntoArrow> AddDataBuilder<T> {
         self
     }
 
+    /// Call `progress` after each batch is handed to the writer
+    ///
+    /// The callback runs as part of the write, so it should return quickly.  The
+    /// data only becomes visible in the table once the write has completed.
+    pub fn on_progress(mut self, progress: WriteProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Cast the columns of the data to the types of the columns of the table
+    ///
+    /// Numbers are cast to other numeric types, strings to timestamps and dates
+    /// (in the formats that Arrow parses, such as RFC 3339) and lists of numbers
+    /// to vectors of the dimension of the table.  The values of vectors are always
+    /// cast, for example from doubles to floats.  Each batch is cast as it is written.
+    ///
+    /// Any other value that doesn't survive the cast, such as a number that is out
+    /// of range or a string that isn't a timestamp, fails the write with an error
+    /// that names the column, unless [`Self::allow_lossy`] is set.  A vector with the
+    /// wrong dimension always fails the write.
+    ///
+    /// By default, data is not cast, except for lists that become vectors of the
+    /// same type (see [`Table::add`]).
+    pub fn coerce(mut self, coerce: bool) -> Self {
+        self.coerce = coerce;
+        self
+    }
+
+    /// Allow the casts of [`Self::coerce`] to lose values
+    ///
+    /// Numbers are truncated, and values that are out of range or can't be parsed
+    /// become null.  This has no effect unless the data is coerced.
+    pub fn allow_lossy(mut self, allow_lossy: bool) -> Self {
+        self.allow_lossy = allow_lossy;
+        self
+    }
+
+    /// Embed up to `concurrency` batches at the same time
+    ///
+    /// By default the embedding functions are applied to one batch at a time.  A
+    /// slow embedding function, such as one that calls a remote service, can embed
+    /// several batches at once instead.  The batches are still written in their
+    /// original order, and if embedding any of them fails the others are cancelled
+    /// and nothing is added.
+    ///
+    /// Up to `concurrency` batches are held in memory.  This has no effect if the
+    /// table has no embedding columns.
+    pub fn embedding_concurrency(mut self, concurrency: usize) -> Self {
+        self.embedding_concurrency = concurrency;
+        self
+    }
+
     pub async fn execute(self) -> Result<()> {
+        if self.embedding_concurrency == 0 {
+            return Err(Error::InvalidInput {
+                message: "embedding_concurrency must be at least 1".to_string(),
+            });
+        }
         let parent = self.parent.clone();
-        let data = self.data.into_arrow()?;
+        let mut data = self.data.into_arrow()?;
+        if self.coerce {
+            // The data is cast to the current schema of the table, even for an overwrite
+            let schema = parent.schema().await?;
+            let coercion = Coercion::All {
+                allow_lossy: self.allow_lossy,
+            };
+            data = Box::new(CoercingReader::new(data, &schema, coercion));
+        } else if matches!(self.mode, AddDataMode::Append) {
+            // An overwrite may change a vector column into a list column
+            let schema = parent.schema().await?;
+            data = Box::new(CoercingReader::new(data, &schema, Coercion::Vectors));
+        }
+        let data: Box<dyn RecordBatchReader + Send> = match self.progress {
+            Some(progress) => Box::new(ProgressReader::new(data, progress)),
+            None => data,
+        };
         let without_data = AddDataBuilder::<NoData> {
             data: NoData {},
             mode: self.mode,
             parent: self.parent,
             write_options: self.write_options,
             embedding_registry: self.embedding_registry,
+            progress: None,
+            coerce: false,
+            allow_lossy: false,
+            embedding_concurrency: self.embedding_concurrency,
         };
         parent.add(without_data, data).await
     }
@@ -298,6 +525,26 @@ pub struct UpdateBuilder {
     parent: Arc<dyn TableInternal>,
     pub(crate) filter: Option<String>,
     pub(crate) columns: Vec<(String, String)>,
+    pub(crate) returning: Option<Returning>,
+}
+
+/// The values an update returns, see [`UpdateBuilder::returning`]
+#[derive(Debug, Clone)]
+pub(crate) enum Returning {
+    /// The values of the columns before the update
+    Old(Vec<String>),
+    /// The values of the columns after the update
+    New(Vec<String>),
+}
+
+/// The outcome of an update, see [`UpdateBuilder::execute_returning`]
+#[derive(Debug, Clone)]
+pub struct UpdateResult {
+    /// The number of rows that were updated, None if a LanceDB Cloud server didn't
+    /// report it
+    pub num_updated: Option<u64>,
+    /// The returned columns of the updated rows
+    pub rows: RecordBatch,
 }
 
 impl UpdateBuilder {
@@ -306,6 +553,7 @@ impl UpdateBuilder {
             parent,
             filter: None,
             columns: Vec::new(),
+            returning: None,
         }
     }
 
@@ -345,8 +593,66 @@ impl UpdateBuilder {
         self
     }
 
+    /// Return the values of `columns` after the update, for each updated row
+    ///
+    /// The rows are returned by [`Self::execute_returning`].  They are read
+    /// from the rows written by the update, so they are exactly the rows
+    /// the update committed, even if other writers change the table.
+    ///
+    /// This is not supported by LanceDB Cloud.
+    pub fn returning(mut self, columns: &[&str]) -> Self {
+        self.returning = Some(Returning::New(
+            columns.iter().map(|c| c.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Return the values of `columns` before the update, for each updated row
+    ///
+    /// The rows are returned by [`Self::execute_returning`].  They are read
+    /// from the version of the table that the update is applied to, if
+    /// another writer commits first then the update is retried and the rows
+    /// are read again.
+    ///
+    /// This is not supported by LanceDB Cloud.
+    pub fn returning_old(mut self, columns: &[&str]) -> Self {
+        self.returning = Some(Returning::Old(
+            columns.iter().map(|c| c.to_string()).collect(),
+        ));
+        self
+    }
+
     /// Executes the update operation
-    pub async fn execute(self) -> Result<()> {
+    ///
+    /// Returns the number of rows that were updated, or None if a LanceDB Cloud
+    /// server didn't report it.
+    ///
+    /// Local tables check that the columns and expressions refer to columns that
+    /// exist before anything is written.
+    pub async fn execute(self) -> Result<Option<u64>> {
+        if self.returning.is_some() {
+            return Err(Error::InvalidInput {
+                message:
+                    "use execute_returning() to get the rows of an update with returning values"
+                        .to_string(),
+            });
+        }
+        Ok(self.run().await?.num_updated)
+    }
+
+    /// Executes the update operation and returns the updated rows, see
+    /// [`Self::returning`] and [`Self::returning_old`]
+    pub async fn execute_returning(self) -> Result<UpdateResult> {
+        if self.returning.is_none() {
+            return Err(Error::InvalidInput {
+                message: "returning() or returning_old() must be called before execute_returning()"
+                    .to_string(),
+            });
+        }
+        self.run().await
+    }
+
+    async fn run(self) -> Result<UpdateResult> {
         if self.columns.is_empty() {
             Err(Error::InvalidInput {
                 message: "at least one column must be specified in an update operation".to_string(),
@@ -363,17 +669,85 @@ pub(crate) trait TableInternal: std::fmt::Display + std::fmt::Debug + Send + Syn
     fn as_any(&self) -> &dyn std::any::Any;
     /// Cast as [`NativeTable`], or return None it if is not a [`NativeTable`].
     fn as_native(&self) -> Option<&NativeTable>;
+    /// Whether the table is stored by a LanceDB Cloud server.
+    fn is_remote(&self) -> bool;
     /// Get the name of the table.
     fn name(&self) -> &str;
+    /// Get the location of the table, see [`Table::uri`].
+    fn uri(&self) -> Option<String>;
     /// Get the arrow [Schema] of the table.
     async fn schema(&self) -> Result<SchemaRef>;
     /// Count the number of rows in this table.
     async fn count_rows(&self, filter: Option<String>) -> Result<usize>;
+    /// Estimate the number of rows in this table.
+    async fn count_rows_approx(&self, filter: Option<String>) -> Result<ApproxCount>;
+    /// Read the rows with the given row ids, in the order given.
+    async fn take(
+        &self,
+        row_ids: &[u64],
+        columns: Option<Vec<String>>,
+        missing: MissingRows,
+    ) -> Result<RecordBatch>;
     async fn create_plan(
         &self,
         query: &VectorQuery,
         options: QueryExecutionOptions,
     ) -> Result<Arc<dyn ExecutionPlan>>;
+    /// The distance type a vector search on `column` should use
+    ///
+    /// Defaults to the distance type of the column's vector index, if any.  An index
+    /// can only answer queries with the distance type it was trained with, so asking
+    /// for a different one is an error unless the index is bypassed.
+    async fn resolve_distance_type(
+        &self,
+        query: &VectorQuery,
+        column: &str,
+    ) -> Result<Option<DistanceType>> {
+        if query.distance_type == Some(DistanceType::Hamming) {
+            // Vector columns are always floating point (see resolve_vector_column)
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "hamming distance is only supported on binary (uint8) vectors but '{}' is a floating point vector column",
+                    column
+                ),
+            });
+        }
+        if !query.use_index {
+            return Ok(query.distance_type);
+        }
+        let index_distance_type = self.index_distance_type(column).await?;
+        match (query.distance_type, index_distance_type) {
+            (Some(requested), Some(indexed)) if requested != indexed => Err(Error::InvalidInput {
+                message: format!(
+                    "the vector index on '{}' was built with the {} distance type and can't be used for a {} search, use distance_type({}) or call bypass_vector_index() for an exact {} search",
+                    column, indexed, requested, indexed, requested
+                ),
+            }),
+            (requested, indexed) => Ok(requested.or(indexed)),
+        }
+    }
+    /// The distance type of the vector index on `column`, if there is one
+    async fn index_distance_type(&self, column: &str) -> Result<Option<DistanceType>> {
+        Ok(self
+            .list_indices()
+            .await?
+            .into_iter()
+            .find(|index| index.columns == [column])
+            .and_then(|index| index.vector_params)
+            .and_then(|params| params.distance_type))
+    }
+
+    /// Plan an aggregate query, see [`Query::aggregate`]
+    async fn create_aggregate_plan(
+        &self,
+        query: &AggregateQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>>;
+    /// Describe the plan of a query, see [`crate::query::ExecutableQuery::explain_plan`]
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String>;
+    /// Run a query and describe its plan with the metrics of the run, see
+    /// [`crate::query::ExecutableQuery::explain_analyze`]
+    async fn analyze_plan(&self, query: &VectorQuery) -> Result<String>;
     async fn plain_query(
         &self,
         query: &Query,
@@ -384,16 +758,25 @@ pub(crate) trait TableInternal: std::fmt::Display + std::fmt::Debug + Send + Syn
         add: AddDataBuilder<NoData>,
         data: Box<dyn arrow_array::RecordBatchReader + Send>,
     ) -> Result<()>;
-    async fn delete(&self, predicate: &str) -> Result<()>;
-    async fn update(&self, update: UpdateBuilder) -> Result<()>;
+    async fn delete(&self, predicate: &str) -> Result<Option<u64>>;
+    async fn delete_rows(&self, row_ids: &[u64]) -> Result<DeleteRowsStats>;
+    async fn update(&self, update: UpdateBuilder) -> Result<UpdateResult>;
     async fn create_index(&self, index: IndexBuilder) -> Result<()>;
     async fn list_indices(&self) -> Result<Vec<IndexConfig>>;
+    async fn index_stats(&self, index_name: &str) -> Result<Option<IndexStats>>;
     async fn merge_insert(
         &self,
         params: MergeInsertBuilder,
         new_data: Box<dyn RecordBatchReader + Send>,
-    ) -> Result<()>;
-    async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats>;
+    ) -> Result<MergeResult>;
+    /// Optimize the table, waiting up to `wait_timeout` for operations that run in
+    /// the background, see [`Table::optimize_and_wait`]
+    async fn optimize(
+        &self,
+        action: OptimizeAction,
+        progress: Option<CompactionProgressCallback>,
+        wait_timeout: Option<std::time::Duration>,
+    ) -> Result<OptimizeStats>;
     async fn add_columns(
         &self,
         transforms: NewColumnTransform,
@@ -402,10 +785,20 @@ pub(crate) trait TableInternal: std::fmt::Display + std::fmt::Debug + Send + Syn
     async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()>;
     async fn drop_columns(&self, columns: &[&str]) -> Result<()>;
     async fn version(&self) -> Result<u64>;
+    /// The latest version of the table, even if another version is checked out
+    async fn latest_version(&self) -> Result<u64>;
+    /// The operation of each version after `from`, up to `to`, see [`Table::watch`]
+    async fn version_operations(&self, from: u64, to: u64) -> Result<Vec<ChangeOperation>>;
+    async fn list_versions(&self) -> Result<Vec<VersionInfo>>;
+    async fn list_tags(&self) -> Result<HashMap<String, u64>>;
+    async fn create_tag(&self, name: &str, version: u64) -> Result<()>;
+    async fn update_tag(&self, name: &str, version: u64) -> Result<()>;
+    async fn delete_tag(&self, name: &str) -> Result<()>;
     async fn checkout(&self, version: u64) -> Result<()>;
     async fn checkout_latest(&self) -> Result<()>;
     async fn restore(&self) -> Result<()>;
     async fn table_definition(&self) -> Result<TableDefinition>;
+    async fn stats(&self) -> Result<TableStatistics>;
 }
 
 /// A Table is a collection of strong typed Rows.
@@ -427,7 +820,7 @@ impl Table {
     pub(crate) fn new(inner: Arc<dyn TableInternal>) -> Self {
         Self {
             inner,
-            embedding_registry: Arc::new(MemoryRegistry::new()),
+            embedding_registry: default_registry(),
         }
     }
 
@@ -449,6 +842,28 @@ impl Table {
         self.inner.as_native()
     }
 
+    /// Whether the table is stored by LanceDB Cloud, rather than being a local
+    /// table (see [`Self::as_native`])
+    pub fn is_remote(&self) -> bool {
+        self.inner.is_remote()
+    }
+
+    /// Cast as [`crate::remote::RemoteTable`], or return None if the table is not
+    /// stored by LanceDB Cloud
+    #[cfg(feature = "remote")]
+    pub fn as_remote(&self) -> Option<&crate::remote::RemoteTable> {
+        self.inner.as_any().downcast_ref()
+    }
+
+    /// Get the location of the table
+    ///
+    /// For local tables this is the URI of the dataset, e.g. a directory ending in
+    /// `.lance` or an object store URL, which can be backed up or measured.  For
+    /// LanceDB Cloud tables this is the URL of the table on the server.
+    pub fn uri(&self) -> Option<String> {
+        self.inner.uri()
+    }
+
     /// Get the name of the table.
     pub fn name(&self) -> &str {
         self.inner.name()
@@ -468,8 +883,80 @@ impl Table {
         self.inner.count_rows(filter).await
     }
 
+    /// Estimate the number of rows in this dataset, faster than [`Self::count_rows`]
+    ///
+    /// Without a filter the count is read from the metadata of the table and is exact.
+    /// With a filter, the filter is evaluated on a random sample of rows and the
+    /// returned [`ApproxCount`] includes the 95% confidence bounds of the estimate.
+    /// Small tables are counted exactly, as is indicated by [`ApproxCount::exact`].
+    ///
+    /// Remote tables count exactly if the server can't estimate counts.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` if present, only count rows matching the filter
+    pub async fn count_rows_approx(&self, filter: Option<String>) -> Result<ApproxCount> {
+        self.inner.count_rows_approx(filter).await
+    }
+
+    /// Read the rows with the given row ids
+    ///
+    /// This is the way to fetch the full rows of the results of a search that returned
+    /// row ids (see [`QueryBase::with_row_id`]).  The rows are read directly, which is
+    /// much faster than a query filtered with `_rowid IN (...)`.
+    ///
+    /// The rows are returned in the order of `row_ids`, repeated ids are returned
+    /// repeatedly.  Row ids of rows that have been deleted, e.g. by a delete, update
+    /// or compaction since the search, are handled as set by `missing`.
+    ///
+    /// Only local tables can take rows by row id.
+    ///
+    /// # Arguments
+    ///
+    /// * `row_ids` the row ids of the rows to read
+    /// * `columns` the columns to read, all columns if `None`
+    /// * `missing` what to return for rows that don't exist
+    pub async fn take(
+        &self,
+        row_ids: &[u64],
+        columns: Option<&[&str]>,
+        missing: MissingRows,
+    ) -> Result<RecordBatch> {
+        let columns = columns.map(|columns| columns.iter().map(|c| c.to_string()).collect());
+        self.inner.take(row_ids, columns, missing).await
+    }
+
+    /// Read the rows whose `key_column` has the given values
+    ///
+    /// This runs a query filtered with `key_column IN (...)`, which uses a scalar
+    /// index on the key column if there is one (see [`crate::index::Index::BTree`]).
+    /// The rows are returned in the order of `values`.  If several rows have the same
+    /// key then only the first one found is returned.  Keys that no row has are
+    /// handled as set by `missing`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_column` the column to look up the values in
+    /// * `values` the keys to look up, cast to the type of the key column
+    /// * `columns` the columns to read, all columns if `None`
+    /// * `missing` what to return for keys that no row has
+    pub async fn take_by_key(
+        &self,
+        key_column: &str,
+        values: &dyn Array,
+        columns: Option<&[&str]>,
+        missing: MissingRows,
+    ) -> Result<RecordBatch> {
+        take::take_by_key(self, key_column, values, columns, missing).await
+    }
+
     /// Insert new records into this Table
     ///
+    /// When appending, a list column of the data is converted to the vector column
+    /// of the same name, if the items have the same type.  Every list must then have
+    /// the dimension of the vectors, or the add fails with an error that names the
+    /// first row that doesn't.
+    ///
     /// # Arguments
     ///
     /// * `batches` data to be added to the Table
@@ -481,7 +968,82 @@ impl Table {
             mode: AddDataMode::Append,
             write_options: WriteOptions::default(),
             embedding_registry: Some(self.embedding_registry.clone()),
+            progress: None,
+            coerce: false,
+            allow_lossy: false,
+            embedding_concurrency: 1,
+        }
+    }
+
+    /// Check that `data` can be appended to this table, without writing it
+    ///
+    /// All of the data is read.  The columns of the data must be columns of the
+    /// table, with the same types and vector dimensions.  Columns that are missing
+    /// from the data must be nullable, or embeddings whose source column is in the
+    /// data.  Columns that aren't nullable must not have nulls.
+    ///
+    /// Every problem is collected in the report, rather than stopping at the first.
+    /// Appending with [`Self::add`] checks the schema of the data the same way.
+    pub async fn validate(&self, data: impl IntoArrow) -> Result<ValidationReport> {
+        let data = data.into_arrow()?;
+        let validator = Validator::new(&self.inner.table_definition().await?);
+        let mut report = ValidationReport {
+            issues: validator.check_schema(&data.schema()),
+            ..Default::default()
+        };
+        for (index, batch) in data.enumerate() {
+            let batch = batch?;
+            report.issues.extend(validator.check_batch(index, &batch));
+            report.num_batches += 1;
+            report.num_rows += batch.num_rows();
         }
+        Ok(report)
+    }
+
+    /// Insert the records of CSV files into this Table
+    ///
+    /// The files are read batch by batch as they are added.  The columns of the
+    /// files are matched to the columns of the table by name and cast to their
+    /// types.  Object store URLs are accessed with the storage options of the table,
+    /// and the path may contain wildcards (e.g. `s3://bucket/data/*.csv`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` the file, or files, to read
+    /// * `options` how the files are parsed and a callback for the progress
+    pub async fn add_from_csv(&self, path: &str, options: CsvOptions) -> Result<()> {
+        let storage_options = self
+            .as_native()
+            .map(|table| table.storage_options.clone())
+            .unwrap_or_default();
+        let table = self.clone();
+        let path = path.to_string();
+        import::run_import(async move {
+            let schema = table.schema().await?;
+            let data = import::read_csv(&path, storage_options, options, Some(schema)).await?;
+            table.add(data).execute().await
+        })
+        .await
+    }
+
+    /// Export the rows of this Table to Parquet files
+    ///
+    /// The rows are streamed from the version of the table that is checked out, so
+    /// a historical version can be exported by checking it out first.  Files are
+    /// written under temporary names and only given their final names, e.g.
+    /// `part-00000.parquet`, once all of them have been written.  Object store URLs
+    /// are accessed with the storage options of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` the directory to write the files to
+    /// * `options` which rows are exported and how the files are written
+    pub async fn export_parquet(&self, uri: &str, options: ExportOptions) -> Result<ExportSummary> {
+        let storage_options = self
+            .as_native()
+            .map(|table| table.storage_options.clone())
+            .unwrap_or_default();
+        export::export_parquet(self, uri, storage_options, options).await
     }
 
     /// Update existing records in the Table
@@ -498,12 +1060,22 @@ impl Table {
     /// you are updating many rows (with different ids) then you will get
     /// better performance with a single [`merge_insert`] call instead of
     /// repeatedly calilng this method.
+    ///
+    /// Embeddings are not recomputed by an update.  Updating the source column
+    /// of an embedding column is an error, use [`merge_insert`] instead.
+    ///
+    /// The old or new values of the updated rows can be returned, e.g. for an
+    /// audit log, see [`UpdateBuilder::returning`].
     pub fn update(&self) -> UpdateBuilder {
         UpdateBuilder::new(self.inner.clone())
     }
 
     /// Delete the rows from table that match the predicate.
     ///
+    /// Returns the number of rows that were deleted.  A predicate that matches no
+    /// rows, e.g. because of a typo, is not an error, so check the count if that
+    /// matters.  The count is None if a LanceDB Cloud server didn't report it.
+    ///
     /// # Arguments
     /// - `predicate` - The SQL predicate string to filter the rows to be deleted.
     ///
@@ -548,13 +1120,34 @@ impl Table {
     ///     .execute()
     ///     .await
     ///     .unwrap();
-    /// tbl.delete("id > 5").await.unwrap();
+    /// let num_deleted = tbl.delete("id > 5").await.unwrap();
     /// # });
     /// ```
-    pub async fn delete(&self, predicate: &str) -> Result<()> {
+    pub async fn delete(&self, predicate: &str) -> Result<Option<u64>> {
         self.inner.delete(predicate).await
     }
 
+    /// Delete the rows with the given row ids
+    ///
+    /// This is faster than [`Self::delete`] with an `_rowid IN (...)` predicate when
+    /// there are many ids.  Ids that don't belong to a row, because the row has
+    /// already been deleted or never existed, are counted in
+    /// [`DeleteRowsStats::num_missing`] rather than being an error.  Repeated ids are
+    /// counted once.
+    ///
+    /// Rows can't be deleted while a version is checked out (see [`Self::checkout`]).
+    ///
+    /// Local tables check the ids against the table, write the deletion files of the
+    /// fragments of the rows and commit them as one version.
+    ///
+    /// Remote tables delete the rows in batches of ids, one commit each, so the
+    /// deletion isn't atomic: if it fails, the batches before the failed one are
+    /// deleted.  The rows of each batch are counted before they are deleted, so a
+    /// concurrent delete of the same rows can make the counts wrong.
+    pub async fn delete_rows(&self, row_ids: &[u64]) -> Result<DeleteRowsStats> {
+        self.inner.delete_rows(row_ids).await
+    }
+
     /// Create an index on the provided column(s).
     ///
     /// Indices are used to speed up searches and are often needed when the size of the table
@@ -639,6 +1232,9 @@ impl Table {
     /// operation.  This is because updated rows will be deleted from the
     /// dataset and then reinserted at the end with the new values.
     ///
+    /// Like [`Self::add`], the embedding columns of the new data are computed
+    /// with the table's embedding functions.
+    ///
     /// # Arguments
     ///
     /// * `on` One or more columns to join on.  This is how records from the
@@ -694,6 +1290,7 @@ impl Table {
         MergeInsertBuilder::new(
             self.inner.clone(),
             on.iter().map(|s| s.to_string()).collect(),
+            self.embedding_registry.clone(),
         )
     }
 
@@ -785,7 +1382,27 @@ impl Table {
     /// # });
     /// ```
     pub fn query(&self) -> Query {
-        Query::new(self.inner.clone())
+        Query::new(self.inner.clone(), self.embedding_registry.clone())
+    }
+
+    /// Use the table as a table of a DataFusion `SessionContext`
+    ///
+    /// The provider reads the table with the same plans as [`Table::query`].  The
+    /// projection, the limit and the filters that can be written as Lance filters
+    /// are pushed down to the scan of local tables.  Remote tables only push down
+    /// the projection, DataFusion filters the rows it receives.
+    ///
+    /// The statistics of the provider are the number of rows when it was created.
+    ///
+    /// ```ignore
+    /// let ctx = SessionContext::new();
+    /// ctx.register_table("items", table.as_table_provider().await?)?;
+    /// let df = ctx.sql("SELECT * FROM items JOIN other ON items.id = other.id").await?;
+    /// ```
+    pub async fn as_table_provider(&self) -> Result<Arc<dyn TableProvider>> {
+        Ok(Arc::new(
+            provider::TableProviderAdapter::try_new(self.clone()).await?,
+        ))
     }
 
     /// Search the table with a given query vector.
@@ -824,7 +1441,89 @@ impl Table {
     /// you have added or modified 100,000 or more records or run more than 20 data
     /// modification operations.
     pub async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats> {
-        self.inner.optimize(action).await
+        self.inner.optimize(action, None, None).await
+    }
+
+    /// Like [`Self::optimize`], but call `progress` as fragments are compacted
+    ///
+    /// Progress is reported once before compaction starts and again after each
+    /// group of fragments is rewritten.  Only local tables report progress, remote
+    /// tables are compacted by the server.
+    pub async fn optimize_with_progress(
+        &self,
+        action: OptimizeAction,
+        progress: CompactionProgressCallback,
+    ) -> Result<OptimizeStats> {
+        self.inner.optimize(action, Some(progress), None).await
+    }
+
+    /// Like [`Self::optimize`], but wait up to `timeout` for operations that the
+    /// server runs in the background
+    ///
+    /// LanceDB Cloud servers may accept an operation and run it after replying.
+    /// [`Self::optimize`] returns without waiting for those, and leaves their
+    /// statistics out of the [`OptimizeStats`].  This polls the server until they
+    /// finish instead, and returns [`Error::Timeout`] if they are still running
+    /// after `timeout`.  Local tables always finish before returning.
+    pub async fn optimize_and_wait(
+        &self,
+        action: OptimizeAction,
+        timeout: std::time::Duration,
+    ) -> Result<OptimizeStats> {
+        self.inner.optimize(action, None, Some(timeout)).await
+    }
+
+    /// Optimize the table periodically in a background task
+    ///
+    /// The optimization runs every [`OptimizeSchedule::interval`], counted from the end
+    /// of the previous run, so runs never overlap.  A run is skipped if the version of
+    /// the table hasn't changed since the previous one, as there is nothing new to
+    /// optimize.  Writes made through other handles are only seen as the read
+    /// consistency interval of the connection allows.
+    ///
+    /// The result of each run is passed to [`OptimizeSchedule::callback`].  A failed
+    /// run doesn't stop the schedule.
+    ///
+    /// The runs stop when the returned handle is dropped.
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    pub fn start_background_optimize(&self, schedule: OptimizeSchedule) -> BackgroundOptimize {
+        background::start(self.clone(), schedule)
+    }
+
+    /// Remove the versions of the table that are older than `older_than` from disk
+    ///
+    /// This is the same as [`OptimizeAction::Prune`].  The latest version is never
+    /// removed, and this fails if a version is checked out, since that version could
+    /// be removed.  It is safe to run while the table is being read, except for
+    /// readers that have checked out one of the removed versions.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` versions created longer ago than this are removed
+    /// * `delete_unverified` files newer than 7 days old are not deleted by default,
+    ///   because they may be part of an in-progress transaction.  If you are sure
+    ///   that there are no in-progress transactions, set this to true to delete all
+    ///   of the files of the removed versions.
+    ///
+    /// Returns None when the server accepted the cleanup and removes the versions in
+    /// the background.  Use [`Self::optimize_and_wait`] to wait for the statistics
+    /// instead.
+    pub async fn cleanup_old_versions(
+        &self,
+        older_than: Duration,
+        delete_unverified: bool,
+    ) -> Result<Option<CleanupStats>> {
+        let stats = self
+            .optimize(OptimizeAction::Prune {
+                older_than: Some(older_than),
+                delete_unverified: Some(delete_unverified),
+                compaction: None,
+            })
+            .await?;
+        Ok(stats.prune)
     }
 
     /// Add new columns to the table, providing values to fill in.
@@ -856,6 +1555,25 @@ impl Table {
         self.inner.version().await
     }
 
+    /// Follow the new versions of the table, e.g. to invalidate a cache when the
+    /// table changes
+    ///
+    /// The latest version of the table is checked every `poll_interval`, starting
+    /// from the latest version when the stream is first polled.  Each new version
+    /// is reported once.  If several versions were committed between two checks
+    /// they are reported as a single [`ChangeEvent`] from the old to the newest
+    /// version.  For local tables the event tells what each version changed, see
+    /// [`ChangeOperation`].
+    ///
+    /// Local tables check the latest manifest of the table, tables in LanceDB
+    /// Cloud describe the table.  Dropping the stream stops the polling.
+    pub fn watch(
+        &self,
+        poll_interval: std::time::Duration,
+    ) -> impl futures::Stream<Item = Result<ChangeEvent>> + Send + 'static {
+        watch::watch(self.clone(), poll_interval, None)
+    }
+
     /// Checks out a specific version of the Table
     ///
     /// Any read operation on the table will now access the data at the checked out version.
@@ -874,6 +1592,58 @@ impl Table {
         self.inner.checkout(version).await
     }
 
+    /// List the versions of the Table that have not been cleaned up, oldest first
+    pub async fn list_versions(&self) -> Result<Vec<VersionInfo>> {
+        self.inner.list_versions().await
+    }
+
+    /// Checks out the version of the Table as it was at a point in time
+    ///
+    /// This is the latest version created at or before `timestamp`.  See
+    /// [`Self::checkout`] for what it means to check out a version.  It is an error
+    /// if the Table had no version at that time, because it did not exist yet or
+    /// because the versions from then have been cleaned up.
+    pub async fn checkout_at(&self, timestamp: DateTime<Utc>) -> Result<()> {
+        let versions = self.list_versions().await?;
+        let version = versions
+            .iter()
+            .filter(|version| version.timestamp <= timestamp)
+            .max_by_key(|version| version.version)
+            .ok_or_else(|| Error::InvalidInput {
+                message: match versions.iter().map(|version| version.timestamp).min() {
+                    Some(earliest) => format!(
+                        "table '{}' has no version at {}, its earliest version is from {}",
+                        self.name(),
+                        timestamp,
+                        earliest
+                    ),
+                    None => format!("table '{}' has no versions", self.name()),
+                },
+            })?;
+        self.checkout(version.version).await
+    }
+
+    /// The tags of the Table, names that refer to versions of the Table
+    pub fn tags(&self) -> Tags {
+        Tags::new(self.inner.clone())
+    }
+
+    /// Checks out the version of the Table a tag refers to
+    ///
+    /// See [`Self::checkout`] for what it means to check out a version.
+    pub async fn checkout_tag(&self, name: &str) -> Result<()> {
+        let version = self
+            .tags()
+            .list()
+            .await?
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("tag '{}' does not exist on table '{}'", name, self.name()),
+            })?;
+        self.checkout(version).await
+    }
+
     /// Ensures the table is pointing at the latest version
     ///
     /// This can be used to manually update a table when the read_consistency_interval is None
@@ -900,6 +1670,46 @@ impl Table {
     pub async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
         self.inner.list_indices().await
     }
+
+    /// Wait until the named indices cover every row in the table
+    ///
+    /// Remote tables build indices in the background, queries made before an index is
+    /// ready will search the unindexed rows without it.  This polls the statistics of
+    /// each index (see [`Self::index_stats`]), backing off between polls, until none
+    /// of them have unindexed rows.  If `timeout` elapses first then
+    /// [`Error::Timeout`] is returned, describing an index that was not ready.
+    ///
+    /// Native tables build indices before [`Self::create_index`] returns, so this
+    /// usually returns immediately.  However, rows added to a native table are not
+    /// indexed until [`Self::optimize`] is called, which no amount of waiting will fix.
+    pub async fn wait_for_index(
+        &self,
+        index_names: &[&str],
+        timeout: std::time::Duration,
+    ) -> Result<()> {
+        crate::index::wait_for_index(self.inner.as_ref(), index_names, timeout).await
+    }
+
+    /// Get statistics about an index
+    ///
+    /// Returns `None` if there is no index with the given name.  Index names can be
+    /// found with [`Self::list_indices`].
+    ///
+    /// Rows added after an index is built are not covered by the index until the
+    /// index is optimized.  The `num_unindexed_rows` statistic can be used to decide
+    /// when to call [`Self::optimize`] with [`OptimizeAction::Index`].
+    pub async fn index_stats(&self, index_name: impl AsRef<str>) -> Result<Option<IndexStats>> {
+        self.inner.index_stats(index_name.as_ref()).await
+    }
+
+    /// Get statistics about the storage of the table
+    ///
+    /// This includes the size of the table, how fragmented it is and how much of
+    /// it the indices cover, which can be used to decide when to run
+    /// [`Self::optimize`].
+    pub async fn stats(&self) -> Result<TableStatistics> {
+        self.inner.stats().await
+    }
 }
 
 impl From<NativeTable> for Table {
@@ -923,6 +1733,12 @@ pub struct NativeTable {
     // This comes from the connection options. We store here so we can pass down
     // to the dataset when we recreate it (for example, in checkout_latest).
     read_consistency_interval: Option<std::time::Duration>,
+
+    // How writes that conflict with concurrent writes are retried
+    commit_retry_config: CommitRetryConfig,
+    // The distance types of the vector indices, by index uuid, so that searches
+    // don't open the index to find it
+    index_distance_types: Arc<std::sync::Mutex<HashMap<uuid::Uuid, Option<DistanceType>>>>,
 }
 
 impl std::fmt::Display for NativeTable {
@@ -1012,9 +1828,19 @@ impl NativeTable {
             store_wrapper: write_store_wrapper,
             storage_options,
             read_consistency_interval,
+            commit_retry_config: CommitRetryConfig::default(),
+            index_distance_types: Default::default(),
         })
     }
 
+    /// Set how writes that conflict with concurrent writes are retried
+    ///
+    /// See [`CommitRetryConfig`], the default is [`CommitRetryConfig::default`].
+    pub fn with_commit_retry_config(mut self, commit_retry_config: CommitRetryConfig) -> Self {
+        self.commit_retry_config = commit_retry_config;
+        self
+    }
+
     fn get_table_name(uri: &str) -> Result<String> {
         let path = Path::new(uri);
         let name = path
@@ -1045,6 +1871,74 @@ impl NativeTable {
             )
     }
 
+    /// Recover the type of a vector index from its statistics
+    ///
+    /// Lance names the type of the index and of its sub index, e.g. "IVF_HNSW_SQ",
+    /// or "IVF" with a "PQ" or "HNSW" sub index.
+    fn vector_index_type(stats: &serde_json::Value) -> crate::index::IndexType {
+        let names = [
+            &stats["index_type"],
+            &stats["indices"][0]["index_type"],
+            &stats["indices"][0]["sub_index"]["index_type"],
+        ]
+        .into_iter()
+        .filter_map(|name| name.as_str())
+        .map(|name| name.to_uppercase())
+        .collect::<Vec<_>>();
+        let index_type = names
+            .iter()
+            .filter_map(|name| crate::index::IndexType::try_from(name.as_str()).ok())
+            .find(|index_type| index_type.is_vector());
+        match index_type {
+            Some(index_type) => index_type,
+            None if names.iter().any(|name| name.contains("HNSW")) => {
+                if names.iter().any(|name| name.contains("SQ")) {
+                    crate::index::IndexType::IvfHnswSq
+                } else {
+                    crate::index::IndexType::IvfHnswPq
+                }
+            }
+            None => crate::index::IndexType::IvfPq,
+        }
+    }
+
+    /// Recover the build parameters of a vector index from its statistics
+    fn vector_index_params(stats: &serde_json::Value) -> Result<VectorIndexParams> {
+        let index_type = Self::vector_index_type(stats);
+        // Each delta index is described separately, they share the same parameters
+        let stats = &stats["indices"][0];
+        // The parameters of the sub indices are nested at different depths
+        fn find<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+            let object = value.as_object()?;
+            object
+                .get(key)
+                .or_else(|| object.values().find_map(|value| find(value, key)))
+        }
+        let as_u32 = |key: &str| {
+            find(stats, key)
+                .and_then(|value| value.as_u64())
+                .map(|v| v as u32)
+        };
+        let distance_type = stats["metric_type"]
+            .as_str()
+            .map(DistanceType::try_from)
+            .transpose()?;
+        let is_hnsw = matches!(
+            index_type,
+            crate::index::IndexType::IvfHnswPq | crate::index::IndexType::IvfHnswSq
+        );
+        Ok(VectorIndexParams {
+            distance_type,
+            num_partitions: stats["num_partitions"].as_u64().map(|v| v as u32),
+            num_sub_vectors: match index_type {
+                crate::index::IndexType::IvfHnswSq => None,
+                _ => as_u32("num_sub_vectors"),
+            },
+            m: is_hnsw.then(|| as_u32("m")).flatten(),
+            ef_construction: is_hnsw.then(|| as_u32("ef_construction")).flatten(),
+        })
+    }
+
     fn supported_vector_data_type(dtype: &DataType) -> bool {
         match dtype {
             DataType::FixedSizeList(inner, _) => DataType::is_floating(inner.data_type()),
@@ -1100,6 +1994,8 @@ impl NativeTable {
             store_wrapper: write_store_wrapper,
             storage_options,
             read_consistency_interval,
+            commit_retry_config: CommitRetryConfig::default(),
+            index_distance_types: Default::default(),
         })
     }
 
@@ -1130,10 +2026,187 @@ impl NativeTable {
             .await?
             .optimize_indices(options)
             .await?;
-        Ok(())
-    }
 
-    /// Merge new data into this table.
+        // Full text indices can't be updated, they are rebuilt if the table has
+        // fragments they don't cover
+        let dataset = self.dataset.get().await?.clone();
+        for field in dataset.schema().fields.iter() {
+            let Some(index) = self.open_fts_index(&dataset, &field.name).await? else {
+                continue;
+            };
+            let (_, num_unindexed_rows) = index.count_rows(&dataset).await?;
+            if num_unindexed_rows > 0 {
+                fts::create_index(
+                    &dataset,
+                    &field.name,
+                    &index.builder(),
+                    &fts::index_uri(&self.uri, field.id),
+                    self.patch_write_params(WriteParams::default())?,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The params to read the full text indices of the table with
+    fn fts_read_params(&self) -> Result<ReadParams> {
+        let params = ReadParams {
+            store_options: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match self.store_wrapper.clone() {
+            Some(wrapper) => Ok(params.patch_with_store_wrapper(wrapper)?),
+            None => Ok(params),
+        }
+    }
+
+    /// Open the full text index on `column`, None if the column has none
+    ///
+    /// Full text indices are stored next to the table rather than in its manifest,
+    /// see [`fts::index_uri`].
+    async fn open_fts_index(&self, dataset: &Dataset, column: &str) -> Result<Option<FtsIndex>> {
+        let Some(field) = dataset.schema().field(column) else {
+            return Ok(None);
+        };
+        if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+            return Ok(None);
+        }
+        FtsIndex::open(
+            &fts::index_uri(&self.uri, field.id),
+            self.fts_read_params()?,
+        )
+        .await
+    }
+
+    /// Describe the fragments of the table
+    ///
+    /// The rows of a table are stored in fragments.  Scans can be limited to some
+    /// of them with [`crate::query::QueryBase::with_fragments`], to split the work
+    /// of scanning the table.  Fragments change when data is added or deleted and
+    /// when the table is compacted.
+    pub async fn fragments(&self) -> Result<Vec<FragmentMetadata>> {
+        fragments::list_fragments(&*self.dataset.get().await?).await
+    }
+
+    /// Start a transaction, several writes that are committed as one version of
+    /// the table, see [`Transaction`]
+    pub fn begin(&self) -> Transaction {
+        Transaction::new(self.clone())
+    }
+
+    /// Run a merge insert with a `when_matched_delete` clause, which lance can't run
+    ///
+    /// The changes are worked out by [`merge::plan_merge`] and committed as one
+    /// version: the deleted and updated rows get deletion files and the new rows are
+    /// written as new fragments.
+    async fn merge_insert_with_delete(
+        &self,
+        params: MergeInsertBuilder,
+        source_schema: SchemaRef,
+        source: Vec<RecordBatch>,
+    ) -> Result<MergeResult> {
+        self.dataset.ensure_mutable().await?;
+        let table = Table::new(Arc::new(self.clone()));
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            let dataset = self.dataset.get().await?.clone();
+            let changes =
+                merge::plan_merge(&params, &table, source_schema.clone(), source.clone()).await?;
+            let (updated_fragments, removed_fragment_ids) =
+                take::delete_row_ids(&dataset, &changes.removed_row_ids).await?;
+            let write_params = self.patch_write_params(WriteParams {
+                mode: WriteMode::Append,
+                ..Default::default()
+            })?;
+            let mut new_fragments = Vec::new();
+            if !changes.new_rows.is_empty() {
+                let new_rows = Box::new(RecordBatchIterator::new(
+                    changes.new_rows.into_iter().map(Ok),
+                    source_schema.clone(),
+                ));
+                let new_rows = MaybeEmbedded::try_new(
+                    new_rows,
+                    self.table_definition().await?,
+                    Some(params.embedding_registry.clone()),
+                )?
+                .embed_ahead(self.commit_retry_config.max_replayable_size)
+                .await?;
+                let new_rows = CoercingReader::new(
+                    Box::new(new_rows),
+                    &Schema::from(dataset.schema()),
+                    Coercion::Vectors,
+                );
+                new_fragments = write_fragments(&self.uri, new_rows, write_params.clone()).await?;
+            }
+
+            let version = if removed_fragment_ids.is_empty()
+                && updated_fragments.is_empty()
+                && new_fragments.is_empty()
+            {
+                dataset.version().version
+            } else {
+                let operation = Operation::Update {
+                    removed_fragment_ids,
+                    updated_fragments,
+                    new_fragments,
+                };
+                match Dataset::commit(
+                    &self.uri,
+                    operation,
+                    Some(dataset.version().version),
+                    write_params.store_params,
+                    None,
+                )
+                .await
+                {
+                    Ok(new_dataset) => {
+                        let version = new_dataset.version().version;
+                        self.dataset.set_latest(new_dataset).await;
+                        version
+                    }
+                    Err(err) => {
+                        retry.retry(err.into()).await?;
+                        continue;
+                    }
+                }
+            };
+            return Ok(MergeResult {
+                num_inserted: Some(changes.num_inserted),
+                num_updated: Some(changes.num_updated),
+                num_deleted: Some(changes.num_deleted),
+                version: Some(version),
+            });
+        }
+    }
+
+    /// Add the storage options and the store wrapper of the table to the params
+    /// of a write
+    fn patch_write_params(&self, mut lance_params: WriteParams) -> Result<WriteParams> {
+        // Bring storage options from table
+        let storage_options = lance_params
+            .store_params
+            .get_or_insert(Default::default())
+            .storage_options
+            .get_or_insert(Default::default());
+        for (key, value) in self.storage_options.iter() {
+            if !storage_options.contains_key(key) {
+                storage_options.insert(key.clone(), value.clone());
+            }
+        }
+
+        // patch the params if we have a write store wrapper
+        match self.store_wrapper.clone() {
+            Some(wrapper) => Ok(lance_params.patch_with_store_wrapper(wrapper)?),
+            None => Ok(lance_params),
+        }
+    }
+
+    /// Merge new data into this table.
     pub async fn merge(
         &mut self,
         batches: impl RecordBatchReader + Send + 'static,
@@ -1164,6 +2237,14 @@ impl NativeTable {
         older_than: Duration,
         delete_unverified: Option<bool>,
     ) -> Result<RemovalStats> {
+        if let Some(version) = self.dataset.time_travel_version().await {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "cannot clean up old versions while version {} is checked out, it could be deleted",
+                    version
+                ),
+            });
+        }
         Ok(self
             .dataset
             .get_mut()
@@ -1177,14 +2258,81 @@ impl NativeTable {
     /// This can be run after making several small appends to optimize the table
     /// for faster reads.
     ///
-    /// This calls into [lance::dataset::optimize::compact_files].
+    /// This calls into [lance::dataset::optimize::compact_files].  If `progress` is
+    /// set, the plan is executed task by task instead so each task can be reported.
+    ///
+    /// The fragments that were rewritten are found by comparing the fragments
+    /// before and after the compaction.  If nothing needs to be compacted then
+    /// no version is committed and no fragment is reported.
     async fn compact_files(
         &self,
         options: CompactionOptions,
         remap_options: Option<Arc<dyn IndexRemapperOptions>>,
-    ) -> Result<CompactionMetrics> {
+        progress: Option<CompactionProgressCallback>,
+    ) -> Result<(CompactionMetrics, CompactedFragments)> {
         let mut dataset_mut = self.dataset.get_mut().await?;
-        let metrics = compact_files(&mut dataset_mut, options, remap_options).await?;
+        let fragment_ids = |dataset: &Dataset| {
+            dataset
+                .get_fragments()
+                .iter()
+                .map(|fragment| fragment.id() as u64)
+                .collect::<HashSet<_>>()
+        };
+        let before = fragment_ids(&dataset_mut);
+        let metrics =
+            Self::run_compaction(&mut dataset_mut, options, remap_options, progress).await?;
+        let after = fragment_ids(&dataset_mut);
+
+        let mut fragments = CompactedFragments {
+            removed: before.difference(&after).copied().collect(),
+            added: after.difference(&before).copied().collect(),
+        };
+        fragments.removed.sort_unstable();
+        fragments.added.sort_unstable();
+        Ok((metrics, fragments))
+    }
+
+    async fn run_compaction(
+        dataset_mut: &mut Dataset,
+        options: CompactionOptions,
+        remap_options: Option<Arc<dyn IndexRemapperOptions>>,
+        progress: Option<CompactionProgressCallback>,
+    ) -> Result<CompactionMetrics> {
+        let Some(progress) = progress else {
+            let metrics = compact_files(dataset_mut, options, remap_options).await?;
+            return Ok(metrics);
+        };
+
+        let plan = plan_compaction(dataset_mut, &options).await?;
+        let tasks = plan.compaction_tasks().collect::<Vec<_>>();
+        let mut report = CompactionProgress {
+            fragments_compacted: 0,
+            total_fragments: tasks.iter().map(|task| task.task.fragments.len()).sum(),
+        };
+        progress(&report);
+        if tasks.is_empty() {
+            return Ok(CompactionMetrics::default());
+        }
+
+        let dataset: &Dataset = dataset_mut;
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let mut results = futures::stream::iter(tasks)
+            .map(|task| async move {
+                let num_fragments = task.task.fragments.len();
+                Ok::<_, Error>((num_fragments, task.execute(dataset).await?))
+            })
+            .buffer_unordered(parallelism);
+        let mut completed = Vec::new();
+        while let Some((num_fragments, result)) = results.try_next().await? {
+            completed.push(result);
+            report.fragments_compacted += num_fragments;
+            progress(&report);
+        }
+        drop(results);
+
+        let remap_options = remap_options
+            .unwrap_or_else(|| Arc::new(lance::index::DatasetIndexRemapperOptions::default()));
+        let metrics = commit_compaction(dataset_mut, completed, remap_options).await?;
         Ok(metrics)
     }
 
@@ -1506,414 +2654,1387 @@ impl NativeTable {
         Ok(())
     }
 
-    async fn generic_query(
+    async fn create_fts_index(
         &self,
-        query: &VectorQuery,
-        options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream> {
-        let plan = self.create_plan(query, options).await?;
-        Ok(DatasetRecordBatchStream::new(execute_plan(
-            plan,
-            Default::default(),
-        )?))
-    }
-}
-
-#[async_trait::async_trait]
-impl TableInternal for NativeTable {
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
-    }
-
-    fn as_native(&self) -> Option<&NativeTable> {
-        Some(self)
-    }
-
-    fn name(&self) -> &str {
-        self.name.as_str()
-    }
-
-    async fn version(&self) -> Result<u64> {
-        Ok(self.dataset.get().await?.version().version)
-    }
-
-    async fn checkout(&self, version: u64) -> Result<()> {
-        self.dataset.as_time_travel(version).await
+        field: &Field,
+        fts: FtsIndexBuilder,
+        replace: bool,
+    ) -> Result<()> {
+        if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+            return Err(Error::Schema {
+                message: format!(
+                    "A FTS index cannot be created on the field `{}` which has data type {}",
+                    field.name(),
+                    field.data_type()
+                ),
+            });
+        }
+        self.dataset.ensure_mutable().await?;
+        let dataset = self.dataset.get().await?.clone();
+        if !replace && self.open_fts_index(&dataset, field.name()).await?.is_some() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the column '{}' already has a FTS index, set replace to rebuild it",
+                    field.name()
+                ),
+            });
+        }
+        let field_id = dataset
+            .schema()
+            .field(field.name())
+            .ok_or_else(|| Error::Schema {
+                message: format!("the table has no column '{}'", field.name()),
+            })?
+            .id;
+        fts::create_index(
+            &dataset,
+            field.name(),
+            &fts,
+            &fts::index_uri(&self.uri, field_id),
+            self.patch_write_params(WriteParams::default())?,
+        )
+        .await
     }
 
-    async fn checkout_latest(&self) -> Result<()> {
-        self.dataset
-            .as_latest(self.read_consistency_interval)
-            .await?;
-        self.dataset.reload().await
-    }
+    /// A filter that keeps the rows of the fragments that the vector index on
+    /// `column` covers, see [`VectorQuery::fast_search`]
+    ///
+    /// Row ids are the id of the fragment in the upper 32 bits and the offset in the
+    /// lower ones, so the rows of consecutive fragments are one range of ids.
+    async fn indexed_fragments_filter(dataset: &Dataset, column: &str) -> Result<String> {
+        let field_id = dataset
+            .schema()
+            .field(column)
+            .map(|field| field.id)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("the column '{}' does not exist", column),
+            })?;
+        // Optimizing an index can add delta indices, the fragments they cover together
+        // are indexed
+        let indices = dataset.load_indices().await?;
+        let indices = indices
+            .iter()
+            .filter(|index| index.fields == [field_id])
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "fast_search needs a vector index on '{}', create one with create_index()",
+                    column
+                ),
+            });
+        }
+        let mut fragment_ids = BTreeSet::new();
+        for index in indices {
+            match &index.fragment_bitmap {
+                Some(bitmap) => fragment_ids.extend(bitmap.iter()),
+                // Indices written by older versions of lance don't record the
+                // fragments, they cover those of the version they were built on
+                None => fragment_ids.extend(
+                    dataset
+                        .checkout_version(index.dataset_version)
+                        .await?
+                        .get_fragments()
+                        .iter()
+                        .map(|fragment| fragment.id() as u32),
+                ),
+            }
+        }
 
-    async fn restore(&self) -> Result<()> {
-        let version =
-            self.dataset
-                .time_travel_version()
-                .await
-                .ok_or_else(|| Error::InvalidInput {
-                    message: "you must run checkout before running restore".to_string(),
-                })?;
-        {
-            // Use get_mut_unchecked as restore is the only "write" operation that is allowed
-            // when the table is in time travel mode.
-            // Also, drop the guard after .restore because as_latest will need it
-            let mut dataset = self.dataset.get_mut_unchecked().await?;
-            debug_assert_eq!(dataset.version().version, version);
-            dataset.restore().await?;
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for id in fragment_ids {
+            let id = id as u64;
+            match ranges.last_mut() {
+                Some((_, end)) if *end == id => *end = id + 1,
+                _ => ranges.push((id, id + 1)),
+            }
         }
-        self.dataset
-            .as_latest(self.read_consistency_interval)
-            .await?;
-        Ok(())
+        if ranges.is_empty() {
+            return Ok("false".to_string());
+        }
+        Ok(ranges
+            .iter()
+            .map(|(start, end)| format!("(_rowid >= {} AND _rowid < {})", start << 32, end << 32))
+            .collect::<Vec<_>>()
+            .join(" OR "))
     }
 
-    async fn schema(&self) -> Result<SchemaRef> {
-        let lance_schema = self.dataset.get().await?.schema().clone();
-        Ok(Arc::new(Schema::from(&lance_schema)))
+    /// Compute the columns of a [`Select::Dynamic`] from the output of `plan`
+    fn project_dynamic(
+        plan: Arc<dyn ExecutionPlan>,
+        select: &[(String, String)],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let planner = Planner::new(plan.schema());
+        let mut exprs = Vec::with_capacity(select.len());
+        for (name, sql) in select {
+            let expr = planner
+                .parse_expr(sql)
+                .and_then(|expr| planner.optimize_expr(expr))
+                .and_then(|expr| planner.create_physical_expr(&expr))
+                .map_err(|err| Self::invalid_select(sql, err))?;
+            exprs.push((expr, name.clone()));
+        }
+        Ok(Arc::new(ProjectionExec::try_new(exprs, plan)?))
     }
 
-    async fn table_definition(&self) -> Result<TableDefinition> {
-        let schema = self.schema().await?;
-        TableDefinition::try_from_rich_schema(schema)
+    /// Cast the `_distance` column of a vector search to f32, if it isn't already
+    ///
+    /// Rerankers and the merging of searches read the distances as f32, whatever the
+    /// type of the vectors, e.g. f16.
+    fn distance_as_f32(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = plan.schema();
+        match schema.field_with_name(DISTANCE) {
+            Ok(field) if field.data_type() != &DataType::Float32 => {}
+            _ => return Ok(plan),
+        }
+        let mut exprs: Vec<(Arc<dyn PhysicalExpr>, String)> = Vec::new();
+        for (i, field) in schema.fields().iter().enumerate() {
+            let column: Arc<dyn PhysicalExpr> = Arc::new(Column::new(field.name(), i));
+            let expr = if field.name() == DISTANCE {
+                cast(column, &schema, DataType::Float32)?
+            } else {
+                column
+            };
+            exprs.push((expr, field.name().clone()));
+        }
+        Ok(Arc::new(ProjectionExec::try_new(exprs, plan)?))
     }
 
-    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
-        Ok(self.dataset.get().await?.count_rows(filter).await?)
+    fn invalid_select(sql: &str, err: lance::Error) -> Error {
+        Error::InvalidInput {
+            message: format!("invalid select expression '{}': {}", sql, err),
+        }
     }
 
-    async fn add(
-        &self,
-        add: AddDataBuilder<NoData>,
-        data: Box<dyn RecordBatchReader + Send>,
+    /// Reject updates of the source columns of embeddings
+    ///
+    /// The new values are SQL expressions, the embeddings can't be recomputed.
+    pub(crate) fn check_update_embeddings<'a>(
+        table_definition: &TableDefinition,
+        columns: impl Iterator<Item = &'a str> + Clone,
     ) -> Result<()> {
-        let data =
-            MaybeEmbedded::try_new(data, self.table_definition().await?, add.embedding_registry)?;
-
-        let mut lance_params = add.write_options.lance_write_params.unwrap_or(WriteParams {
-            mode: match add.mode {
-                AddDataMode::Append => WriteMode::Append,
-                AddDataMode::Overwrite => WriteMode::Overwrite,
-            },
-            ..Default::default()
-        });
-
-        // Bring storage options from table
-        let storage_options = lance_params
-            .store_params
-            .get_or_insert(Default::default())
-            .storage_options
-            .get_or_insert(Default::default());
-        for (key, value) in self.storage_options.iter() {
-            if !storage_options.contains_key(key) {
-                storage_options.insert(key.clone(), value.clone());
+        for column in &table_definition.column_definitions {
+            if let ColumnKind::Embedding(definition) = &column.kind {
+                if columns.clone().any(|name| name == definition.source_column) {
+                    return Err(Error::InvalidInput {
+                        message: format!(
+                            "cannot update column '{}', the embedding column '{}' is computed from it.  Use merge_insert to update it and recompute the embeddings",
+                            definition.source_column,
+                            definition.dest_column_name()
+                        ),
+                    });
+                }
             }
         }
-
-        // patch the params if we have a write store wrapper
-        let lance_params = match self.store_wrapper.clone() {
-            Some(wrapper) => lance_params.patch_with_store_wrapper(wrapper)?,
-            None => lance_params,
-        };
-
-        self.dataset.ensure_mutable().await?;
-        let dataset = Dataset::write(data, &self.uri, Some(lance_params)).await?;
-
-        self.dataset.set_latest(dataset).await;
         Ok(())
     }
 
-    async fn create_index(&self, opts: IndexBuilder) -> Result<()> {
-        if opts.columns.len() != 1 {
-            return Err(Error::Schema {
-                message: "Multi-column (composite) indices are not yet supported".to_string(),
-            });
+    /// Check that the columns, expressions and filter of an update refer to columns
+    /// that exist, before anything is written
+    fn check_update(update: &UpdateBuilder, dataset: &Dataset) -> Result<()> {
+        let returning = match &update.returning {
+            Some(Returning::Old(columns) | Returning::New(columns)) => columns.as_slice(),
+            None => &[],
+        };
+        let columns = update.columns.iter().map(|(column, _)| column);
+        for column in columns.chain(returning) {
+            if dataset.schema().field(column).is_none() {
+                return Err(Error::InvalidInput {
+                    message: format!("column '{}' does not exist in the table", column),
+                });
+            }
         }
-        let schema = self.schema().await?;
-
-        let field = schema.field_with_name(&opts.columns[0])?;
 
-        match opts.index {
-            Index::Auto => self.create_auto_index(field, opts).await,
-            Index::BTree(_) => self.create_btree_index(field, opts).await,
-            Index::IvfPq(ivf_pq) => self.create_ivf_pq_index(ivf_pq, field, opts.replace).await,
-            Index::IvfHnswPq(ivf_hnsw_pq) => {
-                self.create_ivf_hnsw_pq_index(ivf_hnsw_pq, field, opts.replace)
-                    .await
-            }
-            Index::IvfHnswSq(ivf_hnsw_sq) => {
-                self.create_ivf_hnsw_sq_index(ivf_hnsw_sq, field, opts.replace)
-                    .await
+        // Planned with lance's planner, like the update itself, and with the columns
+        // of row ids and addresses, which filters can refer to
+        let mut fields = Schema::from(dataset.schema()).fields().to_vec();
+        for meta_column in [ROW_ID, ROW_ADDR] {
+            if dataset.schema().field(meta_column).is_none() {
+                fields.push(Arc::new(Field::new(meta_column, DataType::UInt64, true)));
             }
         }
-    }
-
-    async fn update(&self, update: UpdateBuilder) -> Result<()> {
-        let dataset = self.dataset.get().await?.clone();
-        let mut builder = LanceUpdateBuilder::new(Arc::new(dataset));
-        if let Some(predicate) = update.filter {
-            builder = builder.update_where(&predicate)?;
+        let planner = Planner::new(Arc::new(Schema::new(fields)));
+        for (column, expr) in &update.columns {
+            planner
+                .parse_expr(expr)
+                .and_then(|expr| planner.optimize_expr(expr))
+                .and_then(|expr| planner.create_physical_expr(&expr))
+                .map_err(|err| Error::InvalidInput {
+                    message: format!(
+                        "invalid expression '{}' for column '{}': {}",
+                        expr, column, err
+                    ),
+                })?;
         }
-
-        for (column, value) in update.columns {
-            builder = builder.set(column, &value)?;
+        if let Some(filter) = &update.filter {
+            planner
+                .parse_filter(filter)
+                .and_then(|expr| planner.optimize_expr(expr))
+                .and_then(|expr| planner.create_physical_expr(&expr))
+                .map_err(|err| Error::InvalidInput {
+                    message: format!("invalid update filter '{}': {}", filter, err),
+                })?;
         }
-
-        let operation = builder.build()?;
-        let ds = operation.execute().await?;
-        self.dataset.set_latest(ds.as_ref().clone()).await;
         Ok(())
     }
 
-    async fn create_plan(
-        &self,
-        query: &VectorQuery,
-        options: QueryExecutionOptions,
-    ) -> Result<Arc<dyn ExecutionPlan>> {
-        let ds_ref = self.dataset.get().await?;
-        let mut scanner: Scanner = ds_ref.scan();
+    /// The rows of a scan of `columns`, as one batch
+    async fn collect_rows(
+        dataset: &Dataset,
+        columns: &[String],
+        scanner: Scanner,
+    ) -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::from(&dataset.schema().project(columns)?));
+        let batches = scanner
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(arrow::compute::concat_batches(&schema, &batches)?)
+    }
 
-        if let Some(query_vector) = query.query_vector.as_ref() {
-            // If there is a vector query, default to limit=10 if unspecified
-            let column = if let Some(col) = query.column.as_ref() {
-                col.clone()
+    /// Plan a search for each query vector and combine their results
+    ///
+    /// The searches run in parallel, a `query_index` column tells which query
+    /// vector each row was found for.
+    async fn create_multi_vector_plan(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // The searches share the distance type, which may have to be read from the index
+        let schema = self.schema().await?;
+        let column = resolve_vector_column(
+            &schema,
+            query.column.as_deref(),
+            query.query_vector[0].len(),
+        )?;
+        let distance_type = self.resolve_distance_type(query, &column).await?;
+        let mut plans = Vec::with_capacity(query.query_vector.len());
+        for (query_index, query_vector) in query.query_vector.iter().enumerate() {
+            let mut single = query.clone();
+            single.query_vector = vec![query_vector.clone()];
+            let plan = self
+                .plan_query(&single, options.clone(), Some(distance_type))
+                .await?;
+            let mut exprs: Vec<(Arc<dyn PhysicalExpr>, String)> = vec![(
+                Arc::new(Literal::new(ScalarValue::UInt32(Some(query_index as u32)))),
+                QUERY_INDEX.to_string(),
+            )];
+            for (i, field) in plan.schema().fields().iter().enumerate() {
+                exprs.push((Arc::new(Column::new(field.name(), i)), field.name().clone()));
+            }
+            plans.push(Arc::new(ProjectionExec::try_new(exprs, plan)?) as Arc<dyn ExecutionPlan>);
+        }
+        Ok(Arc::new(CoalescePartitionsExec::new(Arc::new(
+            UnionExec::new(plans),
+        ))))
+    }
+
+    /// Plan a query, see [`TableInternal::create_plan`]
+    ///
+    /// `distance_type` is the result of [`TableInternal::resolve_distance_type`] if
+    /// it was already resolved for the query.
+    async fn plan_query(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+        distance_type: Option<Option<DistanceType>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let mut query = query.clone();
+        query.base = query.base.resolve_row_id_selection().await?;
+        let query = &query;
+        if !query.base.group_by.is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "group_by() only applies to aggregate(), {} can't be grouped",
+                    if query.query_vector.is_empty() && query.query_text.is_none() {
+                        "a query without aggregates"
+                    } else {
+                        "a vector search"
+                    }
+                ),
+            });
+        }
+        if query.query_vector.len() > 1 && !query.multivector {
+            return self.create_multi_vector_plan(query, options).await;
+        }
+        let ds_ref = self.dataset.get().await?;
+        if query.base.fragments.is_some() {
+            let unsupported = if query.base.sample.is_some() {
+                Some("samples")
+            } else if query.base.full_text_search.is_some() {
+                Some("full text searches")
             } else {
-                // Infer a vector column with the same dimension of the query vector.
-                let arrow_schema = Schema::from(ds_ref.schema());
-                default_vector_column(&arrow_schema, Some(query_vector.len() as i32))?
+                None
             };
-            let field = ds_ref.schema().field(&column).ok_or(Error::Schema {
-                message: format!("Column {} not found in dataset schema", column),
-            })?;
-            if let arrow_schema::DataType::FixedSizeList(f, dim) = field.data_type() {
-                if !f.data_type().is_floating() {
-                    return Err(Error::InvalidInput {
-                        message: format!(
-                            "The data type of the vector column '{}' is not a floating point type",
-                            column
-                        ),
-                    });
+            if let Some(unsupported) = unsupported {
+                return Err(Error::InvalidInput {
+                    message: format!("with_fragments() doesn't apply to {}", unsupported),
+                });
+            }
+        }
+        if let Some(sample) = &query.base.sample {
+            if !query.query_vector.is_empty()
+                || query.query_text.is_some()
+                || query.base.full_text_search.is_some()
+            {
+                return Err(Error::InvalidInput {
+                    message: "sample() only applies to queries that aren't searches".to_string(),
+                });
+            }
+            let batch = sample::sample_rows(&ds_ref, &query.base, sample).await?;
+            let stream =
+                RecordBatchStreamAdapter::new(batch.schema(), futures::stream::iter([Ok(batch)]));
+            return Ok(Arc::new(OneShotExec::new(Box::pin(stream))));
+        }
+        if let Some(full_text_search) = &query.base.full_text_search {
+            if !query.query_vector.is_empty() {
+                return Err(Error::InvalidInput {
+                    message: "a query can't be a vector search and a full text search, use hybrid() to combine them".to_string(),
+                });
+            }
+            let columns = fts::searched_columns(&ds_ref, full_text_search)?;
+            let mut indices = Vec::with_capacity(columns.len());
+            for column in &columns {
+                if !full_text_search.use_index {
+                    indices.push(None);
+                    continue;
                 }
-                if dim != query_vector.len() as i32 {
+                let index = self.open_fts_index(&ds_ref, column).await?;
+                if index.is_none() {
                     return Err(Error::InvalidInput {
                         message: format!(
-                            "The dimension of the query vector does not match with the dimension of the vector column '{}': \
-                                query dim={}, expected vector dim={}",
-                            column,
-                            query_vector.len(),
-                            dim,
+                            "the column '{}' has no FTS index, create one with Index::FTS or call bypass_index() to read every row",
+                            column
                         ),
                     });
                 }
+                indices.push(index);
+            }
+            let batch =
+                fts::full_text_search(&ds_ref, &columns, &indices, &query.base, full_text_search)
+                    .await?;
+            let stream =
+                RecordBatchStreamAdapter::new(batch.schema(), futures::stream::iter([Ok(batch)]));
+            return Ok(Arc::new(OneShotExec::new(Box::pin(stream))));
+        }
+        if let Some(query_vector) = query.query_vector.first() {
+            let arrow_schema = Schema::from(ds_ref.schema());
+            let column =
+                resolve_vector_column(&arrow_schema, query.column.as_deref(), query_vector.len())?;
+            let is_multivector =
+                multivector_dimension(arrow_schema.field_with_name(&column)?.data_type()).is_some();
+            if query.multivector && !is_multivector {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the column '{}' is not a multivector column, use nearest_to_many() for a batch of searches",
+                        column
+                    ),
+                });
+            }
+            if is_multivector {
+                let batch = multivector::flat_multivector_search(&ds_ref, query, &column).await?;
+                let stream = RecordBatchStreamAdapter::new(
+                    batch.schema(),
+                    futures::stream::iter([Ok(batch)]),
+                );
+                return Ok(Arc::new(OneShotExec::new(Box::pin(stream))));
+            }
+        }
+        if query.base.fragments.is_some() && !query.query_vector.is_empty() && query.use_index {
+            return Err(Error::InvalidInput {
+                message: "with_fragments() doesn't apply to vector searches that use the vector index, use bypass_vector_index() for a flat search".to_string(),
+            });
+        }
+        let mut scanner: Scanner = ds_ref.scan();
+        let mut fast_search_filter = None;
+
+        if let Some(query_vector) = query.query_vector.first() {
+            // If there is a vector query, default to limit=10 if unspecified
+            let arrow_schema = Schema::from(ds_ref.schema());
+            let column =
+                resolve_vector_column(&arrow_schema, query.column.as_deref(), query_vector.len())?;
+            if query.fast_search && query.use_index {
+                fast_search_filter = Some(Self::indexed_fragments_filter(&ds_ref, &column).await?);
             }
             let query_vector = query_vector.as_primitive::<Float32Type>();
+            // The nearest rows are skipped by the limit below, so search for them too
+            let limit = query.base.limit.unwrap_or(DEFAULT_TOP_K);
             scanner.nearest(
                 &column,
                 query_vector,
-                query.base.limit.unwrap_or(DEFAULT_TOP_K),
+                limit + query.base.offset.unwrap_or(0),
             )?;
+            if let Some(offset) = query.base.offset {
+                scanner.limit(Some(limit as i64), Some(offset as i64))?;
+            }
+            let distance_type = match distance_type {
+                Some(distance_type) => distance_type,
+                None => self.resolve_distance_type(query, &column).await?,
+            };
+            if let Some(distance_type) = distance_type {
+                scanner.distance_metric(distance_type.into());
+            }
         } else {
             // If there is no vector query, it's ok to not have a limit
-            scanner.limit(query.base.limit.map(|limit| limit as i64), None)?;
+            scanner.limit(
+                query.base.limit.map(|limit| limit as i64),
+                query.base.offset.map(|offset| offset as i64),
+            )?;
+        }
+        if let Some(fragment_ids) = &query.base.fragments {
+            fragments::scan_fragments(&mut scanner, &ds_ref, fragment_ids)?;
         }
         scanner.nprobs(query.nprobes);
         scanner.use_index(query.use_index);
-        scanner.prefilter(query.prefilter);
+        // The rows that aren't indexed must be left out before the search
+        scanner.prefilter(query.prefilter || fast_search_filter.is_some());
         scanner.batch_size(options.max_batch_length as usize);
+        if query.base.with_row_id {
+            scanner.with_row_id();
+        }
+        if query.base.with_row_address {
+            scanner.with_row_address();
+        }
 
         match &query.base.select {
             Select::Columns(select) => {
                 scanner.project(select.as_slice())?;
             }
             Select::Dynamic(select_with_transform) => {
-                scanner.project_with_transform(select_with_transform.as_slice())?;
+                // The expressions are computed from the output of the scan, so they can
+                // use columns like _distance, the scan only needs the stored columns
+                let planner = Planner::new(Arc::new(Schema::from(ds_ref.schema())));
+                let mut columns = Vec::new();
+                for (_, sql) in select_with_transform {
+                    let expr = planner
+                        .parse_expr(sql)
+                        .map_err(|err| Self::invalid_select(sql, err))?;
+                    for column in Planner::column_names_in_expr(&expr) {
+                        if ds_ref.schema().field(&column).is_some() && !columns.contains(&column) {
+                            columns.push(column);
+                        }
+                    }
+                }
+                scanner.project(columns.as_slice())?;
             }
             Select::All => { /* Do nothing */ }
         }
 
-        if let Some(filter) = &query.base.filter {
-            scanner.filter(filter)?;
+        match (&query.base.filter, fast_search_filter) {
+            (Some(filter), Some(indexed)) => {
+                scanner.filter(&format!("({}) AND ({})", filter, indexed))?;
+            }
+            (Some(filter), None) | (None, Some(filter)) => {
+                scanner.filter(filter)?;
+            }
+            (None, None) => {}
         }
 
         if let Some(refine_factor) = query.refine_factor {
             scanner.refine(refine_factor);
         }
 
-        if let Some(distance_type) = query.distance_type {
-            scanner.distance_metric(distance_type.into());
+        if let Some(ef) = query.ef {
+            scanner.ef(ef);
+        }
+
+        let plan = Self::distance_as_f32(scanner.create_plan().await?)?;
+        match &query.base.select {
+            Select::Dynamic(select_with_transform) => {
+                Self::project_dynamic(plan, select_with_transform)
+            }
+            _ => Ok(plan),
         }
-        Ok(scanner.create_plan().await?)
     }
 
-    async fn plain_query(
+    async fn generic_query(
         &self,
-        query: &Query,
+        query: &VectorQuery,
         options: QueryExecutionOptions,
     ) -> Result<DatasetRecordBatchStream> {
-        self.generic_query(&query.clone().into_vector(), options)
-            .await
+        let plan = self.create_plan(query, options).await?;
+        Ok(DatasetRecordBatchStream::new(execute_plan(
+            plan,
+            Default::default(),
+        )?))
     }
+}
 
-    async fn merge_insert(
-        &self,
-        params: MergeInsertBuilder,
-        new_data: Box<dyn RecordBatchReader + Send>,
-    ) -> Result<()> {
-        let dataset = Arc::new(self.dataset.get().await?.clone());
-        let mut builder = LanceMergeInsertBuilder::try_new(dataset.clone(), params.on)?;
-        match (
-            params.when_matched_update_all,
-            params.when_matched_update_all_filt,
-        ) {
-            (false, _) => builder.when_matched(WhenMatched::DoNothing),
-            (true, None) => builder.when_matched(WhenMatched::UpdateAll),
-            (true, Some(filt)) => builder.when_matched(WhenMatched::update_if(&dataset, &filt)?),
-        };
-        if params.when_not_matched_insert_all {
-            builder.when_not_matched(lance::dataset::WhenNotMatched::InsertAll);
-        } else {
-            builder.when_not_matched(lance::dataset::WhenNotMatched::DoNothing);
+#[async_trait::async_trait]
+impl TableInternal for NativeTable {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_native(&self) -> Option<&NativeTable> {
+        Some(self)
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    fn uri(&self) -> Option<String> {
+        Some(self.uri.clone())
+    }
+
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    async fn version(&self) -> Result<u64> {
+        Ok(self.dataset.get().await?.version().version)
+    }
+
+    async fn latest_version(&self) -> Result<u64> {
+        Ok(self.dataset.get().await?.latest_version_id().await?)
+    }
+
+    async fn version_operations(&self, from: u64, to: u64) -> Result<Vec<ChangeOperation>> {
+        let dataset = self.dataset.get().await?.clone();
+        let mut previous = dataset.checkout_version(from).await?;
+        let mut operations = Vec::new();
+        for version in from + 1..=to {
+            let next = dataset.checkout_version(version).await?;
+            operations.push(watch::classify(&previous, &next));
+            previous = next;
         }
-        if params.when_not_matched_by_source_delete {
-            let behavior = if let Some(filter) = params.when_not_matched_by_source_delete_filt {
-                WhenNotMatchedBySource::delete_if(dataset.as_ref(), &filter)?
-            } else {
-                WhenNotMatchedBySource::Delete
-            };
-            builder.when_not_matched_by_source(behavior);
-        } else {
-            builder.when_not_matched_by_source(WhenNotMatchedBySource::Keep);
+        Ok(operations)
+    }
+
+    async fn list_versions(&self) -> Result<Vec<VersionInfo>> {
+        let mut versions = self
+            .dataset
+            .get()
+            .await?
+            .versions()
+            .await?
+            .into_iter()
+            .map(|version| VersionInfo {
+                version: version.version,
+                timestamp: version.timestamp,
+                metadata: version.metadata.into_iter().collect(),
+            })
+            .collect::<Vec<_>>();
+        versions.sort_by_key(|version| version.version);
+        Ok(versions)
+    }
+
+    async fn list_tags(&self) -> Result<HashMap<String, u64>> {
+        let dataset = self.dataset.get().await?;
+        Ok(dataset
+            .tags
+            .list()
+            .await?
+            .into_iter()
+            .map(|(name, tag)| (name, tag.version))
+            .collect())
+    }
+
+    async fn create_tag(&self, name: &str, version: u64) -> Result<()> {
+        if self.list_tags().await?.contains_key(name) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "tag '{}' already exists, use update to change its version",
+                    name
+                ),
+            });
         }
-        let job = builder.try_build()?;
-        let (new_dataset, _stats) = job.execute_reader(new_data).await?;
-        self.dataset.set_latest(new_dataset.as_ref().clone()).await;
+        // Tags don't change the data, so they can be created while checked out
+        let mut dataset = self.dataset.get_mut_unchecked().await?;
+        dataset.tags.create(name, version).await?;
         Ok(())
     }
 
-    /// Delete rows from the table
-    async fn delete(&self, predicate: &str) -> Result<()> {
-        self.dataset.get_mut().await?.delete(predicate).await?;
+    async fn update_tag(&self, name: &str, version: u64) -> Result<()> {
+        if !self.list_tags().await?.contains_key(name) {
+            return Err(Error::InvalidInput {
+                message: format!("tag '{}' does not exist", name),
+            });
+        }
+        let mut dataset = self.dataset.get_mut_unchecked().await?;
+        dataset.tags.update(name, version).await?;
         Ok(())
     }
 
-    async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats> {
-        let mut stats = OptimizeStats {
-            compaction: None,
-            prune: None,
-        };
-        match action {
-            OptimizeAction::All => {
-                stats.compaction = self
-                    .optimize(OptimizeAction::Compact {
-                        options: CompactionOptions::default(),
-                        remap_options: None,
-                    })
-                    .await?
-                    .compaction;
-                stats.prune = self
-                    .optimize(OptimizeAction::Prune {
-                        older_than: None,
-                        delete_unverified: None,
-                    })
-                    .await?
-                    .prune;
-                self.optimize(OptimizeAction::Index(OptimizeOptions::default()))
-                    .await?;
-            }
-            OptimizeAction::Compact {
-                options,
-                remap_options,
-            } => {
-                stats.compaction = Some(self.compact_files(options, remap_options).await?);
-            }
-            OptimizeAction::Prune {
-                older_than,
-                delete_unverified,
-            } => {
-                stats.prune = Some(
-                    self.cleanup_old_versions(
-                        older_than.unwrap_or(Duration::try_days(7).expect("valid delta")),
-                        delete_unverified,
-                    )
-                    .await?,
-                );
-            }
-            OptimizeAction::Index(options) => {
-                self.optimize_indices(&options).await?;
-            }
+    async fn delete_tag(&self, name: &str) -> Result<()> {
+        if !self.list_tags().await?.contains_key(name) {
+            return Err(Error::InvalidInput {
+                message: format!("tag '{}' does not exist", name),
+            });
         }
-        Ok(stats)
+        let mut dataset = self.dataset.get_mut_unchecked().await?;
+        dataset.tags.delete(name).await?;
+        Ok(())
     }
 
-    async fn add_columns(
-        &self,
-        transforms: NewColumnTransform,
-        read_columns: Option<Vec<String>>,
-    ) -> Result<()> {
+    async fn checkout(&self, version: u64) -> Result<()> {
+        self.dataset.as_time_travel(version).await
+    }
+
+    async fn checkout_latest(&self) -> Result<()> {
         self.dataset
-            .get_mut()
-            .await?
-            .add_columns(transforms, read_columns)
+            .as_latest(self.read_consistency_interval)
             .await?;
-        Ok(())
+        self.dataset.reload().await
     }
 
-    async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()> {
+    async fn restore(&self) -> Result<()> {
+        let version =
+            self.dataset
+                .time_travel_version()
+                .await
+                .ok_or_else(|| Error::InvalidInput {
+                    message: "you must run checkout before running restore".to_string(),
+                })?;
+        {
+            // Use get_mut_unchecked as restore is the only "write" operation that is allowed
+            // when the table is in time travel mode.
+            // Also, drop the guard after .restore because as_latest will need it
+            let mut dataset = self.dataset.get_mut_unchecked().await?;
+            debug_assert_eq!(dataset.version().version, version);
+            dataset.restore().await?;
+        }
         self.dataset
-            .get_mut()
-            .await?
-            .alter_columns(alterations)
+            .as_latest(self.read_consistency_interval)
             .await?;
         Ok(())
     }
 
-    async fn drop_columns(&self, columns: &[&str]) -> Result<()> {
-        self.dataset.get_mut().await?.drop_columns(columns).await?;
-        Ok(())
+    async fn schema(&self) -> Result<SchemaRef> {
+        let lance_schema = self.dataset.get().await?.schema().clone();
+        Ok(Arc::new(Schema::from(&lance_schema)))
     }
 
-    async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
-        let dataset = self.dataset.get().await?;
-        let indices = dataset.load_indices().await?;
-        indices.iter().map(|idx| {
-            let mut is_vector = false;
-            let mut columns = Vec::with_capacity(idx.fields.len());
-            for field_id in &idx.fields {
-                let field = dataset.schema().field_by_id(*field_id).ok_or_else(|| Error::Runtime { message: format!("The index with name {} and uuid {} referenced a field with id {} which does not exist in the schema", idx.name, idx.uuid, field_id) })?;
-                if field.data_type().is_nested() {
-                    // Temporary hack to determine if an index is scalar or vector
-                    // Should be removed in https://github.com/lancedb/lance/issues/2039
-                    is_vector = true;
-                }
-                columns.push(field.name.clone());
-            }
+    async fn stats(&self) -> Result<TableStatistics> {
+        let dataset = (*self.dataset.get().await?).clone();
+        let version = dataset.version();
+        let mut lengths = Vec::new();
+        let mut num_deleted_rows = 0;
+        for fragment in dataset.get_fragments() {
+            let rows = fragment.count_rows().await?;
+            num_deleted_rows += fragment.physical_rows().await? - rows;
+            lengths.push(rows);
+        }
 
-            let index_type = if is_vector {
-                crate::index::IndexType::IvfPq
-            } else {
-                crate::index::IndexType::BTree
-            };
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, base) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        let total_bytes = store
+            .inner
+            .list(Some(&base))
+            .try_fold(0, |total, file| async move { Ok(total + file.size as u64) })
+            .await?;
 
-            let name = idx.name.clone();
-            Ok(IndexConfig { index_type, columns, name })
-        }).collect::<Result<Vec<_>>>()
+        Ok(TableStatistics {
+            version: version.version,
+            last_write: version.timestamp,
+            total_bytes,
+            num_rows: lengths.iter().sum(),
+            num_deleted_rows,
+            fragment_stats: FragmentStatistics::from_lengths(lengths),
+            index_coverage: stats::index_coverage(self).await?,
+        })
     }
-}
 
-#[cfg(test)]
-#[allow(deprecated)]
-mod tests {
-    use std::iter;
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
-    use std::time::Duration;
+    async fn table_definition(&self) -> Result<TableDefinition> {
+        let schema = self.schema().await?;
+        TableDefinition::try_from_rich_schema(schema)
+    }
 
-    use arrow_array::{
-        Array, BooleanArray, Date32Array, FixedSizeListArray, Float32Array, Float64Array,
-        Int32Array, Int64Array, LargeStringArray, RecordBatch, RecordBatchIterator,
-        RecordBatchReader, StringArray, TimestampMillisecondArray, TimestampNanosecondArray,
+    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        Ok(self.dataset.get().await?.count_rows(filter).await?)
+    }
+
+    async fn count_rows_approx(&self, filter: Option<String>) -> Result<ApproxCount> {
+        let dataset = self.dataset.get().await?;
+        count::count_rows_approx(&dataset, filter.as_deref()).await
+    }
+
+    async fn take(
+        &self,
+        row_ids: &[u64],
+        columns: Option<Vec<String>>,
+        missing: MissingRows,
+    ) -> Result<RecordBatch> {
+        let dataset = self.dataset.get().await?;
+        take::take_rows(&dataset, row_ids, columns.as_deref(), missing).await
+    }
+
+    async fn add(
+        &self,
+        add: AddDataBuilder<NoData>,
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<()> {
+        let table_definition = self.table_definition().await?;
+        // An overwrite may replace the schema, so only appends need to match it
+        if matches!(add.mode, AddDataMode::Append) {
+            ValidationReport {
+                issues: Validator::new(&table_definition).check_schema(&data.schema()),
+                ..Default::default()
+            }
+            .into_result()?;
+        }
+        // The part of the data that is kept for retries is embedded up front, so that
+        // the embeddings are awaited rather than computed while lance reads the data
+        let data = MaybeEmbedded::try_new(data, table_definition, add.embedding_registry)?
+            .with_concurrency(add.embedding_concurrency)
+            .embed_ahead(self.commit_retry_config.max_replayable_size)
+            .await?;
+
+        let lance_params =
+            self.patch_write_params(add.write_options.lance_write_params.unwrap_or(WriteParams {
+                mode: match add.mode {
+                    AddDataMode::Append => WriteMode::Append,
+                    AddDataMode::Overwrite => WriteMode::Overwrite,
+                },
+                ..Default::default()
+            }))?;
+
+        self.dataset.ensure_mutable().await?;
+        let mut data = ReplayableData::new(Box::new(data), &self.commit_retry_config);
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            match Dataset::write(data.reader()?, &self.uri, Some(lance_params.clone())).await {
+                Ok(dataset) => {
+                    self.dataset.set_latest(dataset).await;
+                    return Ok(());
+                }
+                Err(err) => retry.retry_write(err.into(), &data).await?,
+            }
+        }
+    }
+
+    async fn create_index(&self, opts: IndexBuilder) -> Result<()> {
+        if opts.columns.len() != 1 {
+            return Err(Error::Schema {
+                message: "Multi-column (composite) indices are not yet supported".to_string(),
+            });
+        }
+        let schema = self.schema().await?;
+
+        let field = schema.field_with_name(&opts.columns[0])?;
+        if multivector_dimension(field.data_type()).is_some() {
+            return Err(Error::NotSupported {
+                message: format!(
+                    "indices over multivector columns are not supported yet, searches of the column '{}' score every row",
+                    field.name()
+                ),
+            });
+        }
+
+        // Lance doesn't report progress while building, only the start and end are
+        let progress = match opts.progress.clone() {
+            Some(progress) => Some((progress, self.count_rows(None).await?)),
+            None => None,
+        };
+        if let Some((progress, total_rows)) = &progress {
+            progress(&IndexProgress {
+                rows_indexed: 0,
+                total_rows: *total_rows,
+            });
+        }
+
+        match opts.index {
+            Index::Auto => self.create_auto_index(field, opts).await,
+            Index::BTree(_) => self.create_btree_index(field, opts).await,
+            Index::FTS(fts) => self.create_fts_index(field, fts, opts.replace).await,
+            Index::IvfPq(ivf_pq) => self.create_ivf_pq_index(ivf_pq, field, opts.replace).await,
+            Index::IvfHnswPq(ivf_hnsw_pq) => {
+                self.create_ivf_hnsw_pq_index(ivf_hnsw_pq, field, opts.replace)
+                    .await
+            }
+            Index::IvfHnswSq(ivf_hnsw_sq) => {
+                self.create_ivf_hnsw_sq_index(ivf_hnsw_sq, field, opts.replace)
+                    .await
+            }
+        }?;
+
+        if let Some((progress, total_rows)) = &progress {
+            progress(&IndexProgress {
+                rows_indexed: *total_rows,
+                total_rows: *total_rows,
+            });
+        }
+        Ok(())
+    }
+
+    async fn update(&self, update: UpdateBuilder) -> Result<UpdateResult> {
+        Self::check_update_embeddings(
+            &self.table_definition().await?,
+            update.columns.iter().map(|(column, _)| column.as_str()),
+        )?;
+        Self::check_update(&update, &*self.dataset.get().await?)?;
+
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            let dataset = self.dataset.get().await?.clone();
+            let old_rows = match &update.returning {
+                Some(Returning::Old(columns)) => {
+                    let mut scanner = dataset.scan();
+                    scanner.project(columns)?;
+                    if let Some(filter) = &update.filter {
+                        scanner.filter(filter)?;
+                    }
+                    Some(Self::collect_rows(&dataset, columns, scanner).await?)
+                }
+                _ => None,
+            };
+            let fragment_ids = dataset
+                .get_fragments()
+                .iter()
+                .map(|fragment| fragment.id())
+                .collect::<HashSet<_>>();
+            let mut builder = LanceUpdateBuilder::new(Arc::new(dataset));
+            if let Some(predicate) = &update.filter {
+                builder = builder.update_where(predicate)?;
+            }
+
+            for (column, value) in &update.columns {
+                builder = builder.set(column, value)?;
+            }
+
+            let operation = builder.build()?;
+            match operation.execute().await {
+                Ok(ds) => {
+                    // Lance doesn't count the rows it updates, but it rewrites them to
+                    // new fragments, whose row counts are in the manifest
+                    let fragments = ds
+                        .get_fragments()
+                        .into_iter()
+                        .filter(|fragment| !fragment_ids.contains(&fragment.id()))
+                        .collect::<Vec<_>>();
+                    let mut num_rows = 0;
+                    for fragment in &fragments {
+                        num_rows += fragment.physical_rows().await? as u64;
+                    }
+                    let rows = match (old_rows, &update.returning) {
+                        (Some(rows), _) => rows,
+                        (None, Some(Returning::New(columns))) => {
+                            let fragments = fragments
+                                .iter()
+                                .map(|fragment| fragment.metadata().clone())
+                                .collect::<Vec<_>>();
+                            let mut scanner = ds.scan();
+                            scanner.project(columns)?;
+                            if fragments.is_empty() {
+                                scanner.limit(Some(0), None)?;
+                            } else {
+                                scanner.with_fragments(fragments);
+                            }
+                            Self::collect_rows(&ds, columns, scanner).await?
+                        }
+                        _ => RecordBatch::new_empty(Arc::new(Schema::empty())),
+                    };
+                    self.dataset.set_latest(ds.as_ref().clone()).await;
+                    return Ok(UpdateResult {
+                        num_updated: Some(num_rows),
+                        rows,
+                    });
+                }
+                Err(err) => retry.retry(err.into()).await?,
+            }
+        }
+    }
+
+    async fn create_plan(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        self.plan_query(query, options, None).await
+    }
+
+    async fn create_aggregate_plan(
+        &self,
+        query: &AggregateQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if query.base.full_text_search.is_some() {
+            return Err(Error::InvalidInput {
+                message: "a full text search can't be aggregated".to_string(),
+            });
+        }
+        if query.aggregates.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "aggregate() needs at least one aggregate".to_string(),
+            });
+        }
+        // Scan the filtered rows, reading only the columns that are aggregated
+        let mut columns = query.base.group_by.clone();
+        for aggregate in &query.aggregates {
+            if let Some(column) = &aggregate.column {
+                if !columns.contains(column) {
+                    columns.push(column.clone());
+                }
+            }
+        }
+        let mut input = query.base.clone();
+        input.group_by = Vec::new();
+        input.limit = None;
+        input.offset = None;
+        input.with_row_id = columns.is_empty();
+        input.with_row_address = false;
+        input.select = Select::Columns(columns);
+        let plan = self.create_plan(&input.into_vector(), options).await?;
+        let schema = plan.schema();
+
+        let group_by = query
+            .base
+            .group_by
+            .iter()
+            .map(|column| Ok((col(column, &schema)?, column.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        let mut aggregates: Vec<Arc<dyn AggregateExpr>> =
+            Vec::with_capacity(query.aggregates.len());
+        for aggregate in &query.aggregates {
+            let name = aggregate.name();
+            let input = match &aggregate.column {
+                Some(column) => col(column, &schema)?,
+                None => Arc::new(Literal::new(ScalarValue::Int64(Some(1)))),
+            };
+            let data_type = input.data_type(&schema)?;
+            aggregates.push(match aggregate.function {
+                AggFunction::Count => Arc::new(Count::new(input, name, DataType::Int64)),
+                AggFunction::Min => Arc::new(Min::new(input, name, data_type)),
+                AggFunction::Max => Arc::new(Max::new(input, name, data_type)),
+                AggFunction::Sum => {
+                    let sum_type = match &data_type {
+                        t if t.is_signed_integer() => DataType::Int64,
+                        t if t.is_unsigned_integer() => DataType::UInt64,
+                        t if t.is_floating() => DataType::Float64,
+                        _ => {
+                            return Err(Error::InvalidInput {
+                                message: format!(
+                                    "can't sum the column '{}' of type {}",
+                                    aggregate.column.as_deref().unwrap_or_default(),
+                                    data_type
+                                ),
+                            })
+                        }
+                    };
+                    Arc::new(Sum::new(input, name, sum_type))
+                }
+            });
+        }
+        let filters = vec![None; aggregates.len()];
+        let mut plan: Arc<dyn ExecutionPlan> = Arc::new(AggregateExec::try_new(
+            AggregateMode::Single,
+            PhysicalGroupBy::new_single(group_by),
+            aggregates,
+            filters,
+            Arc::new(CoalescePartitionsExec::new(plan)),
+            schema,
+        )?);
+        if query.base.limit.is_some() || query.base.offset.is_some() {
+            plan = Arc::new(GlobalLimitExec::new(
+                plan,
+                query.base.offset.unwrap_or(0),
+                query.base.limit,
+            ));
+        }
+        Ok(plan)
+    }
+
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String> {
+        let plan = self.create_plan(query, Default::default()).await?;
+        Ok(displayable(plan.as_ref()).indent(verbose).to_string())
+    }
+
+    async fn analyze_plan(&self, query: &VectorQuery) -> Result<String> {
+        analyze_plan(self.create_plan(query, Default::default()).await?).await
+    }
+
+    async fn plain_query(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<DatasetRecordBatchStream> {
+        self.generic_query(&query.clone().into_vector(), options)
+            .await
+    }
+
+    async fn merge_insert(
+        &self,
+        params: MergeInsertBuilder,
+        new_data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<MergeResult> {
+        let source_schema = new_data.schema();
+        merge::check_conditions(&params, source_schema.clone(), self.schema().await?).await?;
+        if params.when_matched_delete {
+            let source = new_data.collect::<std::result::Result<Vec<_>, _>>()?;
+            return self
+                .merge_insert_with_delete(params, source_schema, source)
+                .await;
+        }
+        let new_data = MaybeEmbedded::try_new(
+            new_data,
+            self.table_definition().await?,
+            Some(params.embedding_registry.clone()),
+        )?
+        .embed_ahead(self.commit_retry_config.max_replayable_size)
+        .await?;
+        let mut new_data = ReplayableData::new(Box::new(new_data), &self.commit_retry_config);
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            let dataset = Arc::new(self.dataset.get().await?.clone());
+            let mut builder = LanceMergeInsertBuilder::try_new(dataset.clone(), params.on.clone())?;
+            match (
+                params.when_matched_update_all,
+                &params.when_matched_update_all_filt,
+            ) {
+                (false, _) => builder.when_matched(WhenMatched::DoNothing),
+                (true, None) => builder.when_matched(WhenMatched::UpdateAll),
+                (true, Some(filt)) => builder.when_matched(WhenMatched::update_if(&dataset, filt)?),
+            };
+            if params.when_not_matched_insert_all {
+                builder.when_not_matched(lance::dataset::WhenNotMatched::InsertAll);
+            } else {
+                builder.when_not_matched(lance::dataset::WhenNotMatched::DoNothing);
+            }
+            if params.when_not_matched_by_source_delete {
+                let behavior = if let Some(filter) = &params.when_not_matched_by_source_delete_filt
+                {
+                    WhenNotMatchedBySource::delete_if(dataset.as_ref(), filter)?
+                } else {
+                    WhenNotMatchedBySource::Delete
+                };
+                builder.when_not_matched_by_source(behavior);
+            } else {
+                builder.when_not_matched_by_source(WhenNotMatchedBySource::Keep);
+            }
+            let job = builder.try_build()?;
+            match job.execute_reader(new_data.reader()?).await {
+                Ok((new_dataset, stats)) => {
+                    self.dataset.set_latest(new_dataset.as_ref().clone()).await;
+                    return Ok(MergeResult {
+                        num_inserted: Some(stats.num_inserted_rows),
+                        num_updated: Some(stats.num_updated_rows),
+                        num_deleted: Some(stats.num_deleted_rows),
+                        version: Some(new_dataset.version().version),
+                    });
+                }
+                Err(err) => retry.retry_write(err.into(), &new_data).await?,
+            }
+        }
+    }
+
+    /// Delete rows from the table
+    async fn delete(&self, predicate: &str) -> Result<Option<u64>> {
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            let mut dataset = self.dataset.get_mut().await?;
+            // Lance doesn't count the rows it deletes, but the version it commits only
+            // differs from this one by the delete
+            let num_rows = dataset.count_rows(None).await?;
+            let result = dataset.delete(predicate).await;
+            match result {
+                Ok(()) => return Ok(Some((num_rows - dataset.count_rows(None).await?) as u64)),
+                Err(err) => {
+                    drop(dataset);
+                    retry.retry(err.into()).await?
+                }
+            }
+        }
+    }
+
+    async fn delete_rows(&self, row_ids: &[u64]) -> Result<DeleteRowsStats> {
+        self.dataset.ensure_mutable().await?;
+        let mut row_ids = row_ids.to_vec();
+        row_ids.sort_unstable();
+        row_ids.dedup();
+
+        let mut retry = CommitRetry::new(&self.commit_retry_config, &self.dataset);
+        loop {
+            retry.begin().await?;
+            let dataset = self.dataset.get().await?.clone();
+            // A concurrent write may have deleted some of the rows, so check every try
+            let existing = take::existing_row_ids(&dataset, &row_ids).await?;
+            let stats = DeleteRowsStats {
+                num_deleted: existing.len() as u64,
+                num_missing: (row_ids.len() - existing.len()) as u64,
+            };
+            if existing.is_empty() {
+                return Ok(stats);
+            }
+            let (updated_fragments, removed_fragment_ids) =
+                take::delete_row_ids(&dataset, &existing).await?;
+            let operation = Operation::Update {
+                removed_fragment_ids,
+                updated_fragments,
+                new_fragments: Vec::new(),
+            };
+            let store_params = self
+                .patch_write_params(WriteParams::default())?
+                .store_params;
+            match Dataset::commit(
+                &self.uri,
+                operation,
+                Some(dataset.version().version),
+                store_params,
+                None,
+            )
+            .await
+            {
+                Ok(new_dataset) => {
+                    self.dataset.set_latest(new_dataset).await;
+                    return Ok(stats);
+                }
+                Err(err) => retry.retry(err.into()).await?,
+            }
+        }
+    }
+
+    async fn optimize(
+        &self,
+        action: OptimizeAction,
+        progress: Option<CompactionProgressCallback>,
+        _wait_timeout: Option<std::time::Duration>,
+    ) -> Result<OptimizeStats> {
+        let mut stats = OptimizeStats {
+            compaction: None,
+            compacted_fragments: None,
+            prune: None,
+        };
+        match action {
+            OptimizeAction::All => {
+                let compacted = self
+                    .optimize(
+                        OptimizeAction::Compact {
+                            options: CompactionOptions::default(),
+                            remap_options: None,
+                        },
+                        progress,
+                        None,
+                    )
+                    .await?;
+                stats.compaction = compacted.compaction;
+                stats.compacted_fragments = compacted.compacted_fragments;
+                stats.prune = self
+                    .optimize(
+                        OptimizeAction::Prune {
+                            older_than: None,
+                            delete_unverified: None,
+                            compaction: None,
+                        },
+                        None,
+                        None,
+                    )
+                    .await?
+                    .prune;
+                self.optimize(
+                    OptimizeAction::Index(OptimizeOptions::default()),
+                    None,
+                    None,
+                )
+                .await?;
+            }
+            OptimizeAction::Compact {
+                options,
+                remap_options,
+            } => {
+                let (metrics, fragments) =
+                    self.compact_files(options, remap_options, progress).await?;
+                stats.compaction = Some(metrics);
+                stats.compacted_fragments = Some(fragments);
+            }
+            OptimizeAction::Prune {
+                older_than,
+                delete_unverified,
+                compaction,
+            } => {
+                if let Some(options) = compaction {
+                    let (metrics, fragments) = self.compact_files(options, None, progress).await?;
+                    stats.compaction = Some(metrics);
+                    stats.compacted_fragments = Some(fragments);
+                }
+                stats.prune = Some(
+                    self.cleanup_old_versions(
+                        older_than.unwrap_or(Duration::try_days(7).expect("valid delta")),
+                        delete_unverified,
+                    )
+                    .await?,
+                );
+            }
+            OptimizeAction::Index(options) => {
+                self.optimize_indices(&options).await?;
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn add_columns(
+        &self,
+        transforms: NewColumnTransform,
+        read_columns: Option<Vec<String>>,
+    ) -> Result<()> {
+        self.dataset
+            .get_mut()
+            .await?
+            .add_columns(transforms, read_columns)
+            .await?;
+        Ok(())
+    }
+
+    async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()> {
+        self.dataset
+            .get_mut()
+            .await?
+            .alter_columns(alterations)
+            .await?;
+        Ok(())
+    }
+
+    async fn drop_columns(&self, columns: &[&str]) -> Result<()> {
+        self.dataset.get_mut().await?.drop_columns(columns).await?;
+        Ok(())
+    }
+
+    async fn index_distance_type(&self, column: &str) -> Result<Option<DistanceType>> {
+        let dataset = self.dataset.get().await?;
+        let Some(field) = dataset.schema().field(column) else {
+            return Ok(None);
+        };
+        let indices = dataset.load_indices().await?;
+        let Some(index) = indices.iter().find(|index| index.fields == [field.id]) else {
+            return Ok(None);
+        };
+        if let Some(distance_type) = self.index_distance_types.lock()?.get(&index.uuid) {
+            return Ok(*distance_type);
+        }
+        // The distance type is only recorded in the index files, which the statistics
+        // open.  Index files never change, so it is read once for each of them.
+        let stats = dataset.index_statistics(&index.name).await?;
+        let stats: serde_json::Value = whatever!(
+            serde_json::from_str(&stats),
+            "error deserializing index statistics {stats}",
+        );
+        let distance_type = Self::vector_index_params(&stats)?.distance_type;
+        self.index_distance_types
+            .lock()?
+            .insert(index.uuid, distance_type);
+        Ok(distance_type)
+    }
+
+    async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
+        let dataset = self.dataset.get().await?;
+        let indices = dataset.load_indices().await?;
+        let mut configs = Vec::with_capacity(indices.len());
+        for idx in indices.iter() {
+            let mut is_vector = false;
+            let mut columns = Vec::with_capacity(idx.fields.len());
+            for field_id in &idx.fields {
+                let field = dataset.schema().field_by_id(*field_id).ok_or_else(|| Error::Runtime { message: format!("The index with name {} and uuid {} referenced a field with id {} which does not exist in the schema", idx.name, idx.uuid, field_id) })?;
+                if field.data_type().is_nested() {
+                    // Temporary hack to determine if an index is scalar or vector
+                    // Should be removed in https://github.com/lancedb/lance/issues/2039
+                    is_vector = true;
+                }
+                columns.push(field.name.clone());
+            }
+
+            let (index_type, vector_params) = if is_vector {
+                let stats = dataset.index_statistics(&idx.name).await?;
+                let stats: serde_json::Value = whatever!(
+                    serde_json::from_str(&stats),
+                    "error deserializing index statistics {stats}",
+                );
+                (
+                    Self::vector_index_type(&stats),
+                    Some(Self::vector_index_params(&stats)?),
+                )
+            } else {
+                (crate::index::IndexType::BTree, None)
+            };
+
+            let name = idx.name.clone();
+            configs.push(IndexConfig {
+                index_type,
+                columns,
+                name,
+                vector_params,
+            });
+        }
+        for field in dataset.schema().fields.iter() {
+            if self.open_fts_index(&dataset, &field.name).await?.is_some() {
+                configs.push(IndexConfig {
+                    index_type: crate::index::IndexType::FTS,
+                    columns: vec![field.name.clone()],
+                    name: fts::index_name(&field.name),
+                    vector_params: None,
+                });
+            }
+        }
+        Ok(configs)
+    }
+
+    async fn index_stats(&self, index_name: &str) -> Result<Option<IndexStats>> {
+        let config = self
+            .list_indices()
+            .await?
+            .into_iter()
+            .find(|config| config.name == index_name);
+        let Some(config) = config else {
+            return Ok(None);
+        };
+        if config.index_type == crate::index::IndexType::FTS {
+            let dataset = self.dataset.get().await?;
+            let Some(index) = self.open_fts_index(&dataset, &config.columns[0]).await? else {
+                return Ok(None);
+            };
+            let (num_indexed_rows, num_unindexed_rows) = index.count_rows(&dataset).await?;
+            return Ok(Some(IndexStats {
+                num_indexed_rows,
+                num_unindexed_rows,
+                index_type: config.index_type,
+                distance_type: None,
+                num_partitions: None,
+            }));
+        }
+        let stats = self
+            .dataset
+            .get()
+            .await?
+            .index_statistics(index_name)
+            .await?;
+        let stats: IndexStatistics = whatever!(
+            serde_json::from_str(&stats),
+            "error deserializing index statistics {stats}",
+        );
+        let vector_params = config.vector_params.unwrap_or_default();
+        Ok(Some(IndexStats {
+            num_indexed_rows: stats.num_indexed_rows,
+            num_unindexed_rows: stats.num_unindexed_rows,
+            index_type: config.index_type,
+            distance_type: vector_params.distance_type,
+            num_partitions: vector_params.num_partitions,
+        }))
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::iter;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use arrow_array::types::{
+        Float64Type, Int32Type, Int64Type, TimestampMicrosecondType, UInt64Type,
+    };
+    use arrow_array::{
+        Array, BooleanArray, Date32Array, FixedSizeListArray, Float32Array, Float64Array,
+        Int32Array, Int64Array, LargeStringArray, ListArray, RecordBatch, RecordBatchIterator,
+        RecordBatchReader, StringArray, TimestampMillisecondArray, TimestampNanosecondArray,
         UInt32Array,
     };
     use arrow_data::ArrayDataBuilder;
@@ -1924,137 +4045,994 @@ mod tests {
     use rand::Rng;
     use tempfile::tempdir;
 
-    use crate::connect;
-    use crate::connection::ConnectBuilder;
-    use crate::index::scalar::BTreeIndexBuilder;
-    use crate::query::{ExecutableQuery, QueryBase};
+    use crate::connect;
+    use crate::connection::ConnectBuilder;
+    use crate::index::scalar::{BTreeIndexBuilder, FtsIndexBuilder};
+    use crate::query::{ExecutableQuery, FullTextSearchQuery, QueryBase};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+
+        let batches = make_test_batches();
+        Dataset::write(batches, dataset_path.to_str().unwrap(), None)
+            .await
+            .unwrap();
+
+        let table = NativeTable::open(dataset_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(table.name, "test")
+    }
+
+    #[tokio::test]
+    async fn test_open_not_found() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let table = NativeTable::open(uri).await;
+        assert!(matches!(table.unwrap_err(), Error::TableNotFound { .. }));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_object_store_path() {
+        use std::path::Path as StdPath;
+        let p = StdPath::new("s3://bucket/path/to/file");
+        let c = p.join("subfile");
+        assert_eq!(c.to_str().unwrap(), "s3://bucket/path/to/file/subfile");
+    }
+
+    #[tokio::test]
+    async fn test_count_rows() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let batches = make_test_batches();
+        let table = NativeTable::create(uri, "test", batches, None, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        assert_eq!(
+            table.count_rows(Some("i >= 5".to_string())).await.unwrap(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let batches = make_test_batches();
+        let schema = batches.schema().clone();
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        let new_batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(100..110))],
+            )
+            .unwrap()]
+            .into_iter()
+            .map(Ok),
+            schema.clone(),
+        );
+
+        table.add(new_batches).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+        assert_eq!(table.name(), "test");
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        // Create a dataset with i=0..10
+        let batches = merge_insert_test_batches(0, 0);
+        let table = conn
+            .create_table("my_table", batches)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        // Create new data with i=5..15
+        let new_batches = Box::new(merge_insert_test_batches(5, 1));
+
+        // Perform a "insert if not exists"
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_not_matched_insert_all();
+        let stats = merge_insert_builder.execute(new_batches).await.unwrap();
+        // Only 5 rows should actually be inserted
+        assert_eq!(
+            stats,
+            MergeResult {
+                num_inserted: Some(5),
+                num_updated: Some(0),
+                num_deleted: Some(0),
+                version: Some(table.version().await.unwrap()),
+            }
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 15);
 
-    use super::*;
+        // Create new data with i=15..25 (no id matches)
+        let new_batches = Box::new(merge_insert_test_batches(15, 2));
+        // Perform a "bulk update" (should not affect anything)
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_matched_update_all(None);
+        let stats = merge_insert_builder.execute(new_batches).await.unwrap();
+        // No new rows should have been inserted
+        assert_eq!(
+            (stats.num_inserted, stats.num_updated, stats.num_deleted),
+            (Some(0), Some(0), Some(0))
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        assert_eq!(
+            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
+            0
+        );
+
+        // Conditional update that only replaces the age=0 data
+        let new_batches = Box::new(merge_insert_test_batches(5, 3));
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_matched_update_all(Some("target.age = 0".to_string()));
+        merge_insert_builder.execute(new_batches).await.unwrap();
+        assert_eq!(
+            table.count_rows(Some("age = 3".to_string())).await.unwrap(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert_conditions() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", merge_insert_test_batches(0, 0))
+            .execute()
+            .await
+            .unwrap();
+
+        // Matched rows that don't satisfy the condition are neither updated nor inserted
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_update_all(Some("target.i >= 8".to_string()))
+            .when_not_matched_insert_all();
+        let stats = merge_insert_builder
+            .execute(Box::new(merge_insert_test_batches(5, 1)))
+            .await
+            .unwrap();
+        assert_eq!(stats.num_inserted, Some(5));
+        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        assert_eq!(
+            table.count_rows(Some("age = 0".to_string())).await.unwrap(),
+            8
+        );
+        assert_eq!(
+            table
+                .count_rows(Some("age = 1 AND i < 10".to_string()))
+                .await
+                .unwrap(),
+            2
+        );
+
+        // The delete takes precedence, the other matched rows are updated, and both
+        // are committed as one version
+        let version = table.version().await.unwrap();
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_delete(Some("source.i < 12".to_string()))
+            .when_matched_update_all(None);
+        let stats = merge_insert_builder
+            .execute(Box::new(merge_insert_test_batches(10, 2)))
+            .await
+            .unwrap();
+        assert_eq!(stats.num_deleted, Some(2));
+        assert_eq!(stats.num_updated, Some(3));
+        assert_eq!(stats.num_inserted, Some(0));
+        assert_eq!(stats.version, Some(version + 1));
+        assert_eq!(table.version().await.unwrap(), version + 1);
+        assert_eq!(table.count_rows(None).await.unwrap(), 13);
+        assert_eq!(
+            table
+                .count_rows(Some("i = 10 OR i = 11".to_string()))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
+            3
+        );
+
+        // An unconditional delete on its own
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_matched_delete(None);
+        let stats = merge_insert_builder
+            .execute(Box::new(merge_insert_test_batches(0, 3)))
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            MergeResult {
+                num_inserted: Some(0),
+                num_updated: Some(0),
+                num_deleted: Some(10),
+                version: Some(table.version().await.unwrap()),
+            }
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+
+        // Conditions with unknown columns fail before anything is written
+        let version = table.version().await.unwrap();
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_delete(Some("source.nope > 0".to_string()))
+            .when_not_matched_insert_all();
+        let err = merge_insert_builder
+            .execute(Box::new(merge_insert_test_batches(20, 4)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+        assert!(err.to_string().contains("when_matched_delete"), "{}", err);
+
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_update_all(Some("target.nope = 1".to_string()))
+            .when_not_matched_insert_all();
+        let err = merge_insert_builder
+            .execute(Box::new(merge_insert_test_batches(20, 4)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+        assert_eq!(table.version().await.unwrap(), version);
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+
+        // Adding data reports each batch
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batches = (0..3)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(i * 10..(i + 1) * 10))],
+                )
+            })
+            .collect::<Vec<_>>();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        table
+            .add(RecordBatchIterator::new(batches, schema))
+            .on_progress(Arc::new(move |progress: &WriteProgress| {
+                reports_clone.lock().unwrap().push(progress.clone());
+            }))
+            .execute()
+            .await
+            .unwrap();
+        let reports = reports.lock().unwrap().clone();
+        assert_eq!(
+            reports
+                .iter()
+                .map(|p| (p.batches, p.rows))
+                .collect::<Vec<_>>(),
+            vec![(1, 10), (2, 20), (3, 30)]
+        );
+        assert!(reports.windows(2).all(|w| w[0].bytes < w[1].bytes));
+
+        // Building an index reports the start and the end
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .on_progress(Arc::new(move |progress: &IndexProgress| {
+                reports_clone.lock().unwrap().push(progress.clone());
+            }))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![
+                IndexProgress {
+                    rows_indexed: 0,
+                    total_rows: 40
+                },
+                IndexProgress {
+                    rows_indexed: 40,
+                    total_rows: 40
+                }
+            ]
+        );
+
+        // Compaction reports each rewritten group of fragments
+        for _ in 0..3 {
+            table.add(make_test_batches()).execute().await.unwrap();
+        }
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        let stats = table
+            .optimize_with_progress(
+                OptimizeAction::Compact {
+                    options: CompactionOptions::default(),
+                    remap_options: None,
+                },
+                Arc::new(move |progress: &CompactionProgress| {
+                    reports_clone.lock().unwrap().push(progress.clone());
+                }),
+            )
+            .await
+            .unwrap();
+        let reports = reports.lock().unwrap().clone();
+        let total_fragments = stats.compaction.unwrap().fragments_removed;
+        assert_eq!(
+            stats.compacted_fragments.unwrap().removed.len(),
+            total_fragments
+        );
+        assert_eq!(reports.first().unwrap().fragments_compacted, 0);
+        assert_eq!(
+            reports.last().unwrap(),
+            &CompactionProgress {
+                fragments_compacted: total_fragments,
+                total_fragments,
+            }
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 70);
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let vector_type = |dim| {
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), dim)
+        };
+        let table_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("vector", vector_type(4), true),
+        ]));
+        let table = conn
+            .create_empty_table("test", table_schema)
+            .execute()
+            .await
+            .unwrap();
+
+        let batches = |dim: i32, ids: Vec<Option<i32>>| {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, true),
+                Field::new("vector", vector_type(dim), true),
+            ]));
+            let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                ids.iter().map(|_| Some(vec![Some(1.0); dim as usize])),
+                dim,
+            );
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(ids)), Arc::new(vectors)],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch.clone()), Ok(batch)], schema)
+        };
+
+        // Only the dimension of the vectors is wrong
+        let report = table
+            .validate(batches(3, vec![Some(1), Some(2)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            report.issues,
+            vec![ValidationIssue {
+                batch: None,
+                column: "vector".to_string(),
+                message: "has vectors of dimension 3 but the table expects 4".to_string(),
+            }]
+        );
+        assert_eq!((report.num_batches, report.num_rows), (2, 4));
+        assert!(!report.is_valid());
+
+        // Nulls in a column that isn't nullable are reported for each batch
+        let report = table
+            .validate(batches(4, vec![Some(1), None]))
+            .await
+            .unwrap();
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "batch 0: column 'id' has 1 null values but is not nullable",
+                "batch 1: column 'id' has 1 null values but is not nullable",
+            ]
+        );
+
+        let report = table
+            .validate(batches(4, vec![Some(1), Some(2)]))
+            .await
+            .unwrap();
+        assert!(report.is_valid());
+        assert_eq!(table.count_rows(None).await.unwrap(), 0);
+
+        // Appending reports the same problems
+        let err = table
+            .add(batches(3, vec![Some(1), Some(2)]))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("column 'vector' has vectors of dimension 3 but the table expects 4"),
+            "{}",
+            err
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_add_coerce() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table_schema = Arc::new(Schema::new(vec![
+            Field::new("narrow", DataType::Int32, true),
+            Field::new("wide", DataType::Int64, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                true,
+            ),
+        ]));
+        let table = conn
+            .create_empty_table("test", table_schema)
+            .execute()
+            .await
+            .unwrap();
+
+        let data = |narrow: i64, ts: &str| {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("narrow", DataType::Int64, true),
+                Field::new("wide", DataType::Int32, true),
+                Field::new("ts", DataType::Utf8, true),
+                Field::new(
+                    "vector",
+                    DataType::List(Arc::new(Field::new("item", DataType::Float64, true))),
+                    true,
+                ),
+            ]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(vec![narrow])),
+                    Arc::new(Int32Array::from(vec![i32::MIN])),
+                    Arc::new(StringArray::from(vec![ts])),
+                    Arc::new(ListArray::from_iter_primitive::<Float64Type, _, _>(vec![
+                        Some(vec![Some(0.5), Some(1.5)]),
+                    ])),
+                ],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema)
+        };
+
+        // Without coercion the types don't match
+        assert!(table
+            .add(data(1, "2024-01-02T03:04:05Z"))
+            .execute()
+            .await
+            .is_err());
+
+        table
+            .add(data(1, "2024-01-02T03:04:05Z"))
+            .coerce(true)
+            .execute()
+            .await
+            .unwrap();
+        let batch = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(batch.schema(), table.schema().await.unwrap());
+        assert_eq!(batch["narrow"].as_primitive::<Int32Type>().value(0), 1);
+        assert_eq!(
+            batch["wide"].as_primitive::<Int64Type>().value(0),
+            i32::MIN as i64
+        );
+        assert_eq!(
+            batch["ts"]
+                .as_primitive::<TimestampMicrosecondType>()
+                .value(0),
+            1_704_164_645_000_000
+        );
+
+        // A value that doesn't fit is reported unless it may be lost
+        let err = table
+            .add(data(1 << 40, "2024-01-02"))
+            .coerce(true)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("cannot cast column 'narrow'"),
+            "{}",
+            err
+        );
+        let err = table
+            .add(data(1, "not a timestamp"))
+            .coerce(true)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("cannot cast column 'ts'"),
+            "{}",
+            err
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 1);
+
+        table
+            .add(data(1 << 40, "not a timestamp"))
+            .coerce(true)
+            .allow_lossy(true)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            table
+                .count_rows(Some("narrow IS NULL AND ts IS NULL".to_string()))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_list_vectors() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let vector_type =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2);
+        let table_schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            vector_type.clone(),
+            true,
+        )]));
+        let table = conn
+            .create_empty_table("test", table_schema)
+            .execute()
+            .await
+            .unwrap();
+
+        let data = |vectors: Vec<Option<Vec<Option<f32>>>>| {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "vector",
+                DataType::List(Arc::new(Field::new("item", DataType::Float32, true))),
+                true,
+            )]));
+            let vectors = ListArray::from_iter_primitive::<Float32Type, _, _>(vectors);
+            let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors)]).unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema)
+        };
+
+        // Lists of floats become vectors without asking for coercion
+        table
+            .add(data(vec![
+                Some(vec![Some(1.0), Some(2.0)]),
+                None,
+                Some(vec![Some(3.0), Some(4.0)]),
+            ]))
+            .execute()
+            .await
+            .unwrap();
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches[0]["vector"].data_type(), &vector_type);
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+
+        let err = table
+            .add(data(vec![
+                Some(vec![Some(1.0), Some(2.0)]),
+                Some(vec![Some(3.0), Some(4.0), Some(5.0)]),
+            ]))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the vector of row 1 has dimension 3 but the table expects 2"),
+            "{}",
+            err
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+    }
 
     #[tokio::test]
-    async fn test_open() {
+    async fn test_background_optimize() {
         let tmp_dir = tempdir().unwrap();
-        let dataset_path = tmp_dir.path().join("test.lance");
-
-        let batches = make_test_batches();
-        Dataset::write(batches, dataset_path.to_str().unwrap(), None)
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
             .await
             .unwrap();
+        table.add(make_test_batches()).execute().await.unwrap();
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = runs.clone();
+        let interval = Duration::from_millis(20);
+        let handle = table.start_background_optimize(OptimizeSchedule {
+            action: OptimizeAction::Compact {
+                options: CompactionOptions::default(),
+                remap_options: None,
+            },
+            callback: Some(Arc::new(move |result: &Result<OptimizeStats>| {
+                assert!(result.is_ok());
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..OptimizeSchedule::new(interval)
+        });
+        let wait_for_runs = |expected: usize| {
+            let runs = runs.clone();
+            async move {
+                for _ in 0..500 {
+                    if runs.load(Ordering::SeqCst) >= expected {
+                        return;
+                    }
+                    tokio::time::sleep(interval / 4).await;
+                }
+                panic!("the table was not optimized {} times", expected);
+            }
+        };
 
-        let table = NativeTable::open(dataset_path.to_str().unwrap())
-            .await
-            .unwrap();
+        // The first run compacts the two fragments
+        wait_for_runs(1).await;
+        assert_eq!(
+            table.as_native().unwrap().count_fragments().await.unwrap(),
+            1
+        );
 
-        assert_eq!(table.name, "test")
+        // Nothing changed, so the following runs are skipped
+        tokio::time::sleep(interval * 10).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // A write is picked up by the next run
+        table.add(make_test_batches()).execute().await.unwrap();
+        wait_for_runs(2).await;
+
+        // After stopping there are no more runs
+        handle.stop().await;
+        table.add(make_test_batches()).execute().await.unwrap();
+        tokio::time::sleep(interval * 10).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(table.count_rows(None).await.unwrap(), 40);
     }
 
     #[tokio::test]
-    async fn test_open_not_found() {
+    async fn test_compaction_targets() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
-        let table = NativeTable::open(uri).await;
-        assert!(matches!(table.unwrap_err(), Error::TableNotFound { .. }));
-    }
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        for _ in 0..9 {
+            table.add(make_test_batches()).execute().await.unwrap();
+        }
+        let native = table.as_native().unwrap();
+        let old_ids = native
+            .fragments()
+            .await
+            .unwrap()
+            .iter()
+            .map(|fragment| fragment.id)
+            .collect::<Vec<_>>();
+        assert_eq!(old_ids.len(), 10);
 
-    #[test]
-    #[cfg(not(windows))]
-    fn test_object_store_path() {
-        use std::path::Path as StdPath;
-        let p = StdPath::new("s3://bucket/path/to/file");
-        let c = p.join("subfile");
-        assert_eq!(c.to_str().unwrap(), "s3://bucket/path/to/file/subfile");
+        let options = CompactionOptions {
+            target_rows_per_fragment: 25,
+            max_rows_per_group: 5,
+            num_threads: 1,
+            ..Default::default()
+        };
+        let compact = || OptimizeAction::Compact {
+            options: options.clone(),
+            remap_options: None,
+        };
+        let stats = table.optimize(compact()).await.unwrap();
+        let fragments = native.fragments().await.unwrap();
+        assert_eq!(
+            fragments
+                .iter()
+                .map(|fragment| fragment.num_rows)
+                .collect::<Vec<_>>(),
+            vec![25; 4]
+        );
+        let compacted = stats.compacted_fragments.unwrap();
+        assert_eq!(compacted.removed, old_ids);
+        assert_eq!(
+            compacted.added,
+            fragments
+                .iter()
+                .map(|fragment| fragment.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(stats.compaction.unwrap().fragments_added, 4);
+
+        // The fragments already have the target size
+        let version = table.version().await.unwrap();
+        let stats = table.optimize(compact()).await.unwrap();
+        assert!(stats.compacted_fragments.unwrap().is_noop());
+        assert_eq!(stats.compaction.unwrap().fragments_removed, 0);
+        assert_eq!(table.version().await.unwrap(), version);
+
+        // Fragments with enough deleted rows are rewritten without them
+        table.delete("i < 5").await.unwrap();
+        let stats = table
+            .optimize(OptimizeAction::Compact {
+                options: CompactionOptions {
+                    materialize_deletions_threshold: 0.1,
+                    ..options.clone()
+                },
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(stats.compacted_fragments.unwrap().removed.len(), 4);
+        let fragments = native.fragments().await.unwrap();
+        assert!(fragments.iter().all(|fragment| fragment.num_deleted == 0));
+        assert_eq!(
+            fragments
+                .iter()
+                .map(|fragment| fragment.num_rows)
+                .collect::<Vec<_>>(),
+            vec![25; 2]
+        );
     }
 
     #[tokio::test]
-    async fn test_count_rows() {
+    async fn test_count_rows_approx() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
 
-        let batches = make_test_batches();
-        let table = NativeTable::create(uri, "test", batches, None, None, None)
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batches = (0..5)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        i * 10_000..(i + 1) * 10_000,
+                    ))],
+                )
+            })
+            .collect::<Vec<_>>();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(batches, schema))
+            .write_options(WriteOptions {
+                lance_write_params: Some(WriteParams {
+                    max_rows_per_file: 10_000,
+                    ..Default::default()
+                }),
+            })
+            .execute()
             .await
             .unwrap();
+        table.delete("i >= 45000").await.unwrap();
 
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        // Without a filter the count is exact, deletions included
         assert_eq!(
-            table.count_rows(Some("i >= 5".to_string())).await.unwrap(),
-            5
+            table.count_rows_approx(None).await.unwrap(),
+            ApproxCount::exact(45_000)
+        );
+
+        // A filter is estimated from a sample
+        let count = table
+            .count_rows_approx(Some("i < 9000".to_string()))
+            .await
+            .unwrap();
+        assert!(!count.exact);
+        assert!(count.lower <= count.estimate && count.estimate <= count.upper);
+        assert!((7_000..=11_000).contains(&count.estimate), "{:?}", count);
+
+        // Small tables are counted exactly
+        let table = conn
+            .create_table("small", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            table
+                .count_rows_approx(Some("i >= 5".to_string()))
+                .await
+                .unwrap(),
+            ApproxCount::exact(5)
         );
     }
 
     #[tokio::test]
-    async fn test_add() {
+    async fn test_take() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
 
-        let batches = make_test_batches();
-        let schema = batches.schema().clone();
-        let table = conn.create_table("test", batches).execute().await.unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        let results = table
+            .query()
+            .with_row_id()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut row_ids = HashMap::new();
+        for batch in results {
+            let values = batch["i"].as_primitive::<Int32Type>();
+            let ids = batch["_rowid"].as_primitive::<UInt64Type>();
+            for (value, id) in values.values().iter().zip(ids.values().iter()) {
+                row_ids.insert(*value, *id);
+            }
+        }
+        let values = |batch: &RecordBatch| {
+            batch["i"]
+                .as_primitive::<Int32Type>()
+                .iter()
+                .collect::<Vec<_>>()
+        };
 
-        let new_batches = RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
-                schema.clone(),
-                vec![Arc::new(Int32Array::from_iter_values(100..110))],
-            )
-            .unwrap()]
-            .into_iter()
-            .map(Ok),
-            schema.clone(),
-        );
+        // The rows are returned in the order of the ids
+        let ids = [row_ids[&7], row_ids[&2], row_ids[&7]];
+        let rows = table.take(&ids, None, MissingRows::Error).await.unwrap();
+        assert_eq!(values(&rows), vec![Some(7), Some(2), Some(7)]);
+        let rows = table
+            .take(&ids, Some(&["i"]), MissingRows::Error)
+            .await
+            .unwrap();
+        assert_eq!(rows.num_columns(), 1);
 
-        table.add(new_batches).execute().await.unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 20);
-        assert_eq!(table.name(), "test");
+        // Deleted rows are an error, or null
+        assert_eq!(table.delete("i = 2").await.unwrap(), 1);
+        assert_eq!(table.delete("i = 2").await.unwrap(), 0);
+        let err = table
+            .take(&ids, None, MissingRows::Error)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        let rows = table.take(&ids, None, MissingRows::Null).await.unwrap();
+        assert_eq!(values(&rows), vec![Some(7), None, Some(7)]);
+        let rows = table
+            .take(&[u64::MAX], None, MissingRows::Null)
+            .await
+            .unwrap();
+        assert_eq!(values(&rows), vec![None]);
+
+        // Taking by key uses the scalar index, if there is one
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+        let keys = Int64Array::from(vec![9, 3, 42]);
+        let rows = table
+            .take_by_key("i", &keys, Some(&["i"]), MissingRows::Null)
+            .await
+            .unwrap();
+        assert_eq!(values(&rows), vec![Some(9), Some(3), None]);
+        let err = table
+            .take_by_key("i", &keys, None, MissingRows::Error)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
     }
 
     #[tokio::test]
-    async fn test_merge_insert() {
+    async fn test_delete_rows() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
-
-        // Create a dataset with i=0..10
-        let batches = merge_insert_test_batches(0, 0);
         let table = conn
-            .create_table("my_table", batches)
+            .create_table("test", make_test_batches())
             .execute()
             .await
             .unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
-
-        // Create new data with i=5..15
-        let new_batches = Box::new(merge_insert_test_batches(5, 1));
 
-        // Perform a "insert if not exists"
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_not_matched_insert_all();
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        // Only 5 rows should actually be inserted
-        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        let results = table
+            .query()
+            .with_row_id()
+            .only_if("i IN (1, 3)")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut row_ids = results
+            .iter()
+            .flat_map(|batch| {
+                batch["_rowid"]
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(row_ids.len(), 2);
+        row_ids.push(row_ids[0]);
+        row_ids.push(u64::MAX);
 
-        // Create new data with i=15..25 (no id matches)
-        let new_batches = Box::new(merge_insert_test_batches(15, 2));
-        // Perform a "bulk update" (should not affect anything)
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_matched_update_all(None);
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        // No new rows should have been inserted
-        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        // The deletion files of the rows are committed as one version
+        let version = table.version().await.unwrap();
+        let stats = table.delete_rows(&row_ids).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), version + 1);
         assert_eq!(
-            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
+            stats,
+            DeleteRowsStats {
+                num_deleted: 2,
+                num_missing: 1,
+            }
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 8);
+        assert_eq!(
+            table
+                .count_rows(Some("i IN (1, 3)".to_string()))
+                .await
+                .unwrap(),
             0
         );
 
-        // Conditional update that only replaces the age=0 data
-        let new_batches = Box::new(merge_insert_test_batches(5, 3));
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_matched_update_all(Some("target.age = 0".to_string()));
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        assert_eq!(
-            table.count_rows(Some("age = 3".to_string())).await.unwrap(),
-            5
+        // Deleting the rows again deletes nothing, and doesn't create a version
+        let version = table.version().await.unwrap();
+        let stats = table.delete_rows(&row_ids).await.unwrap();
+        assert_eq!(stats.num_deleted, 0);
+        assert_eq!(stats.num_missing, 3);
+        assert_eq!(table.version().await.unwrap(), version);
+
+        table.checkout(1).await.unwrap();
+        let err = table.delete_rows(&row_ids).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("checked out")),
+            "{:?}",
+            err
         );
     }
 
@@ -2149,13 +5127,24 @@ mod tests {
             .await
             .unwrap();
 
-        table
+        let num_updated = table
             .update()
             .only_if("id > 5")
             .column("name", "'foo'")
             .execute()
             .await
             .unwrap();
+        assert_eq!(num_updated, Some(4));
+
+        // A predicate that matches nothing updates nothing
+        let num_updated = table
+            .update()
+            .only_if("id > 100")
+            .column("name", "'foo'")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(num_updated, Some(0));
 
         let mut batches = table
             .query()
@@ -2409,6 +5398,146 @@ mod tests {
         assert_eq!(1, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
         tbl.update().column("i", "i+1").execute().await.unwrap();
         assert_eq!(0, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
+
+        // Filters can refer to the row ids
+        let results = tbl
+            .query()
+            .only_if("i == 5")
+            .with_row_id()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let row_id = results[0]["_rowid"].as_primitive::<UInt64Type>().value(0);
+        tbl.update()
+            .only_if(format!("_rowid = {}", row_id))
+            .column("i", "100")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            1,
+            tbl.count_rows(Some("i == 100".to_string())).await.unwrap()
+        );
+
+        // but not to columns that don't exist
+        let err = tbl
+            .update()
+            .only_if("j = 1")
+            .column("i", "1")
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_update_returning() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("views", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(Int32Array::from_iter_values((0..10).map(|i| i * 10))),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "my_table",
+                RecordBatchIterator::new(vec![Ok(batch)], schema),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let views = |rows: &RecordBatch| {
+            let mut views = rows["views"].as_primitive::<Int32Type>().values().to_vec();
+            views.sort();
+            views
+        };
+
+        // The values before the update
+        let result = table
+            .update()
+            .column("views", "views + 1")
+            .only_if("id = 7")
+            .returning_old(&["id", "views"])
+            .execute_returning()
+            .await
+            .unwrap();
+        assert_eq!(result.num_updated, Some(1));
+        assert_eq!(result.rows["id"].as_primitive::<Int32Type>().values(), &[7]);
+        assert_eq!(views(&result.rows), vec![70]);
+
+        // The values after the update, of the rows that were updated
+        let result = table
+            .update()
+            .column("views", "views + 1")
+            .only_if("id >= 7")
+            .returning(&["views"])
+            .execute_returning()
+            .await
+            .unwrap();
+        assert_eq!(result.num_updated, Some(3));
+        assert_eq!(result.rows.num_columns(), 1);
+        assert_eq!(views(&result.rows), vec![72, 81, 91]);
+
+        let result = table
+            .update()
+            .column("views", "views + 1")
+            .only_if("id > 100")
+            .returning(&["views"])
+            .execute_returning()
+            .await
+            .unwrap();
+        assert_eq!(result.num_updated, Some(0));
+        assert_eq!(result.rows.num_rows(), 0);
+
+        // Returning values needs execute_returning()
+        let err = table
+            .update()
+            .column("views", "0")
+            .returning(&["views"])
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+
+        // Unknown columns are rejected before anything is written
+        let version = table.version().await.unwrap();
+        for update in [
+            table.update().column("views", "nope + 1"),
+            table.update().column("nope", "1"),
+            table.update().column("views", "0").only_if("nope = 1"),
+        ] {
+            let err = update.execute().await.unwrap_err();
+            assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+        }
+        let err = table
+            .update()
+            .column("views", "0")
+            .returning_old(&["nope"])
+            .execute_returning()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+        assert_eq!(table.version().await.unwrap(), version);
+        assert_eq!(
+            table
+                .count_rows(Some("views = 0".to_string()))
+                .await
+                .unwrap(),
+            1
+        );
     }
 
     #[derive(Default, Debug)]
@@ -2566,6 +5695,36 @@ mod tests {
         let index = index_configs.into_iter().next().unwrap();
         assert_eq!(index.index_type, crate::index::IndexType::IvfPq);
         assert_eq!(index.columns, vec!["embeddings".to_string()]);
+        let params = index.vector_params.unwrap();
+        assert_eq!(params.distance_type, Some(DistanceType::L2));
+        assert_eq!(params.num_partitions, Some(22));
+        assert_eq!(params.num_sub_vectors, Some(1));
+
+        let stats = table.index_stats("embeddings_idx").await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                num_indexed_rows: 512,
+                num_unindexed_rows: 0,
+                index_type: crate::index::IndexType::IvfPq,
+                distance_type: Some(DistanceType::L2),
+                num_partitions: Some(22),
+            }
+        );
+        // ef is only used by HNSW indices, other indices ignore it
+        let results = table
+            .query()
+            .nearest_to(&[0.5; 16])
+            .unwrap()
+            .ef(64)
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
         assert_eq!(table.count_rows(None).await.unwrap(), 512);
         assert_eq!(table.name(), "test");
 
@@ -2678,8 +5837,28 @@ mod tests {
         let index_configs = table.list_indices().await.unwrap();
         assert_eq!(index_configs.len(), 1);
         let index = index_configs.into_iter().next().unwrap();
-        assert_eq!(index.index_type, crate::index::IndexType::IvfPq);
+        assert_eq!(index.index_type, crate::index::IndexType::IvfHnswSq);
         assert_eq!(index.columns, vec!["embeddings".to_string()]);
+        let params = index.vector_params.unwrap();
+        assert_eq!(params.distance_type, Some(DistanceType::L2));
+        assert_eq!(params.num_partitions, Some(22));
+        assert_eq!(params.num_sub_vectors, None);
+        assert_eq!(params.m, Some(20));
+        assert_eq!(params.ef_construction, Some(300));
+
+        let results = table
+            .query()
+            .nearest_to(&[0.5; 16])
+            .unwrap()
+            .ef(64)
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
         assert_eq!(table.count_rows(None).await.unwrap(), 512);
         assert_eq!(table.name(), "test");
 
@@ -2774,8 +5953,13 @@ mod tests {
         let index_configs = table.list_indices().await.unwrap();
         assert_eq!(index_configs.len(), 1);
         let index = index_configs.into_iter().next().unwrap();
-        assert_eq!(index.index_type, crate::index::IndexType::IvfPq);
+        assert_eq!(index.index_type, crate::index::IndexType::IvfHnswPq);
         assert_eq!(index.columns, vec!["embeddings".to_string()]);
+        let params = index.vector_params.unwrap();
+        assert_eq!(params.distance_type, Some(DistanceType::L2));
+        assert_eq!(params.num_partitions, Some(22));
+        assert_eq!(params.m, Some(20));
+        assert_eq!(params.ef_construction, Some(300));
         assert_eq!(table.count_rows(None).await.unwrap(), 512);
         assert_eq!(table.name(), "test");
 
@@ -2815,6 +5999,74 @@ mod tests {
         Ok(FixedSizeListArray::from(data))
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_appends() {
+        const APPENDS: i32 = 20;
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let conn = connect(uri).execute().await.unwrap();
+        conn.create_empty_table("test", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+
+        // Each task writes through its own connection, as separate processes would
+        let retry_config = CommitRetryConfig {
+            max_retries: 50,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let tasks = (0..2)
+            .map(|task| {
+                let uri = uri.to_string();
+                let schema = schema.clone();
+                let retry_config = retry_config.clone();
+                tokio::spawn(async move {
+                    let conn = ConnectBuilder::new(&uri)
+                        .commit_retry_config(retry_config)
+                        .execute()
+                        .await
+                        .unwrap();
+                    let table = conn.open_table("test").execute().await.unwrap();
+                    for i in 0..APPENDS {
+                        let batch = RecordBatch::try_new(
+                            schema.clone(),
+                            vec![Arc::new(Int32Array::from(vec![task * APPENDS + i]))],
+                        )
+                        .unwrap();
+                        let data = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+                        table.add(data).execute().await.unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let table = conn.open_table("test").execute().await.unwrap();
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut values = batches
+            .iter()
+            .flat_map(|batch| {
+                let column = batch.column_by_name("i").unwrap();
+                let column = column.as_any().downcast_ref::<Int32Array>().unwrap();
+                column.values().to_vec()
+            })
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (0..2 * APPENDS).collect::<Vec<_>>());
+    }
+
     fn some_sample_data() -> Box<dyn RecordBatchReader + Send> {
         let batch = RecordBatch::try_new(
             Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)])),
@@ -2827,6 +6079,198 @@ mod tests {
         Box::new(RecordBatchIterator::new(vec![batch], schema))
     }
 
+    #[tokio::test]
+    async fn test_create_fts_index() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("i", DataType::Int32, false),
+                Field::new("text", DataType::Utf8, false),
+            ])),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec![
+                    "pump failure",
+                    "valve stuck",
+                    "the pump is fine",
+                ])),
+            ],
+        )
+        .unwrap();
+        let schema = batch.schema();
+        let conn = ConnectBuilder::new(uri).execute().await.unwrap();
+        let table = conn
+            .create_table(
+                "my_table",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let err = table
+            .create_index(&["i"], Index::FTS(FtsIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        let err = table
+            .create_index(&["text"], Index::FTS(FtsIndexBuilder::default().stem(true)))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotSupported { .. }), "{:?}", err);
+
+        table
+            .create_index(&["text"], Index::FTS(FtsIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+        let indices = table.list_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].index_type, crate::index::IndexType::FTS);
+        assert_eq!(indices[0].columns, vec!["text".to_string()]);
+        assert_eq!(indices[0].name, "text_fts_idx");
+        let err = table
+            .create_index(&["text"], Index::FTS(FtsIndexBuilder::default()))
+            .replace(false)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+
+        let search = |query: &'static str| {
+            let table = table.clone();
+            async move {
+                let batches = table
+                    .query()
+                    .full_text_search(FullTextSearchQuery::new(query))
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let mut ids = batches
+                    .iter()
+                    .flat_map(|batch| batch["i"].as_primitive::<Int32Type>().values().to_vec())
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            }
+        };
+        assert_eq!(search("pump").await, vec![1, 3]);
+        assert_eq!(search("\"pump failure\"").await, vec![1]);
+
+        // Rows added since the index was built are searched without it
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![4])),
+                Arc::new(StringArray::from(vec!["pump leak"])),
+            ],
+        )
+        .unwrap();
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+        table.delete("i = 3").await.unwrap();
+        assert_eq!(search("pump").await, vec![1, 4]);
+        let stats = table.index_stats("text_fts_idx").await.unwrap().unwrap();
+        assert_eq!(stats.num_indexed_rows, 2);
+        assert_eq!(stats.num_unindexed_rows, 1);
+
+        table
+            .optimize(OptimizeAction::Index(OptimizeOptions::default()))
+            .await
+            .unwrap();
+        let stats = table.index_stats("text_fts_idx").await.unwrap().unwrap();
+        assert_eq!(stats.num_indexed_rows, 3);
+        assert_eq!(stats.num_unindexed_rows, 0);
+        assert_eq!(search("pump").await, vec![1, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_index_stats() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let make_batch = |range: std::ops::Range<i32>| {
+            RecordBatch::try_new(
+                Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)])),
+                vec![Arc::new(Int32Array::from_iter_values(range))],
+            )
+            .unwrap()
+        };
+        let batch = make_batch(0..100);
+        let conn = ConnectBuilder::new(uri).execute().await.unwrap();
+        let table = conn
+            .create_table(
+                "my_table",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.index_stats("i_idx").await.unwrap(), None);
+
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+        let stats = table.index_stats("i_idx").await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                num_indexed_rows: 100,
+                num_unindexed_rows: 0,
+                index_type: crate::index::IndexType::BTree,
+                distance_type: None,
+                num_partitions: None,
+            }
+        );
+
+        // New rows are not indexed until the index is optimized
+        let batch = make_batch(100..150);
+        table
+            .add(RecordBatchIterator::new(
+                vec![Ok(batch.clone())],
+                batch.schema(),
+            ))
+            .execute()
+            .await
+            .unwrap();
+        let stats = table.index_stats("i_idx").await.unwrap().unwrap();
+        assert_eq!(stats.num_indexed_rows, 100);
+        assert_eq!(stats.num_unindexed_rows, 50);
+        let err = table
+            .wait_for_index(&["i_idx"], Duration::from_millis(150))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Timeout { message, .. } if message.contains("50 rows not yet indexed")),
+            "{:?}",
+            err
+        );
+
+        table
+            .optimize(OptimizeAction::Index(OptimizeOptions::default()))
+            .await
+            .unwrap();
+        let stats = table.index_stats("i_idx").await.unwrap().unwrap();
+        assert_eq!(stats.num_indexed_rows, 150);
+        assert_eq!(stats.num_unindexed_rows, 0);
+        table
+            .wait_for_index(&["i_idx"], Duration::from_secs(10))
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_create_scalar_index() {
         let tmp_dir = tempdir().unwrap();
@@ -2948,6 +6392,57 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_read_consistency_interval_open_table() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn1 = ConnectBuilder::new(uri).execute().await.unwrap();
+        let table1 = conn1
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+
+        // The setting of the table overrides the setting of the connection
+        let conn2 = ConnectBuilder::new(uri)
+            .read_consistency_interval(Duration::from_secs(3600))
+            .execute()
+            .await
+            .unwrap();
+        let inherited = conn2.open_table("my_table").execute().await.unwrap();
+        let never = conn2
+            .open_table("my_table")
+            .read_consistency_interval(None)
+            .execute()
+            .await
+            .unwrap();
+        let always = conn2
+            .open_table("my_table")
+            .read_consistency_interval(Duration::ZERO)
+            .execute()
+            .await
+            .unwrap();
+
+        let stream = always.query().execute().await.unwrap();
+        table1.add(some_sample_data()).execute().await.unwrap();
+        assert_eq!(inherited.count_rows(None).await.unwrap(), 1);
+        assert_eq!(never.count_rows(None).await.unwrap(), 1);
+        assert_eq!(always.count_rows(None).await.unwrap(), 2);
+        // A query that already started keeps reading the version it started with
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+
+        // Newer versions are found after the loaded version was cleaned up
+        table1.add(some_sample_data()).execute().await.unwrap();
+        table1.add(some_sample_data()).execute().await.unwrap();
+        table1
+            .cleanup_old_versions(chrono::Duration::zero(), false)
+            .await
+            .unwrap();
+        assert_eq!(always.count_rows(None).await.unwrap(), 4);
+        assert_eq!(never.count_rows(None).await.unwrap(), 1);
+    }
+
     #[tokio::test]
     async fn test_time_travel_write() {
         let tmp_dir = tempdir().unwrap();
@@ -2968,4 +6463,221 @@ mod tests {
         table.checkout(version).await.unwrap();
         assert!(table.add(some_sample_data()).execute().await.is_err())
     }
+
+    #[tokio::test]
+    async fn test_cleanup_old_versions() {
+        fn disk_usage(path: &std::path::Path) -> u64 {
+            walkdir::WalkDir::new(path)
+                .into_iter()
+                .map(|entry| entry.unwrap().metadata().unwrap().len())
+                .sum()
+        }
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+        for _ in 0..10 {
+            table
+                .add(some_sample_data())
+                .mode(AddDataMode::Overwrite)
+                .execute()
+                .await
+                .unwrap();
+        }
+        assert_eq!(table.version().await.unwrap(), 11);
+
+        table.checkout(10).await.unwrap();
+        let err = table
+            .cleanup_old_versions(chrono::Duration::zero(), true)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("checked out")),
+            "{:?}",
+            err
+        );
+        table.checkout_latest().await.unwrap();
+
+        let before = disk_usage(tmp_dir.path());
+        let stats = table
+            .cleanup_old_versions(chrono::Duration::zero(), true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.old_versions, 10);
+        assert!(stats.bytes_removed > 0);
+        assert!(disk_usage(tmp_dir.path()) < before);
+        assert_eq!(table.list_versions().await.unwrap().len(), 1);
+        assert_eq!(table.count_rows(None).await.unwrap(), 1);
+
+        // Compact and prune in one call
+        for _ in 0..3 {
+            table.add(some_sample_data()).execute().await.unwrap();
+        }
+        let stats = table
+            .optimize(OptimizeAction::Prune {
+                older_than: Some(chrono::Duration::zero()),
+                delete_unverified: Some(true),
+                compaction: Some(CompactionOptions::default()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(stats.compaction.unwrap().fragments_removed, 4);
+        assert!(stats.prune.unwrap().old_versions > 0);
+        assert_eq!(table.count_rows(None).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let data = |range: std::ops::Range<i32>| {
+            let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(range))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema)
+        };
+        let table = conn
+            .create_table("my_table", data(0..10))
+            .execute()
+            .await
+            .unwrap();
+        table.add(data(10..15)).execute().await.unwrap();
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+        table.add(data(15..17)).execute().await.unwrap();
+        table.delete("i < 3").await.unwrap();
+
+        let stats = table.stats().await.unwrap();
+        let latest = table.list_versions().await.unwrap().pop().unwrap();
+        assert_eq!(stats.version, latest.version);
+        assert_eq!(stats.last_write, latest.timestamp);
+        assert_eq!(stats.num_rows, 14);
+        assert_eq!(stats.num_deleted_rows, 3);
+        assert!(stats.total_bytes > 0);
+        assert_eq!(
+            stats.fragment_stats,
+            FragmentStatistics {
+                num_fragments: 3,
+                num_small_fragments: 3,
+                lengths: FragmentSummaryStats {
+                    min: 2,
+                    max: 7,
+                    mean: 4,
+                    p25: 2,
+                    p50: 5,
+                    p75: 7,
+                    p99: 7,
+                },
+            }
+        );
+        let index = table.index_stats("i_idx").await.unwrap().unwrap();
+        assert_eq!(index.num_unindexed_rows, 2);
+        assert_eq!(
+            stats.index_coverage,
+            vec![IndexCoverage {
+                name: "i_idx".to_string(),
+                columns: vec!["i".to_string()],
+                num_indexed_rows: index.num_indexed_rows,
+                num_unindexed_rows: index.num_unindexed_rows,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tags() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+
+        let tags = table.tags();
+        assert!(tags.list().await.unwrap().is_empty());
+        tags.create("prod", 1).await.unwrap();
+        let err = tags.create("prod", 2).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("already exists")),
+            "{:?}",
+            err
+        );
+        tags.update("prod", 2).await.unwrap();
+        tags.create("first", 1).await.unwrap();
+        assert_eq!(
+            tags.list().await.unwrap(),
+            HashMap::from([("prod".to_string(), 2), ("first".to_string(), 1)])
+        );
+
+        table.checkout_tag("prod").await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        let err = table.checkout_tag("missing").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        table.checkout_latest().await.unwrap();
+
+        // Deleting a tag keeps its version
+        tags.delete("prod").await.unwrap();
+        assert!(tags.delete("prod").await.is_err());
+        assert_eq!(tags.list().await.unwrap().len(), 1);
+        table.checkout(2).await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_at() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            table.add(some_sample_data()).execute().await.unwrap();
+        }
+
+        let versions = table.list_versions().await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(versions.windows(2).all(|v| v[0].timestamp < v[1].timestamp));
+
+        // A version is in effect from the moment it was created
+        table.checkout_at(versions[1].timestamp).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+        let just_before = versions[1].timestamp - chrono::Duration::nanoseconds(1);
+        table.checkout_at(just_before).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 1);
+        table.checkout_at(Utc::now()).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 3);
+
+        let before_creation = versions[0].timestamp - chrono::Duration::nanoseconds(1_000_000);
+        let err = table.checkout_at(before_creation).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("earliest version")),
+            "{:?}",
+            err
+        );
+        assert_eq!(table.version().await.unwrap(), 3);
+    }
 }
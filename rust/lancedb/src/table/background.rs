@@ -0,0 +1,113 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Optimizing a table periodically in the background
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{select, Either};
+use rand::Rng;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use super::{OptimizeAction, OptimizeStats, Table};
+use crate::Result;
+
+pub type OptimizeCallback = Arc<dyn Fn(&Result<OptimizeStats>) + Send + Sync>;
+
+/// When and how to optimize a table, see [`Table::start_background_optimize`]
+#[derive(Clone)]
+pub struct OptimizeSchedule {
+    /// The time from the end of one run to the start of the next
+    pub interval: Duration,
+    /// The optimization to run
+    pub action: OptimizeAction,
+    /// A random duration of up to this much is added to each interval, so that
+    /// tables that are scheduled together don't all run at once
+    pub jitter: Duration,
+    /// Called with the outcome of each run
+    ///
+    /// Errors don't stop the schedule, the next run is tried after the next interval.
+    pub callback: Option<OptimizeCallback>,
+}
+
+impl OptimizeSchedule {
+    /// Run [`OptimizeAction::All`] every `interval`, without jitter
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            action: OptimizeAction::All,
+            jitter: Duration::ZERO,
+            callback: None,
+        }
+    }
+}
+
+/// A handle to the optimizations started by [`Table::start_background_optimize`]
+///
+/// The optimizations stop when this is dropped.  A run that is in progress is not
+/// interrupted, use [`Self::stop`] to wait for it to finish.
+pub struct BackgroundOptimize {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl BackgroundOptimize {
+    /// Stop the optimizations and wait for a run that is in progress, if any
+    pub async fn stop(self) {
+        drop(self.stop);
+        // The task only panics if the callback does
+        if let Err(err) = self.task.await {
+            if err.is_panic() {
+                std::panic::resume_unwind(err.into_panic());
+            }
+        }
+    }
+}
+
+pub(crate) fn start(table: Table, schedule: OptimizeSchedule) -> BackgroundOptimize {
+    let (stop, mut stopped) = oneshot::channel::<()>();
+    let task = tokio::spawn(async move {
+        let mut optimized_version = None;
+        loop {
+            let mut delay = schedule.interval;
+            if !schedule.jitter.is_zero() {
+                delay += rand::thread_rng().gen_range(Duration::ZERO..=schedule.jitter);
+            }
+            let sleep = Box::pin(tokio::time::sleep(delay));
+            // The sender is never used, the channel closes when the handle is dropped
+            if let Either::Left(_) = select(&mut stopped, sleep).await {
+                return;
+            }
+
+            // Runs are sequential, so a slow run delays the next one rather than
+            // overlapping it
+            let result = match table.version().await {
+                // Nothing was written since the last run, so there is nothing to do
+                Ok(version) if Some(version) == optimized_version => continue,
+                Ok(_) => table.optimize(schedule.action.clone()).await,
+                Err(err) => Err(err),
+            };
+            if result.is_ok() {
+                // The optimization itself may have created versions
+                optimized_version = table.version().await.ok();
+            }
+            if let Some(callback) = &schedule.callback {
+                callback(&result);
+            }
+        }
+    });
+    BackgroundOptimize { stop, task }
+}
@@ -0,0 +1,398 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Casting the columns of added data to the types of the table, see
+//! [`super::AddDataBuilder::coerce`]
+//!
+//! Lists are always converted to the vectors of the table, even if the data isn't
+//! coerced, because vectors are so often built as lists.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array, ArrayRef, RecordBatch, RecordBatchReader};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_cast::{can_cast_types, cast_with_options, CastOptions};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use crate::error::{Error, Result};
+
+/// Which columns a [`CoercingReader`] casts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Coercion {
+    /// Only lists that become vectors of the same item type
+    Vectors,
+    /// Every column that [`can_coerce`] allows
+    All { allow_lossy: bool },
+}
+
+/// Casts the columns of each batch to the types of the columns of the table
+///
+/// Columns that aren't in the table, or whose types can't be cast, are passed on
+/// as they are and left for the validation of the data to report.
+pub(crate) struct CoercingReader {
+    source: Box<dyn RecordBatchReader + Send>,
+    schema: SchemaRef,
+    /// The type to cast each column to, if it is cast
+    targets: Vec<Option<DataType>>,
+    allow_lossy: bool,
+    batch_index: usize,
+}
+
+impl CoercingReader {
+    pub(crate) fn new(
+        source: Box<dyn RecordBatchReader + Send>,
+        table_schema: &Schema,
+        coercion: Coercion,
+    ) -> Self {
+        let source_schema = source.schema();
+        let targets = source_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let table_field = table_schema.field_with_name(field.name()).ok()?;
+                let (from, to) = (field.data_type(), table_field.data_type());
+                let cast = match coercion {
+                    Coercion::Vectors => is_list_of_vector(from, to),
+                    Coercion::All { .. } => can_coerce(from, to),
+                };
+                (from != to && cast).then(|| to.clone())
+            })
+            .collect::<Vec<_>>();
+        let fields = source_schema
+            .fields()
+            .iter()
+            .zip(&targets)
+            .map(|(field, target)| match target {
+                Some(target) => Arc::new(Field::new(
+                    field.name(),
+                    target.clone(),
+                    field.is_nullable(),
+                )),
+                None => field.clone(),
+            })
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            source_schema.metadata().clone(),
+        ));
+        Self {
+            source,
+            schema,
+            targets,
+            allow_lossy: matches!(coercion, Coercion::All { allow_lossy: true }),
+            batch_index: 0,
+        }
+    }
+
+    fn coerce(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(&self.targets)
+            .zip(batch.schema().fields())
+            .map(|((column, target), field)| match target {
+                Some(target) => {
+                    coerce_column(column, target, self.allow_lossy).map_err(|problem| {
+                        Error::InvalidInput {
+                            message: format!(
+                                "cannot cast column '{}' of batch {} from {} to {}: {}",
+                                field.name(),
+                                self.batch_index,
+                                column.data_type(),
+                                target,
+                                problem
+                            ),
+                        }
+                    })
+                }
+                None => Ok(column.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+impl Iterator for CoercingReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.source.next()? {
+            Ok(batch) => self.coerce(batch),
+            Err(err) => return Some(Err(err)),
+        };
+        self.batch_index += 1;
+        Some(batch.map_err(|err| ArrowError::ExternalError(Box::new(err))))
+    }
+}
+
+impl RecordBatchReader for CoercingReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Whether `from` is a list with the same items as the vector `to`
+fn is_list_of_vector(from: &DataType, to: &DataType) -> bool {
+    match (from, to) {
+        (DataType::List(item) | DataType::LargeList(item), DataType::FixedSizeList(to_item, _)) => {
+            item.data_type() == to_item.data_type()
+        }
+        _ => false,
+    }
+}
+
+/// Whether a column of `from` is cast to `to`
+///
+/// Numbers are cast to other numbers, strings to dates and timestamps and lists of
+/// numbers to vectors.
+fn can_coerce(from: &DataType, to: &DataType) -> bool {
+    match (from, to) {
+        (DataType::List(item) | DataType::LargeList(item), DataType::FixedSizeList(to_item, _))
+        | (DataType::FixedSizeList(item, _), DataType::FixedSizeList(to_item, _)) => {
+            item.data_type().is_numeric()
+                && to_item.data_type().is_numeric()
+                && can_cast_types(from, to)
+        }
+        (from, to) if from.is_numeric() && to.is_numeric() => true,
+        (
+            DataType::Utf8 | DataType::LargeUtf8,
+            DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64,
+        ) => true,
+        _ => false,
+    }
+}
+
+/// Cast `column` to `to`, or describe why it can't be
+///
+/// Unless `allow_lossy` is set, the cast fails if a value doesn't survive it: a
+/// number that is out of range or loses precision, or a string that isn't a date.
+/// Vectors always fail if their dimension is wrong, but their values are cast to
+/// the type of the vector even if that loses precision.
+fn coerce_column(
+    column: &ArrayRef,
+    to: &DataType,
+    allow_lossy: bool,
+) -> std::result::Result<ArrayRef, String> {
+    let safe = CastOptions {
+        safe: true,
+        ..Default::default()
+    };
+    if let DataType::FixedSizeList(_, dimension) = to {
+        check_dimension(column, *dimension as usize)?;
+        return cast_with_options(column, to, &safe).map_err(|err| err.to_string());
+    }
+
+    let cast = cast_with_options(column, to, &safe).map_err(|err| err.to_string())?;
+    if allow_lossy {
+        return Ok(cast);
+    }
+    // A value that can't be cast becomes null
+    if cast.null_count() > column.null_count() {
+        let row = (0..column.len())
+            .find(|&i| column.is_valid(i) && cast.is_null(i))
+            .expect("a value became null");
+        return Err(if column.data_type().is_numeric() {
+            format!(
+                "the value {} of row {} is out of range",
+                format_value(column, row),
+                row
+            )
+        } else {
+            format!(
+                "the value '{}' of row {} can't be parsed",
+                format_value(column, row),
+                row
+            )
+        });
+    }
+    if column.data_type().is_numeric() {
+        let round_trip =
+            cast_with_options(&cast, column.data_type(), &safe).map_err(|err| err.to_string())?;
+        let changed =
+            arrow_ord::cmp::distinct(column, &round_trip).map_err(|err| err.to_string())?;
+        if let Some(row) = changed.iter().position(|changed| changed == Some(true)) {
+            return Err(format!(
+                "the value {} of row {} would become {}",
+                format_value(column, row),
+                row,
+                format_value(&cast, row)
+            ));
+        }
+    }
+    Ok(cast)
+}
+
+/// Fail if a vector of `column` doesn't have `dimension` values
+fn check_dimension(column: &ArrayRef, dimension: usize) -> std::result::Result<(), String> {
+    let lengths: Box<dyn Iterator<Item = usize>> = match column.data_type() {
+        DataType::List(_) => Box::new(
+            column
+                .as_list::<i32>()
+                .offsets()
+                .windows(2)
+                .map(|w| (w[1] - w[0]) as usize),
+        ),
+        DataType::LargeList(_) => Box::new(
+            column
+                .as_list::<i64>()
+                .offsets()
+                .windows(2)
+                .map(|w| (w[1] - w[0]) as usize),
+        ),
+        DataType::FixedSizeList(_, size) => {
+            Box::new(std::iter::repeat(*size as usize).take(column.len()))
+        }
+        _ => return Ok(()),
+    };
+    for (row, length) in lengths.enumerate() {
+        if column.is_valid(row) && length != dimension {
+            return Err(format!(
+                "the vector of row {} has dimension {} but the table expects {}",
+                row, length, dimension
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn format_value(column: &dyn Array, row: usize) -> String {
+    match ArrayFormatter::try_new(column, &FormatOptions::default()) {
+        Ok(formatter) => formatter.value(row).to_string(),
+        Err(_) => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        types::Float64Type, FixedSizeListArray, Float64Array, Int32Array, Int64Array, ListArray,
+        StringArray,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_coerce_column() {
+        // Widening never loses values
+        let column: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None, Some(-3)]));
+        let cast = coerce_column(&column, &DataType::Int64, false).unwrap();
+        assert_eq!(
+            cast.as_ref(),
+            &Int64Array::from(vec![Some(1), None, Some(-3)]) as &dyn Array
+        );
+
+        // Narrowing works as long as the values fit
+        let column: ArrayRef = Arc::new(Int64Array::from(vec![1, i32::MAX as i64]));
+        let cast = coerce_column(&column, &DataType::Int32, false).unwrap();
+        assert_eq!(
+            cast.as_ref(),
+            &Int32Array::from(vec![1, i32::MAX]) as &dyn Array
+        );
+        let column: ArrayRef = Arc::new(Int64Array::from(vec![1, 1 << 40]));
+        let err = coerce_column(&column, &DataType::Int32, false).unwrap_err();
+        assert!(
+            err.contains("1099511627776 of row 1 is out of range"),
+            "{}",
+            err
+        );
+        let cast = coerce_column(&column, &DataType::Int32, true).unwrap();
+        assert_eq!(
+            cast.as_ref(),
+            &Int32Array::from(vec![Some(1), None]) as &dyn Array
+        );
+
+        let column: ArrayRef = Arc::new(Float64Array::from(vec![1.0, 2.5]));
+        let err = coerce_column(&column, &DataType::Int32, false).unwrap_err();
+        assert!(err.contains("2.5 of row 1 would become 2"), "{}", err);
+
+        // Vectors of doubles become vectors of floats
+        let to = DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2);
+        let column: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Float64Type, _, _>(vec![
+            Some(vec![Some(0.1), Some(0.2)]),
+            None,
+        ]));
+        let cast = coerce_column(&column, &to, false).unwrap();
+        assert_eq!(cast.data_type(), &to);
+        assert_eq!(cast.null_count(), 1);
+        let column: ArrayRef = Arc::new(
+            FixedSizeListArray::from_iter_primitive::<Float64Type, _, _>(
+                vec![Some(vec![Some(0.1), Some(0.2), Some(0.3)])],
+                3,
+            ),
+        );
+        let err = coerce_column(&column, &to, true).unwrap_err();
+        assert!(
+            err.contains("has dimension 3 but the table expects 2"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_coerce_vectors_only() {
+        let item = |data_type| Arc::new(Field::new("item", data_type, true));
+        let table_schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(item(DataType::Float32), 2),
+                true,
+            ),
+        ]);
+        let reader = |vector_item| {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int64, true),
+                Field::new("vector", DataType::List(vector_item), true),
+            ]));
+            let source = arrow_array::RecordBatchIterator::new(vec![], schema);
+            CoercingReader::new(Box::new(source), &table_schema, Coercion::Vectors)
+        };
+
+        // Only lists of the same items are converted
+        let schema = reader(item(DataType::Float32)).schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(
+            schema.field(1).data_type(),
+            table_schema.field(1).data_type()
+        );
+        let schema = reader(item(DataType::Float64)).schema();
+        assert_eq!(
+            schema.field(1).data_type(),
+            &DataType::List(item(DataType::Float64))
+        );
+    }
+
+    #[test]
+    fn test_coerce_timestamps() {
+        let to = DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None);
+        let column: ArrayRef = Arc::new(StringArray::from(vec![
+            "2024-01-02T03:04:05Z",
+            "2024-01-02 03:04:05.5",
+            "2024-01-02",
+        ]));
+        let cast = coerce_column(&column, &to, false).unwrap();
+        assert_eq!(cast.null_count(), 0);
+        assert_eq!(format_value(&cast, 1), "2024-01-02T03:04:05.500");
+
+        let column: ArrayRef = Arc::new(StringArray::from(vec!["2024-01-02", "yesterday"]));
+        let err = coerce_column(&column, &to, false).unwrap_err();
+        assert!(
+            err.contains("'yesterday' of row 1 can't be parsed"),
+            "{}",
+            err
+        );
+        let cast = coerce_column(&column, &to, true).unwrap();
+        assert_eq!(cast.null_count(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retrying writes that conflict with a concurrent write to the same table
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{ArrowError, Fields, Schema, SchemaRef};
+use rand::Rng;
+
+use crate::error::{Error, Result};
+
+use super::dataset::DatasetConsistencyWrapper;
+
+/// How writes to a native table are retried when they conflict with a concurrent write
+///
+/// A write is committed by creating the manifest of the next version of the table.
+/// When another writer (e.g. another process) committed that version first, the
+/// write conflicts.  [`crate::Table::add`], [`crate::Table::delete`],
+/// [`crate::Table::update`] and [`crate::Table::merge_insert`] then load the latest
+/// version of the table and run the write again.  A write is not retried if the
+/// schema of the table changed in the meantime, since the write may no longer make
+/// sense, and fails with [`Error::CommitConflict`] instead.  It also fails with
+/// [`Error::CommitConflict`] once the retries are used up.
+///
+/// The delay between attempts starts at [`Self::initial_backoff`] and doubles after
+/// every attempt, up to [`Self::max_backoff`].  Delays are jittered so that writers
+/// that conflicted with each other don't retry at the same moment.
+///
+/// The data passed to `add` and `merge_insert` can only be read once, so up to
+/// [`Self::max_replayable_size`] bytes of it are kept in memory until the write is
+/// committed.  Larger writes are streamed without keeping them, and fail with
+/// [`Error::CommitConflict`] rather than being retried.
+#[derive(Clone, Debug)]
+pub struct CommitRetryConfig {
+    /// The maximum number of times a write is retried, the default is 5
+    pub max_retries: usize,
+    /// The delay before the first retry, the default is 100ms
+    pub initial_backoff: Duration,
+    /// The maximum delay between retries, the default is 5s
+    pub max_backoff: Duration,
+    /// The number of bytes of the data of a write that are kept so that the write
+    /// can be retried, the default is 64MiB
+    ///
+    /// Larger writes are streamed and are not retried, which isn't worth giving up
+    /// unless the data is large.
+    pub max_replayable_size: usize,
+}
+
+impl Default for CommitRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_replayable_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The state of the retry loop of a single write
+///
+/// Call [`Self::begin`] before every attempt and [`Self::retry`] when an attempt
+/// fails.
+pub(crate) struct CommitRetry<'a> {
+    config: &'a CommitRetryConfig,
+    dataset: &'a DatasetConsistencyWrapper,
+    attempts: usize,
+    backoff: Duration,
+    /// The fields of the table when the current attempt started
+    fields: Option<Fields>,
+}
+
+impl<'a> CommitRetry<'a> {
+    pub(crate) fn new(
+        config: &'a CommitRetryConfig,
+        dataset: &'a DatasetConsistencyWrapper,
+    ) -> Self {
+        Self {
+            config,
+            dataset,
+            attempts: 0,
+            backoff: config.initial_backoff,
+            fields: None,
+        }
+    }
+
+    /// Record the schema the next attempt is written against
+    ///
+    /// The first attempt fails with [`Error::TableNotFound`] if the table was
+    /// dropped, rather than letting the write create a new table.  The latest
+    /// version of the table is only loaded by [`Self::retry`], so writes that don't
+    /// conflict don't pay for it.
+    pub(crate) async fn begin(&mut self) -> Result<()> {
+        if self.attempts == 0 {
+            self.dataset.ensure_exists().await?;
+        }
+        self.fields = Some(self.current_fields().await?);
+        Ok(())
+    }
+
+    /// Decide whether to try a failed write again
+    ///
+    /// Errors other than commit conflicts are returned as they are.  If the conflict
+    /// can be retried then the latest version of the table is loaded and this waits
+    /// out the backoff before returning.
+    pub(crate) async fn retry(&mut self, err: Error) -> Result<()> {
+        if !matches!(
+            &err,
+            Error::Lance {
+                source: lance::Error::CommitConflict { .. }
+            }
+        ) {
+            return Err(err);
+        }
+        self.attempts += 1;
+
+        self.dataset.reload().await?;
+        if self.fields.as_ref() != Some(&self.current_fields().await?) {
+            return Err(Error::CommitConflict {
+                message: format!(
+                    "the schema of the table was changed by a concurrent write: {}",
+                    err
+                ),
+            });
+        }
+        if self.attempts > self.config.max_retries {
+            return Err(Error::CommitConflict {
+                message: format!("gave up after {} attempts: {}", self.attempts, err),
+            });
+        }
+
+        log::debug!(
+            "Write conflicted with a concurrent write, retrying (attempt {}): {}",
+            self.attempts,
+            err
+        );
+        // Jitter between half and all of the backoff
+        let delay = self
+            .backoff
+            .mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        self.backoff = (self.backoff * 2).min(self.config.max_backoff);
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+
+    /// Like [`Self::retry`], for writes of `data` that can only be retried if all
+    /// of it was kept
+    pub(crate) async fn retry_write(&mut self, err: Error, data: &ReplayableData) -> Result<()> {
+        let conflict = matches!(
+            &err,
+            Error::Lance {
+                source: lance::Error::CommitConflict { .. }
+            }
+        );
+        if conflict && !data.is_replayable() {
+            return Err(Error::CommitConflict {
+                message: format!(
+                    "the data of the write was larger than {} bytes, so it was not kept to retry the write: {}",
+                    data.max_kept_size, err
+                ),
+            });
+        }
+        self.retry(err).await
+    }
+
+    async fn current_fields(&self) -> Result<Fields> {
+        let dataset = self.dataset.get().await?;
+        Ok(Schema::from(dataset.schema()).fields().clone())
+    }
+}
+
+/// The data of a write that may have to be written more than once
+pub(crate) struct ReplayableData {
+    schema: SchemaRef,
+    source: Option<Box<dyn RecordBatchReader + Send>>,
+    /// The batches read from the source so far, if they are kept
+    kept: Option<Arc<Mutex<KeptBatches>>>,
+    max_kept_size: usize,
+}
+
+/// The batches that were read from the source of a write
+#[derive(Default)]
+struct KeptBatches {
+    batches: Vec<RecordBatch>,
+    size: usize,
+    /// Set once the batches exceed the maximum size, they are dropped then
+    overflowed: bool,
+}
+
+impl ReplayableData {
+    /// Wrap the data of a write that is retried according to `config`
+    pub(crate) fn new(
+        source: Box<dyn RecordBatchReader + Send>,
+        config: &CommitRetryConfig,
+    ) -> Self {
+        let max_kept_size = if config.max_retries > 0 {
+            config.max_replayable_size
+        } else {
+            0
+        };
+        Self::with_max_kept_size(source, max_kept_size)
+    }
+
+    /// Wrap the data of a write, keeping up to `max_kept_size` bytes of it
+    fn with_max_kept_size(source: Box<dyn RecordBatchReader + Send>, max_kept_size: usize) -> Self {
+        Self {
+            schema: source.schema(),
+            source: Some(source),
+            kept: (max_kept_size > 0).then(Default::default),
+            max_kept_size,
+        }
+    }
+
+    /// Whether all of the data that was read so far can be written again
+    pub(crate) fn is_replayable(&self) -> bool {
+        match &self.kept {
+            Some(kept) => !kept.lock().unwrap().overflowed,
+            None => self.source.is_some(),
+        }
+    }
+
+    /// The data for the next attempt of the write
+    pub(crate) fn reader(&mut self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        if let Some(source) = self.source.take() {
+            return Ok(match &self.kept {
+                Some(kept) => Box::new(KeepingReader {
+                    source,
+                    kept: kept.clone(),
+                    max_kept_size: self.max_kept_size,
+                }),
+                None => source,
+            });
+        }
+        if !self.is_replayable() {
+            return Err(Error::Runtime {
+                message: "the data of the write was not kept and cannot be written again"
+                    .to_string(),
+            });
+        }
+        let batches = match &self.kept {
+            Some(kept) => kept.lock()?.batches.clone(),
+            None => Vec::new(),
+        };
+        Ok(Box::new(RecordBatchIterator::new(
+            batches.into_iter().map(Ok),
+            self.schema.clone(),
+        )))
+    }
+}
+
+/// Passes on the batches of a reader and keeps a copy of them, until they exceed
+/// `max_kept_size` bytes
+struct KeepingReader {
+    source: Box<dyn RecordBatchReader + Send>,
+    kept: Arc<Mutex<KeptBatches>>,
+    max_kept_size: usize,
+}
+
+impl Iterator for KeepingReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.source.next()?;
+        if let Ok(batch) = &batch {
+            let mut kept = self.kept.lock().unwrap();
+            if !kept.overflowed {
+                kept.size += batch.get_array_memory_size();
+                if kept.size > self.max_kept_size {
+                    // Stream the rest of the write, it can't be retried anymore
+                    kept.overflowed = true;
+                    kept.batches = Vec::new();
+                } else {
+                    kept.batches.push(batch.clone());
+                }
+            }
+        }
+        Some(batch)
+    }
+}
+
+impl RecordBatchReader for KeepingReader {
+    fn schema(&self) -> SchemaRef {
+        self.source.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Int32Array;
+    use arrow_schema::{DataType, Field};
+
+    use super::*;
+
+    #[test]
+    fn test_replayable_data() {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batches = (0..3)
+            .map(|i| {
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![i]))])
+            })
+            .collect::<Vec<_>>();
+        let source = Box::new(RecordBatchIterator::new(batches, schema.clone()));
+
+        let mut data = ReplayableData::new(source, &CommitRetryConfig::default());
+        let first = data.reader().unwrap().collect::<Vec<_>>();
+        let second = data.reader().unwrap().collect::<Vec<_>>();
+        assert_eq!(first.len(), 3);
+        assert_eq!(
+            first
+                .into_iter()
+                .map(|batch| batch.unwrap())
+                .collect::<Vec<_>>(),
+            second
+                .into_iter()
+                .map(|batch| batch.unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        let source = Box::new(RecordBatchIterator::new(vec![], schema.clone()));
+        let mut data = ReplayableData::new(
+            source,
+            &CommitRetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            },
+        );
+        assert!(data.is_replayable());
+        data.reader().unwrap();
+        assert!(!data.is_replayable());
+        assert!(data.reader().is_err());
+    }
+
+    #[test]
+    fn test_replayable_data_max_size() {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batches = (0..10)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        i * 1000..(i + 1) * 1000,
+                    ))],
+                )
+            })
+            .collect::<Vec<_>>();
+        let max_kept_size = batches[0].as_ref().unwrap().get_array_memory_size() * 3;
+
+        // Small writes are kept
+        let source = Box::new(RecordBatchIterator::new(
+            batches[..2].to_vec(),
+            schema.clone(),
+        ));
+        let mut data = ReplayableData::with_max_kept_size(source, max_kept_size);
+        assert_eq!(data.reader().unwrap().count(), 2);
+        assert!(data.is_replayable());
+        assert_eq!(data.reader().unwrap().count(), 2);
+
+        // Larger writes are still read in full, but not kept
+        let source = Box::new(RecordBatchIterator::new(batches, schema));
+        let mut data = ReplayableData::with_max_kept_size(source, max_kept_size);
+        assert_eq!(data.reader().unwrap().count(), 10);
+        assert!(!data.is_replayable());
+        assert!(data.reader().is_err());
+    }
+}
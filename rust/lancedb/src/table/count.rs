@@ -0,0 +1,154 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Approximate row counts, see [`super::Table::count_rows_approx`]
+
+use std::sync::Arc;
+
+use arrow::array::AsArray;
+use arrow_schema::Schema;
+use lance::dataset::Dataset;
+use lance_datafusion::planner::Planner;
+
+use crate::error::Result;
+
+/// The number of rows that are read to estimate a filtered count
+const SAMPLE_ROWS: usize = 10_000;
+
+/// The z-score of the confidence bounds of an estimate (95%)
+const Z: f64 = 1.96;
+
+/// An approximate number of rows, see [`super::Table::count_rows_approx`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApproxCount {
+    /// The estimated number of rows
+    pub estimate: usize,
+    /// The lower bound of the 95% confidence interval of the estimate
+    pub lower: usize,
+    /// The upper bound of the 95% confidence interval of the estimate
+    pub upper: usize,
+    /// Whether the rows were counted exactly, in which case all three counts are equal
+    pub exact: bool,
+}
+
+impl ApproxCount {
+    pub(crate) fn exact(count: usize) -> Self {
+        Self {
+            estimate: count,
+            lower: count,
+            upper: count,
+            exact: true,
+        }
+    }
+
+    /// Estimate the number of matches in `total` rows from `matches` in a sample of
+    /// `sampled` rows, chosen without replacement
+    ///
+    /// The bounds are the Wilson score interval of the fraction of matching rows.
+    /// The sample size is adjusted by the finite population correction, since the
+    /// sample is a significant part of smaller tables.
+    fn from_sample(matches: usize, sampled: usize, total: usize) -> Self {
+        if sampled >= total {
+            return Self::exact(matches);
+        }
+        let n = sampled as f64 * (total - 1) as f64 / (total - sampled) as f64;
+        let p = matches as f64 / sampled as f64;
+        let z2 = Z * Z / n;
+        let center = (p + z2 / 2.0) / (1.0 + z2);
+        let margin = Z * (p * (1.0 - p) / n + z2 / (4.0 * n)).sqrt() / (1.0 + z2);
+        // The rows of the sample are known to match, or not
+        let (min, max) = (matches, total - (sampled - matches));
+        let scale = |fraction: f64| {
+            let count = (fraction.clamp(0.0, 1.0) * total as f64).round() as usize;
+            count.clamp(min, max)
+        };
+        Self {
+            estimate: scale(p),
+            lower: scale(center - margin),
+            upper: scale(center + margin),
+            exact: false,
+        }
+    }
+}
+
+/// Count the rows of `dataset` that match `filter`, approximately
+///
+/// Without a filter the count comes from the metadata of the fragments and their
+/// deletion files and is exact.  With a filter, a uniform sample of the rows is
+/// read and the filter is evaluated on it.  Tables no larger than the sample are
+/// counted exactly.
+pub(crate) async fn count_rows_approx(
+    dataset: &Dataset,
+    filter: Option<&str>,
+) -> Result<ApproxCount> {
+    let mut total = 0;
+    for fragment in dataset.get_fragments() {
+        total += fragment.count_rows().await?;
+    }
+    let Some(filter) = filter else {
+        return Ok(ApproxCount::exact(total));
+    };
+
+    let schema = Arc::new(Schema::from(dataset.schema()));
+    let planner = Planner::new(schema);
+    let expr = planner.parse_filter(filter)?;
+    let columns = Planner::column_names_in_expr(&expr);
+    if total <= SAMPLE_ROWS || columns.is_empty() {
+        let count = dataset.count_rows(Some(filter.to_string())).await?;
+        return Ok(ApproxCount::exact(count));
+    }
+
+    let mut offsets = rand::seq::index::sample(&mut rand::thread_rng(), total, SAMPLE_ROWS)
+        .into_iter()
+        .map(|offset| offset as u64)
+        .collect::<Vec<_>>();
+    offsets.sort_unstable();
+    let projection = dataset.schema().project(&columns)?;
+    let sample = dataset.take(&offsets, &projection).await?;
+
+    // The filter is planned against the columns that were read
+    let planner = Planner::new(sample.schema());
+    let expr = planner.create_physical_expr(&planner.optimize_expr(expr)?)?;
+    let matches = expr.evaluate(&sample)?.into_array(sample.num_rows())?;
+    let matches = matches.as_boolean().true_count();
+    Ok(ApproxCount::from_sample(matches, sample.num_rows(), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_sample() {
+        let count = ApproxCount::from_sample(1_000, 10_000, 100_000);
+        assert_eq!(count.estimate, 10_000);
+        assert!(count.lower < 10_000 && count.upper > 10_000);
+        assert!(count.upper - count.lower < 2_000, "{:?}", count);
+        assert!(!count.exact);
+
+        // The rows of the sample are accounted for in the bounds
+        let count = ApproxCount::from_sample(0, 10_000, 100_000);
+        assert_eq!(count.lower, 0);
+        assert!(count.upper <= 90_000);
+        let count = ApproxCount::from_sample(10_000, 10_000, 100_000);
+        assert_eq!(count.upper, 100_000);
+        assert!(count.lower >= 10_000);
+
+        // A sample of the whole table is exact
+        assert_eq!(
+            ApproxCount::from_sample(500, 10_000, 10_000),
+            ApproxCount::exact(500)
+        );
+    }
+}
@@ -14,6 +14,7 @@
 
 use std::{
     ops::{Deref, DerefMut},
+    path::Path,
     sync::Arc,
     time::{self, Duration, Instant},
 };
@@ -72,9 +73,7 @@ impl DatasetRef {
 
     async fn need_reload(&self) -> Result<bool> {
         Ok(match self {
-            Self::Latest { dataset, .. } => {
-                dataset.latest_version_id().await? != dataset.version().version
-            }
+            Self::Latest { dataset, .. } => has_newer_version(dataset).await?,
             Self::TimeTravel { dataset, version } => dataset.version().version != *version,
         })
     }
@@ -136,6 +135,68 @@ impl DatasetRef {
     }
 }
 
+/// Check whether a newer version of the dataset has been committed
+///
+/// Versions are numbered consecutively, so rather than listing all the manifests
+/// this looks up the manifest of the next version.  If the manifest of the current
+/// version is gone as well, it was cleaned up after newer versions were committed
+/// (or the dataset was removed) and the manifests are listed after all.
+async fn has_newer_version(dataset: &Dataset) -> Result<bool> {
+    let version = dataset.version().version;
+    let Some(versions_dir) = versions_dir(dataset.uri()) else {
+        return Ok(dataset.latest_version_id().await? != version);
+    };
+    let store = &dataset.object_store().inner;
+    let manifest = |version: u64| versions_dir.child(format!("{}.manifest", version));
+    let (next, current) = futures::join!(
+        store.head(&manifest(version + 1)),
+        store.head(&manifest(version))
+    );
+    match (next, current) {
+        (Ok(_), _) => Ok(true),
+        (Err(object_store::Error::NotFound { .. }), Ok(_)) => Ok(false),
+        (Err(object_store::Error::NotFound { .. }), Err(object_store::Error::NotFound { .. })) => {
+            Ok(dataset.latest_version_id().await? != version)
+        }
+        (Err(err), _) | (_, Err(err)) => Err(err.into()),
+    }
+}
+
+/// Check that the dataset hasn't been removed
+///
+/// This looks up the manifest of the current version.  If it is gone, it may have
+/// been cleaned up after newer versions were committed, so the manifests are listed
+/// after all, which fails if the dataset was removed.
+async fn check_exists(dataset: &Dataset) -> Result<()> {
+    if let Some(versions_dir) = versions_dir(dataset.uri()) {
+        let manifest = versions_dir.child(format!("{}.manifest", dataset.version().version));
+        match dataset.object_store().inner.head(&manifest).await {
+            Ok(_) => return Ok(()),
+            Err(object_store::Error::NotFound { .. }) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    dataset.latest_version_id().await?;
+    Ok(())
+}
+
+/// The location of the manifests of a dataset in its object store
+///
+/// This resolves the URI the same way lance does.  `None` if it can't be resolved,
+/// e.g. because the local directory no longer exists.
+fn versions_dir(uri: &str) -> Option<object_store::path::Path> {
+    let base = match url::Url::parse(uri) {
+        Ok(url) if url.scheme().len() > 1 || !cfg!(windows) => {
+            object_store::path::Path::from(url.path())
+        }
+        _ => {
+            let path = Path::new(uri).canonicalize().ok()?;
+            object_store::path::Path::from_absolute_path(path).ok()?
+        }
+    };
+    Some(base.child("_versions"))
+}
+
 impl DatasetConsistencyWrapper {
     /// Create a new wrapper in the latest version mode.
     pub fn new_latest(dataset: Dataset, read_consistency_interval: Option<Duration>) -> Self {
@@ -185,7 +246,12 @@ impl DatasetConsistencyWrapper {
             return Ok(());
         }
 
-        write_guard.as_latest(read_consistency_interval).await
+        let result = write_guard.as_latest(read_consistency_interval).await;
+        drop(write_guard);
+        match result {
+            Err(err) => Err(self.not_found(err).await),
+            result => result,
+        }
     }
 
     pub async fn as_time_travel(&self, target_version: u64) -> Result<()> {
@@ -201,6 +267,32 @@ impl DatasetConsistencyWrapper {
     }
 
     pub async fn reload(&self) -> Result<()> {
+        match self.do_reload().await {
+            Err(err) => Err(self.not_found(err).await),
+            result => result,
+        }
+    }
+
+    /// Fail with [`crate::Error::TableNotFound`] if the table was dropped or renamed
+    /// since it was opened
+    ///
+    /// Unlike [`Self::reload`] this doesn't load a newer version.
+    pub async fn ensure_exists(&self) -> Result<()> {
+        let result = {
+            let dataset_ref = self.0.read().await;
+            let dataset = match &*dataset_ref {
+                DatasetRef::Latest { dataset, .. } => dataset,
+                DatasetRef::TimeTravel { dataset, .. } => dataset,
+            };
+            check_exists(dataset).await
+        };
+        match result {
+            Err(err) => Err(self.not_found(err).await),
+            result => result,
+        }
+    }
+
+    async fn do_reload(&self) -> Result<()> {
         if !self.0.read().await.need_reload().await? {
             return Ok(());
         }
@@ -215,6 +307,28 @@ impl DatasetConsistencyWrapper {
         write_guard.reload().await
     }
 
+    /// The latest version can't be found if the table was dropped or renamed
+    /// since it was opened
+    async fn not_found(&self, err: crate::Error) -> crate::Error {
+        match err {
+            crate::Error::Lance {
+                source: lance::Error::NotFound { .. } | lance::Error::DatasetNotFound { .. },
+            } => {
+                let dataset_ref = self.0.read().await;
+                let dataset = match &*dataset_ref {
+                    DatasetRef::Latest { dataset, .. } => dataset,
+                    DatasetRef::TimeTravel { dataset, .. } => dataset,
+                };
+                let name = Path::new(dataset.uri())
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                crate::Error::TableNotFound { name }
+            }
+            err => err,
+        }
+    }
+
     /// Returns the version, if in time travel mode, or None otherwise
     pub async fn time_travel_version(&self) -> Option<u64> {
         self.0.read().await.time_travel_version()
@@ -0,0 +1,469 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export the rows of a table to Parquet files
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::compute::take_record_batch;
+use arrow_array::{Array, RecordBatch, UInt32Array};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::SchemaRef;
+use futures::TryStreamExt;
+use lance::io::{ObjectStore, ObjectStoreParams};
+use object_store::{path::Path, MultipartId, ObjectStore as _};
+use parquet::{arrow::AsyncArrowWriter, file::properties::WriterProperties};
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::io::AsyncWrite;
+
+pub use parquet::basic::Compression;
+
+use super::Table;
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, QueryBase, Select};
+
+/// The directory name used for null values of partition columns, as in Hive
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Options for [`Table::export_parquet`]
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Only export the rows that match this filter
+    pub filter: Option<String>,
+    /// Only export these columns, all columns are exported by default
+    pub columns: Option<Vec<String>>,
+    /// The maximum number of rows in a row group, 1M by default
+    pub row_group_size: usize,
+    /// The compression of the files, snappy by default
+    pub compression: Compression,
+    /// Columns to partition the files by
+    ///
+    /// The files are written to Hive style directories, e.g. `year=2024/`, and the
+    /// partition columns are not stored in the files.
+    pub partition_by: Vec<String>,
+    /// Start a new file once a file reaches this size in bytes
+    ///
+    /// Files are only closed between batches, so they can be somewhat larger.  By
+    /// default there is one file per partition.
+    pub max_file_size: Option<usize>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            columns: None,
+            row_group_size: 1024 * 1024,
+            compression: Compression::SNAPPY,
+            partition_by: Vec::new(),
+            max_file_size: None,
+        }
+    }
+}
+
+/// What was written by [`Table::export_parquet`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    /// The URIs of the files that were written
+    pub files: Vec<String>,
+    /// The number of rows that were exported
+    pub rows: usize,
+    /// The total size of the files in bytes
+    pub bytes: usize,
+}
+
+struct OpenFile {
+    path: Path,
+    multipart_id: MultipartId,
+    writer: AsyncArrowWriter<Box<dyn AsyncWrite + Send + Unpin>>,
+}
+
+/// Writes batches to files, which are given temporary names until the export is
+/// complete
+struct Exporter {
+    store: Arc<dyn object_store::ObjectStore>,
+    base: Path,
+    schema: SchemaRef,
+    properties: WriterProperties,
+    max_file_size: Option<usize>,
+    /// A random suffix for the temporary names of the files
+    token: String,
+    /// The open file of each partition, by the directories of the partition
+    open: HashMap<Vec<String>, OpenFile>,
+    /// The temporary and final paths of the files that have been closed
+    closed: Vec<(Path, Path)>,
+}
+
+impl Exporter {
+    fn partition_dir(&self, partition: &[String]) -> Path {
+        partition
+            .iter()
+            .fold(self.base.clone(), |path, part| path.child(part.as_str()))
+    }
+
+    async fn write(&mut self, partition: Vec<String>, batch: RecordBatch) -> Result<()> {
+        if !self.open.contains_key(&partition) {
+            let name = format!("part-{:05}.parquet", self.closed.len() + self.open.len());
+            let path = self
+                .partition_dir(&partition)
+                .child(format!(".{}.{}.tmp", name, self.token));
+            let (multipart_id, writer) = self.store.put_multipart(&path).await?;
+            let writer = AsyncArrowWriter::try_new(
+                writer,
+                self.schema.clone(),
+                Some(self.properties.clone()),
+            )
+            .map_err(parquet_error)?;
+            let file = OpenFile {
+                path,
+                multipart_id,
+                writer,
+            };
+            self.open.insert(partition.clone(), file);
+        }
+        let file = self.open.get_mut(&partition).unwrap();
+        file.writer.write(&batch).await.map_err(parquet_error)?;
+        let size = file.writer.bytes_written() + file.writer.in_progress_size();
+        if self.max_file_size.is_some_and(|max| size >= max) {
+            self.close(&partition).await?;
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self, partition: &[String]) -> Result<()> {
+        let file = self.open.remove(partition).unwrap();
+        file.writer.close().await.map_err(parquet_error)?;
+        // The temporary name is ".part-00000.parquet.<token>.tmp"
+        let name = file.path.filename().unwrap()[1..].split(".parquet.").next();
+        let path = self
+            .partition_dir(partition)
+            .child(format!("{}.parquet", name.unwrap()));
+        self.closed.push((file.path, path));
+        Ok(())
+    }
+
+    /// Give the files their final names
+    async fn finish(mut self) -> Result<Vec<(Path, usize)>> {
+        let partitions = self.open.keys().cloned().collect::<Vec<_>>();
+        for partition in partitions {
+            self.close(&partition).await?;
+        }
+        let mut files = Vec::with_capacity(self.closed.len());
+        for (temporary, path) in &self.closed {
+            self.store.rename(temporary, path).await?;
+            let size = self.store.head(path).await?.size;
+            files.push((path.clone(), size));
+        }
+        Ok(files)
+    }
+
+    /// Remove what has been written after a failure
+    async fn abort(self) {
+        for file in self.open.values() {
+            let _ = self
+                .store
+                .abort_multipart(&file.path, &file.multipart_id)
+                .await;
+        }
+        for (temporary, _) in &self.closed {
+            let _ = self.store.delete(temporary).await;
+        }
+    }
+}
+
+fn parquet_error(err: parquet::errors::ParquetError) -> Error {
+    Error::Runtime {
+        message: format!("failed to write parquet: {}", err),
+    }
+}
+
+/// Split a batch into the rows of each partition, without the partition columns
+fn partition_batch(
+    batch: &RecordBatch,
+    partition_by: &[usize],
+    data_columns: &[usize],
+) -> Result<Vec<(Vec<String>, RecordBatch)>> {
+    let options = FormatOptions::default();
+    let formatters = partition_by
+        .iter()
+        .map(|i| ArrayFormatter::try_new(batch.column(*i).as_ref(), &options))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let schema = batch.schema();
+    let mut partitions = Vec::<(Vec<String>, Vec<u32>)>::new();
+    let mut index = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let key = partition_by
+            .iter()
+            .zip(&formatters)
+            .map(|(column, formatter)| {
+                let value = if batch.column(*column).is_null(row) {
+                    NULL_PARTITION.to_string()
+                } else {
+                    formatter.value(row).to_string()
+                };
+                format!("{}={}", schema.field(*column).name(), value)
+            })
+            .collect::<Vec<_>>();
+        let i = *index.entry(key.clone()).or_insert_with(|| {
+            partitions.push((key, Vec::new()));
+            partitions.len() - 1
+        });
+        partitions[i].1.push(row as u32);
+    }
+    partitions
+        .into_iter()
+        .map(|(key, rows)| {
+            let rows = take_record_batch(batch, &UInt32Array::from(rows))?;
+            Ok((key, rows.project(data_columns)?))
+        })
+        .collect()
+}
+
+pub(crate) async fn export_parquet(
+    table: &Table,
+    uri: &str,
+    storage_options: HashMap<String, String>,
+    options: ExportOptions,
+) -> Result<ExportSummary> {
+    let mut query = table.query();
+    if let Some(filter) = &options.filter {
+        query = query.only_if(filter);
+    }
+    if let Some(columns) = &options.columns {
+        query = query.select(Select::columns(columns.as_slice()));
+    }
+    let mut stream = query.execute().await?;
+    let schema = stream.schema();
+
+    let partition_by = options
+        .partition_by
+        .iter()
+        .map(|name| {
+            schema.index_of(name).map_err(|_| Error::InvalidInput {
+                message: format!(
+                    "can't partition by '{}', it is not an exported column",
+                    name
+                ),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let data_columns = (0..schema.fields().len())
+        .filter(|i| !partition_by.contains(i))
+        .collect::<Vec<_>>();
+    if data_columns.is_empty() {
+        return Err(Error::InvalidInput {
+            message: "can't partition by all of the exported columns".to_string(),
+        });
+    }
+
+    let params = ObjectStoreParams {
+        storage_options: Some(storage_options),
+        ..Default::default()
+    };
+    let (store, base) = ObjectStore::from_uri_and_params(uri, &params).await?;
+    let mut exporter = Exporter {
+        store: store.inner.clone(),
+        base,
+        schema: Arc::new(schema.project(&data_columns)?),
+        properties: WriterProperties::builder()
+            .set_max_row_group_size(options.row_group_size)
+            .set_compression(options.compression)
+            .build(),
+        max_file_size: options.max_file_size,
+        token: rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect(),
+        open: HashMap::new(),
+        closed: Vec::new(),
+    };
+
+    let mut rows = 0;
+    let written = async {
+        while let Some(batch) = stream.try_next().await? {
+            rows += batch.num_rows();
+            if partition_by.is_empty() {
+                exporter.write(Vec::new(), batch).await?;
+            } else {
+                for (partition, batch) in partition_batch(&batch, &partition_by, &data_columns)? {
+                    exporter.write(partition, batch).await?;
+                }
+            }
+        }
+        Ok::<_, Error>(())
+    }
+    .await;
+    if let Err(err) = written {
+        exporter.abort().await;
+        return Err(err);
+    }
+
+    let base = exporter.base.clone();
+    let files = exporter.finish().await?;
+    Ok(ExportSummary {
+        files: files
+            .iter()
+            .map(|(path, _)| {
+                let relative = path
+                    .as_ref()
+                    .strip_prefix(base.as_ref())
+                    .unwrap_or_default()
+                    .trim_start_matches('/');
+                format!("{}/{}", uri.trim_end_matches('/'), relative)
+            })
+            .collect(),
+        rows,
+        bytes: files.iter().map(|(_, size)| size).sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::connect;
+
+    fn read_rows(file: &str) -> (SchemaRef, usize) {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(file).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let schema = reader.schema();
+        let rows = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        (schema, rows)
+    }
+
+    #[tokio::test]
+    async fn test_export_parquet() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().join("db").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("category", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..1000)),
+                Arc::new(StringArray::from_iter(
+                    (0..1000).map(|i| [Some("a"), Some("b"), None][i % 3]),
+                )),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+
+        // A single file of the whole table
+        let export = tmp_dir.path().join("export");
+        let summary = table
+            .export_parquet(export.to_str().unwrap(), ExportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.rows, 2000);
+        assert_eq!(summary.files.len(), 1);
+        assert!(summary.files[0].ends_with("/part-00000.parquet"));
+        let (file_schema, rows) = read_rows(&summary.files[0]);
+        assert_eq!(file_schema.fields(), schema.fields());
+        assert_eq!(rows, 2000);
+        assert_eq!(
+            summary.bytes as u64,
+            std::fs::metadata(&summary.files[0]).unwrap().len()
+        );
+        // No temporary files are left
+        assert_eq!(std::fs::read_dir(&export).unwrap().count(), 1);
+
+        // Files roll over at the size limit
+        let summary = table
+            .export_parquet(
+                tmp_dir.path().join("small").to_str().unwrap(),
+                ExportOptions {
+                    row_group_size: 100,
+                    max_file_size: Some(1),
+                    compression: Compression::UNCOMPRESSED,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(summary.files.len() > 1);
+        let total = summary.files.iter().map(|f| read_rows(f).1).sum::<usize>();
+        assert_eq!(total, 2000);
+
+        // A historical version, filtered and partitioned
+        table.checkout(1).await.unwrap();
+        let summary = table
+            .export_parquet(
+                tmp_dir.path().join("partitioned").to_str().unwrap(),
+                ExportOptions {
+                    filter: Some("id < 100".to_string()),
+                    partition_by: vec!["category".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(summary.rows, 100);
+        let mut partitions = summary
+            .files
+            .iter()
+            .map(|file| {
+                let (file_schema, rows) = read_rows(file);
+                assert_eq!(file_schema.fields().len(), 1);
+                let dir = std::path::Path::new(file).parent().unwrap();
+                (dir.file_name().unwrap().to_str().unwrap().to_string(), rows)
+            })
+            .collect::<Vec<_>>();
+        partitions.sort();
+        assert_eq!(
+            partitions,
+            vec![
+                (format!("category={}", NULL_PARTITION), 33),
+                ("category=a".to_string(), 34),
+                ("category=b".to_string(), 33),
+            ]
+        );
+
+        let err = table
+            .export_parquet(
+                tmp_dir.path().join("bad").to_str().unwrap(),
+                ExportOptions {
+                    columns: Some(vec!["id".to_string()]),
+                    partition_by: vec!["category".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The fragments of a table, to split the work of scanning it, see
+//! [`super::NativeTable::fragments`]
+
+use lance::dataset::scanner::Scanner;
+use lance::dataset::Dataset;
+
+use crate::error::{Error, Result};
+
+/// A fragment of a table, see [`super::NativeTable::fragments`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentMetadata {
+    /// The id of the fragment, which [`crate::query::QueryBase::with_fragments`]
+    /// takes
+    pub id: u64,
+    /// The number of rows of the fragment, without the deleted rows
+    pub num_rows: usize,
+    /// The number of rows of the fragment that were deleted
+    pub num_deleted: usize,
+    /// The data files of the fragment, relative to the data directory of the table
+    pub files: Vec<String>,
+}
+
+/// Describe every fragment of a version of a table
+pub(crate) async fn list_fragments(dataset: &Dataset) -> Result<Vec<FragmentMetadata>> {
+    let mut fragments = Vec::new();
+    for fragment in dataset.get_fragments() {
+        let num_rows = fragment.count_rows().await?;
+        fragments.push(FragmentMetadata {
+            id: fragment.id() as u64,
+            num_rows,
+            num_deleted: fragment.physical_rows().await? - num_rows,
+            files: fragment
+                .metadata()
+                .files
+                .iter()
+                .map(|file| file.path.clone())
+                .collect(),
+        });
+    }
+    Ok(fragments)
+}
+
+/// Limit a scan of `dataset` to the fragments with `ids`, see
+/// [`crate::query::QueryBase::with_fragments`]
+pub(crate) fn scan_fragments(scanner: &mut Scanner, dataset: &Dataset, ids: &[u64]) -> Result<()> {
+    if ids.is_empty() {
+        return Err(Error::InvalidInput {
+            message: "with_fragments() needs at least one fragment".to_string(),
+        });
+    }
+    let fragments = ids
+        .iter()
+        .map(|id| {
+            dataset
+                .get_fragment(*id as usize)
+                .map(|fragment| fragment.metadata().clone())
+                .ok_or_else(|| Error::InvalidInput {
+                    message: format!(
+                        "the fragment {} does not exist in version {} of the table",
+                        id,
+                        dataset.version().version
+                    ),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    scanner.with_fragments(fragments);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use arrow::array::AsArray;
+    use arrow_array::{
+        types::{Float32Type, UInt64Type},
+        FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+    use crate::Error;
+
+    fn batch(ids: std::ops::Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                true,
+            ),
+        ]));
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            ids.clone().map(|id| Some(vec![Some(id as f32), Some(0.0)])),
+            2,
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_scan_fragments() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("test", batch(0..100))
+            .execute()
+            .await
+            .unwrap();
+        for start in (100..500).step_by(100) {
+            table
+                .add(batch(start..start + 100))
+                .execute()
+                .await
+                .unwrap();
+        }
+        table.delete("id % 10 = 0").await.unwrap();
+
+        let fragments = table.as_native().unwrap().fragments().await.unwrap();
+        assert_eq!(fragments.len(), 5);
+        assert!(fragments.iter().all(|fragment| !fragment.files.is_empty()));
+        assert_eq!(
+            fragments
+                .iter()
+                .map(|fragment| fragment.num_rows)
+                .sum::<usize>(),
+            450
+        );
+        assert_eq!(
+            fragments
+                .iter()
+                .map(|fragment| fragment.num_deleted)
+                .sum::<usize>(),
+            50
+        );
+
+        // Each worker scans its share of the fragments, together they scan every row
+        let num_workers = 3;
+        let mut row_ids = HashSet::new();
+        for worker in 0..num_workers {
+            let share = fragments
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % num_workers == worker)
+                .map(|(_, fragment)| fragment.id)
+                .collect::<Vec<_>>();
+            let batches = table
+                .query()
+                .with_fragments(&share)
+                .with_row_id()
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            for batch in batches {
+                for row_id in batch["_rowid"].as_primitive::<UInt64Type>().values() {
+                    assert!(share.contains(&(row_id >> 32)));
+                    assert!(row_ids.insert(*row_id));
+                }
+            }
+        }
+        assert_eq!(row_ids.len(), table.count_rows(None).await.unwrap());
+
+        // A flat vector search of some of the fragments
+        let results = table
+            .query()
+            .nearest_to(&[0.0, 0.0])
+            .unwrap()
+            .bypass_vector_index()
+            .with_fragments(&[fragments[2].id])
+            .with_row_address()
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let addresses = results
+            .iter()
+            .flat_map(|batch| {
+                batch["_rowaddr"]
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(addresses.len(), 5);
+        assert!(addresses
+            .iter()
+            .all(|address| address >> 32 == fragments[2].id));
+
+        let err = table
+            .query()
+            .nearest_to(&[0.0, 0.0])
+            .unwrap()
+            .with_fragments(&[fragments[2].id])
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+
+        let err = table
+            .query()
+            .with_fragments(&[1000])
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+    }
+}
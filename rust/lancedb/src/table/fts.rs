@@ -0,0 +1,908 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Full text search, with a full text index or by reading the searched columns
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use arrow::array::AsArray;
+use arrow_array::{
+    builder::{StringBuilder, UInt64Builder},
+    types::UInt64Type,
+    Array, ArrayRef, Float32Array, RecordBatch, RecordBatchIterator, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lance::dataset::builder::DatasetBuilder;
+use lance::dataset::{Dataset, ReadParams, WriteMode, WriteParams, ROW_ID};
+use lance::index::scalar::ScalarIndexParams;
+use lance_index::{DatasetIndexExt, IndexType};
+use serde::{Deserialize, Serialize};
+
+use super::take::{self, MissingRows};
+use crate::{
+    error::{Error, Result},
+    index::scalar::{BaseTokenizer, FtsIndexBuilder},
+    query::{FullTextSearchQuery, Query, Select, ROW_ADDR},
+    rerankers::SCORE,
+};
+
+/// BM25 parameters, the defaults of most search engines
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// The English stop words of Lucene, sorted
+const STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// How text is split into words, both the text of the rows and the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Tokenizer {
+    base: BaseTokenizer,
+    lower_case: bool,
+    remove_stop_words: bool,
+}
+
+impl Default for Tokenizer {
+    /// The tokenizer of columns without a full text index
+    fn default() -> Self {
+        Self {
+            base: BaseTokenizer::Simple,
+            lower_case: true,
+            remove_stop_words: false,
+        }
+    }
+}
+
+impl Tokenizer {
+    fn try_new(builder: &FtsIndexBuilder) -> Result<Self> {
+        if builder.stem {
+            return Err(Error::NotSupported {
+                message: "full text indices of local tables can't stem words, set stem to false"
+                    .to_string(),
+            });
+        }
+        if builder.remove_stop_words && !builder.language.eq_ignore_ascii_case("english") {
+            return Err(Error::NotSupported {
+                message: format!(
+                    "full text indices of local tables only know the stop words of English, not {}",
+                    builder.language
+                ),
+            });
+        }
+        Ok(Self {
+            base: builder.base_tokenizer,
+            lower_case: builder.lower_case,
+            remove_stop_words: builder.remove_stop_words,
+        })
+    }
+
+    fn tokenize<'a>(&self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        let tokenizer = *self;
+        let words: Box<dyn Iterator<Item = &'a str>> = match self.base {
+            BaseTokenizer::Simple => Box::new(text.split(|c: char| !c.is_alphanumeric())),
+            BaseTokenizer::Whitespace => Box::new(text.split(char::is_whitespace)),
+        };
+        words
+            .filter(|word| !word.is_empty())
+            .filter(move |word| {
+                !tokenizer.remove_stop_words
+                    || STOP_WORDS
+                        .binary_search(&word.to_lowercase().as_str())
+                        .is_err()
+            })
+            .map(move |word| {
+                if tokenizer.lower_case {
+                    word.to_lowercase()
+                } else {
+                    word.to_string()
+                }
+            })
+    }
+}
+
+fn strings(array: &ArrayRef) -> Box<dyn Iterator<Item = Option<&str>> + '_> {
+    match array.data_type() {
+        DataType::LargeUtf8 => Box::new(array.as_string::<i64>().iter()),
+        _ => Box::new(array.as_string::<i32>().iter()),
+    }
+}
+
+/// A part of a query that a row must contain
+#[derive(Debug, PartialEq)]
+enum Clause {
+    Term(String),
+    /// Words that must appear in this order, in the same column
+    Phrase(Vec<String>),
+}
+
+/// A parsed query, rows match if they contain all the clauses of any group
+///
+/// Words are alternatives unless they are joined with `AND`, which binds
+/// tighter than `OR`.  Quoted words are phrases.
+#[derive(Debug, PartialEq)]
+struct ParsedQuery {
+    groups: Vec<Vec<Clause>>,
+}
+
+impl ParsedQuery {
+    fn parse(query: &str, tokenizer: &Tokenizer) -> Result<Self> {
+        let invalid = |reason: &str| Error::InvalidInput {
+            message: format!("invalid full text query '{}': {}", query, reason),
+        };
+        let mut groups: Vec<Vec<Clause>> = Vec::new();
+        // Set after an AND or OR, until the clause that follows it
+        let mut operator = None;
+        let mut rest = query.trim_start();
+        while !rest.is_empty() {
+            let (word, clause) = if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').ok_or_else(|| invalid("unclosed quote"))?;
+                let words = tokenizer.tokenize(&quoted[..end]).collect::<Vec<_>>();
+                rest = &quoted[end + 1..];
+                (None, Some(Self::clause(words)))
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '"')
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                rest = &rest[end..];
+                match word {
+                    "AND" | "OR" => (Some(word), None),
+                    word => (None, Some(Self::clause(tokenizer.tokenize(word).collect()))),
+                }
+            };
+            rest = rest.trim_start();
+
+            if let Some(word) = word {
+                if operator.is_some() || groups.is_empty() {
+                    return Err(invalid(&format!("{} must follow a word", word)));
+                }
+                operator = Some(word);
+                continue;
+            }
+            match (clause.flatten(), operator.take()) {
+                // Punctuation only
+                (None, None) => {}
+                (None, Some(_)) => return Err(invalid("an operator must be followed by a word")),
+                (Some(clause), Some("AND")) => groups.last_mut().unwrap().push(clause),
+                (Some(clause), _) => groups.push(vec![clause]),
+            }
+        }
+        if operator.is_some() {
+            return Err(invalid("an operator must be followed by a word"));
+        }
+        Ok(Self { groups })
+    }
+
+    fn clause(mut words: Vec<String>) -> Option<Clause> {
+        match words.len() {
+            0 => None,
+            1 => Some(Clause::Term(words.pop().unwrap())),
+            _ => Some(Clause::Phrase(words)),
+        }
+    }
+
+    /// The distinct words of the query, sorted
+    fn terms(&self) -> Vec<String> {
+        let mut terms = self
+            .groups
+            .iter()
+            .flatten()
+            .flat_map(|clause| match clause {
+                Clause::Term(term) => std::slice::from_ref(term),
+                Clause::Phrase(words) => words.as_slice(),
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    /// Whether a row with the given words, per column, matches
+    fn matches(&self, columns: &[Vec<String>]) -> bool {
+        self.groups.iter().any(|group| {
+            group.iter().all(|clause| match clause {
+                Clause::Term(term) => columns.iter().flatten().any(|word| word == term),
+                Clause::Phrase(words) => columns
+                    .iter()
+                    .any(|column| column.windows(words.len()).any(|w| w == words.as_slice())),
+            })
+        })
+    }
+}
+
+/// The key of the [`IndexDetails`] in the schema metadata of an index
+const DETAILS_KEY: &str = "lancedb:fts";
+/// The columns of an index, a row for each distinct word of each row of the table
+const TOKEN: &str = "token";
+const TABLE_ROW_ID: &str = "row_id";
+
+/// What an index was built with and what it covers
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexDetails {
+    base_tokenizer: String,
+    language: String,
+    lower_case: bool,
+    remove_stop_words: bool,
+    /// The fragments of the table that were indexed
+    fragments: Vec<IndexedFragment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedFragment {
+    id: u64,
+    /// The number of words in the indexed column of the rows of the fragment
+    num_words: u64,
+}
+
+/// Where the full text index on the field with `field_id` of a table is stored
+///
+/// Indices are datasets of their own, next to the data of the table.  Fields are
+/// identified by id, so renaming a column keeps its index.
+pub(crate) fn index_uri(table_uri: &str, field_id: i32) -> String {
+    format!("{}/_fts/{}", table_uri.trim_end_matches('/'), field_id)
+}
+
+/// The name of the full text index on `column`
+pub(crate) fn index_name(column: &str) -> String {
+    format!("{}_fts_idx", column)
+}
+
+/// A full text index on a string column of a table
+///
+/// The index covers the fragments of the table that existed when it was built.
+/// The rows of newer fragments, e.g. the rows added since, are read and searched
+/// without the index.  Deleted rows stay in the index until it's rebuilt, they are
+/// left out of the results but still count in the statistics.
+pub(crate) struct FtsIndex {
+    postings: Dataset,
+    tokenizer: Tokenizer,
+    language: String,
+    /// The number of words of each indexed fragment, by fragment id
+    fragments: HashMap<u64, u64>,
+}
+
+impl FtsIndex {
+    /// Open the index at `uri`, None if there is none
+    pub(crate) async fn open(uri: &str, params: ReadParams) -> Result<Option<Self>> {
+        let postings = match DatasetBuilder::from_uri(uri)
+            .with_read_params(params)
+            .load()
+            .await
+        {
+            Ok(postings) => postings,
+            Err(lance::Error::DatasetNotFound { .. }) => return Ok(None),
+            Err(source) => return Err(Error::Lance { source }),
+        };
+        let invalid = |reason: String| Error::Runtime {
+            message: format!("the full text index at {} is invalid: {}", uri, reason),
+        };
+        let details = postings
+            .schema()
+            .metadata
+            .get(DETAILS_KEY)
+            .ok_or_else(|| invalid("it has no details".to_string()))?;
+        let details: IndexDetails =
+            serde_json::from_str(details).map_err(|e| invalid(e.to_string()))?;
+        let base = match details.base_tokenizer.as_str() {
+            "simple" => BaseTokenizer::Simple,
+            "whitespace" => BaseTokenizer::Whitespace,
+            other => return Err(invalid(format!("unknown tokenizer '{}'", other))),
+        };
+        Ok(Some(Self {
+            postings,
+            tokenizer: Tokenizer {
+                base,
+                lower_case: details.lower_case,
+                remove_stop_words: details.remove_stop_words,
+            },
+            language: details.language,
+            fragments: details
+                .fragments
+                .iter()
+                .map(|fragment| (fragment.id, fragment.num_words))
+                .collect(),
+        }))
+    }
+
+    /// A builder for an index like this one, to rebuild it
+    pub(crate) fn builder(&self) -> FtsIndexBuilder {
+        FtsIndexBuilder::default()
+            .base_tokenizer(self.tokenizer.base)
+            .language(self.language.clone())
+            .lower_case(self.tokenizer.lower_case)
+            .remove_stop_words(self.tokenizer.remove_stop_words)
+    }
+
+    /// The number of rows of `dataset` that the index covers, and that it doesn't
+    pub(crate) async fn count_rows(&self, dataset: &Dataset) -> Result<(usize, usize)> {
+        let mut indexed = 0;
+        let mut unindexed = 0;
+        for fragment in dataset.get_fragments() {
+            let num_rows = fragment.count_rows().await?;
+            if self.fragments.contains_key(&(fragment.id() as u64)) {
+                indexed += num_rows;
+            } else {
+                unindexed += num_rows;
+            }
+        }
+        Ok((indexed, unindexed))
+    }
+
+    /// The rows of `fragments` that contain each of `terms`
+    ///
+    /// The words of the index have a btree index, so only the rows of the index
+    /// with the terms are read.
+    async fn rows_with_terms(
+        &self,
+        terms: &[String],
+        fragments: &HashSet<u64>,
+    ) -> Result<Vec<HashSet<u64>>> {
+        let mut rows = vec![HashSet::new(); terms.len()];
+        if terms.is_empty() {
+            return Ok(rows);
+        }
+        let term_index = terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| (term.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        let literals = terms
+            .iter()
+            .map(|term| format!("'{}'", term.replace('\'', "''")))
+            .collect::<Vec<_>>();
+        let mut scanner = self.postings.scan();
+        scanner.project(&[TOKEN, TABLE_ROW_ID])?;
+        scanner.filter(&format!("{} IN ({})", TOKEN, literals.join(", ")))?;
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let tokens = batch[TOKEN].as_string::<i32>();
+            let row_ids = batch[TABLE_ROW_ID].as_primitive::<UInt64Type>();
+            for (token, row_id) in tokens.iter().zip(row_ids.values().iter()) {
+                let Some(i) = token.and_then(|token| term_index.get(token)) else {
+                    continue;
+                };
+                if fragments.contains(&(row_id >> 32)) {
+                    rows[*i].insert(*row_id);
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
+
+/// Build a full text index on `column` of `dataset` and write it to `uri`
+///
+/// An index that is already at `uri` is replaced.  The words of the rows are kept
+/// in memory until the index is written.
+pub(crate) async fn create_index(
+    dataset: &Dataset,
+    column: &str,
+    builder: &FtsIndexBuilder,
+    uri: &str,
+    params: WriteParams,
+) -> Result<()> {
+    let tokenizer = Tokenizer::try_new(builder)?;
+    let mut num_words = dataset
+        .get_fragments()
+        .iter()
+        .map(|fragment| (fragment.id() as u64, 0))
+        .collect::<BTreeMap<u64, u64>>();
+    let mut scanner = dataset.scan();
+    scanner.project(&[column])?;
+    scanner.with_row_id();
+    let mut stream = scanner.try_into_stream().await?;
+    let mut columns = Vec::new();
+    while let Some(batch) = stream.try_next().await? {
+        let mut tokens = StringBuilder::new();
+        let mut table_row_ids = UInt64Builder::new();
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        for (text, row_id) in strings(&batch[column]).zip(row_ids.values().iter()) {
+            let mut words = tokenizer
+                .tokenize(text.unwrap_or_default())
+                .collect::<Vec<_>>();
+            *num_words.entry(row_id >> 32).or_default() += words.len() as u64;
+            words.sort_unstable();
+            words.dedup();
+            for word in words {
+                tokens.append_value(word);
+                table_row_ids.append_value(*row_id);
+            }
+        }
+        columns.push(vec![
+            Arc::new(tokens.finish()) as ArrayRef,
+            Arc::new(table_row_ids.finish()),
+        ]);
+    }
+
+    let details = IndexDetails {
+        base_tokenizer: builder.base_tokenizer.to_string(),
+        language: builder.language.clone(),
+        lower_case: builder.lower_case,
+        remove_stop_words: builder.remove_stop_words,
+        fragments: num_words
+            .into_iter()
+            .map(|(id, num_words)| IndexedFragment { id, num_words })
+            .collect(),
+    };
+    let details = serde_json::to_string(&details).map_err(|e| Error::Runtime {
+        message: format!("error serializing the details of a full text index: {}", e),
+    })?;
+    let schema = Arc::new(Schema::new_with_metadata(
+        vec![
+            Field::new(TOKEN, DataType::Utf8, false),
+            Field::new(TABLE_ROW_ID, DataType::UInt64, false),
+        ],
+        HashMap::from([(DETAILS_KEY.to_string(), details)]),
+    ));
+    let has_words = columns.iter().any(|columns| !columns[0].is_empty());
+    let mut batches = columns
+        .into_iter()
+        .map(|columns| RecordBatch::try_new(schema.clone(), columns))
+        .collect::<Vec<_>>();
+    if batches.is_empty() {
+        // The index of an empty table is empty, but it still has the details
+        batches.push(Ok(RecordBatch::new_empty(schema.clone())));
+    }
+    let params = WriteParams {
+        mode: WriteMode::Overwrite,
+        ..params
+    };
+    let mut postings =
+        Dataset::write(RecordBatchIterator::new(batches, schema), uri, Some(params)).await?;
+    if has_words {
+        postings
+            .create_index(
+                &[TOKEN],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams {},
+                true,
+            )
+            .await?;
+    }
+    // The previous build of the index, if any, is no longer needed
+    postings
+        .cleanup_old_versions(chrono::Duration::zero(), Some(true))
+        .await?;
+    Ok(())
+}
+
+/// The columns that a full text search searches
+///
+/// All the string columns of the table, unless the query names the columns.
+pub(crate) fn searched_columns(
+    dataset: &Dataset,
+    full_text_search: &FullTextSearchQuery,
+) -> Result<Vec<String>> {
+    let schema = Schema::from(dataset.schema());
+    let columns = if full_text_search.columns.is_empty() {
+        schema
+            .fields()
+            .iter()
+            .filter(|f| matches!(f.data_type(), DataType::Utf8 | DataType::LargeUtf8))
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>()
+    } else {
+        for column in &full_text_search.columns {
+            let field = schema
+                .field_with_name(column)
+                .map_err(|_| Error::InvalidInput {
+                    message: format!(
+                        "cannot search the column '{}' for text, there is no such column",
+                        column
+                    ),
+                })?;
+            if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "cannot search the column '{}' for text, it has type {}",
+                        column,
+                        field.data_type()
+                    ),
+                });
+            }
+        }
+        full_text_search.columns.clone()
+    };
+    if columns.is_empty() {
+        return Err(Error::InvalidInput {
+            message: "cannot search for text, the table has no string columns".to_string(),
+        });
+    }
+    Ok(columns)
+}
+
+/// A row that matches the query
+struct Match {
+    row_id: u64,
+    length: usize,
+    term_frequencies: Vec<usize>,
+}
+
+/// The words of each of the searched columns of a row, and how often each of the
+/// terms appears in them
+fn read_row<'a>(
+    tokenizer: &Tokenizer,
+    term_index: &HashMap<&str, usize>,
+    texts: impl Iterator<Item = Option<&'a str>>,
+) -> (Vec<Vec<String>>, Vec<usize>) {
+    let words = texts
+        .map(|text| tokenizer.tokenize(text.unwrap_or_default()).collect())
+        .collect::<Vec<Vec<_>>>();
+    let mut term_frequencies = vec![0; term_index.len()];
+    for word in words.iter().flatten() {
+        if let Some(i) = term_index.get(word.as_str()) {
+            term_frequencies[*i] += 1;
+        }
+    }
+    (words, term_frequencies)
+}
+
+/// Score the rows of the searched columns with BM25 and take the best matches
+///
+/// `indices` has the full text index of each of the `columns`, if there is one.
+/// If every column has an index, the rows the indices cover are looked up in them.
+/// The other rows are read and searched.  The columns must be split into words the
+/// same way.
+///
+/// The statistics of BM25, the number of rows that contain each word and the
+/// average length of the rows, cover every row of the table.  The filter of the
+/// query only decides which rows can match.  Rows are scored with all the words of
+/// the query, see [`ParsedQuery`] for which rows match.
+pub(crate) async fn full_text_search(
+    dataset: &Dataset,
+    columns: &[String],
+    indices: &[Option<FtsIndex>],
+    query: &Query,
+    full_text_search: &FullTextSearchQuery,
+) -> Result<RecordBatch> {
+    let projection = match &query.select {
+        Select::All => dataset.schema().clone(),
+        Select::Columns(select) => dataset.schema().project(select)?,
+        Select::Dynamic(_) => {
+            return Err(Error::NotSupported {
+                message: "dynamic projections are not supported with full text search".to_string(),
+            })
+        }
+    };
+    let tokenizers = indices
+        .iter()
+        .map(|index| {
+            index
+                .as_ref()
+                .map_or_else(Tokenizer::default, |i| i.tokenizer)
+        })
+        .collect::<Vec<_>>();
+    let tokenizer = tokenizers[0];
+    if tokenizers.iter().any(|other| *other != tokenizer) {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the columns {} are split into words differently by their full text indices, search them separately",
+                columns.join(", ")
+            ),
+        });
+    }
+
+    let parsed = ParsedQuery::parse(&full_text_search.query, &tokenizer)?;
+    let terms = parsed.terms();
+    let term_index = terms
+        .iter()
+        .enumerate()
+        .map(|(i, term)| (term.as_str(), i))
+        .collect::<HashMap<_, _>>();
+
+    // The rows that pass the filter, None if every row does
+    let allowed = match &query.filter {
+        Some(filter) => {
+            let mut scanner = dataset.scan();
+            scanner.project::<&str>(&[])?;
+            scanner.with_row_id();
+            scanner.filter(filter)?;
+            let mut stream = scanner.try_into_stream().await?;
+            let mut allowed = HashSet::new();
+            while let Some(batch) = stream.try_next().await? {
+                allowed.extend(batch[ROW_ID].as_primitive::<UInt64Type>().values().iter());
+            }
+            Some(allowed)
+        }
+        None => None,
+    };
+    let is_allowed = |row_id: &u64| {
+        allowed
+            .as_ref()
+            .map_or(true, |allowed| allowed.contains(row_id))
+    };
+
+    // The fragments that every searched column has indexed
+    let fragments = dataset.get_fragments();
+    let indexed = if indices.iter().all(Option::is_some) {
+        fragments
+            .iter()
+            .map(|fragment| fragment.id() as u64)
+            .filter(|id| {
+                indices
+                    .iter()
+                    .flatten()
+                    .all(|index| index.fragments.contains_key(id))
+            })
+            .collect::<HashSet<_>>()
+    } else {
+        HashSet::new()
+    };
+
+    let mut num_rows = 0;
+    let mut total_length = 0;
+    let mut document_frequencies = vec![0; terms.len()];
+    let mut matches = Vec::new();
+
+    let unindexed = fragments
+        .iter()
+        .filter(|fragment| !indexed.contains(&(fragment.id() as u64)))
+        .map(|fragment| fragment.metadata().clone())
+        .collect::<Vec<_>>();
+    if !unindexed.is_empty() {
+        let mut scanner = dataset.scan();
+        scanner.project(columns)?;
+        scanner.with_row_id();
+        scanner.with_fragments(unindexed);
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+            let mut texts = columns
+                .iter()
+                .map(|column| strings(&batch[column.as_str()]))
+                .collect::<Vec<_>>();
+            for row_id in row_ids.values().iter() {
+                let (words, term_frequencies) = read_row(
+                    &tokenizer,
+                    &term_index,
+                    texts.iter_mut().map(|text| text.next().flatten()),
+                );
+                let length = words.iter().map(Vec::len).sum::<usize>();
+                num_rows += 1;
+                total_length += length;
+                for (df, tf) in document_frequencies.iter_mut().zip(&term_frequencies) {
+                    *df += (*tf > 0) as usize;
+                }
+                if is_allowed(row_id) && parsed.matches(&words) {
+                    matches.push(Match {
+                        row_id: *row_id,
+                        length,
+                        term_frequencies,
+                    });
+                }
+            }
+        }
+    }
+
+    if !indexed.is_empty() {
+        for fragment in fragments.iter() {
+            let id = fragment.id() as u64;
+            if indexed.contains(&id) {
+                num_rows += fragment.count_rows().await?;
+                for index in indices.iter().flatten() {
+                    total_length += index.fragments[&id] as usize;
+                }
+            }
+        }
+        // A row contains a term if any of its searched columns does
+        let mut rows_with_terms = vec![HashSet::new(); terms.len()];
+        for index in indices.iter().flatten() {
+            let found = index.rows_with_terms(&terms, &indexed).await?;
+            for (rows, found) in rows_with_terms.iter_mut().zip(found) {
+                rows.extend(found);
+            }
+        }
+        let mut candidates = rows_with_terms
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+        // The index still has the rows that were deleted since it was built
+        let candidates = take::existing_row_ids(dataset, &candidates).await?;
+        for (df, rows) in document_frequencies.iter_mut().zip(&rows_with_terms) {
+            *df += rows
+                .iter()
+                .filter(|row_id| candidates.binary_search(row_id).is_ok())
+                .count();
+        }
+
+        // The candidates contain some of the words, phrases and operators are
+        // checked on their text
+        let candidates = candidates
+            .into_iter()
+            .filter(|row_id| is_allowed(row_id))
+            .collect::<Vec<_>>();
+        if !candidates.is_empty() {
+            let rows =
+                take::take_rows(dataset, &candidates, Some(columns), MissingRows::Error).await?;
+            let mut texts = columns
+                .iter()
+                .map(|column| strings(&rows[column.as_str()]))
+                .collect::<Vec<_>>();
+            for row_id in candidates {
+                let (words, term_frequencies) = read_row(
+                    &tokenizer,
+                    &term_index,
+                    texts.iter_mut().map(|text| text.next().flatten()),
+                );
+                if parsed.matches(&words) {
+                    matches.push(Match {
+                        row_id,
+                        length: words.iter().map(Vec::len).sum(),
+                        term_frequencies,
+                    });
+                }
+            }
+        }
+    }
+
+    let idf = document_frequencies
+        .iter()
+        .map(|df| (1.0 + (num_rows as f32 - *df as f32 + 0.5) / (*df as f32 + 0.5)).ln())
+        .collect::<Vec<_>>();
+    let average_length = total_length as f32 / num_rows.max(1) as f32;
+    let mut scored = matches
+        .iter()
+        .map(|m| {
+            let norm = K1 * (1.0 - B + B * m.length as f32 / average_length);
+            let score = m
+                .term_frequencies
+                .iter()
+                .zip(&idf)
+                .map(|(tf, idf)| idf * (*tf as f32 * (K1 + 1.0)) / (*tf as f32 + norm))
+                .sum::<f32>();
+            (m.row_id, score)
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then(a_id.cmp(b_id)));
+    scored.drain(..query.offset.unwrap_or(0).min(scored.len()));
+    if let Some(limit) = query.limit {
+        scored.truncate(limit);
+    }
+
+    let row_ids = scored.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let rows = dataset.take_rows(&row_ids, &projection).await?;
+    let mut fields = rows.schema().fields().iter().cloned().collect::<Vec<_>>();
+    let mut arrays = rows.columns().to_vec();
+    if query.with_row_id {
+        fields.push(Arc::new(Field::new(ROW_ID, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids.clone())));
+    }
+    if query.with_row_address {
+        // Without stable row ids, which are not enabled, the row id is the address
+        fields.push(Arc::new(Field::new(ROW_ADDR, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids)));
+    }
+    fields.push(Arc::new(Field::new(SCORE, DataType::Float32, false)));
+    arrays.push(Arc::new(Float32Array::from_iter_values(
+        scored.iter().map(|(_, score)| *score),
+    )));
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(word: &str) -> Clause {
+        Clause::Term(word.to_string())
+    }
+
+    fn phrase(words: &[&str]) -> Clause {
+        Clause::Phrase(words.iter().map(|w| w.to_string()).collect())
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = ParsedQuery::parse("Pump failure", &Tokenizer::default()).unwrap();
+        assert_eq!(
+            parsed.groups,
+            vec![vec![term("pump")], vec![term("failure")]]
+        );
+
+        let parsed = ParsedQuery::parse(
+            "pump AND failure OR \"valve stuck\" leak",
+            &Tokenizer::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.groups,
+            vec![
+                vec![term("pump"), term("failure")],
+                vec![phrase(&["valve", "stuck"])],
+                vec![term("leak")],
+            ]
+        );
+        assert_eq!(
+            parsed.terms(),
+            vec!["failure", "leak", "pump", "stuck", "valve"]
+        );
+
+        // Words joined by punctuation are phrases, punctuation alone is ignored
+        let parsed = ParsedQuery::parse("pump-failure - \"\"", &Tokenizer::default()).unwrap();
+        assert_eq!(parsed.groups, vec![vec![phrase(&["pump", "failure"])]]);
+
+        for query in ["AND pump", "pump OR", "pump AND OR failure", "\"pump"] {
+            assert!(
+                matches!(
+                    ParsedQuery::parse(query, &Tokenizer::default()),
+                    Err(Error::InvalidInput { .. })
+                ),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let row = |columns: &[&str]| {
+            columns
+                .iter()
+                .map(|c| Tokenizer::default().tokenize(c).collect())
+                .collect::<Vec<Vec<_>>>()
+        };
+        let parsed =
+            ParsedQuery::parse("pump AND failure OR \"valve stuck\"", &Tokenizer::default())
+                .unwrap();
+        assert!(parsed.matches(&row(&["the pump", "reported a failure"])));
+        assert!(parsed.matches(&row(&["a valve stuck open", ""])));
+        assert!(!parsed.matches(&row(&["the pump works", "stuck valve"])));
+        // Phrases don't span columns
+        assert!(!parsed.matches(&row(&["valve", "stuck"])));
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let text = "The pump's valve-stuck, AGAIN";
+        let words = |tokenizer: Tokenizer| tokenizer.tokenize(text).collect::<Vec<_>>();
+        assert_eq!(
+            words(Tokenizer::default()),
+            vec!["the", "pump", "s", "valve", "stuck", "again"]
+        );
+
+        let builder = FtsIndexBuilder::default()
+            .base_tokenizer(BaseTokenizer::Whitespace)
+            .lower_case(false)
+            .remove_stop_words(true);
+        assert_eq!(
+            words(Tokenizer::try_new(&builder).unwrap()),
+            vec!["pump's", "valve-stuck,", "AGAIN"]
+        );
+
+        let builder = FtsIndexBuilder::default().stem(true);
+        assert!(matches!(
+            Tokenizer::try_new(&builder),
+            Err(Error::NotSupported { .. })
+        ));
+        let builder = FtsIndexBuilder::default()
+            .language("French")
+            .remove_stop_words(true);
+        assert!(matches!(
+            Tokenizer::try_new(&builder),
+            Err(Error::NotSupported { .. })
+        ));
+    }
+}
@@ -14,11 +14,34 @@
 
 use std::sync::Arc;
 
-use arrow_array::RecordBatchReader;
+use arrow_array::{cast::AsArray, types::UInt64Type, RecordBatch, RecordBatchReader};
+use arrow_schema::SchemaRef;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::{SessionConfig, SessionContext};
 
-use crate::Result;
+use crate::{
+    embeddings::EmbeddingRegistry,
+    error::{Error, Result},
+};
 
-use super::TableInternal;
+use super::provider::TableProviderAdapter;
+use super::{Table, TableInternal};
+
+/// The outcome of a merge insert, see [`MergeInsertBuilder::execute`]
+///
+/// LanceDB Cloud servers that don't report some of these numbers leave them None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeResult {
+    /// The number of rows of the new data that were inserted
+    pub num_inserted: Option<u64>,
+    /// The number of rows that were updated with the new data
+    pub num_updated: Option<u64>,
+    /// The number of rows that were deleted, because they matched a
+    /// [`MergeInsertBuilder::when_matched_delete`] clause or weren't in the new data
+    pub num_deleted: Option<u64>,
+    /// The version of the table written by the merge insert
+    pub version: Option<u64>,
+}
 
 /// A builder used to create and run a merge insert operation
 ///
@@ -26,16 +49,23 @@ use super::TableInternal;
 #[derive(Debug, Clone)]
 pub struct MergeInsertBuilder {
     table: Arc<dyn TableInternal>,
-    pub(super) on: Vec<String>,
-    pub(super) when_matched_update_all: bool,
-    pub(super) when_matched_update_all_filt: Option<String>,
-    pub(super) when_not_matched_insert_all: bool,
-    pub(super) when_not_matched_by_source_delete: bool,
-    pub(super) when_not_matched_by_source_delete_filt: Option<String>,
+    pub(crate) on: Vec<String>,
+    pub(crate) when_matched_update_all: bool,
+    pub(crate) when_matched_update_all_filt: Option<String>,
+    pub(crate) when_not_matched_insert_all: bool,
+    pub(crate) when_not_matched_by_source_delete: bool,
+    pub(crate) when_not_matched_by_source_delete_filt: Option<String>,
+    pub(crate) when_matched_delete: bool,
+    pub(crate) when_matched_delete_filt: Option<String>,
+    pub(crate) embedding_registry: Arc<dyn EmbeddingRegistry>,
 }
 
 impl MergeInsertBuilder {
-    pub(super) fn new(table: Arc<dyn TableInternal>, on: Vec<String>) -> Self {
+    pub(super) fn new(
+        table: Arc<dyn TableInternal>,
+        on: Vec<String>,
+        embedding_registry: Arc<dyn EmbeddingRegistry>,
+    ) -> Self {
         Self {
             table,
             on,
@@ -44,6 +74,9 @@ impl MergeInsertBuilder {
             when_not_matched_insert_all: false,
             when_not_matched_by_source_delete: false,
             when_not_matched_by_source_delete_filt: None,
+            when_matched_delete: false,
+            when_matched_delete_filt: None,
+            embedding_registry,
         }
     }
 
@@ -73,6 +106,26 @@ impl MergeInsertBuilder {
         self
     }
 
+    /// Rows that exist in both the source table (new data) and the
+    /// target table (old data) will be deleted from the target table.
+    ///
+    /// An optional condition may be specified, with the same prefixes
+    /// as the condition of [`Self::when_matched_update_all`].  If it
+    /// is, then only matched rows that satisfy the condition will be
+    /// deleted.
+    ///
+    /// The delete takes precedence over [`Self::when_matched_update_all`],
+    /// a row that satisfies both conditions is deleted.
+    ///
+    /// Local tables commit the deletes in the same version as the rest of
+    /// the merge.  The new data is read into memory to match it to the
+    /// rows of the table.
+    pub fn when_matched_delete(&mut self, condition: Option<String>) -> &mut Self {
+        self.when_matched_delete = true;
+        self.when_matched_delete_filt = condition;
+        self
+    }
+
     /// Rows that exist only in the source table (new data) should
     /// be inserted into the target table.
     pub fn when_not_matched_insert_all(&mut self) -> &mut Self {
@@ -97,8 +150,208 @@ impl MergeInsertBuilder {
 
     /// Executes the merge insert operation
     ///
-    /// Nothing is returned but the [`super::Table`] is updated
-    pub async fn execute(self, new_data: Box<dyn RecordBatchReader + Send>) -> Result<()> {
+    /// Returns the number of rows that were inserted, updated and deleted, and the
+    /// version of the table that was written.  LanceDB Cloud servers that don't report
+    /// these numbers leave them None.
+    ///
+    /// Conditions that refer to columns which don't exist are an error, and nothing
+    /// is written.
+    pub async fn execute(self, new_data: Box<dyn RecordBatchReader + Send>) -> Result<MergeResult> {
         self.table.clone().merge_insert(self, new_data).await
     }
 }
+
+/// A session whose column names are case sensitive, like everywhere else in LanceDB
+pub(super) fn session() -> SessionContext {
+    let mut config = SessionConfig::new();
+    config.options_mut().sql_parser.enable_ident_normalization = false;
+    SessionContext::new_with_config(config)
+}
+
+/// A column name as a DataFusion SQL identifier
+fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Check that the conditions of a merge insert refer to columns that exist
+///
+/// The conditions are planned against empty tables, so this reads no data.
+pub(crate) async fn check_conditions(
+    params: &MergeInsertBuilder,
+    source_schema: SchemaRef,
+    target_schema: SchemaRef,
+) -> Result<()> {
+    let ctx = session();
+    ctx.register_table(
+        "source",
+        Arc::new(MemTable::try_new(source_schema, vec![vec![]])?),
+    )?;
+    ctx.register_table(
+        "target",
+        Arc::new(MemTable::try_new(target_schema, vec![vec![]])?),
+    )?;
+    let conditions = [
+        (
+            "when_matched_update_all",
+            &params.when_matched_update_all_filt,
+            "target, source",
+        ),
+        (
+            "when_matched_delete",
+            &params.when_matched_delete_filt,
+            "target, source",
+        ),
+        (
+            "when_not_matched_by_source_delete",
+            &params.when_not_matched_by_source_delete_filt,
+            "target",
+        ),
+    ];
+    for (clause, condition, tables) in conditions {
+        let Some(condition) = condition else {
+            continue;
+        };
+        let sql = format!("SELECT 1 FROM {} WHERE {}", tables, condition);
+        if let Err(err) = ctx.sql(&sql).await {
+            return Err(Error::InvalidInput {
+                message: format!("invalid condition '{}' of {}: {}", condition, clause, err),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The changes of a merge insert, which are committed as one version of the table
+pub(crate) struct MergeChanges {
+    /// The row ids of the rows of the table that are deleted or updated, sorted
+    pub(crate) removed_row_ids: Vec<u64>,
+    /// The new values of the updated rows and the inserted rows
+    pub(crate) new_rows: Vec<RecordBatch>,
+    pub(crate) num_inserted: u64,
+    pub(crate) num_updated: u64,
+    pub(crate) num_deleted: u64,
+}
+
+/// Work out the changes of a merge insert of `source` into `table`
+///
+/// This is used for the merge inserts that lance can't run, those with a
+/// [`MergeInsertBuilder::when_matched_delete`] clause.  The rows are matched with
+/// DataFusion joins and the rows of the table are identified by their row ids, so
+/// nothing is written until the changes are committed.
+pub(crate) async fn plan_merge(
+    params: &MergeInsertBuilder,
+    table: &Table,
+    source_schema: SchemaRef,
+    source: Vec<RecordBatch>,
+) -> Result<MergeChanges> {
+    let ctx = session();
+    ctx.register_table(
+        "source",
+        Arc::new(MemTable::try_new(source_schema, vec![source])?),
+    )?;
+    ctx.register_table(
+        "target",
+        Arc::new(TableProviderAdapter::try_new_with_row_id(table.clone()).await?),
+    )?;
+    let join = |left: &str, right: &str| {
+        params
+            .on
+            .iter()
+            .map(|column| format!("{0}.{2} = {1}.{2}", left, right, ident(column)))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    };
+    let matched = format!("FROM target JOIN source ON {}", join("target", "source"));
+    let delete_condition = params.when_matched_delete_filt.as_deref();
+
+    let mut removed_row_ids = Vec::new();
+    let deleted = row_ids(
+        &ctx,
+        &match delete_condition {
+            Some(condition) => format!("SELECT target._rowid {} WHERE {}", matched, condition),
+            None => format!("SELECT target._rowid {}", matched),
+        },
+    )
+    .await?;
+    let mut num_deleted = deleted.len() as u64;
+    removed_row_ids.extend(deleted);
+
+    // The delete takes precedence, so only the matched rows it keeps are updated
+    let mut new_rows = Vec::new();
+    let mut num_updated = 0;
+    if let (true, Some(delete_condition)) = (params.when_matched_update_all, delete_condition) {
+        let mut filter = format!("({}) IS NOT TRUE", delete_condition);
+        if let Some(condition) = &params.when_matched_update_all_filt {
+            filter = format!("{} AND ({})", filter, condition);
+        }
+        let updated = row_ids(
+            &ctx,
+            &format!("SELECT target._rowid {} WHERE {}", matched, filter),
+        )
+        .await?;
+        num_updated = updated.len() as u64;
+        removed_row_ids.extend(updated);
+        new_rows.extend(
+            ctx.sql(&format!("SELECT source.* {} WHERE {}", matched, filter))
+                .await?
+                .collect()
+                .await?,
+        );
+    }
+
+    let mut num_inserted = 0;
+    if params.when_not_matched_insert_all {
+        let inserted = ctx
+            .sql(&format!(
+                "SELECT source.* FROM source LEFT ANTI JOIN target ON {}",
+                join("source", "target")
+            ))
+            .await?
+            .collect()
+            .await?;
+        num_inserted = inserted.iter().map(|batch| batch.num_rows() as u64).sum();
+        new_rows.extend(inserted);
+    }
+
+    if params.when_not_matched_by_source_delete {
+        let mut sql = format!(
+            "SELECT target._rowid FROM target LEFT ANTI JOIN source ON {}",
+            join("target", "source")
+        );
+        if let Some(condition) = &params.when_not_matched_by_source_delete_filt {
+            sql = format!("{} WHERE {}", sql, condition);
+        }
+        let deleted = row_ids(&ctx, &sql).await?;
+        num_deleted += deleted.len() as u64;
+        removed_row_ids.extend(deleted);
+    }
+
+    removed_row_ids.sort_unstable();
+    removed_row_ids.dedup();
+    Ok(MergeChanges {
+        removed_row_ids,
+        new_rows,
+        num_inserted,
+        num_updated,
+        num_deleted,
+    })
+}
+
+/// The distinct row ids that a query of a single `_rowid` column returns
+async fn row_ids(ctx: &SessionContext, sql: &str) -> Result<Vec<u64>> {
+    let batches = ctx
+        .sql(&format!("SELECT DISTINCT _rowid FROM ({})", sql))
+        .await?
+        .collect()
+        .await?;
+    Ok(batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(0)
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect())
+}
@@ -0,0 +1,216 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A search of a multivector column without an index, see
+//! [`crate::query::Query::nearest_to_multivector`]
+
+use std::sync::Arc;
+
+use arrow::array::AsArray;
+use arrow_array::{
+    types::{Float32Type, UInt64Type},
+    Array, ArrayRef, Float32Array, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lance::dataset::{Dataset, ROW_ID};
+
+use crate::{
+    error::{Error, Result},
+    query::{Select, VectorQuery, DEFAULT_TOP_K, ROW_ADDR},
+    rerankers::DISTANCE,
+    DistanceType,
+};
+
+use super::fragments::scan_fragments;
+
+/// The distance between two vectors, as lance computes it for `distance_type`
+fn distance(distance_type: DistanceType, a: &[f32], b: &[f32]) -> f32 {
+    let dot = || a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    match distance_type {
+        DistanceType::L2 => a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum(),
+        DistanceType::Cosine => {
+            let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            1.0 - dot() / (norm(a) * norm(b))
+        }
+        DistanceType::Dot => 1.0 - dot(),
+        DistanceType::Hamming => unreachable!("rejected before searching"),
+    }
+}
+
+/// The distance of a row, the sum of the distance of each query vector to its
+/// nearest vector of the row
+///
+/// This is None for rows without vectors.
+fn row_distance(
+    distance_type: DistanceType,
+    query_vectors: &[&[f32]],
+    vectors: &[f32],
+    dim: usize,
+) -> Option<f32> {
+    if vectors.is_empty() {
+        return None;
+    }
+    Some(
+        query_vectors
+            .iter()
+            .map(|query_vector| {
+                vectors
+                    .chunks_exact(dim)
+                    .map(|vector| distance(distance_type, query_vector, vector))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .sum(),
+    )
+}
+
+/// The vectors of each row of a multivector column, as f32
+fn row_vectors(column: &ArrayRef) -> Result<Vec<Option<Vec<f32>>>> {
+    let (offsets, vectors): (Vec<usize>, ArrayRef) = match column.data_type() {
+        DataType::List(_) => {
+            let list = column.as_list::<i32>();
+            let offsets = list.offsets().iter().map(|o| *o as usize).collect();
+            (offsets, list.values().clone())
+        }
+        DataType::LargeList(_) => {
+            let list = column.as_list::<i64>();
+            let offsets = list.offsets().iter().map(|o| *o as usize).collect();
+            (offsets, list.values().clone())
+        }
+        data_type => {
+            return Err(Error::InvalidInput {
+                message: format!("{} is not a multivector type", data_type),
+            })
+        }
+    };
+    let vectors = vectors.as_fixed_size_list();
+    let dim = vectors.value_length() as usize;
+    let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+    let values = values.as_primitive::<Float32Type>().values();
+    Ok((0..column.len())
+        .map(|row| {
+            column.is_valid(row).then(|| {
+                // Vectors that are null are skipped
+                (offsets[row]..offsets[row + 1])
+                    .filter(|i| vectors.is_valid(*i))
+                    .flat_map(|i| values[i * dim..(i + 1) * dim].iter().copied())
+                    .collect()
+            })
+        })
+        .collect())
+}
+
+/// Score every row of a multivector column and take the nearest ones
+///
+/// The filter of the query is applied before scoring, so the search is exact.
+/// Rows without vectors are never returned.
+pub async fn flat_multivector_search(
+    dataset: &Dataset,
+    query: &VectorQuery,
+    column: &str,
+) -> Result<RecordBatch> {
+    let distance_type = query.distance_type.unwrap_or(DistanceType::Cosine);
+    if distance_type == DistanceType::Hamming {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "hamming distance is only supported on binary (uint8) vectors but '{}' is a multivector column of floats",
+                column
+            ),
+        });
+    }
+    let projection = match &query.base.select {
+        Select::All => dataset.schema().clone(),
+        Select::Columns(select) => dataset.schema().project(select)?,
+        Select::Dynamic(_) => {
+            return Err(Error::NotSupported {
+                message: "dynamic projections are not supported with multivector search"
+                    .to_string(),
+            })
+        }
+    };
+    let query_vectors = query
+        .query_vector
+        .iter()
+        .map(|v| v.as_primitive::<Float32Type>().values().as_ref())
+        .collect::<Vec<&[f32]>>();
+    let dim = query_vectors[0].len();
+
+    let mut scanner = dataset.scan();
+    scanner.project(&[column])?;
+    scanner.with_row_id();
+    if let Some(fragment_ids) = &query.base.fragments {
+        scan_fragments(&mut scanner, dataset, fragment_ids)?;
+    }
+    if let Some(filter) = &query.base.filter {
+        scanner.filter(filter)?;
+    }
+    let mut stream = scanner.try_into_stream().await?;
+    let mut scored = Vec::new();
+    while let Some(batch) = stream.try_next().await? {
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        let rows = row_vectors(&batch[column])?;
+        for (row_id, vectors) in row_ids.values().iter().zip(rows) {
+            if let Some(distance) = vectors
+                .and_then(|vectors| row_distance(distance_type, &query_vectors, &vectors, dim))
+            {
+                scored.push((*row_id, distance));
+            }
+        }
+    }
+    scored.sort_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then(a_id.cmp(b_id)));
+    scored.drain(..query.base.offset.unwrap_or(0).min(scored.len()));
+    scored.truncate(query.base.limit.unwrap_or(DEFAULT_TOP_K));
+
+    let row_ids = scored.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let rows = dataset.take_rows(&row_ids, &projection).await?;
+    let mut fields = rows.schema().fields().iter().cloned().collect::<Vec<_>>();
+    let mut arrays = rows.columns().to_vec();
+    if query.base.with_row_id {
+        fields.push(Arc::new(Field::new(ROW_ID, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids.clone())));
+    }
+    if query.base.with_row_address {
+        // Without stable row ids, which are not enabled, the row id is the address
+        fields.push(Arc::new(Field::new(ROW_ADDR, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids)));
+    }
+    fields.push(Arc::new(Field::new(DISTANCE, DataType::Float32, true)));
+    arrays.push(Arc::new(Float32Array::from_iter_values(
+        scored.iter().map(|(_, distance)| *distance),
+    )));
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_distance() {
+        let query: [&[f32]; 2] = [&[1.0, 0.0], &[0.0, 1.0]];
+        // Each query vector is matched with its nearest vector
+        let vectors = [1.0, 0.0, 0.0, 2.0, -1.0, 0.0];
+        let cosine = row_distance(DistanceType::Cosine, &query, &vectors, 2).unwrap();
+        assert!(cosine.abs() < 1e-6, "{}", cosine);
+        let l2 = row_distance(DistanceType::L2, &query, &vectors, 2).unwrap();
+        assert_eq!(l2, 1.0);
+        let dot = row_distance(DistanceType::Dot, &query, &vectors, 2).unwrap();
+        assert_eq!(dot, -1.0);
+
+        // A row with one vector matches every query vector with it
+        let cosine = row_distance(DistanceType::Cosine, &query, &[1.0, 1.0], 2).unwrap();
+        assert!((cosine - (2.0 - 2.0_f32.sqrt())).abs() < 1e-6, "{}", cosine);
+        assert_eq!(row_distance(DistanceType::L2, &query, &[], 2), None);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress reports of long running operations on a table
+
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, SchemaRef};
+
+/// The progress of a write, see [`super::AddDataBuilder::on_progress`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteProgress {
+    /// The number of batches written so far
+    pub batches: usize,
+    /// The number of rows written so far
+    pub rows: usize,
+    /// The size of the batches written so far, in memory
+    pub bytes: usize,
+}
+
+pub type WriteProgressCallback = Arc<dyn Fn(&WriteProgress) + Send + Sync>;
+
+/// The progress of building an index, see [`crate::index::IndexBuilder::on_progress`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexProgress {
+    /// The number of rows indexed so far
+    pub rows_indexed: usize,
+    /// The number of rows in the table
+    pub total_rows: usize,
+}
+
+pub type IndexProgressCallback = Arc<dyn Fn(&IndexProgress) + Send + Sync>;
+
+/// The progress of a compaction, see [`super::Table::optimize_with_progress`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionProgress {
+    /// The number of fragments compacted so far
+    pub fragments_compacted: usize,
+    /// The number of fragments that are compacted
+    pub total_fragments: usize,
+}
+
+pub type CompactionProgressCallback = Arc<dyn Fn(&CompactionProgress) + Send + Sync>;
+
+/// Reports the progress of a write as its batches are read
+pub(crate) struct ProgressReader {
+    source: Box<dyn RecordBatchReader + Send>,
+    progress: WriteProgress,
+    callback: WriteProgressCallback,
+}
+
+impl ProgressReader {
+    pub(crate) fn new(
+        source: Box<dyn RecordBatchReader + Send>,
+        callback: WriteProgressCallback,
+    ) -> Self {
+        Self {
+            source,
+            progress: WriteProgress::default(),
+            callback,
+        }
+    }
+}
+
+impl Iterator for ProgressReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.source.next()?;
+        if let Ok(batch) = &batch {
+            self.progress.batches += 1;
+            self.progress.rows += batch.num_rows();
+            self.progress.bytes += batch.get_array_memory_size();
+            (self.callback)(&self.progress);
+        }
+        Some(batch)
+    }
+}
+
+impl RecordBatchReader for ProgressReader {
+    fn schema(&self) -> SchemaRef {
+        self.source.schema()
+    }
+}
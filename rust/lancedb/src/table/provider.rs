@@ -0,0 +1,388 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Use a [`Table`] as a table of a DataFusion `SessionContext`
+
+use std::{any::Any, sync::Arc};
+
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    execution::context::SessionState,
+    logical_expr::{
+        expr::{Between, BinaryExpr, InList, Like},
+        Expr, Operator, TableProviderFilterPushDown,
+    },
+};
+use datafusion_common::{stats::Precision, DataFusionError, Statistics};
+use datafusion_physical_plan::{expressions::col, projection::ProjectionExec, ExecutionPlan};
+
+use super::Table;
+use crate::expr::literal_to_sql;
+use crate::query::{QueryBase, QueryExecutionOptions, Select};
+
+const ROW_ID: &str = "_rowid";
+
+/// A DataFusion [`TableProvider`] that reads a [`Table`], see [`Table::as_table_provider`]
+pub(crate) struct TableProviderAdapter {
+    table: Table,
+    schema: SchemaRef,
+    /// The number of rows when the provider was created
+    num_rows: usize,
+    /// Whether the schema ends with the row id column
+    with_row_id: bool,
+}
+
+impl TableProviderAdapter {
+    pub(crate) async fn try_new(table: Table) -> crate::Result<Self> {
+        let schema = table.schema().await?;
+        let num_rows = table.count_rows(None).await?;
+        Ok(Self {
+            table,
+            schema,
+            num_rows,
+            with_row_id: false,
+        })
+    }
+
+    /// Like [`Self::try_new`], with the row ids of the rows as a `_rowid` column
+    pub(crate) async fn try_new_with_row_id(table: Table) -> crate::Result<Self> {
+        let mut provider = Self::try_new(table).await?;
+        let mut fields = provider.schema.fields().to_vec();
+        fields.push(Arc::new(Field::new(ROW_ID, DataType::UInt64, false)));
+        provider.schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            provider.schema.metadata().clone(),
+        ));
+        provider.with_row_id = true;
+        Ok(provider)
+    }
+
+    /// Filters are only pushed down to local tables, the query of a remote table
+    /// is run as it is and filtered by DataFusion.
+    fn can_push_down(&self, filter: &Expr) -> bool {
+        self.table.as_native().is_some() && filter_to_sql(filter).is_some()
+    }
+}
+
+impl std::fmt::Debug for TableProviderAdapter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TableProviderAdapter")
+            .field("table", &self.table.to_string())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TableProvider for TableProviderAdapter {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion_common::Result<Arc<dyn ExecutionPlan>> {
+        let columns = match projection {
+            Some(projection) => projection
+                .iter()
+                .map(|i| self.schema.field(*i).name().clone())
+                .collect::<Vec<_>>(),
+            None => self
+                .schema
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect(),
+        };
+        let mut query = self.table.query();
+        let stored = columns
+            .iter()
+            .filter(|name| !(self.with_row_id && name.as_str() == ROW_ID))
+            .cloned()
+            .collect::<Vec<_>>();
+        if stored.len() < columns.len() {
+            query = query.with_row_id();
+        }
+        if stored.is_empty() {
+            // Nothing is read, but the rows still need to be counted
+            query = query.with_row_id().select(Select::Columns(Vec::new()));
+        } else {
+            query = query.select(Select::Columns(stored));
+        }
+        let filters = filters
+            .iter()
+            .filter(|filter| self.can_push_down(filter))
+            .filter_map(filter_to_sql)
+            .collect::<Vec<_>>();
+        if !filters.is_empty() {
+            query = query.only_if(filters.join(" AND "));
+        }
+        if self.table.as_native().is_some() {
+            if let Some(limit) = limit {
+                query = query.limit(limit);
+            }
+        }
+
+        let plan = self
+            .table
+            .inner
+            .create_plan(&query.into_vector(), QueryExecutionOptions::default())
+            .await
+            .map_err(|err| DataFusionError::External(Box::new(err)))?;
+        // Match the order of the projection, and drop the row id of empty projections
+        let schema = plan.schema();
+        let exprs = columns
+            .iter()
+            .map(|name| Ok((col(name, &schema)?, name.clone())))
+            .collect::<datafusion_common::Result<Vec<_>>>()?;
+        Ok(Arc::new(ProjectionExec::try_new(exprs, plan)?))
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> datafusion_common::Result<Vec<TableProviderFilterPushDown>> {
+        // The filters are rewritten as Lance filters, which might not treat every
+        // edge case the same way, so DataFusion applies them again
+        Ok(filters
+            .iter()
+            .map(|filter| {
+                if self.can_push_down(filter) {
+                    TableProviderFilterPushDown::Inexact
+                } else {
+                    TableProviderFilterPushDown::Unsupported
+                }
+            })
+            .collect())
+    }
+
+    fn statistics(&self) -> Option<Statistics> {
+        Some(Statistics {
+            num_rows: Precision::Inexact(self.num_rows),
+            total_byte_size: Precision::Absent,
+            column_statistics: Statistics::unknown_column(&self.schema),
+        })
+    }
+}
+
+/// Write a DataFusion filter as the SQL of a Lance filter
+///
+/// Returns None for the expressions that can't be written.
+fn filter_to_sql(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Column(column) if !column.name.contains('`') => Some(format!("`{}`", column.name)),
+        Expr::Literal(value) => literal_to_sql(value),
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let op = match op {
+                Operator::Eq => "=",
+                Operator::NotEq => "!=",
+                Operator::Lt => "<",
+                Operator::LtEq => "<=",
+                Operator::Gt => ">",
+                Operator::GtEq => ">=",
+                Operator::And => "AND",
+                Operator::Or => "OR",
+                Operator::Plus => "+",
+                Operator::Minus => "-",
+                Operator::Multiply => "*",
+                Operator::Divide => "/",
+                Operator::Modulo => "%",
+                _ => return None,
+            };
+            Some(format!(
+                "({} {} {})",
+                filter_to_sql(left)?,
+                op,
+                filter_to_sql(right)?
+            ))
+        }
+        Expr::Not(expr) => Some(format!("(NOT {})", filter_to_sql(expr)?)),
+        Expr::IsNull(expr) => Some(format!("({} IS NULL)", filter_to_sql(expr)?)),
+        Expr::IsNotNull(expr) => Some(format!("({} IS NOT NULL)", filter_to_sql(expr)?)),
+        Expr::Between(Between {
+            expr,
+            negated,
+            low,
+            high,
+        }) => Some(format!(
+            "({} {}BETWEEN {} AND {})",
+            filter_to_sql(expr)?,
+            if *negated { "NOT " } else { "" },
+            filter_to_sql(low)?,
+            filter_to_sql(high)?
+        )),
+        Expr::InList(InList {
+            expr,
+            list,
+            negated,
+        }) => Some(format!(
+            "({} {}IN ({}))",
+            filter_to_sql(expr)?,
+            if *negated { "NOT " } else { "" },
+            list.iter()
+                .map(filter_to_sql)
+                .collect::<Option<Vec<_>>>()?
+                .join(", ")
+        )),
+        Expr::Like(Like {
+            negated,
+            expr,
+            pattern,
+            escape_char: None,
+            case_insensitive: false,
+        }) => Some(format!(
+            "({} {}LIKE {})",
+            filter_to_sql(expr)?,
+            if *negated { "NOT " } else { "" },
+            filter_to_sql(pattern)?
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::compute::concat_batches;
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::{
+        datasource::MemTable,
+        logical_expr::{col, lit},
+        prelude::SessionContext,
+    };
+
+    use super::*;
+    use crate::connect;
+
+    #[test]
+    fn test_filter_to_sql() {
+        let filter = col("id").gt(lit(5)).and(col("name").eq(lit("it's")));
+        assert_eq!(
+            filter_to_sql(&filter).unwrap(),
+            "((`id` > 5) AND (`name` = 'it''s'))"
+        );
+        let filter = col("id").in_list(vec![lit(1), lit(2)], true);
+        assert_eq!(filter_to_sql(&filter).unwrap(), "(`id` NOT IN (1, 2))");
+        let filter = col("name").is_null().or(col("name").like(lit("a%")));
+        assert_eq!(
+            filter_to_sql(&filter).unwrap(),
+            "((`name` IS NULL) OR (`name` LIKE 'a%'))"
+        );
+        assert!(filter_to_sql(&col("name").ilike(lit("a%"))).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_table_provider() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("category", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| ["a", "b", "c"][i % 3]),
+                )),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "items",
+                Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let names_schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Utf8, false),
+            Field::new("label", DataType::Utf8, false),
+        ]));
+        let names = RecordBatch::try_new(
+            names_schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b"])),
+                Arc::new(StringArray::from(vec!["apples", "bananas"])),
+            ],
+        )
+        .unwrap();
+
+        let ctx = SessionContext::new();
+        let provider = table.as_table_provider().await.unwrap();
+        assert_eq!(
+            provider.statistics().unwrap().num_rows,
+            Precision::Inexact(100)
+        );
+        ctx.register_table("items", provider).unwrap();
+        ctx.register_table(
+            "names",
+            Arc::new(MemTable::try_new(names_schema, vec![vec![names]]).unwrap()),
+        )
+        .unwrap();
+
+        let collect = |sql: &'static str| {
+            let ctx = ctx.clone();
+            async move {
+                let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+                concat_batches(&batches[0].schema(), &batches).unwrap()
+            }
+        };
+
+        let results = collect(
+            "SELECT items.id, names.label FROM items \
+             JOIN names ON items.category = names.category \
+             WHERE items.id < 6 ORDER BY items.id",
+        )
+        .await;
+        assert_eq!(
+            results["id"].as_ref(),
+            &Int32Array::from(vec![0, 1, 3, 4]) as &dyn arrow_array::Array
+        );
+        assert_eq!(
+            results["label"].as_ref(),
+            &StringArray::from(vec!["apples", "bananas", "apples", "bananas"])
+                as &dyn arrow_array::Array
+        );
+
+        let results = collect("SELECT category, id FROM items LIMIT 5").await;
+        assert_eq!(results.num_rows(), 5);
+        assert_eq!(results.schema().field(0).name(), "category");
+
+        let results = collect("SELECT COUNT(*) AS n FROM items WHERE category = 'c'").await;
+        assert_eq!(
+            results["n"].as_ref(),
+            &arrow_array::Int64Array::from(vec![33]) as &dyn arrow_array::Array
+        );
+    }
+}
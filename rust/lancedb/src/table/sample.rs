@@ -0,0 +1,111 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A uniform random sample of the rows of a table
+
+use std::sync::Arc;
+
+use arrow::array::AsArray;
+use arrow_array::{types::UInt64Type, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lance::dataset::{Dataset, ROW_ID};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    error::{Error, Result},
+    query::{Query, Sample, SampleSize, Select, ROW_ADDR},
+};
+
+/// Choose a sample of the rows that match the filter of the query and read them
+///
+/// Only the row ids (and the filter columns) are scanned to make the choice.  A
+/// sample of a number of rows is chosen with reservoir sampling, so the memory
+/// used depends on the size of the sample and not on the size of the table.
+pub async fn sample_rows(dataset: &Dataset, query: &Query, sample: &Sample) -> Result<RecordBatch> {
+    if let SampleSize::Fraction(fraction) = sample.size {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the sample fraction must be between 0 and 1, got {}",
+                    fraction
+                ),
+            });
+        }
+    }
+    let projection = match &query.select {
+        Select::All => dataset.schema().clone(),
+        Select::Columns(select) => dataset.schema().project(select)?,
+        Select::Dynamic(_) => {
+            return Err(Error::NotSupported {
+                message: "dynamic projections are not supported with sampling".to_string(),
+            })
+        }
+    };
+    let mut rng = match sample.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut scanner = dataset.scan();
+    scanner.project::<&str>(&[])?;
+    scanner.with_row_id();
+    if let Some(filter) = &query.filter {
+        scanner.filter(filter)?;
+    }
+    let mut stream = scanner.try_into_stream().await?;
+    let mut row_ids = Vec::new();
+    let mut num_rows = 0;
+    while let Some(batch) = stream.try_next().await? {
+        for row_id in batch[ROW_ID].as_primitive::<UInt64Type>().values().iter() {
+            match sample.size {
+                SampleSize::Fraction(fraction) => {
+                    if rng.gen_bool(fraction) {
+                        row_ids.push(*row_id);
+                    }
+                }
+                SampleSize::Rows(n) => {
+                    if row_ids.len() < n {
+                        row_ids.push(*row_id);
+                    } else {
+                        let i = rng.gen_range(0..=num_rows);
+                        if i < n {
+                            row_ids[i] = *row_id;
+                        }
+                    }
+                }
+            }
+            num_rows += 1;
+        }
+    }
+    row_ids.sort_unstable();
+    row_ids.drain(..query.offset.unwrap_or(0).min(row_ids.len()));
+    if let Some(limit) = query.limit {
+        row_ids.truncate(limit);
+    }
+
+    let rows = dataset.take_rows(&row_ids, &projection).await?;
+    let mut fields = rows.schema().fields().iter().cloned().collect::<Vec<_>>();
+    let mut arrays = rows.columns().to_vec();
+    if query.with_row_id {
+        fields.push(Arc::new(Field::new(ROW_ID, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids.clone())));
+    }
+    if query.with_row_address {
+        // Without stable row ids, which are not enabled, the row id is the address
+        fields.push(Arc::new(Field::new(ROW_ADDR, DataType::UInt64, false)));
+        arrays.push(Arc::new(UInt64Array::from(row_ids)));
+    }
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
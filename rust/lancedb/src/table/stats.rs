@@ -0,0 +1,157 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics about the storage of a table
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::TableInternal;
+use crate::Result;
+
+/// Fragments with fewer rows than this are considered small, compacting them
+/// (see [`super::OptimizeAction::Compact`]) will improve performance
+pub const SMALL_FRAGMENT_ROWS: usize = 100_000;
+
+/// Statistics about a table, see [`super::Table::stats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    /// The version of the table the statistics are for
+    pub version: u64,
+    /// When the version was created, i.e. the time of the latest write
+    pub last_write: DateTime<Utc>,
+    /// The number of bytes the table takes up in storage, including old versions
+    pub total_bytes: u64,
+    /// The number of rows in the table
+    pub num_rows: usize,
+    /// The number of rows that have been deleted but still take up space in the
+    /// fragments that contain them
+    pub num_deleted_rows: usize,
+    /// Statistics about the fragments, the files, the table is made of
+    pub fragment_stats: FragmentStatistics,
+    /// How many of the rows of the table each index covers
+    pub index_coverage: Vec<IndexCoverage>,
+}
+
+/// Statistics about the fragments of a table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FragmentStatistics {
+    /// The number of fragments
+    pub num_fragments: usize,
+    /// The number of fragments with fewer than [`SMALL_FRAGMENT_ROWS`] rows
+    pub num_small_fragments: usize,
+    /// The distribution of the number of rows in the fragments
+    pub lengths: FragmentSummaryStats,
+}
+
+/// The distribution of the number of rows in the fragments of a table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FragmentSummaryStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: usize,
+    pub p25: usize,
+    pub p50: usize,
+    pub p75: usize,
+    pub p99: usize,
+}
+
+/// How many of the rows of a table an index covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexCoverage {
+    /// The name of the index
+    pub name: String,
+    /// The columns of the index
+    pub columns: Vec<String>,
+    /// The number of rows in the index
+    pub num_indexed_rows: usize,
+    /// The number of rows that have been added since the index was built
+    pub num_unindexed_rows: usize,
+}
+
+impl FragmentStatistics {
+    /// Summarize the number of rows in each fragment
+    pub(crate) fn from_lengths(mut lengths: Vec<usize>) -> Self {
+        if lengths.is_empty() {
+            return Self::default();
+        }
+        lengths.sort_unstable();
+        // Nearest rank percentiles
+        let percentile = |p: f64| {
+            let rank = (p * lengths.len() as f64).ceil() as usize;
+            lengths[rank.clamp(1, lengths.len()) - 1]
+        };
+        Self {
+            num_fragments: lengths.len(),
+            num_small_fragments: lengths
+                .iter()
+                .filter(|len| **len < SMALL_FRAGMENT_ROWS)
+                .count(),
+            lengths: FragmentSummaryStats {
+                min: lengths[0],
+                max: lengths[lengths.len() - 1],
+                mean: lengths.iter().sum::<usize>() / lengths.len(),
+                p25: percentile(0.25),
+                p50: percentile(0.5),
+                p75: percentile(0.75),
+                p99: percentile(0.99),
+            },
+        }
+    }
+}
+
+/// The coverage of each index of a table
+pub(crate) async fn index_coverage(table: &dyn TableInternal) -> Result<Vec<IndexCoverage>> {
+    let mut coverage = Vec::new();
+    for index in table.list_indices().await? {
+        // The index may have been dropped since it was listed
+        if let Some(stats) = table.index_stats(&index.name).await? {
+            coverage.push(IndexCoverage {
+                name: index.name,
+                columns: index.columns,
+                num_indexed_rows: stats.num_indexed_rows,
+                num_unindexed_rows: stats.num_unindexed_rows,
+            });
+        }
+    }
+    Ok(coverage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fragment_statistics() {
+        assert_eq!(
+            FragmentStatistics::from_lengths(vec![]),
+            FragmentStatistics::default()
+        );
+        let stats = FragmentStatistics::from_lengths(vec![200_000, 10, 40, 30, 20]);
+        assert_eq!(stats.num_fragments, 5);
+        assert_eq!(stats.num_small_fragments, 4);
+        assert_eq!(
+            stats.lengths,
+            FragmentSummaryStats {
+                min: 10,
+                max: 200_000,
+                mean: 40_020,
+                p25: 20,
+                p50: 30,
+                p75: 40,
+                p99: 200_000,
+            }
+        );
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::Result;
+
+use super::TableInternal;
+
+/// The tags of a table, names that refer to versions of the table
+///
+/// A tag keeps referring to the same version until it is updated, so a version can
+/// be checked out by name with [`super::Table::checkout_tag`].
+///
+/// See [`super::Table::tags`]
+#[derive(Debug, Clone)]
+pub struct Tags {
+    table: Arc<dyn TableInternal>,
+}
+
+impl Tags {
+    pub(super) fn new(table: Arc<dyn TableInternal>) -> Self {
+        Self { table }
+    }
+
+    /// The tags of the table and the versions they refer to
+    pub async fn list(&self) -> Result<HashMap<String, u64>> {
+        self.table.list_tags().await
+    }
+
+    /// Create a tag that refers to a version
+    ///
+    /// This fails if the tag already exists, use [`Self::update`] to change the
+    /// version a tag refers to.
+    pub async fn create(&self, name: &str, version: u64) -> Result<()> {
+        self.table.create_tag(name, version).await
+    }
+
+    /// Change the version an existing tag refers to
+    pub async fn update(&self, name: &str, version: u64) -> Result<()> {
+        self.table.update_tag(name, version).await
+    }
+
+    /// Delete a tag
+    ///
+    /// The version the tag refers to is not affected.
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        self.table.delete_tag(name).await
+    }
+}
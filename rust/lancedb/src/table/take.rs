@@ -0,0 +1,280 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fetching and deleting rows by their row id, or fetching them by the value of a
+//! key column
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::concat_batches;
+use arrow_array::{new_null_array, Array, RecordBatch, RecordBatchOptions, UInt32Array};
+use arrow_schema::{Field, Schema, SchemaRef};
+use datafusion_common::ScalarValue;
+use futures::TryStreamExt;
+use lance::dataset::Dataset;
+use lance::table::format::Fragment;
+
+use super::Table;
+use crate::error::{Error, Result};
+use crate::expr::literal_to_sql;
+use crate::query::{ExecutableQuery, QueryBase, Select};
+
+/// What to return for the rows of a take that don't exist, see [`Table::take`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingRows {
+    /// Return an error if any of the rows doesn't exist
+    #[default]
+    Error,
+    /// Return a row of nulls in place of each row that doesn't exist
+    ///
+    /// All the columns of the result are nullable, even those of the table that aren't.
+    Null,
+}
+
+/// Read the rows with `row_ids` from `dataset`, in the order of `row_ids`
+///
+/// The rows are read with the take path of the dataset.  The deletion vectors of
+/// the fragments are checked first, since a take of a deleted row would return it.
+pub(crate) async fn take_rows(
+    dataset: &Dataset,
+    row_ids: &[u64],
+    columns: Option<&[String]>,
+    missing: MissingRows,
+) -> Result<RecordBatch> {
+    let projection = match columns {
+        Some(columns) => dataset.schema().project(columns)?,
+        None => dataset.schema().clone(),
+    };
+
+    let mut unique = row_ids.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    let existing = existing_row_ids(dataset, &unique).await?;
+
+    let indices = row_ids
+        .iter()
+        .map(|row_id| {
+            existing
+                .binary_search(row_id)
+                .ok()
+                .map(|index| index as u32)
+        })
+        .collect::<UInt32Array>();
+    if let (MissingRows::Error, Some(position)) =
+        (missing, indices.iter().position(|i| i.is_none()))
+    {
+        let row_id = row_ids[position];
+        return Err(Error::InvalidInput {
+            message: format!("row id {} does not exist, it may have been deleted", row_id),
+        });
+    }
+
+    let rows = if existing.is_empty() {
+        RecordBatch::new_empty(Arc::new(Schema::from(&projection)))
+    } else {
+        dataset.take_rows(&existing, &projection).await?
+    };
+    reorder(&rows, &indices)
+}
+
+/// The ids of `row_ids`, which must be sorted, of rows that exist in `dataset`
+///
+/// A row doesn't exist if its fragment doesn't, if its offset is past the end of the
+/// fragment or if it's in the deletion vector of the fragment.
+pub(crate) async fn existing_row_ids(dataset: &Dataset, row_ids: &[u64]) -> Result<Vec<u64>> {
+    // The ids are sorted, so the fragments are visited in turn
+    let mut existing = Vec::with_capacity(row_ids.len());
+    let mut fragment = None;
+    for &row_id in row_ids {
+        let fragment_id = row_id >> 32;
+        let offset = (row_id & 0xFFFF_FFFF) as u32;
+        if fragment.as_ref().map(|(id, _, _)| *id) != Some(fragment_id) {
+            fragment = Some(match dataset.get_fragment(fragment_id as usize) {
+                Some(file_fragment) => (
+                    fragment_id,
+                    file_fragment.physical_rows().await?,
+                    file_fragment.get_deletion_vector().await?,
+                ),
+                None => (fragment_id, 0, None),
+            });
+        }
+        let (_, num_rows, deletion_vector) = fragment.as_ref().expect("fragment was just loaded");
+        let deleted = deletion_vector
+            .as_ref()
+            .is_some_and(|deletion_vector| deletion_vector.contains(offset));
+        if (offset as usize) < *num_rows && !deleted {
+            existing.push(row_id);
+        }
+    }
+    Ok(existing)
+}
+
+/// Delete the rows with `row_ids`, which must exist and be sorted, with deletion files
+///
+/// The ids are grouped by fragment, so every fragment gets one new deletion file.
+/// Returns the fragments that still have rows, with their new deletion files, and
+/// the ids of the fragments whose rows are all deleted.  The rows are only deleted
+/// once the fragments are committed, e.g. with an `Operation::Update`.
+pub(crate) async fn delete_row_ids(
+    dataset: &Dataset,
+    row_ids: &[u64],
+) -> Result<(Vec<Fragment>, Vec<u64>)> {
+    let mut updated = Vec::new();
+    let mut removed = Vec::new();
+    let mut rest = row_ids;
+    while let Some(first) = rest.first() {
+        let fragment_id = first >> 32;
+        let (ids, next) = rest.split_at(rest.partition_point(|row_id| row_id >> 32 == fragment_id));
+        rest = next;
+        let fragment =
+            dataset
+                .get_fragment(fragment_id as usize)
+                .ok_or_else(|| Error::Runtime {
+                    message: format!("fragment {} of row {} does not exist", fragment_id, first),
+                })?;
+        let offsets = ids.iter().map(|row_id| (row_id & 0xFFFF_FFFF) as u32);
+        match fragment.extend_deletions(offsets).await? {
+            Some(fragment) => updated.push(fragment.metadata().clone()),
+            None => removed.push(fragment_id),
+        }
+    }
+    Ok((updated, removed))
+}
+
+/// A filter that matches the rows with `row_ids`
+pub(crate) fn row_ids_filter(row_ids: &[u64]) -> String {
+    let row_ids = row_ids
+        .iter()
+        .map(|row_id| row_id.to_string())
+        .collect::<Vec<_>>();
+    format!("_rowid IN ({})", row_ids.join(", "))
+}
+
+/// Read the rows of `table` whose `key_column` has one of `values`, in the order
+/// of `values`
+///
+/// This is a filtered query, so it uses a scalar index on the key column if there
+/// is one.  If several rows have the same key, the first one read is returned.
+pub(crate) async fn take_by_key(
+    table: &Table,
+    key_column: &str,
+    values: &dyn Array,
+    columns: Option<&[&str]>,
+    missing: MissingRows,
+) -> Result<RecordBatch> {
+    let schema = table.schema().await?;
+    let key_type = schema.field_with_name(key_column)?.data_type();
+    let values = arrow_cast::cast(values, key_type)?;
+    let columns = match columns {
+        Some(columns) => columns.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        None => schema.fields().iter().map(|f| f.name().clone()).collect(),
+    };
+
+    let mut keys = Vec::with_capacity(values.len());
+    let mut literals = Vec::with_capacity(values.len());
+    for i in 0..values.len() {
+        let key = ScalarValue::try_from_array(&values, i)?;
+        literals.push(literal_to_sql(&key).ok_or_else(|| Error::NotSupported {
+            message: format!("taking rows by keys of type {}", key_type),
+        })?);
+        keys.push(key);
+    }
+
+    let rows = if values.is_empty() {
+        RecordBatch::new_empty(Arc::new(
+            schema.project(
+                &columns
+                    .iter()
+                    .map(|c| schema.index_of(c))
+                    .collect::<std::result::Result<Vec<_>, _>>()?,
+            )?,
+        ))
+    } else {
+        let mut select = columns.clone();
+        if !select.iter().any(|c| c == key_column) {
+            select.push(key_column.to_string());
+        }
+        let stream = table
+            .query()
+            .only_if(format!("{} IN ({})", key_column, literals.join(", ")))
+            .select(Select::columns(&select))
+            .execute()
+            .await?;
+        let result_schema = stream.schema();
+        let batches = stream.try_collect::<Vec<_>>().await?;
+        let rows = concat_batches(&result_schema, &batches)?;
+
+        let mut positions = HashMap::with_capacity(rows.num_rows());
+        let found = rows
+            .column_by_name(key_column)
+            .expect("key column is selected");
+        for i in 0..rows.num_rows() {
+            positions
+                .entry(ScalarValue::try_from_array(found, i)?)
+                .or_insert(i as u32);
+        }
+        let indices = keys
+            .iter()
+            .map(|key| positions.get(key).copied())
+            .collect::<UInt32Array>();
+        if let (MissingRows::Error, Some(position)) =
+            (missing, indices.iter().position(|i| i.is_none()))
+        {
+            let key = &keys[position];
+            return Err(Error::InvalidInput {
+                message: format!("no row has {} = {}", key_column, key),
+            });
+        }
+        let rows = reorder(&rows, &indices)?;
+        rows.project(
+            &columns
+                .iter()
+                .map(|c| rows.schema().index_of(c))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+        )?
+    };
+    Ok(rows)
+}
+
+/// Take the rows at `indices` from `rows`, a null index gives a row of nulls
+fn reorder(rows: &RecordBatch, indices: &UInt32Array) -> Result<RecordBatch> {
+    if indices.null_count() == 0 {
+        return Ok(arrow::compute::take_record_batch(rows, indices)?);
+    }
+    let schema: SchemaRef = Arc::new(Schema::new_with_metadata(
+        rows.schema()
+            .fields()
+            .iter()
+            .map(|field| Field::clone(field).with_nullable(true))
+            .collect::<Vec<_>>(),
+        rows.schema().metadata().clone(),
+    ));
+    let columns = rows
+        .columns()
+        .iter()
+        .map(|column| {
+            if rows.num_rows() == 0 {
+                Ok(new_null_array(column.data_type(), indices.len()))
+            } else {
+                arrow::compute::take(column, indices, None)
+            }
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new_with_options(
+        schema,
+        columns,
+        &RecordBatchOptions::new().with_row_count(Some(indices.len())),
+    )?)
+}
@@ -0,0 +1,588 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Several writes that are committed as one version, see [`NativeTable::begin`]
+
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::Schema;
+use futures::TryStreamExt;
+use lance::dataset::fragment::FileFragment;
+use lance::dataset::transaction::Operation;
+use lance::dataset::{write_fragments, Dataset, WriteMode, WriteParams};
+use lance_datafusion::planner::Planner;
+
+use crate::arrow::IntoArrow;
+use crate::error::{Error, Result};
+
+use super::{
+    CoercingReader, Coercion, ColumnKind, NativeTable, TableInternal, ValidationReport, Validator,
+};
+
+/// A write of a [`Transaction`]
+enum Write {
+    Delete(String),
+    Update {
+        filter: Option<String>,
+        columns: Vec<(String, String)>,
+    },
+    Add(Box<dyn RecordBatchReader + Send>),
+}
+
+/// Deletes, updates and appends to a [`NativeTable`] that are committed as one
+/// version of the table
+///
+/// Readers see the table either before or after all of the writes, never in
+/// between, and nothing is written if the transaction fails.  Create one with
+/// [`NativeTable::begin`], add writes to it and then [`Self::commit`] it.
+///
+/// ```no_run
+/// # use lancedb::table::NativeTable;
+/// # use arrow_array::RecordBatchReader;
+/// # async fn doctest_helper(table: NativeTable, new_rows: impl RecordBatchReader + Send + 'static) {
+/// table
+///     .begin()
+///     .delete("id IN (1, 2, 3)")
+///     .add(new_rows)
+///     .commit()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+///
+/// The writes are applied in the order they were added, to the rows of the table
+/// when the transaction is committed.  Rows added by the transaction are not
+/// affected by its deletes and updates.  Like [`crate::Table::update`], an update
+/// deletes the rows it updates and adds their new values as new rows.
+///
+/// Only these writes can be part of a transaction, other operations, such as
+/// creating an index, are committed on their own.  Embeddings are not computed
+/// for the data of a transaction, so it must include the embedding columns, and
+/// the source columns of embeddings can't be updated.
+///
+/// A transaction isn't retried.  If a concurrent write changed the rows that the
+/// transaction deletes or updates, the commit fails with [`Error::CommitConflict`]
+/// and none of the writes are applied.  Begin a new transaction to apply them
+/// against the latest version of the table.
+pub struct Transaction {
+    table: NativeTable,
+    writes: Vec<Write>,
+    /// The first error of the data passed to [`Self::add`]
+    error: Option<Error>,
+}
+
+impl Transaction {
+    pub(super) fn new(table: NativeTable) -> Self {
+        Self {
+            table,
+            writes: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Delete the rows that match `predicate`, see [`crate::Table::delete`]
+    pub fn delete(mut self, predicate: impl Into<String>) -> Self {
+        self.writes.push(Write::Delete(predicate.into()));
+        self
+    }
+
+    /// Set `columns` to the values of SQL expressions, for the rows that match
+    /// `filter` or for every row, see [`crate::Table::update`]
+    ///
+    /// The expressions are evaluated against the values of the row before the
+    /// update.
+    pub fn update(mut self, columns: &[(&str, &str)], filter: Option<&str>) -> Self {
+        self.writes.push(Write::Update {
+            filter: filter.map(|filter| filter.to_string()),
+            columns: columns
+                .iter()
+                .map(|(column, expr)| (column.to_string(), expr.to_string()))
+                .collect(),
+        });
+        self
+    }
+
+    /// Append rows to the table, see [`crate::Table::add`]
+    pub fn add<T: IntoArrow>(mut self, data: T) -> Self {
+        match data.into_arrow() {
+            Ok(data) => self.writes.push(Write::Add(data)),
+            Err(err) => {
+                self.error.get_or_insert(err);
+            }
+        }
+        self
+    }
+
+    /// Apply the writes and commit them as one new version of the table
+    ///
+    /// The writes are checked against the schema of the table before any data is
+    /// written.  A transaction without writes doesn't create a version.
+    pub async fn commit(self) -> Result<()> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let table = self.table;
+        table.dataset.ensure_mutable().await?;
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+        table.dataset.reload().await?;
+        let dataset = table.dataset.get().await?.clone();
+        let schema = Arc::new(Schema::from(dataset.schema()));
+        let table_definition = table.table_definition().await?;
+
+        let planner = Planner::new(schema.clone());
+        for write in &self.writes {
+            match write {
+                Write::Add(data) => {
+                    ValidationReport {
+                        issues: Validator::new(&table_definition).check_schema(&data.schema()),
+                        ..Default::default()
+                    }
+                    .into_result()?;
+                    // Rather than writing rows without their embeddings
+                    for column in &table_definition.column_definitions {
+                        if let ColumnKind::Embedding(definition) = &column.kind {
+                            let dest_column = definition.dest_column_name();
+                            if data.schema().column_with_name(&dest_column).is_none() {
+                                return Err(Error::InvalidInput {
+                                    message: format!(
+                                        "the data added by a transaction must include the embedding column '{}', embeddings are not computed for transactions",
+                                        dest_column
+                                    ),
+                                });
+                            }
+                        }
+                    }
+                }
+                Write::Update { columns, .. } if columns.is_empty() => {
+                    return Err(Error::InvalidInput {
+                        message: "at least one column must be specified in an update operation"
+                            .to_string(),
+                    });
+                }
+                Write::Update { columns, .. } => {
+                    NativeTable::check_update_embeddings(
+                        &table_definition,
+                        columns.iter().map(|(column, _)| column.as_str()),
+                    )?;
+                    for (column, expr) in columns {
+                        if schema.column_with_name(column).is_none() {
+                            return Err(Error::InvalidInput {
+                                message: format!("column '{}' does not exist in the table", column),
+                            });
+                        }
+                        planner
+                            .parse_expr(expr)
+                            .and_then(|expr| planner.optimize_expr(expr))
+                            .and_then(|expr| planner.create_physical_expr(&expr))
+                            .map_err(|err| Error::InvalidInput {
+                                message: format!(
+                                    "invalid expression '{}' for column '{}': {}",
+                                    expr, column, err
+                                ),
+                            })?;
+                    }
+                }
+                Write::Delete(_) => {}
+            }
+        }
+
+        // The deletes are written as deletion files of the fragments, which only
+        // take effect once the version that refers to them is committed
+        let mut fragments = dataset
+            .get_fragments()
+            .into_iter()
+            .map(|fragment| (fragment.id() as u64, Some(fragment), false))
+            .collect::<Vec<_>>();
+        let mut new_data: Vec<Box<dyn RecordBatchReader + Send>> = Vec::new();
+        for write in self.writes {
+            match write {
+                Write::Delete(predicate) => delete(&mut fragments, &predicate).await?,
+                Write::Update { filter, columns } => {
+                    let live = fragments
+                        .iter()
+                        .filter_map(|(_, fragment, _)| fragment.as_ref())
+                        .map(|fragment| fragment.metadata().clone())
+                        .collect::<Vec<_>>();
+                    let mut updated = Vec::new();
+                    if !live.is_empty() {
+                        let mut scanner = dataset.scan();
+                        scanner.with_fragments(live);
+                        if let Some(filter) = &filter {
+                            scanner.filter(filter)?;
+                        }
+                        let mut stream = scanner.try_into_stream().await?;
+                        while let Some(batch) = stream.try_next().await? {
+                            updated.push(update_batch(&batch, &columns)?);
+                        }
+                    }
+                    if !updated.is_empty() {
+                        new_data.push(Box::new(RecordBatchIterator::new(
+                            updated.into_iter().map(Ok),
+                            schema.clone(),
+                        )));
+                    }
+                    delete(&mut fragments, filter.as_deref().unwrap_or("true")).await?;
+                }
+                Write::Add(data) => {
+                    new_data.push(Box::new(CoercingReader::new(
+                        data,
+                        &schema,
+                        Coercion::Vectors,
+                    )));
+                }
+            }
+        }
+
+        let params = table.patch_write_params(WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        })?;
+        let mut new_fragments = Vec::new();
+        for data in new_data {
+            new_fragments.extend(write_fragments(&table.uri, data, params.clone()).await?);
+        }
+        let mut removed_fragment_ids = Vec::new();
+        let mut updated_fragments = Vec::new();
+        for (id, fragment, changed) in fragments {
+            match fragment {
+                None => removed_fragment_ids.push(id),
+                Some(fragment) if changed => updated_fragments.push(fragment.metadata().clone()),
+                Some(_) => {}
+            }
+        }
+        if removed_fragment_ids.is_empty()
+            && updated_fragments.is_empty()
+            && new_fragments.is_empty()
+        {
+            return Ok(());
+        }
+
+        let operation = Operation::Update {
+            removed_fragment_ids,
+            updated_fragments,
+            new_fragments,
+        };
+        match Dataset::commit(
+            &table.uri,
+            operation,
+            Some(dataset.version().version),
+            params.store_params,
+            None,
+        )
+        .await
+        {
+            Ok(new_dataset) => {
+                table.dataset.set_latest(new_dataset).await;
+                Ok(())
+            }
+            Err(err @ lance::Error::CommitConflict { .. }) => Err(Error::CommitConflict {
+                message: format!(
+                    "the transaction conflicted with a concurrent write and was not applied, begin a new transaction to apply it to the latest version: {}",
+                    err
+                ),
+            }),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Delete the rows that match `predicate` from the fragments that are left
+///
+/// A fragment is None once all of its rows are deleted.  The flag is set when the
+/// fragment has changed.
+async fn delete(
+    fragments: &mut [(u64, Option<FileFragment>, bool)],
+    predicate: &str,
+) -> Result<()> {
+    for (_, fragment, changed) in fragments.iter_mut() {
+        let Some(current) = fragment.take() else {
+            continue;
+        };
+        let metadata = current.metadata().clone();
+        *fragment = current.delete(predicate).await?;
+        *changed |= fragment
+            .as_ref()
+            .map_or(true, |fragment| fragment.metadata() != &metadata);
+    }
+    Ok(())
+}
+
+/// Evaluate the expressions of an update against a batch of the rows it updates
+fn update_batch(batch: &RecordBatch, columns: &[(String, String)]) -> Result<RecordBatch> {
+    let schema = batch.schema();
+    let planner = Planner::new(schema.clone());
+    let mut arrays = batch.columns().to_vec();
+    for (column, sql) in columns {
+        let index = schema.index_of(column)?;
+        let expr = planner.parse_expr(sql)?;
+        let expr = planner.create_physical_expr(&planner.optimize_expr(expr)?)?;
+        let values = expr.evaluate(batch)?.into_array(batch.num_rows())?;
+        arrays[index] = arrow_cast::cast(&values, schema.field(index).data_type())?;
+    }
+    Ok(RecordBatch::try_new(schema, arrays)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::AsArray;
+    use arrow_array::{types::Int32Type, Int32Array};
+    use arrow_schema::{DataType, Field};
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batch(ids: std::ops::Range<i32>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("views", DataType::Int32, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids.clone())),
+                Arc::new(Int32Array::from_iter_values(ids.map(|id| id * 10))),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn reader(batch: RecordBatch) -> impl RecordBatchReader + Send + 'static {
+        let schema = batch.schema();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("test", reader(batch(0..10)))
+            .execute()
+            .await
+            .unwrap();
+        let native = table.as_native().unwrap();
+        let version = table.version().await.unwrap();
+
+        native
+            .begin()
+            .delete("id < 3")
+            .update(&[("views", "views + 1")], Some("id >= 8"))
+            .add(reader(batch(10..15)))
+            .commit()
+            .await
+            .unwrap();
+        // The writes are committed as one version
+        assert_eq!(table.version().await.unwrap(), version + 1);
+        assert_eq!(table.count_rows(None).await.unwrap(), 12);
+        assert_eq!(
+            table.count_rows(Some("id < 3".to_string())).await.unwrap(),
+            0
+        );
+        let updated = table
+            .query()
+            .only_if("id >= 8 AND id < 10")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut views = updated
+            .iter()
+            .flat_map(|batch| batch["views"].as_primitive::<Int32Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        views.sort();
+        assert_eq!(views, vec![81, 91]);
+
+        // Later writes see the rows of earlier writes, but not the added rows
+        native
+            .begin()
+            .add(reader(batch(20..25)))
+            .delete("id >= 8")
+            .update(&[("views", "0")], Some("id >= 8"))
+            .commit()
+            .await
+            .unwrap();
+        assert_eq!(table.version().await.unwrap(), version + 2);
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        assert_eq!(
+            table
+                .count_rows(Some("views = 0".to_string()))
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Nothing to do, nothing to commit
+        native.begin().commit().await.unwrap();
+        native.begin().delete("id > 100").commit().await.unwrap();
+        assert_eq!(table.version().await.unwrap(), version + 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_transaction() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("test", reader(batch(0..10)))
+            .execute()
+            .await
+            .unwrap();
+        let native = table.as_native().unwrap();
+        let version = table.version().await.unwrap();
+
+        let err = native
+            .begin()
+            .delete("id < 3")
+            .update(&[("nope", "1")], None)
+            .commit()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+        let err = native
+            .begin()
+            .update(&[("views", "nope + 1")], None)
+            .commit()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+
+        let other = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new(
+                "other",
+                DataType::Int32,
+                false,
+            )])),
+            vec![Arc::new(Int32Array::from(vec![1]))],
+        )
+        .unwrap();
+        let err = native
+            .begin()
+            .delete("id < 3")
+            .add(reader(other))
+            .commit()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{}", err);
+
+        // Nothing was applied
+        assert_eq!(table.version().await.unwrap(), version);
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+    }
+    #[tokio::test]
+    async fn test_transaction_embeddings() {
+        use arrow_array::{types::Float32Type, FixedSizeListArray, StringArray};
+
+        use crate::embeddings::EmbeddingDefinition;
+        use crate::table::TableDefinition;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let vector_type =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, true),
+            Field::new("vector", vector_type, true),
+        ]));
+        let mut column_definitions =
+            TableDefinition::new_from_schema(schema.clone()).column_definitions;
+        column_definitions[2].kind =
+            ColumnKind::Embedding(EmbeddingDefinition::new("text", "mock", Some("vector")));
+        let table = db
+            .create_empty_table(
+                "test",
+                TableDefinition::new(schema.clone(), column_definitions).into_rich_schema(),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let native = table.as_native().unwrap();
+
+        // The data must include the embeddings, they aren't computed
+        let text_only = RecordBatch::try_new(
+            Arc::new(schema.project(&[0, 1]).unwrap()),
+            vec![
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["hello"])),
+            ],
+        )
+        .unwrap();
+        let err = native
+            .begin()
+            .add(reader(text_only))
+            .commit()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("'vector'")),
+            "{}",
+            err
+        );
+
+        let with_vectors = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["hello"])),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        vec![Some(vec![Some(1.0), Some(2.0)])],
+                        2,
+                    ),
+                ),
+            ],
+        )
+        .unwrap();
+        native
+            .begin()
+            .add(reader(with_vectors))
+            .commit()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 1);
+
+        // Updating the source of the embeddings would leave them stale
+        let err = native
+            .begin()
+            .update(&[("text", "'bye'")], None)
+            .commit()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidInput { message } if message.contains("'text'")),
+            "{}",
+            err
+        );
+        native
+            .begin()
+            .update(&[("id", "id + 1")], None)
+            .commit()
+            .await
+            .unwrap();
+    }
+}
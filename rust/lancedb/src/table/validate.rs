@@ -0,0 +1,235 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking data against the definition of a table, see [`super::Table::validate`]
+
+use std::fmt;
+
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Schema, SchemaRef};
+
+use super::{ColumnKind, TableDefinition};
+use crate::error::{Error, Result};
+
+/// A problem with data that would be added to a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The index of the batch with the problem
+    ///
+    /// This is None for problems with the schema of the data, which apply to
+    /// every batch.
+    pub batch: Option<usize>,
+    /// The column with the problem
+    pub column: String,
+    /// What the problem is
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(batch) = self.batch {
+            write!(f, "batch {}: ", batch)?;
+        }
+        write!(f, "column '{}' {}", self.column, self.message)
+    }
+}
+
+/// The result of [`super::Table::validate`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Every problem found, schema problems first and then the problems of each
+    /// batch in turn
+    pub issues: Vec<ValidationIssue>,
+    /// The number of batches read
+    pub num_batches: usize,
+    /// The number of rows read
+    pub num_rows: usize,
+}
+
+impl ValidationReport {
+    /// Whether the data can be added to the table
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Fail with an [`Error::Schema`] that lists every issue, if there are any
+    pub fn into_result(self) -> Result<()> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        let issues = self
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        Err(Error::Schema {
+            message: format!("the data does not match the table: {}", issues.join("; ")),
+        })
+    }
+}
+
+/// Checks data that is appended to a table against the definition of the table
+///
+/// This is used by [`super::Table::validate`] and before appending data, so both
+/// report the same problems.
+pub(crate) struct Validator {
+    schema: SchemaRef,
+    /// The source and destination columns of the embeddings of the table
+    embeddings: Vec<(String, String)>,
+}
+
+impl Validator {
+    pub(crate) fn new(definition: &TableDefinition) -> Self {
+        let embeddings = definition
+            .column_definitions
+            .iter()
+            .filter_map(|column| match &column.kind {
+                ColumnKind::Embedding(embedding) => Some((
+                    embedding.source_column.clone(),
+                    embedding.dest_column_name(),
+                )),
+                ColumnKind::Physical => None,
+            })
+            .collect();
+        Self {
+            schema: definition.schema.clone(),
+            embeddings,
+        }
+    }
+
+    /// The problems with the schema of the data
+    ///
+    /// Every column of the data must be in the table, with the same type.  Columns
+    /// of the table that are missing from the data must be nullable or computed by
+    /// an embedding whose source column is in the data.
+    pub(crate) fn check_schema(&self, data_schema: &Schema) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut issue = |column: &str, message: String| {
+            issues.push(ValidationIssue {
+                batch: None,
+                column: column.to_string(),
+                message,
+            })
+        };
+
+        for field in data_schema.fields() {
+            let Ok(table_field) = self.schema.field_with_name(field.name()) else {
+                issue(field.name(), "does not exist in the table".to_string());
+                continue;
+            };
+            match (field.data_type(), table_field.data_type()) {
+                (DataType::FixedSizeList(_, size), DataType::FixedSizeList(_, table_size))
+                    if size != table_size =>
+                {
+                    issue(
+                        field.name(),
+                        format!(
+                            "has vectors of dimension {} but the table expects {}",
+                            size, table_size
+                        ),
+                    );
+                }
+                (data_type, table_type) if !same_type(data_type, table_type) => {
+                    issue(
+                        field.name(),
+                        format!(
+                            "has type {} but the table expects {}",
+                            data_type, table_type
+                        ),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        for (source, dest) in &self.embeddings {
+            if data_schema.field_with_name(dest).is_err()
+                && data_schema.field_with_name(source).is_err()
+            {
+                issue(
+                    source,
+                    format!(
+                        "is missing, the embedding column '{}' is computed from it",
+                        dest
+                    ),
+                );
+            }
+        }
+        for table_field in self.schema.fields() {
+            let is_embedding = self
+                .embeddings
+                .iter()
+                .any(|(_, dest)| dest == table_field.name());
+            if !table_field.is_nullable()
+                && !is_embedding
+                && data_schema.field_with_name(table_field.name()).is_err()
+            {
+                issue(
+                    table_field.name(),
+                    "is not nullable but is missing from the data".to_string(),
+                );
+            }
+        }
+        issues
+    }
+
+    /// The problems with the values of the batch at `index`
+    ///
+    /// Columns of the table that aren't nullable must not have nulls.
+    pub(crate) fn check_batch(&self, index: usize, batch: &RecordBatch) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            let Ok(table_field) = self.schema.field_with_name(field.name()) else {
+                continue;
+            };
+            if !table_field.is_nullable() && column.null_count() > 0 {
+                issues.push(ValidationIssue {
+                    batch: Some(index),
+                    column: field.name().clone(),
+                    message: format!(
+                        "has {} null values but is not nullable",
+                        column.null_count()
+                    ),
+                });
+            }
+        }
+        issues
+    }
+}
+
+/// Whether values of `data_type` can be written to a column of `table_type`
+///
+/// The names and nullability of the children of nested types don't matter.
+fn same_type(data_type: &DataType, table_type: &DataType) -> bool {
+    match (data_type, table_type) {
+        (DataType::FixedSizeList(item, size), DataType::FixedSizeList(table_item, table_size)) => {
+            size == table_size && same_type(item.data_type(), table_item.data_type())
+        }
+        (DataType::List(item), DataType::List(table_item))
+        | (DataType::LargeList(item), DataType::LargeList(table_item)) => {
+            same_type(item.data_type(), table_item.data_type())
+        }
+        (DataType::Struct(fields), DataType::Struct(table_fields)) => {
+            fields.len() == table_fields.len()
+                && fields.iter().all(|field| {
+                    table_fields
+                        .find(field.name())
+                        .is_some_and(|(_, table_field)| {
+                            same_type(field.data_type(), table_field.data_type())
+                        })
+                })
+        }
+        _ => data_type == table_type,
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Following the new versions of a table, see [`crate::Table::watch`]
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use arrow_schema::Schema;
+use futures::Stream;
+use lance::dataset::Dataset;
+
+use crate::error::Result;
+use crate::Table;
+
+/// New versions of a table, see [`crate::Table::watch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The latest version before the change
+    pub old_version: u64,
+    /// The latest version after the change
+    ///
+    /// This is more than one version after `old_version` if several versions
+    /// were committed between two polls.
+    pub new_version: u64,
+    /// The operation of each new version, in order
+    ///
+    /// This is empty for tables in LanceDB Cloud and if the versions were cleaned
+    /// up before they were read.
+    pub operations: Vec<ChangeOperation>,
+}
+
+/// What a version changed, as told by comparing its manifest with the manifest of
+/// the version before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChangeOperation {
+    /// Rows were appended
+    Append,
+    /// Rows were deleted
+    Delete,
+    /// Some of the rows were rewritten, e.g. by an update, a merge insert or a
+    /// compaction
+    Rewrite,
+    /// All of the rows were replaced
+    Overwrite,
+    /// Columns were added, altered or dropped
+    SchemaChange,
+    /// The rows didn't change, e.g. an index was created
+    Other,
+}
+
+/// Compare the manifests of two versions
+pub(crate) fn classify(old: &Dataset, new: &Dataset) -> ChangeOperation {
+    let old_fragments = old
+        .get_fragments()
+        .into_iter()
+        .map(|fragment| (fragment.id(), fragment.metadata().clone()))
+        .collect::<HashMap<_, _>>();
+    let new_fragments = new
+        .get_fragments()
+        .into_iter()
+        .map(|fragment| (fragment.id(), fragment.metadata().clone()))
+        .collect::<HashMap<_, _>>();
+    let added = new_fragments
+        .keys()
+        .any(|id| !old_fragments.contains_key(id));
+    let kept = old_fragments
+        .keys()
+        .filter(|id| new_fragments.contains_key(id))
+        .count();
+    let changed = old_fragments
+        .iter()
+        .any(|(id, fragment)| new_fragments.get(id) != Some(fragment));
+
+    if added && kept == 0 && !old_fragments.is_empty() {
+        return ChangeOperation::Overwrite;
+    }
+    if Schema::from(old.schema()).fields() != Schema::from(new.schema()).fields() {
+        return ChangeOperation::SchemaChange;
+    }
+    match (added, changed) {
+        (true, false) => ChangeOperation::Append,
+        (false, true) => ChangeOperation::Delete,
+        (true, true) => ChangeOperation::Rewrite,
+        (false, false) => ChangeOperation::Other,
+    }
+}
+
+/// Poll the latest version of `table` every `poll_interval`, starting from
+/// `since` or from the latest version when the stream is first polled
+pub(crate) fn watch(
+    table: Table,
+    poll_interval: Duration,
+    since: Option<u64>,
+) -> impl Stream<Item = Result<ChangeEvent>> + Send + 'static {
+    futures::stream::unfold((table, since), move |(table, since)| async move {
+        let mut old_version = match since {
+            Some(version) => version,
+            None => match table.inner.latest_version().await {
+                Ok(version) => version,
+                Err(err) => return Some((Err(err), (table, None))),
+            },
+        };
+        loop {
+            tokio::time::sleep(poll_interval).await;
+            let new_version = match table.inner.latest_version().await {
+                Ok(version) => version,
+                Err(err) => return Some((Err(err), (table, Some(old_version)))),
+            };
+            // A restore commits a new version, so the latest version only moves
+            // backwards if the table was dropped and created again
+            if new_version == old_version {
+                continue;
+            }
+            if new_version < old_version {
+                old_version = new_version;
+                continue;
+            }
+            let operations = match table
+                .inner
+                .version_operations(old_version, new_version)
+                .await
+            {
+                Ok(operations) => operations,
+                Err(err) => {
+                    log::warn!(
+                        "Could not read the versions {} to {} of {}: {}",
+                        old_version + 1,
+                        new_version,
+                        table,
+                        err
+                    );
+                    Vec::new()
+                }
+            };
+            let event = ChangeEvent {
+                old_version,
+                new_version,
+                operations,
+            };
+            return Some((Ok(event), (table, Some(new_version))));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field};
+    use futures::StreamExt;
+    use lance::dataset::NewColumnTransform;
+
+    use super::*;
+    use crate::connect;
+    use crate::table::AddDataMode;
+
+    fn batch(ids: std::ops::Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(ids))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("test", batch(0..10))
+            .execute()
+            .await
+            .unwrap();
+        let version = table.version().await.unwrap();
+        let mut changes = Box::pin(watch(
+            table.clone(),
+            Duration::from_millis(10),
+            Some(version),
+        ));
+
+        table.add(batch(10..20)).execute().await.unwrap();
+        let event = changes.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            ChangeEvent {
+                old_version: version,
+                new_version: version + 1,
+                operations: vec![ChangeOperation::Append],
+            }
+        );
+
+        // Several versions between two polls are one event
+        table.delete("id < 5").await.unwrap();
+        table.add(batch(20..30)).execute().await.unwrap();
+        table
+            .add_columns(
+                NewColumnTransform::SqlExpressions(vec![(
+                    "double".to_string(),
+                    "id * 2".to_string(),
+                )]),
+                None,
+            )
+            .await
+            .unwrap();
+        let event = changes.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            ChangeEvent {
+                old_version: version + 1,
+                new_version: version + 4,
+                operations: vec![
+                    ChangeOperation::Delete,
+                    ChangeOperation::Append,
+                    ChangeOperation::SchemaChange
+                ],
+            }
+        );
+
+        table
+            .add(batch(0..5))
+            .mode(AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+        let event = changes.next().await.unwrap().unwrap();
+        assert_eq!(event.operations, vec![ChangeOperation::Overwrite]);
+        assert_eq!(event.new_version, version + 5);
+    }
+}
@@ -14,7 +14,7 @@
 
 use std::sync::Arc;
 
-use arrow_schema::Schema;
+use arrow_schema::{DataType, Schema};
 use lance::dataset::{ReadParams, WriteParams};
 use lance::io::{ObjectStoreParams, WrappingObjectStore};
 use lazy_static::lazy_static;
@@ -101,22 +101,40 @@ pub fn validate_table_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// The dimension of the vectors of a multivector column, a list of vectors of floats
+pub(crate) fn multivector_dimension(data_type: &DataType) -> Option<i32> {
+    match data_type {
+        DataType::List(item) | DataType::LargeList(item) => match item.data_type() {
+            DataType::FixedSizeList(f, d) if f.data_type().is_floating() => Some(*d),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Find one default column to create index or perform vector query.
 pub(crate) fn default_vector_column(schema: &Schema, dim: Option<i32>) -> Result<String> {
-    // Try to find one fixed size list array column.
-    let candidates = schema
-        .fields()
-        .iter()
-        .filter_map(|field| match field.data_type() {
-            arrow_schema::DataType::FixedSizeList(f, d)
-                if f.data_type().is_floating()
-                    && dim.map(|expect| *d == expect).unwrap_or(true) =>
-            {
-                Some(field.name())
-            }
-            _ => None,
-        })
-        .collect::<Vec<_>>();
+    // Try to find one fixed size list array column, and only then a list of them, so
+    // that adding a multivector column doesn't change the column of plain searches.
+    let candidates_of = |dimension: fn(&DataType) -> Option<i32>| {
+        schema
+            .fields()
+            .iter()
+            .filter_map(|field| {
+                let d = dimension(field.data_type())?;
+                dim.map(|expect| d == expect)
+                    .unwrap_or(true)
+                    .then(|| field.name())
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut candidates = candidates_of(|data_type| match data_type {
+        DataType::FixedSizeList(f, d) if f.data_type().is_floating() => Some(*d),
+        _ => None,
+    });
+    if candidates.is_empty() {
+        candidates = candidates_of(multivector_dimension);
+    }
     if candidates.is_empty() {
         Err(Error::InvalidInput {
             message: format!(
@@ -137,6 +155,55 @@ pub(crate) fn default_vector_column(schema: &Schema, dim: Option<i32>) -> Result
     }
 }
 
+/// Resolve the column a vector search runs against and verify it can be compared
+/// with a query vector of dimension `dim`.
+///
+/// If `column` is not provided a column is inferred with [`default_vector_column`].
+pub(crate) fn resolve_vector_column(
+    schema: &Schema,
+    column: Option<&str>,
+    dim: usize,
+) -> Result<String> {
+    let column = match column {
+        Some(column) => column.to_string(),
+        None => default_vector_column(schema, Some(dim as i32))?,
+    };
+    let field = schema.field_with_name(&column).map_err(|_| Error::Schema {
+        message: format!("Column {} not found in dataset schema", column),
+    })?;
+    if let Some(expected_dim) = multivector_dimension(field.data_type()) {
+        if expected_dim != dim as i32 {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "The dimension of the query vector does not match with the dimension of the vectors of the multivector column '{}': \
+                        query dim={}, expected vector dim={}",
+                    column, dim, expected_dim,
+                ),
+            });
+        }
+    }
+    if let arrow_schema::DataType::FixedSizeList(f, expected_dim) = field.data_type() {
+        if !f.data_type().is_floating() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "The data type of the vector column '{}' is not a floating point type",
+                    column
+                ),
+            });
+        }
+        if *expected_dim != dim as i32 {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "The dimension of the query vector does not match with the dimension of the vector column '{}': \
+                        query dim={}, expected vector dim={}",
+                    column, dim, expected_dim,
+                ),
+            });
+        }
+    }
+    Ok(column)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,6 +251,46 @@ mod tests {
             .unwrap_err()
             .to_string()
             .contains("More than one"));
+
+        // A list of vectors is a multivector column
+        let vector =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4);
+        let schema_with_multivector = Schema::new(vec![
+            Field::new("id", DataType::Int16, true),
+            Field::new(
+                "tokens",
+                DataType::List(Arc::new(Field::new("item", vector.clone(), true))),
+                true,
+            ),
+        ]);
+        assert_eq!(
+            default_vector_column(&schema_with_multivector, Some(4)).unwrap(),
+            "tokens"
+        );
+        assert!(default_vector_column(&schema_with_multivector, Some(3)).is_err());
+        // but a single vector column is preferred
+        let schema_with_both = Schema::new(vec![
+            Field::new(
+                "tokens",
+                DataType::List(Arc::new(Field::new("item", vector.clone(), true))),
+                true,
+            ),
+            Field::new("vec", vector, true),
+        ]);
+        assert_eq!(
+            default_vector_column(&schema_with_both, Some(4)).unwrap(),
+            "vec"
+        );
+        assert_eq!(
+            default_vector_column(&schema_with_both, None).unwrap(),
+            "vec"
+        );
+        assert!(
+            resolve_vector_column(&schema_with_multivector, Some("tokens"), 3)
+                .unwrap_err()
+                .to_string()
+                .contains("multivector column 'tokens'")
+        );
     }
 
     #[test]
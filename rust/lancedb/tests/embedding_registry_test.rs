@@ -2,32 +2,46 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     iter::repeat,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
-use arrow::buffer::NullBuffer;
+use arrow::{
+    array::AsArray,
+    buffer::{NullBuffer, OffsetBuffer},
+};
 use arrow_array::{
-    Array, FixedSizeListArray, Float32Array, Int32Array, RecordBatch, RecordBatchIterator,
-    StringArray,
+    types::Float16Type, Array, ArrayRef, BinaryArray, FixedSizeBinaryArray, FixedSizeListArray,
+    Float16Array, Float32Array, Int32Array, LargeBinaryArray, LargeStringArray, ListArray,
+    RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray,
 };
 use arrow_schema::{DataType, Field, Schema};
-use futures::StreamExt;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
 use lancedb::{
     arrow::IntoArrow,
     connect,
-    embeddings::{EmbeddingDefinition, EmbeddingFunction, EmbeddingRegistry},
-    query::ExecutableQuery,
-    Error, Result,
+    embeddings::{
+        default_registry, CachedEmbeddingFunction, EmbeddingCacheStats, EmbeddingDefinition,
+        EmbeddingFunction, EmbeddingFunctionFactory, EmbeddingRegistry, ExistingColumnPolicy,
+        MemoryRegistry, NullPolicy, WithEmbeddings,
+    },
+    query::{ExecutableQuery, QueryBase, Select, VectorQuery},
+    schema::Builder,
+    Connection, DistanceType, Error, Result,
 };
+use tempfile::TempDir;
 
 #[tokio::test]
 async fn test_custom_func() -> Result<()> {
-    let tempdir = tempfile::tempdir().unwrap();
-    let tempdir = tempdir.path().to_str().unwrap();
-    let db = connect(tempdir).execute().await?;
-    let embed_fun = MockEmbed::new("embed_fun".to_string(), 1);
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let embed_fun = Arc::new(MockEmbed::new("embed_fun", 1));
     db.embedding_registry()
-        .register("embed_fun", Arc::new(embed_fun.clone()))?;
+        .register("embed_fun", embed_fun.clone())?;
 
     let tbl = db
         .create_table("test", create_some_records()?)
@@ -59,14 +73,131 @@ async fn test_custom_func() -> Result<()> {
 }
 
 #[tokio::test]
-async fn test_custom_registry() -> Result<()> {
-    let tempdir = tempfile::tempdir().unwrap();
-    let tempdir = tempdir.path().to_str().unwrap();
+async fn test_async_func() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let embed_fun = Arc::new(MockEmbed::new("async_fun", 2).slow(Duration::from_millis(10)));
+    db.embedding_registry()
+        .register("async_fun", embed_fun.clone())?;
+
+    let tbl = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "async_fun",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    tbl.add(create_some_records()?).execute().await?;
+
+    let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+    let num_rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+    assert_eq!(num_rows, 4);
+    for batch in batches {
+        let embeddings = batch.column_by_name("embeddings").unwrap();
+        assert_eq!(embeddings.data_type(), embed_fun.dest_type()?.as_ref());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_embed_without_runtime() -> Result<()> {
+    // Outside of a runtime the reader calls the synchronous interface of functions
+    let func = Arc::new(MockEmbed::new("sync_fun", 2));
+    let embedded = WithEmbeddings::try_new(
+        create_batched_records(3, 2).into_arrow()?,
+        vec![(
+            EmbeddingDefinition::new("text", "sync_fun", Some("embeddings")),
+            func.clone(),
+        )],
+    )?;
+    let batches = embedded.collect::<std::result::Result<Vec<_>, _>>()?;
+    assert_eq!(batches.len(), 3);
+    for batch in batches {
+        assert_eq!(batch["embeddings"].data_type(), func.dest_type()?.as_ref());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embed_ahead() -> Result<()> {
+    // The function can't be called synchronously, so all the batches must have been
+    // embedded ahead with the async interface, even on a current thread runtime
+    let func = Arc::new(MockEmbed::new("async_fun", 2).slow(Duration::from_millis(10)));
+    let embedded = WithEmbeddings::try_new(
+        create_batched_records(3, 2).into_arrow()?,
+        vec![(
+            EmbeddingDefinition::new("text", "async_fun", Some("embeddings")),
+            func.clone(),
+        )],
+    )?
+    .with_concurrency(2)
+    .embed_ahead(usize::MAX)
+    .await?;
+    let batches = embedded.collect::<std::result::Result<Vec<_>, _>>()?;
+    assert_eq!(batches.len(), 3);
+    for batch in batches {
+        assert_eq!(batch["embeddings"].data_type(), func.dest_type()?.as_ref());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_large_string_source() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    // The function takes Utf8 strings, large strings are cast for it
+    db.embedding_registry().register(
+        "large_string_fun",
+        Arc::new(MockEmbed::new("large_string_fun", 1)),
+    )?;
+    let records = || {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::LargeUtf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..3)),
+                Arc::new(LargeStringArray::from(vec!["hello", "large", "world"])),
+            ],
+        )
+        .unwrap();
+        Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+    };
 
-    let db = connect(tempdir)
-        .embedding_registry(Arc::new(MyRegistry::default()))
+    let tbl = db
+        .create_table("test", records())
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "large_string_fun",
+            Some("embeddings"),
+        ))?
         .execute()
         .await?;
+    tbl.add(records()).execute().await?;
+    let schema = tbl.schema().await?;
+    assert_eq!(
+        schema.field_with_name("text")?.data_type(),
+        &DataType::LargeUtf8
+    );
+    assert_eq!(tbl.count_rows(None).await?, 6);
+    assert_eq!(
+        tbl.count_rows(Some("embeddings IS NULL".to_string()))
+            .await?,
+        0
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_custom_registry() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db
+        .connect_with(Arc::new(MyRegistry::default()))
+        .await?;
 
     let tbl = db
         .create_table("test", create_some_records()?)
@@ -84,26 +215,618 @@ async fn test_custom_registry() -> Result<()> {
         let embeddings = embeddings.unwrap();
         assert_eq!(
             embeddings.data_type(),
-            MockEmbed::new("func_1".to_string(), 1)
-                .dest_type()?
-                .as_ref()
+            MockEmbed::new("func_1", 1).dest_type()?.as_ref()
         );
     }
     Ok(())
 }
 
 #[tokio::test]
-async fn test_multiple_embeddings() -> Result<()> {
-    let tempdir = tempfile::tempdir().unwrap();
-    let tempdir = tempdir.path().to_str().unwrap();
+async fn test_default_registry() -> Result<()> {
+    let (first_db, second_db) = (TestDb::new(), TestDb::new());
+
+    // Connections without a registry of their own share the default one
+    let first = connect(first_db.uri()).execute().await?;
+    let second = connect(second_db.uri()).execute().await?;
+    let func = Arc::new(MockEmbed::new("default_fun", 1));
+    first
+        .embedding_registry()
+        .register("default_fun", func.clone())?;
+    assert!(second.embedding_registry().get("default_fun").is_some());
+    assert!(default_registry().get("default_fun").is_some());
+
+    // Registering the same function again, e.g. from another component, is a no-op
+    second
+        .embedding_registry()
+        .register("default_fun", func.clone())?;
+    let configurable = |dim| Arc::new(MockEmbed::new("configurable", dim).configurable());
+    first
+        .embedding_registry()
+        .register("default_configurable_fun", configurable(2))?;
+    second
+        .embedding_registry()
+        .register("default_configurable_fun", configurable(2))?;
+    // but a different function can't take the name
+    let err = second
+        .embedding_registry()
+        .register("default_configurable_fun", configurable(3))
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::EmbeddingFunctionAlreadyExists { name } if name == "default_configurable_fun"),
+        "{:?}",
+        err
+    );
+    let err = second
+        .embedding_registry()
+        .register("default_fun", Arc::new(MockEmbed::new("default_fun", 1)))
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::EmbeddingFunctionAlreadyExists { .. }),
+        "{:?}",
+        err
+    );
+
+    let tbl = second
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "default_fun",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    tbl.add(create_some_records()?).execute().await?;
+    let tbl = second.open_table("test").execute().await?;
+    tbl.add(create_some_records()?).execute().await?;
+    assert_eq!(
+        tbl.count_rows(Some("embeddings IS NULL".to_string()))
+            .await?,
+        0
+    );
+
+    // A connection with its own registry doesn't see the function, so adding fails
+    // instead of writing rows without embeddings
+    let other = second_db.connect().await?;
+    assert!(other.embedding_registry().get("default_fun").is_none());
+    let tbl = other.open_table("test").execute().await?;
+    let num_rows = tbl.count_rows(None).await?;
+    let err = tbl.add(create_some_records()?).execute().await.unwrap_err();
+    assert!(
+        matches!(&err, Error::EmbeddingFunctionNotFound { name, .. } if name == "default_fun"),
+        "{:?}",
+        err
+    );
+    assert_eq!(tbl.count_rows(None).await?, num_rows);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_duplicate_dest_column() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let func_1 = Arc::new(MockEmbed::new("func_1", 1));
+    let func_2 = Arc::new(MockEmbed::new("func_2", 10));
+    db.embedding_registry()
+        .register(&func_1.name, func_1.clone())?;
+    db.embedding_registry()
+        .register(&func_2.name, func_2.clone())?;
+
+    let res = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            &func_1.name,
+            Some("embeddings"),
+        ))?
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            &func_2.name,
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await;
+    match res.err().unwrap() {
+        Error::InvalidInput { message } => assert!(message.contains("'embeddings'")),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    // the default destination name collides too
+    let res = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new("text", &func_1.name, None))?
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            &func_2.name,
+            Some("text_embedding"),
+        ))?
+        .execute()
+        .await;
+    assert!(matches!(res.err().unwrap(), Error::InvalidInput { .. }));
+
+    // as does a column of the data
+    let res = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new("text", &func_1.name, Some("id")))?
+        .execute()
+        .await;
+    match res.err().unwrap() {
+        Error::InvalidInput { message } => {
+            assert!(message.contains("'id'"), "{}", message);
+            assert!(message.contains("'func_1'"), "{}", message);
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert!(db.table_names().execute().await?.is_empty());
+
+    // and the columns that searches add to their results
+    for reserved in ["_distance", "_rowid", "_score"] {
+        let res = db
+            .create_table("test", create_some_records()?)
+            .add_embedding(EmbeddingDefinition::new(
+                "text",
+                &func_1.name,
+                Some(reserved),
+            ))?
+            .execute()
+            .await;
+        match res.err().unwrap() {
+            Error::InvalidInput { message } => {
+                assert!(message.contains(reserved), "{}", message);
+                assert!(message.contains("reserved"), "{}", message);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_existing_dest_column() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let func = Arc::new(MockEmbed::new("embed_fun", 1));
+    db.embedding_registry()
+        .register("embed_fun", func.clone())?;
+    let definition = |if_exists| {
+        EmbeddingDefinition::new("text", "embed_fun", Some("embeddings")).if_exists(if_exists)
+    };
+    // Pre-computed embeddings of the right type, the function computes 1.0
+    let vectors = |value: f32| -> ArrayRef {
+        Arc::new(FixedSizeListArray::from_iter_primitive::<
+            arrow_array::types::Float32Type,
+            _,
+            _,
+        >(vec![Some(vec![Some(value)]); 2], 1))
+    };
+    let embeddings_of = |batches: Vec<RecordBatch>| {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                batch["embeddings"]
+                    .as_fixed_size_list()
+                    .values()
+                    .as_primitive::<arrow_array::types::Float32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // By default the column is an error
+    let err = db
+        .create_table("test", create_precomputed_records(vectors(7.0)))
+        .add_embedding(definition(ExistingColumnPolicy::Error))?
+        .execute()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidInput { message } if message.contains("if_exists")),
+        "{:?}",
+        err
+    );
+
+    // Skip keeps the pre-computed embeddings, also when adding data later
+    let tbl = db
+        .create_table("skip", create_precomputed_records(vectors(7.0)))
+        .add_embedding(definition(ExistingColumnPolicy::Skip))?
+        .execute()
+        .await?;
+    tbl.add(create_precomputed_records(vectors(8.0)))
+        .execute()
+        .await?;
+    tbl.add(create_some_records()?).execute().await?;
+    let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+    assert_eq!(embeddings_of(batches), vec![7.0, 7.0, 8.0, 8.0, 1.0, 1.0]);
+
+    // but only if they have the type of the function
+    let wrong_type: ArrayRef = Arc::new(StringArray::from(vec!["a", "b"]));
+    let err = db
+        .create_table(
+            "skip_wrong_type",
+            create_precomputed_records(wrong_type.clone()),
+        )
+        .add_embedding(definition(ExistingColumnPolicy::Skip))?
+        .execute()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidInput { message } if message.contains("'embeddings'")),
+        "{:?}",
+        err
+    );
+
+    // Overwrite replaces them, whatever their type
+    for (name, embeddings) in [
+        ("overwrite", vectors(7.0)),
+        ("overwrite_wrong_type", wrong_type),
+    ] {
+        let tbl = db
+            .create_table(name, create_precomputed_records(embeddings))
+            .add_embedding(definition(ExistingColumnPolicy::Overwrite))?
+            .execute()
+            .await?;
+        let schema = tbl.schema().await?;
+        assert_eq!(
+            schema.field_with_name("embeddings")?.data_type(),
+            func.dest_type()?.as_ref()
+        );
+        let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+        assert_eq!(embeddings_of(batches), vec![1.0, 1.0]);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_batch_size() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let embed_fun = Arc::new(
+        MockEmbed::new("chunked_func", 1)
+            .embeds(Output::Number)
+            .max_rows(10),
+    );
+    db.embedding_registry()
+        .register("chunked_func", embed_fun.clone())?;
+
+    let tbl = db
+        .create_table("test", create_numbered_records(25))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "chunked_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    let batch_sizes = embed_fun
+        .inputs
+        .lock()
+        .unwrap()
+        .iter()
+        .map(Vec::len)
+        .collect::<Vec<_>>();
+    assert_eq!(batch_sizes, vec![10, 10, 5]);
+
+    // Rows keep their order across chunk boundaries
+    let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+    for batch in batches {
+        let text = batch["text"]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let embeddings = batch["embeddings"]
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap();
+        for i in 0..batch.num_rows() {
+            let embedding = embeddings.value(i);
+            let value = embedding.as_any().downcast_ref::<Float32Array>().unwrap();
+            assert_eq!(value.value(0), text.value(i).parse::<f32>().unwrap());
+        }
+    }
+
+    // A failing chunk reports which rows it covered
+    db.embedding_registry().register(
+        "failing_chunk",
+        Arc::new(
+            MockEmbed::new("failing_chunk", 1)
+                .embeds(Output::Number)
+                .max_rows(10)
+                .fails(Failure::OnRow(15)),
+        ),
+    )?;
+    let err = db
+        .create_table("test2", create_numbered_records(25))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "failing_chunk",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    let message = err.to_string();
+    assert!(message.contains("rows 10..20"), "{}", message);
+    assert!(message.contains("chunk 2 of 3"), "{}", message);
+    assert!(message.contains("cannot embed row 15"), "{}", message);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_null_policy() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    // MockEmbed panics if it is given a null
+    let func = Arc::new(MockEmbed::new("chunked_func", 1).embeds(Output::Number));
+    db.embedding_registry()
+        .register("chunked_func", func.clone())?;
+    let text = || {
+        create_text_records(vec![
+            Some("0".to_string()),
+            None,
+            Some("2".to_string()),
+            None,
+            Some("4".to_string()),
+        ])
+    };
+    let definition = || EmbeddingDefinition::new("text", "chunked_func", Some("embeddings"));
+    let embed = |text: Box<dyn RecordBatchReader + Send>, definition: EmbeddingDefinition| {
+        let func = func.clone();
+        async move {
+            let mut text = text;
+            let batch = text.next().unwrap()?;
+            let embedded = WithEmbeddings::try_new(text, vec![(definition, func)])?;
+            let batch = embedded.embed_batch(batch).await?;
+            let ids = batch["id"]
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap()
+                .values()
+                .to_vec();
+            Result::Ok((ids, batch["embeddings"].clone()))
+        }
+    };
+
+    // Null values get a null embedding by default
+    let (ids, embeddings) = embed(text().into_arrow()?, definition()).await?;
+    assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+    let embeddings = embeddings
+        .as_any()
+        .downcast_ref::<FixedSizeListArray>()
+        .unwrap();
+    let values = (0..embeddings.len())
+        .map(|i| {
+            embeddings.is_valid(i).then(|| {
+                let value = embeddings.value(i);
+                value
+                    .as_any()
+                    .downcast_ref::<Float32Array>()
+                    .unwrap()
+                    .value(0)
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![Some(0.0), None, Some(2.0), None, Some(4.0)]);
+
+    // A batch that is entirely null never calls the embedding function
+    let (ids, embeddings) = embed(
+        create_text_records(vec![None, None]).into_arrow()?,
+        definition(),
+    )
+    .await?;
+    assert_eq!(ids, vec![0, 1]);
+    assert_eq!(embeddings.null_count(), 2);
+    assert_eq!(embeddings.data_type(), func.dest_type()?.as_ref());
+
+    let (ids, embeddings) = embed(
+        text().into_arrow()?,
+        definition().on_null(NullPolicy::SkipRow),
+    )
+    .await?;
+    assert_eq!(ids, vec![0, 2, 4]);
+    assert_eq!(embeddings.null_count(), 0);
+
+    // The policy is applied when writing to a table
+    let tbl = db
+        .create_table("null_vector", text())
+        .add_embedding(definition())?
+        .execute()
+        .await?;
+    tbl.add(create_text_records(vec![None, None]))
+        .execute()
+        .await?;
+    assert_eq!(tbl.count_rows(None).await?, 7);
+
+    let tbl = db
+        .create_table("skip_row", text())
+        .add_embedding(definition().on_null(NullPolicy::SkipRow))?
+        .execute()
+        .await?;
+    tbl.add(create_text_records(vec![None, None]))
+        .execute()
+        .await?;
+    assert_eq!(tbl.count_rows(None).await?, 3);
+
+    let err = db
+        .create_table("error", text())
+        .add_embedding(definition().on_null(NullPolicy::Error))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("null value at row 1"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_nearest_to_text() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry().register(
+        "chunked_func",
+        Arc::new(MockEmbed::new("chunked_func", 1).embeds(Output::Number)),
+    )?;
+
+    let tbl = db
+        .create_table("test", create_numbered_records(10))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "chunked_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    async fn nearest_id(query: VectorQuery) -> Result<i32> {
+        let batches = query
+            .limit(1)
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(batches[0]["id"]
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap()
+            .value(0))
+    }
+    assert_eq!(nearest_id(tbl.query().nearest_to_text("7")).await?, 7);
 
-    let db = connect(tempdir).execute().await?;
-    let func_1 = MockEmbed::new("func_1".to_string(), 1);
-    let func_2 = MockEmbed::new("func_2".to_string(), 10);
+    // With more than one embedding column the column must be chosen
+    let tbl = db
+        .create_table("two_columns", create_numbered_records(10))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "chunked_func",
+            Some("first"),
+        ))?
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "chunked_func",
+            Some("second"),
+        ))?
+        .execute()
+        .await?;
+    let err = tbl
+        .query()
+        .nearest_to_text("7")
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("first, second"), "{}", err);
+    assert_eq!(
+        nearest_id(tbl.query().nearest_to_text("3").column("second")).await?,
+        3
+    );
+    let err = tbl
+        .query()
+        .nearest_to_text("3")
+        .column("text")
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("no embedding function"), "{}", err);
+
+    let tbl = db
+        .create_table("no_embeddings", create_numbered_records(10))
+        .execute()
+        .await?;
+    let err = tbl
+        .query()
+        .nearest_to_text("7")
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_replace_unregister() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let registry = db.embedding_registry();
+    assert!(registry.is_empty());
+
+    registry.register("func", Arc::new(MockEmbed::new("func", 1)))?;
+    let err = registry
+        .register("func", Arc::new(MockEmbed::new("func", 2)))
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::EmbeddingFunctionAlreadyExists { name } if name == "func"),
+        "{:?}",
+        err
+    );
+    assert_eq!(registry.len(), 1);
+    assert_eq!(
+        registry.get("func").unwrap().dest_type()?,
+        MockEmbed::new("func", 1).dest_type()?
+    );
+
+    let tbl = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new("text", "func", Some("embeddings")))?
+        .execute()
+        .await?;
+
+    // A write that has already looked up the function keeps it
+    let mut records = create_some_records()?.into_arrow()?;
+    let batch = records.next().unwrap()?;
+    let in_progress = WithEmbeddings::try_new(
+        records,
+        vec![(
+            EmbeddingDefinition::new("text", "func", Some("embeddings")),
+            registry.get("func").unwrap(),
+        )],
+    )?;
+
+    // Replacing changes the function used by later writes
+    registry.register_or_replace("func", Arc::new(MockEmbed::new("func", 2)))?;
+    assert_eq!(registry.len(), 1);
+    let err = tbl
+        .add(create_some_records()?)
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("embeddings"), "{}", err);
+
+    assert!(registry.unregister("func")?);
+    assert!(!registry.unregister("func")?);
+    assert!(registry.is_empty());
+    let err = tbl
+        .add(create_some_records()?)
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(err, Error::EmbeddingFunctionNotFound { .. }),
+        "{:?}",
+        err
+    );
+
+    let batch = in_progress.embed_batch(batch).await?;
+    let embeddings = batch["embeddings"]
+        .as_any()
+        .downcast_ref::<FixedSizeListArray>()
+        .unwrap();
+    assert_eq!(embeddings.value_length(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_multiple_embeddings() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let func_1 = Arc::new(MockEmbed::new("func_1", 1));
+    let func_2 = Arc::new(MockEmbed::new("func_2", 10));
     db.embedding_registry()
-        .register(&func_1.name, Arc::new(func_1.clone()))?;
+        .register(&func_1.name, func_1.clone())?;
     db.embedding_registry()
-        .register(&func_2.name, Arc::new(func_2.clone()))?;
+        .register(&func_2.name, func_2.clone())?;
 
     let tbl = db
         .create_table("test", create_some_records()?)
@@ -154,10 +877,8 @@ async fn test_multiple_embeddings() -> Result<()> {
 
 #[tokio::test]
 async fn test_no_func_in_registry() -> Result<()> {
-    let tempdir = tempfile::tempdir().unwrap();
-    let tempdir = tempdir.path().to_str().unwrap();
-
-    let db = connect(tempdir).execute().await?;
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
 
     let res = db
         .create_table("test", create_some_records()?)
@@ -177,14 +898,10 @@ async fn test_no_func_in_registry() -> Result<()> {
 
 #[tokio::test]
 async fn test_no_func_in_registry_on_add() -> Result<()> {
-    let tempdir = tempfile::tempdir().unwrap();
-    let tempdir = tempdir.path().to_str().unwrap();
-
-    let db = connect(tempdir).execute().await?;
-    db.embedding_registry().register(
-        "some_func",
-        Arc::new(MockEmbed::new("some_func".to_string(), 1)),
-    )?;
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry()
+        .register("some_func", Arc::new(MockEmbed::new("some_func", 1)))?;
 
     db.create_table("test", create_some_records()?)
         .add_embedding(EmbeddingDefinition::new(
@@ -195,7 +912,7 @@ async fn test_no_func_in_registry_on_add() -> Result<()> {
         .execute()
         .await?;
 
-    let db = connect(tempdir).execute().await?;
+    let db = test_db.connect().await?;
 
     let tbl = db.open_table("test").execute().await?;
     // This should fail because 'tbl' is expecting "some_func" to be in the registry
@@ -209,11 +926,804 @@ async fn test_no_func_in_registry_on_add() -> Result<()> {
     Ok(())
 }
 
-fn create_some_records() -> Result<impl IntoArrow> {
-    const TOTAL: usize = 2;
+#[tokio::test]
+async fn test_func_restored_from_metadata() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry().register(
+        "some_func",
+        Arc::new(MockEmbed::new("configurable", 3).configurable()),
+    )?;
+    db.create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "some_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
 
-    let schema = Arc::new(Schema::new(vec![
-        Field::new("id", DataType::Int32, false),
+    // A new session only needs the factory, the function's config comes from the table
+    let db = test_db.connect().await?;
+    db.embedding_registry()
+        .register_factory(Arc::new(MockEmbedFactory))?;
+    let tbl = db.open_table("test").execute().await?;
+    tbl.add(create_some_records()?).execute().await?;
+    assert_eq!(tbl.count_rows(None).await?, 4);
+    let func = db.embedding_registry().get("some_func").unwrap();
+    assert_eq!(func.to_config(), Some(serde_json::json!({ "dim": 3 })));
+
+    let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+    for batch in batches {
+        assert_eq!(
+            batch["embeddings"].data_type(),
+            &DataType::new_fixed_size_list(DataType::Float32, 3, true)
+        );
+    }
+
+    // Without the factory the function can't be re-created
+    let db = test_db.connect().await?;
+    let tbl = db.open_table("test").execute().await?;
+    let err = tbl
+        .add(create_some_records()?)
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&err, Error::EmbeddingFunctionNotFound { reason, .. } if reason.contains("configurable")),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_source_column() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry()
+        .register("some_func", Arc::new(MockEmbed::new("some_func", 1)))?;
+
+    // A misspelled source column is rejected when creating the table
+    let err = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "txet",
+            "some_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&err, Error::InvalidInput { message } if message.contains("txet")),
+        "{:?}",
+        err
+    );
+
+    // Data added to a table must contain the source column
+    let tbl = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "some_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(Int32Array::from_iter_values(0..2))],
+    )?;
+    let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+    let err = tbl.add(data).execute().await.unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidInput { message } if message.contains("text")),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_error() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry().register(
+        "failing_func",
+        Arc::new(MockEmbed::new("failing_func", 1).fails(Failure::Always)),
+    )?;
+
+    let err = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "failing_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("service unavailable"), "{}", err);
+
+    // The failure also surfaces when adding data to an existing table
+    db.embedding_registry()
+        .register("flaky_func", Arc::new(MockEmbed::new("flaky_func", 1)))?;
+    let tbl = db
+        .create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "flaky_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+    db.embedding_registry().register_or_replace(
+        "flaky_func",
+        Arc::new(MockEmbed::new("flaky_func", 1).fails(Failure::Always)),
+    )?;
+    let err = tbl.add(create_some_records()?).execute().await.unwrap_err();
+    assert!(err.to_string().contains("service unavailable"), "{}", err);
+    assert_eq!(tbl.count_rows(None).await?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_output_mismatch() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+
+    // A function that loses a row
+    db.embedding_registry().register(
+        "dropping_func",
+        Arc::new(
+            MockEmbed::new("dropping_func", 1)
+                .max_rows(10)
+                .fails(Failure::DropRow),
+        ),
+    )?;
+    let err = db
+        .create_table("test", create_numbered_records(5))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "dropping_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    let message = err.to_string();
+    assert!(message.contains("'dropping_func'"), "{}", message);
+    assert!(
+        message.contains("returned 4 embeddings but 5 embeddings were expected"),
+        "{}",
+        message
+    );
+
+    // A function whose dimension changes after the first chunk
+    db.embedding_registry().register(
+        "resizing_func",
+        Arc::new(
+            MockEmbed::new("resizing_func", 1)
+                .max_rows(10)
+                .fails(Failure::ChangeDimension),
+        ),
+    )?;
+    let err = db
+        .create_table("test", create_numbered_records(25))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "resizing_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    let message = err.to_string();
+    assert!(message.contains("'resizing_func'"), "{}", message);
+    assert!(
+        message.contains(
+            "returned 2-dimensional Float32 vectors but 1-dimensional Float32 vectors were expected"
+        ),
+        "{}",
+        message
+    );
+    assert!(db.table_names().execute().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconfigured_func_on_open() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry()
+        .register("embed_fun", Arc::new(MockEmbed::new("embed_fun", 1)))?;
+    db.create_table("test", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "embed_fun",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+
+    // The function now produces vectors that don't fit the table
+    db.embedding_registry()
+        .register_or_replace("embed_fun", Arc::new(MockEmbed::new("embed_fun", 2)))?;
+    let err = db.open_table("test").execute().await.err().unwrap();
+    assert!(matches!(err, Error::Schema { .. }), "{}", err);
+    let message = err.to_string();
+    assert!(
+        message.contains(
+            "column 'embeddings' stores 1-dimensional Float32 vectors but embedding function 'embed_fun' produces 2-dimensional Float32 vectors"
+        ),
+        "{}",
+        message
+    );
+
+    // Without the function, the table opens and re-creates it when needed
+    db.embedding_registry().unregister("embed_fun")?;
+    db.open_table("test").execute().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_merge_insert_and_update() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry().register(
+        "chunked_func",
+        Arc::new(
+            MockEmbed::new("chunked_func", 1)
+                .embeds(Output::Number)
+                .max_rows(10),
+        ),
+    )?;
+    let documents = |ids: Vec<i32>, text: Vec<&str>| {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(text)),
+            ],
+        )
+        .unwrap();
+        Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+    };
+    let tbl = db
+        .create_table("test", documents(vec![0, 1, 2], vec!["0", "1", "2"]))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "chunked_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+
+    // Upsert a changed document and a new one
+    let mut merge_insert = tbl.merge_insert(&["id"]);
+    merge_insert
+        .when_matched_update_all(None)
+        .when_not_matched_insert_all();
+    merge_insert
+        .execute(documents(vec![1, 3], vec!["42", "3"]))
+        .await?;
+    assert_eq!(tbl.count_rows(None).await?, 4);
+
+    let results = tbl
+        .query()
+        .nearest_to(&[42.0])?
+        .limit(1)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let ids = results[0]["id"].as_primitive::<arrow_array::types::Int32Type>();
+    assert_eq!(ids.values(), &[1]);
+    assert_eq!(results[0]["text"].as_string::<i32>().value(0), "42");
+
+    // An update can't recompute the embeddings of the source column
+    let err = tbl
+        .update()
+        .only_if("id = 2")
+        .column("text", "'7'")
+        .execute()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidInput { .. }), "{}", err);
+    assert!(err.to_string().contains("'embeddings'"), "{}", err);
+
+    // Other columns can still be updated
+    tbl.update()
+        .only_if("id = 2")
+        .column("id", "20")
+        .execute()
+        .await?;
+    assert_eq!(tbl.count_rows(Some("id = 20".to_string())).await?, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cached_embeddings() -> Result<()> {
+    let inner = Arc::new(MockEmbed::new("chunked_func", 1).embeds(Output::Number));
+    let cached = CachedEmbeddingFunction::try_new(inner.clone(), 3)?;
+    let embed = |values: &[&str]| {
+        let source = Arc::new(StringArray::from(values.to_vec()));
+        let cached = &cached;
+        async move {
+            let embeddings = cached.compute_source_embeddings_async(source).await?;
+            let embeddings = embeddings
+                .as_any()
+                .downcast_ref::<FixedSizeListArray>()
+                .unwrap();
+            Result::Ok(
+                embeddings
+                    .values()
+                    .as_any()
+                    .downcast_ref::<Float32Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec(),
+            )
+        }
+    };
+
+    // Duplicates within a batch are embedded once and scattered back
+    assert_eq!(
+        embed(&["1", "2", "1", "3"]).await?,
+        vec![1.0, 2.0, 1.0, 3.0]
+    );
+    assert_eq!(*inner.inputs.lock().unwrap(), vec![vec!["1", "2", "3"]]);
+
+    // Only values that are not cached are embedded
+    assert_eq!(
+        embed(&["2", "4", "4", "1"]).await?,
+        vec![2.0, 4.0, 4.0, 1.0]
+    );
+    assert_eq!(inner.inputs.lock().unwrap()[1], vec!["4"]);
+    assert_eq!(
+        cached.stats(),
+        EmbeddingCacheStats {
+            hits: 2,
+            misses: 4,
+            entries: 3,
+        }
+    );
+
+    // The least recently used value ("3") was evicted
+    assert_eq!(embed(&["3", "1"]).await?, vec![3.0, 1.0]);
+    assert_eq!(inner.inputs.lock().unwrap()[2], vec!["3"]);
+
+    // A fully cached batch doesn't call the wrapped function at all
+    embed(&["3", "3"]).await?;
+    assert_eq!(inner.inputs.lock().unwrap().len(), 3);
+
+    // The cache works in the embedding pipeline
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    db.embedding_registry()
+        .register("cached_func", Arc::new(cached))?;
+    db.create_table(
+        "test",
+        create_text_records(["5", "6", "5", "3"].map(|t| Some(t.to_string())).to_vec()),
+    )
+    .add_embedding(EmbeddingDefinition::new(
+        "text",
+        "cached_func",
+        Some("embeddings"),
+    ))?
+    .execute()
+    .await?;
+    assert_eq!(inner.inputs.lock().unwrap()[3], vec!["5", "6"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cached_embeddings_invalid_input() -> Result<()> {
+    let int_embed = MockEmbed::new("int_func", 1).takes(DataType::Int32);
+    let err = CachedEmbeddingFunction::try_new(Arc::new(int_embed), 10).unwrap_err();
+    assert!(err.to_string().contains("string and binary"), "{}", err);
+
+    let inner = Arc::new(MockEmbed::new("func", 1));
+    assert!(CachedEmbeddingFunction::try_new(inner.clone(), 0).is_err());
+
+    let cached = CachedEmbeddingFunction::try_new(inner, 10)?;
+    let err = cached
+        .compute_source_embeddings(Arc::new(StringArray::from(vec![Some("a"), None])))
+        .unwrap_err();
+    assert!(err.to_string().contains("null"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_binary_source() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let images: Vec<&[u8]> = vec![&[1, 2, 3, 4], &[5, 6, 7, 8], &[9, 10, 11, 12]];
+    let source_types = [
+        DataType::Binary,
+        DataType::LargeBinary,
+        DataType::FixedSizeBinary(4),
+    ];
+    for (i, source_type) in source_types.into_iter().enumerate() {
+        let name = format!("binary_{}", i);
+        db.embedding_registry().register(
+            &name,
+            Arc::new(
+                MockEmbed::new(&name, 2)
+                    .takes(source_type.clone())
+                    .embeds(Output::Binary),
+            ),
+        )?;
+        let images: ArrayRef = match source_type {
+            DataType::Binary => Arc::new(BinaryArray::from(images.clone())),
+            DataType::LargeBinary => Arc::new(LargeBinaryArray::from(images.clone())),
+            _ => Arc::new(FixedSizeBinaryArray::try_from_iter(images.iter()).unwrap()),
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("image", source_type.clone(), false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![0, 1, 2])), images],
+        )?;
+        let tbl = db
+            .create_table(
+                &name,
+                Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+            )
+            .add_embedding(EmbeddingDefinition::new("image", &name, Some("vector")))?
+            .execute()
+            .await?;
+
+        let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+        let vectors = batches[0]["vector"].as_fixed_size_list();
+        assert_eq!(
+            vectors.value_type(),
+            DataType::Float16,
+            "embeddings of {} input",
+            source_type
+        );
+        let vector = vectors.value(1);
+        let vector = vector.as_primitive::<Float16Type>();
+        assert_eq!(vector.value(0).to_f32(), 4.0);
+        assert_eq!(vector.value(1).to_f32(), 5.0);
+
+        // f16 embedding columns can be searched
+        let results = tbl
+            .query()
+            .nearest_to_text("\x09abc")
+            .limit(1)
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(
+            results[0]["id"]
+                .as_primitive::<arrow_array::types::Int32Type>()
+                .value(0),
+            2
+        );
+    }
+
+    // A source column of the wrong type is rejected before the function is called
+    db.embedding_registry().register(
+        "binary",
+        Arc::new(
+            MockEmbed::new("binary", 2)
+                .takes(DataType::Binary)
+                .embeds(Output::Binary),
+        ),
+    )?;
+    let err = db
+        .create_table("wrong_type", create_some_records()?)
+        .add_embedding(EmbeddingDefinition::new("text", "binary", Some("vector")))?
+        .execute()
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains(
+            "source column 'text' has type Utf8 but embedding function 'binary' takes Binary"
+        ),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_float16_dest_column() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    // The function produces f32 vectors, which are stored at half precision
+    db.embedding_registry()
+        .register("embed_fun", Arc::new(MockEmbed::new("embed_fun", 2)))?;
+    let definition = Builder::new()
+        .field("id", DataType::Int32)
+        .text("text")
+        .vector_of("embeddings", 2, DataType::Float16)
+        .embedded("text", "embed_fun", Some("embeddings"))
+        .build_with_registry(db.embedding_registry())?;
+    db.create_empty_table("test", definition.into_rich_schema())
+        .execute()
+        .await?;
+
+    let tbl = db.open_table("test").execute().await?;
+    tbl.add(create_some_records()?).execute().await?;
+    let batches = tbl.query().execute().await?.try_collect::<Vec<_>>().await?;
+    let vectors = batches[0]["embeddings"].as_fixed_size_list();
+    assert_eq!(vectors.value_type(), DataType::Float16);
+    let vector = vectors.value(0);
+    assert_eq!(vector.as_primitive::<Float16Type>().value(0).to_f32(), 1.0);
+
+    // f32 query vectors search the f16 vectors, with f32 distances
+    let results = tbl
+        .query()
+        .nearest_to(&[1.0_f32, 1.0])?
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(results[0]["_distance"].data_type(), &DataType::Float32);
+    assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+
+    // Vectors of a different dimension are still rejected
+    db.embedding_registry()
+        .register_or_replace("embed_fun", Arc::new(MockEmbed::new("embed_fun", 3)))?;
+    let err = db.open_table("test").execute().await.err().unwrap();
+    assert!(
+        err.to_string().contains(
+            "column 'embeddings' stores 2-dimensional Float16 vectors but embedding function 'embed_fun' produces 3-dimensional Float32 vectors"
+        ),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_multivector_embeddings() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let func = Arc::new(MockEmbed::new("words_func", 2).embeds(Output::Words));
+    db.embedding_registry()
+        .register("words_func", func.clone())?;
+    let text = vec![
+        Some("a bb".to_string()),
+        Some("ccc".to_string()),
+        Some("dddd eeeee".to_string()),
+    ];
+    let tbl = db
+        .create_table("test", create_text_records(text))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "words_func",
+            Some("words"),
+        ))?
+        .execute()
+        .await?;
+    let schema = tbl.schema().await?;
+    assert_eq!(
+        schema.field_with_name("words")?.data_type(),
+        func.dest_type()?.as_ref()
+    );
+
+    // The text of the query is embedded as several vectors too
+    let results = tbl
+        .query()
+        .nearest_to_text("bb a")
+        .distance_type(DistanceType::L2)
+        .limit(1)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(
+        results[0]["id"]
+            .as_primitive::<arrow_array::types::Int32Type>()
+            .value(0),
+        0
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_asymmetric_embeddings() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let func = Arc::new(MockEmbed::new("asymmetric_func", 2).embeds(Output::Asymmetric));
+    db.embedding_registry()
+        .register("asymmetric_func", func.clone())?;
+    let tbl = db
+        .create_table(
+            "test",
+            create_text_records(vec![Some("red shoe".to_string())]),
+        )
+        .add_embedding(
+            EmbeddingDefinition::new("text", "asymmetric_func", Some("embeddings"))
+                .document_prefix("passage: ")
+                .query_prefix("query: "),
+        )?
+        .execute()
+        .await?;
+    assert_eq!(func.documents(), vec!["passage: red shoe"]);
+    assert!(func.queries.lock().unwrap().is_empty());
+
+    // The query is embedded with the query method, [0, 1], the rows with the
+    // document method, [1, 0]
+    let results = tbl
+        .query()
+        .nearest_to_text("red shoe")
+        .distance_type(DistanceType::L2)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(
+        results[0]["_distance"]
+            .as_primitive::<arrow_array::types::Float32Type>()
+            .value(0),
+        2.0
+    );
+    assert_eq!(*func.queries.lock().unwrap(), vec!["query: red shoe"]);
+    assert_eq!(func.documents().len(), 1);
+
+    // The prefixes are stored with the table
+    let tbl = db.open_table("test").execute().await?;
+    tbl.add(create_text_records(vec![Some("blue hat".to_string())]))
+        .execute()
+        .await?;
+    tbl.query()
+        .nearest_to_text("hat")
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(
+        func.documents(),
+        vec!["passage: red shoe", "passage: blue hat"]
+    );
+    assert_eq!(
+        *func.queries.lock().unwrap(),
+        vec!["query: red shoe", "query: hat"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_concurrency() -> Result<()> {
+    let test_db = TestDb::new();
+    let db = test_db.connect().await?;
+    let slow = || Arc::new(MockEmbed::new("slow_func", 1).slow(Duration::from_millis(100)));
+    db.embedding_registry().register("slow_func", slow())?;
+    let tbl = db
+        .create_table("test", create_batched_records(1, 2))
+        .add_embedding(EmbeddingDefinition::new(
+            "text",
+            "slow_func",
+            Some("embeddings"),
+        ))?
+        .execute()
+        .await?;
+
+    let serial = slow();
+    db.embedding_registry()
+        .register_or_replace("slow_func", serial.clone())?;
+    let start = Instant::now();
+    tbl.add(create_batched_records(8, 4)).execute().await?;
+    let serial_time = start.elapsed();
+    assert_eq!(serial.max_in_flight.load(Ordering::SeqCst), 1);
+
+    let concurrent = slow();
+    db.embedding_registry()
+        .register_or_replace("slow_func", concurrent.clone())?;
+    let start = Instant::now();
+    tbl.add(create_batched_records(8, 4))
+        .embedding_concurrency(4)
+        .execute()
+        .await?;
+    let concurrent_time = start.elapsed();
+    assert_eq!(concurrent.calls.load(Ordering::SeqCst), 8);
+    assert_eq!(concurrent.max_in_flight.load(Ordering::SeqCst), 4);
+    // About 4 times faster, with some slack for slow machines
+    assert!(
+        concurrent_time * 2 < serial_time,
+        "{:?} vs {:?}",
+        concurrent_time,
+        serial_time
+    );
+
+    // The batches are written in their original order
+    let batches = tbl
+        .query()
+        .select(Select::columns(&["id"]))
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let ids = batches
+        .iter()
+        .flat_map(|batch| {
+            batch["id"]
+                .as_primitive::<arrow_array::types::Int32Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    let expected = (0..2).chain(0..32).chain(0..32).collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+
+    // A failed batch fails the whole add
+    db.embedding_registry().register_or_replace(
+        "slow_func",
+        Arc::new(
+            MockEmbed::new("slow_func", 1)
+                .slow(Duration::from_millis(100))
+                .fails(Failure::OnCall(2)),
+        ),
+    )?;
+    let err = tbl
+        .add(create_batched_records(8, 4))
+        .embedding_concurrency(4)
+        .execute()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("cannot embed batch 2"), "{}", err);
+    assert_eq!(tbl.count_rows(None).await?, 66);
+
+    let err = tbl
+        .add(create_batched_records(1, 1))
+        .embedding_concurrency(0)
+        .execute()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    Ok(())
+}
+
+/// A database in a temporary directory, which is removed when the fixture is dropped
+struct TestDb(TempDir);
+
+impl TestDb {
+    fn new() -> Self {
+        Self(tempfile::tempdir().unwrap())
+    }
+
+    fn uri(&self) -> &str {
+        self.0.path().to_str().unwrap()
+    }
+
+    /// Connect with a registry of its own, so that the functions that a test
+    /// registers don't clash with those of the tests running alongside it
+    async fn connect(&self) -> Result<Connection> {
+        self.connect_with(Arc::new(MemoryRegistry::new())).await
+    }
+
+    async fn connect_with(&self, registry: Arc<dyn EmbeddingRegistry>) -> Result<Connection> {
+        connect(self.uri())
+            .embedding_registry(registry)
+            .execute()
+            .await
+    }
+}
+
+fn create_some_records() -> Result<impl IntoArrow> {
+    const TOTAL: usize = 2;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
         Field::new("text", DataType::Utf8, true),
     ]));
 
@@ -236,6 +1746,68 @@ fn create_some_records() -> Result<impl IntoArrow> {
     Ok(Box::new(batches))
 }
 
+/// A single batch of `num_rows` rows whose text is the row number
+fn create_numbered_records(num_rows: usize) -> impl IntoArrow {
+    create_text_records((0..num_rows).map(|i| Some(i.to_string())).collect())
+}
+
+/// Two rows with the given `embeddings` column
+fn create_precomputed_records(embeddings: ArrayRef) -> impl IntoArrow {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("text", DataType::Utf8, true),
+        Field::new("embeddings", embeddings.data_type().clone(), true),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter_values(0..2)),
+            Arc::new(StringArray::from(vec!["hello", "world"])),
+            embeddings,
+        ],
+    )
+    .unwrap();
+    Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+}
+
+/// `num_batches` batches of `num_rows` rows, numbered across the batches
+fn create_batched_records(num_batches: usize, num_rows: usize) -> impl IntoArrow {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("text", DataType::Utf8, true),
+    ]));
+    let batches = (0..num_batches)
+        .map(|i| {
+            let ids = (i * num_rows) as i32..((i + 1) * num_rows) as i32;
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(ids.clone())),
+                    Arc::new(StringArray::from_iter_values(ids.map(|id| id.to_string()))),
+                ],
+            )
+        })
+        .collect::<Vec<_>>();
+    Box::new(RecordBatchIterator::new(batches, schema))
+}
+
+/// A single batch with the given text values
+fn create_text_records(text: Vec<Option<String>>) -> impl IntoArrow {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("text", DataType::Utf8, true),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter_values(0..text.len() as i32)),
+            Arc::new(StringArray::from(text)),
+        ],
+    )
+    .unwrap();
+    Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+}
+
 #[derive(Debug)]
 struct MyRegistry {
     functions: HashMap<String, Arc<dyn EmbeddingFunction>>,
@@ -243,8 +1815,8 @@ struct MyRegistry {
 impl Default for MyRegistry {
     fn default() -> Self {
         let funcs: Vec<Arc<dyn EmbeddingFunction>> = vec![
-            Arc::new(MockEmbed::new("func_1".to_string(), 1)),
-            Arc::new(MockEmbed::new("func_2".to_string(), 10)),
+            Arc::new(MockEmbed::new("func_1", 1)),
+            Arc::new(MockEmbed::new("func_2", 10)),
         ];
         Self {
             functions: funcs
@@ -273,25 +1845,239 @@ impl EmbeddingRegistry for MyRegistry {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What the embeddings of a [`MockEmbed`] are
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    /// Every value is 1
+    Ones,
+    /// Every value is the text of the row parsed as a number, so row order can be checked
+    Number,
+    /// Documents are `[1, 0, ..]` and queries are `[0, 1, ..]`
+    Asymmetric,
+    /// The length and first byte of binary values, stored as f16
+    ///
+    /// Queries are embedded from the bytes of the query text.
+    Binary,
+    /// A multivector, with a vector of `[length, 1]` for each word
+    Words,
+}
+
+/// How a [`MockEmbed`] fails or breaks the contract of [`EmbeddingFunction`]
+#[derive(Debug, Clone, Copy)]
+enum Failure {
+    /// Every call fails, like a remote service that is down
+    Always,
+    /// The given call, counting from 0, fails
+    OnCall(usize),
+    /// The call that embeds the row with the given number fails
+    OnRow(usize),
+    /// Return one embedding less than there are rows
+    DropRow,
+    /// Return vectors with one more dimension after the first call
+    ChangeDimension,
+}
+
+/// An embedding function for tests, which records how it is called
+///
+/// It checks that it is given the type it takes, like functions that downcast their
+/// input, and panics if it is given a null.
+#[derive(Debug)]
 struct MockEmbed {
-    source_type: DataType,
-    dest_type: DataType,
     name: String,
+    source_type: DataType,
     dim: usize,
+    output: Output,
+    max_rows: Option<usize>,
+    failure: Option<Failure>,
+    delay: Option<Duration>,
+    configurable: bool,
+    /// The number of calls that embedded documents
+    calls: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+    /// The text of the documents of each call
+    inputs: Mutex<Vec<Vec<String>>>,
+    /// The text of each query
+    queries: Mutex<Vec<String>>,
 }
 
 impl MockEmbed {
-    pub fn new(name: String, dim: usize) -> Self {
+    fn new(name: &str, dim: usize) -> Self {
         Self {
+            name: name.to_string(),
             source_type: DataType::Utf8,
-            dest_type: DataType::new_fixed_size_list(DataType::Float32, dim as _, true),
-            name,
             dim,
+            output: Output::Ones,
+            max_rows: None,
+            failure: None,
+            delay: None,
+            configurable: false,
+            calls: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+            inputs: Mutex::new(Vec::new()),
+            queries: Mutex::new(Vec::new()),
         }
     }
+
+    /// Take input of the given type instead of strings
+    fn takes(mut self, source_type: DataType) -> Self {
+        self.source_type = source_type;
+        self
+    }
+
+    fn embeds(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Accept at most `max_rows` rows per call
+    fn max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    fn fails(mut self, failure: Failure) -> Self {
+        self.failure = Some(failure);
+        self
+    }
+
+    /// Take `delay` for each call, like a slow remote service
+    ///
+    /// Documents can then only be embedded with the async interface.
+    fn slow(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Make the function re-creatable from its config with [`MockEmbedFactory`]
+    fn configurable(mut self) -> Self {
+        self.configurable = true;
+        self
+    }
+
+    /// The text of all the documents embedded so far
+    fn documents(&self) -> Vec<String> {
+        self.inputs.lock().unwrap().concat()
+    }
+
+    fn word_vector_type() -> DataType {
+        DataType::new_fixed_size_list(DataType::Float32, 2, false)
+    }
+
+    fn embed_documents(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        assert_eq!(source.data_type(), &self.source_type);
+        if let Some(max_rows) = self.max_rows {
+            assert!(source.len() <= max_rows);
+        }
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(text) = source.as_string_opt::<i32>() {
+            self.inputs
+                .lock()
+                .unwrap()
+                .push(text.iter().map(|t| t.unwrap().to_string()).collect());
+        }
+        match self.failure {
+            Some(Failure::Always) => Err(Error::Runtime {
+                message: "service unavailable".to_string(),
+            }),
+            Some(Failure::OnCall(fail_on)) if fail_on == call => Err(Error::Runtime {
+                message: format!("cannot embed batch {}", call),
+            }),
+            Some(Failure::DropRow) => {
+                self.embed(source.slice(0, source.len() - 1).as_ref(), self.dim, false)
+            }
+            Some(Failure::ChangeDimension) if call > 0 => {
+                self.embed(source.as_ref(), self.dim + 1, false)
+            }
+            _ => self.embed(source.as_ref(), self.dim, false),
+        }
+    }
+
+    fn embed(&self, input: &dyn Array, dim: usize, query: bool) -> Result<Arc<dyn Array>> {
+        let len = input.len();
+        // We can't use the FixedSizeListBuilder here because it always adds a null bitmap
+        // and we want to explicitly work with non-nullable arrays.
+        let vectors = |values: Vec<f32>| -> Arc<dyn Array> {
+            Arc::new(FixedSizeListArray::new(
+                Arc::new(Field::new("item", DataType::Float32, false)),
+                dim as _,
+                Arc::new(Float32Array::from(values)),
+                Some(NullBuffer::new_valid(len)),
+            ))
+        };
+        let text = || input.as_string::<i32>().iter();
+        Ok(match self.output {
+            Output::Ones => vectors(vec![1.0; len * dim]),
+            Output::Number => {
+                let values = text()
+                    .map(|text| text.unwrap().parse::<usize>().unwrap())
+                    .map(|row| match self.failure {
+                        Some(Failure::OnRow(fail_on)) if fail_on == row => Err(Error::Runtime {
+                            message: format!("cannot embed row {}", row),
+                        }),
+                        _ => Ok(repeat(row as f32).take(dim)),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                vectors(values.into_iter().flatten().collect())
+            }
+            Output::Asymmetric => {
+                let vector = (0..dim).map(|i| if i == query as usize { 1.0 } else { 0.0 });
+                vectors(repeat(vector).take(len).flatten().collect())
+            }
+            Output::Binary => {
+                let values: Vec<&[u8]> = match input.data_type() {
+                    DataType::Utf8 => text().flatten().map(str::as_bytes).collect(),
+                    DataType::Binary => input.as_binary::<i32>().iter().flatten().collect(),
+                    DataType::LargeBinary => input.as_binary::<i64>().iter().flatten().collect(),
+                    DataType::FixedSizeBinary(_) => {
+                        input.as_fixed_size_binary().iter().flatten().collect()
+                    }
+                    other => panic!("unexpected input type {}", other),
+                };
+                let values = values
+                    .into_iter()
+                    .flat_map(|v| [v.len() as f32, v[0] as f32])
+                    .map(half::f16::from_f32)
+                    .collect::<Vec<_>>();
+                Arc::new(FixedSizeListArray::new(
+                    Arc::new(Field::new("item", DataType::Float16, false)),
+                    2,
+                    Arc::new(Float16Array::from(values)),
+                    None,
+                ))
+            }
+            Output::Words => {
+                let words = text()
+                    .map(|text| {
+                        text.unwrap_or_default()
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                let values = words
+                    .iter()
+                    .flatten()
+                    .flat_map(|word| [word.len() as f32, 1.0])
+                    .collect::<Vec<_>>();
+                let vectors = FixedSizeListArray::new(
+                    Arc::new(Field::new("item", DataType::Float32, false)),
+                    2,
+                    Arc::new(Float32Array::from(values)),
+                    None,
+                );
+                Arc::new(ListArray::new(
+                    Arc::new(Field::new("item", Self::word_vector_type(), false)),
+                    OffsetBuffer::from_lengths(words.iter().map(|w| w.len())),
+                    Arc::new(vectors),
+                    None,
+                ))
+            }
+        })
+    }
 }
 
+#[async_trait]
 impl EmbeddingFunction for MockEmbed {
     fn name(&self) -> &str {
         &self.name
@@ -300,26 +2086,57 @@ impl EmbeddingFunction for MockEmbed {
         Ok(Cow::Borrowed(&self.source_type))
     }
     fn dest_type(&self) -> Result<Cow<DataType>> {
-        Ok(Cow::Borrowed(&self.dest_type))
+        Ok(Cow::Owned(match self.output {
+            Output::Binary => DataType::new_fixed_size_list(DataType::Float16, 2, false),
+            Output::Words => DataType::new_list(Self::word_vector_type(), false),
+            _ => DataType::new_fixed_size_list(DataType::Float32, self.dim as _, true),
+        }))
     }
     fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
-        // We can't use the FixedSizeListBuilder here because it always adds a null bitmap
-        // and we want to explicitly work with non-nullable arrays.
-        let len = source.len();
-        let inner = Arc::new(Float32Array::from(vec![Some(1.0); len * self.dim]));
-        let field = Field::new("item", inner.data_type().clone(), false);
-        let arr = FixedSizeListArray::new(
-            Arc::new(field),
-            self.dim as _,
-            inner,
-            Some(NullBuffer::new_valid(len)),
+        assert!(
+            self.delay.is_none(),
+            "embeddings should be computed with the async interface"
         );
-
-        Ok(Arc::new(arr))
+        self.embed_documents(source)
     }
-
-    #[allow(unused_variables)]
     fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
-        unimplemented!()
+        self.queries
+            .lock()
+            .unwrap()
+            .extend(input.as_string::<i32>().iter().flatten().map(String::from));
+        self.embed(input.as_ref(), self.dim, true)
+    }
+    async fn compute_source_embeddings_async(
+        &self,
+        source: Arc<dyn Array>,
+    ) -> Result<Arc<dyn Array>> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.embed_documents(source)
+    }
+    fn max_batch_size(&self) -> Option<usize> {
+        self.max_rows
+    }
+    fn to_config(&self) -> Option<serde_json::Value> {
+        self.configurable
+            .then(|| serde_json::json!({ "dim": self.dim }))
+    }
+}
+
+/// Re-creates the functions made with [`MockEmbed::configurable`]
+#[derive(Debug)]
+struct MockEmbedFactory;
+
+impl EmbeddingFunctionFactory for MockEmbedFactory {
+    fn name(&self) -> &str {
+        "configurable"
+    }
+    fn from_config(&self, config: &serde_json::Value) -> Result<Arc<dyn EmbeddingFunction>> {
+        let dim = config["dim"].as_u64().unwrap() as usize;
+        Ok(Arc::new(MockEmbed::new("configurable", dim).configurable()))
     }
 }
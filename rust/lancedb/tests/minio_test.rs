@@ -0,0 +1,81 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests against a real MinIO server, configured only through storage options
+//!
+//! These only run when `LANCEDB_TEST_MINIO_ENDPOINT` is set, e.g. to
+//! `http://127.0.0.1:9000`.  The bucket, `lancedb-test` by default, must exist and
+//! can be changed with `LANCEDB_TEST_MINIO_BUCKET`.  The credentials default to
+//! MinIO's `minioadmin` and can be changed with `LANCEDB_TEST_MINIO_ACCESS_KEY`
+//! and `LANCEDB_TEST_MINIO_SECRET_KEY`.
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{DataType, Field, Schema};
+use lancedb::Result;
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn test_data() -> impl RecordBatchReader + Send + 'static {
+    let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+    )
+    .unwrap();
+    RecordBatchIterator::new(vec![Ok(batch)], schema)
+}
+
+#[tokio::test]
+async fn test_minio_storage_options() -> Result<()> {
+    let Ok(endpoint) = std::env::var("LANCEDB_TEST_MINIO_ENDPOINT") else {
+        return Ok(());
+    };
+    let bucket = env_or("LANCEDB_TEST_MINIO_BUCKET", "lancedb-test");
+    let access_key = env_or("LANCEDB_TEST_MINIO_ACCESS_KEY", "minioadmin");
+    let secret_key = env_or("LANCEDB_TEST_MINIO_SECRET_KEY", "minioadmin");
+    let uri = format!("s3://{}/{}", bucket, uuid::Uuid::new_v4());
+
+    let db = lancedb::connect(&uri)
+        .aws_endpoint(&endpoint)
+        .aws_region("us-east-1")
+        .allow_http(true)
+        .storage_option("aws_access_key_id", &access_key)
+        .storage_option("aws_secret_access_key", &secret_key)
+        .execute()
+        .await?;
+
+    // The table inherits the options of the connection
+    let table = db.create_table("test", test_data()).execute().await?;
+    table.add(test_data()).execute().await?;
+    assert_eq!(table.count_rows(None).await?, 6);
+    assert_eq!(db.table_names().execute().await?, vec!["test"]);
+
+    let table = db.open_table("test").execute().await?;
+    assert_eq!(table.count_rows(None).await?, 6);
+
+    // Options set on the table take precedence over those of the connection
+    let result = db
+        .open_table("test")
+        .storage_option("aws_secret_access_key", "not-the-secret-key")
+        .execute()
+        .await;
+    assert!(result.is_err());
+
+    db.drop_table("test").await?;
+    assert!(db.table_names().execute().await?.is_empty());
+    Ok(())
+}
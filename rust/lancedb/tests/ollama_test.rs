@@ -0,0 +1,92 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests against a real Ollama server
+//!
+//! These only run when `LANCEDB_TEST_OLLAMA_HOST` is set, e.g. to
+//! `http://localhost:11434`.  The model defaults to `nomic-embed-text` and
+//! can be changed with `LANCEDB_TEST_OLLAMA_MODEL`.
+#![cfg(feature = "ollama")]
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lancedb::{
+    connect,
+    embeddings::{ollama::OllamaEmbeddingFunction, EmbeddingDefinition},
+    query::{ExecutableQuery, QueryBase},
+    Result,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_ollama_embeddings() -> Result<()> {
+    let Ok(host) = std::env::var("LANCEDB_TEST_OLLAMA_HOST") else {
+        return Ok(());
+    };
+    let model = std::env::var("LANCEDB_TEST_OLLAMA_MODEL")
+        .unwrap_or_else(|_| "nomic-embed-text".to_string());
+
+    let func = OllamaEmbeddingFunction::new(model).host(host);
+    let ndims = func.initialize().await?;
+    assert!(ndims > 0);
+
+    let tempdir = tempfile::tempdir().unwrap();
+    let db = connect(tempdir.path().to_str().unwrap()).execute().await?;
+    db.embedding_registry().register("ollama", Arc::new(func))?;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("text", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from(vec![0, 1, 2])),
+            Arc::new(StringArray::from(vec![
+                "a red running shoe",
+                "a bowl of ramen",
+                "a mountain bike",
+            ])),
+        ],
+    )?;
+    let tbl = db
+        .create_table(
+            "test",
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)),
+        )
+        .add_embedding(EmbeddingDefinition::new("text", "ollama", None))?
+        .execute()
+        .await?;
+
+    let results = tbl
+        .query()
+        .nearest_to_text("noodle soup")
+        .limit(1)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let ids = results[0]["id"]
+        .as_any()
+        .downcast_ref::<Int32Array>()
+        .unwrap();
+    assert_eq!(ids.value(0), 1);
+    let func = db.embedding_registry().get("ollama").unwrap();
+    assert_eq!(
+        func.dest_type()?.as_ref(),
+        &DataType::new_fixed_size_list(DataType::Float32, ndims as i32, false)
+    );
+    Ok(())
+}